@@ -1,15 +1,14 @@
-use std::{collections::HashMap, sync::Arc};
+use std::collections::HashMap;
 
 use futures::future::join_all;
 use tempfile::tempdir;
-use tokio::sync::RwLock;
-use velarixdb::db::DataStore;
+use velarixdb::db::Db;
 
 #[tokio::main]
 async fn main() {
     let root = tempdir().unwrap();
     let path = root.path().join("velarix");
-    let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    let db = Db::open("big_tech", path).await.unwrap(); // handle IO error
     let mut entries = HashMap::new();
     entries.insert("apple", "tim cook");
     entries.insert("google", "sundar pichai");
@@ -18,15 +17,11 @@ async fn main() {
     entries.insert("meta", "mark zuckerberg");
     entries.insert("openai", "sam altman");
 
-    let store_ref = Arc::new(RwLock::new(store));
     let writes = entries.iter().map(|(k, v)| {
-        let store_inner = Arc::clone(&store_ref);
+        let db = db.clone();
         let key = k.to_owned();
         let val = v.to_owned();
-        tokio::spawn(async move {
-            let mut writer = store_inner.write().await;
-            writer.put(key, val).await
-        })
+        tokio::spawn(async move { db.put(key, val).await })
     });
     let all_results = join_all(writes).await;
     for tokio_res in all_results {
@@ -36,10 +31,10 @@ async fn main() {
 
     // Read entries concurrently
     let reads = entries.keys().map(|k| {
-        let store_inner = Arc::clone(&store_ref);
+        let db = db.clone();
         let key = k.to_owned();
         tokio::spawn(async move {
-            match store_inner.read().await.get(key.to_owned()).await {
+            match db.get(key.to_owned()).await {
                 Ok(entry) => Ok((key, entry)),
                 Err(err) => Err(err),
             }
@@ -6,7 +6,7 @@ use velarixdb::db::DataStore;
 async fn main() {
     let root = tempdir().unwrap();
     let path = root.path().join("velarix");
-    let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
 
     #[derive(Serialize, Deserialize)]
     struct BigTech {
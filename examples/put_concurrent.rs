@@ -1,15 +1,12 @@
-use std::sync::Arc;
-
 use futures::future::join_all;
 use tempfile::tempdir;
-use tokio::sync::RwLock;
-use velarixdb::db::DataStore;
+use velarixdb::db::Db;
 
 #[tokio::main]
 async fn main() {
     let root = tempdir().unwrap();
     let path = root.path().join("velarix");
-    let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    let db = Db::open("big_tech", path).await.unwrap(); // handle IO error
 
     let entries = [
         ["apple", "tim cook"],
@@ -20,15 +17,11 @@ async fn main() {
         ["openai", "sam altman"],
     ];
 
-    let store_ref = Arc::new(RwLock::new(store));
     let write_tasks = entries.iter().map(|e| {
-        let store_inner = Arc::clone(&store_ref);
+        let db = db.clone();
         let key = e[0];
         let val = e[1];
-        tokio::spawn(async move {
-            let mut writer = store_inner.write().await;
-            writer.put(key, val).await
-        })
+        tokio::spawn(async move { db.put(key, val).await })
     });
     let all_results = join_all(write_tasks).await;
     for tokio_res in all_results {
@@ -0,0 +1,55 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::sleep;
+
+/// Artificial latency added to foreground IO, e.g. to see how a config
+/// behaves on a slower disk than the one benchmarks run on
+///
+/// Reads of SSTable blocks and values and appends to the value log are
+/// delayed, background flushes and compactions are not
+#[derive(Clone, Debug, Default)]
+pub(crate) struct IoLatency {
+    /// Delay of reads in nanoseconds
+    read: Arc<AtomicU64>,
+
+    /// Delay of appends in nanoseconds
+    write: Arc<AtomicU64>,
+}
+
+impl IoLatency {
+    /// Sets delay of reads and appends, zero disables it
+    pub fn set(&self, read: Duration, write: Duration) {
+        self.read.store(read.as_nanos() as u64, Ordering::Relaxed);
+        self.write.store(write.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Returns delay of reads and appends
+    pub fn get(&self) -> (Duration, Duration) {
+        (
+            Duration::from_nanos(self.read.load(Ordering::Relaxed)),
+            Duration::from_nanos(self.write.load(Ordering::Relaxed)),
+        )
+    }
+
+    /// Waits for the read delay
+    pub async fn read(&self) {
+        Self::delay(&self.read).await
+    }
+
+    /// Waits for the append delay
+    pub async fn write(&self) {
+        Self::delay(&self.write).await
+    }
+
+    async fn delay(nanos: &AtomicU64) {
+        let nanos = nanos.load(Ordering::Relaxed);
+        if nanos > 0 {
+            sleep(Duration::from_nanos(nanos)).await;
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! Benchmarks of common workloads, in the spirit of RocksDB's `db_bench`
+//!
+//! Workloads run against a [`Db`] so config changes can be compared on the
+//! same machine. Keys and values come from a seeded generator, the same
+//! config replays the same operations.
+//!
+//! # Examples
+//! ```
+//! # use tempfile::tempdir;
+//! use velarixdb::bench::{self, BenchConfig, Workload};
+//! use velarixdb::db::Db;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let root = tempdir().unwrap();
+//!     let path = root.path().join("velarixdb");
+//!     let db = Db::open("bench", path).await.unwrap(); // handle IO error
+//!
+//!     let config = BenchConfig {
+//!         num: 1000,
+//!         reads: 1000,
+//!         ..Default::default()
+//!     };
+//!     let report = bench::run(&db, &config, &[Workload::FillSeq, Workload::ReadRandom])
+//!         .await
+//!         .unwrap(); // handle error
+//!     assert_eq!(report.workloads[1].found, 1000);
+//!     println!("{}", report);
+//! }
+//! ```
+mod latency;
+
+use crate::consts::{
+    BENCH_DEFAULT_KEY_SIZE, BENCH_DEFAULT_NUM, BENCH_DEFAULT_SEED, BENCH_DEFAULT_VALUE_SIZE,
+    BENCH_VALUE_POOL_SIZE,
+};
+use crate::db::Db;
+use crate::err::Error;
+pub(crate) use latency::IoLatency;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::Serialize;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Workload run by [`run`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Workload {
+    /// Writes `num` keys in ascending order
+    FillSeq,
+
+    /// Writes `num` random keys out of `num`, some keys are written more than once
+    FillRandom,
+
+    /// Reads `reads` random keys out of `num`
+    ReadRandom,
+
+    /// Reads as [`Workload::ReadRandom`] while a task keeps writing random keys,
+    /// only reads are reported
+    ReadWhileWriting,
+}
+
+impl Workload {
+    /// Returns name of the workload as used by `db_bench`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Workload::FillSeq => "fillseq",
+            Workload::FillRandom => "fillrandom",
+            Workload::ReadRandom => "readrandom",
+            Workload::ReadWhileWriting => "readwhilewriting",
+        }
+    }
+}
+
+/// Parameters of a benchmark run
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct BenchConfig {
+    /// Number of keys written by fill workloads and read by read workloads
+    pub num: usize,
+
+    /// Number of reads of read workloads
+    pub reads: usize,
+
+    /// Length of keys in bytes, keys are zero padded decimal indexes
+    pub key_size: usize,
+
+    /// Length of values in bytes
+    pub value_size: usize,
+
+    /// Seed of key and value generators
+    pub seed: u64,
+
+    /// Latency added to every SSTable and value log read
+    pub read_latency: Duration,
+
+    /// Latency added to every value log append
+    pub write_latency: Duration,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            num: BENCH_DEFAULT_NUM,
+            reads: BENCH_DEFAULT_NUM,
+            key_size: BENCH_DEFAULT_KEY_SIZE,
+            value_size: BENCH_DEFAULT_VALUE_SIZE,
+            seed: BENCH_DEFAULT_SEED,
+            read_latency: Duration::ZERO,
+            write_latency: Duration::ZERO,
+        }
+    }
+}
+
+/// Measurements of a workload
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct WorkloadReport {
+    /// Workload measured
+    pub workload: Workload,
+
+    /// Number of operations
+    pub ops: usize,
+
+    /// Number of reads that found a value, zero for fill workloads
+    pub found: usize,
+
+    /// Bytes of keys and values written or read
+    pub bytes: usize,
+
+    /// Time taken by every operation
+    pub elapsed: Duration,
+
+    /// Operations per second
+    pub ops_per_sec: f64,
+
+    /// Average latency of an operation in microseconds
+    pub micros_per_op: f64,
+
+    /// Throughput in megabytes per second
+    pub mb_per_sec: f64,
+
+    /// Median latency of an operation
+    pub p50: Duration,
+
+    /// 99th percentile latency of an operation
+    pub p99: Duration,
+
+    /// Latency of the slowest operation
+    pub max: Duration,
+}
+
+impl WorkloadReport {
+    fn new(
+        workload: Workload,
+        found: usize,
+        bytes: usize,
+        elapsed: Duration,
+        mut latencies: Vec<Duration>,
+    ) -> Self {
+        latencies.sort_unstable();
+        let percentile = |p: f64| match latencies.len() {
+            0 => Duration::ZERO,
+            len => latencies[((len - 1) as f64 * p).round() as usize],
+        };
+        let ops = latencies.len();
+        let secs = elapsed.as_secs_f64();
+        Self {
+            workload,
+            ops,
+            found,
+            bytes,
+            elapsed,
+            ops_per_sec: if secs > 0.0 { ops as f64 / secs } else { 0.0 },
+            micros_per_op: if ops > 0 { secs * 1e6 / ops as f64 } else { 0.0 },
+            mb_per_sec: if secs > 0.0 {
+                bytes as f64 / 1048576.0 / secs
+            } else {
+                0.0
+            },
+            p50: percentile(0.5),
+            p99: percentile(0.99),
+            max: percentile(1.0),
+        }
+    }
+}
+
+impl fmt::Display for WorkloadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<16} : {:>11.3} micros/op {:>9.0} ops/sec; {:>7.1} MB/s (p50 {:?}, p99 {:?}, max {:?})",
+            self.workload.name(),
+            self.micros_per_op,
+            self.ops_per_sec,
+            self.mb_per_sec,
+            self.p50,
+            self.p99,
+            self.max
+        )?;
+        if matches!(self.workload, Workload::ReadRandom | Workload::ReadWhileWriting) {
+            write!(f, " ({} of {} found)", self.found, self.ops)?;
+        }
+        Ok(())
+    }
+}
+
+/// Measurements of every workload of a run, serializable to compare runs
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[non_exhaustive]
+pub struct BenchReport {
+    /// Parameters of the run
+    pub config: BenchConfig,
+
+    /// Measurements in the order workloads were run
+    pub workloads: Vec<WorkloadReport>,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Keys: {} bytes each, Values: {} bytes each, Entries: {}, Reads: {}",
+            self.config.key_size, self.config.value_size, self.config.num, self.config.reads
+        )?;
+        for workload in &self.workloads {
+            writeln!(f, "{}", workload)?;
+        }
+        Ok(())
+    }
+}
+
+/// Generates keys and values of a workload
+#[derive(Debug)]
+struct Generator {
+    rng: StdRng,
+    num: usize,
+    key_size: usize,
+    value_size: usize,
+
+    /// Random bytes values are sliced from, as `db_bench` does
+    pool: Vec<u8>,
+    position: usize,
+}
+
+impl Generator {
+    fn new(config: &BenchConfig, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let pool = (0..BENCH_VALUE_POOL_SIZE.max(config.value_size))
+            .map(|_| rng.sample(rand::distributions::Alphanumeric))
+            .collect();
+        Self {
+            rng,
+            num: config.num.max(1),
+            key_size: config.key_size,
+            value_size: config.value_size,
+            pool,
+            position: 0,
+        }
+    }
+
+    fn key(&self, index: usize) -> Vec<u8> {
+        format!("{:0width$}", index, width = self.key_size).into_bytes()
+    }
+
+    fn random_key(&mut self) -> Vec<u8> {
+        let index = self.rng.gen_range(0..self.num);
+        self.key(index)
+    }
+
+    fn value(&mut self) -> &[u8] {
+        if self.position + self.value_size > self.pool.len() {
+            self.position = 0;
+        }
+        self.position += self.value_size;
+        &self.pool[self.position - self.value_size..self.position]
+    }
+}
+
+/// Runs `workloads` in order against `db` and reports their measurements
+///
+/// Read latency and write latency of `config` are added to the IO of `db`
+/// while the run lasts. Read workloads expect keys written by a fill
+/// workload of the same config, in this run or an earlier one
+///
+/// # Errors
+///
+/// Returns error if an operation fails, e.g. because of an IO error or a
+/// key or value size the store rejects
+pub async fn run(db: &Db, config: &BenchConfig, workloads: &[Workload]) -> Result<BenchReport, Error> {
+    let latency = db.store.read().await.io_latency.clone();
+    let (read, write) = latency.get();
+    latency.set(config.read_latency, config.write_latency);
+    let mut reports = Vec::with_capacity(workloads.len());
+    let mut res = Ok(());
+    for (i, workload) in workloads.iter().enumerate() {
+        // every workload has its own seed so reordering workloads does not change them
+        let seed = config.seed.wrapping_add(i as u64);
+        match run_workload(db, config, *workload, seed).await {
+            Ok(report) => reports.push(report),
+            Err(err) => {
+                res = Err(err);
+                break;
+            }
+        }
+    }
+    latency.set(read, write);
+    res?;
+    Ok(BenchReport {
+        config: config.to_owned(),
+        workloads: reports,
+    })
+}
+
+async fn run_workload(
+    db: &Db,
+    config: &BenchConfig,
+    workload: Workload,
+    seed: u64,
+) -> Result<WorkloadReport, Error> {
+    match workload {
+        Workload::FillSeq | Workload::FillRandom => {
+            let mut generator = Generator::new(config, seed);
+            let mut latencies = Vec::with_capacity(config.num);
+            let mut bytes = 0;
+            let started = Instant::now();
+            for i in 0..config.num {
+                let key = match workload {
+                    Workload::FillSeq => generator.key(i),
+                    _ => generator.random_key(),
+                };
+                let value = generator.value();
+                let op_start = Instant::now();
+                db.put(&key, value).await?;
+                latencies.push(op_start.elapsed());
+                bytes += key.len() + value.len();
+            }
+            Ok(WorkloadReport::new(
+                workload,
+                0,
+                bytes,
+                started.elapsed(),
+                latencies,
+            ))
+        }
+        Workload::ReadRandom => read_random(db, config, workload, seed).await,
+        Workload::ReadWhileWriting => {
+            let stop = Arc::new(AtomicBool::new(false));
+            let writer = {
+                let db = db.clone();
+                let stop = Arc::clone(&stop);
+                let mut generator = Generator::new(config, seed ^ u64::MAX);
+                tokio::spawn(async move {
+                    while !stop.load(Ordering::Relaxed) {
+                        let key = generator.random_key();
+                        db.put(&key, generator.value()).await?;
+                    }
+                    Ok::<_, Error>(())
+                })
+            };
+            let res = read_random(db, config, workload, seed).await;
+            stop.store(true, Ordering::Relaxed);
+            writer.await.map_err(|_| Error::TokioJoin)??;
+            res
+        }
+    }
+}
+
+async fn read_random(
+    db: &Db,
+    config: &BenchConfig,
+    workload: Workload,
+    seed: u64,
+) -> Result<WorkloadReport, Error> {
+    let mut generator = Generator::new(config, seed);
+    let mut latencies = Vec::with_capacity(config.reads);
+    let (mut found, mut bytes) = (0, 0);
+    let started = Instant::now();
+    for _ in 0..config.reads {
+        let key = generator.random_key();
+        let op_start = Instant::now();
+        let entry = db.get_ref(&key).await?;
+        latencies.push(op_start.elapsed());
+        if let Some(entry) = entry {
+            found += 1;
+            bytes += key.len() + entry.val.len();
+        }
+        // lets the writer of `ReadWhileWriting` take turns with reads
+        tokio::task::yield_now().await;
+    }
+    Ok(WorkloadReport::new(
+        workload,
+        found,
+        bytes,
+        started.elapsed(),
+        latencies,
+    ))
+}
@@ -69,6 +69,7 @@ use crate::{
     err::{self, Error},
     fs::{FileAsync, FileNode},
     types::ByteSerializedEntry,
+    util,
 };
 type BytesWritten = usize;
 
@@ -138,18 +139,24 @@ impl Block {
         Ok(())
     }
 
-    /// Writes entries in the block to the sstable file
+    /// Writes entries in the block to the sstable file, `checksum` is
+    /// updated with every byte written
     ///
     /// Returns a `Result` indicating success or failure.
     ///
     /// # Errors
     ///
     /// Returns an error if write fails
-    pub async fn write_to_file(&self, file: FileNode) -> Result<BytesWritten, Error> {
+    pub async fn write_to_file(
+        &self,
+        file: FileNode,
+        checksum: &mut crc32fast::Hasher,
+    ) -> Result<BytesWritten, Error> {
         let mut bytes_written = 0;
         for entry in &self.entries {
             let serialized_entry = self.serialize(entry)?;
             file.write_all(&serialized_entry).await?;
+            checksum.update(&serialized_entry);
             bytes_written += serialized_entry.len();
         }
         Ok(bytes_written)
@@ -182,7 +189,7 @@ impl Block {
 
         entry_vec.extend_from_slice(&entry.value_offset.to_le_bytes());
 
-        entry_vec.extend_from_slice(&entry.creation_date.timestamp_millis().to_le_bytes());
+        entry_vec.extend_from_slice(&util::datetime_to_timestamp(entry.creation_date).to_le_bytes());
 
         entry_vec.push(entry.is_tombstone as u8);
         if entry_len != entry_vec.len() {
@@ -297,10 +304,12 @@ mod tests {
 
         let file = FileNode {
             file_path: temp_file_path.to_owned(),
-            file: Arc::new(RwLock::new(tokio_file)),
+            file: Arc::new(RwLock::new(tokio_file.into())),
             file_type: crate::fs::FileType::Data,
         };
-        let write_res = block.write_to_file(file.clone()).await;
+        let write_res = block
+            .write_to_file(file.clone(), &mut crc32fast::Hasher::new())
+            .await;
         assert!(write_res.is_ok());
         assert_eq!(write_res.unwrap(), block.size)
     }
@@ -1,23 +1,25 @@
-use crate::consts::{
-    BUCKET_DIRECTORY_PREFIX, BUCKET_HIGH, BUCKET_LOW, MAX_TRESHOLD, MIN_SSTABLE_SIZE, MIN_TRESHOLD,
-};
+use super::tuning::BucketTuning;
+use crate::compactors::{BucketInfo, CompactionJob, SSTableInfo, TableInsertor};
+use crate::consts::{BUCKET_DIRECTORY_PREFIX, DEFAULT_PERSIST_FILTER_BITS, TEMP_SSTABLE_EXTENSION};
+use crate::encryption::Keyring;
 use crate::err::Error;
 use crate::filter::BloomFilter;
 use crate::fs::{FileAsync, FileNode};
+use crate::meta::Manifest;
 use crate::sst::Table;
 use crate::types::{Bool, Key, SkipMapEntries};
-use chrono::Utc;
+use crate::util;
+use crossbeam_skiplist::SkipMap;
 use indexmap::IndexMap;
 use std::fmt::Debug;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{path::PathBuf, sync::Arc};
 use tokio::fs;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 use Error::*;
 
-static SST_PREFIX: &str = "sstable";
-
 /// Alias for SSTables to remove from each bucket
 pub type SSTablesToRemove = Vec<(BucketID, Vec<Table>)>;
 
@@ -35,6 +37,18 @@ pub type AvgSize = usize;
 pub struct BucketMap {
     pub dir: PathBuf,
     pub buckets: IndexMap<BucketID, Bucket>,
+
+    /// Records which SSTables in the buckets are live
+    pub(crate) manifest: Manifest,
+
+    /// Thresholds used to group and compact SSTables, shared with the store config
+    pub(crate) tuning: Arc<std::sync::RwLock<BucketTuning>>,
+
+    /// Whether filter bits are written with new SSTables, shared with the store config
+    pub(crate) persist_filter_bits: Arc<AtomicBool>,
+
+    /// Keys SSTables are encrypted with, new SSTables use the active one
+    pub(crate) keyring: Arc<Keyring>,
 }
 
 /// Enum to signify to create new bucket or use exisiting one
@@ -63,8 +77,16 @@ pub trait InsertableToBucket: Debug + Send + Sync {
 
 impl Bucket {
     pub async fn new<P: AsRef<Path>>(dir: P) -> Result<Bucket, Error> {
+        Bucket::with_id(dir, Uuid::new_v4()).await
+    }
+
+    /// Creates empty `Bucket` `id` in `dir`
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub(crate) async fn with_id<P: AsRef<Path>>(dir: P, id: BucketID) -> Result<Bucket, Error> {
         let dir = dir.as_ref();
-        let id = Uuid::new_v4();
         let dir = dir.join(BUCKET_DIRECTORY_PREFIX.to_string() + id.to_string().as_str());
         FileNode::create_dir_all(dir.to_owned()).await?;
         Ok(Self {
@@ -76,6 +98,27 @@ impl Bucket {
         })
     }
 
+    /// Parses bucket id from the name of bucket directory `dir`
+    ///
+    /// Only the last path component is read, so the rest of the path can be
+    /// relative, a symlink or contain `bucket` anywhere
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the directory name is not a bucket directory name
+    pub(crate) fn id_from_dir<P: AsRef<Path>>(dir: P) -> Result<BucketID, Error> {
+        let name = dir
+            .as_ref()
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let id = util::strip_prefix_ignore_case(&name, BUCKET_DIRECTORY_PREFIX).unwrap_or(&name);
+        Uuid::parse_str(id).map_err(|error| InvaidUUIDParseString {
+            input_string: id.to_string(),
+            error,
+        })
+    }
+
     /// Creates `Bucket` from the passed in variables
     ///
     /// Returns Ok(Bucket)
@@ -134,18 +177,22 @@ impl Bucket {
     ///
     /// Returns `true` if table fits or `false` if it doesn't
     ///
-    pub(crate) fn fits_into_bucket<T: InsertableToBucket + ?Sized>(&self, table: Arc<Box<T>>) -> Bool {
-        (self.avarage_size as f64 * BUCKET_LOW < table.size() as f64)
-            && (table.size() < (self.avarage_size as f64 * BUCKET_HIGH) as usize)
-            || (table.size() < MIN_SSTABLE_SIZE && self.avarage_size < MIN_SSTABLE_SIZE)
+    pub(crate) fn fits_into_bucket<T: InsertableToBucket + ?Sized>(
+        &self,
+        table: Arc<Box<T>>,
+        tuning: &BucketTuning,
+    ) -> Bool {
+        (self.avarage_size as f64 * tuning.bucket_low < table.size() as f64)
+            && (table.size() < (self.avarage_size as f64 * tuning.bucket_high) as usize)
+            || (table.size() < tuning.min_sstable_size && self.avarage_size < tuning.min_sstable_size)
     }
 
     /// Returns SSTables that needs to be compacted in a [`Bucket`]
     ///
-    /// If sstables in bucket exceeds `MAX_TRESHOLD` then only returns the
-    /// MAX_TRESHOLD otherwise return all the sstables in the bucket,
-    /// if sstables in bucket is less than `MIN_TRESHOLD`, then we ignore
-    /// that bucket
+    /// If sstables in bucket exceeds `max_tables_to_merge` then only returns the
+    /// first `max_tables_to_merge` otherwise return all the sstables in the bucket,
+    /// if sstables in bucket is less than `min_tables_to_merge`, then we ignore
+    /// that bucket unless one of its sstables holds too many tombstones
     ///
     /// Returns `Result` of a tuple of sstables to merge and their average size
     /// or error
@@ -153,23 +200,45 @@ impl Bucket {
     /// # Error
     ///
     /// Returns error in case an error occurs while calculating average
-    pub(crate) async fn extract_sstables(&self) -> Result<(Vec<Table>, AvgSize), Error> {
-        if self.sstables.read().await.len() < MIN_TRESHOLD {
+    pub(crate) async fn extract_sstables(
+        &self,
+        tuning: &BucketTuning,
+    ) -> Result<(Vec<Table>, AvgSize), Error> {
+        if !self.sstable_count_exceeds_threshhold(tuning).await
+            && !self.has_tombstone_heavy_sstable(tuning).await
+        {
             return Ok((vec![], 0));
         }
         let extracted_sstables = self
             .sstables
             .read()
             .await
-            .get(0..MAX_TRESHOLD)
+            .get(0..tuning.max_tables_to_merge)
             .unwrap_or(&self.sstables.read().await.clone())
             .to_vec();
         let average = Bucket::cal_average_size(extracted_sstables.clone()).await?;
         Ok((extracted_sstables, average))
     }
 
-    pub(crate) async fn sstable_count_exceeds_threshhold(&self) -> bool {
-        self.sstables.read().await.len() >= MIN_TRESHOLD
+    pub(crate) async fn sstable_count_exceeds_threshhold(&self, tuning: &BucketTuning) -> bool {
+        self.sstables.read().await.len() >= tuning.min_tables_to_merge
+    }
+
+    /// Returns `true` if deletion triggered compaction is enabled and an sstable
+    /// in the bucket has at least `tombstone_ratio` tombstones
+    ///
+    /// A single sstable is never merged on its own, so the bucket needs at least two
+    pub(crate) async fn has_tombstone_heavy_sstable(&self, tuning: &BucketTuning) -> bool {
+        if tuning.tombstone_ratio <= 0.0 {
+            return false;
+        }
+        let sstables = self.sstables.read().await;
+        sstables.len() >= 2
+            && sstables.iter().any(|sst| {
+                sst.summary
+                    .as_ref()
+                    .is_some_and(|s| s.tombstone_ratio() >= tuning.tombstone_ratio)
+            })
     }
 }
 
@@ -182,12 +251,33 @@ impl BucketMap {
     pub async fn new(dir: impl AsRef<Path>) -> Result<BucketMap, Error> {
         let dir = dir.as_ref();
         FileNode::create_dir_all(dir.to_path_buf()).await?;
+        let manifest = Manifest::open(dir).await?;
         Ok(Self {
             dir: dir.to_path_buf(),
             buckets: IndexMap::new(),
+            manifest,
+            tuning: Default::default(),
+            persist_filter_bits: Arc::new(AtomicBool::new(DEFAULT_PERSIST_FILTER_BITS)),
+            keyring: Default::default(),
         })
     }
 
+    /// Returns thresholds currently used to group and compact SSTables
+    pub(crate) fn tuning(&self) -> BucketTuning {
+        self.tuning.read().unwrap().clone()
+    }
+
+    /// Records `added` SSTables as live and `removed` ones as obsolete in the manifest
+    ///
+    /// SSTables must be written to disk before they are published
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn publish(&mut self, added: &[PathBuf], removed: &[PathBuf]) -> Result<(), Error> {
+        self.manifest.apply(added, removed).await
+    }
+
     /// Inserts merged sstable or memtable to a bucket
     ///
     /// Tables to be inserted to bucket must have the `InsertableToBucket` trait
@@ -201,8 +291,13 @@ impl BucketMap {
         &mut self,
         table: Arc<Box<T>>,
     ) -> Result<Table, Error> {
-        for (_, bucket) in self.buckets.iter() {
-            if bucket.fits_into_bucket(table.clone()) {
+        let tuning = self.tuning();
+        for (id, bucket) in self.buckets.iter() {
+            // dedicated buckets only take entries of their prefix
+            if tuning.pin_of(*id).is_some() {
+                continue;
+            }
+            if bucket.fits_into_bucket(table.clone(), &tuning) {
                 return self
                     .insert_to_bucket(bucket.to_owned(), table, InsertionType::Exisiting)
                     .await;
@@ -213,6 +308,68 @@ impl BucketMap {
         self.insert_to_bucket(bucket, table, InsertionType::New).await
     }
 
+    /// Inserts merged sstable or memtable to bucket `id`, which is created if it does not exist
+    ///
+    /// Used for buckets dedicated to a pinned prefix, see `BucketPin`
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there was an IO error or any kind of Error
+    pub(crate) async fn insert_to_pinned_bucket<T: InsertableToBucket + ?Sized>(
+        &mut self,
+        id: BucketID,
+        table: Arc<Box<T>>,
+    ) -> Result<Table, Error> {
+        match self.buckets.get(&id) {
+            Some(bucket) => {
+                self.insert_to_bucket(bucket.to_owned(), table, InsertionType::Exisiting)
+                    .await
+            }
+            None => {
+                let bucket = Bucket::with_id(self.dir.clone(), id).await?;
+                self.insert_to_bucket(bucket, table, InsertionType::New).await
+            }
+        }
+    }
+
+    /// Inserts flushed memtable to buckets, entries under a pinned prefix go to its bucket
+    ///
+    /// Returns one `Table` per bucket written to
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there was an IO error or any kind of Error
+    pub(crate) async fn insert_flushed<T: InsertableToBucket + ?Sized>(
+        &mut self,
+        table: Arc<Box<T>>,
+    ) -> Result<Vec<Table>, Error> {
+        let tuning = self.tuning();
+        if tuning.pins.is_empty() {
+            return Ok(vec![self.insert_to_appropriate_bucket(table).await?]);
+        }
+        let mut parts: IndexMap<Option<BucketID>, SkipMapEntries<Key>> = IndexMap::new();
+        for entry in table.get_entries().iter() {
+            let pin = tuning.pin_for(entry.key()).map(|pin| pin.bucket);
+            parts
+                .entry(pin)
+                .or_insert_with(|| Arc::new(SkipMap::new()))
+                .insert(entry.key().to_owned(), entry.value().to_owned());
+        }
+        let false_positive_rate = table.get_filter().false_positive_rate;
+        let mut ssts = Vec::with_capacity(parts.len());
+        for (pin, entries) in parts {
+            let mut filter = BloomFilter::new(false_positive_rate, entries.len());
+            filter.build_filter_from_entries(&entries);
+            let part = Arc::new(Box::new(TableInsertor::from(entries, &filter)));
+            let sst = match pin {
+                Some(id) => self.insert_to_pinned_bucket(id, part).await?,
+                None => self.insert_to_appropriate_bucket(part).await?,
+            };
+            ssts.push(sst);
+        }
+        Ok(ssts)
+    }
+
     /// Determines which bucket to insert merged sstable or memtable based on `InsertionType`
     ///
     /// Returns Result `Table` or `Err`
@@ -226,15 +383,30 @@ impl BucketMap {
         table: Arc<Box<T>>,
         insert_type: InsertionType,
     ) -> Result<Table, Error> {
-        let created_at = Utc::now();
         let sst_dir = bucket
             .dir
-            .join(format!("{}_{}", SST_PREFIX, created_at.timestamp_millis()));
-        let mut sst = Table::new(sst_dir).await?;
+            .join(Table::dir_name(self.manifest.allocate_file_number()));
+        // write to a temporary directory so a crash never leaves a partial sstable behind
+        let cipher = self.keyring.new_table();
+        if let Some(cipher) = cipher.as_ref() {
+            self.manifest.record_table_key(&sst_dir, cipher.key);
+        }
+        let mut sst = Table::new(sst_dir.with_extension(TEMP_SSTABLE_EXTENSION), cipher).await?;
 
-        sst.set_entries(table.get_entries());
-        sst.filter = Some(table.get_filter());
-        sst.write_to_file().await?;
+        let entries = table.get_entries();
+        let mut filter = table.get_filter();
+        // colder, larger sstables can trade filter accuracy for memory
+        if let Some(rate) = self.tuning().false_positive_rate_for(table.size()) {
+            if rate != filter.false_positive_rate {
+                filter = BloomFilter::new(rate, entries.len());
+                filter.build_filter_from_entries(&entries);
+            }
+        }
+        sst.set_entries(entries);
+        sst.filter = Some(filter);
+        sst.write_to_file(self.persist_filter_bits.load(Ordering::Relaxed))
+            .await?;
+        sst.install(sst_dir).await?;
         bucket.sstables.write().await.push(sst.to_owned());
 
         match insert_type {
@@ -246,12 +418,6 @@ impl BucketMap {
                 self.buckets.insert(bucket.id, bucket);
             }
             InsertionType::Exisiting => {
-                bucket
-                    .sstables
-                    .write()
-                    .await
-                    .iter_mut()
-                    .for_each(|s| s.increase_hotness());
                 bucket.avarage_size = Bucket::cal_average_size(bucket.sstables.read().await.to_vec()).await?;
                 bucket.size = bucket.avarage_size * bucket.sstables.read().await.len();
                 self.buckets.insert(bucket.id, bucket);
@@ -270,9 +436,10 @@ impl BucketMap {
     pub(crate) async fn extract_imbalanced_buckets(&self) -> ImbalancedBuckets {
         let mut ssts_to_delete: SSTablesToRemove = Vec::new();
         let mut imbalanced_buckets: Vec<Bucket> = Vec::new();
+        let tuning = self.tuning();
 
         for (bucket_id, bucket) in self.buckets.iter() {
-            let (ssts, avg) = Bucket::extract_sstables(bucket).await?;
+            let (ssts, avg) = Bucket::extract_sstables(bucket, &tuning).await?;
 
             if !ssts.is_empty() {
                 ssts_to_delete.push((*bucket_id, ssts.clone()));
@@ -285,13 +452,99 @@ impl BucketMap {
                 });
             }
         }
+        // buckets serving the most reads are merged first
+        imbalanced_buckets.sort_by_cached_key(|bucket| {
+            let hotness: u64 = ssts_to_delete
+                .iter()
+                .filter(|(id, _)| *id == bucket.id)
+                .flat_map(|(_, ssts)| ssts.iter())
+                .map(|sst| sst.get_hotness())
+                .sum();
+            std::cmp::Reverse(hotness)
+        });
         Ok((imbalanced_buckets, ssts_to_delete))
     }
 
+    /// Returns a snapshot of every bucket for a custom [`CompactionStrategy`]
+    ///
+    /// [`CompactionStrategy`]: crate::compactors::CompactionStrategy
+    pub(crate) async fn bucket_infos(&self) -> Vec<BucketInfo> {
+        let mut infos = Vec::with_capacity(self.buckets.len());
+        for (bucket_id, bucket) in self.buckets.iter() {
+            let sstables = bucket
+                .sstables
+                .read()
+                .await
+                .iter()
+                .map(|sst| {
+                    let properties = sst.properties();
+                    SSTableInfo {
+                        dir: sst.dir.to_owned(),
+                        size: sst.size,
+                        entry_count: properties.map_or(0, |p| p.entry_count),
+                        tombstone_count: properties.map_or(0, |p| p.tombstone_count),
+                        created_at: sst.created_at,
+                        hotness: sst.get_hotness(),
+                    }
+                })
+                .collect();
+            infos.push(BucketInfo {
+                id: *bucket_id,
+                dir: bucket.dir.to_owned(),
+                sstables,
+            });
+        }
+        infos
+    }
+
+    /// Returns buckets and sstables to remove for the SSTables selected by `job`
+    ///
+    /// Unknown buckets and SSTables are ignored, so are inputs left with less than
+    /// two SSTables since merging a single SSTable gains nothing
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case an error occurs while calculating average
+    pub(crate) async fn extract_job(&self, job: &CompactionJob) -> ImbalancedBuckets {
+        let mut ssts_to_delete: SSTablesToRemove = Vec::new();
+        let mut selected_buckets: Vec<Bucket> = Vec::new();
+
+        for input in job.inputs.iter() {
+            let bucket = match self.buckets.get(&input.bucket) {
+                Some(bucket) => bucket,
+                None => continue,
+            };
+            let ssts: Vec<Table> = bucket
+                .sstables
+                .read()
+                .await
+                .iter()
+                .filter(|sst| input.sstables.contains(&sst.dir))
+                .cloned()
+                .collect();
+            if ssts.len() < 2 {
+                continue;
+            }
+            let avg = Bucket::cal_average_size(ssts.clone()).await?;
+            ssts_to_delete.push((input.bucket, ssts.clone()));
+            selected_buckets.push(Bucket {
+                size: avg * ssts.len(),
+                sstables: Arc::new(RwLock::new(ssts)),
+                id: input.bucket,
+                dir: bucket.dir.to_owned(),
+                avarage_size: avg,
+            });
+        }
+        Ok((selected_buckets, ssts_to_delete))
+    }
+
     /// Checks if a [`Bucket`] is balanced
     pub(crate) async fn is_balanced(&self) -> bool {
+        let tuning = self.tuning();
         for (_, bucket) in self.buckets.iter() {
-            if bucket.sstable_count_exceeds_threshhold().await {
+            if bucket.sstable_count_exceeds_threshhold(&tuning).await
+                || bucket.has_tombstone_heavy_sstable(&tuning).await
+            {
                 return false;
             }
         }
@@ -309,11 +562,25 @@ impl BucketMap {
         let mut all_ssts_deleted = true;
         let mut buckets_to_delete: Vec<&BucketID> = Vec::new();
 
+        // mark sstables obsolete before removing them so a crash midway does not reload them
+        let obsolete: Vec<PathBuf> = ssts_to_delete
+            .iter()
+            .flat_map(|(_, ssts)| ssts.iter().map(|sst| sst.dir.to_owned()))
+            .collect();
+        self.publish(&[], &obsolete).await?;
+
         for (bucket_id, ssts) in ssts_to_delete {
             if let Some(bucket) = self.buckets.get_mut(bucket_id) {
                 let bucket_clone = bucket.clone();
-                let b = bucket_clone.sstables.read().await;
-                let ssts_remaining = b.get(ssts.len()..).unwrap_or_default();
+                // custom strategies can pick any sstables, not only the oldest ones
+                let ssts_remaining: Vec<Table> = bucket_clone
+                    .sstables
+                    .read()
+                    .await
+                    .iter()
+                    .filter(|s| !ssts.iter().any(|removed| removed.id() == s.id()))
+                    .cloned()
+                    .collect();
                 if !ssts_remaining.is_empty() {
                     let new_average = Bucket::cal_average_size(ssts_remaining.to_vec()).await?;
                     *bucket = Bucket {
@@ -321,7 +588,7 @@ impl BucketMap {
                         size: new_average * ssts_remaining.len(),
                         dir: bucket.dir.clone(),
                         avarage_size: new_average,
-                        sstables: Arc::new(RwLock::new(ssts_remaining.to_vec())),
+                        sstables: Arc::new(RwLock::new(ssts_remaining)),
                     };
                 } else {
                     buckets_to_delete.push(bucket_id);
@@ -333,6 +600,13 @@ impl BucketMap {
 
             for sst in ssts {
                 if fs::metadata(&sst.dir).await.is_ok() {
+                    // open files keep the directory from being deleted on Windows, a
+                    // failed delete leaves an orphan that is removed later
+                    #[cfg(windows)]
+                    {
+                        sst.data_file.file.node.close().await;
+                        sst.index_file.file.node.close().await;
+                    }
                     if let Err(err) = fs::remove_dir_all(&sst.dir).await {
                         all_ssts_deleted = false;
                         log::error!("{}", DirDelete(err));
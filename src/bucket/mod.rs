@@ -1,7 +1,9 @@
 pub(crate) mod bucket_manager;
+mod tuning;
 pub use bucket_manager::Bucket;
 pub use bucket_manager::BucketID;
 pub use bucket_manager::BucketMap;
 pub use bucket_manager::ImbalancedBuckets;
 pub use bucket_manager::InsertableToBucket;
 pub use bucket_manager::SSTablesToRemove;
+pub(crate) use tuning::BucketTuning;
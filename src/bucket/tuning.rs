@@ -0,0 +1,137 @@
+use super::BucketID;
+use crate::{
+    cfg::Config,
+    consts::{
+        BUCKET_HIGH, BUCKET_LOW, DEFAULT_TOMBSTONE_COMPACTION_RATIO, MAX_TRESHOLD, MIN_SSTABLE_SIZE,
+        MIN_TRESHOLD,
+    },
+    types::Key,
+};
+use uuid::Uuid;
+
+/// Thresholds used to group SSTables into buckets and pick them for compaction
+///
+/// Wider size ranges and higher merge thresholds mean fewer compactions (lower write
+/// amplification) at the cost of more SSTables to search (higher read amplification)
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct BucketTuning {
+    /// Table fits into a bucket if its size is above `bucket_low` times the bucket average size
+    pub bucket_low: f64,
+
+    /// Table fits into a bucket if its size is below `bucket_high` times the bucket average size
+    pub bucket_high: f64,
+
+    /// Tables and buckets below this size in bytes are grouped together regardless of ratio
+    pub min_sstable_size: usize,
+
+    /// Minimum number of tables in a bucket before it is compacted
+    pub min_tables_to_merge: usize,
+
+    /// Maximum number of tables of a bucket merged in one compaction
+    pub max_tables_to_merge: usize,
+
+    /// Bucket is compacted below `min_tables_to_merge` once a table has this share of
+    /// tombstones, 0.0 disables deletion triggered compaction
+    pub tombstone_ratio: f64,
+
+    /// Filter false positive rates of SSTables at least the paired size in bytes,
+    /// sorted by size
+    pub filter_false_positive_tiers: Vec<(usize, f64)>,
+
+    /// Key prefixes whose flushed entries are kept in dedicated buckets
+    pub pins: Vec<BucketPin>,
+}
+
+/// Key prefix whose entries are flushed and compacted in a bucket of their own
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct BucketPin {
+    /// Prefix as written by the user
+    pub prefix: Key,
+
+    /// Prefix as stored, see `Comparator::encode_prefix`
+    pub encoded: Key,
+
+    /// Id of the dedicated bucket, derived from `encoded` so it is the same after restart
+    pub bucket: BucketID,
+}
+
+impl BucketPin {
+    /// Creates `BucketPin` of `prefix` stored as `encoded`
+    pub fn new(prefix: Key, encoded: Key) -> Self {
+        // FNV-1a, stable across builds unlike the std hasher
+        let mut hash: u128 = 0x6c62272e07bb014262b821756295c58d;
+        for byte in encoded.iter() {
+            hash ^= *byte as u128;
+            hash = hash.wrapping_mul(0x0000000001000000000000000000013B);
+        }
+        Self {
+            prefix,
+            encoded,
+            bucket: Uuid::from_u128(hash),
+        }
+    }
+}
+
+impl Default for BucketTuning {
+    fn default() -> Self {
+        Self {
+            bucket_low: BUCKET_LOW,
+            bucket_high: BUCKET_HIGH,
+            min_sstable_size: MIN_SSTABLE_SIZE,
+            min_tables_to_merge: MIN_TRESHOLD,
+            max_tables_to_merge: MAX_TRESHOLD,
+            tombstone_ratio: DEFAULT_TOMBSTONE_COMPACTION_RATIO,
+            filter_false_positive_tiers: Vec::new(),
+            pins: Vec::new(),
+        }
+    }
+}
+
+impl From<&Config> for BucketTuning {
+    fn from(config: &Config) -> Self {
+        Self {
+            bucket_low: config.bucket_low,
+            bucket_high: config.bucket_high,
+            min_sstable_size: config.min_sstable_size,
+            min_tables_to_merge: config.min_tables_to_merge,
+            max_tables_to_merge: config.max_tables_to_merge,
+            tombstone_ratio: config.tombstone_compaction_ratio,
+            filter_false_positive_tiers: config.filter_false_positive_tiers.to_owned(),
+            pins: config
+                .pinned_prefixes
+                .iter()
+                .map(|prefix| {
+                    BucketPin::new(
+                        prefix.to_owned(),
+                        config.comparator.encode_prefix(prefix).into_owned(),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+impl BucketTuning {
+    /// Returns false positive rate of the largest tier `size` reaches, `None` if
+    /// it is below every tier
+    pub fn false_positive_rate_for(&self, size: usize) -> Option<f64> {
+        self.filter_false_positive_tiers
+            .iter()
+            .rev()
+            .find(|(min_size, _)| size >= *min_size)
+            .map(|(_, rate)| *rate)
+    }
+
+    /// Returns pin of the longest pinned prefix `key` starts with, `key` is encoded
+    pub fn pin_for(&self, key: &[u8]) -> Option<&BucketPin> {
+        self.pins
+            .iter()
+            .filter(|pin| key.starts_with(&pin.encoded))
+            .max_by_key(|pin| pin.encoded.len())
+    }
+
+    /// Returns pin of bucket `id`, `None` if it is not a dedicated bucket
+    pub fn pin_of(&self, id: BucketID) -> Option<&BucketPin> {
+        self.pins.iter().find(|pin| pin.bucket == id)
+    }
+}
@@ -0,0 +1,256 @@
+use crate::{
+    filter::BloomFilter,
+    sst::{Hotness, SstId},
+};
+use bit_vec::BitVec;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+/// Bloom filter of a live SSTable tracked by the cache
+#[derive(Debug)]
+struct CachedFilter {
+    /// Bit vector shared with every clone of the filter
+    bits: Arc<Mutex<BitVec>>,
+
+    /// Bytes held by the bit vector
+    size: usize,
+
+    /// Last access tick, used to find least recently used filters
+    tick: u64,
+
+    /// Sampled read hits of the SSTable, colder filters are evicted first
+    hotness: Hotness,
+}
+
+#[derive(Debug, Default)]
+struct FilterCacheInner {
+    filters: HashMap<SstId, CachedFilter>,
+
+    /// Maps access tick to SSTable, the first entry is the least recently used filter
+    recency: BTreeMap<u64, SstId>,
+
+    /// SSTables whose filters were evicted
+    evicted: HashSet<SstId>,
+
+    /// Bytes currently held by filters
+    size: usize,
+
+    /// Maximum bytes held by filters, 0 means no limit
+    capacity: usize,
+
+    /// Drop filters of new SSTables until they are read
+    load_on_read: bool,
+
+    /// Monotonic counter incremented on every access
+    tick: u64,
+}
+
+/// Accounts memory held by SSTable bloom filters
+///
+/// Once a capacity is set, filters of the SSTables with the fewest sampled
+/// read hits are evicted to stay under it, the least recently used first
+/// among equally hot ones. Evicted filters give up their bits and report
+/// every key as possibly present, so lookups fall back to the SSTable index
+#[derive(Clone, Debug, Default)]
+pub struct FilterCache {
+    inner: Arc<Mutex<FilterCacheInner>>,
+}
+
+impl FilterCache {
+    /// Creates new `FilterCache` holding up to `capacity` bytes, 0 means no limit
+    pub fn new(capacity: usize) -> Self {
+        let cache = Self::default();
+        cache.set_capacity(capacity);
+        cache
+    }
+
+    /// Sets maximum bytes held by filters, evicting filters if needed
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.capacity = capacity;
+        inner.evict_to_capacity();
+    }
+
+    /// Sets whether filters of new SSTables are dropped until the SSTable is read
+    pub fn set_load_on_read(&self, load_on_read: bool) {
+        self.inner.lock().unwrap().load_on_read = load_on_read;
+    }
+
+    /// Returns `true` if filters of new SSTables are dropped until the SSTable is read
+    pub fn loads_on_read(&self) -> bool {
+        self.inner.lock().unwrap().load_on_read
+    }
+
+    /// Tracks filter of SSTable `id` read as often as `hotness`, replacing the previous one
+    pub(crate) fn insert(&self, id: SstId, filter: &BloomFilter, hotness: &Hotness) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(id);
+        inner.tick += 1;
+        let tick = inner.tick;
+        let size = filter.memory_size();
+        inner.recency.insert(tick, id);
+        inner.filters.insert(
+            id,
+            CachedFilter {
+                bits: Arc::clone(&filter.bit_vec),
+                size,
+                tick,
+                hotness: hotness.to_owned(),
+            },
+        );
+        inner.size += size;
+        inner.evict_to_capacity();
+    }
+
+    /// Marks filter of SSTable `id` as recently used
+    pub(crate) fn touch(&self, id: SstId) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        if let Some(filter) = inner.filters.get_mut(&id) {
+            let previous_tick = filter.tick;
+            filter.tick = tick;
+            inner.recency.remove(&previous_tick);
+            inner.recency.insert(tick, id);
+        }
+    }
+
+    /// Stops tracking filter of SSTable `id`
+    pub(crate) fn remove(&self, id: SstId) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.remove(id);
+    }
+
+    /// Returns bytes held by tracked filters
+    pub fn memory_usage(&self) -> usize {
+        self.inner.lock().unwrap().size
+    }
+
+    /// Returns number of SSTables whose filters were evicted
+    pub fn evicted(&self) -> usize {
+        self.inner.lock().unwrap().evicted.len()
+    }
+}
+
+impl FilterCacheInner {
+    fn remove(&mut self, id: SstId) {
+        self.evicted.remove(&id);
+        if let Some(filter) = self.filters.remove(&id) {
+            self.recency.remove(&filter.tick);
+            self.size -= filter.size;
+        }
+    }
+
+    /// Evicts coldest filters until size is within capacity
+    fn evict_to_capacity(&mut self) {
+        if self.capacity == 0 {
+            return;
+        }
+        while self.size > self.capacity {
+            // recency is in tick order so the least recently used of the coldest wins ties
+            let Some((tick, _)) = self
+                .recency
+                .iter()
+                .min_by_key(|(_, id)| self.filters.get(*id).map_or(0, |filter| filter.hotness.get()))
+                .map(|(tick, id)| (*tick, *id))
+            else {
+                break;
+            };
+            let id = self.recency.remove(&tick).unwrap();
+            if let Some(filter) = self.filters.remove(&id) {
+                self.size -= filter.size;
+                // drop the bits for every clone of the filter
+                *filter.bits.lock().unwrap() = BitVec::new();
+                self.evicted.insert(id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter() -> BloomFilter {
+        let mut filter = BloomFilter::new(0.01, 100);
+        filter.set(&b"apple".to_vec());
+        filter
+    }
+
+    #[test]
+    fn test_filter_cache_accounts_memory() {
+        let cache = FilterCache::new(0);
+        let first = filter();
+        cache.insert(SstId::new(1), &first, &Hotness::default());
+        cache.insert(SstId::new(2), &filter(), &Hotness::default());
+        assert_eq!(cache.memory_usage(), 2 * first.memory_size());
+
+        cache.remove(SstId::new(1));
+        assert_eq!(cache.memory_usage(), first.memory_size());
+        assert_eq!(cache.evicted(), 0);
+    }
+
+    #[test]
+    fn test_filter_cache_evicts_least_recently_used() {
+        let size = filter().memory_size();
+        let cache = FilterCache::new(2 * size);
+        let (a, b, c) = (filter(), filter(), filter());
+        cache.insert(SstId::new(1), &a, &Hotness::default());
+        cache.insert(SstId::new(2), &b, &Hotness::default());
+
+        // touch `a` so `b` becomes least recently used
+        cache.touch(SstId::new(1));
+        cache.insert(SstId::new(3), &c, &Hotness::default());
+
+        assert_eq!(cache.memory_usage(), 2 * size);
+        assert_eq!(cache.evicted(), 1);
+        assert_eq!(b.num_bits(), 0);
+        assert!(a.num_bits() > 0);
+        assert!(c.num_bits() > 0);
+
+        // evicted filter can no longer rule out keys
+        assert!(b.contains(&b"banana".to_vec()));
+        assert!(a.contains(&b"apple".to_vec()));
+
+        cache.remove(SstId::new(2));
+        assert_eq!(cache.evicted(), 0);
+    }
+
+    #[test]
+    fn test_filter_cache_evicts_coldest_first() {
+        let size = filter().memory_size();
+        let cache = FilterCache::new(2 * size);
+        let (a, b, c) = (filter(), filter(), filter());
+        let warming = Hotness::default();
+        cache.insert(SstId::new(1), &a, &Hotness::new(3));
+        cache.insert(SstId::new(2), &b, &Hotness::default());
+
+        // `a` is least recently used but read more often than `b`
+        cache.touch(SstId::new(2));
+        cache.insert(SstId::new(3), &c, &warming);
+        assert_eq!(b.num_bits(), 0);
+        assert!(a.num_bits() > 0);
+
+        // hits sampled after insertion count on the next eviction
+        warming.add(5);
+        cache.set_capacity(size);
+        assert_eq!(a.num_bits(), 0);
+        assert!(c.num_bits() > 0);
+    }
+
+    #[test]
+    fn test_filter_cache_set_capacity() {
+        let size = filter().memory_size();
+        let cache = FilterCache::new(0);
+        let (a, b) = (filter(), filter());
+        cache.insert(SstId::new(1), &a, &Hotness::default());
+        cache.insert(SstId::new(2), &b, &Hotness::default());
+
+        cache.set_capacity(size);
+        assert_eq!(cache.memory_usage(), size);
+        assert_eq!(cache.evicted(), 1);
+        assert_eq!(a.num_bits(), 0);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{consts::INDEX_CACHE_CAPACITY, index::BlockOffset, types::Key};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+/// Index lookup result stored in the cache
+#[derive(Debug)]
+struct CachedLookup {
+    /// Block that can hold the key, `None` if the index ruled the key out
+    offset: Option<BlockOffset>,
+
+    /// Last access tick, used to find least recently used lookups
+    tick: u64,
+}
+
+#[derive(Debug, Default)]
+struct IndexCacheInner {
+    lookups: HashMap<Key, CachedLookup>,
+
+    /// Maps access tick to key, the first entry is the least recently used lookup
+    recency: BTreeMap<u64, Key>,
+
+    /// Monotonic counter incremented on every access
+    tick: u64,
+}
+
+/// Least recently used cache of index lookups of a single SSTable
+///
+/// SSTables are immutable so a lookup never goes stale, repeated reads of a
+/// hot key skip the index file. Clones of a table share the cache
+#[derive(Clone, Debug)]
+pub struct IndexCache {
+    inner: Arc<Mutex<IndexCacheInner>>,
+
+    /// Maximum number of lookups held by the cache
+    capacity: usize,
+}
+
+impl Default for IndexCache {
+    fn default() -> Self {
+        Self::new(INDEX_CACHE_CAPACITY)
+    }
+}
+
+impl IndexCache {
+    /// Creates new `IndexCache` holding up to `capacity` lookups, 0 disables the cache
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(IndexCacheInner::default())),
+            capacity,
+        }
+    }
+
+    /// Returns cached lookup for `key` and marks it as recently used
+    ///
+    /// Outer `None` means the key was not looked up yet
+    pub fn get<T: AsRef<[u8]>>(&self, key: T) -> Option<Option<BlockOffset>> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let lookup = inner.lookups.get_mut(key.as_ref())?;
+        let previous_tick = lookup.tick;
+        lookup.tick = tick;
+        let offset = lookup.offset;
+        // move the key already held to the new tick instead of copying it
+        let key = inner
+            .recency
+            .remove(&previous_tick)
+            .unwrap_or_else(|| key.as_ref().to_vec());
+        inner.recency.insert(tick, key);
+        Some(offset)
+    }
+
+    /// Caches `offset` found for `key`, evicting the least recently used lookup if full
+    pub fn insert<T: AsRef<[u8]>>(&self, key: T, offset: Option<BlockOffset>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(previous) = inner.lookups.remove(key.as_ref()) {
+            inner.recency.remove(&previous.tick);
+        }
+        if inner.lookups.len() >= self.capacity {
+            if let Some((_, oldest)) = inner.recency.pop_first() {
+                inner.lookups.remove(&oldest);
+            }
+        }
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.recency.insert(tick, key.as_ref().to_vec());
+        inner
+            .lookups
+            .insert(key.as_ref().to_vec(), CachedLookup { offset, tick });
+    }
+
+    /// Returns number of cached lookups
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().lookups.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_cache_evicts_least_recently_used() {
+        let cache = IndexCache::new(2);
+        cache.insert("apple", Some(0));
+        cache.insert("google", None);
+        assert_eq!(cache.get("apple"), Some(Some(0)));
+        assert_eq!(cache.get("google"), Some(None));
+        assert_eq!(cache.get("nvidia"), None);
+
+        // google is now the least recently used
+        cache.get("apple");
+        cache.insert("nvidia", Some(4096));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("google"), None);
+        assert_eq!(cache.get("nvidia"), Some(Some(4096)));
+
+        let disabled = IndexCache::new(0);
+        disabled.insert("apple", Some(0));
+        assert_eq!(disabled.get("apple"), None);
+    }
+}
@@ -0,0 +1,6 @@
+mod filter_cache;
+mod index_cache;
+mod row_cache;
+pub use filter_cache::FilterCache;
+pub use index_cache::IndexCache;
+pub use row_cache::RowCache;
@@ -0,0 +1,258 @@
+use crate::{memtable::UserEntryRef, types::Key};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+/// Entry stored in the row cache
+#[derive(Debug)]
+struct CachedRow {
+    entry: UserEntryRef,
+
+    /// Last access tick, used to find least recently used rows
+    tick: u64,
+}
+
+#[derive(Debug, Default)]
+struct RowCacheInner {
+    rows: HashMap<Key, CachedRow>,
+
+    /// Maps access tick to key, the first entry is the least recently used row
+    recency: BTreeMap<u64, Key>,
+
+    /// Bytes (key + value) currently held by the cache
+    size: usize,
+
+    /// Monotonic counter incremented on every access
+    tick: u64,
+
+    /// Incremented on every invalidation, see [`RowCache::generation`]
+    generation: u64,
+}
+
+/// Least recently used cache of resolved values keyed by user key
+///
+/// Values are cached after they have been read from the value log so hits skip
+/// both SSTables and value log. Writes to a key must invalidate it once the
+/// new entry is in the memtable. Readers cache what they resolved with
+/// [`RowCache::insert_if_unchanged`], so a value read before an overlapping
+/// write is not cached after the write invalidated the key.
+///
+/// A capacity of 0 disables the cache
+#[derive(Clone, Debug)]
+pub struct RowCache {
+    inner: Arc<Mutex<RowCacheInner>>,
+
+    /// Maximum bytes (key + value) held by the cache
+    capacity: usize,
+}
+
+impl RowCache {
+    /// Creates new `RowCache` holding up to `capacity` bytes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(RowCacheInner::default())),
+            capacity,
+        }
+    }
+
+    /// Returns `true` if the cache can hold entries
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Returns cached entry for `key` and marks it as recently used
+    ///
+    /// The returned value shares the cached buffer
+    pub fn get<T: AsRef<[u8]>>(&self, key: T) -> Option<UserEntryRef> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let row = inner.rows.get_mut(key.as_ref())?;
+        let previous_tick = row.tick;
+        row.tick = tick;
+        let entry = row.entry.to_owned();
+        // move the key already held to the new tick instead of copying it
+        let key = inner
+            .recency
+            .remove(&previous_tick)
+            .unwrap_or_else(|| key.as_ref().to_vec());
+        inner.recency.insert(tick, key);
+        Some(entry)
+    }
+
+    /// Caches `entry` for `key`, evicting least recently used rows if needed
+    ///
+    /// Rows bigger than the cache capacity are not cached
+    #[cfg(test)]
+    pub fn insert<T: AsRef<[u8]>>(&self, key: T, entry: UserEntryRef) {
+        let charge = key.as_ref().len() + entry.val.len();
+        if !self.is_enabled() || charge > self.capacity {
+            return;
+        }
+        self.inner
+            .lock()
+            .unwrap()
+            .insert(key.as_ref(), entry, charge, self.capacity);
+    }
+
+    /// Caches `entry` for `key` unless a key was invalidated since `generation`
+    /// was taken, see [`RowCache::generation`]. Least recently used rows are
+    /// evicted if needed, rows bigger than the cache capacity are not cached
+    ///
+    /// Returns `true` if the entry was cached
+    pub fn insert_if_unchanged<T: AsRef<[u8]>>(&self, key: T, entry: UserEntryRef, generation: u64) -> bool {
+        let charge = key.as_ref().len() + entry.val.len();
+        if !self.is_enabled() || charge > self.capacity {
+            return false;
+        }
+        // held across the check and the insert, an invalidation cannot land in between
+        let mut inner = self.inner.lock().unwrap();
+        if inner.generation != generation {
+            return false;
+        }
+        inner.insert(key.as_ref(), entry, charge, self.capacity);
+        true
+    }
+
+    /// Returns the number of invalidations so far
+    ///
+    /// Readers take it before they look a key up and pass it to
+    /// [`RowCache::insert_if_unchanged`]. Any invalidation in between skips
+    /// the insert, the resolved value may be older than the write
+    pub fn generation(&self) -> u64 {
+        self.inner.lock().unwrap().generation
+    }
+
+    /// Removes `key` from the cache
+    pub fn invalidate<T: AsRef<[u8]>>(&self, key: T) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.generation += 1;
+        inner.remove(key.as_ref());
+    }
+
+    /// Returns number of cached rows
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().rows.len()
+    }
+
+    /// Returns bytes held by the cache
+    #[cfg(test)]
+    pub fn size(&self) -> usize {
+        self.inner.lock().unwrap().size
+    }
+}
+
+impl RowCacheInner {
+    fn insert(&mut self, key: &[u8], entry: UserEntryRef, charge: usize, capacity: usize) {
+        self.remove(key);
+        while self.size + charge > capacity {
+            if !self.evict_oldest() {
+                break;
+            }
+        }
+        self.tick += 1;
+        let tick = self.tick;
+        self.recency.insert(tick, key.to_vec());
+        self.rows.insert(key.to_vec(), CachedRow { entry, tick });
+        self.size += charge;
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some(row) = self.rows.remove(key) {
+            self.recency.remove(&row.tick);
+            self.size -= key.len() + row.entry.val.len();
+        }
+    }
+
+    /// Evicts least recently used row, returns `false` if cache is empty
+    fn evict_oldest(&mut self) -> bool {
+        match self.recency.pop_first() {
+            Some((_, key)) => {
+                if let Some(row) = self.rows.remove(&key) {
+                    self.size -= key.len() + row.entry.val.len();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn entry(val: &str) -> UserEntryRef {
+        UserEntryRef::new(val.as_bytes().to_vec(), Utc::now())
+    }
+
+    #[test]
+    fn test_row_cache_get_insert_invalidate() {
+        let cache = RowCache::new(1024);
+        assert!(cache.get("apple").is_none());
+
+        cache.insert("apple", entry("tim cook"));
+        assert_eq!(cache.get("apple").unwrap().val, b"tim cook".to_vec());
+        assert_eq!(cache.size(), "apple".len() + "tim cook".len());
+
+        cache.insert("apple", entry("steve jobs"));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get("apple").unwrap().val, b"steve jobs".to_vec());
+
+        cache.invalidate("apple");
+        assert!(cache.get("apple").is_none());
+        assert_eq!(cache.size(), 0);
+    }
+
+    #[test]
+    fn test_row_cache_evicts_least_recently_used() {
+        // each row charges 2 bytes
+        let cache = RowCache::new(6);
+        cache.insert("a", entry("1"));
+        cache.insert("b", entry("2"));
+        cache.insert("c", entry("3"));
+
+        // touch `a` so `b` becomes least recently used
+        assert!(cache.get("a").is_some());
+        cache.insert("d", entry("4"));
+
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+        assert!(cache.get("d").is_some());
+        assert_eq!(cache.size(), 6);
+    }
+
+    #[test]
+    fn test_row_cache_skips_insert_after_invalidation() {
+        let cache = RowCache::new(1024);
+        cache.insert("apple", entry("tim cook"));
+
+        // a reader resolves the old value while a write invalidates the key
+        let generation = cache.generation();
+        cache.invalidate("apple");
+        assert!(!cache.insert_if_unchanged("apple", entry("tim cook"), generation));
+        assert!(cache.get("apple").is_none());
+
+        let generation = cache.generation();
+        assert!(cache.insert_if_unchanged("apple", entry("steve jobs"), generation));
+        assert_eq!(cache.get("apple").unwrap().val, b"steve jobs".to_vec());
+    }
+
+    #[test]
+    fn test_row_cache_disabled() {
+        let cache = RowCache::new(0);
+        cache.insert("a", entry("1"));
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.len(), 0);
+    }
+}
@@ -1,17 +1,38 @@
+use crate::{
+    bucket::BucketTuning,
+    cache::RowCache,
+    clock::Clock,
+    comparator::Comparator,
+    db::{DataStore, PrefixQuota, ScrubAction, SizeUnit, TtlSweeper},
+    encryption::EncryptionKey,
+    fs::FileNode,
+    types::{ConfigHash, Key},
+};
 use crate::{
     compactors,
     consts::{
-        DEFAULT_ALLOW_PREFETCH, DEFAULT_COMPACTION_FLUSH_LISTNER_INTERVAL, DEFAULT_COMPACTION_INTERVAL,
-        DEFAULT_ENABLE_TTL, DEFAULT_FALSE_POSITIVE_RATE, DEFAULT_MAX_WRITE_BUFFER_NUMBER,
-        DEFAULT_ONLINE_GC_INTERVAL, DEFAULT_PREFETCH_SIZE, DEFAULT_TOMBSTONE_COMPACTION_INTERVAL,
-        DEFAULT_TOMBSTONE_TTL, ENTRY_TTL, GC_CHUNK_SIZE, WRITE_BUFFER_SIZE,
+        BUCKET_HIGH, BUCKET_LOW, DEFAULT_ALLOW_PREFETCH, DEFAULT_COMPACTION_FLUSH_LISTNER_INTERVAL,
+        DEFAULT_COMPACTION_INTERVAL, DEFAULT_DEGRADED_FAILURE_THRESHOLD, DEFAULT_ENABLE_TTL,
+        DEFAULT_EXPIRED_COMPACTION_RATIO, DEFAULT_FALSE_POSITIVE_RATE, DEFAULT_FILTER_MEMORY_CAP,
+        DEFAULT_HEAD_CHECKPOINT_INTERVAL, DEFAULT_HEAD_CHECKPOINT_SIZE, DEFAULT_MANUAL_BACKGROUND_MODE,
+        DEFAULT_MAX_WRITE_BUFFER_NUMBER, DEFAULT_MEMTABLE_MAX_AGE, DEFAULT_ONLINE_GC_INTERVAL,
+        DEFAULT_OPTIMIZE_FILTERS_FOR_HITS, DEFAULT_ORPHAN_FILE_GRACE_PERIOD, DEFAULT_PERSIST_FILTER_BITS,
+        DEFAULT_PREFETCH_SIZE, DEFAULT_READ_ONLY_FAILURE_THRESHOLD, DEFAULT_READ_PROFILE_WINDOW,
+        DEFAULT_READ_SAMPLE_EVERY, DEFAULT_RECOVERY_PARALLELISM, DEFAULT_ROW_CACHE_SIZE, DEFAULT_SCRUB_RATE,
+        DEFAULT_SLOW_BACKGROUND_OP_THRESHOLD, DEFAULT_SLOW_OP_THRESHOLD,
+        DEFAULT_TOMBSTONE_COMPACTION_INTERVAL, DEFAULT_TOMBSTONE_COMPACTION_RATIO, DEFAULT_TOMBSTONE_TTL,
+        DEFAULT_TOTAL_WRITE_BUFFER_SIZE, DEFAULT_TTL_SWEEP_INTERVAL, DEFAULT_VALUE_DEDUP_MIN_SIZE,
+        DEFAULT_VERIFY_COMPACTION, DEFAULT_VLOG_READ_AHEAD_SIZE, ENTRY_TTL, GC_CHUNK_SIZE, MAX_TRESHOLD,
+        MIN_SSTABLE_SIZE, MIN_TRESHOLD, WRITE_BUFFER_SIZE,
     },
 };
-use crate::{
-    db::{DataStore, SizeUnit},
-    types::Key,
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
-use std::time::Duration;
 
 #[derive(Clone, Debug)]
 /// Configuration for  data store.
@@ -20,6 +41,11 @@ pub struct Config {
     /// but it incurs extra cost on the CPU for more accuracy.
     pub false_positive_rate: f64,
 
+    /// False positive rates of filters of SSTables at least the paired size in bytes.
+    /// Larger, colder SSTables can use a higher rate to save memory, SSTables below
+    /// every size use `false_positive_rate`.
+    pub filter_false_positive_tiers: Vec<(usize, f64)>,
+
     /// Should we prefetch values in case of range queries?
     pub allow_prefetch: bool,
 
@@ -59,8 +85,128 @@ pub struct Config {
     /// How many bytes should be checked in value log for garbage collection in kilobytes
     pub gc_chunk_size: usize,
 
-    /// Maximum number of files that can be opened at once
+    /// Order of keys, fixed when the store is created
+    pub comparator: Comparator,
+
+    /// Maximum number of SSTable files kept open at once, least recently used
+    /// files are closed beyond it and reopened on demand. 0 keeps every file open
     pub open_files_limit: usize,
+
+    /// Consecutive flush or compaction failures before the store is marked degraded
+    pub degraded_failure_threshold: usize,
+
+    /// Consecutive flush or compaction failures before the store becomes read-only
+    pub read_only_failure_threshold: usize,
+
+    /// The size of the row cache for recently read values in bytes, 0 disables the cache
+    pub row_cache_size: usize,
+
+    /// How many bytes of value log range scans read at once when value offsets
+    /// are ascending, 0 disables read-ahead
+    pub vlog_read_ahead_size: usize,
+
+    /// Bytes appended to value log after which the head is checkpointed,
+    /// 0 disables size based checkpoints. A checkpoint writes memtable entries
+    /// to the meta directory so recovery replays value log from it
+    pub head_checkpoint_size: usize,
+
+    /// Interval at which value log head is checkpointed while writes arrive,
+    /// zero disables time based checkpoints
+    pub head_checkpoint_interval: std::time::Duration,
+
+    /// Age after which the active memtable is sealed and flushed even if it
+    /// is not full, zero disables it
+    pub memtable_max_age: std::time::Duration,
+
+    /// Maximum bytes held by SSTable bloom filters, least recently used filters
+    /// are evicted beyond it. 0 disables the cap
+    pub filter_memory_cap: usize,
+
+    /// Drops bloom filters of flushed and compacted SSTables until they are read,
+    /// suits write heavy stores where most SSTables are never read
+    pub optimize_filters_for_hits: bool,
+
+    /// Writes bloom filter bits built by the memtable into the SSTable filter file,
+    /// recovery then loads them instead of rebuilding filters from SSTable entries
+    pub persist_filter_bits: bool,
+
+    /// Time SSTables left behind by an interrupted flush or compaction
+    /// are kept on disk before they are deleted
+    pub orphan_file_grace_period: std::time::Duration,
+
+    /// SSTable fits into a bucket if its size is above `bucket_low` times the bucket average size
+    pub bucket_low: f64,
+
+    /// SSTable fits into a bucket if its size is below `bucket_high` times the bucket average size
+    pub bucket_high: f64,
+
+    /// SSTables and buckets below this size in bytes are grouped together regardless of ratio
+    pub min_sstable_size: usize,
+
+    /// Minimum number of SSTables in a bucket before it is compacted
+    pub min_tables_to_merge: usize,
+
+    /// Maximum number of SSTables of a bucket merged in one compaction
+    pub max_tables_to_merge: usize,
+
+    /// Share of tombstones in an SSTable that gets its bucket compacted before
+    /// reaching `min_tables_to_merge`, 0.0 disables deletion triggered compaction
+    pub tombstone_compaction_ratio: f64,
+
+    /// Bytes held by the active and read-only memtables together before they are
+    /// flushed early, 0 leaves them bounded by `max_buffer_write_number` only
+    pub total_write_buffer_size: usize,
+
+    /// Gets, puts and scans taking at least this long are logged with a timing
+    /// breakdown, zero disables logging
+    pub slow_op_threshold: std::time::Duration,
+
+    /// Flushes and compactions taking at least this long are logged, zero disables logging
+    pub slow_background_op_threshold: std::time::Duration,
+
+    /// One of every `read_sample_every` gets is sampled for read profiles, 0 disables sampling
+    pub read_sample_every: usize,
+
+    /// Time over which sampled reads are counted before counts restart
+    pub read_profile_window: std::time::Duration,
+
+    /// Background flushes, compactions and GC only run when driven by
+    /// `tick_flush`, `tick_compaction` and `tick_gc`
+    pub manual_background_mode: bool,
+
+    /// Bytes of SSTable data files the background scrubber verifies per second, 0 disables it
+    pub scrub_rate: usize,
+
+    /// What the scrubber does with SSTables that fail their checksum
+    pub scrub_action: ScrubAction,
+
+    /// Values written by puts of at least this many bytes are stored once in the
+    /// value log when identical to an earlier value, 0 disables deduplication
+    pub value_dedup_min_size: usize,
+
+    /// Number of SSTables whose metadata is loaded at once when the store is opened
+    pub recovery_parallelism: usize,
+
+    /// Caps on bytes and keys stored under key prefixes, writes going over them are rejected
+    pub prefix_quotas: Vec<PrefixQuota>,
+
+    /// Key prefixes whose entries are flushed and compacted in buckets of their own
+    pub pinned_prefixes: Vec<Key>,
+
+    /// Time between background passes of the TTL sweeper, zero disables them
+    pub ttl_sweep_interval: std::time::Duration,
+
+    /// Estimated share of expired entries in an SSTable that gets it compacted by the TTL sweeper
+    pub expired_compaction_ratio: f64,
+
+    /// Reads compaction outputs back and checks them against their inputs
+    /// before the inputs are deleted
+    pub verify_compaction: bool,
+
+    /// Keys files of the store are encrypted with, see [`DataStore::open_encrypted`].
+    /// Every key files are still encrypted with has to be passed, a store that is
+    /// not encrypted yet is encrypted with the last one from then on
+    pub encryption_keys: Vec<EncryptionKey>,
 }
 
 fn get_open_file_limit() -> usize {
@@ -74,10 +220,23 @@ fn get_open_file_limit() -> usize {
     return 150;
 }
 
+/// Number of SSTables whose metadata is loaded at once when a store is opened,
+/// shared by every store in the process since recovery happens before a store can be configured
+static RECOVERY_PARALLELISM: AtomicUsize = AtomicUsize::new(DEFAULT_RECOVERY_PARALLELISM);
+
+fn recovery_parallelism() -> usize {
+    RECOVERY_PARALLELISM.load(Ordering::Relaxed)
+}
+
+pub(crate) fn set_recovery_parallelism(parallelism: usize) {
+    RECOVERY_PARALLELISM.store(parallelism, Ordering::Relaxed);
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
             false_positive_rate: DEFAULT_FALSE_POSITIVE_RATE,
+            filter_false_positive_tiers: Vec::new(),
             enable_ttl: DEFAULT_ENABLE_TTL,
             entry_ttl: ENTRY_TTL,
             allow_prefetch: DEFAULT_ALLOW_PREFETCH,
@@ -91,11 +250,57 @@ impl Default for Config {
             compaction_strategy: compactors::Strategy::STCS,
             online_gc_interval: DEFAULT_ONLINE_GC_INTERVAL,
             gc_chunk_size: GC_CHUNK_SIZE,
+            comparator: Comparator::Bytewise,
             open_files_limit: get_open_file_limit(),
+            degraded_failure_threshold: DEFAULT_DEGRADED_FAILURE_THRESHOLD,
+            read_only_failure_threshold: DEFAULT_READ_ONLY_FAILURE_THRESHOLD,
+            row_cache_size: DEFAULT_ROW_CACHE_SIZE,
+            vlog_read_ahead_size: DEFAULT_VLOG_READ_AHEAD_SIZE,
+            head_checkpoint_size: DEFAULT_HEAD_CHECKPOINT_SIZE,
+            head_checkpoint_interval: DEFAULT_HEAD_CHECKPOINT_INTERVAL,
+            memtable_max_age: DEFAULT_MEMTABLE_MAX_AGE,
+            filter_memory_cap: DEFAULT_FILTER_MEMORY_CAP,
+            optimize_filters_for_hits: DEFAULT_OPTIMIZE_FILTERS_FOR_HITS,
+            persist_filter_bits: DEFAULT_PERSIST_FILTER_BITS,
+            orphan_file_grace_period: DEFAULT_ORPHAN_FILE_GRACE_PERIOD,
+            bucket_low: BUCKET_LOW,
+            bucket_high: BUCKET_HIGH,
+            min_sstable_size: MIN_SSTABLE_SIZE,
+            min_tables_to_merge: MIN_TRESHOLD,
+            max_tables_to_merge: MAX_TRESHOLD,
+            tombstone_compaction_ratio: DEFAULT_TOMBSTONE_COMPACTION_RATIO,
+            total_write_buffer_size: DEFAULT_TOTAL_WRITE_BUFFER_SIZE,
+            slow_op_threshold: DEFAULT_SLOW_OP_THRESHOLD,
+            slow_background_op_threshold: DEFAULT_SLOW_BACKGROUND_OP_THRESHOLD,
+            read_sample_every: DEFAULT_READ_SAMPLE_EVERY,
+            read_profile_window: DEFAULT_READ_PROFILE_WINDOW,
+            manual_background_mode: DEFAULT_MANUAL_BACKGROUND_MODE,
+            scrub_rate: DEFAULT_SCRUB_RATE,
+            scrub_action: ScrubAction::default(),
+            value_dedup_min_size: DEFAULT_VALUE_DEDUP_MIN_SIZE,
+            recovery_parallelism: recovery_parallelism(),
+            prefix_quotas: Vec::new(),
+            pinned_prefixes: Vec::new(),
+            ttl_sweep_interval: DEFAULT_TTL_SWEEP_INTERVAL,
+            expired_compaction_ratio: DEFAULT_EXPIRED_COMPACTION_RATIO,
+            verify_compaction: DEFAULT_VERIFY_COMPACTION,
+            encryption_keys: Vec::new(),
         }
     }
 }
 
+impl Config {
+    /// Returns fingerprint of the configuration, the same on every run
+    pub(crate) fn fingerprint(&self) -> ConfigHash {
+        // FNV-1a over the debug representation
+        format!("{:?}", self)
+            .bytes()
+            .fold(0xcbf29ce484222325, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+            })
+    }
+}
+
 impl DataStore<'static, Key> {
     /// Sets the false positive rate for the DataStore.
     /// The rate must be greater than 0.0.
@@ -105,6 +310,27 @@ impl DataStore<'static, Key> {
         self
     }
 
+    /// Sets filter false positive rates by SSTable size, each tier pairs a
+    /// minimum size in kilobytes with the rate used for SSTables of at least
+    /// that size.
+    /// Rates are applied when filters are built at flush and compaction time,
+    /// SSTables below every tier use the default false positive rate.
+    /// Every rate must be greater than 0.0.
+    pub fn with_filter_false_positive_tiers(mut self, tiers: Vec<(usize, f64)>) -> Self {
+        assert!(
+            tiers.iter().all(|(_, rate)| *rate > 0.0),
+            "false_positive_rate must be greater than 0.0"
+        );
+        let mut tiers: Vec<(usize, f64)> = tiers
+            .into_iter()
+            .map(|(size, rate)| (SizeUnit::Kilobytes.as_bytes(size), rate))
+            .collect();
+        tiers.sort_by_key(|(size, _)| *size);
+        self.config.filter_false_positive_tiers = tiers;
+        *self.bucket_tuning.write().unwrap() = BucketTuning::from(&self.config);
+        self
+    }
+
     /// Enables or disables prefetching.
     pub fn with_allow_prefetch(mut self, allow: bool) -> Self {
         self.config.allow_prefetch = allow;
@@ -122,14 +348,10 @@ impl DataStore<'static, Key> {
         self
     }
 
-    /// Sets the write buffer size in kilobytes.
+    /// Sets the write buffer size in kilobytes, see [`DataStore::set_write_buffer_size`].
     /// The size must be at least 50 kilobytes.
     pub fn with_write_buffer_size(mut self, size: usize) -> Self {
-        assert!(
-            size >= 50,
-            "write_buffer_size should not be less than 50 Kilobytes"
-        );
-        self.config.write_buffer_size = SizeUnit::Kilobytes.as_bytes(size);
+        self.set_write_buffer_size(size);
         self
     }
 
@@ -144,6 +366,8 @@ impl DataStore<'static, Key> {
     /// Enables or disables TTL (Time-To-Live) for entries.
     pub fn with_enable_ttl(mut self, enable: bool) -> Self {
         self.config.enable_ttl = enable;
+        self.compactor.config.use_ttl = enable;
+        self.ttl_sweeper.set_ttl(TtlSweeper::ttl_of(&self.config));
         self
     }
 
@@ -155,6 +379,8 @@ impl DataStore<'static, Key> {
             "entry_ttl_millis cannot be less than 3 days if enable_ttl is set to true"
         );
         self.config.entry_ttl = ttl;
+        self.compactor.config.entry_ttl = ttl;
+        self.ttl_sweeper.set_ttl(TtlSweeper::ttl_of(&self.config));
         self
     }
 
@@ -166,6 +392,8 @@ impl DataStore<'static, Key> {
             "tombstone_ttl should not be less than 10 days to prevent resurrecting entries marked deleted"
         );
         self.config.tombstone_ttl = ttl;
+        self.compactor.config.tombstone_ttl = ttl;
+        self.ttl_sweeper.set_ttl(TtlSweeper::ttl_of(&self.config));
         self
     }
 
@@ -227,6 +455,391 @@ impl DataStore<'static, Key> {
         self.config.gc_chunk_size = SizeUnit::Kilobytes.as_bytes(size);
         self
     }
+
+    /// Sets how many consecutive flush or compaction failures
+    /// mark the store as degraded.
+    /// The number must be greater than 0 and not greater than read_only_failure_threshold.
+    pub fn with_degraded_failure_threshold(mut self, number: usize) -> Self {
+        assert!(
+            number > 0,
+            "degraded_failure_threshold should be greater than zero"
+        );
+        assert!(
+            number <= self.config.read_only_failure_threshold,
+            "degraded_failure_threshold should not be greater than read_only_failure_threshold"
+        );
+        self.config.degraded_failure_threshold = number;
+        self.health
+            .set_thresholds(number, self.config.read_only_failure_threshold);
+        self
+    }
+
+    /// Sets how many consecutive flush or compaction failures
+    /// make the store read-only.
+    /// The number must not be less than degraded_failure_threshold.
+    pub fn with_read_only_failure_threshold(mut self, number: usize) -> Self {
+        assert!(
+            number >= self.config.degraded_failure_threshold,
+            "read_only_failure_threshold should not be less than degraded_failure_threshold"
+        );
+        self.config.read_only_failure_threshold = number;
+        self.health
+            .set_thresholds(self.config.degraded_failure_threshold, number);
+        self
+    }
+
+    /// Sets the row cache size in kilobytes.
+    /// A size of 0 disables the row cache.
+    pub fn with_row_cache_size(mut self, size: usize) -> Self {
+        self.config.row_cache_size = SizeUnit::Kilobytes.as_bytes(size);
+        self.row_cache = RowCache::new(self.config.row_cache_size);
+        self.compactor.config.row_cache = self.row_cache.clone();
+        self
+    }
+
+    /// Sets the value log read-ahead size for range scans in kilobytes.
+    /// A size of 0 disables read-ahead.
+    pub fn with_vlog_read_ahead_size(mut self, size: usize) -> Self {
+        self.config.vlog_read_ahead_size = SizeUnit::Kilobytes.as_bytes(size);
+        self
+    }
+
+    /// Sets how many kilobytes can be appended to value log before
+    /// its head is checkpointed.
+    /// A checkpoint writes the entries of the memtables to the meta directory and
+    /// records the value log offset they cover in meta, recovery loads them and only
+    /// replays value log written after it. Memtables are left in place.
+    /// Defaults to 16 megabytes, a size of 0 disables size based checkpoints.
+    pub fn with_head_checkpoint_size(mut self, size: usize) -> Self {
+        self.config.head_checkpoint_size = SizeUnit::Kilobytes.as_bytes(size);
+        self
+    }
+
+    /// Sets the interval for value log head checkpoints.
+    /// Checkpoints are only taken on writes, see `with_head_checkpoint_size`.
+    /// Defaults to 60 seconds.
+    /// The interval must be at least 1 second, zero disables time based checkpoints.
+    pub fn with_head_checkpoint_interval(mut self, interval: std::time::Duration) -> Self {
+        assert!(
+            interval.is_zero() || interval >= Duration::from_secs(1),
+            "head_checkpoint_interval should not be less than 1 second"
+        );
+        self.config.head_checkpoint_interval = interval;
+        self
+    }
+
+    /// Sets the age after which the active memtable is sealed and flushed even if it is not full.
+    /// The age counts from the first write to the memtable, writes check it and
+    /// a background task also checks it so idle stores are flushed as well.
+    /// Zero disables it.
+    pub fn with_memtable_max_age(mut self, age: std::time::Duration) -> Self {
+        self.config.memtable_max_age = age;
+        self.memtable_age.set(age);
+        self
+    }
+
+    /// Sets the bloom filter memory cap in kilobytes.
+    /// Beyond the cap filters of least recently used SSTables are evicted and
+    /// lookups on those SSTables use the index only. A size of 0 disables the cap.
+    pub fn with_filter_memory_cap(mut self, size: usize) -> Self {
+        self.config.filter_memory_cap = SizeUnit::Kilobytes.as_bytes(size);
+        self.key_range
+            .filter_cache
+            .set_capacity(self.config.filter_memory_cap);
+        self
+    }
+
+    /// Sets whether bloom filters of new SSTables are only built once the SSTable is read.
+    /// Saves filter memory for SSTables that are never read, the first lookup of
+    /// each SSTable reads its data file to rebuild the filter.
+    pub fn with_optimize_filters_for_hits(mut self, enable: bool) -> Self {
+        self.config.optimize_filters_for_hits = enable;
+        self.key_range.filter_cache.set_load_on_read(enable);
+        self
+    }
+
+    /// Sets whether bloom filter bits are written with new SSTables.
+    /// Persisted bits cost one bit per filter bit on disk but spare recovery and
+    /// filter reloads a full read of the SSTable data file.
+    pub fn with_persist_filter_bits(mut self, enable: bool) -> Self {
+        self.config.persist_filter_bits = enable;
+        self.persist_filter_bits.store(enable, Ordering::Relaxed);
+        self
+    }
+
+    /// Sets how long orphaned SSTables are kept before they are deleted.
+    /// SSTables left behind by an interrupted flush or compaction are never
+    /// loaded, the grace period leaves time to inspect them.
+    pub fn with_orphan_file_grace_period(mut self, period: std::time::Duration) -> Self {
+        self.config.orphan_file_grace_period = period;
+        self.orphans.set_grace_period(period);
+        self
+    }
+
+    /// Sets the size ratios an SSTable must fall within, relative to a bucket
+    /// average size, to join that bucket.
+    /// Wider ratios mean fewer buckets and less write amplification, narrower
+    /// ratios merge SSTables of closer sizes.
+    /// bucket_low must be greater than 0.0 and less than 1.0, bucket_high greater than 1.0.
+    pub fn with_bucket_size_ratios(mut self, bucket_low: f64, bucket_high: f64) -> Self {
+        assert!(
+            bucket_low > 0.0 && bucket_low < 1.0 && bucket_high > 1.0,
+            "bucket_low should be between 0.0 and 1.0 and bucket_high greater than 1.0"
+        );
+        self.config.bucket_low = bucket_low;
+        self.config.bucket_high = bucket_high;
+        *self.bucket_tuning.write().unwrap() = BucketTuning::from(&self.config);
+        self
+    }
+
+    /// Sets the size in kilobytes below which SSTables are grouped together
+    /// regardless of size ratios.
+    pub fn with_min_sstable_size(mut self, size: usize) -> Self {
+        self.config.min_sstable_size = SizeUnit::Kilobytes.as_bytes(size);
+        *self.bucket_tuning.write().unwrap() = BucketTuning::from(&self.config);
+        self
+    }
+
+    /// Sets how many SSTables a bucket needs before it is compacted and
+    /// how many of them are merged at once.
+    /// Higher thresholds lower write amplification but leave more SSTables to search on reads.
+    /// min must be at least 2 and max must not be less than min.
+    pub fn with_tables_to_merge(mut self, min: usize, max: usize) -> Self {
+        assert!(min >= 2, "min_tables_to_merge should not be less than 2");
+        assert!(
+            max >= min,
+            "max_tables_to_merge should not be less than min_tables_to_merge"
+        );
+        self.config.min_tables_to_merge = min;
+        self.config.max_tables_to_merge = max;
+        *self.bucket_tuning.write().unwrap() = BucketTuning::from(&self.config);
+        self
+    }
+
+    /// Sets the share of tombstones in an SSTable that triggers compaction of its
+    /// bucket without waiting for enough SSTables, so space freed by bulk deletes
+    /// is reclaimed promptly.
+    /// The ratio must be between 0.0 and 1.0, 0.0 disables deletion triggered compaction.
+    pub fn with_tombstone_compaction_ratio(mut self, ratio: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&ratio),
+            "tombstone_compaction_ratio should be between 0.0 and 1.0"
+        );
+        self.config.tombstone_compaction_ratio = ratio;
+        *self.bucket_tuning.write().unwrap() = BucketTuning::from(&self.config);
+        self
+    }
+
+    /// Sets the memory budget in kilobytes shared by the active and read-only memtables.
+    /// Once the budget is crossed the active memtable is flushed early along with
+    /// read-only memtables waiting for a flush. A size of 0 disables the budget.
+    pub fn with_total_write_buffer_size(mut self, size: usize) -> Self {
+        self.config.total_write_buffer_size = SizeUnit::Kilobytes.as_bytes(size);
+        self.write_buffer_manager
+            .set_budget(self.config.total_write_buffer_size);
+        self
+    }
+
+    /// Sets how long a get, put or scan may take before it is logged as slow.
+    /// Slow operations are logged as warnings with the time spent searching memtables,
+    /// checking filters, reading indexes, data blocks and the value log.
+    /// A threshold of zero disables logging.
+    pub fn with_slow_op_threshold(mut self, threshold: std::time::Duration) -> Self {
+        self.config.slow_op_threshold = threshold;
+        self.slow_log.set_foreground(threshold);
+        self
+    }
+
+    /// Sets how long a flush or compaction may take before it is logged as slow.
+    /// A threshold of zero disables logging.
+    pub fn with_slow_background_op_threshold(mut self, threshold: std::time::Duration) -> Self {
+        self.config.slow_background_op_threshold = threshold;
+        self.slow_log.set_background(threshold);
+        self
+    }
+
+    /// Samples one of every `every` gets to profile which SSTables, buckets and
+    /// key prefixes serve reads, counts restart every `window`.
+    /// Profiles are returned by `read_profile`. An `every` of 0 disables sampling.
+    pub fn with_read_sampling(mut self, every: usize, window: std::time::Duration) -> Self {
+        assert!(
+            !window.is_zero(),
+            "read_profile_window should be greater than zero"
+        );
+        self.config.read_sample_every = every;
+        self.config.read_profile_window = window;
+        self.read_profiler.set_sampling(every, window);
+        self
+    }
+
+    /// Sets whether background work only runs when driven manually.
+    /// Background tasks stay idle and sealed memtables are kept in memory until
+    /// `tick_flush`, `tick_compaction` and `tick_gc` are called, for reproducible tests.
+    pub fn with_manual_background_mode(mut self, enable: bool) -> Self {
+        self.config.manual_background_mode = enable;
+        self.manual_background.store(enable, Ordering::Relaxed);
+        self
+    }
+
+    /// Sets bytes of SSTable data files verified per second by the background
+    /// scrubber and what is done with SSTables that fail their checksum.
+    /// Passes run hourly, a `rate` of 0 disables background scrubbing.
+    pub fn with_scrubber(mut self, rate: usize, action: ScrubAction) -> Self {
+        self.config.scrub_rate = rate;
+        self.config.scrub_action = action;
+        self.scrubber.set(rate, action);
+        self
+    }
+
+    /// Sets time between background passes of the TTL sweeper and the estimated
+    /// share of expired entries that gets an SSTable compacted with its bucket.
+    /// SSTables whose entries all expired are dropped whole. Passes only run
+    /// with TTL enabled, an `interval` of zero disables background passes.
+    /// The ratio must be greater than 0.0 and at most 1.0.
+    pub fn with_ttl_sweeper(mut self, interval: std::time::Duration, ratio: f64) -> Self {
+        assert!(
+            ratio > 0.0 && ratio <= 1.0,
+            "expired_compaction_ratio should be greater than 0.0 and at most 1.0"
+        );
+        self.config.ttl_sweep_interval = interval;
+        self.config.expired_compaction_ratio = ratio;
+        self.ttl_sweeper.set(interval, ratio);
+        self
+    }
+
+    /// Sets whether compaction reads every merged SSTable back before its inputs
+    /// are deleted, checking its checksum and key order and that every input key
+    /// not dropped by the merge was written. A failed check aborts the compaction
+    /// and keeps the inputs, at the cost of reading inputs and outputs once more.
+    pub fn with_compaction_verification(mut self, enable: bool) -> Self {
+        self.config.verify_compaction = enable;
+        self.compactor.config.verify_output = enable;
+        self
+    }
+
+    /// Sets size in bytes from which values written by `put` are deduplicated.
+    /// A value identical to an earlier one is stored once in the value log and
+    /// shared by both keys until GC finds neither refers to it anymore.
+    /// Values written by batches are always stored on their own, a `min_size`
+    /// of 0 disables deduplication.
+    pub fn with_value_dedup(mut self, min_size: usize) -> Self {
+        self.config.value_dedup_min_size = min_size;
+        self.dedup.set_min_size(min_size);
+        self
+    }
+
+    /// Sets the number of SSTables whose metadata is loaded at once when a store
+    /// is opened. Recovery of this store is already done, the setting applies to
+    /// every store opened afterwards in the process.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parallelism` is 0
+    pub fn with_recovery_parallelism(mut self, parallelism: usize) -> Self {
+        assert!(parallelism > 0, "recovery parallelism must be greater than 0");
+        self.config.recovery_parallelism = parallelism;
+        set_recovery_parallelism(parallelism);
+        self
+    }
+
+    /// Caps bytes of keys and values and number of keys stored under `prefix`.
+    /// Puts and batches that would go over either cap fail with `QuotaExceeded`,
+    /// writes that shrink usage are always allowed. Setting the quota of a prefix
+    /// again replaces its caps, usage is returned by `quota_usage`.
+    ///
+    /// Usage is counted from stored entries on the first write or query after
+    /// open, then tracked with every write. Writes of keys under a prefix read
+    /// the value they replace to do so.
+    pub fn with_prefix_quota(mut self, prefix: impl AsRef<[u8]>, max_bytes: usize, max_keys: usize) -> Self {
+        let quota = PrefixQuota {
+            prefix: prefix.as_ref().to_vec(),
+            max_bytes,
+            max_keys,
+        };
+        self.config.prefix_quotas.retain(|q| q.prefix != quota.prefix);
+        self.config.prefix_quotas.push(quota.clone());
+        self.quotas.set(quota);
+        self
+    }
+
+    /// Keeps entries under `prefix` in a bucket of their own. Flushes split
+    /// memtables so entries under a pinned prefix go to its bucket, and the
+    /// bucket is only ever merged with itself, so compactions of one prefix do
+    /// not rewrite data of others. Of nested pinned prefixes the longest wins.
+    ///
+    /// Applies to data flushed from now on, entries already on disk stay in
+    /// their buckets. `buckets` shows which bucket a prefix is pinned to.
+    pub fn with_pinned_prefix(mut self, prefix: impl AsRef<[u8]>) -> Self {
+        let prefix = prefix.as_ref().to_vec();
+        if !self.config.pinned_prefixes.contains(&prefix) {
+            self.config.pinned_prefixes.push(prefix);
+        }
+        *self.bucket_tuning.write().unwrap() = BucketTuning::from(&self.config);
+        self
+    }
+
+    /// Sets the maximum number of SSTable data and index files kept open.
+    /// Least recently used files are closed beyond the limit and reopened on next use,
+    /// the limit is shared by every store in the process. A limit of 0 keeps every file open.
+    pub fn with_open_files_limit(mut self, limit: usize) -> Self {
+        self.config.open_files_limit = limit;
+        FileNode::set_open_files_limit(limit);
+        self
+    }
+
+    /// Sets the clock used to timestamp entries.
+    /// The clock is shared with background tasks such as compaction and garbage collection.
+    /// Entry versions never go backwards, so writes stamped by a clock behind the
+    /// previous one get the last issued time with an incremented logical counter.
+    pub fn with_clock(self, clock: impl Clock + 'static) -> Self {
+        self.clock.set(Arc::new(clock));
+        self
+    }
+
+    /// Sets a custom policy picking which SSTables are merged during compaction,
+    /// replacing the built-in Sized Tier strategy and its merge thresholds.
+    /// The policy is used by manual and background compaction and by `compaction_plan`.
+    pub fn with_custom_compaction_strategy(
+        self,
+        strategy: impl compactors::CompactionStrategy + 'static,
+    ) -> Self {
+        self.compactor.config.custom_strategy.set(Arc::new(strategy));
+        self
+    }
+
+    /// Sets a filter deciding whether entries outliving entry and tombstone TTLs
+    /// are kept, removed or replaced by a tombstone when their SSTable is compacted,
+    /// e.g. to purge every key of a tenant during normal compaction.
+    pub fn with_compaction_filter(self, filter: impl compactors::CompactionFilter + 'static) -> Self {
+        self.compactor.config.filter.set(Arc::new(filter));
+        self
+    }
+
+    /// Adds `read` latency to every SSTable and value log read and `write` latency
+    /// to every value log append of gets and writes, e.g. to benchmark a config
+    /// as if the store was on a slower disk. Zero disables the latency.
+    pub fn with_io_latency(self, read: std::time::Duration, write: std::time::Duration) -> Self {
+        self.io_latency.set(read, write);
+        self
+    }
+
+    /// Adds an interceptor called with every put and delete of the store before
+    /// it reaches the value log, e.g. to validate values against a schema or
+    /// audit writes. Interceptors run in the order they were added.
+    pub fn with_write_interceptor(self, interceptor: impl crate::db::WriteInterceptor + 'static) -> Self {
+        self.interceptors.add(Arc::new(interceptor));
+        self
+    }
+
+    /// Adds a hook receiving the entries read and written by every compaction,
+    /// so tests can assert invariants such as no key loss or tombstone rules.
+    /// Collecting entries costs memory, hooks are only available with the
+    /// `compaction-hooks` feature.
+    #[cfg(feature = "compaction-hooks")]
+    pub fn with_compaction_hook(self, hook: impl compactors::CompactionHook + 'static) -> Self {
+        self.compactor.config.hooks.add(Arc::new(hook));
+        self
+    }
 }
 
 #[cfg(test)]
@@ -245,6 +858,7 @@ mod tests {
         // Initialize with default or dummy values
         let config = Config {
             false_positive_rate: 0.01,
+            filter_false_positive_tiers: Vec::new(),
             allow_prefetch: false,
             prefetch_size: 0,
             write_buffer_size: 51200,
@@ -258,7 +872,41 @@ mod tests {
             compaction_strategy: compactors::Strategy::STCS,
             online_gc_interval: Duration::from_secs(0),
             gc_chunk_size: 51200,
+            comparator: Comparator::Bytewise,
             open_files_limit: 150,
+            degraded_failure_threshold: 3,
+            read_only_failure_threshold: 10,
+            row_cache_size: 0,
+            vlog_read_ahead_size: 0,
+            head_checkpoint_size: 0,
+            head_checkpoint_interval: Duration::from_secs(0),
+            memtable_max_age: Duration::from_secs(0),
+            filter_memory_cap: 0,
+            optimize_filters_for_hits: false,
+            persist_filter_bits: true,
+            orphan_file_grace_period: Duration::from_secs(0),
+            bucket_low: 0.0,
+            bucket_high: 0.0,
+            min_sstable_size: 0,
+            min_tables_to_merge: 0,
+            max_tables_to_merge: 0,
+            tombstone_compaction_ratio: 0.0,
+            total_write_buffer_size: 0,
+            slow_op_threshold: Duration::from_secs(0),
+            slow_background_op_threshold: Duration::from_secs(0),
+            read_sample_every: 0,
+            read_profile_window: Duration::from_secs(0),
+            manual_background_mode: false,
+            scrub_rate: 0,
+            scrub_action: ScrubAction::Report,
+            value_dedup_min_size: 0,
+            recovery_parallelism: DEFAULT_RECOVERY_PARALLELISM,
+            prefix_quotas: Vec::new(),
+            pinned_prefixes: Vec::new(),
+            ttl_sweep_interval: Duration::from_secs(0),
+            expired_compaction_ratio: 0.0,
+            verify_compaction: false,
+            encryption_keys: Vec::new(),
         };
         store.config = config;
         store
@@ -457,4 +1105,392 @@ mod tests {
         let ds = ds.with_gc_chunk_size(100);
         assert_eq!(ds.config.gc_chunk_size, SizeUnit::Kilobytes.as_bytes(100));
     }
+
+    #[tokio::test]
+    #[should_panic(expected = "degraded_failure_threshold should be greater than zero")]
+    async fn test_with_degraded_failure_threshold_invalid() {
+        let ds = create_datastore().await;
+        ds.with_degraded_failure_threshold(0);
+    }
+
+    #[tokio::test]
+    #[should_panic(
+        expected = "degraded_failure_threshold should not be greater than read_only_failure_threshold"
+    )]
+    async fn test_with_degraded_failure_threshold_above_read_only() {
+        let ds = create_datastore().await;
+        ds.with_degraded_failure_threshold(11);
+    }
+
+    #[tokio::test]
+    async fn test_with_degraded_failure_threshold() {
+        let ds = create_datastore().await;
+        let ds = ds.with_degraded_failure_threshold(5);
+        assert_eq!(ds.config.degraded_failure_threshold, 5);
+    }
+
+    #[tokio::test]
+    #[should_panic(
+        expected = "read_only_failure_threshold should not be less than degraded_failure_threshold"
+    )]
+    async fn test_with_read_only_failure_threshold_invalid() {
+        let ds = create_datastore().await;
+        ds.with_read_only_failure_threshold(2);
+    }
+
+    #[tokio::test]
+    async fn test_with_read_only_failure_threshold() {
+        let ds = create_datastore().await;
+        let ds = ds.with_read_only_failure_threshold(20);
+        assert_eq!(ds.config.read_only_failure_threshold, 20);
+    }
+
+    #[tokio::test]
+    async fn test_with_row_cache_size() {
+        let ds = create_datastore().await;
+        assert!(!ds.row_cache.is_enabled());
+        let ds = ds.with_row_cache_size(64);
+        assert_eq!(ds.config.row_cache_size, SizeUnit::Kilobytes.as_bytes(64));
+        assert!(ds.row_cache.is_enabled());
+    }
+
+    #[tokio::test]
+    async fn test_with_vlog_read_ahead_size() {
+        let ds = create_datastore().await;
+        let ds = ds.with_vlog_read_ahead_size(128);
+        assert_eq!(ds.config.vlog_read_ahead_size, SizeUnit::Kilobytes.as_bytes(128));
+    }
+
+    #[tokio::test]
+    async fn test_with_head_checkpoint_size() {
+        let ds = create_datastore().await;
+        let ds = ds.with_head_checkpoint_size(1024);
+        assert_eq!(ds.config.head_checkpoint_size, SizeUnit::Kilobytes.as_bytes(1024));
+    }
+
+    #[tokio::test]
+    async fn test_with_filter_memory_cap() {
+        let ds = create_datastore().await;
+        let ds = ds.with_filter_memory_cap(256);
+        assert_eq!(ds.config.filter_memory_cap, SizeUnit::Kilobytes.as_bytes(256));
+    }
+
+    #[tokio::test]
+    async fn test_with_optimize_filters_for_hits() {
+        let ds = create_datastore().await;
+        let ds = ds.with_optimize_filters_for_hits(true);
+        assert!(ds.config.optimize_filters_for_hits);
+        assert!(ds.key_range.filter_cache.loads_on_read());
+    }
+
+    #[tokio::test]
+    async fn test_with_persist_filter_bits() {
+        let ds = create_datastore().await;
+        assert!(ds.persist_filter_bits.load(Ordering::Relaxed));
+        let ds = ds.with_persist_filter_bits(false);
+        assert!(!ds.config.persist_filter_bits);
+        assert!(!ds
+            .buckets
+            .read()
+            .await
+            .persist_filter_bits
+            .load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "head_checkpoint_interval should not be less than 1 second")]
+    async fn test_with_head_checkpoint_interval_invalid() {
+        let ds = create_datastore().await;
+        ds.with_head_checkpoint_interval(Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_with_head_checkpoint_interval() {
+        let ds = create_datastore().await;
+        let ds = ds.with_head_checkpoint_interval(Duration::from_secs(30));
+        assert_eq!(ds.config.head_checkpoint_interval, Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn test_with_memtable_max_age() {
+        let ds = create_datastore().await;
+        assert!(ds.config.memtable_max_age.is_zero());
+        let ds = ds.with_memtable_max_age(Duration::from_secs(5));
+        assert_eq!(ds.config.memtable_max_age, Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_with_clock() {
+        let ds = create_datastore().await;
+        let time = chrono::DateTime::from_timestamp_millis(1_000).unwrap();
+        let ds = ds.with_clock(crate::clock::MockClock::new(time));
+        assert_eq!(ds.clock.now(), time);
+        assert_eq!(ds.compactor.config.clock.now(), time);
+        assert_eq!(ds.gc.config.clock.now(), time);
+    }
+
+    #[tokio::test]
+    async fn test_with_orphan_file_grace_period() {
+        let ds = create_datastore().await;
+        let ds = ds.with_orphan_file_grace_period(Duration::from_secs(120));
+        assert_eq!(ds.config.orphan_file_grace_period, Duration::from_secs(120));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "bucket_low should be between 0.0 and 1.0 and bucket_high greater than 1.0")]
+    async fn test_with_bucket_size_ratios_invalid() {
+        let ds = create_datastore().await;
+        ds.with_bucket_size_ratios(0.5, 0.9);
+    }
+
+    #[tokio::test]
+    async fn test_with_bucket_size_ratios() {
+        let ds = create_datastore().await;
+        let ds = ds.with_bucket_size_ratios(0.25, 2.0);
+        assert_eq!(ds.config.bucket_low, 0.25);
+        assert_eq!(ds.config.bucket_high, 2.0);
+        assert_eq!(ds.bucket_tuning.read().unwrap().bucket_high, 2.0);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "false_positive_rate must be greater than 0.0")]
+    async fn test_with_filter_false_positive_tiers_invalid() {
+        let ds = create_datastore().await;
+        ds.with_filter_false_positive_tiers(vec![(64, 0.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_with_filter_false_positive_tiers() {
+        let ds = create_datastore().await;
+        let ds = ds.with_filter_false_positive_tiers(vec![(1024, 0.1), (64, 0.01)]);
+        let tiers = vec![
+            (SizeUnit::Kilobytes.as_bytes(64), 0.01),
+            (SizeUnit::Kilobytes.as_bytes(1024), 0.1),
+        ];
+        assert_eq!(ds.config.filter_false_positive_tiers, tiers);
+        let tuning = ds.buckets.read().await.tuning();
+        assert_eq!(
+            tuning.false_positive_rate_for(SizeUnit::Kilobytes.as_bytes(32)),
+            None
+        );
+        assert_eq!(
+            tuning.false_positive_rate_for(SizeUnit::Kilobytes.as_bytes(64)),
+            Some(0.01)
+        );
+        assert_eq!(
+            tuning.false_positive_rate_for(SizeUnit::Kilobytes.as_bytes(4096)),
+            Some(0.1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_with_min_sstable_size() {
+        let ds = create_datastore().await;
+        let ds = ds.with_min_sstable_size(16);
+        assert_eq!(ds.config.min_sstable_size, SizeUnit::Kilobytes.as_bytes(16));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "max_tables_to_merge should not be less than min_tables_to_merge")]
+    async fn test_with_tables_to_merge_invalid() {
+        let ds = create_datastore().await;
+        ds.with_tables_to_merge(8, 4);
+    }
+
+    #[tokio::test]
+    async fn test_with_tables_to_merge() {
+        let ds = create_datastore().await;
+        let ds = ds.with_tables_to_merge(8, 64);
+        assert_eq!(ds.config.min_tables_to_merge, 8);
+        assert_eq!(ds.config.max_tables_to_merge, 64);
+        assert_eq!(ds.buckets.read().await.tuning().min_tables_to_merge, 8);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "tombstone_compaction_ratio should be between 0.0 and 1.0")]
+    async fn test_with_tombstone_compaction_ratio_invalid() {
+        let ds = create_datastore().await;
+        ds.with_tombstone_compaction_ratio(1.5);
+    }
+
+    #[tokio::test]
+    async fn test_with_tombstone_compaction_ratio() {
+        let ds = create_datastore().await;
+        let ds = ds.with_tombstone_compaction_ratio(0.3);
+        assert_eq!(ds.config.tombstone_compaction_ratio, 0.3);
+        assert_eq!(ds.bucket_tuning.read().unwrap().tombstone_ratio, 0.3);
+    }
+
+    #[tokio::test]
+    async fn test_with_total_write_buffer_size() {
+        let ds = create_datastore().await;
+        let ds = ds.with_total_write_buffer_size(512);
+        assert_eq!(
+            ds.config.total_write_buffer_size,
+            SizeUnit::Kilobytes.as_bytes(512)
+        );
+        assert_eq!(
+            ds.write_buffer_manager.budget(),
+            SizeUnit::Kilobytes.as_bytes(512)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_with_open_files_limit() {
+        let ds = create_datastore().await;
+        let ds = ds.with_open_files_limit(get_open_file_limit());
+        assert_eq!(ds.config.open_files_limit, get_open_file_limit());
+    }
+
+    #[tokio::test]
+    async fn test_with_slow_op_thresholds() {
+        let ds = create_datastore().await;
+        let ds = ds
+            .with_slow_op_threshold(Duration::from_millis(50))
+            .with_slow_background_op_threshold(Duration::ZERO);
+        assert_eq!(ds.config.slow_op_threshold, Duration::from_millis(50));
+        assert_eq!(ds.config.slow_background_op_threshold, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "read_profile_window should be greater than zero")]
+    async fn test_with_read_sampling_invalid() {
+        let ds = create_datastore().await;
+        ds.with_read_sampling(10, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_with_read_sampling() {
+        let ds = create_datastore().await;
+        let ds = ds.with_read_sampling(100, Duration::from_secs(60));
+        assert_eq!(ds.config.read_sample_every, 100);
+        assert_eq!(ds.config.read_profile_window, Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_with_manual_background_mode() {
+        let ds = create_datastore().await;
+        assert!(!ds.manual_background.load(Ordering::Relaxed));
+        let ds = ds.with_manual_background_mode(true);
+        assert!(ds.config.manual_background_mode);
+        assert!(ds.manual_background.load(Ordering::Relaxed));
+        assert!(ds.compactor.config.manual_background.load(Ordering::Relaxed));
+        assert!(ds.gc.config.manual_background.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn test_with_value_dedup() {
+        let ds = create_datastore().await;
+        let ds = ds.with_value_dedup(64);
+        assert_eq!(ds.config.value_dedup_min_size, 64);
+        assert!(ds.dedup.eligible(&[0; 63]).is_none());
+        assert!(ds.dedup.eligible(&[0; 64]).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_with_recovery_parallelism() {
+        let ds = create_datastore().await;
+        let ds = ds.with_recovery_parallelism(2);
+        assert_eq!(ds.config.recovery_parallelism, 2);
+        assert_eq!(Config::default().recovery_parallelism, 2);
+        set_recovery_parallelism(DEFAULT_RECOVERY_PARALLELISM);
+    }
+
+    #[tokio::test]
+    async fn test_with_prefix_quota() {
+        let ds = create_datastore().await;
+        let ds = ds
+            .with_prefix_quota("tenant_a/", 1024, 10)
+            .with_prefix_quota("tenant_b/", 64, 1)
+            .with_prefix_quota("tenant_a/", 2048, 20);
+        assert_eq!(
+            ds.config.prefix_quotas,
+            vec![
+                PrefixQuota {
+                    prefix: b"tenant_b/".to_vec(),
+                    max_bytes: 64,
+                    max_keys: 1,
+                },
+                PrefixQuota {
+                    prefix: b"tenant_a/".to_vec(),
+                    max_bytes: 2048,
+                    max_keys: 20,
+                },
+            ]
+        );
+        assert!(ds.quotas.tracks(b"tenant_a/apple"));
+        assert!(!ds.quotas.tracks(b"tenant_c/apple"));
+    }
+
+    #[tokio::test]
+    async fn test_with_pinned_prefix() {
+        let ds = create_datastore().await;
+        let ds = ds
+            .with_pinned_prefix("tenant_a/")
+            .with_pinned_prefix("tenant_a/")
+            .with_pinned_prefix("tenant_a/vip/");
+        assert_eq!(
+            ds.config.pinned_prefixes,
+            vec![b"tenant_a/".to_vec(), b"tenant_a/vip/".to_vec()]
+        );
+        let tuning = ds.bucket_tuning.read().unwrap().clone();
+        let vip = tuning.pin_for(b"tenant_a/vip/apple").unwrap();
+        assert_eq!(vip.prefix, b"tenant_a/vip/".to_vec());
+        assert_eq!(tuning.pin_of(vip.bucket), Some(vip));
+        assert_eq!(
+            tuning.pin_for(b"tenant_a/apple").unwrap().prefix,
+            b"tenant_a/".to_vec()
+        );
+        assert!(tuning.pin_for(b"tenant_b/apple").is_none());
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "recovery parallelism must be greater than 0")]
+    async fn test_with_recovery_parallelism_zero() {
+        let ds = create_datastore().await;
+        let _ = ds.with_recovery_parallelism(0);
+    }
+
+    #[tokio::test]
+    async fn test_with_scrubber() {
+        let ds = create_datastore().await;
+        let ds = ds.with_scrubber(1024, ScrubAction::Quarantine);
+        assert_eq!(ds.config.scrub_rate, 1024);
+        assert_eq!(ds.config.scrub_action, ScrubAction::Quarantine);
+        assert_eq!(ds.scrubber.settings(), (1024, ScrubAction::Quarantine));
+    }
+
+    #[tokio::test]
+    async fn test_with_ttl_sweeper() {
+        let ds = create_datastore().await;
+        let ds = ds.with_ttl_sweeper(Duration::from_secs(600), 0.75);
+        assert_eq!(ds.config.ttl_sweep_interval, Duration::from_secs(600));
+        assert_eq!(ds.config.expired_compaction_ratio, 0.75);
+        let (ttl, interval, ratio) = ds.ttl_sweeper.settings();
+        assert!(ttl.is_none());
+        assert_eq!((interval, ratio), (Duration::from_secs(600), 0.75));
+
+        let ds = ds
+            .with_enable_ttl(true)
+            .with_entry_ttl(Duration::from_secs(4 * 24 * 60 * 60));
+        assert!(ds.compactor.config.use_ttl);
+        let (ttl, _, _) = ds.ttl_sweeper.settings();
+        assert_eq!(ttl.unwrap().entry_ttl, Duration::from_secs(4 * 24 * 60 * 60));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "expired_compaction_ratio should be greater than 0.0 and at most 1.0")]
+    async fn test_with_ttl_sweeper_invalid_ratio() {
+        let ds = create_datastore().await;
+        let _ = ds.with_ttl_sweeper(Duration::from_secs(600), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_with_compaction_verification() {
+        let ds = create_datastore().await;
+        assert!(!ds.compactor.config.verify_output);
+        let ds = ds.with_compaction_verification(true);
+        assert!(ds.config.verify_compaction);
+        assert!(ds.compactor.config.verify_output);
+    }
 }
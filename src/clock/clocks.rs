@@ -0,0 +1,160 @@
+use super::HybridLogicalClock;
+use crate::types::CreatedAt;
+use chrono::{DateTime, Utc};
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+/// Source of timestamps for entries
+///
+/// Put, delete, memtable rotation, garbage collection and TTL checks during
+/// compaction all read time from the clock configured on the store, so a
+/// custom clock can be used to control time in tests or guard against skew
+pub trait Clock: Debug + Send + Sync {
+    /// Returns current time
+    fn now(&self) -> CreatedAt;
+}
+
+/// Clock backed by the system time, used by default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> CreatedAt {
+        Utc::now()
+    }
+}
+
+/// Manually controlled clock
+///
+/// Time only moves when set or advanced, clones share the same time
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    /// Current time in milliseconds since epoch
+    millis: Arc<AtomicI64>,
+}
+
+impl MockClock {
+    /// Creates new `MockClock` starting at `start`
+    pub fn new(start: CreatedAt) -> Self {
+        Self {
+            millis: Arc::new(AtomicI64::new(start.timestamp_millis())),
+        }
+    }
+
+    /// Sets current time to `time`
+    pub fn set(&self, time: CreatedAt) {
+        self.millis.store(time.timestamp_millis(), Ordering::SeqCst);
+    }
+
+    /// Moves current time forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        self.millis
+            .fetch_add(duration.as_millis() as i64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> CreatedAt {
+        DateTime::from_timestamp_millis(self.millis.load(Ordering::SeqCst)).unwrap_or_default()
+    }
+}
+
+/// Clock shared by the store and its background tasks
+///
+/// Replacing the clock is visible to every clone of the handle, the hybrid
+/// logical clock used to version entries is kept when the clock is replaced
+#[derive(Clone, Debug)]
+pub struct ClockHandle {
+    inner: Arc<RwLock<Arc<dyn Clock>>>,
+    hlc: Arc<HybridLogicalClock>,
+}
+
+impl ClockHandle {
+    /// Creates new `ClockHandle`
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(clock)),
+            hlc: Arc::new(HybridLogicalClock::new()),
+        }
+    }
+
+    /// Returns current time of the configured clock
+    pub fn now(&self) -> CreatedAt {
+        self.inner.read().unwrap().now()
+    }
+
+    /// Returns timestamp for a new entry
+    ///
+    /// Unlike [`ClockHandle::now`] the timestamp is unique and increasing,
+    /// see [`HybridLogicalClock`]
+    pub fn tick(&self) -> CreatedAt {
+        self.hlc.tick(self.now())
+    }
+
+    /// Returns the last entry timestamp issued or observed
+    pub fn latest(&self) -> CreatedAt {
+        self.hlc.latest()
+    }
+
+    /// Moves hybrid logical clock forward to at least `time`
+    pub fn observe(&self, time: CreatedAt) {
+        self.hlc.observe(time)
+    }
+
+    /// Replaces the configured clock
+    pub fn set(&self, clock: Arc<dyn Clock>) {
+        *self.inner.write().unwrap() = clock;
+    }
+}
+
+impl Default for ClockHandle {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let start = Utc::now();
+        let clock = MockClock::new(start);
+        assert_eq!(clock.now().timestamp_millis(), start.timestamp_millis());
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(clock.now().timestamp_millis(), start.timestamp_millis() + 2000);
+
+        // clones share time
+        let shared = clock.clone();
+        shared.set(start);
+        assert_eq!(clock.now().timestamp_millis(), start.timestamp_millis());
+    }
+
+    #[test]
+    fn test_clock_handle_set() {
+        let handle = ClockHandle::default();
+        let shared = handle.clone();
+        let time = DateTime::from_timestamp_millis(1_000).unwrap();
+        handle.set(Arc::new(MockClock::new(time)));
+        assert_eq!(shared.now(), time);
+    }
+
+    #[test]
+    fn test_clock_handle_tick() {
+        let time = DateTime::from_timestamp_millis(1_000).unwrap();
+        let handle = ClockHandle::new(Arc::new(MockClock::new(time)));
+        let shared = handle.clone();
+        let first = handle.tick();
+        assert_eq!(first, time);
+        // clones share the hybrid logical clock
+        assert!(shared.tick() > first);
+    }
+}
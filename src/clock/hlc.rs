@@ -0,0 +1,156 @@
+use crate::types::{CreatedAt, SeqNumber};
+use chrono::{DateTime, Utc};
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// Number of logical ticks that fit in one millisecond of physical time
+pub(crate) const LOGICAL_TICKS_PER_MILLI: i64 = 1_000_000;
+
+/// Version of an entry assigned by the hybrid logical clock
+///
+/// Versions are ordered by physical time first and logical counter second,
+/// so two writes within the same millisecond still have a deterministic order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    /// Wall clock time in milliseconds since epoch
+    pub physical: i64,
+
+    /// Counter distinguishing writes within the same millisecond
+    pub logical: u32,
+}
+
+impl Version {
+    /// Creates new `Version`
+    pub fn new(physical: i64, logical: u32) -> Self {
+        Self { physical, logical }
+    }
+
+    /// Returns sequence number of the write versioned `self`
+    ///
+    /// Sequence numbers count logical ticks since epoch, they order the same
+    /// as versions but are not contiguous
+    pub fn sequence(&self) -> SeqNumber {
+        (self.physical * LOGICAL_TICKS_PER_MILLI + self.logical as i64) as SeqNumber
+    }
+}
+
+impl From<CreatedAt> for Version {
+    fn from(created_at: CreatedAt) -> Self {
+        let logical = created_at.timestamp_subsec_nanos() as i64 % LOGICAL_TICKS_PER_MILLI;
+        Self::new(created_at.timestamp_millis(), logical as u32)
+    }
+}
+
+impl From<Version> for CreatedAt {
+    fn from(version: Version) -> Self {
+        DateTime::from_timestamp_nanos(version.physical * LOGICAL_TICKS_PER_MILLI + version.logical as i64)
+    }
+}
+
+/// Hybrid logical clock
+///
+/// Issues timestamps that follow the wall clock at millisecond precision
+/// but never repeat or go backwards. The logical counter is kept in the
+/// sub-millisecond part of the returned timestamp, so entry timestamps
+/// double as [`Version`]s and compare the same way
+#[derive(Debug, Default)]
+pub struct HybridLogicalClock {
+    /// Last issued timestamp in logical ticks since epoch
+    last: AtomicI64,
+}
+
+impl HybridLogicalClock {
+    /// Creates new `HybridLogicalClock`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a timestamp greater than every timestamp issued or observed so far
+    ///
+    /// `wall` is truncated to milliseconds, if it has not moved past the last
+    /// timestamp the logical counter is incremented instead
+    pub fn tick(&self, wall: CreatedAt) -> CreatedAt {
+        let physical = wall.timestamp_millis() * LOGICAL_TICKS_PER_MILLI;
+        let mut last = self.last.load(Ordering::SeqCst);
+        loop {
+            let next = physical.max(last + 1);
+            match self
+                .last
+                .compare_exchange_weak(last, next, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return DateTime::<Utc>::from_timestamp_nanos(next),
+                Err(current) => last = current,
+            }
+        }
+    }
+
+    /// Returns the last timestamp issued or observed
+    pub fn latest(&self) -> CreatedAt {
+        DateTime::<Utc>::from_timestamp_nanos(self.last.load(Ordering::SeqCst))
+    }
+
+    /// Moves clock forward to at least `time`
+    ///
+    /// Used after recovery so timestamps issued after a restart stay ahead
+    /// of persisted entries even if the wall clock went backwards
+    pub fn observe(&self, time: CreatedAt) {
+        let version = Version::from(time);
+        let ticks = version.physical * LOGICAL_TICKS_PER_MILLI + version.logical as i64;
+        self.last.fetch_max(ticks, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tick_orders_writes_within_millisecond() {
+        let clock = HybridLogicalClock::new();
+        let wall = DateTime::from_timestamp_millis(1_700_000_000_000).unwrap();
+        let first = clock.tick(wall);
+        let second = clock.tick(wall);
+        assert_eq!(first, wall);
+        assert!(second > first);
+        assert_eq!(Version::from(first), Version::new(1_700_000_000_000, 0));
+        assert_eq!(Version::from(second), Version::new(1_700_000_000_000, 1));
+
+        // wall clock going backwards does not move versions back
+        let earlier = clock.tick(wall - chrono::Duration::seconds(1));
+        assert_eq!(Version::from(earlier), Version::new(1_700_000_000_000, 2));
+
+        // logical counter resets once wall clock moves forward
+        let later = clock.tick(wall + chrono::Duration::milliseconds(1));
+        assert_eq!(Version::from(later), Version::new(1_700_000_000_001, 0));
+    }
+
+    #[test]
+    fn test_observe() {
+        let clock = HybridLogicalClock::new();
+        let wall = DateTime::from_timestamp_millis(1_700_000_000_000).unwrap();
+        clock.observe(CreatedAt::from(Version::new(1_700_000_000_000, 5)));
+        assert_eq!(
+            Version::from(clock.tick(wall)),
+            Version::new(1_700_000_000_000, 6)
+        );
+    }
+
+    #[test]
+    fn test_version_round_trip() {
+        let version = Version::new(1_700_000_000_123, 42);
+        let created_at = CreatedAt::from(version);
+        assert_eq!(created_at.timestamp_millis(), 1_700_000_000_123);
+        assert_eq!(Version::from(created_at), version);
+        assert!(Version::new(1_700_000_000_123, 43).sequence() > version.sequence());
+        assert!(Version::new(1_700_000_000_124, 0).sequence() > version.sequence());
+    }
+
+    #[test]
+    fn test_latest() {
+        let clock = HybridLogicalClock::new();
+        let wall = DateTime::from_timestamp_millis(1_700_000_000_000).unwrap();
+        let issued = clock.tick(wall);
+        assert_eq!(clock.latest(), issued);
+        clock.observe(CreatedAt::from(Version::new(1_700_000_000_001, 3)));
+        assert_eq!(Version::from(clock.latest()), Version::new(1_700_000_000_001, 3));
+    }
+}
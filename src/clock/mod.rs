@@ -0,0 +1,8 @@
+mod clocks;
+mod hlc;
+pub use clocks::Clock;
+pub use clocks::ClockHandle;
+pub use clocks::MockClock;
+pub use clocks::SystemClock;
+pub use hlc::HybridLogicalClock;
+pub use hlc::Version;
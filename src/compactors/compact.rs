@@ -1,6 +1,17 @@
+use super::{CompactionProgress, FilterHandle, StrategyHandle, WriteAmpTracker};
 use crate::bucket::InsertableToBucket;
+use crate::cache::RowCache;
+use crate::clock::ClockHandle;
+use crate::comparator::Comparator;
+use crate::gc::DeadOffsets;
+use crate::health::{BackgroundTask, HealthMonitor};
+use crate::quota::QuotaTracker;
+use crate::slow_log::SlowLog;
+use crate::sst::SstId;
 use crate::types::{Bool, BucketMapHandle, FlushReceiver, KeyRangeHandle};
 use crate::{err::Error, filter::BloomFilter};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time;
 use tokio::sync::Mutex;
@@ -12,7 +23,8 @@ use Error::*;
 /// During this process, it handles obsolete entries and tombstones (markers for deleted entries) as follows:
 ///
 /// - **Expired Tombstones**: If a tombstone's timestamp is older than a specified threshold (defined by `tombstone_ttl`), it's considered expired.
-///   These expired tombstones are removed entirely during compaction, freeing up disk space.
+///   These expired tombstones are removed entirely during compaction, freeing up disk space, unless an SSTable
+///   outside the merge whose keys overlap the merged ones may still hold an older version of their key.
 ///
 /// - **Unexpired Tombstones**: If a tombstone is not expired, it means the data it shadows might still be relevant in other tiers.
 ///   In this case, velarixDB keeps both the tombstone and the data in the new SSTable. This ensures consistency across tiers and allows for repairs if needed.
@@ -23,7 +35,7 @@ pub struct Compactor {
     pub config: Config,
 
     /// Compaction reason (manual or automated)
-    pub reason: CompactionReason,
+    pub reason: Arc<Mutex<CompactionReason>>,
 
     /// Is compaction active or sleeping
     pub is_active: Arc<Mutex<CompState>>,
@@ -54,6 +66,88 @@ pub struct Config {
     pub(crate) strategy: Strategy,
 
     pub(crate) filter_false_positive: f64,
+
+    /// clock used to check entry expiry
+    pub(crate) clock: ClockHandle,
+
+    /// user-defined policy replacing `strategy` when set
+    pub(crate) custom_strategy: StrategyHandle,
+
+    /// user-defined rule applied to entries outliving TTLs
+    pub(crate) filter: FilterHandle,
+
+    /// decodes keys passed to the compaction filter
+    pub(crate) comparator: Comparator,
+
+    /// values of keys compaction hides are evicted, shared with the store
+    pub(crate) row_cache: RowCache,
+
+    /// sstables taking part in a running compaction
+    pub(crate) compacting: CompactingTables,
+
+    /// value log offsets of entries dropped by compaction, shared with GC
+    pub(crate) dead_offsets: DeadOffsets,
+
+    /// background workers skip their runs while set, shared with GC and the store
+    pub(crate) manual_background: Arc<AtomicBool>,
+
+    /// outcome of compaction runs
+    pub(crate) progress: CompactionProgress,
+
+    /// bytes written by flushes and merges, shared with the flusher
+    pub(crate) write_amp: WriteAmpTracker,
+
+    /// thresholds for logging slow compactions
+    pub(crate) slow_log: SlowLog,
+
+    /// usage of prefixes with a quota, counted again when expired entries are dropped
+    pub(crate) quotas: QuotaTracker,
+
+    /// merged sstables are read back and checked against their inputs before inputs are deleted
+    pub(crate) verify_output: bool,
+
+    /// observers of merged entries
+    #[cfg(feature = "compaction-hooks")]
+    pub(crate) hooks: super::HookHandle,
+}
+
+/// SSTables taking part in a running compaction, shared by every clone of the config
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CompactingTables {
+    ids: Arc<std::sync::Mutex<HashSet<SstId>>>,
+}
+
+impl CompactingTables {
+    /// Marks `ids` as being compacted until the returned guard is dropped
+    pub fn track(&self, ids: Vec<SstId>) -> CompactingGuard {
+        self.ids.lock().unwrap().extend(ids.iter().copied());
+        CompactingGuard {
+            tables: self.clone(),
+            ids,
+        }
+    }
+
+    /// Returns `true` if SSTable `id` is being compacted
+    pub fn contains(&self, id: SstId) -> bool {
+        self.ids.lock().unwrap().contains(&id)
+    }
+}
+
+/// Clears SSTables marked by [`CompactingTables::track`] once compaction
+/// of them ends, whether it succeeded or not
+#[derive(Debug)]
+pub(crate) struct CompactingGuard {
+    tables: CompactingTables,
+    ids: Vec<SstId>,
+}
+
+impl Drop for CompactingGuard {
+    fn drop(&mut self) {
+        let mut tracked = self.tables.ids.lock().unwrap();
+        for id in self.ids.iter() {
+            tracked.remove(id);
+        }
+    }
 }
 
 /// Groups TTL params
@@ -111,27 +205,6 @@ impl WriteTracker {
     }
 }
 
-/// Pointers used during merge
-#[derive(Debug, Clone)]
-pub(crate) struct MergePointer {
-    pub ptr1: usize,
-    pub ptr2: usize,
-}
-impl MergePointer {
-    pub fn new() -> Self {
-        Self {
-            ptr1: Default::default(),
-            ptr2: Default::default(),
-        }
-    }
-    pub fn increment_ptr1(&mut self) {
-        self.ptr1 += 1;
-    }
-    pub fn increment_ptr2(&mut self) {
-        self.ptr2 += 1;
-    }
-}
-
 /// Merged SSTable stored here
 /// before being flushed to disk
 #[derive(Debug)]
@@ -182,6 +255,21 @@ impl Config {
             tombstone_compaction_interval: intervals.tombstone_compaction_interval,
             strategy,
             filter_false_positive,
+            clock: ClockHandle::default(),
+            custom_strategy: StrategyHandle::default(),
+            filter: FilterHandle::default(),
+            comparator: Comparator::default(),
+            row_cache: RowCache::new(0),
+            compacting: CompactingTables::default(),
+            dead_offsets: DeadOffsets::default(),
+            manual_background: Arc::new(AtomicBool::new(false)),
+            progress: CompactionProgress::default(),
+            write_amp: WriteAmpTracker::default(),
+            slow_log: SlowLog::default(),
+            quotas: QuotaTracker::default(),
+            verify_output: false,
+            #[cfg(feature = "compaction-hooks")]
+            hooks: super::HookHandle::default(),
         }
     }
 }
@@ -195,11 +283,14 @@ impl Compactor {
         strategy: Strategy,
         reason: CompactionReason,
         filter_false_positive: f64,
+        clock: ClockHandle,
     ) -> Self {
+        let mut config = Config::new(use_ttl, ttl, intervals, strategy, filter_false_positive);
+        config.clock = clock;
         Self {
             is_active: Arc::new(Mutex::new(CompState::Sleep)),
-            reason,
-            config: Config::new(use_ttl, ttl, intervals, strategy, filter_false_positive),
+            reason: Arc::new(Mutex::new(reason)),
+            config,
         }
     }
     /// FUTURE: Explicitly trigger tombstone compaction to remove expired tombstones, although this is handled during
@@ -220,43 +311,49 @@ impl Compactor {
 
     /// Background flush listener
     ///
-    /// If a flush signal has been sent then compaction handler is called
+    /// If the flush generation moved since the last check then compaction handler
+    /// is called. Flushes are only marked as seen once compaction starts, so a
+    /// flush that lands while the compactor is busy is picked up on the next check
     pub fn start_flush_listener(
         &self,
         flush_rx: FlushReceiver,
         bucket_map: BucketMapHandle,
         key_range: KeyRangeHandle,
+        health: HealthMonitor,
     ) {
         let mut rx = flush_rx.clone();
         let comp_state = Arc::clone(&self.is_active);
         let cfg = self.config.to_owned();
         tokio::spawn(async move {
+            let _alive = health.task_started(BackgroundTask::Compaction);
             loop {
                 Compactor::sleep_compaction(cfg.flush_listener_interval).await;
-                let signal = rx.try_recv();
+                // flushes stay unseen until a manual tick or manual mode is turned off
+                if cfg.manual_background.load(Ordering::Relaxed) {
+                    continue;
+                }
+                match rx.has_changed() {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(_) => {
+                        log::info!("{}", FlushSignalChannelClosed);
+                        return;
+                    }
+                }
                 let mut state = comp_state.lock().await;
                 if let CompState::Sleep = *state {
-                    if let Err(err) = signal {
-                        drop(state);
-                        match err {
-                            async_broadcast::TryRecvError::Overflowed(_) => {
-                                log::error!("{}", FlushSignalChannelOverflow)
-                            }
-                            async_broadcast::TryRecvError::Closed => {
-                                log::error!("{}", FlushSignalChannelClosed)
-                            }
-                            async_broadcast::TryRecvError::Empty => {}
-                        }
-                        continue;
-                    }
+                    rx.mark_unchanged();
                     *state = CompState::Active;
                     drop(state);
-                    if let Err(err) =
-                        Compactor::handle_compaction(Arc::clone(&bucket_map), Arc::clone(&key_range), &cfg)
-                            .await
+                    match Compactor::handle_compaction(Arc::clone(&bucket_map), Arc::clone(&key_range), &cfg)
+                        .await
                     {
-                        log::info!("{}", Error::CompactionFailed(Box::new(err)));
-                        continue;
+                        Ok(_) => health.record_success(BackgroundTask::Compaction),
+                        Err(err) => {
+                            let err = Error::CompactionFailed(Box::new(err));
+                            log::error!("{}", err);
+                            health.record_failure(BackgroundTask::Compaction, &err);
+                        }
                     }
                     let mut state = comp_state.lock().await;
                     *state = CompState::Sleep;
@@ -267,20 +364,34 @@ impl Compactor {
     }
 
     /// Background compaction runner for maintenance
-    pub fn spawn_compaction_worker(&self, buckets: BucketMapHandle, key_range: KeyRangeHandle) {
+    pub fn spawn_compaction_worker(
+        &self,
+        buckets: BucketMapHandle,
+        key_range: KeyRangeHandle,
+        health: HealthMonitor,
+    ) {
         let cfg = self.config.to_owned();
         let comp_state = Arc::clone(&self.is_active);
         tokio::spawn(async move {
+            let _alive = health.task_started(BackgroundTask::Compaction);
             loop {
                 Compactor::sleep_compaction(cfg.background_interval).await;
+                if cfg.manual_background.load(Ordering::Relaxed) {
+                    continue;
+                }
                 let mut state = comp_state.lock().await;
                 if let CompState::Sleep = *state {
                     *state = CompState::Active;
                     drop(state);
-                    if let Err(err) =
-                        Compactor::handle_compaction(Arc::clone(&buckets), Arc::clone(&key_range), &cfg).await
+                    match Compactor::handle_compaction(Arc::clone(&buckets), Arc::clone(&key_range), &cfg)
+                        .await
                     {
-                        log::info!("{}", Error::CompactionFailed(Box::new(err)))
+                        Ok(_) => health.record_success(BackgroundTask::Compaction),
+                        Err(err) => {
+                            let err = Error::CompactionFailed(Box::new(err));
+                            log::error!("{}", err);
+                            health.record_failure(BackgroundTask::Compaction, &err);
+                        }
                     }
                     let mut state = comp_state.lock().await;
                     *state = CompState::Sleep;
@@ -294,13 +405,18 @@ impl Compactor {
         key_range: KeyRangeHandle,
         cfg: &Config,
     ) -> Result<(), Error> {
-        match cfg.strategy {
+        cfg.progress.start();
+        let timer = cfg.slow_log.background("compaction");
+        let res = match cfg.strategy {
             Strategy::STCS => {
                 let mut runner =
                     super::sized::SizedTierRunner::new(Arc::clone(&buckets), Arc::clone(&key_range), cfg);
                 runner.run_compaction().await
             } // LCS, UCS and TWS will be added later
-        }
+        };
+        timer.finish();
+        cfg.progress.finish(&res, cfg.clock.now());
+        res
     }
 
     async fn sleep_compaction(duration: std::time::Duration) {
@@ -336,6 +452,7 @@ mod tests {
             strategy,
             reason.to_owned(),
             filter_false_positive,
+            ClockHandle::default(),
         );
 
         assert_eq!(compactor.config.use_ttl, use_ttl);
@@ -354,7 +471,7 @@ mod tests {
             intervals.tombstone_compaction_interval
         );
         assert_eq!(compactor.config.strategy, strategy);
-        assert_eq!(compactor.reason, reason);
+        assert_eq!(*compactor.reason.try_lock().unwrap(), reason);
         assert_eq!(compactor.config.filter_false_positive, filter_false_positive);
     }
 }
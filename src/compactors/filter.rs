@@ -0,0 +1,95 @@
+use crate::{
+    comparator::Comparator,
+    consts::{HEAD_ENTRY_KEY, TAIL_ENTRY_KEY},
+    memtable::Entry,
+    types::{CreatedAt, Key, ValOffset},
+};
+use std::{
+    fmt::Debug,
+    sync::{Arc, RwLock},
+};
+
+/// Per-entry rule applied while SSTables are merged
+///
+/// Implement it to purge entries matching business rules during normal
+/// compaction, e.g. every key of a tenant that asked to be forgotten, and set
+/// it with [`crate::db::DataStore::with_compaction_filter`]. Entry and
+/// tombstone TTLs are applied first, the filter sees the newest version of
+/// every key that outlives them.
+///
+/// Entries are only filtered when the SSTable holding them is compacted, so
+/// removal is eventual. The filter may see an entry more than once, the same
+/// entry should get the same decision.
+pub trait CompactionFilter: Debug + Send + Sync {
+    /// Returns what happens to `entry` in the merged SSTable, `now` is the
+    /// time of the store clock
+    fn filter(&self, entry: &FilteredEntry, now: CreatedAt) -> FilterDecision;
+}
+
+/// Entry offered to a [`CompactionFilter`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FilteredEntry {
+    /// Key as seen by the user
+    pub key: Key,
+
+    /// Time the entry was written
+    pub created_at: CreatedAt,
+
+    /// Whether the entry deletes its key
+    pub is_tombstone: bool,
+}
+
+/// What a [`CompactionFilter`] does with an entry
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FilterDecision {
+    /// Entry is written to the merged SSTable
+    #[default]
+    Keep,
+
+    /// Entry is left out of the merged SSTable, as expired entries are.
+    /// Older versions of its key in SSTables not being compacted become
+    /// visible again
+    Remove,
+
+    /// Entry is replaced by a tombstone written at the same time, which
+    /// keeps hiding older versions of its key until the tombstone expires
+    Tombstone,
+}
+
+/// Compaction filter shared by every clone of the compactor config
+#[derive(Clone, Debug, Default)]
+pub(crate) struct FilterHandle {
+    inner: Arc<RwLock<Option<Arc<dyn CompactionFilter>>>>,
+}
+
+impl FilterHandle {
+    /// Replaces the compaction filter
+    pub fn set(&self, filter: Arc<dyn CompactionFilter>) {
+        *self.inner.write().unwrap() = Some(filter);
+    }
+
+    /// Returns decision of the filter for `entry`, entries are kept if no filter is set
+    pub fn decide(
+        &self,
+        entry: &Entry<Key, ValOffset>,
+        comparator: Comparator,
+        now: CreatedAt,
+    ) -> FilterDecision {
+        // head and tail entries belong to the store, not the user
+        if entry.key == HEAD_ENTRY_KEY || entry.key == TAIL_ENTRY_KEY {
+            return FilterDecision::Keep;
+        }
+        match self.inner.read().unwrap().as_ref() {
+            Some(filter) => filter.filter(
+                &FilteredEntry {
+                    key: comparator.decode(entry.key.to_owned()),
+                    created_at: entry.created_at,
+                    is_tombstone: entry.is_tombstone,
+                },
+                now,
+            ),
+            None => FilterDecision::Keep,
+        }
+    }
+}
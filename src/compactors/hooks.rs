@@ -0,0 +1,81 @@
+use crate::{
+    bucket::BucketID,
+    types::{CreatedAt, Key, SkipMapEntries, ValOffset},
+};
+use std::{
+    fmt::Debug,
+    sync::{Arc, RwLock},
+};
+
+/// Observer of the entries read and written by each compaction
+///
+/// Meant for tests asserting compaction invariants such as no key loss,
+/// tombstone rules or ordering. Set it with
+/// [`crate::db::DataStore::with_compaction_hook`]
+pub trait CompactionHook: Debug + Send + Sync {
+    /// Called once the SSTables of a bucket are merged, before the merged
+    /// SSTable is written
+    fn on_merge(&self, trace: &CompactionTrace);
+}
+
+/// Entry read or written by a compaction
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompactionEntry {
+    pub key: Key,
+    pub val_offset: ValOffset,
+    pub created_at: CreatedAt,
+    pub is_tombstone: bool,
+}
+
+/// Entries going in and coming out of the merge of one bucket
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct CompactionTrace {
+    /// Bucket whose SSTables were merged
+    pub bucket: BucketID,
+
+    /// Entries of each merged SSTable, oldest SSTable first, in key order
+    pub inputs: Vec<Vec<CompactionEntry>>,
+
+    /// Entries of the merged SSTable in key order
+    pub output: Vec<CompactionEntry>,
+}
+
+impl CompactionTrace {
+    pub(crate) fn entries(entries: &SkipMapEntries<Key>) -> Vec<CompactionEntry> {
+        entries
+            .iter()
+            .map(|e| CompactionEntry {
+                key: e.key().to_owned(),
+                val_offset: e.value().val_offset,
+                created_at: e.value().created_at,
+                is_tombstone: e.value().is_tombstone,
+            })
+            .collect()
+    }
+}
+
+/// Compaction hooks shared by every clone of the compactor config
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HookHandle {
+    inner: Arc<RwLock<Vec<Arc<dyn CompactionHook>>>>,
+}
+
+impl HookHandle {
+    /// Adds `hook` to the ones called on every merge
+    pub fn add(&self, hook: Arc<dyn CompactionHook>) {
+        self.inner.write().unwrap().push(hook);
+    }
+
+    /// Returns `true` if no hook is set, so traces are not collected for nothing
+    pub fn is_empty(&self) -> bool {
+        self.inner.read().unwrap().is_empty()
+    }
+
+    /// Passes `trace` to every hook
+    pub fn notify(&self, trace: &CompactionTrace) {
+        for hook in self.inner.read().unwrap().iter() {
+            hook.on_merge(trace);
+        }
+    }
+}
@@ -1,8 +1,14 @@
 mod compact;
+mod filter;
+#[cfg(feature = "compaction-hooks")]
+mod hooks;
 mod insertor;
 mod sized;
+mod status;
+mod strategy;
 
 pub use compact::CompState;
+pub(crate) use compact::CompactingTables;
 pub use compact::CompactionReason;
 pub use compact::Compactor;
 pub use compact::Config;
@@ -10,5 +16,21 @@ pub use compact::IntervalParams;
 pub use compact::MergedSSTable;
 pub use compact::Strategy;
 pub use compact::TtlParams;
+pub(crate) use filter::FilterHandle;
+pub use filter::{CompactionFilter, FilterDecision, FilteredEntry};
+#[cfg(feature = "compaction-hooks")]
+pub(crate) use hooks::HookHandle;
+#[cfg(feature = "compaction-hooks")]
+pub use hooks::{CompactionEntry, CompactionHook, CompactionTrace};
 pub use insertor::TableInsertor;
 pub use sized::SizedTierRunner;
+pub use status::{BucketWriteStats, CompactionState, CompactionStatus};
+pub(crate) use status::{CompactionProgress, WriteAmpTracker};
+pub use strategy::BucketInfo;
+pub use strategy::CompactionInput;
+pub use strategy::CompactionJob;
+pub use strategy::CompactionStats;
+pub use strategy::CompactionStrategy;
+pub use strategy::SSTableInfo;
+pub use strategy::SizeTiered;
+pub(crate) use strategy::StrategyHandle;
@@ -1,16 +1,26 @@
-use std::{cmp, collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Arc,
+};
 
 use crossbeam_skiplist::SkipMap;
 
+#[cfg(feature = "compaction-hooks")]
+use super::CompactionTrace;
 use super::{
-    compact::{Config, MergePointer, WriteTracker},
-    MergedSSTable, TableInsertor,
+    compact::{Config, WriteTracker},
+    CompactionJob, CompactionStats, FilterDecision, MergedSSTable, TableInsertor,
 };
 use crate::{
-    bucket::{Bucket, ImbalancedBuckets, InsertableToBucket, SSTablesToRemove},
+    bucket::{Bucket, BucketMap, ImbalancedBuckets, InsertableToBucket, SSTablesToRemove},
+    consts::{HEAD_ENTRY_KEY, TAIL_ENTRY_KEY},
     err::Error,
     filter::BloomFilter,
+    key_range::Range,
     memtable::Entry,
+    range::{MergeIterator, Suppression},
+    sst::Table,
     types::{BucketMapHandle, CreatedAt, Key, KeyRangeHandle, ValOffset},
 };
 use crate::{err::Error::*, memtable::SkipMapValue};
@@ -34,6 +44,18 @@ pub struct SizedTierRunner<'a> {
     /// Keeps track of tombstones encountered during compaction
     /// to predict validity of subseqeunt entries
     pub(crate) tombstones: HashMap<Key, CreatedAt>,
+
+    /// Value log offsets of entries left out of merged sstables, handed
+    /// to GC once the merged sstables are published
+    pub(crate) dropped: Vec<ValOffset>,
+
+    /// Keys of entries left out of merged sstables, only kept when
+    /// outputs are verified
+    pub(crate) dropped_keys: HashSet<Key>,
+
+    /// SSTables outside the bucket being merged whose keys overlap it, older
+    /// versions of keys whose tombstones expired may still be stored there
+    pub(crate) overlaps: Vec<Table>,
 }
 
 impl<'a> SizedTierRunner<'a> {
@@ -45,6 +67,9 @@ impl<'a> SizedTierRunner<'a> {
     ) -> SizedTierRunner<'a> {
         Self {
             tombstones: HashMap::new(),
+            dropped: Vec::new(),
+            dropped_keys: HashSet::new(),
+            overlaps: Vec::new(),
             bucket_map,
             key_range,
             config,
@@ -56,81 +81,237 @@ impl<'a> SizedTierRunner<'a> {
         bucket_map.read().await.extract_imbalanced_buckets().await
     }
 
+    /// Returns buckets picked by the custom strategy if one is set, otherwise
+    /// buckets whose size exceeds max threshold
+    pub(crate) async fn pick_buckets(bucket_map: &BucketMap, config: &Config) -> ImbalancedBuckets {
+        let strategy = match config.custom_strategy.get() {
+            Some(strategy) => strategy,
+            None => return bucket_map.extract_imbalanced_buckets().await,
+        };
+        let buckets = bucket_map.bucket_infos().await;
+        let stats = CompactionStats {
+            sstable_count: buckets.iter().map(|b| b.sstables.len()).sum(),
+            total_size: buckets
+                .iter()
+                .flat_map(|b| b.sstables.iter())
+                .map(|s| s.size)
+                .sum(),
+            now: config.clock.now(),
+        };
+        match strategy.pick_compaction(&buckets, &stats) {
+            Some(job) => bucket_map.extract_job(&job).await,
+            None => Ok((vec![], vec![])),
+        }
+    }
+
     /// Main compaction runner
     pub async fn run_compaction(&mut self) -> Result<(), Error> {
-        if self.bucket_map.read().await.is_balanced().await {
+        if self.config.custom_strategy.get().is_none() && self.bucket_map.read().await.is_balanced().await {
             return Ok(());
         }
         // The compaction loop will keep running until there
         // are no more buckets with more than minimum treshold size
         // TODO: Handle this with multiple threads
         loop {
-            let buckets: BucketMapHandle = Arc::clone(&self.bucket_map);
-            let key_range = Arc::clone(&self.key_range);
             // Step 1: Extract imbalanced buckets
             let (imbalanced_buckets, ssts_to_remove) =
-                SizedTierRunner::fetch_imbalanced_buckets(buckets.clone()).await?;
+                SizedTierRunner::pick_buckets(&*self.bucket_map.read().await, self.config).await?;
             if imbalanced_buckets.is_empty() {
                 self.tombstones.clear();
                 return Ok(());
             }
+            self.compact_buckets(imbalanced_buckets, ssts_to_remove).await?;
+        }
+    }
+
+    /// Merges SSTables picked by `job` once, inputs with less than two
+    /// SSTables still live are skipped
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an error occured during merge
+    pub(crate) async fn run_job(&mut self, job: &CompactionJob) -> Result<(), Error> {
+        let (buckets, ssts_to_remove) = self.bucket_map.read().await.extract_job(job).await?;
+        if buckets.is_empty() {
+            return Ok(());
+        }
+        let res = self.compact_buckets(buckets, ssts_to_remove).await;
+        self.tombstones.clear();
+        res
+    }
+
+    /// Merges SSTables of each bucket in `imbalanced_buckets` and swaps
+    /// `ssts_to_remove` for the merged ones
+    async fn compact_buckets(
+        &mut self,
+        imbalanced_buckets: Vec<Bucket>,
+        ssts_to_remove: SSTablesToRemove,
+    ) -> Result<(), Error> {
+        let buckets: BucketMapHandle = Arc::clone(&self.bucket_map);
+        let key_range = Arc::clone(&self.key_range);
+        let obsolete: Vec<PathBuf> = ssts_to_remove
+            .iter()
+            .flat_map(|(_, ssts)| ssts.iter().map(|sst| sst.dir.to_owned()))
+            .collect();
+        let _compacting = self.config.compacting.track(
+            ssts_to_remove
+                .iter()
+                .flat_map(|(_, ssts)| ssts.iter().map(|sst| sst.id()))
+                .collect(),
+        );
+        self.dropped.clear();
+        self.dropped_keys.clear();
 
-            // Step 2: Merge SSTs in each imbalanced buckct
-            match self.merge_ssts_in_buckets(&imbalanced_buckets.to_owned()).await {
-                Ok(merged_sstables) => {
-                    let mut tracker = WriteTracker::new(merged_sstables.len());
-                    // Step 3: Insert Merged SSTs to appropriate buckets
-                    for merged_sst in merged_sstables.into_iter() {
-                        let mut bucket = buckets.write().await;
-                        let table = merged_sst.clone().sstable;
-                        let insert_res = bucket.insert_to_appropriate_bucket(Arc::new(table)).await;
-                        drop(bucket);
-                        match insert_res {
-                            Ok(sst) => {
-                                if sst.summary.is_none() {
-                                    return Err(TableSummaryIsNone);
-                                }
-                                if sst.filter.is_none() {
-                                    return Err(FilterNotProvidedForFlush);
-                                }
-                                // IMPORTANT: Don't keep sst entries in memory
-                                sst.entries.clear();
-                                let summary = sst.summary.clone().unwrap();
-                                // Step 5 Store sst key range
-                                key_range
-                                    .set(sst.dir.to_owned(), summary.smallest_key, summary.biggest_key, sst)
-                                    .await;
-                                tracker.actual += 1;
+        // Step 2: Merge SSTs in each imbalanced buckct
+        match self.merge_ssts_in_buckets(&imbalanced_buckets.to_owned()).await {
+            Ok(merged_sstables) => {
+                let mut tracker = WriteTracker::new(merged_sstables.len());
+                let mut merged_dirs = Vec::new();
+                let mut merged_size = 0;
+                let mut bucket_writes = Vec::new();
+                let mut merged_ranges = Vec::new();
+                let mut outputs = Vec::new();
+                // Step 3: Insert Merged SSTs to appropriate buckets
+                // NOTE: merge_ssts_in_buckets() returns one merged sstable per bucket in order
+                for (merged_sst, source) in merged_sstables.into_iter().zip(imbalanced_buckets.iter()) {
+                    let mut bucket = buckets.write().await;
+                    let hotness = merged_sst.hotness;
+                    let table = merged_sst.clone().sstable;
+                    // tables of a dedicated bucket are merged back into it
+                    let insert_res = match bucket.tuning().pin_of(source.id) {
+                        Some(pin) => bucket.insert_to_pinned_bucket(pin.bucket, Arc::new(table)).await,
+                        None => bucket.insert_to_appropriate_bucket(Arc::new(table)).await,
+                    };
+                    drop(bucket);
+                    match insert_res {
+                        Ok(sst) => {
+                            if sst.summary.is_none() {
+                                return Err(TableSummaryIsNone);
                             }
-                            Err(err) => {
-                                return Err(CompactionFailed(Box::new(err)));
+                            if sst.filter.is_none() {
+                                return Err(FilterNotProvidedForFlush);
                             }
+                            sst.increase_hotness(hotness);
+                            // IMPORTANT: Don't keep sst entries in memory
+                            sst.entries.clear();
+                            let summary = sst.summary.clone().unwrap();
+                            merged_dirs.push(sst.dir.to_owned());
+                            outputs.push(sst.to_owned());
+                            merged_size += sst.size;
+                            let read: usize = source.sstables.read().await.iter().map(|t| t.size).sum();
+                            bucket_writes.push((source.id, read, sst.size));
+                            merged_ranges.push(Range::new(summary.smallest_key, summary.biggest_key, sst));
+                            tracker.actual += 1;
+                        }
+                        Err(err) => {
+                            return Err(CompactionFailed(Box::new(err)));
                         }
                     }
+                }
 
-                    if tracker.expected == tracker.actual {
-                        // Step 6:  Delete the sstables that we already merged from their previous buckets
-                        let clean_up_successful = self
-                            .clean_up_after_compaction(buckets, &ssts_to_remove.clone(), key_range)
-                            .await;
-                        match clean_up_successful {
-                            Ok(None) => {
-                                return Err(Error::CompactionPartiallyFailed(Box::new(
-                                    CompactionCleanupPartial,
-                                )));
-                            }
-                            Err(err) => {
-                                return Err(Error::CompactionCleanup(Box::new(err)));
-                            }
-                            _ => {}
+                if self.config.verify_output {
+                    if let Err(err) = self.verify_outputs(&imbalanced_buckets, &outputs).await {
+                        self.discard_outputs(&outputs).await;
+                        return Err(CompactionFailed(Box::new(err)));
+                    }
+                }
+                if tracker.expected == tracker.actual {
+                    // Merged sstables replace the obsolete ones in the manifest in one write, so
+                    // after a crash either set is loaded but never both
+                    buckets
+                        .write()
+                        .await
+                        .publish(&merged_dirs, &obsolete)
+                        .await
+                        .map_err(|err| CompactionFailed(Box::new(err)))?;
+                    // Step 5: Swap obsolete key ranges for merged ones in one update so
+                    // reads never see a partially installed compaction
+                    let obsolete_ids: Vec<_> = ssts_to_remove
+                        .iter()
+                        .flat_map(|(_, ssts)| ssts.iter().map(|sst| sst.id()))
+                        .collect();
+                    key_range.install(merged_ranges, &obsolete_ids).await;
+                    // nothing live refers to dropped entries anymore
+                    self.config.dead_offsets.record(self.dropped.drain(..));
+                    if let Err(err) = self.config.dead_offsets.persist().await {
+                        log::warn!("Value log liveness could not be persisted: {}", err);
+                    }
+                    self.config.progress.record_merge(obsolete.len(), merged_size);
+                    for (bucket, read, written) in bucket_writes {
+                        self.config.write_amp.record_merge(bucket, read, written);
+                    }
+
+                    // Step 6:  Delete the sstables that we already merged from their previous buckets
+                    let clean_up_successful = self
+                        .clean_up_after_compaction(buckets, &ssts_to_remove.clone(), key_range)
+                        .await;
+                    match clean_up_successful {
+                        Ok(None) => {
+                            return Err(Error::CompactionPartiallyFailed(Box::new(
+                                CompactionCleanupPartial,
+                            )));
+                        }
+                        Err(err) => {
+                            return Err(Error::CompactionCleanup(Box::new(err)));
                         }
-                    } else {
-                        log::error!("{}", Error::CannotRemoveObsoleteSST)
+                        _ => {}
+                    }
+                } else {
+                    log::error!("{}", Error::CannotRemoveObsoleteSST)
+                }
+            }
+            Err(err) => return Err(CompactionFailed(Box::new(err))),
+        }
+        Ok(())
+    }
+
+    /// Reads each merged sstable in `outputs` back and checks that every key of
+    /// the sstables it was merged from was either written or dropped by the merge
+    ///
+    /// NOTE: `outputs` are in the order of the buckets they were merged from
+    ///
+    /// # Errors
+    ///
+    /// Returns error if an output is corrupted, holds keys out of order or lost a key
+    async fn verify_outputs(&self, sources: &[Bucket], outputs: &[Table]) -> Result<(), Error> {
+        for (source, output) in sources.iter().zip(outputs.iter()) {
+            let entries = output.read_verified().await?;
+            let written: HashSet<&Key> = entries.iter().map(|entry| &entry.key).collect();
+            for table in source.sstables.read().await.iter() {
+                let mut input = table.to_owned();
+                input.load_entries_from_file().await?;
+                for entry in input.entries.iter() {
+                    if !written.contains(entry.key()) && !self.dropped_keys.contains(entry.key()) {
+                        return Err(CompactionLostKey {
+                            dir: output.dir.to_owned(),
+                            key: entry.key().to_owned(),
+                        });
                     }
                 }
-                Err(err) => return Err(CompactionFailed(Box::new(err))),
             }
         }
+        Ok(())
+    }
+
+    /// Removes merged sstables that failed verification, the sstables they
+    /// were merged from stay live
+    async fn discard_outputs(&self, outputs: &[Table]) {
+        let mut bucket_map = self.bucket_map.write().await;
+        let mut ssts_to_delete: SSTablesToRemove = Vec::new();
+        for (id, bucket) in bucket_map.buckets.iter() {
+            let tables = bucket.sstables.read().await;
+            let discarded: Vec<Table> = outputs
+                .iter()
+                .filter(|output| tables.iter().any(|table| table.id() == output.id()))
+                .cloned()
+                .collect();
+            if !discarded.is_empty() {
+                ssts_to_delete.push((*id, discarded));
+            }
+        }
+        if let Err(err) = bucket_map.delete_ssts(&ssts_to_delete).await {
+            log::error!("{}", err);
+        }
     }
 
     /// Removes sstables that are already merged to form larger table(s)
@@ -149,10 +330,11 @@ impl<'a> SizedTierRunner<'a> {
     ) -> Result<Option<()>, Error> {
         // if all obsolete sstables were not deleted then don't remove the associated key range
         if buckets.write().await.delete_ssts(ssts_to_delete).await? {
-            // Step 7: Remove obsolete keys from keys range
+            // Step 7: Remove obsolete keys from keys range, they are normally
+            // dropped already when merged ranges were installed
             for (_, sstables) in ssts_to_delete {
                 for s in sstables {
-                    key_range.remove(s.dir.to_owned()).await;
+                    key_range.remove(s.id()).await;
                 }
             }
             return Ok(Some(()));
@@ -170,17 +352,34 @@ impl<'a> SizedTierRunner<'a> {
     pub async fn merge_ssts_in_buckets(&mut self, buckets: &[Bucket]) -> Result<Vec<MergedSSTable>, Error> {
         let mut merged_ssts = Vec::new();
         for bucket in buckets.iter() {
-            let mut hotness: u64 = Default::default();
             let tables = &bucket.sstables.read().await;
+            self.overlaps = self.tables_overlapping(tables).await;
+            // merged sstable keeps serving the reads of its inputs
+            let hotness: u64 = tables.iter().map(|sst| sst.get_hotness()).sum();
 
-            let mut merged_sst: Box<dyn InsertableToBucket> = Box::new(tables.first().unwrap().to_owned());
+            // flushed sstables do not keep their entries in memory
+            let mut first = tables.first().unwrap().to_owned();
+            first
+                .load_entries_from_file()
+                .await
+                .map_err(|err| CompactionFailed(Box::new(err)))?;
+            #[cfg(feature = "compaction-hooks")]
+            let mut inputs = Vec::new();
+            #[cfg(feature = "compaction-hooks")]
+            if !self.config.hooks.is_empty() {
+                inputs.push(CompactionTrace::entries(&first.get_entries()));
+            }
+            let mut merged_sst: Box<dyn InsertableToBucket> = Box::new(first);
             for sst in tables[1..].iter() {
                 let mut insertable_sst = sst.to_owned();
-                hotness += insertable_sst.hotness;
                 insertable_sst
                     .load_entries_from_file()
                     .await
                     .map_err(|err| CompactionFailed(Box::new(err)))?;
+                #[cfg(feature = "compaction-hooks")]
+                if !self.config.hooks.is_empty() {
+                    inputs.push(CompactionTrace::entries(&insertable_sst.get_entries()));
+                }
 
                 // TODO: merge_sstables() can be CPU intensive so we should use spawn blocking here
                 // tokio::task::spawn_blocking(||{
@@ -189,6 +388,14 @@ impl<'a> SizedTierRunner<'a> {
                 merged_sst = self.merge_sstables(merged_sst, Box::new(insertable_sst));
             }
             let entries = &merged_sst.get_entries();
+            #[cfg(feature = "compaction-hooks")]
+            if !self.config.hooks.is_empty() {
+                self.config.hooks.notify(&CompactionTrace {
+                    bucket: bucket.id,
+                    inputs,
+                    output: CompactionTrace::entries(entries),
+                });
+            }
             let mut filter = BloomFilter::new(self.config.filter_false_positive, entries.len());
             filter.build_filter_from_entries(entries);
             merged_ssts.push(MergedSSTable::new(merged_sst, filter, hotness));
@@ -199,6 +406,41 @@ impl<'a> SizedTierRunner<'a> {
         Ok(merged_ssts)
     }
 
+    /// Returns SSTables outside `tables` whose keys overlap theirs, selected
+    /// through the interval trees of the key range
+    async fn tables_overlapping(&self, tables: &[Table]) -> Vec<Table> {
+        let mut span: Option<(&[u8], &[u8])> = None;
+        for summary in tables.iter().filter_map(|sst| sst.summary.as_ref()) {
+            let (smallest, biggest) = (&summary.smallest_key[..], &summary.biggest_key[..]);
+            span = Some(match span {
+                Some((start, end)) => (start.min(smallest), end.max(biggest)),
+                None => (smallest, biggest),
+            });
+        }
+        let Some((start, end)) = span else {
+            return Vec::new();
+        };
+        let inputs: HashSet<_> = tables.iter().map(|sst| sst.id()).collect();
+        self.key_range
+            .tables_overlapping(start, end)
+            .await
+            .into_iter()
+            .filter(|sst| !inputs.contains(&sst.id()))
+            .collect()
+    }
+
+    /// Returns `true` if an SSTable overlapping the merged ones may hold `key`
+    fn stored_outside_merge(&self, key: &[u8]) -> bool {
+        self.overlaps.iter().any(|sst| {
+            let in_range = sst
+                .summary
+                .as_ref()
+                .is_none_or(|s| s.smallest_key.as_slice() <= key && key <= s.biggest_key.as_slice());
+            // filters not loaded since a crash contain every key
+            in_range && sst.filter.as_ref().is_none_or(|filter| filter.contains(key))
+        })
+    }
+
     /// Merge two `Table` together one returns a larger one
     ///
     /// Errors
@@ -212,63 +454,13 @@ impl<'a> SizedTierRunner<'a> {
         let mut new_sst = TableInsertor::default();
         let new_sst_map = Arc::new(SkipMap::new());
         let mut merged_entries = Vec::new();
-        let entries1 = sst1
-            .get_entries()
-            .iter()
-            .map(|e| {
-                Entry::new(
-                    e.key().to_vec(),
-                    e.value().val_offset,
-                    e.value().created_at,
-                    e.value().is_tombstone,
-                )
-            })
-            .collect::<Vec<Entry<Key, ValOffset>>>();
-        let entries2 = sst2
-            .get_entries()
-            .iter()
-            .map(|e| {
-                Entry::new(
-                    e.key().to_vec(),
-                    e.value().val_offset,
-                    e.value().created_at,
-                    e.value().is_tombstone,
-                )
-            })
-            .collect::<Vec<Entry<Key, ValOffset>>>();
-        let mut ptr = MergePointer::new();
-
-        while ptr.ptr1 < entries1.len() && ptr.ptr2 < entries2.len() {
-            match entries1[ptr.ptr1].key.cmp(&entries2[ptr.ptr2].key) {
-                cmp::Ordering::Less => {
-                    self.tombstone_check(&entries1[ptr.ptr1], &mut merged_entries);
-
-                    ptr.increment_ptr1();
-                }
-                cmp::Ordering::Equal => {
-                    if entries1[ptr.ptr1].created_at > entries2[ptr.ptr2].created_at {
-                        self.tombstone_check(&entries1[ptr.ptr1], &mut merged_entries);
-                    } else {
-                        self.tombstone_check(&entries2[ptr.ptr2], &mut merged_entries);
-                    }
-                    ptr.increment_ptr1();
-                    ptr.increment_ptr2();
-                }
-                cmp::Ordering::Greater => {
-                    self.tombstone_check(&entries2[ptr.ptr2], &mut merged_entries);
-                    ptr.increment_ptr2();
-                }
-            }
-        }
-
-        while ptr.ptr1 < entries1.len() {
-            self.tombstone_check(&entries1[ptr.ptr1], &mut merged_entries);
-            ptr.increment_ptr1();
-        }
-
-        while ptr.ptr2 < entries2.len() {
-            self.tombstone_check(&entries2[ptr.ptr2], &mut merged_entries);
-            ptr.increment_ptr2();
+        // sst2 is merged first so it wins when both versions share a timestamp
+        let mut merger = MergeIterator::new(Suppression::default());
+        merger.merge_all(&sst2.get_entries());
+        merger.merge_all(&sst1.get_entries());
+        self.dropped.extend(merger.take_superseded());
+        for entry in merger {
+            self.tombstone_check(&entry, &mut merged_entries);
         }
 
         merged_entries.iter().for_each(|e| {
@@ -292,29 +484,61 @@ impl<'a> SizedTierRunner<'a> {
         entry: &Entry<Key, usize>,
         merged_entries: &mut Vec<Entry<Key, usize>>,
     ) {
-        let mut should_insert = false;
-        if self.tombstones.contains_key(&entry.key) {
-            let tomb_insert_time = *self.tombstones.get(&entry.key).unwrap();
-            if entry.created_at > tomb_insert_time {
-                if entry.is_tombstone {
-                    self.tombstones.insert(entry.key.to_owned(), entry.created_at);
-                    should_insert = !entry.to_owned().has_expired(self.config.tombstone_ttl);
-                } else if self.config.use_ttl {
-                    should_insert = !entry.has_expired(self.config.entry_ttl);
-                } else {
-                    should_insert = true
-                }
+        if let Some(tomb_insert_time) = self.tombstones.get(&entry.key) {
+            if entry.created_at <= *tomb_insert_time {
+                self.drop_entry(entry);
+                return;
             }
-        } else if entry.is_tombstone {
+        }
+        if entry.is_tombstone {
             self.tombstones.insert(entry.key.to_owned(), entry.created_at);
-            should_insert = !entry.has_expired(self.config.tombstone_ttl);
-        } else if self.config.use_ttl {
-            should_insert = !entry.has_expired(self.config.entry_ttl);
-        } else {
-            should_insert = true
         }
-        if should_insert {
-            merged_entries.push(entry.clone())
+        let entry_ttl = self.config.use_ttl.then_some(self.config.entry_ttl);
+        let suppression =
+            Suppression::for_compaction(self.config.tombstone_ttl, entry_ttl, self.config.clock.now());
+        // an expired tombstone keeps hiding older versions of its key stored outside the merge
+        if suppression.suppresses(entry) && !(entry.is_tombstone && self.stored_outside_merge(&entry.key)) {
+            self.forget(entry);
+            self.drop_entry(entry);
+            return;
+        }
+        let decision = self
+            .config
+            .filter
+            .decide(entry, self.config.comparator, self.config.clock.now());
+        match decision {
+            FilterDecision::Keep => merged_entries.push(entry.clone()),
+            FilterDecision::Remove => {
+                self.forget(entry);
+                self.drop_entry(entry);
+            }
+            FilterDecision::Tombstone => {
+                self.forget(entry);
+                self.tombstones.insert(entry.key.to_owned(), entry.created_at);
+                let mut tombstone = entry.clone();
+                tombstone.is_tombstone = true;
+                merged_entries.push(tombstone)
+            }
+        }
+    }
+
+    /// Stops serving `entry` from the row cache and has quotas of its key counted
+    /// again, called when compaction hides an entry that reads could return
+    fn forget(&self, entry: &Entry<Key, usize>) {
+        if !entry.is_tombstone {
+            self.config.quotas.invalidate(&entry.key);
+            self.config.row_cache.invalidate(&entry.key);
+        }
+    }
+
+    /// Remembers value log offset of an entry left out of the merged sstable
+    fn drop_entry(&mut self, entry: &Entry<Key, usize>) {
+        if self.config.verify_output {
+            self.dropped_keys.insert(entry.key.to_owned());
+        }
+        // head and tail entries hold offsets of other records, not their own
+        if entry.key != HEAD_ENTRY_KEY && entry.key != TAIL_ENTRY_KEY {
+            self.dropped.push(entry.val_offset);
         }
     }
 }
@@ -0,0 +1,205 @@
+use crate::{bucket::BucketID, err::Error, types::CreatedAt};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// State of compaction
+///
+/// `Idle` means no compaction is running and the last one, if any, succeeded
+/// `Running` means a compaction is in progress
+/// `Failed` means no compaction is running and the last one failed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompactionState {
+    #[default]
+    Idle,
+    Running,
+    Failed,
+}
+
+/// Snapshot of compaction progress returned by `DataStore::compaction_status`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CompactionStatus {
+    /// Current state of compaction
+    pub state: CompactionState,
+
+    /// Time the last compaction finished, `None` if none ran since open
+    pub last_run_at: Option<CreatedAt>,
+
+    /// Number of SSTables merged by the last compaction
+    pub tables_merged: usize,
+
+    /// Bytes of SSTable data files written by the last compaction
+    pub bytes_written: usize,
+
+    /// Error of the most recent failed compaction, kept after later successes
+    pub last_error: Option<String>,
+}
+
+/// Records compaction runs, shared by every clone of the compactor config
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CompactionProgress {
+    inner: Arc<Mutex<CompactionStatus>>,
+}
+
+impl CompactionProgress {
+    /// Marks a compaction as started, counters of the previous run are reset
+    pub fn start(&self) {
+        let mut status = self.inner.lock().unwrap();
+        status.state = CompactionState::Running;
+        status.tables_merged = 0;
+        status.bytes_written = 0;
+    }
+
+    /// Records `tables` SSTables merged into new ones of `bytes` in total
+    pub fn record_merge(&self, tables: usize, bytes: usize) {
+        let mut status = self.inner.lock().unwrap();
+        status.tables_merged += tables;
+        status.bytes_written += bytes;
+    }
+
+    /// Marks the running compaction as finished at `at` with `result`
+    pub fn finish(&self, result: &Result<(), Error>, at: CreatedAt) {
+        let mut status = self.inner.lock().unwrap();
+        status.last_run_at = Some(at);
+        status.state = match result {
+            Ok(_) => CompactionState::Idle,
+            Err(err) => {
+                status.last_error = Some(err.to_string());
+                CompactionState::Failed
+            }
+        };
+    }
+
+    /// Returns a snapshot of compaction progress
+    pub fn snapshot(&self) -> CompactionStatus {
+        self.inner.lock().unwrap().clone()
+    }
+}
+
+/// Cumulative compaction figures of one bucket returned by `DataStore::bucket_write_stats`
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BucketWriteStats {
+    /// Bucket the merged SSTables were taken from
+    pub bucket: BucketID,
+
+    /// Bytes of SSTable data files read by merges of the bucket
+    pub bytes_read: usize,
+
+    /// Bytes of SSTable data files written by merges of the bucket
+    pub bytes_written: usize,
+
+    /// Number of merges run on the bucket
+    pub merges: usize,
+}
+
+/// Records bytes written by flushes and compactions since the store was
+/// opened, shared by the flusher and every clone of the compactor config
+#[derive(Clone, Debug, Default)]
+pub(crate) struct WriteAmpTracker {
+    inner: Arc<Mutex<WriteAmp>>,
+}
+
+#[derive(Debug, Default)]
+struct WriteAmp {
+    flushed_bytes: usize,
+    buckets: BTreeMap<BucketID, BucketWriteStats>,
+}
+
+impl WriteAmpTracker {
+    /// Records a memtable flushed into an SSTable of `bytes`
+    pub fn record_flush(&self, bytes: usize) {
+        self.inner.lock().unwrap().flushed_bytes += bytes;
+    }
+
+    /// Records SSTables of `bucket` totalling `read` bytes merged into one of `written` bytes
+    pub fn record_merge(&self, bucket: BucketID, read: usize, written: usize) {
+        let mut amp = self.inner.lock().unwrap();
+        let stats = amp.buckets.entry(bucket).or_insert_with(|| BucketWriteStats {
+            bucket,
+            bytes_read: 0,
+            bytes_written: 0,
+            merges: 0,
+        });
+        stats.bytes_read += read;
+        stats.bytes_written += written;
+        stats.merges += 1;
+    }
+
+    /// Returns bytes written to SSTables by flushes and compactions per
+    /// byte flushed, 1.0 until anything is flushed
+    pub fn write_amplification(&self) -> f64 {
+        let amp = self.inner.lock().unwrap();
+        if amp.flushed_bytes == 0 {
+            return 1.0;
+        }
+        let compacted: usize = amp.buckets.values().map(|b| b.bytes_written).sum();
+        (amp.flushed_bytes + compacted) as f64 / amp.flushed_bytes as f64
+    }
+
+    /// Returns figures of every bucket compacted so far ordered by bucket id
+    pub fn buckets(&self) -> Vec<BucketWriteStats> {
+        self.inner.lock().unwrap().buckets.values().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_compaction_progress_tracks_runs() {
+        let progress = CompactionProgress::default();
+        assert_eq!(progress.snapshot(), CompactionStatus::default());
+
+        progress.start();
+        progress.record_merge(4, 100);
+        progress.record_merge(2, 50);
+        assert_eq!(progress.snapshot().state, CompactionState::Running);
+        let at = Utc::now();
+        progress.finish(&Err(Error::CompactionCleanupPartial), at);
+        let status = progress.snapshot();
+        assert_eq!(status.state, CompactionState::Failed);
+        assert_eq!(status.tables_merged, 6);
+        assert_eq!(status.bytes_written, 150);
+        assert_eq!(status.last_run_at, Some(at));
+        assert!(status.last_error.is_some());
+
+        // counters restart with every run, the last error is kept
+        progress.start();
+        progress.finish(&Ok(()), at);
+        let status = progress.snapshot();
+        assert_eq!(status.state, CompactionState::Idle);
+        assert_eq!(status.tables_merged, 0);
+        assert_eq!(
+            status.last_error,
+            Some(Error::CompactionCleanupPartial.to_string())
+        );
+    }
+
+    #[test]
+    fn test_write_amp_tracker_sums_flushes_and_merges() {
+        let tracker = WriteAmpTracker::default();
+        assert_eq!(tracker.write_amplification(), 1.0);
+        assert!(tracker.buckets().is_empty());
+
+        tracker.record_flush(100);
+        tracker.record_flush(100);
+        let bucket = BucketID::new_v4();
+        tracker.record_merge(bucket, 200, 150);
+        tracker.record_merge(bucket, 250, 250);
+        assert_eq!(tracker.write_amplification(), 3.0);
+        assert_eq!(
+            tracker.buckets(),
+            vec![BucketWriteStats {
+                bucket,
+                bytes_read: 450,
+                bytes_written: 400,
+                merges: 2,
+            }]
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{
+    bucket::BucketID,
+    consts::{MAX_TRESHOLD, MIN_TRESHOLD},
+    types::CreatedAt,
+};
+use std::{
+    fmt::Debug,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+/// Policy deciding which SSTables are merged together
+///
+/// Implement it to plug in custom selection, e.g. only compacting outside
+/// business hours or keeping tenants apart, and set it with
+/// [`crate::db::DataStore::with_custom_compaction_strategy`].
+/// The built-in strategy is used when none is set
+pub trait CompactionStrategy: Debug + Send + Sync {
+    /// Returns SSTables to merge or `None` if nothing should be compacted
+    ///
+    /// Called repeatedly until it returns `None` or a job without mergeable
+    /// inputs, merged SSTables are part of `buckets` on the next call
+    fn pick_compaction(&self, buckets: &[BucketInfo], stats: &CompactionStats) -> Option<CompactionJob>;
+}
+
+/// Snapshot of a bucket offered to a [`CompactionStrategy`]
+#[derive(Clone, Debug)]
+pub struct BucketInfo {
+    /// Id of the bucket
+    pub id: BucketID,
+
+    /// Directory of the bucket
+    pub dir: PathBuf,
+
+    /// SSTables in the bucket, oldest first
+    pub sstables: Vec<SSTableInfo>,
+}
+
+/// Snapshot of an SSTable offered to a [`CompactionStrategy`]
+#[derive(Clone, Debug)]
+pub struct SSTableInfo {
+    /// Directory of the SSTable, used to select it in a [`CompactionInput`]
+    pub dir: PathBuf,
+
+    /// Size of the SSTable in bytes
+    pub size: usize,
+
+    /// Number of entries, 0 for SSTables written before counts were recorded
+    pub entry_count: usize,
+
+    /// Number of tombstones, 0 for SSTables written before counts were recorded
+    pub tombstone_count: usize,
+
+    /// Date created
+    pub created_at: CreatedAt,
+
+    /// Sampled read hits since the SSTable was loaded, merged SSTables
+    /// inherit the hits of their inputs
+    pub hotness: u64,
+}
+
+/// Store wide figures offered to a [`CompactionStrategy`]
+#[derive(Clone, Debug)]
+pub struct CompactionStats {
+    /// Number of live SSTables
+    pub sstable_count: usize,
+
+    /// Size of every live SSTable in bytes
+    pub total_size: usize,
+
+    /// Current time of the store clock
+    pub now: CreatedAt,
+}
+
+/// SSTables picked by a [`CompactionStrategy`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompactionJob {
+    /// Each input is merged into one SSTable
+    pub inputs: Vec<CompactionInput>,
+}
+
+/// SSTables of one bucket merged together
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompactionInput {
+    /// Bucket the SSTables belong to
+    pub bucket: BucketID,
+
+    /// Directories of the SSTables, inputs with less than two SSTables are skipped
+    pub sstables: Vec<PathBuf>,
+}
+
+/// Built-in Sized Tier policy
+///
+/// Merges the oldest `max_tables_to_merge` SSTables of every bucket holding
+/// at least `min_tables_to_merge`, hottest buckets first, useful to fall back
+/// to from custom strategies
+#[derive(Clone, Copy, Debug)]
+pub struct SizeTiered {
+    /// Minimum number of SSTables in a bucket before it is compacted
+    pub min_tables_to_merge: usize,
+
+    /// Maximum number of SSTables of a bucket merged at once
+    pub max_tables_to_merge: usize,
+}
+
+impl Default for SizeTiered {
+    fn default() -> Self {
+        Self {
+            min_tables_to_merge: MIN_TRESHOLD,
+            max_tables_to_merge: MAX_TRESHOLD,
+        }
+    }
+}
+
+impl CompactionStrategy for SizeTiered {
+    fn pick_compaction(&self, buckets: &[BucketInfo], _: &CompactionStats) -> Option<CompactionJob> {
+        let mut buckets: Vec<&BucketInfo> = buckets
+            .iter()
+            .filter(|bucket| bucket.sstables.len() >= self.min_tables_to_merge.max(2))
+            .collect();
+        buckets
+            .sort_by_key(|bucket| std::cmp::Reverse(bucket.sstables.iter().map(|s| s.hotness).sum::<u64>()));
+        let inputs: Vec<CompactionInput> = buckets
+            .into_iter()
+            .map(|bucket| CompactionInput {
+                bucket: bucket.id,
+                sstables: bucket
+                    .sstables
+                    .iter()
+                    .take(self.max_tables_to_merge)
+                    .map(|sst| sst.dir.to_owned())
+                    .collect(),
+            })
+            .collect();
+        if inputs.is_empty() {
+            return None;
+        }
+        Some(CompactionJob { inputs })
+    }
+}
+
+/// Shared slot for a custom [`CompactionStrategy`]
+///
+/// Setting a strategy is visible to every clone, including the ones held by
+/// background compaction tasks
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StrategyHandle {
+    inner: Arc<RwLock<Option<Arc<dyn CompactionStrategy>>>>,
+}
+
+impl StrategyHandle {
+    /// Returns the custom strategy if one is set
+    pub fn get(&self) -> Option<Arc<dyn CompactionStrategy>> {
+        self.inner.read().unwrap().clone()
+    }
+
+    /// Replaces the custom strategy
+    pub fn set(&self, strategy: Arc<dyn CompactionStrategy>) {
+        *self.inner.write().unwrap() = Some(strategy);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn bucket(tables: usize) -> BucketInfo {
+        BucketInfo {
+            id: BucketID::new_v4(),
+            dir: PathBuf::from("bucket"),
+            sstables: (0..tables)
+                .map(|i| SSTableInfo {
+                    dir: PathBuf::from(format!("sstable_{}", i)),
+                    size: 10,
+                    entry_count: 1,
+                    tombstone_count: 0,
+                    created_at: Utc::now(),
+                    hotness: 0,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_size_tiered_pick_compaction() {
+        let stats = CompactionStats {
+            sstable_count: 7,
+            total_size: 70,
+            now: Utc::now(),
+        };
+        let strategy = SizeTiered {
+            min_tables_to_merge: 3,
+            max_tables_to_merge: 4,
+        };
+        let buckets = vec![bucket(2), bucket(5)];
+        let job = strategy.pick_compaction(&buckets, &stats).unwrap();
+        assert_eq!(job.inputs.len(), 1);
+        assert_eq!(job.inputs[0].bucket, buckets[1].id);
+        assert_eq!(job.inputs[0].sstables.len(), 4);
+
+        assert!(strategy.pick_compaction(&buckets[..1], &stats).is_none());
+    }
+
+    #[test]
+    fn test_size_tiered_picks_hottest_buckets_first() {
+        let stats = CompactionStats {
+            sstable_count: 8,
+            total_size: 80,
+            now: Utc::now(),
+        };
+        let mut buckets = vec![bucket(4), bucket(4)];
+        buckets[1].sstables[0].hotness = 5;
+        let job = SizeTiered::default().pick_compaction(&buckets, &stats).unwrap();
+        assert_eq!(job.inputs[0].bucket, buckets[1].id);
+        assert_eq!(job.inputs[1].bucket, buckets[0].id);
+    }
+}
@@ -0,0 +1,122 @@
+use std::borrow::Cow;
+
+/// Escape written in place of a zero byte by `Comparator::Reverse`
+const REVERSE_ESCAPE: [u8; 2] = [0x00, 0xFF];
+
+/// Terminator written after every key by `Comparator::Reverse`
+const REVERSE_TERMINATOR: [u8; 2] = [0x00, 0x01];
+
+/// Order of keys in the store
+///
+/// The comparator is chosen when the store is created and recorded in the
+/// manifest, reopening with another comparator fails with
+/// [`crate::err::Error::ComparatorMismatch`]
+///
+/// Keys are encoded so that their byte order matches the comparator before
+/// they are stored, memtables and SSTables only ever compare bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Comparator {
+    /// Keys are ordered byte by byte
+    #[default]
+    Bytewise,
+
+    /// Keys are ordered byte by byte from the biggest to the smallest
+    Reverse,
+
+    /// Keys that only differ in ASCII case are the same key, keys are
+    /// stored and returned by scans in lowercase
+    CaseInsensitive,
+}
+
+impl Comparator {
+    /// Returns name of the comparator recorded in the manifest
+    pub fn name(&self) -> &'static str {
+        match self {
+            Comparator::Bytewise => "bytewise",
+            Comparator::Reverse => "reverse",
+            Comparator::CaseInsensitive => "case_insensitive",
+        }
+    }
+
+    /// Returns key as stored in the store
+    pub(crate) fn encode<'k>(&self, key: &'k [u8]) -> Cow<'k, [u8]> {
+        match self {
+            Comparator::Bytewise => Cow::Borrowed(key),
+            Comparator::Reverse => {
+                // zero bytes are escaped and a terminator is appended so no encoded key is
+                // a prefix of another, flipping every bit then reverses their order
+                let mut encoded = Vec::with_capacity(key.len() + REVERSE_TERMINATOR.len());
+                for byte in key {
+                    match byte {
+                        0 => encoded.extend(REVERSE_ESCAPE.iter().map(|b| !b)),
+                        _ => encoded.push(!byte),
+                    }
+                }
+                encoded.extend(REVERSE_TERMINATOR.iter().map(|b| !b));
+                Cow::Owned(encoded)
+            }
+            Comparator::CaseInsensitive => Cow::Owned(key.to_ascii_lowercase()),
+        }
+    }
+
+    /// Returns bytes every encoded key starting with `prefix` starts with
+    pub(crate) fn encode_prefix<'k>(&self, prefix: &'k [u8]) -> Cow<'k, [u8]> {
+        let mut encoded = self.encode(prefix);
+        if let (Comparator::Reverse, Cow::Owned(encoded)) = (self, &mut encoded) {
+            encoded.truncate(encoded.len() - REVERSE_TERMINATOR.len());
+        }
+        encoded
+    }
+
+    /// Returns key as seen by the user from key encoded with `encode`
+    pub(crate) fn decode(&self, key: Vec<u8>) -> Vec<u8> {
+        match self {
+            Comparator::Bytewise | Comparator::CaseInsensitive => key,
+            Comparator::Reverse => {
+                let flipped: Vec<u8> = key.into_iter().map(|byte| !byte).collect();
+                let body = flipped.strip_suffix(&REVERSE_TERMINATOR).unwrap_or(&flipped);
+                let mut decoded = Vec::with_capacity(body.len());
+                let mut bytes = body.iter();
+                while let Some(byte) = bytes.next() {
+                    decoded.push(*byte);
+                    if *byte == 0 {
+                        // skip the escape following a zero byte
+                        bytes.next();
+                    }
+                }
+                decoded
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comparator_encoding_orders_keys() {
+        let keys: Vec<&[u8]> = vec![
+            b"", b"\0", b"\0\0", b"\0a", b"a", b"a\0", b"ab", b"a\xff", b"b", b"\xff",
+        ];
+        let reverse = Comparator::Reverse;
+        for pair in keys.windows(2) {
+            assert!(reverse.encode(pair[0]) > reverse.encode(pair[1]));
+        }
+        for key in keys {
+            assert_eq!(reverse.decode(reverse.encode(key).to_vec()), key.to_vec());
+        }
+
+        let insensitive = Comparator::CaseInsensitive;
+        assert_eq!(insensitive.encode(b"Apple"), insensitive.encode(b"aPPLE"));
+        assert!(insensitive.encode(b"Banana") > insensitive.encode(b"apple"));
+        assert_eq!(Comparator::Bytewise.encode(b"Apple").as_ref(), b"Apple");
+
+        for comparator in [Comparator::Bytewise, Comparator::Reverse, insensitive] {
+            let prefix = comparator.encode_prefix(b"App");
+            assert!(comparator.encode(b"Apple").starts_with(&prefix));
+            assert!(comparator.encode(b"App").starts_with(&prefix));
+            assert!(!comparator.encode(b"Ap").starts_with(&prefix));
+        }
+    }
+}
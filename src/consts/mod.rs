@@ -8,9 +8,10 @@ pub const MAX_KEY_SIZE: usize = 65536;
 
 pub const MAX_KEY_SPACE_SIZE: usize = 255;
 
-pub const MAX_VALUE_SIZE: usize = (1u64 << 32) as usize; // 2^32
+/// Differences kept as samples by a consistency report, the rest are only counted
+pub const MAX_CONSISTENCY_SAMPLES: usize = 100;
 
-pub const DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE: usize = 1;
+pub const MAX_VALUE_SIZE: usize = (1u64 << 32) as usize; // 2^32
 
 pub const DEFAULT_MAX_WRITE_BUFFER_NUMBER: usize = 2;
 
@@ -22,28 +23,90 @@ pub const BUCKETS_DIRECTORY_NAME: &str = "buckets";
 
 pub const BUCKET_DIRECTORY_PREFIX: &str = "bucket";
 
+pub const SSTABLE_DIRECTORY_PREFIX: &str = "sstable";
+
 pub const VLOG_FILE_NAME: &str = "val_log.bin";
 
+/// Marks a value log holding references to deduplicated values
+pub const DEDUP_MARKER_FILE_NAME: &str = "dedup";
+
+/// Holds value log writes per segment and dead records, see `DeadOffsets::persist`
+pub const VLOG_LIVENESS_FILE_NAME: &str = "liveness.bin";
+
 pub const FILTER_FILE_NAME: &str = "filter";
 
 pub const DATA_FILE_NAME: &str = "data";
 
 pub const META_FILE_NAME: &str = "meta";
 
+pub const MANIFEST_FILE_NAME: &str = "manifest.bin";
+
+/// Metadata set with `DataStore::set_meta`, kept in the meta directory
+pub const USER_META_FILE_NAME: &str = "user_meta.bin";
+
+/// Memtable entries of the last head checkpoint, kept in the meta directory
+pub const HEAD_CHECKPOINT_FILE_NAME: &str = "head_checkpoint.bin";
+
+/// Read traffic saved with `DataStore::save_access_profile`, kept in the meta directory
+pub const ACCESS_PROFILE_FILE_NAME: &str = "access_profile.bin";
+
+/// Meta feature flag of stores whose SSTable blocks are compressed
+pub const META_FEATURE_COMPRESSION: u8 = 1;
+
+/// Meta feature flag of stores whose files are encrypted
+pub const META_FEATURE_ENCRYPTION: u8 = 1 << 1;
+
+/// Meta feature flag of stores whose value log records end with a checksum
+pub const META_FEATURE_VLOG_CHECKSUM: u8 = 1 << 2;
+
+/// Meta feature flags this version can open stores with
+pub const SUPPORTED_META_FEATURES: u8 = META_FEATURE_ENCRYPTION | META_FEATURE_VLOG_CHECKSUM;
+
+/// Bit of a value log record type tag set on records that end with a checksum,
+/// record types are tagged below it
+pub const VLOG_RECORD_CHECKSUM_FLAG: u8 = 1 << 7;
+
+/// On-disk format version written to meta, stores from before it was recorded have 0
+pub const FORMAT_VERSION: u32 = 1;
+
 pub const SUMMARY_FILE_NAME: &str = "summary";
 
 pub const INDEX_FILE_NAME: &str = "index";
 
+/// Lists the files of an SSTable, see `TableFiles`
+pub const DESCRIPTOR_FILE_NAME: &str = "descriptor";
+
+/// Extension of SSTable directories being written, renamed away once complete
+pub const TEMP_SSTABLE_EXTENSION: &str = "tmp";
+
 pub const DEFAULT_DB_NAME: &str = "velarix";
 
 pub const META_DIRECTORY_NAME: &str = "meta";
 
+/// Corrupted SSTables are moved here by the scrubber
+pub const QUARANTINE_DIRECTORY_NAME: &str = "quarantine";
+
 pub const TOMB_STONE_MARKER: &str = "*";
 
 /// TODO: Many lightweight computations here, benchmark with Lazy initialization
 /// 1KB
 pub static GC_CHUNK_SIZE: usize = SizeUnit::Kilobytes.as_bytes(1);
 
+/// Value log is split in segments of this size to estimate its garbage, 1MB
+pub const VLOG_SEGMENT_SIZE: usize = SizeUnit::Megabytes.as_bytes(1);
+
+/// GC takes the next chunk in the same run while at least this share of it is estimated dead
+pub const GC_BATCH_GARBAGE_RATIO: f64 = 0.5;
+
+/// GC collects a segment past the tail in place once at least this share of it is estimated dead
+pub const GC_SEGMENT_GARBAGE_RATIO: f64 = 0.5;
+
+/// Chunks GC takes in one run at most
+pub const GC_MAX_BATCH_CHUNKS: usize = 8;
+
+/// Appends chained before the value of a key is written again in full
+pub const APPEND_CHAIN_MAX_DEPTH: u32 = 16;
+
 /// 50KB
 pub const WRITE_BUFFER_SIZE: usize = SizeUnit::Kilobytes.as_bytes(50);
 
@@ -53,6 +116,12 @@ pub const DEFAULT_TOMBSTONE_COMPACTION_INTERVAL: Duration = Duration::from_milli
 /// 1 Hour
 pub const DEFAULT_COMPACTION_INTERVAL: Duration = Duration::from_millis(1000 * 60 * 60);
 
+/// How often `close` checks whether background flushes finished
+pub const CLOSE_FLUSH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long a session read waits for the store to apply writes the session observed
+pub const DEFAULT_SESSION_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// 5 Min
 pub const DEFAULT_COMPACTION_FLUSH_LISTNER_INTERVAL: Duration = Duration::from_millis(1000 * 60 * 5);
 
@@ -78,10 +147,119 @@ pub const MIN_TRESHOLD: usize = 4;
 
 pub const MAX_TRESHOLD: usize = 32;
 
+/// Deletion triggered compaction is disabled by default
+pub const DEFAULT_TOMBSTONE_COMPACTION_RATIO: f64 = 0.0;
+
+/// Row cache is disabled by default
+pub const DEFAULT_ROW_CACHE_SIZE: usize = 0;
+
+/// 1 Hour
+pub const DEFAULT_ORPHAN_FILE_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Memtables are only bounded by `max_buffer_write_number` by default
+pub const DEFAULT_TOTAL_WRITE_BUFFER_SIZE: usize = 0;
+
+/// Filter memory is not capped by default
+pub const DEFAULT_FILTER_MEMORY_CAP: usize = 0;
+
+/// Filters of new SSTables are kept in memory by default
+pub const DEFAULT_OPTIMIZE_FILTERS_FOR_HITS: bool = false;
+
+/// Filter bits are written with SSTables so recovery does not rebuild them
+pub const DEFAULT_PERSIST_FILTER_BITS: bool = true;
+
+/// Consecutive background failures before the store is marked degraded
+pub const DEFAULT_DEGRADED_FAILURE_THRESHOLD: usize = 3;
+
+/// Consecutive background failures before the store stops accepting writes
+pub const DEFAULT_READ_ONLY_FAILURE_THRESHOLD: usize = 10;
+
+/// Gets, puts and scans taking longer are logged as slow
+pub const DEFAULT_SLOW_OP_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Flushes and compactions taking longer are logged as slow
+pub const DEFAULT_SLOW_BACKGROUND_OP_THRESHOLD: Duration = Duration::from_secs(60);
+
+/// Gets are not sampled by default
+pub const DEFAULT_READ_SAMPLE_EVERY: usize = 0;
+
+/// 10 Minutes
+pub const DEFAULT_READ_PROFILE_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Background flushes, compactions and GC run on their own by default
+pub const DEFAULT_MANUAL_BACKGROUND_MODE: bool = false;
+
+/// Background scrubbing is disabled by default
+pub const DEFAULT_SCRUB_RATE: usize = 0;
+
+/// 1 Hour
+pub const DEFAULT_TTL_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// SSTables with half of their entries expired are compacted by the TTL sweeper
+pub const DEFAULT_EXPIRED_COMPACTION_RATIO: f64 = 0.5;
+
+/// Compaction outputs are not read back by default
+pub const DEFAULT_VERIFY_COMPACTION: bool = false;
+
+/// Values are not deduplicated by default
+pub const DEFAULT_VALUE_DEDUP_MIN_SIZE: usize = 0;
+
+/// Entries written per batch when migrating from another database
+#[cfg(any(feature = "sled", feature = "redb", feature = "rocksdb-sst"))]
+pub const MIGRATION_BATCH_SIZE: usize = 256;
+
+/// Keys written and read by a benchmark by default
+pub const BENCH_DEFAULT_NUM: usize = 10_000;
+
+/// Key length of a benchmark by default, as in `db_bench`
+pub const BENCH_DEFAULT_KEY_SIZE: usize = 16;
+
+/// Value length of a benchmark by default, as in `db_bench`
+pub const BENCH_DEFAULT_VALUE_SIZE: usize = 100;
+
+/// Seed of benchmark generators by default, as in `db_bench`
+pub const BENCH_DEFAULT_SEED: u64 = 301;
+
+/// Random bytes benchmark values are sliced from
+pub const BENCH_VALUE_POOL_SIZE: usize = 1024 * KB;
+
+/// Number of SSTables whose metadata is loaded at once when a store is opened
+pub const DEFAULT_RECOVERY_PARALLELISM: usize = 8;
+
+/// 1 Hour
+pub const SCRUB_PASS_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Keys are grouped by their first bytes in read profiles
+pub const READ_PROFILE_PREFIX_SIZE: usize = 4;
+
+/// Hottest key prefixes of the access profile whose blocks are read by warm-up
+pub const DEFAULT_WARM_UP_HOT_PREFIXES: usize = 16;
+
+/// One of every 8 gets reaching SSTables records a hit on the SSTable serving it
+pub const HOTNESS_SAMPLE_EVERY: usize = 8;
+
+/// Number of recent background errors kept for health reports
+pub const HEALTH_ERROR_HISTORY_SIZE: usize = 10;
+
 pub const DEFAULT_ALLOW_PREFETCH: bool = true;
 
 pub const DEFAULT_PREFETCH_SIZE: usize = 10;
 
+/// Bounds value log replayed on recovery to 16 megabytes past the last checkpoint
+pub const DEFAULT_HEAD_CHECKPOINT_SIZE: usize = 16 * 1024 * 1024;
+
+/// Checkpoints stores taking few writes at least once a minute
+pub const DEFAULT_HEAD_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Active memtables are only sealed once full by default
+pub const DEFAULT_MEMTABLE_MAX_AGE: Duration = Duration::ZERO;
+
+/// 1 Hour
+pub const MEMTABLE_AGE_CHECK_IDLE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 64KB
+pub const DEFAULT_VLOG_READ_AHEAD_SIZE: usize = SizeUnit::Kilobytes.as_bytes(64);
+
 pub const EOF: &str = "EOF";
 
 pub const HEAD_ENTRY_KEY: &[u8; 4] = b"head";
@@ -102,8 +280,9 @@ pub const SIZE_OF_U64: usize = std::mem::size_of::<u64>();
 
 pub const SIZE_OF_U8: usize = std::mem::size_of::<u8>();
 
-pub const FLUSH_SIGNAL: u8 = 1;
-
 pub const BLOCK_SIZE: usize = 4 * 1024; // 4KB
 
+/// Index lookups cached per SSTable
+pub const INDEX_CACHE_CAPACITY: usize = 64;
+
 pub const VLOG_START_OFFSET: usize = 0;
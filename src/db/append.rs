@@ -0,0 +1,139 @@
+use super::{DataStore, WriteBatch, WriteReceipt};
+use crate::consts::{APPEND_CHAIN_MAX_DEPTH, HEAD_KEY_SIZE};
+use crate::err::Error;
+use crate::memtable::{Entry, MemTable};
+use crate::slow_log::Phase;
+use crate::types::Key;
+use crate::vlog::{AppendLink, RecordType, ValueExpiry};
+use std::time::Instant;
+
+impl DataStore<'static, Key> {
+    /// Appends `suffix` to the value of `key`, an absent or deleted key gets `suffix` as value
+    ///
+    /// Only the suffix is written, in a value log record chained to the one
+    /// holding the current value. Reads concatenate the chain and GC writes
+    /// it again in full, so large values are not read to be extended. The
+    /// whole value is read and written again instead if write interceptors or
+    /// quotas are set, which have to see it, if other keys share the current
+    /// value, once the chain holds `APPEND_CHAIN_MAX_DEPTH` appends, or if the
+    /// current value has a TTL, which the extended value keeps
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.put("log", "boot;").await.unwrap(); // handle error
+    ///     store.append("log", "login;").await.unwrap(); // handle error
+    ///     assert_eq!(store.get("log").await.unwrap().unwrap().val, b"boot;login;");
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the write is invalid or rejected
+    pub async fn append<T: AsRef<[u8]>>(&self, key: T, suffix: T) -> Result<WriteReceipt, Error> {
+        self.validate_size(key.as_ref(), Some(suffix.as_ref()))?;
+        if self.health.is_read_only() {
+            return Err(Error::StoreReadOnly);
+        }
+        let encoded = self.config.comparator.encode(key.as_ref()).into_owned();
+        let size = MemTable::entry_size(encoded.len()) + MemTable::entry_size(HEAD_KEY_SIZE);
+        let capacity = self.active_memtable.read().unwrap().capacity();
+        if size > capacity {
+            return Err(Error::EntryLargerThanBuffer { size, capacity });
+        }
+        let mut timer = self.slow_log.foreground("append");
+        // the current value is read before the suffix is written
+        let _gate = self.write_gate.write().await;
+
+        if !self.gc_updated_entries.read().await.is_empty() {
+            self.sync_gc_update_with_store().await?
+        }
+        self.key_range.update_key_range().await;
+        let (previous, expires_at) = match self.locate(&encoded, &mut timer, false).await? {
+            Some(val) if !val.is_tombstone => (
+                Some(val.val_offset),
+                self.val_log.read().await.record_expiry(val.val_offset).await?,
+            ),
+            _ => (None, None),
+        };
+        // an expired value reads as deleted, the suffix replaces it
+        let (previous, expires_at) = match ValueExpiry::has_passed(expires_at, self.clock.now()) {
+            true => (None, None),
+            false => (previous, expires_at),
+        };
+        // GC has to know about chains in the value log before it frees records
+        self.dedup.load(&*self.val_log.read().await).await?;
+        let depth = previous
+            .and_then(|offset| self.dedup.chain(offset))
+            .map_or(1, |(_, depth)| depth + 1);
+        if expires_at.is_some() {
+            // chains do not extend values with a TTL, the value is written in full and keeps it
+            timer.finish();
+            let mut batch = WriteBatch::new();
+            batch.merge(key.as_ref(), suffix.as_ref());
+            return self.apply_batch(batch).await;
+        }
+        if !self.interceptors.is_empty()
+            || !self.quotas.is_empty()
+            || depth > APPEND_CHAIN_MAX_DEPTH
+            || previous.is_some_and(|offset| self.dedup.is_shared(offset))
+        {
+            let mut value = match previous {
+                Some(offset) => self.payload_value(offset).await?.to_vec(),
+                None => Vec::new(),
+            };
+            value.extend_from_slice(suffix.as_ref());
+            timer.finish();
+            return self.put_holding_gate(key.as_ref(), &value).await;
+        }
+        let mut val_log = self.val_log.write().await;
+        self.dedup.mark(&val_log).await?;
+
+        let link = AppendLink { previous, depth };
+        let created_at = self.clock.tick();
+        let phase_start = Instant::now();
+        self.io_latency.write().await;
+        let v_offset = val_log
+            .append_record(
+                &encoded[..],
+                &link.encode(suffix.as_ref())[..],
+                created_at,
+                RecordType::Append,
+            )
+            .await?;
+        timer.record(Phase::VLog, phase_start);
+
+        let phase_start = Instant::now();
+        let entry = Entry::new(encoded.to_owned(), v_offset, created_at, false);
+        let memtable_id = self.insert_into_active_memtable(&mut val_log, &entry);
+        self.row_cache.invalidate(&encoded);
+        timer.record(Phase::Memtable, phase_start);
+        // GC has to see the entry before the chain is linked, see `ValueDedup::link`
+        self.gc_table().write().await.insert(&entry);
+        let mut receipt = WriteReceipt::new(v_offset, memtable_id, created_at);
+        if !self.dedup.link(v_offset, link) {
+            // GC is checking the chain, the value is written in full
+            let (value, ..) = val_log.get_with_expiry(v_offset).await?;
+            let committed_at = self.clock.tick();
+            let v_offset = val_log
+                .append(&encoded[..], &value[..], committed_at, false)
+                .await?;
+            let entry = Entry::new(encoded.to_owned(), v_offset, committed_at, false);
+            let memtable_id = self.insert_into_active_memtable(&mut val_log, &entry);
+            receipt = WriteReceipt::new(v_offset, memtable_id, committed_at);
+            self.gc_table().write().await.insert(&entry);
+        }
+        self.enforce_write_buffer_budget(&mut val_log);
+        self.maintain_after_write(val_log).await;
+        timer.finish();
+        Ok(receipt)
+    }
+}
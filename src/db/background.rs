@@ -0,0 +1,82 @@
+use super::DataStore;
+use crate::compactors::CompactionStatus;
+use crate::err::Error;
+use crate::flush::FlushReport;
+use crate::gc::garbage_collector::GC;
+use crate::types::Key;
+use std::sync::Arc;
+
+impl DataStore<'static, Key> {
+    /// Flushes read-only memtables to disk
+    ///
+    /// Drives flushes in manual background mode, see
+    /// [`DataStore::with_manual_background_mode`]. The active memtable is
+    /// left in place, it is flushed once sealed. Only shared access is needed,
+    /// reads keep being served from the memtables while they are written
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn tick_flush(&self) -> Result<FlushReport, Error> {
+        self.flush_read_only_job().await
+    }
+
+    /// Seals and flushes the active memtable if it holds entries older than
+    /// `memtable_max_age`, see [`DataStore::with_memtable_max_age`]
+    ///
+    /// Drives the memtable age check in manual background mode, the sealed
+    /// memtable is flushed by [`DataStore::tick_flush`]. Returns `true` if the
+    /// memtable was sealed
+    pub async fn tick_memtable_age(&self) -> bool {
+        // writers seal memtables while they hold value log as well
+        let mut val_log = self.val_log.write().await;
+        let Some(head_offset) = self.rotate_expired_memtable() else {
+            return false;
+        };
+        val_log.set_head(head_offset);
+        true
+    }
+
+    /// Runs one compaction
+    ///
+    /// Drives compaction in manual background mode, see
+    /// [`DataStore::with_manual_background_mode`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if compaction failed
+    pub async fn tick_compaction(&self) -> Result<CompactionStatus, Error> {
+        self.compact_async().await
+    }
+
+    /// Runs one garbage collection of the value log
+    ///
+    /// Drives GC in manual background mode, see
+    /// [`DataStore::with_manual_background_mode`]. Entries moved by GC are
+    /// synced with the store before this returns. Writers only wait for GC
+    /// while it appends moved entries to value log
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn tick_gc(&self) -> Result<(), Error> {
+        if !self.gc_updated_entries.read().await.is_empty() {
+            self.sync_gc_update_with_store().await?;
+        }
+        GC::gc_handler(
+            &self.gc.config,
+            self.gc_table(),
+            Arc::clone(&self.gc_log),
+            Arc::clone(&self.key_range),
+            Arc::clone(&self.read_only_memtables),
+            Arc::clone(&self.gc_updated_entries),
+            Arc::clone(&self.gc.punch_marker),
+        )
+        .await?;
+        // nothing was moved if GC found no garbage
+        if self.gc_updated_entries.read().await.is_empty() {
+            return Ok(());
+        }
+        self.sync_gc_update_with_store().await
+    }
+}
@@ -0,0 +1,562 @@
+use super::{DataStore, InterceptedWrite, WriteReceipt};
+use crate::consts::{HEAD_KEY_SIZE, TOMB_STONE_MARKER};
+use crate::err::Error;
+use crate::memtable::{Entry, MemTable};
+use crate::slow_log::{OpTimer, Phase};
+use crate::types::{Key, ValOffset};
+use crate::vlog::{RecordType, ValueDedup, ValueExpiry, ValueLogEntry};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+/// Write of a [`WriteBatch`] as it is committed
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum BatchOp {
+    /// Insert or update of `key`
+    Put { key: Vec<u8>, value: Vec<u8> },
+
+    /// Insert or update of `key` whose value reads as deleted once `ttl` passed
+    PutWithTtl {
+        key: Vec<u8>,
+        value: Vec<u8>,
+        ttl: Duration,
+    },
+
+    /// Removal of `key`
+    Delete { key: Vec<u8> },
+
+    /// Insert of `key` sharing the value stored at `payload` of the value log
+    ValueRef { key: Vec<u8>, payload: ValOffset },
+}
+
+impl BatchOp {
+    /// Returns put of `value` to `key`, expiring after `ttl` if there is one
+    fn put(key: Vec<u8>, value: Vec<u8>, ttl: Option<Duration>) -> Self {
+        match ttl {
+            Some(ttl) => BatchOp::PutWithTtl { key, value, ttl },
+            None => BatchOp::Put { key, value },
+        }
+    }
+
+    fn key(&self) -> &[u8] {
+        match self {
+            BatchOp::Put { key, .. }
+            | BatchOp::PutWithTtl { key, .. }
+            | BatchOp::Delete { key }
+            | BatchOp::ValueRef { key, .. } => key,
+        }
+    }
+}
+
+/// Operation staged in a [`WriteBatch`]
+///
+/// Merges and range deletes are resolved to the writes they make when the
+/// batch is applied, see [`DataStore::write_batch`]
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum StagedOp {
+    /// Write committed as staged
+    Write(BatchOp),
+
+    /// Extension of the value of `key` with `operand`
+    Merge { key: Vec<u8>, operand: Vec<u8> },
+
+    /// Removal of every key within `[start, end]`
+    DeleteRange { start: Vec<u8>, end: Vec<u8> },
+}
+
+/// Writes that are committed atomically
+///
+/// Every operation of the batch is framed between batch records in the
+/// value log and written with a single IO, after a crash either all or
+/// none of them are recovered
+///
+/// Merges and range deletes are resolved against the store and the earlier
+/// operations of the batch when it is applied, and committed as the puts
+/// and deletes they make
+#[derive(Clone, Debug, Default)]
+pub struct WriteBatch {
+    pub(crate) ops: Vec<StagedOp>,
+}
+
+impl WriteBatch {
+    /// Creates an empty batch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stages insert of `key` with `value`
+    pub fn put(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> &mut Self {
+        self.ops.push(StagedOp::Write(BatchOp::Put {
+            key: key.as_ref().to_vec(),
+            value: value.as_ref().to_vec(),
+        }));
+        self
+    }
+
+    /// Stages insert of `key` with `value` that reads as deleted once `ttl`
+    /// passed after the batch is committed
+    ///
+    /// The TTL applies to this value only and is kept when the value is
+    /// extended by [`WriteBatch::merge`] or [`DataStore::append`] or moved by
+    /// [`DataStore::rename`], a later put replaces it. Until GC rewrites an
+    /// expired value as a tombstone, [`DataStore::count_range`] still counts
+    /// its key. Once a store wrote such a value, [`DataStore::contains_key`]
+    /// reports keys holding values as [`crate::db::KeyStatus::MaybeExpired`]
+    pub fn put_with_ttl(
+        &mut self,
+        key: impl AsRef<[u8]>,
+        value: impl AsRef<[u8]>,
+        ttl: Duration,
+    ) -> &mut Self {
+        self.ops.push(StagedOp::Write(BatchOp::PutWithTtl {
+            key: key.as_ref().to_vec(),
+            value: value.as_ref().to_vec(),
+            ttl,
+        }));
+        self
+    }
+
+    /// Stages removal of `key`
+    pub fn delete(&mut self, key: impl AsRef<[u8]>) -> &mut Self {
+        self.ops.push(StagedOp::Write(BatchOp::Delete {
+            key: key.as_ref().to_vec(),
+        }));
+        self
+    }
+
+    /// Stages extension of the value of `key` with `operand`, an absent or
+    /// deleted key gets `operand` as value
+    ///
+    /// The merged value is written in full when the batch is applied
+    pub fn merge(&mut self, key: impl AsRef<[u8]>, operand: impl AsRef<[u8]>) -> &mut Self {
+        self.ops.push(StagedOp::Merge {
+            key: key.as_ref().to_vec(),
+            operand: operand.as_ref().to_vec(),
+        });
+        self
+    }
+
+    /// Stages removal of every key within `[start, end]` in the order of the
+    /// store comparator, including keys put earlier in the batch
+    ///
+    /// Keys are looked up when the batch is applied and a tombstone is
+    /// written for each, keys put after the batch are not affected
+    pub fn delete_range(&mut self, start: impl AsRef<[u8]>, end: impl AsRef<[u8]>) -> &mut Self {
+        self.ops.push(StagedOp::DeleteRange {
+            start: start.as_ref().to_vec(),
+            end: end.as_ref().to_vec(),
+        });
+        self
+    }
+
+    /// Stages insert of `key` with the value stored at `payload` of the value
+    /// log, which is not written again unless it cannot be shared
+    pub(crate) fn value_ref(&mut self, key: impl AsRef<[u8]>, payload: ValOffset) -> &mut Self {
+        self.ops.push(StagedOp::Write(BatchOp::ValueRef {
+            key: key.as_ref().to_vec(),
+            payload,
+        }));
+        self
+    }
+
+    /// Returns number of staged operations
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns true if no operation is staged
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Removes every staged operation
+    pub fn clear(&mut self) {
+        self.ops.clear();
+    }
+}
+
+impl DataStore<'static, Key> {
+    /// Applies every operation of `batch` atomically
+    ///
+    /// Operations are applied in the order they were staged, so a later
+    /// operation on a key overrides an earlier one. Unlike [`DataStore::delete`],
+    /// deletes in a batch do not require the key to exist.
+    ///
+    /// Returns receipt of the batch's commit record, whose sequence number
+    /// every operation of the batch is visible at, along with the memtable
+    /// holding its last entry. An empty batch returns the receipt of
+    /// [`DataStore::unwritten_receipt`]
+    ///
+    /// Merges and range deletes read the store before the batch is written,
+    /// other writes wait for such a batch so none lands in between. Readers
+    /// see every entry of the batch or none of them
+    ///
+    /// # Errors
+    ///
+    /// Returns error if any key or value is invalid or an interceptor rejects
+    /// an operation, in which case nothing is written
+    pub async fn write_batch(&self, batch: WriteBatch) -> Result<WriteReceipt, Error> {
+        let reads_store = batch.ops.iter().any(|op| !matches!(op, StagedOp::Write(_)));
+        let (_exclusive, _shared) = match reads_store {
+            true => (Some(self.write_gate.write().await), None),
+            false => (None, Some(self.write_gate.read().await)),
+        };
+        self.apply_batch(batch).await
+    }
+
+    /// Applies `batch` like [`DataStore::write_batch`], callers hold `write_gate`
+    pub(crate) async fn apply_batch(&self, batch: WriteBatch) -> Result<WriteReceipt, Error> {
+        if batch.is_empty() {
+            return Ok(self.unwritten_receipt().await);
+        }
+        let mut timer = self.slow_log.foreground("write_batch");
+        let mut staged = self.resolve_staged_ops(batch.ops, &mut timer).await?;
+        if staged.is_empty() {
+            timer.finish();
+            return Ok(self.unwritten_receipt().await);
+        }
+        if !self.interceptors.is_empty() {
+            let mut intercepted = Vec::with_capacity(staged.len());
+            for op in staged {
+                let (write, ttl) = match op {
+                    BatchOp::Put { key, value } => (InterceptedWrite::Put { key, value }, None),
+                    BatchOp::PutWithTtl { key, value, ttl } => {
+                        (InterceptedWrite::Put { key, value }, Some(ttl))
+                    }
+                    BatchOp::Delete { key } => (InterceptedWrite::Delete { key }, None),
+                    // interceptors have to see the value being put, it is written again
+                    BatchOp::ValueRef { key, payload } => {
+                        let (value, ttl) = self.payload_with_ttl(payload).await?;
+                        (InterceptedWrite::Put { key, value }, ttl)
+                    }
+                };
+                intercepted.push(match self.interceptors.intercept(write).await? {
+                    InterceptedWrite::Put { key, value } => BatchOp::put(key, value, ttl),
+                    write => write.into(),
+                });
+            }
+            staged = intercepted;
+        }
+        let capacity = self.active_memtable.read().unwrap().capacity();
+        let mut ops = Vec::with_capacity(staged.len());
+        for op in staged {
+            match &op {
+                BatchOp::Put { key, value } | BatchOp::PutWithTtl { key, value, .. } => {
+                    self.validate_size(key, Some(value))?
+                }
+                BatchOp::Delete { key } | BatchOp::ValueRef { key, .. } => {
+                    self.validate_size(key, None::<&[u8]>)?
+                }
+            }
+            let key = self.config.comparator.encode(op.key()).into_owned();
+            let size = MemTable::entry_size(key.len()) + MemTable::entry_size(HEAD_KEY_SIZE);
+            if size > capacity {
+                return Err(Error::EntryLargerThanBuffer { size, capacity });
+            }
+            ops.push(match op {
+                BatchOp::Put { value, .. } => BatchOp::Put { key, value },
+                BatchOp::PutWithTtl { value, ttl, .. } => BatchOp::PutWithTtl { key, value, ttl },
+                BatchOp::Delete { .. } => BatchOp::Delete { key },
+                BatchOp::ValueRef { payload, .. } => BatchOp::ValueRef { key, payload },
+            });
+        }
+        if self.health.is_read_only() {
+            return Err(Error::StoreReadOnly);
+        }
+
+        if !self.gc_updated_entries.read().await.is_empty() {
+            self.sync_gc_update_with_store().await?
+        }
+        self.key_range.update_key_range().await;
+        // shared payloads are pinned before anything is written, so GC cannot free them
+        // before the references are added, the ones that cannot be pinned are written again
+        let mut pins = HashMap::new();
+        if ops.iter().any(|op| matches!(op, BatchOp::ValueRef { .. })) {
+            // GC has to know about references in the value log before it frees payloads
+            self.dedup.load(&*self.val_log.read().await).await?;
+            for (index, op) in ops.iter_mut().enumerate() {
+                let BatchOp::ValueRef { key, payload } = op else {
+                    continue;
+                };
+                // GC writes appended values again under the key of the chain, and
+                // values with a TTL are written again so GC does not have to share them
+                let pin = match self.dedup.chain(*payload) {
+                    Some(_) => None,
+                    None if self.val_log.read().await.record_expiry(*payload).await?.is_some() => None,
+                    None => self.dedup.pin(*payload),
+                };
+                match pin {
+                    Some(pin) => {
+                        pins.insert(index, pin);
+                    }
+                    None => {
+                        let (value, ttl) = self.payload_with_ttl(*payload).await?;
+                        *op = BatchOp::put(std::mem::take(key), value, ttl);
+                    }
+                }
+            }
+            if !pins.is_empty() {
+                self.dedup.mark(&*self.val_log.read().await).await?;
+            }
+        }
+        // values written by the batch as quotas count them
+        let quota_values = match self.quotas.is_empty() {
+            true => Vec::new(),
+            false => {
+                let mut values = Vec::with_capacity(ops.len());
+                for op in &ops {
+                    values.push(match op {
+                        BatchOp::Put { value, .. } | BatchOp::PutWithTtl { value, .. } => {
+                            Some(value.to_owned())
+                        }
+                        BatchOp::Delete { .. } => None,
+                        BatchOp::ValueRef { payload, .. } => {
+                            Some(self.payload_value(*payload).await?.to_vec())
+                        }
+                    });
+                }
+                values
+            }
+        };
+        if ops.iter().any(|op| matches!(op, BatchOp::PutWithTtl { .. })) {
+            self.record_expiring_values().await?;
+        }
+
+        let mut val_log = self.val_log.write().await;
+        // usage is read while the value log is held, so no other write changes it before the batch
+        let writes: Vec<_> = ops
+            .iter()
+            .zip(&quota_values)
+            .map(|(op, value)| (op.key(), value.as_deref()))
+            .collect();
+        let quota_deltas = self.quota_deltas(&writes, &val_log, &mut timer).await?;
+        self.quotas.check(&quota_deltas)?;
+        let begun_at = self.clock.tick();
+        let created_at: Vec<_> = ops.iter().map(|_| self.clock.tick()).collect();
+        // values of records that do not hold the value as staged
+        let encoded: Vec<_> = ops
+            .iter()
+            .zip(&created_at)
+            .map(|(op, created_at)| match op {
+                BatchOp::PutWithTtl { value, ttl, .. } => ValueExpiry::after(*created_at, *ttl).encode(value),
+                BatchOp::ValueRef { payload, .. } => ValueDedup::encode_ref(*payload).to_vec(),
+                _ => Vec::new(),
+            })
+            .collect();
+        let mut records = Vec::with_capacity(ops.len() + 2);
+        records.push(ValueLogEntry::with_record_type(
+            0,
+            0,
+            &[][..],
+            &[][..],
+            begun_at,
+            RecordType::BatchBegin,
+        ));
+        for ((op, encoded), created_at) in ops.iter().zip(&encoded).zip(created_at) {
+            let (value, record_type) = match op {
+                BatchOp::Put { value, .. } => (value.as_slice(), RecordType::Put),
+                BatchOp::PutWithTtl { .. } => (&encoded[..], RecordType::Expiring),
+                BatchOp::Delete { .. } => (TOMB_STONE_MARKER.as_bytes(), RecordType::Delete),
+                BatchOp::ValueRef { .. } => (&encoded[..], RecordType::ValueRef),
+            };
+            records.push(ValueLogEntry::with_record_type(
+                op.key().len(),
+                value.len(),
+                op.key(),
+                value,
+                created_at,
+                record_type,
+            ));
+        }
+        records.push(ValueLogEntry::with_record_type(
+            0,
+            0,
+            &[][..],
+            &[][..],
+            self.clock.tick(),
+            RecordType::BatchCommit,
+        ));
+        let phase_start = Instant::now();
+        self.io_latency.write().await;
+        let offsets = val_log.append_records(&records).await?;
+        timer.record(Phase::VLog, phase_start);
+
+        // every operation of the batch is visible at the sequence of its commit record
+        let committed_at = records[records.len() - 1].created_at;
+        let commit_offset = offsets[offsets.len() - 1];
+        let phase_start = Instant::now();
+        let entries: Vec<_> = ops
+            .iter()
+            .zip(records.iter().zip(offsets).skip(1))
+            .map(|(op, (record, v_offset))| {
+                let is_tombstone = matches!(op, BatchOp::Delete { .. });
+                // the entry reads its value from the shared payload
+                let v_offset = match op {
+                    BatchOp::ValueRef { payload, .. } => *payload,
+                    _ => v_offset,
+                };
+                Entry::new(op.key().to_vec(), v_offset, record.created_at, is_tombstone)
+            })
+            .collect();
+        let memtable_id = self.insert_batch_into_active_memtable(&mut val_log, &entries);
+        timer.record(Phase::Memtable, phase_start);
+        // GC has to see the entries before references are added, see `ValueDedup::attach`
+        {
+            let gc_table = self.gc_table();
+            let mut gc_table = gc_table.write().await;
+            for entry in &entries {
+                gc_table.insert(entry);
+            }
+        }
+        for (index, pin) in pins {
+            pin.refer(entries[index].key.to_owned(), entries[index].created_at);
+        }
+        self.enforce_write_buffer_budget(&mut val_log);
+        self.quotas.apply(&quota_deltas);
+        self.maintain_after_write(val_log).await;
+        timer.finish();
+        Ok(WriteReceipt::new(commit_offset, memtable_id, committed_at))
+    }
+
+    /// Resolves merges and range deletes of `ops` to the puts and deletes they make
+    ///
+    /// A merge extends the value the latest earlier operation of the batch
+    /// leaves its key with, or else the stored value, and keeps its TTL. A
+    /// range delete removes stored keys within the range along with keys
+    /// written earlier in the batch
+    async fn resolve_staged_ops(
+        &self,
+        ops: Vec<StagedOp>,
+        timer: &mut OpTimer,
+    ) -> Result<Vec<BatchOp>, Error> {
+        let comparator = self.config.comparator;
+        // index of the latest write of each encoded key, `None` once deleted
+        let mut written: BTreeMap<Key, Option<usize>> = BTreeMap::new();
+        let mut resolved = Vec::with_capacity(ops.len());
+        for op in ops {
+            match op {
+                StagedOp::Write(op) => {
+                    let key = comparator.encode(op.key()).into_owned();
+                    let index = (!matches!(op, BatchOp::Delete { .. })).then_some(resolved.len());
+                    written.insert(key, index);
+                    resolved.push(op);
+                }
+                StagedOp::Merge { key, operand } => {
+                    let encoded = comparator.encode(&key).into_owned();
+                    let current = match written.get(&encoded) {
+                        Some(Some(index)) => match &resolved[*index] {
+                            BatchOp::Put { value, .. } => Some((value.to_owned(), None)),
+                            BatchOp::PutWithTtl { value, ttl, .. } => Some((value.to_owned(), Some(*ttl))),
+                            BatchOp::ValueRef { payload, .. } => Some(self.payload_with_ttl(*payload).await?),
+                            BatchOp::Delete { .. } => None,
+                        },
+                        Some(None) => None,
+                        None => {
+                            self.get_uncached(&encoded, timer, false)
+                                .await?
+                                .map(|(entry, expires_at)| {
+                                    let ttl = expires_at.map(|expires_at| {
+                                        ValueExpiry { expires_at }.remaining(self.clock.now())
+                                    });
+                                    (entry.val.to_vec(), ttl)
+                                })
+                        }
+                    };
+                    let (mut value, ttl) = current.unwrap_or_default();
+                    value.extend_from_slice(&operand);
+                    written.insert(encoded, Some(resolved.len()));
+                    resolved.push(BatchOp::put(key, value, ttl));
+                }
+                StagedOp::DeleteRange { start, end } => {
+                    let (start, end) = (
+                        comparator.encode(&start).into_owned(),
+                        comparator.encode(&end).into_owned(),
+                    );
+                    if start > end {
+                        continue;
+                    }
+                    let (merger, _) = self.merge_keys_in_range(&start, &end, timer).await?;
+                    let mut keys: BTreeSet<Key> = merger.map(|entry| entry.key).collect();
+                    keys.extend(written.range(start..=end).map(|(key, _)| key.to_owned()));
+                    for key in keys {
+                        // keys deleted earlier in the batch are not deleted again
+                        if written.insert(key.to_owned(), None) != Some(None) {
+                            resolved.push(BatchOp::Delete {
+                                key: comparator.decode(key),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Records in meta that values with a TTL of their own are written, see
+    /// [`crate::db::KeyStatus::MaybeExpired`]
+    ///
+    /// Meta is written before the first such value is appended, so a crash
+    /// cannot leave the value without the record
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error in case it occurs, the record is retried by the next such write
+    async fn record_expiring_values(&self) -> Result<(), Error> {
+        let mut meta = {
+            let mut meta = self.meta.lock().unwrap();
+            if meta.expiring_values {
+                return Ok(());
+            }
+            meta.expiring_values = true;
+            meta.update_last_modified();
+            meta.to_owned()
+        };
+        if let Err(err) = meta.write().await {
+            self.meta.lock().unwrap().expiring_values = false;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Returns value stored at `payload` of the value log along with the time
+    /// left until it expires, if it has a TTL
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFoundInDB`] if no value is stored there or it expired,
+    /// or error if an IO error occured
+    async fn payload_with_ttl(&self, payload: ValOffset) -> Result<(Vec<u8>, Option<Duration>), Error> {
+        self.io_latency.read().await;
+        let now = self.clock.now();
+        match self.val_log.read().await.get_with_expiry(payload).await? {
+            (_, true, _) => Err(Error::NotFoundInDB),
+            (_, false, expires_at) if ValueExpiry::has_passed(expires_at, now) => Err(Error::NotFoundInDB),
+            (value, false, expires_at) => {
+                let ttl = expires_at.map(|expires_at| ValueExpiry { expires_at }.remaining(now));
+                Ok((value.to_vec(), ttl))
+            }
+        }
+    }
+
+    /// Removes every key of `keys` with a single value log write
+    ///
+    /// Tombstones are committed together as with [`DataStore::write_batch`],
+    /// so unlike calling [`DataStore::delete`] for each key, keys are not
+    /// looked up first and do not have to exist
+    ///
+    /// Returns receipt of the commit record of the tombstones, see
+    /// [`DataStore::write_batch`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error if any key is invalid, in which case nothing is deleted
+    pub async fn delete_many<T: AsRef<[u8]>>(
+        &self,
+        keys: impl IntoIterator<Item = T>,
+    ) -> Result<WriteReceipt, Error> {
+        let mut batch = WriteBatch::new();
+        for key in keys {
+            batch.delete(key);
+        }
+        self.write_batch(batch).await
+    }
+}
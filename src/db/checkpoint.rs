@@ -0,0 +1,408 @@
+use super::DataStore;
+use crate::{
+    consts::{
+        HEAD_CHECKPOINT_FILE_NAME, HEAD_ENTRY_KEY, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, TAIL_ENTRY_KEY,
+    },
+    err::Error::{self, *},
+    fs::{FileAsync, FileNode},
+    memtable::Entry,
+    meta::Meta,
+    types::{ActiveMemTable, CreatedAt, ImmutableMemTables, Key, MetaHandle, ValOffset},
+    util,
+    vlog::ValueLog,
+};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::{OwnedMutexGuard, RwLockWriteGuard},
+};
+
+/// Tracks when value log head was last checkpointed
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct HeadCheckpoint {
+    /// Time of the last checkpoint
+    pub(crate) last_checkpoint_at: CreatedAt,
+
+    /// Value log size at the last checkpoint
+    pub(crate) last_vlog_size: usize,
+}
+
+impl HeadCheckpoint {
+    /// Creates new `HeadCheckpoint`
+    pub(crate) fn new(last_checkpoint_at: CreatedAt, last_vlog_size: usize) -> Self {
+        Self {
+            last_checkpoint_at,
+            last_vlog_size,
+        }
+    }
+}
+
+/// Memtable entries written by a head checkpoint
+///
+/// Entries cover value log up to `offset`, recovery inserts them and replays
+/// value log from `offset` instead of from `head`. Meta records `head` and
+/// `offset` of the last checkpoint, a file not matching them is ignored
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct CheckpointRecord {
+    /// Value log head when the checkpoint was taken
+    pub(crate) head: usize,
+
+    /// Value log size when the checkpoint was taken
+    pub(crate) offset: usize,
+
+    /// Entries of read-only memtables followed by entries of the active memtable
+    pub(crate) entries: Vec<Entry<Key, ValOffset>>,
+}
+
+impl CheckpointRecord {
+    /// Returns path of the checkpoint file in the meta directory `dir`
+    pub(crate) fn path<P: AsRef<Path>>(dir: P) -> PathBuf {
+        dir.as_ref().join(HEAD_CHECKPOINT_FILE_NAME)
+    }
+
+    /// Writes the record to a temporary file and renames it over the old one
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error in case it occurs
+    pub(crate) async fn write<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        let path = Self::path(dir);
+        let tmp_path = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path).await.map_err(|error| FileCreation {
+            path: tmp_path.to_owned(),
+            error,
+        })?;
+        file.write_all(&self.serialize())
+            .await
+            .map_err(|error| FileWrite {
+                path: tmp_path.to_owned(),
+                error,
+            })?;
+        file.sync_all().await.map_err(FileSync)?;
+        fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|error| FileRename { path, error })
+    }
+
+    /// Loads the checkpoint `meta` records from the meta directory `dir`
+    ///
+    /// Returns `None` if meta records none for its current head, or the file
+    /// is missing, corrupted, does not match meta or covers more than the
+    /// `vlog_size` bytes of value log. Recovery replays value log from head then
+    pub(crate) async fn load<P: AsRef<Path>>(dir: P, meta: &Meta, vlog_size: usize) -> Option<Self> {
+        if !meta.has_checkpoint() {
+            return None;
+        }
+        let path = Self::path(dir);
+        let bytes = match fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                log::warn!(
+                    "Head checkpoint {:?} could not be read, replaying value log from head: {}",
+                    path,
+                    err
+                );
+                return None;
+            }
+        };
+        match Self::deserialize(&bytes) {
+            Some(record)
+                if record.head == meta.checkpoint_head
+                    && record.offset == meta.checkpoint_offset
+                    && record.offset <= vlog_size =>
+            {
+                Some(record)
+            }
+            _ => {
+                log::warn!(
+                    "Head checkpoint {:?} does not match meta, replaying value log from head",
+                    path
+                );
+                None
+            }
+        }
+    }
+
+    /// Serializes the record as head, offset and entry count followed by entries
+    /// and a checksum of everything before it
+    fn serialize(&self) -> Vec<u8> {
+        let mut serialized_data = Vec::new();
+        serialized_data.extend_from_slice(&(self.head as u64).to_le_bytes());
+        serialized_data.extend_from_slice(&(self.offset as u64).to_le_bytes());
+        serialized_data.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for entry in self.entries.iter() {
+            serialized_data.extend_from_slice(&(entry.key.len() as u32).to_le_bytes());
+            serialized_data.extend_from_slice(&entry.key);
+            serialized_data.extend_from_slice(&(entry.val_offset as u64).to_le_bytes());
+            serialized_data.extend_from_slice(&util::datetime_to_timestamp(entry.created_at).to_le_bytes());
+            serialized_data.push(entry.is_tombstone as u8);
+        }
+        let checksum = crc32fast::hash(&serialized_data);
+        serialized_data.extend_from_slice(&checksum.to_le_bytes());
+        serialized_data
+    }
+
+    /// Parses bytes written by `serialize`, returns `None` if they are malformed
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        let body_len = bytes.len().checked_sub(SIZE_OF_U32)?;
+        let (body, checksum) = bytes.split_at(body_len);
+        if crc32fast::hash(body) != u32::from_le_bytes(checksum.try_into().ok()?) {
+            return None;
+        }
+        let mut offset = 0;
+        let mut read_bytes = |len: usize| -> Option<&[u8]> {
+            let bytes = body.get(offset..offset + len)?;
+            offset += len;
+            Some(bytes)
+        };
+        let head = u64::from_le_bytes(read_bytes(SIZE_OF_U64)?.try_into().ok()?) as usize;
+        let vlog_offset = u64::from_le_bytes(read_bytes(SIZE_OF_U64)?.try_into().ok()?) as usize;
+        let count = u32::from_le_bytes(read_bytes(SIZE_OF_U32)?.try_into().ok()?);
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let key_len = u32::from_le_bytes(read_bytes(SIZE_OF_U32)?.try_into().ok()?);
+            let key = read_bytes(key_len as usize)?.to_vec();
+            let val_offset = u64::from_le_bytes(read_bytes(SIZE_OF_U64)?.try_into().ok()?) as usize;
+            let created_at =
+                util::timestamp_to_datetime(u64::from_le_bytes(read_bytes(SIZE_OF_U64)?.try_into().ok()?));
+            let is_tombstone = match read_bytes(SIZE_OF_U8)?[0] {
+                0 => false,
+                1 => true,
+                _ => return None,
+            };
+            entries.push(Entry::new(key, val_offset, created_at, is_tombstone));
+        }
+        (offset == body.len()).then_some(Self {
+            head,
+            offset: vlog_offset,
+            entries,
+        })
+    }
+}
+
+/// Head checkpoint whose value log offset was taken, its entries are yet to be written
+pub(crate) struct PendingCheckpoint {
+    /// Held until the checkpoint is written, no other checkpoint starts meanwhile
+    last: OwnedMutexGuard<HeadCheckpoint>,
+
+    /// Value log offset the checkpoint covers
+    offset: usize,
+}
+
+/// Writes head checkpoints
+///
+/// Holds everything writing a checkpoint reads, so its entries are collected
+/// and written by a background task once writers are let go
+#[derive(Clone)]
+pub(crate) struct HeadCheckpointer {
+    pub(crate) active_memtable: ActiveMemTable<Key>,
+    pub(crate) read_only_memtables: ImmutableMemTables<Key>,
+    pub(crate) meta: MetaHandle,
+    pub(crate) meta_dir: PathBuf,
+    pub(crate) vlog_file: FileNode,
+}
+
+impl HeadCheckpointer {
+    /// Writes entries of the memtables covering value log up to the offset of
+    /// `pending` to the meta directory and records the checkpoint in meta
+    ///
+    /// Entries written since the offset was taken may be written too, recovery
+    /// inserts them again when it replays value log from the offset. Value log
+    /// is synced after they are collected so none of them points past its end
+    /// after a crash
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error in case it occurs, the previous checkpoint stays in use then
+    pub(crate) async fn write(&self, pending: PendingCheckpoint) -> Result<(), Error> {
+        let record = {
+            // sealing holds the active memtable while it moves it to read-only
+            // memtables and moves the head. Only its entries are taken while it
+            // is held, writers insert into it while they are collected
+            let (active, head) = {
+                let active = self.active_memtable.read().unwrap();
+                (Arc::clone(&active.entries), self.meta.lock().unwrap().v_log_head)
+            };
+            let entries = self
+                .read_only_memtables
+                .iter()
+                .flat_map(|table| {
+                    table
+                        .value()
+                        .entries
+                        .iter()
+                        .map(|e| {
+                            Entry::new(
+                                e.key().to_owned(),
+                                e.value().val_offset,
+                                e.value().created_at,
+                                e.value().is_tombstone,
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .chain(active.iter().map(|e| {
+                    Entry::new(
+                        e.key().to_owned(),
+                        e.value().val_offset,
+                        e.value().created_at,
+                        e.value().is_tombstone,
+                    )
+                }))
+                // head and tail are tracked by meta, they are not user entries
+                .filter(|e| e.key != HEAD_ENTRY_KEY && e.key != TAIL_ENTRY_KEY)
+                .collect();
+            CheckpointRecord {
+                head,
+                offset: pending.offset,
+                entries,
+            }
+        };
+        self.vlog_file.sync_all().await?;
+        record.write(&self.meta_dir).await?;
+        let mut meta = {
+            let mut meta = self.meta.lock().unwrap();
+            meta.set_checkpoint(record.head, record.offset);
+            meta.update_last_modified();
+            meta.to_owned()
+        };
+        meta.write().await?;
+        drop(pending.last);
+        Ok(())
+    }
+}
+
+impl DataStore<'static, Key> {
+    /// Returns `true` if enough time has passed or enough bytes were appended
+    /// to value log of `vlog_size` since `head_checkpoint`
+    fn head_checkpoint_due(&self, head_checkpoint: &HeadCheckpoint, vlog_size: usize) -> bool {
+        let bytes_written = vlog_size.saturating_sub(head_checkpoint.last_vlog_size);
+        if self.config.head_checkpoint_size > 0 && bytes_written >= self.config.head_checkpoint_size {
+            return true;
+        }
+        let interval = self.config.head_checkpoint_interval;
+        !interval.is_zero()
+            && self
+                .clock
+                .now()
+                .signed_duration_since(head_checkpoint.last_checkpoint_at)
+                .to_std()
+                .is_ok_and(|elapsed| elapsed >= interval)
+    }
+
+    /// Returns `true` if the active memtable holds entries older than `memtable_max_age`
+    pub(crate) fn memtable_expired(&self) -> bool {
+        let max_age = self.config.memtable_max_age;
+        !max_age.is_zero() && self.active_memtable.read().unwrap().age() >= max_age
+    }
+
+    /// Seals and flushes the active memtable if it holds entries older than
+    /// `memtable_max_age`, returns the value log head it was sealed at
+    pub(crate) fn rotate_expired_memtable(&self) -> Option<usize> {
+        if !self.memtable_expired() {
+            return None;
+        }
+        let head_offset = self.migrate_memtable_to_read_only();
+        self.flush_read_only_memtables();
+        Some(head_offset)
+    }
+
+    /// Runs the checks writes trigger once they are in the memtable, `val_log`
+    /// is released once they are done
+    ///
+    /// A due head checkpoint only takes its value log offset before `val_log`
+    /// is released, its entries are written by a background task. The write
+    /// already succeeded, a failed checkpoint is logged and a later write retries it
+    pub(crate) async fn maintain_after_write(&self, mut val_log: RwLockWriteGuard<'_, ValueLog>) {
+        if let Some(head_offset) = self.rotate_expired_memtable() {
+            val_log.set_head(head_offset);
+        }
+        let Ok(last) = Arc::clone(&self.head_checkpoint).try_lock_owned() else {
+            // a checkpoint is being written
+            return;
+        };
+        if !self.head_checkpoint_due(&last, val_log.size) {
+            return;
+        }
+        let pending = self.start_head_checkpoint(last, &val_log);
+        let checkpointer = self.checkpointer(&val_log);
+        drop(val_log);
+        tokio::spawn(async move {
+            if let Err(err) = checkpointer.write(pending).await {
+                log::error!("Head checkpoint failed: {}", err)
+            }
+        });
+    }
+
+    /// Returns [`HeadCheckpointer`] writing checkpoints of the store, whose value log is `val_log`
+    pub(crate) fn checkpointer(&self, val_log: &ValueLog) -> HeadCheckpointer {
+        HeadCheckpointer {
+            active_memtable: self.active_memtable.clone(),
+            read_only_memtables: self.read_only_memtables.clone(),
+            meta: self.meta.clone(),
+            meta_dir: self.dir.meta.to_owned(),
+            vlog_file: val_log.content.file.node.clone(),
+        }
+    }
+
+    /// Takes the value log offset a checkpoint covers, `last` is the last checkpoint
+    ///
+    /// Writers hold value log until their entries are in the memtable, held
+    /// `val_log` keeps the entries covering the offset in place. GC holds it
+    /// as well while it appends moved values, so its size is where it ends
+    fn start_head_checkpoint(
+        &self,
+        mut last: OwnedMutexGuard<HeadCheckpoint>,
+        val_log: &ValueLog,
+    ) -> PendingCheckpoint {
+        *last = HeadCheckpoint::new(self.clock.now(), val_log.size);
+        PendingCheckpoint {
+            last,
+            offset: val_log.size,
+        }
+    }
+
+    /// Checkpoints value log head
+    ///
+    /// Head is otherwise only advanced once a memtable fills up, so with large
+    /// memtables or slow writes recovery would replay an unbounded amount of
+    /// value log. Entries of the memtables are written to the meta directory
+    /// and meta records the value log offset they cover, memtables are left
+    /// in place. Waits for a checkpoint being written by a background task
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error in case it occurs, the previous checkpoint stays in use then
+    pub(crate) async fn checkpoint_head(&self, val_log: &ValueLog) -> Result<(), Error> {
+        let last = Arc::clone(&self.head_checkpoint).lock_owned().await;
+        let pending = self.start_head_checkpoint(last, val_log);
+        self.checkpointer(val_log).write(pending).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_checkpoint_record_roundtrip() {
+        let record = CheckpointRecord {
+            head: 40,
+            offset: 400,
+            entries: vec![
+                Entry::new(b"a".to_vec(), 40, Utc::now(), false),
+                Entry::new(b"b".to_vec(), 80, Utc::now(), true),
+            ],
+        };
+        let serialized = record.serialize();
+        assert_eq!(CheckpointRecord::deserialize(&serialized), Some(record));
+        // torn or flipped bytes fail the checksum
+        assert!(CheckpointRecord::deserialize(&serialized[..serialized.len() - 1]).is_none());
+        let mut flipped = serialized.to_owned();
+        flipped[SIZE_OF_U64] ^= 1;
+        assert!(CheckpointRecord::deserialize(&flipped).is_none());
+    }
+}
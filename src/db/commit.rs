@@ -0,0 +1,227 @@
+use super::{DataStore, Db};
+use crate::{
+    err::Error::{self, *},
+    types::{Key, SeqNumber},
+};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::{watch, Mutex, RwLockReadGuard, RwLockWriteGuard};
+
+/// Sequence number up to which writes are synced to disk
+///
+/// Syncs are grouped, callers waiting for a sequence number take turns and
+/// one value log sync commits every write applied before it started, so
+/// callers queued behind a sync usually find their write committed by it
+#[derive(Clone, Debug)]
+pub(crate) struct CommitWatermark {
+    committed: Arc<AtomicU64>,
+    sync: Arc<Mutex<()>>,
+
+    /// Latest sequence number applied through a [`Db`], published once the
+    /// writer releases the store
+    applied: Arc<watch::Sender<SeqNumber>>,
+}
+
+impl CommitWatermark {
+    /// Creates new `CommitWatermark` with writes up to `committed` already on disk
+    pub fn new(committed: SeqNumber) -> Self {
+        Self {
+            committed: Arc::new(AtomicU64::new(committed)),
+            sync: Default::default(),
+            applied: Arc::new(watch::channel(committed).0),
+        }
+    }
+
+    /// Returns sequence number of the latest committed write
+    pub fn get(&self) -> SeqNumber {
+        self.committed.load(Ordering::Acquire)
+    }
+
+    /// Records writes up to `seq` as committed
+    fn advance(&self, seq: SeqNumber) {
+        self.committed.fetch_max(seq, Ordering::AcqRel);
+    }
+
+    /// Wakes up callers waiting for writes up to `seq` to be applied
+    fn publish_applied(&self, seq: SeqNumber) {
+        self.applied.send_if_modified(|applied| {
+            if seq > *applied {
+                *applied = seq;
+                return true;
+            }
+            false
+        });
+    }
+}
+
+impl DataStore<'static, Key> {
+    /// Returns sequence number of the latest write synced to disk
+    ///
+    /// Writes are appended to the value log without syncing it, a write whose
+    /// sequence number is at most this one survives a power loss. Writes
+    /// recovered when the store is opened count as committed
+    pub fn last_committed_seq(&self) -> SeqNumber {
+        self.commit_watermark.get()
+    }
+
+    /// Waits until the write with sequence number `seq` is synced to disk,
+    /// syncing the value log unless a sync already covered it
+    ///
+    /// The store cannot apply writes while it is borrowed, see
+    /// [`Db::wait_for_seq`] to wait for writes not applied yet
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::SequenceNotApplied`] if no write with `seq` was applied
+    /// yet or error in case there is an IO error
+    pub async fn wait_for_seq(&self, seq: SeqNumber) -> Result<(), Error> {
+        if seq > self.latest_sequence() {
+            return Err(SequenceNotApplied(seq));
+        }
+        self.commit(seq).await
+    }
+
+    /// Syncs value log if write `seq` is not committed yet, every write applied
+    /// before the sync is committed along with it
+    async fn commit(&self, seq: SeqNumber) -> Result<(), Error> {
+        if self.commit_watermark.get() >= seq {
+            return Ok(());
+        }
+        let _sync = self.commit_watermark.sync.lock().await;
+        // the sync we waited for may have covered it
+        if self.commit_watermark.get() >= seq {
+            return Ok(());
+        }
+        // writers tick the clock and append while holding value log for writing,
+        // every sequence number up to the latest is appended once it is read
+        let val_log = self.val_log.read().await;
+        let latest = self.latest_sequence();
+        val_log.sync_to_disk().await?;
+        self.commit_watermark.advance(latest);
+        Ok(())
+    }
+}
+
+impl Db {
+    /// Returns sequence number of the latest write synced to disk, see [`DataStore::last_committed_seq`]
+    pub async fn last_committed_seq(&self) -> SeqNumber {
+        self.store.read().await.last_committed_seq()
+    }
+
+    /// Waits until the write with sequence number `seq` is applied and synced
+    /// to disk, e.g. a sequence number passed along by a replication consumer
+    /// to read its own writes from another process
+    ///
+    /// Unlike [`DataStore::wait_for_seq`] writes not applied yet are waited
+    /// for, at most `timeout`. Waiters are woken up by writers of this `Db`
+    /// once they release the store
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::SequenceWaitTimedOut`] if no write with `seq` was
+    /// applied within `timeout` or error in case there is an IO error
+    pub async fn wait_for_seq(&self, seq: SeqNumber, timeout: Duration) -> Result<(), Error> {
+        self.wait_for_applied(seq, timeout).await?.commit(seq).await
+    }
+
+    /// Waits at most `timeout` until the write with sequence number `seq` is
+    /// applied, returns the store once it is
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::SequenceWaitTimedOut`] if no write with `seq` was
+    /// applied within `timeout`
+    pub(crate) async fn wait_for_applied(
+        &self,
+        seq: SeqNumber,
+        timeout: Duration,
+    ) -> Result<RwLockReadGuard<'_, DataStore<'static, Key>>, Error> {
+        // a writer publishing before the subscription is seen by `wait_for`
+        let mut applied = {
+            let store = self.store.read().await;
+            if store.latest_sequence() >= seq {
+                return Ok(store);
+            }
+            store.commit_watermark.applied.subscribe()
+        };
+        let waited = tokio::time::timeout(timeout, applied.wait_for(|applied| *applied >= seq))
+            .await
+            .map(|res| res.map(|_| ()));
+        match waited {
+            Ok(Ok(())) => Ok(self.store.read().await),
+            // the sender lives in the store this handle keeps alive
+            Ok(Err(_)) => Err(SequenceNotApplied(seq)),
+            Err(_) => Err(SequenceWaitTimedOut(seq)),
+        }
+    }
+
+    /// Locks the store for a write, waiters of [`Db::wait_for_seq`] are woken
+    /// up once the returned guard is dropped
+    pub(crate) async fn write_store(&self) -> StoreWriteGuard<'_> {
+        StoreWriteGuard {
+            store: self.store.write().await,
+        }
+    }
+
+    /// Shares the store with other writes and reads for a write, waiters of
+    /// [`Db::wait_for_seq`] are woken up once the returned guard is dropped
+    pub(crate) async fn shared_write_store(&self) -> SharedWriteGuard<'_> {
+        SharedWriteGuard {
+            store: self.store.read().await,
+        }
+    }
+}
+
+/// Shared access to the store of a [`Db`] for a write, publishes the latest
+/// applied sequence number when released
+pub(crate) struct SharedWriteGuard<'a> {
+    store: RwLockReadGuard<'a, DataStore<'static, Key>>,
+}
+
+impl Deref for SharedWriteGuard<'_> {
+    type Target = DataStore<'static, Key>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.store
+    }
+}
+
+impl Drop for SharedWriteGuard<'_> {
+    fn drop(&mut self) {
+        let latest = self.store.latest_sequence();
+        self.store.commit_watermark.publish_applied(latest);
+    }
+}
+
+/// Write access to the store of a [`Db`], publishes the latest applied
+/// sequence number when released
+pub(crate) struct StoreWriteGuard<'a> {
+    store: RwLockWriteGuard<'a, DataStore<'static, Key>>,
+}
+
+impl Deref for StoreWriteGuard<'_> {
+    type Target = DataStore<'static, Key>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.store
+    }
+}
+
+impl DerefMut for StoreWriteGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.store
+    }
+}
+
+impl Drop for StoreWriteGuard<'_> {
+    fn drop(&mut self) {
+        let latest = self.store.latest_sequence();
+        self.store.commit_watermark.publish_applied(latest);
+    }
+}
@@ -0,0 +1,87 @@
+use super::DataStore;
+use crate::{
+    bucket::BucketID,
+    compactors::SizedTierRunner,
+    err::Error::{self, *},
+    types::Key,
+};
+use std::path::PathBuf;
+use tokio::fs;
+
+/// SSTables the next compaction would merge, computed without running it
+#[derive(Clone, Debug, Default)]
+pub struct CompactionPlan {
+    /// Buckets selected for compaction
+    pub buckets: Vec<PlannedBucket>,
+
+    /// Bytes read from disk to merge every selected SSTable
+    pub estimated_read_bytes: usize,
+
+    /// Upper bound of bytes written for the merged SSTables
+    pub estimated_write_bytes: usize,
+}
+
+/// Bucket selected for compaction
+#[derive(Clone, Debug)]
+pub struct PlannedBucket {
+    /// Id of the bucket
+    pub bucket: BucketID,
+
+    /// Directory of the bucket
+    pub dir: PathBuf,
+
+    /// Directories of the SSTables that would be merged
+    pub sstables: Vec<PathBuf>,
+
+    /// Size of the data files of the selected SSTables in bytes
+    pub input_size: usize,
+
+    /// Upper bound of the merged SSTable size in bytes, overwritten keys
+    /// and expired tombstones make the actual SSTable smaller
+    pub estimated_output_size: usize,
+}
+
+impl CompactionPlan {
+    /// Returns `true` if compaction would not merge anything
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Returns buckets and SSTables the next compaction would merge along with
+    /// estimated sizes, nothing is merged or written
+    ///
+    /// Only the first round is planned, merged SSTables can fill other buckets
+    /// enough to be compacted again
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn compaction_plan(&self) -> Result<CompactionPlan, Error> {
+        let (buckets, _) =
+            SizedTierRunner::pick_buckets(&*self.buckets.read().await, &self.compactor.config).await?;
+        let mut plan = CompactionPlan::default();
+        for bucket in buckets.iter() {
+            let mut sstables = Vec::new();
+            let mut input_size = 0;
+            for table in bucket.sstables.read().await.iter() {
+                let metadata = fs::metadata(&table.data_file.path)
+                    .await
+                    .map_err(GetFileMetaData)?;
+                input_size += metadata.len() as usize;
+                sstables.push(table.dir.to_owned());
+            }
+            plan.estimated_read_bytes += input_size;
+            plan.estimated_write_bytes += input_size;
+            plan.buckets.push(PlannedBucket {
+                bucket: bucket.id,
+                dir: bucket.dir.to_owned(),
+                sstables,
+                input_size,
+                estimated_output_size: input_size,
+            });
+        }
+        Ok(plan)
+    }
+}
@@ -0,0 +1,143 @@
+use super::DataStore;
+use crate::{
+    consts::MAX_CONSISTENCY_SAMPLES,
+    err::Error::{self, *},
+    types::Key,
+};
+use std::cmp::Ordering;
+
+/// Key found to differ between the store and the source it is compared with
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Inconsistency {
+    /// Key of the source is not in the store
+    Missing(Key),
+
+    /// Key is in both but its values differ
+    Mismatched(Key),
+
+    /// Key of the store is not in the source
+    Unexpected(Key),
+}
+
+/// Outcome of `DataStore::assert_consistent_with`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConsistencyReport {
+    /// Number of entries read from the source
+    pub source_entries: usize,
+
+    /// Number of entries read from the store
+    pub store_entries: usize,
+
+    /// Number of keys with the same value in both
+    pub matched: usize,
+
+    /// Number of source keys not in the store
+    pub missing: usize,
+
+    /// Number of keys whose values differ
+    pub mismatched: usize,
+
+    /// Number of store keys not in the source
+    pub unexpected: usize,
+
+    /// First differences in key order, at most `MAX_CONSISTENCY_SAMPLES` are kept
+    pub samples: Vec<Inconsistency>,
+}
+
+impl ConsistencyReport {
+    /// Returns `true` if the store and the source hold the same entries
+    pub fn is_consistent(&self) -> bool {
+        self.missing == 0 && self.mismatched == 0 && self.unexpected == 0
+    }
+
+    /// Counts `inconsistency`, keeping it as a sample while there is room
+    fn record(&mut self, inconsistency: Inconsistency) {
+        match inconsistency {
+            Inconsistency::Missing(_) => self.missing += 1,
+            Inconsistency::Mismatched(_) => self.mismatched += 1,
+            Inconsistency::Unexpected(_) => self.unexpected += 1,
+        }
+        if self.samples.len() < MAX_CONSISTENCY_SAMPLES {
+            self.samples.push(inconsistency);
+        }
+    }
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Compares entries whose keys are within `[start, end]` with `source`,
+    /// e.g. entries exported from a store being migrated to velarixdb
+    ///
+    /// `source` must yield entries within the range in the order of the store
+    /// comparator. It is walked alongside a scan of the store so only the
+    /// current entry of each side is held, keys the comparator treats as equal
+    /// are the same key. Deleted and expired entries are left out as with
+    /// [`DataStore::seek`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::SourceNotSorted`] if `source` is out of order or error
+    /// from [`DataStore::seek`]
+    pub async fn assert_consistent_with<K, V>(
+        &self,
+        start: &'a [u8],
+        end: &'a [u8],
+        source: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<ConsistencyReport, Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let comparator = self.config.comparator;
+        let mut iter = self.seek(start, end).await?;
+        let mut report = ConsistencyReport::default();
+        let mut stored = iter.next().await?;
+        let mut previous: Option<Key> = None;
+        for (key, value) in source {
+            let key = key.as_ref();
+            let encoded = comparator.encode(key);
+            if previous
+                .as_deref()
+                .is_some_and(|previous| encoded.as_ref() <= previous)
+            {
+                return Err(SourceNotSorted(key.to_vec()));
+            }
+            report.source_entries += 1;
+            loop {
+                let Some(entry) = stored.take() else {
+                    report.record(Inconsistency::Missing(key.to_vec()));
+                    break;
+                };
+                match comparator.encode(&entry.key).as_ref().cmp(encoded.as_ref()) {
+                    Ordering::Less => {
+                        report.store_entries += 1;
+                        report.record(Inconsistency::Unexpected(entry.key));
+                        stored = iter.next().await?;
+                    }
+                    Ordering::Equal => {
+                        report.store_entries += 1;
+                        if entry.val == value.as_ref() {
+                            report.matched += 1;
+                        } else {
+                            report.record(Inconsistency::Mismatched(entry.key));
+                        }
+                        stored = iter.next().await?;
+                        break;
+                    }
+                    Ordering::Greater => {
+                        stored = Some(entry);
+                        report.record(Inconsistency::Missing(key.to_vec()));
+                        break;
+                    }
+                }
+            }
+            previous = Some(encoded.into_owned());
+        }
+        while let Some(entry) = stored {
+            report.store_entries += 1;
+            report.record(Inconsistency::Unexpected(entry.key));
+            stored = iter.next().await?;
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,63 @@
+use super::DataStore;
+use crate::consts::SIZE_OF_U64;
+use crate::err::Error;
+use crate::types::Key;
+
+impl DataStore<'static, Key> {
+    /// Adds `delta` to the counter stored at `key` and returns its new value
+    ///
+    /// Counters are stored as 8 byte little endian signed integers, an absent
+    /// or deleted key counts from zero. The read and the write happen without
+    /// another write in between, so concurrent increments are never lost. Use
+    /// [`DataStore::counter`] to read a counter
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     assert_eq!(store.increment("visits", 1).await.unwrap(), 1); // handle error
+    ///     assert_eq!(store.increment("visits", 41).await.unwrap(), 42); // handle error
+    ///     assert_eq!(store.counter("visits").await.unwrap(), 42); // handle error
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotACounter`] if the value of `key` is not 8 bytes,
+    /// [`Error::CounterOverflow`] if the new value does not fit, or error if
+    /// an IO error occured or the write is invalid or rejected
+    pub async fn increment<T: AsRef<[u8]>>(&self, key: T, delta: i64) -> Result<i64, Error> {
+        let _gate = self.write_gate.write().await;
+        let current = self.counter(key.as_ref()).await?;
+        let value = current.checked_add(delta).ok_or(Error::CounterOverflow)?;
+        self.put_holding_gate(key.as_ref(), &value.to_le_bytes()).await?;
+        Ok(value)
+    }
+
+    /// Returns value of the counter stored at `key`, zero if the key is absent or deleted
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotACounter`] if the value of `key` is not 8 bytes, or
+    /// error if an IO error occured
+    pub async fn counter<T: AsRef<[u8]>>(&self, key: T) -> Result<i64, Error> {
+        match self.get_ref(key).await? {
+            Some(entry) => {
+                let bytes: [u8; SIZE_OF_U64] = entry
+                    .val
+                    .as_ref()
+                    .try_into()
+                    .map_err(|_| Error::NotACounter(entry.val.len()))?;
+                Ok(i64::from_le_bytes(bytes))
+            }
+            None => Ok(0),
+        }
+    }
+}
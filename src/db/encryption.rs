@@ -0,0 +1,78 @@
+use super::DataStore;
+use crate::{
+    consts::META_FEATURE_ENCRYPTION,
+    encryption::{EncryptionKey, FileKey, KeyId},
+    err::Error,
+    fs::FileAsync,
+    types::Key,
+    vlog::ValueLog,
+};
+
+impl<'a> DataStore<'a, Key> {
+    /// Makes `key` the one the store is encrypted with from now on
+    ///
+    /// Nothing is rewritten here: records appended to value log from now on
+    /// are encrypted with `key`, as are SSTables written by flush and
+    /// compaction. GC moves live records off the tail of value log and appends
+    /// them with `key`, so data written with older keys is re-encrypted as it
+    /// is compacted and collected. An unencrypted store is encrypted from now on
+    /// the same way.
+    ///
+    /// Older keys have to be passed to [`DataStore::open_encrypted`] as long as
+    /// [`DataStore::encryption_keys_in_use`] lists them
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn rotate_encryption_key(&self, key: EncryptionKey) -> Result<(), Error> {
+        // no record is appended while value log moves to a new epoch
+        let val_log = self.val_log.write().await;
+        val_log.sync_to_disk().await?;
+        let start = val_log.content.file.node.size().await as u64;
+        // older versions must not take encrypted records for corrupted ones
+        self.meta.lock().unwrap().features |= META_FEATURE_ENCRYPTION;
+        let tail = self.persist_tail(&val_log).await?;
+        let mut buckets = self.buckets.write().await;
+        let key_id = key.id();
+        buckets.keyring.activate(key);
+        buckets
+            .manifest
+            .start_vlog_epoch(start, FileKey::new(key_id), tail)
+            .await?;
+        let cipher = buckets.keyring.value_log(buckets.manifest.vlog_epochs())?;
+        val_log.set_cipher(cipher).await?;
+        log::info!("Rotated encryption key to {}", key_id);
+        Ok(())
+    }
+
+    /// Returns ids of the keys needed to open the store, in ascending order
+    ///
+    /// A key is needed while an SSTable or a part of value log before the
+    /// tail is encrypted with it, the active key always is. A key that is no
+    /// longer listed can be dropped once compaction and GC moved the data
+    /// written with it
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn encryption_keys_in_use(&self) -> Result<Vec<KeyId>, Error> {
+        let val_log = self.val_log.write().await;
+        let tail = self.persist_tail(&val_log).await?;
+        let mut buckets = self.buckets.write().await;
+        buckets.manifest.drop_vlog_epochs_before(tail).await?;
+        Ok(buckets.manifest.keys_in_use().into_iter().collect())
+    }
+
+    /// Writes meta, so records before the tail of `val_log` are not read
+    /// again after a crash, and returns the tail
+    async fn persist_tail(&self, val_log: &ValueLog) -> Result<u64, Error> {
+        let mut meta = {
+            let mut meta = self.meta.lock().unwrap();
+            meta.set_tail(val_log.tail_offset);
+            meta.update_last_modified();
+            meta.to_owned()
+        };
+        meta.write().await?;
+        Ok(val_log.tail_offset as u64)
+    }
+}
@@ -0,0 +1,258 @@
+use super::DataStore;
+use crate::{err::Error, types::Key};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// Format of entries written by `DataStore::export` and read by `DataStore::import`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line with base64 encoded `key` and `value` fields
+    JsonLines,
+
+    /// `key,value` header followed by one row per entry, keys and values are base64 encoded
+    Csv,
+
+    /// Parquet file with `key` and `value` byte array columns
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+/// Entry as written to JSON lines and CSV
+#[derive(Serialize, Deserialize)]
+struct ExportRecord {
+    key: String,
+    value: String,
+}
+
+impl ExportRecord {
+    fn encode(key: &[u8], value: &[u8]) -> Self {
+        Self {
+            key: STANDARD.encode(key),
+            value: STANDARD.encode(value),
+        }
+    }
+
+    fn decode(self) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let key = STANDARD
+            .decode(self.key)
+            .map_err(|err| Error::Import(Box::new(err)))?;
+        let value = STANDARD
+            .decode(self.value)
+            .map_err(|err| Error::Import(Box::new(err)))?;
+        Ok((key, value))
+    }
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Writes entries whose keys are within `[start, end]` to `writer` in `format`
+    ///
+    /// Entries are written in the order of the store comparator, deleted and
+    /// expired entries are left out as with [`DataStore::seek`]
+    ///
+    /// Returns number of entries written
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Export`] if `writer` fails or error from [`DataStore::seek`]
+    pub async fn export<W: Write + Send>(
+        &self,
+        start: &'a [u8],
+        end: &'a [u8],
+        format: ExportFormat,
+        writer: W,
+    ) -> Result<usize, Error> {
+        let mut iter = self.seek(start, end).await?;
+        let mut exported = 0;
+        match format {
+            ExportFormat::JsonLines => {
+                let mut writer = writer;
+                while let Some(entry) = iter.next().await? {
+                    serde_json::to_writer(&mut writer, &ExportRecord::encode(&entry.key, &entry.val))
+                        .map_err(|err| Error::Export(Box::new(err)))?;
+                    writer
+                        .write_all(b"\n")
+                        .map_err(|err| Error::Export(Box::new(err)))?;
+                    exported += 1;
+                }
+                writer.flush().map_err(|err| Error::Export(Box::new(err)))?;
+            }
+            ExportFormat::Csv => {
+                // header is written up front so an empty range still yields one
+                let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(writer);
+                writer
+                    .write_record(["key", "value"])
+                    .map_err(|err| Error::Export(Box::new(err)))?;
+                while let Some(entry) = iter.next().await? {
+                    writer
+                        .serialize(ExportRecord::encode(&entry.key, &entry.val))
+                        .map_err(|err| Error::Export(Box::new(err)))?;
+                    exported += 1;
+                }
+                writer.flush().map_err(|err| Error::Export(Box::new(err)))?;
+            }
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => {
+                let mut writer = parquet_format::Writer::new(writer)?;
+                while let Some(entry) = iter.next().await? {
+                    writer.push(entry.key, entry.val.to_vec())?;
+                    exported += 1;
+                }
+                writer.close()?;
+            }
+        }
+        Ok(exported)
+    }
+}
+
+impl DataStore<'static, Key> {
+    /// Inserts entries read from `reader` in `format`, as written by [`DataStore::export`]
+    ///
+    /// Entries are inserted one by one, entries read before an error stay in the store
+    ///
+    /// Returns number of entries imported
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::Import`] if `reader` fails or holds malformed entries
+    /// or error from [`DataStore::put`]
+    pub async fn import<R: Read + Send>(&self, reader: R, format: ExportFormat) -> Result<usize, Error> {
+        let mut imported = 0;
+        match format {
+            ExportFormat::JsonLines => {
+                for line in BufReader::new(reader).lines() {
+                    let line = line.map_err(|err| Error::Import(Box::new(err)))?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let record: ExportRecord =
+                        serde_json::from_str(&line).map_err(|err| Error::Import(Box::new(err)))?;
+                    let (key, value) = record.decode()?;
+                    self.put(key, value).await?;
+                    imported += 1;
+                }
+            }
+            ExportFormat::Csv => {
+                let mut reader = csv::Reader::from_reader(reader);
+                for record in reader.deserialize::<ExportRecord>() {
+                    let record = record.map_err(|err| Error::Import(Box::new(err)))?;
+                    let (key, value) = record.decode()?;
+                    self.put(key, value).await?;
+                    imported += 1;
+                }
+            }
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => {
+                for (key, value) in parquet_format::read_all(reader)? {
+                    self.put(key, value).await?;
+                    imported += 1;
+                }
+            }
+        }
+        Ok(imported)
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_format {
+    use crate::err::Error;
+    use parquet::{
+        data_type::{ByteArray, ByteArrayType},
+        file::{
+            properties::WriterProperties,
+            reader::{FileReader, SerializedFileReader},
+            writer::SerializedFileWriter,
+        },
+        record::RowAccessor,
+        schema::parser::parse_message_type,
+    };
+    use std::{
+        io::{Read, Write},
+        sync::Arc,
+    };
+
+    /// Entries buffered before a row group is written
+    const ROW_GROUP_SIZE: usize = 1024;
+
+    /// Key and value of an imported entry
+    type Entry = (Vec<u8>, Vec<u8>);
+
+    const SCHEMA: &str = "message entry { REQUIRED BYTE_ARRAY key; REQUIRED BYTE_ARRAY value; }";
+
+    fn export_err(err: parquet::errors::ParquetError) -> Error {
+        Error::Export(Box::new(err))
+    }
+
+    /// Writes entries as row groups of `ROW_GROUP_SIZE` rows
+    pub(super) struct Writer<W: Write + Send> {
+        inner: SerializedFileWriter<W>,
+        keys: Vec<ByteArray>,
+        values: Vec<ByteArray>,
+    }
+
+    impl<W: Write + Send> Writer<W> {
+        pub fn new(writer: W) -> Result<Self, Error> {
+            let schema = Arc::new(parse_message_type(SCHEMA).map_err(export_err)?);
+            let properties = Arc::new(WriterProperties::builder().build());
+            Ok(Self {
+                inner: SerializedFileWriter::new(writer, schema, properties).map_err(export_err)?,
+                keys: Vec::with_capacity(ROW_GROUP_SIZE),
+                values: Vec::with_capacity(ROW_GROUP_SIZE),
+            })
+        }
+
+        pub fn push(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), Error> {
+            self.keys.push(ByteArray::from(key));
+            self.values.push(ByteArray::from(value));
+            if self.keys.len() >= ROW_GROUP_SIZE {
+                self.write_row_group()?;
+            }
+            Ok(())
+        }
+
+        fn write_row_group(&mut self) -> Result<(), Error> {
+            let mut row_group = self.inner.next_row_group().map_err(export_err)?;
+            for column in [&mut self.keys, &mut self.values] {
+                let mut writer = row_group
+                    .next_column()
+                    .map_err(export_err)?
+                    .expect("schema has a key and a value column");
+                writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(column, None, None)
+                    .map_err(export_err)?;
+                writer.close().map_err(export_err)?;
+                column.clear();
+            }
+            row_group.close().map_err(export_err)?;
+            Ok(())
+        }
+
+        pub fn close(mut self) -> Result<(), Error> {
+            if !self.keys.is_empty() {
+                self.write_row_group()?;
+            }
+            self.inner.close().map_err(export_err)?;
+            Ok(())
+        }
+    }
+
+    /// Returns every entry of the Parquet file in `reader`
+    pub(super) fn read_all<R: Read>(mut reader: R) -> Result<Vec<Entry>, Error> {
+        let import_err = |err: parquet::errors::ParquetError| Error::Import(Box::new(err));
+        // footer is at the end of the file, it has to be read whole
+        let mut data = Vec::new();
+        reader
+            .read_to_end(&mut data)
+            .map_err(|err| Error::Import(Box::new(err)))?;
+        let file = SerializedFileReader::new(bytes::Bytes::from(data)).map_err(import_err)?;
+        let mut entries = Vec::new();
+        for row in file.get_row_iter(None).map_err(import_err)? {
+            let row = row.map_err(import_err)?;
+            let key = row.get_bytes(0).map_err(import_err)?.data().to_vec();
+            let value = row.get_bytes(1).map_err(import_err)?.data().to_vec();
+            entries.push((key, value));
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,52 @@
+use super::DataStore;
+use crate::{
+    consts::FORMAT_VERSION,
+    err::Error,
+    types::{FormatVersion, Key},
+};
+
+impl<'a> DataStore<'a, Key> {
+    /// Migrates the store to the current on-disk format, see [`crate::consts::FORMAT_VERSION`]
+    ///
+    /// Stores written with an older format are opened as they are and keep
+    /// their recorded version until upgraded. Migrations run one version at a
+    /// time and the version is recorded after each of them, so an upgrade
+    /// interrupted by a crash resumes from the last finished step. Stores
+    /// written with a newer format are refused by `open`
+    ///
+    /// Returns the format version the store is written with afterwards
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if a migration or writing meta failed
+    pub async fn upgrade_format(&mut self) -> Result<FormatVersion, Error> {
+        loop {
+            let from = self.meta.lock().unwrap().format_version;
+            if from >= FORMAT_VERSION {
+                return Ok(from);
+            }
+            self.migrate_format(from).await?;
+            let mut meta = {
+                let mut meta = self.meta.lock().unwrap();
+                meta.format_version = from + 1;
+                meta.update_last_modified();
+                meta.to_owned()
+            };
+            meta.write().await?;
+            log::info!("Upgraded store format from version {} to {}", from, from + 1);
+        }
+    }
+
+    /// Migrates files of format version `from` to the version after it
+    async fn migrate_format(&mut self, from: FormatVersion) -> Result<(), Error> {
+        match from {
+            // version 0 only lacks meta fields recorded since, they are written
+            // along with the new version
+            0 => Ok(()),
+            _ => Err(Error::UnsupportedFormatVersion {
+                found: from,
+                supported: FORMAT_VERSION,
+            }),
+        }
+    }
+}
@@ -0,0 +1,661 @@
+use super::{
+    BucketStats, CompactionPlan, ConsistencyReport, DataStore, ExportFormat, FlushReport, KeyStatus,
+    LiveFiles, PrefixUsage, ReadProfile, ScrubReport, Session, Stats, StoreInfo, SweepReport, WarmUpOptions,
+    WarmUpReport, WriteBatch, WriteReceipt,
+};
+use crate::compactors::{BucketWriteStats, CompactionStatus};
+use crate::comparator::Comparator;
+use crate::encryption::{EncryptionKey, KeyId};
+use crate::err::Error;
+use crate::fs::P;
+use crate::health::Health;
+use crate::memtable::{UserEntry, UserEntryRef};
+use crate::range::RangeIterator;
+use crate::types::{FormatVersion, Key, SeqNumber};
+use serde::{de::DeserializeOwned, Serialize};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Handle to an open keyspace
+///
+/// Exposes the operations of [`DataStore`] as methods only, store internals
+/// cannot be reached or mutated through it
+///
+/// Cloning is cheap, clones share the same store and can be moved to other
+/// tasks. Writes share the store with reads and other writes, they only wait
+/// for each other while they append to the value log and insert into the
+/// active memtable, and meta is updated under a lock of its own.
+///
+/// Writes that look stored entries up before writing, renames, appends,
+/// increments and batches with merges or range deletes, hold other writes off
+/// so none lands in between. Reads see all of a batch or none of it. Flushes,
+/// compactions and GC run alongside reads and writes, closing holds the store
+/// exclusively
+///
+/// # Examples
+/// ```
+/// # use tempfile::tempdir;
+/// use velarixdb::db::Db;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let root = tempdir().unwrap();
+///     let path = root.path().join("velarixdb");
+///     let db = Db::open("big_tech", path).await.unwrap(); // handle IO error
+///
+///     let writer = db.clone();
+///     tokio::spawn(async move { writer.put("apple", "tim cook").await })
+///         .await
+///         .unwrap()
+///         .unwrap(); // handle error
+///     db.flush().await.unwrap();
+///
+///     let entry = db.get("apple").await.unwrap(); // handle error
+///     assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "tim cook");
+///     assert_eq!(db.live_files().await.unwrap().sstables.len(), 1);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Db {
+    pub(crate) store: Arc<RwLock<DataStore<'static, Key>>>,
+}
+
+impl Db {
+    /// Opens a keyspace in the given directory, see [`DataStore::open`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keyspace name is invalid.
+    pub async fn open(keyspace: &'static str, dir: impl P) -> Result<Self, Error> {
+        Ok(Self::from(DataStore::open(keyspace, dir).await?))
+    }
+
+    /// Opens a keyspace whose keys are ordered with `comparator`, see [`DataStore::open_with_comparator`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the keyspace was created with another comparator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keyspace name is invalid.
+    pub async fn open_with_comparator(
+        keyspace: &'static str,
+        dir: impl P,
+        comparator: Comparator,
+    ) -> Result<Self, Error> {
+        Ok(Self::from(
+            DataStore::open_with_comparator(keyspace, dir, comparator).await?,
+        ))
+    }
+
+    /// Opens a keyspace whose files are encrypted with `keys`, see [`DataStore::open_encrypted`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or a key files are encrypted with is missing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keyspace name is invalid.
+    pub async fn open_encrypted(
+        keyspace: &'static str,
+        dir: impl P,
+        keys: Vec<EncryptionKey>,
+    ) -> Result<Self, Error> {
+        Ok(Self::from(DataStore::open_encrypted(keyspace, dir, keys).await?))
+    }
+
+    /// Inserts a new entry into the store, see [`DataStore::put`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the store is read-only
+    pub async fn put(&self, key: impl AsRef<[u8]>, val: impl AsRef<[u8]>) -> Result<WriteReceipt, Error> {
+        self.shared_write_store().await.put(key, val).await
+    }
+
+    /// Inserts a new entry and returns the value it replaced, see [`DataStore::put_get_old`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the store is read-only
+    pub async fn put_get_old(
+        &self,
+        key: impl AsRef<[u8]>,
+        val: impl AsRef<[u8]>,
+    ) -> Result<(WriteReceipt, Option<UserEntry>), Error> {
+        self.shared_write_store().await.put_get_old(key, val).await
+    }
+
+    /// Retrieves an entry, see [`DataStore::get`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn get<T: AsRef<[u8]>>(&self, key: T) -> Result<Option<UserEntry>, Error> {
+        self.store.read().await.get(key).await
+    }
+
+    /// Retrieves an entry without copying its value, see [`DataStore::get_ref`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn get_ref<T: AsRef<[u8]>>(&self, key: T) -> Result<Option<UserEntryRef>, Error> {
+        self.store.read().await.get_ref(key).await
+    }
+
+    /// Returns whether a key has an entry without reading its value, see [`DataStore::contains_key`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn contains_key<T: AsRef<[u8]>>(&self, key: T) -> Result<KeyStatus, Error> {
+        self.store.read().await.contains_key(key).await
+    }
+
+    /// Updates an existing entry, see [`DataStore::update`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the key was not found
+    pub async fn update(&self, key: impl AsRef<[u8]>, val: impl AsRef<[u8]>) -> Result<WriteReceipt, Error> {
+        self.shared_write_store().await.update(key, val).await
+    }
+
+    /// Deletes an entry, see [`DataStore::delete`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn delete<T: AsRef<[u8]>>(&self, key: T) -> Result<WriteReceipt, Error> {
+        self.shared_write_store().await.delete(key).await
+    }
+
+    /// Deletes an entry and returns the removed entry, see [`DataStore::delete_get_old`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn delete_get_old<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+    ) -> Result<(WriteReceipt, Option<UserEntry>), Error> {
+        self.shared_write_store().await.delete_get_old(key).await
+    }
+
+    /// Deletes an entry if the key exists, see [`DataStore::delete_if_exists`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the key was not found
+    pub async fn delete_if_exists<T: AsRef<[u8]>>(&self, key: T) -> Result<WriteReceipt, Error> {
+        self.shared_write_store().await.delete_if_exists(key).await
+    }
+
+    /// Moves the value of `old_key` to `new_key` atomically, see [`DataStore::rename`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or `old_key` was not found
+    pub async fn rename<T: AsRef<[u8]>>(&self, old_key: T, new_key: T) -> Result<WriteReceipt, Error> {
+        self.shared_write_store().await.rename(old_key, new_key).await
+    }
+
+    /// Adds `delta` to the counter stored at `key` and returns its new value, see [`DataStore::increment`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured, the value is not a counter or the write is rejected
+    pub async fn increment<T: AsRef<[u8]>>(&self, key: T, delta: i64) -> Result<i64, Error> {
+        self.shared_write_store().await.increment(key, delta).await
+    }
+
+    /// Returns value of the counter stored at `key`, see [`DataStore::counter`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the value is not a counter
+    pub async fn counter<T: AsRef<[u8]>>(&self, key: T) -> Result<i64, Error> {
+        self.store.read().await.counter(key).await
+    }
+
+    /// Appends `suffix` to the value of `key`, see [`DataStore::append`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the write is invalid or rejected
+    pub async fn append<T: AsRef<[u8]>>(&self, key: T, suffix: T) -> Result<WriteReceipt, Error> {
+        self.shared_write_store().await.append(key, suffix).await
+    }
+
+    /// Deletes every key of `keys` with a single value log write, see [`DataStore::delete_many`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured, a key is invalid or the store is read-only
+    pub async fn delete_many<T: AsRef<[u8]>>(
+        &self,
+        keys: impl IntoIterator<Item = T>,
+    ) -> Result<WriteReceipt, Error> {
+        self.shared_write_store().await.delete_many(keys).await
+    }
+
+    /// Applies every operation of `batch` atomically, see [`DataStore::write_batch`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured, an entry is invalid or the store is read-only
+    pub async fn write_batch(&self, batch: WriteBatch) -> Result<WriteReceipt, Error> {
+        self.shared_write_store().await.write_batch(batch).await
+    }
+
+    /// Returns a session whose reads observe its own writes, see [`Session`]
+    pub fn session(&self) -> Session {
+        Session::new(self.clone())
+    }
+
+    /// Returns sequence number of the latest write, see [`DataStore::latest_sequence`]
+    pub async fn latest_sequence(&self) -> SeqNumber {
+        self.store.read().await.latest_sequence()
+    }
+
+    /// Returns iterator over entries whose keys are within `[start, end]`, see [`DataStore::seek`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn seek<'a>(&self, start: &'a [u8], end: &'a [u8]) -> Result<RangeIterator<'a>, Error> {
+        let store = self.store.read().await;
+        let store: &DataStore<'a, Key> = &store;
+        store.seek(start, end).await
+    }
+
+    /// Writes entries within `[start, end]` to `writer`, see [`DataStore::export`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if writing or an IO error occured
+    pub async fn export<'a, W: std::io::Write + Send>(
+        &self,
+        start: &'a [u8],
+        end: &'a [u8],
+        format: ExportFormat,
+        writer: W,
+    ) -> Result<usize, Error> {
+        let store = self.store.read().await;
+        let store: &DataStore<'a, Key> = &store;
+        store.export(start, end, format, writer).await
+    }
+
+    /// Compares entries within `[start, end]` with `source`, see [`DataStore::assert_consistent_with`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if `source` is out of order or an IO error occured
+    pub async fn assert_consistent_with<'a, K, V>(
+        &self,
+        start: &'a [u8],
+        end: &'a [u8],
+        source: impl IntoIterator<Item = (K, V)>,
+    ) -> Result<ConsistencyReport, Error>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let store = self.store.read().await;
+        let store: &DataStore<'a, Key> = &store;
+        store.assert_consistent_with(start, end, source).await
+    }
+
+    /// Inserts entries read from `reader`, see [`DataStore::import`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if reading or an IO error occured
+    pub async fn import<R: std::io::Read + Send>(
+        &self,
+        reader: R,
+        format: ExportFormat,
+    ) -> Result<usize, Error> {
+        self.shared_write_store().await.import(reader, format).await
+    }
+
+    /// Returns number of keys within `[start, end]`, see [`DataStore::count_range`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn count_range(&self, start: &[u8], end: &[u8], exact: bool) -> Result<usize, Error> {
+        self.store.read().await.count_range(start, end, exact).await
+    }
+
+    /// Serializes `value` as JSON and inserts it, see [`DataStore::put_json`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if `value` cannot be serialized or an IO error occured
+    pub async fn put_json<T: Serialize + ?Sized>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: &T,
+    ) -> Result<WriteReceipt, Error> {
+        self.shared_write_store().await.put_json(key, value).await
+    }
+
+    /// Retrieves an entry and deserializes it from JSON, see [`DataStore::get_json`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if the value is not valid JSON for `T` or an IO error occured
+    pub async fn get_json<T: DeserializeOwned>(&self, key: impl AsRef<[u8]>) -> Result<Option<T>, Error> {
+        self.store.read().await.get_json(key).await
+    }
+
+    /// Flushes active and read-only memtables to disk
+    ///
+    /// Writes only wait while the active memtable is sealed, reads and writes
+    /// go on while memtables are written
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn flush(&self) -> Result<(), Error> {
+        let job = self.store.read().await.flush_memtable_async();
+        job.await.map(|_| ())
+    }
+
+    /// Flushes memtables and waits for the flush without blocking other
+    /// operations, see [`DataStore::flush_memtable_async`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn flush_memtable_async(&self) -> Result<FlushReport, Error> {
+        let job = self.store.read().await.flush_memtable_async();
+        job.await
+    }
+
+    /// Flushes everything and marks the store as cleanly shut down, see [`DataStore::close`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn close(&self) -> Result<(), Error> {
+        self.write_store().await.close().await
+    }
+
+    /// Triggers compaction manually, see [`DataStore::run_compaction`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if compaction failed
+    pub async fn run_compaction(&self) -> Result<(), Error> {
+        self.store.read().await.run_compaction().await
+    }
+
+    /// Runs a manual compaction and waits for it without blocking other
+    /// operations, see [`DataStore::compact_async`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if compaction failed
+    pub async fn compact_async(&self) -> Result<CompactionStatus, Error> {
+        let job = self.store.read().await.compact_async();
+        job.await
+    }
+
+    /// Flushes read-only memtables, see [`DataStore::tick_flush`]
+    ///
+    /// Reads go on while memtables are written
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn tick_flush(&self) -> Result<FlushReport, Error> {
+        let job = self.store.read().await.flush_read_only_job();
+        job.await
+    }
+
+    /// Runs one compaction, see [`DataStore::tick_compaction`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if compaction failed
+    pub async fn tick_compaction(&self) -> Result<CompactionStatus, Error> {
+        self.store.read().await.tick_compaction().await
+    }
+
+    /// Runs one garbage collection of the value log, see [`DataStore::tick_gc`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn tick_gc(&self) -> Result<(), Error> {
+        self.store.read().await.tick_gc().await
+    }
+
+    /// Returns what the next compaction would merge, see [`DataStore::compaction_plan`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn compaction_plan(&self) -> Result<CompactionPlan, Error> {
+        self.store.read().await.compaction_plan().await
+    }
+
+    /// Sets capacity of memtables in kilobytes from the next rotation on, see [`DataStore::set_write_buffer_size`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is less than 50 kilobytes
+    pub async fn set_write_buffer_size(&self, size: usize) {
+        self.write_store().await.set_write_buffer_size(size)
+    }
+
+    /// Returns statistics of the store
+    pub async fn stats(&self) -> Stats {
+        self.store.read().await.stats()
+    }
+
+    /// Returns a snapshot of every bucket, see [`DataStore::buckets`]
+    pub async fn buckets(&self) -> Vec<BucketStats> {
+        self.store.read().await.buckets().await
+    }
+
+    /// Returns properties of the store recorded in its meta directory, see [`DataStore::store_info`]
+    pub async fn store_info(&self) -> StoreInfo {
+        self.store.read().await.store_info()
+    }
+
+    /// Migrates the store to the current on-disk format, see [`DataStore::upgrade_format`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if a migration or writing meta failed
+    pub async fn upgrade_format(&self) -> Result<FormatVersion, Error> {
+        self.write_store().await.upgrade_format().await
+    }
+
+    /// Makes `key` the one the store is encrypted with from now on, see [`DataStore::rotate_encryption_key`]
+    ///
+    /// Reads and writes go on while the key is rotated
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn rotate_encryption_key(&self, key: EncryptionKey) -> Result<(), Error> {
+        self.store.read().await.rotate_encryption_key(key).await
+    }
+
+    /// Returns ids of the keys needed to open the store, see [`DataStore::encryption_keys_in_use`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn encryption_keys_in_use(&self) -> Result<Vec<KeyId>, Error> {
+        self.store.read().await.encryption_keys_in_use().await
+    }
+
+    /// Stores `value` at `key` of the user metadata, see [`DataStore::set_meta`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn set_meta<T: AsRef<[u8]>>(&self, key: T, value: T) -> Result<(), Error> {
+        self.write_store().await.set_meta(key, value).await
+    }
+
+    /// Returns value stored at `key` of the user metadata, see [`DataStore::get_meta`]
+    pub async fn get_meta<T: AsRef<[u8]>>(&self, key: T) -> Option<Vec<u8>> {
+        self.store.read().await.get_meta(key)
+    }
+
+    /// Removes `key` from the user metadata, see [`DataStore::delete_meta`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn delete_meta<T: AsRef<[u8]>>(&self, key: T) -> Result<bool, Error> {
+        self.write_store().await.delete_meta(key).await
+    }
+
+    /// Returns read traffic sampled in the current window, see [`DataStore::read_profile`]
+    pub async fn read_profile(&self) -> ReadProfile {
+        self.store.read().await.read_profile()
+    }
+
+    /// Saves read traffic sampled in the current window, see [`DataStore::save_access_profile`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn save_access_profile(&self) -> Result<(), Error> {
+        self.store.read().await.save_access_profile().await
+    }
+
+    /// Loads filters, index lookups and blocks of hot keys ahead of reads, see [`DataStore::warm_up`]
+    ///
+    /// The store is not held while reads are throttled, so it serves
+    /// reads and writes meanwhile
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error or the saved access profile
+    /// is corrupted
+    pub async fn warm_up(&self, options: WarmUpOptions) -> Result<WarmUpReport, Error> {
+        let (key_range, meta_dir, comparator) = {
+            let store = self.store.read().await;
+            (
+                store.key_range.clone(),
+                store.dir.meta.clone(),
+                store.config.comparator,
+            )
+        };
+        super::warm_up::warm_up(&key_range, &meta_dir, comparator, options).await
+    }
+
+    /// Returns status of compaction, see [`DataStore::compaction_status`]
+    pub async fn compaction_status(&self) -> CompactionStatus {
+        self.store.read().await.compaction_status()
+    }
+
+    /// Verifies checksums of every SSTable, see [`DataStore::scrub`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn scrub(&self) -> Result<ScrubReport, Error> {
+        self.store.read().await.scrub().await
+    }
+
+    /// Drops and compacts expired SSTables, see [`DataStore::sweep_expired`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error or a compaction fails
+    pub async fn sweep_expired(&self) -> Result<SweepReport, Error> {
+        self.store.read().await.sweep_expired().await
+    }
+
+    /// Returns bytes and keys stored under prefixes with a quota, see [`DataStore::quota_usage`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn quota_usage(&self) -> Result<Vec<PrefixUsage>, Error> {
+        self.store.read().await.quota_usage().await
+    }
+
+    /// Returns write amplification, see [`DataStore::write_amplification`]
+    pub async fn write_amplification(&self) -> f64 {
+        self.store.read().await.write_amplification()
+    }
+
+    /// Returns compaction figures per bucket, see [`DataStore::bucket_write_stats`]
+    pub async fn bucket_write_stats(&self) -> Vec<BucketWriteStats> {
+        self.store.read().await.bucket_write_stats()
+    }
+
+    /// Returns metadata of every live SSTable and value log segment
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn live_files(&self) -> Result<LiveFiles, Error> {
+        self.store.read().await.live_files().await
+    }
+
+    /// Returns health of background tasks, see [`DataStore::health`]
+    pub async fn health(&self) -> Health {
+        self.store.read().await.health()
+    }
+
+    /// Deletes orphaned SSTables whose grace period elapsed, see [`DataStore::delete_orphan_files`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn delete_orphan_files(&self) -> Result<usize, Error> {
+        self.store.read().await.delete_orphan_files().await
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl Db {
+    /// Serializes `value` with bincode and inserts it, see [`DataStore::put_bincode`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if `value` cannot be serialized or an IO error occured
+    pub async fn put_bincode<T: Serialize + ?Sized>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: &T,
+    ) -> Result<WriteReceipt, Error> {
+        self.shared_write_store().await.put_bincode(key, value).await
+    }
+
+    /// Retrieves an entry and deserializes it with bincode, see [`DataStore::get_bincode`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if the value cannot be deserialized to `T` or an IO error occured
+    pub async fn get_bincode<T: DeserializeOwned>(&self, key: impl AsRef<[u8]>) -> Result<Option<T>, Error> {
+        self.store.read().await.get_bincode(key).await
+    }
+}
+
+/// Wraps a store configured through the `with_` builders of [`DataStore`]
+impl From<DataStore<'static, Key>> for Db {
+    fn from(store: DataStore<'static, Key>) -> Self {
+        Self {
+            store: Arc::new(RwLock::new(store)),
+        }
+    }
+}
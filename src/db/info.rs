@@ -0,0 +1,85 @@
+use super::DataStore;
+use crate::{
+    consts::{META_FEATURE_COMPRESSION, META_FEATURE_ENCRYPTION},
+    err::Error,
+    types::{CreatedAt, FormatVersion, Key},
+};
+
+/// Properties of a store recorded in its meta directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StoreInfo {
+    /// On-disk format version the store is written with
+    pub format_version: FormatVersion,
+
+    /// Time the store was created
+    pub created_at: CreatedAt,
+
+    /// Name of the comparator keys are ordered with, see [`crate::db::Comparator::name`]
+    pub comparator: String,
+
+    /// Whether SSTable blocks are compressed
+    pub compression: bool,
+
+    /// Whether files are encrypted
+    pub encryption: bool,
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Returns properties of the store recorded in its meta directory
+    pub fn store_info(&self) -> StoreInfo {
+        let meta = self.meta.lock().unwrap();
+        StoreInfo {
+            format_version: meta.format_version,
+            created_at: meta.created_at,
+            comparator: self.config.comparator.name().to_string(),
+            compression: meta.features & META_FEATURE_COMPRESSION != 0,
+            encryption: meta.features & META_FEATURE_ENCRYPTION != 0,
+        }
+    }
+
+    /// Stores `value` at `key` of the user metadata, e.g. the schema version of values
+    ///
+    /// User metadata lives next to store metadata rather than among entries,
+    /// it is not seen by reads, scans, exports or compaction. The whole map is
+    /// kept in memory and written again on every change, it suits a handful
+    /// of small values. A crash leaves either the old or the new map
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.set_meta("schema_version", "3").await.unwrap(); // handle error
+    ///     assert_eq!(store.get_meta("schema_version"), Some(b"3".to_vec()));
+    ///     assert!(store.get("schema_version").await.unwrap().is_none());
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn set_meta<T: AsRef<[u8]>>(&mut self, key: T, value: T) -> Result<(), Error> {
+        self.user_meta.set(key.as_ref(), value.as_ref()).await
+    }
+
+    /// Returns value stored at `key` of the user metadata, see [`DataStore::set_meta`]
+    pub fn get_meta<T: AsRef<[u8]>>(&self, key: T) -> Option<Vec<u8>> {
+        self.user_meta.get(key.as_ref()).map(<[u8]>::to_vec)
+    }
+
+    /// Removes `key` from the user metadata, returns `false` if it was not set
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn delete_meta<T: AsRef<[u8]>>(&mut self, key: T) -> Result<bool, Error> {
+        self.user_meta.remove(key.as_ref()).await
+    }
+}
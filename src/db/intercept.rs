@@ -0,0 +1,92 @@
+use super::batch::BatchOp;
+use crate::{consts::TOMB_STONE_MARKER, err::Error};
+use async_trait::async_trait;
+use std::{
+    fmt::Debug,
+    sync::{Arc, RwLock},
+};
+
+/// Hook called with every put and delete before it reaches the value log
+///
+/// Implement it to validate values against a schema, audit writes or
+/// normalize keys, and add it with [`crate::db::DataStore::with_write_interceptor`].
+/// Interceptors run in the order they were added, each one sees the write as
+/// left by the previous one. Writes of a batch are intercepted one by one and
+/// a rejected write rejects the whole batch.
+#[async_trait]
+pub trait WriteInterceptor: Debug + Send + Sync {
+    /// Inspects `write`, which can be modified in place, returning an error rejects it
+    ///
+    /// Called before keys and values are validated, so the write may still
+    /// fail afterwards
+    async fn intercept(
+        &self,
+        write: &mut InterceptedWrite,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// Write offered to a [`WriteInterceptor`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InterceptedWrite {
+    /// Insert or update of `key`
+    Put { key: Vec<u8>, value: Vec<u8> },
+
+    /// Removal of `key`
+    Delete { key: Vec<u8> },
+}
+
+impl InterceptedWrite {
+    /// Returns the write of `value` to `key`, a tombstone marker deletes the key
+    pub(crate) fn from_entry(key: &[u8], value: &[u8]) -> Self {
+        match value == TOMB_STONE_MARKER.as_bytes() {
+            true => InterceptedWrite::Delete { key: key.to_vec() },
+            false => InterceptedWrite::Put {
+                key: key.to_vec(),
+                value: value.to_vec(),
+            },
+        }
+    }
+}
+
+impl From<InterceptedWrite> for BatchOp {
+    fn from(write: InterceptedWrite) -> Self {
+        match write {
+            InterceptedWrite::Put { key, value } => BatchOp::Put { key, value },
+            InterceptedWrite::Delete { key } => BatchOp::Delete { key },
+        }
+    }
+}
+
+/// Write interceptors of a store
+#[derive(Clone, Debug, Default)]
+pub(crate) struct WriteInterceptors {
+    inner: Arc<RwLock<Vec<Arc<dyn WriteInterceptor>>>>,
+}
+
+impl WriteInterceptors {
+    /// Adds `interceptor` after the ones already set
+    pub fn add(&self, interceptor: Arc<dyn WriteInterceptor>) {
+        self.inner.write().unwrap().push(interceptor);
+    }
+
+    /// Returns `true` if no interceptor is set, so writes are not copied for nothing
+    pub fn is_empty(&self) -> bool {
+        self.inner.read().unwrap().is_empty()
+    }
+
+    /// Passes `write` through every interceptor
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::WriteRejected`] if an interceptor rejects the write
+    pub async fn intercept(&self, mut write: InterceptedWrite) -> Result<InterceptedWrite, Error> {
+        let interceptors = self.inner.read().unwrap().clone();
+        for interceptor in interceptors {
+            interceptor
+                .intercept(&mut write)
+                .await
+                .map_err(Error::WriteRejected)?;
+        }
+        Ok(write)
+    }
+}
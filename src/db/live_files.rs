@@ -0,0 +1,167 @@
+use super::DataStore;
+use crate::{
+    bucket::BucketID,
+    err::Error::{self, *},
+    fs::{FileAsync, FilterFileNode, FilterFs},
+    sst::Table,
+    types::{CreatedAt, Key},
+};
+use std::path::PathBuf;
+use tokio::fs;
+
+/// Files backing the store at a point in time
+#[derive(Clone, Debug, Default)]
+pub struct LiveFiles {
+    /// Every live SSTable
+    pub sstables: Vec<SSTableFile>,
+
+    /// Value log segments
+    pub vlog_segments: Vec<VLogSegment>,
+}
+
+/// Metadata of a live SSTable
+#[derive(Clone, Debug)]
+pub struct SSTableFile {
+    /// Directory the SSTable files are stored in
+    pub dir: PathBuf,
+
+    /// Data, index, filter and summary files of the SSTable, then its descriptor if it has one
+    pub files: Vec<PathBuf>,
+
+    /// Bucket the SSTable belongs to
+    pub bucket: BucketID,
+
+    /// Size of the SSTable files in bytes
+    pub size: usize,
+
+    /// Smallest key in the SSTable
+    pub smallest_key: Key,
+
+    /// Biggest key in the SSTable
+    pub biggest_key: Key,
+
+    /// Number of entries in the SSTable
+    pub entry_count: usize,
+
+    /// Number of tombstones in the SSTable, 0 if not recorded when it was written
+    pub tombstone_count: usize,
+
+    /// Timestamp of the oldest entry, `None` if not recorded when it was written
+    pub oldest_entry: Option<CreatedAt>,
+
+    /// Timestamp of the newest entry, `None` if not recorded when it was written
+    pub newest_entry: Option<CreatedAt>,
+
+    /// Date created
+    pub created_at: CreatedAt,
+
+    /// Whether the SSTable is being merged by a running compaction
+    pub being_compacted: bool,
+
+    /// Sampled read hits since the SSTable was loaded
+    pub hotness: u64,
+}
+
+/// Metadata of a value log segment
+#[derive(Clone, Debug)]
+pub struct VLogSegment {
+    /// Path of the segment file
+    pub path: PathBuf,
+
+    /// Size of the segment in bytes
+    pub size: usize,
+
+    /// Offset recovery starts replaying from
+    pub head_offset: usize,
+
+    /// Offset of the oldest entry not yet garbage collected
+    pub tail_offset: usize,
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Returns metadata of every live SSTable and value log segment
+    ///
+    /// Meant for backup tooling, which can copy the listed files
+    /// instead of walking the store directory
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn live_files(&self) -> Result<LiveFiles, Error> {
+        let mut sstables = Vec::new();
+        let buckets = self.buckets.read().await;
+        for (bucket_id, bucket) in buckets.buckets.iter() {
+            for table in bucket.sstables.read().await.iter() {
+                sstables.push(self.sstable_file(*bucket_id, table).await?);
+            }
+        }
+        drop(buckets);
+        sstables.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.dir.cmp(&b.dir)));
+
+        let val_log = self.val_log.read().await;
+        let vlog_segments = vec![VLogSegment {
+            path: val_log.content.path.to_owned(),
+            size: val_log.content.file.node.size().await,
+            // the memtable age check only moves the head in meta
+            head_offset: val_log.head_offset.max(self.meta.lock().unwrap().v_log_head),
+            tail_offset: val_log.tail_offset,
+        }];
+        drop(val_log);
+        Ok(LiveFiles {
+            sstables,
+            vlog_segments,
+        })
+    }
+
+    /// Collects metadata of `table`
+    async fn sstable_file(&self, bucket: BucketID, table: &Table) -> Result<SSTableFile, Error> {
+        let files = table.files.paths();
+        let mut size = 0;
+        for file in files.iter() {
+            size += fs::metadata(file).await.map_err(GetFileMetaData)?.len() as usize;
+        }
+
+        // recovered sstables keep summary and filter metadata in key range only
+        let range = self.key_range.key_ranges.read().await.get(&table.id()).cloned();
+        let (smallest_key, biggest_key) = match (table.summary.as_ref(), range.as_ref()) {
+            (Some(summary), _) => (summary.smallest_key.to_owned(), summary.biggest_key.to_owned()),
+            (None, Some(range)) => (range.smallest_key.to_owned(), range.biggest_key.to_owned()),
+            (None, None) => (Key::new(), Key::new()),
+        };
+
+        // sstables written before properties were recorded fall back to the filter,
+        // filters of recovered sstables are only loaded on first lookup
+        let properties = table.properties().cloned().unwrap_or_default();
+        let filter = table
+            .filter
+            .as_ref()
+            .or_else(|| range.as_ref().and_then(|r| r.sst.filter.as_ref()));
+        let entry_count = match filter {
+            _ if properties.entry_count > 0 => properties.entry_count,
+            Some(filter) if filter.num_elements() > 0 => filter.num_elements(),
+            Some(filter) if filter.file_path.is_some() => {
+                let (_, _, no_of_elements, _) =
+                    FilterFileNode::recover(filter.file_path.as_ref().unwrap(), filter.cipher.to_owned())
+                        .await?;
+                no_of_elements as usize
+            }
+            _ => 0,
+        };
+
+        Ok(SSTableFile {
+            dir: table.dir.to_owned(),
+            files,
+            bucket,
+            size,
+            smallest_key,
+            biggest_key,
+            entry_count,
+            tombstone_count: properties.tombstone_count,
+            oldest_entry: properties.oldest_entry,
+            newest_entry: properties.newest_entry,
+            created_at: table.created_at,
+            being_compacted: self.compactor.config.compacting.contains(table.id()),
+            hotness: table.get_hotness(),
+        })
+    }
+}
@@ -1,5 +1,64 @@
+mod append;
+mod background;
+mod batch;
+mod checkpoint;
+mod commit;
+mod compaction_plan;
+mod consistency;
+mod counter;
+mod encryption;
+mod export;
+mod format;
+mod handle;
+mod info;
+mod intercept;
 mod keyspace;
+mod live_files;
+mod orphans;
+mod quota;
+mod read_profile;
+mod receipt;
 mod recovery;
+mod rename;
+mod scrub;
+mod seal;
+mod session;
+mod stats;
 mod store;
+mod topology;
+mod ttl_sweep;
+mod typed;
+mod warm_up;
+pub use crate::clock::{Clock, MockClock, SystemClock, Version};
+pub use crate::compactors::{BucketWriteStats, CompactionState, CompactionStatus};
+#[cfg(feature = "compaction-hooks")]
+pub use crate::compactors::{CompactionEntry, CompactionHook, CompactionTrace};
+pub use crate::comparator::Comparator;
+pub use crate::encryption::{EncryptionKey, KeyId};
+pub use crate::err::Error;
+pub use crate::flush::FlushReport;
+pub use crate::health::{BackgroundError, BackgroundTask, Health, HealthState, TaskHeartbeat, TaskOutcome};
+pub use crate::memtable::{MemtableId, UserEntry, UserEntryRef};
+pub use crate::quota::{PrefixQuota, PrefixUsage};
+pub use crate::range::{FetchedEntry, RangeIterator};
+pub use crate::types::SeqNumber;
+pub use batch::WriteBatch;
+pub use compaction_plan::{CompactionPlan, PlannedBucket};
+pub use consistency::{ConsistencyReport, Inconsistency};
+pub use export::ExportFormat;
+pub use handle::Db;
+pub use info::StoreInfo;
+pub use intercept::{InterceptedWrite, WriteInterceptor};
+pub use live_files::{LiveFiles, SSTableFile, VLogSegment};
+pub use read_profile::ReadProfile;
+pub use receipt::WriteReceipt;
+pub use scrub::{ScrubAction, ScrubReport};
+pub use session::Session;
+pub use stats::Stats;
 pub use store::DataStore;
+pub use store::KeyStatus;
 pub use store::SizeUnit;
+pub use topology::BucketStats;
+pub use ttl_sweep::SweepReport;
+pub(crate) use ttl_sweep::TtlSweeper;
+pub use warm_up::{WarmUpOptions, WarmUpReport};
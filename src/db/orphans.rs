@@ -0,0 +1,132 @@
+use super::DataStore;
+use crate::{
+    err::Error::{self, *},
+    types::Key,
+};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::fs;
+
+#[derive(Debug)]
+struct OrphanFilesInner {
+    /// SSTable directories not recorded in the manifest
+    dirs: Vec<PathBuf>,
+
+    /// Time the orphans were found at open
+    found_at: Instant,
+
+    /// Time orphans are kept before they are deleted
+    grace_period: Duration,
+}
+
+/// SSTable directories left behind by an interrupted flush or compaction
+///
+/// Orphans are not loaded at open, they are kept on disk for a grace period
+/// so they can be inspected before being deleted
+#[derive(Clone, Debug)]
+pub(crate) struct OrphanFiles {
+    inner: Arc<Mutex<OrphanFilesInner>>,
+}
+
+impl OrphanFiles {
+    /// Creates new `OrphanFiles` deletable once `grace_period` elapses
+    pub fn new(dirs: Vec<PathBuf>, grace_period: Duration) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(OrphanFilesInner {
+                dirs,
+                found_at: Instant::now(),
+                grace_period,
+            })),
+        }
+    }
+
+    /// Sets time orphans are kept before they are deleted
+    pub fn set_grace_period(&self, grace_period: Duration) {
+        self.inner.lock().unwrap().grace_period = grace_period;
+    }
+
+    /// Returns time left until orphans can be deleted, `None` if there are none
+    pub fn remaining(&self) -> Option<Duration> {
+        let inner = self.inner.lock().unwrap();
+        if inner.dirs.is_empty() {
+            return None;
+        }
+        Some(inner.grace_period.saturating_sub(inner.found_at.elapsed()))
+    }
+
+    /// Deletes orphans whose grace period has elapsed along with buckets left empty
+    ///
+    /// Returns number of SSTable directories deleted
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn delete_expired(&self) -> Result<usize, Error> {
+        let dirs = match self.remaining() {
+            Some(remaining) if remaining.is_zero() => std::mem::take(&mut self.inner.lock().unwrap().dirs),
+            _ => return Ok(0),
+        };
+        let mut deleted = 0;
+        for (idx, dir) in dirs.iter().enumerate() {
+            if let Err(err) = fs::remove_dir_all(dir).await {
+                if err.kind() != std::io::ErrorKind::NotFound {
+                    // keep the rest for a later attempt
+                    self.inner.lock().unwrap().dirs.extend_from_slice(&dirs[idx..]);
+                    return Err(DirDelete(err));
+                }
+            }
+            deleted += 1;
+            if let Some(bucket_dir) = dir.parent() {
+                let is_empty = match fs::read_dir(bucket_dir).await {
+                    Ok(mut entries) => matches!(entries.next_entry().await, Ok(None)),
+                    Err(_) => false,
+                };
+                if is_empty {
+                    fs::remove_dir(bucket_dir).await.map_err(DirDelete)?;
+                }
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Deletes orphans in the background once the grace period elapses
+    pub fn spawn_deleter(&self) {
+        let orphans = self.clone();
+        tokio::spawn(async move {
+            // grace period can change after open, check it again after every wait
+            while let Some(remaining) = orphans.remaining() {
+                if !remaining.is_zero() {
+                    tokio::time::sleep(remaining).await;
+                    continue;
+                }
+                match orphans.delete_expired().await {
+                    Ok(deleted) => log::info!("Deleted {} orphaned SSTables", deleted),
+                    Err(err) => {
+                        log::error!("{}", err);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Deletes SSTables left behind by an interrupted flush or compaction
+    ///
+    /// Such SSTables are found at open and are never loaded. They are deleted in
+    /// the background once the orphan file grace period elapses, this deletes
+    /// them right away if it has already elapsed.
+    ///
+    /// Returns number of SSTables deleted
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn delete_orphan_files(&self) -> Result<usize, Error> {
+        self.orphans.delete_expired().await
+    }
+}
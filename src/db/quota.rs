@@ -0,0 +1,102 @@
+use super::DataStore;
+use crate::{
+    consts::MAX_KEY_SIZE,
+    err::Error,
+    quota::{PrefixUsage, UsageDelta},
+    slow_log::OpTimer,
+    types::Key,
+    vlog::ValueLog,
+};
+use std::collections::HashMap;
+
+/// Returns smallest key bigger than every key starting with `prefix`
+pub(super) fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    // longer than any encoded key, escapes included
+    vec![u8::MAX; 2 * MAX_KEY_SIZE + 2]
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Returns bytes and keys stored under every prefix with a quota, see `with_prefix_quota`
+    ///
+    /// Bytes count keys and values of live entries, deleted and expired
+    /// entries are left out as with [`DataStore::seek`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error if usage of a prefix has to be counted and an IO error occurs
+    pub async fn quota_usage(&self) -> Result<Vec<PrefixUsage>, Error> {
+        let mut timer = self.slow_log.foreground("quota_usage");
+        self.count_quota_usage(&*self.val_log.read().await, &mut timer)
+            .await?;
+        timer.finish();
+        Ok(self.quotas.usage())
+    }
+
+    /// Counts usage of prefixes not counted yet from stored entries, reading values from `val_log`
+    pub(crate) async fn count_quota_usage(
+        &self,
+        val_log: &ValueLog,
+        timer: &mut OpTimer,
+    ) -> Result<(), Error> {
+        for prefix in self.quotas.uncounted() {
+            let comparator = self.config.comparator;
+            let start = comparator.encode_prefix(&prefix).into_owned();
+            let end = prefix_end(&start);
+            let (merger, _) = self.merge_keys_in_range(&start, &end, timer).await?;
+            let (mut bytes, mut keys) = (0, 0);
+            for entry in merger.filter(|e| e.key.starts_with(&start)) {
+                if let (val, false) = val_log.get(entry.val_offset).await? {
+                    bytes += comparator.decode(entry.key).len() + val.len();
+                    keys += 1;
+                }
+            }
+            self.quotas.set_usage(&prefix, bytes, keys);
+        }
+        Ok(())
+    }
+}
+
+impl DataStore<'static, Key> {
+    /// Returns change in usage of every key of `writes` under a prefix with a quota
+    ///
+    /// Writes are `(key, value)` pairs of encoded keys applied in order, a `None` value deletes the key.
+    /// Callers hold `val_log` for the writes, stored entries are read through it
+    pub(crate) async fn quota_deltas(
+        &self,
+        writes: &[(&[u8], Option<&[u8]>)],
+        val_log: &ValueLog,
+        timer: &mut OpTimer,
+    ) -> Result<Vec<(Vec<u8>, UsageDelta)>, Error> {
+        if self.quotas.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.count_quota_usage(val_log, timer).await?;
+        // value sizes as left by earlier writes of the same batch
+        let mut written: HashMap<Vec<u8>, Option<usize>> = HashMap::new();
+        let mut deltas = Vec::new();
+        for (encoded, value) in writes {
+            let key = self.config.comparator.decode(encoded.to_vec());
+            if !self.quotas.tracks(&key) {
+                continue;
+            }
+            let previous = match written.get(&key) {
+                Some(previous) => *previous,
+                None => self
+                    .get_uncached_in(encoded, Some(val_log), timer, false)
+                    .await?
+                    .map(|(entry, _)| entry.val.len()),
+            };
+            let current = value.map(<[u8]>::len);
+            deltas.push((key.to_owned(), UsageDelta::new(key.len(), previous, current)));
+            written.insert(key, current);
+        }
+        Ok(deltas)
+    }
+}
@@ -0,0 +1,218 @@
+use super::DataStore;
+use crate::{
+    bucket::{Bucket, BucketID},
+    consts::READ_PROFILE_PREFIX_SIZE,
+    types::{CreatedAt, Key},
+};
+use chrono::Utc;
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Read traffic sampled over the current window, returned by `DataStore::read_profile`
+///
+/// Counts are sorted from the most to the least read
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ReadProfile {
+    /// Time the window started, `None` if sampling is disabled
+    pub window_started_at: Option<CreatedAt>,
+
+    /// Number of gets sampled in the window
+    pub sampled_reads: usize,
+
+    /// Number of sampled gets served by the row cache
+    pub row_cache_hits: usize,
+
+    /// Number of sampled gets that probed each SSTable
+    pub sstables: Vec<(PathBuf, usize)>,
+
+    /// Number of sampled gets that probed an SSTable of each bucket
+    pub buckets: Vec<(BucketID, usize)>,
+
+    /// Number of sampled gets of keys starting with each prefix, prefixes are
+    /// the first `READ_PROFILE_PREFIX_SIZE` bytes of keys
+    pub prefixes: Vec<(Key, usize)>,
+}
+
+/// Counts of the current window
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    started_at: CreatedAt,
+    sampled_reads: usize,
+    row_cache_hits: usize,
+    sstables: HashMap<PathBuf, usize>,
+    buckets: HashMap<BucketID, usize>,
+    prefixes: HashMap<Key, usize>,
+}
+
+impl Window {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            started_at: Utc::now(),
+            sampled_reads: 0,
+            row_cache_hits: 0,
+            sstables: HashMap::new(),
+            buckets: HashMap::new(),
+            prefixes: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ProfilerInner {
+    /// One of every `every` gets is sampled, 0 disables sampling
+    every: usize,
+    window: Duration,
+    reads: usize,
+    current: Window,
+}
+
+impl ProfilerInner {
+    /// Starts a new window once the current one is over
+    fn rotate(&mut self) {
+        if self.current.started.elapsed() >= self.window {
+            self.current = Window::new();
+        }
+    }
+}
+
+/// Samples gets to find the SSTables, buckets and key prefixes serving most reads
+#[derive(Clone, Debug)]
+pub(crate) struct ReadProfiler {
+    inner: Arc<Mutex<ProfilerInner>>,
+}
+
+impl ReadProfiler {
+    /// Creates new `ReadProfiler` sampling one of every `every` gets over `window`
+    pub fn new(every: usize, window: Duration) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ProfilerInner {
+                every,
+                window,
+                reads: 0,
+                current: Window::new(),
+            })),
+        }
+    }
+
+    /// Changes sampling and starts a new window
+    pub fn set_sampling(&self, every: usize, window: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.every = every;
+        inner.window = window;
+        inner.current = Window::new();
+    }
+
+    /// Returns `true` if the next get should be sampled
+    pub fn sample(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.every == 0 {
+            return false;
+        }
+        inner.reads = inner.reads.wrapping_add(1);
+        inner.reads.is_multiple_of(inner.every)
+    }
+
+    /// Records a sampled get of `key`
+    pub fn record_read(&self, key: &[u8], row_cache_hit: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.rotate();
+        let window = &mut inner.current;
+        window.sampled_reads += 1;
+        if row_cache_hit {
+            window.row_cache_hits += 1;
+        }
+        let prefix = key[..key.len().min(READ_PROFILE_PREFIX_SIZE)].to_vec();
+        *window.prefixes.entry(prefix).or_default() += 1;
+    }
+
+    /// Records SSTable at `dir` probed by a sampled get
+    pub fn record_sstable(&self, dir: &Path) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.rotate();
+        let window = &mut inner.current;
+        *window.sstables.entry(dir.to_path_buf()).or_default() += 1;
+        if let Some(bucket_id) = dir.parent().and_then(|parent| Bucket::id_from_dir(parent).ok()) {
+            *window.buckets.entry(bucket_id).or_default() += 1;
+        }
+    }
+
+    /// Returns read traffic of the current window
+    pub fn snapshot(&self) -> ReadProfile {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.every == 0 {
+            return ReadProfile::default();
+        }
+        inner.rotate();
+        let window = &inner.current;
+        ReadProfile {
+            window_started_at: Some(window.started_at),
+            sampled_reads: window.sampled_reads,
+            row_cache_hits: window.row_cache_hits,
+            sstables: sorted(&window.sstables),
+            buckets: sorted(&window.buckets),
+            prefixes: sorted(&window.prefixes),
+        }
+    }
+}
+
+/// Returns counts from the biggest to the smallest
+fn sorted<T: Clone + Eq + Hash + Ord>(counts: &HashMap<T, usize>) -> Vec<(T, usize)> {
+    let mut counts: Vec<_> = counts.iter().map(|(k, v)| (k.to_owned(), *v)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Returns which SSTables, buckets and key prefixes served the gets sampled
+    /// in the current window
+    ///
+    /// Sampling is disabled by default, see `with_read_sampling`
+    pub fn read_profile(&self) -> ReadProfile {
+        self.read_profiler.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_read_profiler_samples_and_rotates() {
+        let profiler = ReadProfiler::new(0, Duration::from_secs(60));
+        assert!(!profiler.sample());
+        assert_eq!(profiler.snapshot(), ReadProfile::default());
+
+        profiler.set_sampling(2, Duration::from_secs(60));
+        let sampled = (0..10).filter(|_| profiler.sample()).count();
+        assert_eq!(sampled, 5);
+
+        let bucket_id = Uuid::new_v4();
+        let bucket = PathBuf::from(format!("bucket{}", bucket_id));
+        profiler.record_read(b"user:1", false);
+        profiler.record_read(b"user:2", true);
+        profiler.record_read(b"item", false);
+        profiler.record_sstable(&bucket.join("sstable_1"));
+        profiler.record_sstable(&bucket.join("sstable_2"));
+        profiler.record_sstable(&bucket.join("sstable_2"));
+        let profile = profiler.snapshot();
+        assert_eq!(profile.sampled_reads, 3);
+        assert_eq!(profile.row_cache_hits, 1);
+        assert_eq!(profile.prefixes[0], (b"user".to_vec(), 2));
+        assert_eq!(profile.sstables[0], (bucket.join("sstable_2"), 2));
+        assert_eq!(profile.buckets, vec![(bucket_id, 3)]);
+
+        // counts restart with every window
+        profiler.set_sampling(2, Duration::ZERO);
+        profiler.record_read(b"user:1", false);
+        assert_eq!(profiler.snapshot().sampled_reads, 0);
+    }
+}
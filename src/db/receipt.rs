@@ -0,0 +1,38 @@
+use crate::{
+    clock::Version,
+    memtable::MemtableId,
+    types::{CreatedAt, SeqNumber, ValOffset},
+};
+
+/// Where and when a write was applied, returned by `DataStore::put` and `DataStore::delete`
+///
+/// Lets change capture, dedup checks and debugging follow a write without
+/// looking the key up again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WriteReceipt {
+    /// Sequence number of the write, see `DataStore::latest_sequence`
+    pub seq: SeqNumber,
+
+    /// Offset of the value log record the entry points at, a value shared
+    /// with an earlier put points at the record holding it
+    pub vlog_offset: ValOffset,
+
+    /// Memtable the entry was inserted into
+    pub memtable_id: MemtableId,
+
+    /// Time the entry was created at
+    pub timestamp: CreatedAt,
+}
+
+impl WriteReceipt {
+    /// Creates `WriteReceipt` of an entry created at `timestamp`
+    pub(crate) fn new(vlog_offset: ValOffset, memtable_id: MemtableId, timestamp: CreatedAt) -> Self {
+        Self {
+            seq: Version::from(timestamp).sequence(),
+            vlog_offset,
+            memtable_id,
+            timestamp,
+        }
+    }
+}
@@ -1,34 +1,47 @@
-use std::collections::HashSet;
-
 use super::{store::DirPath, DataStore, SizeUnit};
 
-use crate::bucket::{Bucket, BucketID, BucketMap};
+use super::checkpoint::{CheckpointRecord, HeadCheckpoint};
+use super::orphans::OrphanFiles;
+use crate::bucket::{Bucket, BucketID, BucketMap, BucketTuning};
+use crate::cache::RowCache;
 use crate::cfg::Config;
+use crate::clock::{ClockHandle, Version};
 use crate::compactors::{self, Compactor, IntervalParams, TtlParams};
 use crate::consts::{
-    DEFAULT_DB_NAME, DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE, HEAD_ENTRY_KEY, HEAD_ENTRY_VALUE, SIZE_OF_U32,
-    SIZE_OF_U64, SIZE_OF_U8, TAIL_ENTRY_KEY, TAIL_ENTRY_VALUE,
+    DEFAULT_DB_NAME, FORMAT_VERSION, HEAD_ENTRY_KEY, HEAD_ENTRY_VALUE, META_FEATURE_ENCRYPTION,
+    META_FEATURE_VLOG_CHECKSUM, SUPPORTED_META_FEATURES, TAIL_ENTRY_KEY, TAIL_ENTRY_VALUE,
+    TEMP_SSTABLE_EXTENSION, VLOG_LIVENESS_FILE_NAME,
 };
+use crate::db::commit::CommitWatermark;
+use crate::db::read_profile::ReadProfiler;
+use crate::db::scrub::Scrubber;
+use crate::db::seal::MemTableAgeCheck;
+use crate::db::ttl_sweep::TtlSweeper;
+use crate::encryption::{FileCipher, FileKey, Keyring, TableCipher};
 use crate::err::Error;
 use crate::err::Error::*;
 use crate::filter::BloomFilter;
-use crate::flush::Flusher;
-use crate::fs::{FileAsync, P};
-use crate::gc::garbage_collector::GC;
+use crate::flush::{FlushSignal, Flusher};
+use crate::fs::{FileAsync, FileType, VLogFs, P};
+use crate::gc::{garbage_collector::GC, DeadOffsets};
+use crate::health::HealthMonitor;
 use crate::key_range::KeyRange;
-use crate::memtable::{Entry, MemTable};
-use crate::meta::Meta;
+use crate::memtable::{Entry, MemTable, WriteBufferManager};
+use crate::meta::{Meta, UserMeta};
 use crate::open_dir_stream;
-use crate::sst::{Summary, Table};
+use crate::quota::QuotaTracker;
+use crate::slow_log::SlowLog;
+use crate::sst::{Summary, Table, TableFiles};
 use crate::types::{ImmutableMemTablesLockFree, Key};
-use crate::vlog::ValueLog;
-use async_broadcast::broadcast;
-use chrono::Utc;
+use crate::vlog::{RecordHeader, RecordType, ValueDedup, ValueLog};
 use crossbeam_skiplist::SkipMap;
+use futures::{stream, StreamExt};
 use indexmap::IndexMap;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use tokio::fs::read_dir;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, Mutex, RwLock};
 
 /// Parameters to create an empty ['DataStore'] or recover exisiting one from ['ValueLog']
 pub struct CreateOrRecoverStoreParams<'a, P> {
@@ -39,6 +52,22 @@ pub struct CreateOrRecoverStoreParams<'a, P> {
     pub config: Config,
     pub size_unit: SizeUnit,
     pub meta: Meta,
+    pub user_meta: UserMeta,
+}
+
+/// State recovered or created on open that a [`DataStore`] is built around
+struct StoreParts {
+    dir: DirPath,
+    vlog: ValueLog,
+    key_range: KeyRange,
+    buckets: BucketMap,
+    config: Config,
+    meta: Meta,
+    user_meta: UserMeta,
+    active_memtable: MemTable<Key>,
+    read_only_memtables: ImmutableMemTablesLockFree<Key>,
+    clock: ClockHandle,
+    orphans: Vec<PathBuf>,
 }
 
 impl DataStore<'static, Key> {
@@ -47,10 +76,10 @@ impl DataStore<'static, Key> {
     /// Errors
     ///
     /// Returns error incase there is an IO error
-    pub async fn recover(
+    pub(crate) async fn recover(
         params: CreateOrRecoverStoreParams<'_, impl P>,
     ) -> Result<DataStore<'static, Key>, Error> {
-        let (buckets_path, dir, mut vlog, key_range, config, size_unit, mut meta) = (
+        let (buckets_path, dir, mut vlog, key_range, config, size_unit, mut meta, user_meta) = (
             params.buckets_path,
             params.dir,
             params.vlog,
@@ -58,9 +87,42 @@ impl DataStore<'static, Key> {
             params.config,
             params.size_unit,
             params.meta,
+            params.user_meta,
         );
 
+        // checked before anything is written so a store using features this
+        // version cannot read is left untouched
+        let has_meta = meta.file_handle.file.node.size().await > 0;
+        if has_meta {
+            meta.recover().await?;
+            if meta.features & !SUPPORTED_META_FEATURES != 0 {
+                return Err(Error::UnsupportedStoreFeatures(meta.features));
+            }
+            if meta.format_version > FORMAT_VERSION {
+                return Err(Error::UnsupportedFormatVersion {
+                    found: meta.format_version,
+                    supported: FORMAT_VERSION,
+                });
+            }
+        }
+        vlog.checksums = meta.features & META_FEATURE_VLOG_CHECKSUM != 0;
+        let mut buckets_map = BucketMap::new(buckets_path.as_ref()).await?;
+        buckets_map.manifest.check_comparator(config.comparator).await?;
+        *buckets_map.tuning.write().unwrap() = BucketTuning::from(&config);
+        buckets_map
+            .persist_filter_bits
+            .store(config.persist_filter_bits, Ordering::Relaxed);
+        // records are read from here on, value log has to be decrypted first
+        DataStore::load_keyring(&mut buckets_map, &config, &mut meta)?;
+        let keyring = buckets_map.keyring.clone();
+        let vlog_cipher = keyring.value_log(buckets_map.manifest.vlog_epochs())?;
+        vlog.set_cipher(vlog_cipher.to_owned()).await?;
+        // observes the newest recovered entries so versions issued after restart stay ahead
+        let clock = ClockHandle::default();
         let mut recovered_buckets: IndexMap<BucketID, Bucket> = IndexMap::new();
+        let mut recovered_dirs = Vec::new();
+        let mut orphans = Vec::new();
+        let mut bucket_sst_dirs = Vec::new();
         // Get bucket diretories streams
         let mut buckets_stream = open_dir_stream!(buckets_path.as_ref().to_path_buf());
         // for each bucket directory
@@ -68,218 +130,326 @@ impl DataStore<'static, Key> {
             path: buckets_path.as_ref().to_path_buf(),
             error: err,
         })? {
+            // manifest lives next to bucket directories
+            if !bucket_dir.path().is_dir() {
+                continue;
+            }
             // get read stream for sstable directories stream in the bucket
             let mut sst_dir_stream = open_dir_stream!(bucket_dir.path());
+            let mut sst_dirs = Vec::new();
 
             // iterate over each sstable directory
             while let Some(sst_dir) = sst_dir_stream.next_entry().await.map_err(|err| DirOpen {
                 path: buckets_path.as_ref().to_path_buf(),
                 error: err,
             })? {
-                // get read stream for files in the sstable directory
-                let mut files_stream = open_dir_stream!(sst_dir.path());
-                let mut files = Vec::new();
-
-                // iterate over each file
-                while let Some(file) = files_stream.next_entry().await.map_err(|err| DirOpen {
-                    path: buckets_path.as_ref().to_path_buf(),
-                    error: err,
-                })? {
-                    let file_path = file.path();
-                    if file_path.is_file() {
-                        files.push(file_path);
-                    }
+                let sst_dir = sst_dir.path();
+                let file_number = Table::file_number_of(&sst_dir);
+                // numbers on disk are never handed out again, even those of leftovers
+                if let Some(number) = file_number {
+                    buckets_map.manifest.reserve_file_number(number);
                 }
-                // Sort to make order deterministic
-                files.sort();
-                let bucket_id = Self::get_bucket_id_from_full_bucket_path(sst_dir.path());
-
-                if files.len() < 4 {
+                // temporary sstables and sstables not recorded in manifest are
+                // leftovers of an interrupted flush or compaction
+                let is_temp = sst_dir
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case(TEMP_SSTABLE_EXTENSION));
+                if is_temp || !buckets_map.manifest.is_live(&sst_dir) {
+                    log::warn!("Orphaned SSTable {:?} will not be loaded", sst_dir);
+                    orphans.push(sst_dir);
+                    continue;
+                }
+                let Some(number) = file_number else {
                     return Err(InvalidSSTableDirectory {
-                        input_string: sst_dir.path().to_owned().to_string_lossy().to_string(),
+                        input_string: sst_dir.to_string_lossy().to_string(),
                     });
-                }
-
-                let data_file_path = files[0].to_owned();
-                let filter_file_path = files[1].to_owned();
-                let index_file_path = files[2].to_owned();
-                let _summary_file_path = files[3].to_owned();
-
-                let mut table = Table::build_from(
-                    sst_dir.path().to_owned(),
-                    data_file_path.to_owned(),
-                    index_file_path.to_owned(),
-                )
-                .await;
-                let bucket_uuid = uuid::Uuid::parse_str(&bucket_id).map_err(|err| InvaidUUIDParseString {
-                    input_string: bucket_id,
-                    error: err,
-                })?;
+                };
+                sst_dirs.push((number, sst_dir));
+            }
+            if sst_dirs.is_empty() {
+                continue;
+            }
+            // load sstables in the order they were written
+            sst_dirs.sort();
+            // stores whose manifest predates bucket ids name buckets by id
+            let bucket_uuid = match buckets_map.manifest.bucket_of(&sst_dirs[0].1) {
+                Some(id) => id,
+                None => Bucket::id_from_dir(bucket_dir.path())?,
+            };
+            bucket_sst_dirs.push((bucket_dir.path(), bucket_uuid, sst_dirs));
+        }
 
-                if let Some(b) = recovered_buckets.get(&bucket_uuid) {
-                    let temp_sstables = b.sstables.clone();
-                    temp_sstables.write().await.push(table.clone());
-                    let updated_bucket = Bucket::from(
-                        bucket_dir.path(),
-                        bucket_uuid,
-                        temp_sstables.read().await.clone(),
-                        0,
-                    )
-                    .await?;
-                    recovered_buckets.insert(bucket_uuid, updated_bucket);
-                } else {
-                    // Create new bucket
-                    let updated_bucket =
-                        Bucket::from(bucket_dir.path(), bucket_uuid, vec![table.clone()], 0).await?;
-                    recovered_buckets.insert(bucket_uuid, updated_bucket);
+        // sstable metadata is loaded by a bounded set of tasks, results keep the order they were written in
+        let parallelism = config.recovery_parallelism.max(1);
+        let mut table_ciphers = Vec::new();
+        for (_, sst_dir) in bucket_sst_dirs.iter().flat_map(|(_, _, sst_dirs)| sst_dirs) {
+            let key = buckets_map.manifest.table_key(sst_dir);
+            table_ciphers.push((sst_dir.to_owned(), key.map(|key| keyring.table(key)).transpose()?));
+        }
+        let mut loaded_tables = stream::iter(table_ciphers)
+            .map(|(sst_dir, cipher)| tokio::spawn(DataStore::recover_table(sst_dir, cipher)))
+            .buffered(parallelism);
+        for (bucket_dir, bucket_uuid, sst_dirs) in bucket_sst_dirs.iter() {
+            let mut tables = Vec::with_capacity(sst_dirs.len());
+            for (_, sst_dir) in sst_dirs {
+                let (mut table, summary) = loaded_tables
+                    .next()
+                    .await
+                    .expect("a table is loaded for every directory")
+                    .map_err(|_| TokioJoin)??;
+                recovered_dirs.push(sst_dir.to_owned());
+                if let Some(newest_entry) = summary.properties.newest_entry {
+                    clock.observe(newest_entry);
                 }
-
-                // recover summary
-                let mut summary = Summary::new(sst_dir.path());
-                summary.recover().await?;
-                table.summary = Some(summary.to_owned());
+                tables.push(table.clone());
 
                 // store bloomfilter metadata in table
                 let new_filter = BloomFilter {
-                    file_path: Some(filter_file_path),
+                    file_path: Some(table.files.filter.to_owned()),
+                    cipher: table.file_cipher(&FileType::Filter),
                     ..Default::default()
                 };
                 table.filter = Some(new_filter);
 
                 key_range
-                    .set(sst_dir.path(), summary.smallest_key, summary.biggest_key, table)
+                    .set(summary.smallest_key, summary.biggest_key, table)
                     .await;
             }
+            let bucket = Bucket::from(bucket_dir.to_owned(), *bucket_uuid, tables, 0).await?;
+            recovered_buckets.insert(*bucket_uuid, bucket);
+        }
+        if !buckets_map.manifest.is_tracked() {
+            // store created before manifest existed, adopt every sstable found
+            buckets_map.publish(&recovered_dirs, &[]).await?;
+        } else {
+            let unlabeled: Vec<_> = recovered_dirs
+                .iter()
+                .filter(|dir| buckets_map.manifest.bucket_of(dir).is_none())
+                .cloned()
+                .collect();
+            if !unlabeled.is_empty() {
+                // manifest written before bucket ids were recorded
+                buckets_map.publish(&unlabeled, &[]).await?;
+            }
+            let missing: Vec<_> = buckets_map
+                .manifest
+                .live_tables()
+                .into_iter()
+                .filter(|dir| !recovered_dirs.contains(dir))
+                .collect();
+            if !missing.is_empty() {
+                log::warn!("SSTables {:?} recorded in manifest are missing", missing);
+                buckets_map.publish(&[], &missing).await?;
+            }
         }
-        let mut buckets_map = BucketMap::new(buckets_path.as_ref()).await?;
         for (bucket_id, bucket) in recovered_buckets.iter() {
             buckets_map.buckets.insert(*bucket_id, bucket.clone());
         }
-        if meta.file_handle.file.node.size().await > 0 {
-            meta.recover().await?;
+        let mut clean_shutdown = false;
+        // the comparator was checked against the manifest
+        meta.comparator = Some(config.comparator.name().to_string());
+        if has_meta {
             vlog.set_head(meta.v_log_head);
             vlog.set_tail(meta.v_log_tail);
+            clean_shutdown = meta.is_clean_shutdown(vlog.content.file.node.size().await);
+            // a crash from here on must not be taken for a clean shutdown
+            meta.clean_shutdown = false;
         } else {
             // if meta is empty then no flush has happened before crash
-            // therefore read from the beginning of vlog
-            vlog.set_head(
-                SIZE_OF_U32               // tail key length 
-                +SIZE_OF_U32              // tail value length
-                + SIZE_OF_U64             // date Length
-                + SIZE_OF_U8              // tombstone marker
-                + TAIL_ENTRY_KEY.len()    // tail key
-                + TAIL_ENTRY_VALUE.len(), // tail value
-            );
+            // therefore read from the record after the tail, whose header
+            // also tells if the store was created with checksums
+            let tail = vlog.content.file.read_bytes(0, RecordHeader::LEN).await?;
+            if tail.len() == RecordHeader::LEN {
+                let tail = RecordHeader::decode(&tail);
+                vlog.set_head(tail.record_len());
+                if tail.checksummed {
+                    meta.features |= META_FEATURE_VLOG_CHECKSUM;
+                    vlog.checksums = true;
+                }
+            }
             vlog.set_tail(0);
         }
 
-        let recover_res = DataStore::recover_memtable(
-            size_unit,
-            config.write_buffer_size,
-            config.false_positive_rate,
-            &dir.val_log,
-            vlog.head_offset,
-        )
-        .await;
-        let (flush_signal_tx, flush_signal_rx) = broadcast(DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE);
-        match recover_res {
-            Ok((active_memtable, read_only_memtables)) => {
-                let buckets = Arc::new(RwLock::new(buckets_map.to_owned()));
-                let key_range = Arc::new(key_range.to_owned());
-                let read_only_memtables = Arc::new(read_only_memtables);
-                let gc_table = Arc::new(RwLock::new(active_memtable.to_owned()));
-                let gc_log = Arc::new(RwLock::new(vlog.to_owned()));
-                let flusher = Flusher::new(read_only_memtables.clone(), buckets.clone(), key_range.clone());
-                let gc_updated_entries = Arc::new(RwLock::new(SkipMap::new()));
-                Ok(DataStore {
-                    keyspace: DEFAULT_DB_NAME,
-                    active_memtable: active_memtable.to_owned(),
-                    val_log: vlog,
-                    dir: dir.to_owned(),
-                    buckets,
-                    key_range,
-                    meta: meta.to_owned(),
-                    flusher,
-                    compactor: Compactor::new(
-                        config.enable_ttl,
-                        TtlParams {
-                            entry_ttl: config.entry_ttl,
-                            tombstone_ttl: config.tombstone_ttl,
-                        },
-                        IntervalParams {
-                            background_interval: config.background_compaction_interval,
-                            flush_listener_interval: config.compactor_flush_listener_interval,
-                            tombstone_compaction_interval: config.tombstone_compaction_interval,
-                        },
-                        config.compaction_strategy,
-                        compactors::CompactionReason::MaxSize,
-                        config.false_positive_rate,
-                    ),
-                    config: config.clone(),
-                    gc: GC::new(
-                        config.online_gc_interval,
-                        config.gc_chunk_size,
-                        gc_table.clone(),
-                        gc_log.clone(),
-                        gc_updated_entries.clone(),
-                    ),
-                    read_only_memtables,
-                    range_iterator: None,
-                    flush_signal_tx,
-                    flush_signal_rx,
-                    gc_log,
-                    gc_table,
-                    gc_updated_entries,
-                    flush_stream: HashSet::new(),
-                })
-            }
-            Err(err) => Err(MemTableRecovery(Box::new(err))),
+        let checkpoint = match has_meta && !clean_shutdown {
+            true => CheckpointRecord::load(&dir.meta, &meta, vlog.content.file.node.size().await).await,
+            false => None,
+        };
+        let recover_res = if clean_shutdown {
+            // everything was flushed at close, there is nothing to replay
+            log::info!("Store was closed cleanly, skipping value log replay");
+            Ok((
+                MemTable::with_specified_capacity_and_rate(
+                    size_unit,
+                    config.write_buffer_size,
+                    config.false_positive_rate,
+                ),
+                SkipMap::new(),
+            ))
+        } else {
+            DataStore::recover_memtable(
+                size_unit,
+                config.write_buffer_size,
+                config.false_positive_rate,
+                &dir.val_log,
+                vlog.head_offset,
+                checkpoint,
+                vlog_cipher,
+            )
+            .await
+        };
+        // memtable recovery truncates a torn record at the end of value log
+        vlog.size = vlog.content.file.node.size().await;
+        if let Some(key_id) = keyring.active() {
+            // records past the end may have been written with the keystream of the
+            // last epoch before they were truncated, appends never reuse it
+            buckets_map
+                .manifest
+                .start_vlog_epoch(vlog.size as u64, FileKey::new(key_id), vlog.tail_offset as u64)
+                .await?;
+            vlog.set_cipher(keyring.value_log(buckets_map.manifest.vlog_epochs())?)
+                .await?;
+        }
+        let (active_memtable, read_only_memtables) =
+            recover_res.map_err(|err| MemTableRecovery(Box::new(err)))?;
+        // keep versions issued after restart ahead of recovered entries
+        for table in read_only_memtables.iter() {
+            clock.observe(table.value().most_recent_entry.created_at);
+        }
+        if !active_memtable.entries.is_empty() {
+            clock.observe(active_memtable.most_recent_entry.created_at);
         }
+        let store = DataStore::assemble(StoreParts {
+            dir: dir.to_owned(),
+            vlog,
+            key_range,
+            buckets: buckets_map,
+            config,
+            meta,
+            user_meta,
+            active_memtable,
+            read_only_memtables,
+            clock,
+            orphans,
+        });
+        // dead records and writes per segment found before the restart
+        store
+            .compactor
+            .config
+            .dead_offsets
+            .load(
+                store.val_log.read().await.tail_offset,
+                store.val_log.read().await.size,
+            )
+            .await;
+        Ok(store)
+    }
+
+    /// Opens SSTable in `sst_dir` and recovers its summary, buckets use its
+    /// counts to pick tables for compaction
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case a file of the SSTable is missing or there is an IO error
+    async fn recover_table(sst_dir: PathBuf, cipher: Option<TableCipher>) -> Result<(Table, Summary), Error> {
+        let (id, files) = TableFiles::discover(&sst_dir).await?;
+        let mut summary = Summary::with_path(&files.summary);
+        let mut table = Table::build_from(id, sst_dir.to_owned(), files, cipher).await?;
+        summary.cipher = table.file_cipher(&FileType::Summary);
+        summary.recover().await?;
+        table.summary = Some(summary.to_owned());
+        Ok((table, summary))
     }
 
     /// Recovers memtable state
     ///
     /// Recovers both active and readonly memtable states using value log
     ///
+    /// Value log is replayed from `head_offset`, or from the offset `checkpoint`
+    /// covers after inserting its entries, and decrypted with `cipher`, if any
+    ///
     /// Returns a tuple of active memtable and read only memtables
-    pub async fn recover_memtable(
+    pub(crate) async fn recover_memtable(
         size_unit: SizeUnit,
         capacity: usize,
         false_positive_rate: f64,
         vlog_path: impl P,
         head_offset: usize,
+        checkpoint: Option<CheckpointRecord>,
+        cipher: Option<FileCipher>,
     ) -> Result<(MemTable<Key>, ImmutableMemTablesLockFree<Key>), Error> {
         let read_only_memtables: ImmutableMemTablesLockFree<Key> = SkipMap::new();
         let mut active_memtable =
             MemTable::with_specified_capacity_and_rate(size_unit, capacity, false_positive_rate);
         let mut vlog = ValueLog::new(vlog_path.as_ref()).await?;
-        let mut most_recent_offset = head_offset;
-        let entries = vlog.recover(head_offset).await?;
+        vlog.set_cipher(cipher).await?;
+        // the record at head is already in an SSTable, the checkpoint offset is past its last record
+        let (start_offset, skipped_offset) = match checkpoint.as_ref() {
+            Some(checkpoint) => (checkpoint.offset, None),
+            None => (head_offset, Some(head_offset)),
+        };
+        let mut most_recent_offset = start_offset;
+        let entries = vlog.recover(start_offset).await?;
+
+        // Entries of a batch are held back until its commit record is found
+        let mut pending_batch: Option<Vec<Entry<Key, usize>>> = None;
+        let mut insert_entry = |entry: Entry<Key, usize>| {
+            if !active_memtable.fits(entry.key.len()) {
+                // Make memtable read only
+                active_memtable.read_only = true;
+                let sealed = active_memtable.take();
+                read_only_memtables.insert(sealed.id, Arc::new(sealed));
+            }
+            active_memtable.insert(&entry);
+        };
+        for entry in checkpoint
+            .map(|checkpoint| checkpoint.entries)
+            .unwrap_or_default()
+        {
+            insert_entry(entry);
+        }
 
         for e in entries {
-            let entry = Entry::new(e.key.to_owned(), most_recent_offset, e.created_at, e.is_tombstone);
             // Since the most recent offset is the offset we start reading entries from in value log
             // and we retrieved this from the sstable, therefore should not re-write the initial entry in
             // memtable since it's already in the sstable
-            if most_recent_offset != head_offset {
-                if active_memtable.is_full(e.key.len()) {
-                    // Make memtable read only
-                    active_memtable.read_only = true;
-                    read_only_memtables.insert(
-                        MemTable::generate_table_id(),
-                        Arc::new(active_memtable.to_owned()),
-                    );
-                    active_memtable =
-                        MemTable::with_specified_capacity_and_rate(size_unit, capacity, false_positive_rate);
+            if Some(most_recent_offset) != skipped_offset {
+                match e.record_type {
+                    RecordType::Put | RecordType::Delete | RecordType::Append | RecordType::Expiring => {
+                        let entry =
+                            Entry::new(e.key.to_owned(), most_recent_offset, e.created_at, e.is_tombstone);
+                        match pending_batch.as_mut() {
+                            Some(batch) => batch.push(entry),
+                            None => insert_entry(entry),
+                        }
+                    }
+                    RecordType::ValueRef => {
+                        // the entry reads the value from the shared payload
+                        let payload = ValueDedup::decode_ref(&e.value);
+                        let entry = Entry::new(e.key.to_owned(), payload, e.created_at, false);
+                        match pending_batch.as_mut() {
+                            Some(batch) => batch.push(entry),
+                            None => insert_entry(entry),
+                        }
+                    }
+                    RecordType::BatchBegin => {
+                        if pending_batch.replace(Vec::new()).is_some() {
+                            log::warn!("Discarding uncommitted batch in value log");
+                        }
+                    }
+                    RecordType::BatchCommit => {
+                        for entry in pending_batch.take().unwrap_or_default() {
+                            insert_entry(entry);
+                        }
+                    }
+                    // head and tail are tracked by meta, they are not user entries, value
+                    // log recovery stops at a record of unknown type
+                    RecordType::Head | RecordType::Tail | RecordType::Hole | RecordType::Unknown(_) => {}
                 }
-                active_memtable.insert(&entry);
             }
-            most_recent_offset += SIZE_OF_U32   // Key Size(for fetching key length)
-                        +SIZE_OF_U32            // Value Length(for fetching value length)
-                        + SIZE_OF_U64           // Date Length
-                        + SIZE_OF_U8            // Tombstone marker
-                        + e.key.len()           // Key Length
-                        + e.value.len(); // Value Length
+            most_recent_offset += e.record_size();
+        }
+        if pending_batch.is_some() {
+            log::warn!("Discarding uncommitted batch in value log");
         }
 
         Ok((active_memtable, read_only_memtables))
@@ -287,10 +457,10 @@ impl DataStore<'static, Key> {
 
     /// Creates new [`DataStore`]
     /// Used in case there is no recovery needed
-    pub async fn handle_empty_vlog(
+    pub(crate) async fn handle_empty_vlog(
         params: CreateOrRecoverStoreParams<'_, impl P>,
     ) -> Result<DataStore<'static, Key>, Error> {
-        let (buckets_path, dir, mut vlog, key_range, config, size_unit, meta) = (
+        let (buckets_path, dir, mut vlog, key_range, config, size_unit, mut meta, user_meta) = (
             params.buckets_path,
             params.dir,
             params.vlog,
@@ -298,30 +468,48 @@ impl DataStore<'static, Key> {
             params.config,
             params.size_unit,
             params.meta,
+            params.user_meta,
         );
 
+        // checked before anything is written so a mismatch leaves the store untouched
+        let mut buckets = BucketMap::new(buckets_path).await?;
+        buckets.manifest.check_comparator(config.comparator).await?;
+        meta.comparator = Some(config.comparator.name().to_string());
+        // records of a new store end with a checksum, older stores keep writing them without
+        meta.features |= META_FEATURE_VLOG_CHECKSUM;
+        vlog.checksums = true;
+        DataStore::load_keyring(&mut buckets, &config, &mut meta)?;
+        if let Some(key_id) = buckets.keyring.active() {
+            buckets
+                .manifest
+                .start_vlog_epoch(0, FileKey::new(key_id), 0)
+                .await?;
+            vlog.set_cipher(buckets.keyring.value_log(buckets.manifest.vlog_epochs())?)
+                .await?;
+        }
         let mut active_memtable = MemTable::with_specified_capacity_and_rate(
             size_unit,
             config.write_buffer_size,
             config.false_positive_rate,
         );
+        let clock = ClockHandle::default();
         // if ValueLog is empty then we want to insert both tail and head
-        let created_at = Utc::now();
+        let created_at = clock.tick();
         let tail_offset = vlog
-            .append(
+            .append_record(
                 &TAIL_ENTRY_KEY.to_vec(),
                 &TAIL_ENTRY_VALUE.to_vec(),
                 created_at,
-                false,
+                RecordType::Tail,
             )
             .await?;
         let tail_entry = Entry::new(TAIL_ENTRY_KEY.to_vec(), tail_offset, created_at, false);
         let head_offset = vlog
-            .append(
+            .append_record(
                 &HEAD_ENTRY_KEY.to_vec(),
                 &HEAD_ENTRY_VALUE.to_vec(),
                 created_at,
-                false,
+                RecordType::Head,
             )
             .await?;
         let head_entry = Entry::new(HEAD_ENTRY_KEY.to_vec(), head_offset, created_at, false);
@@ -331,72 +519,188 @@ impl DataStore<'static, Key> {
         // insert tail and head to memtable
         active_memtable.insert(&tail_entry.to_owned());
         active_memtable.insert(&head_entry.to_owned());
-        let buckets = BucketMap::new(buckets_path).await?;
-        let (flush_signal_tx, flush_signal_rx) = broadcast(DEFAULT_FLUSH_SIGNAL_CHANNEL_SIZE);
-        let read_only_memtables = SkipMap::new();
-        let buckets = Arc::new(RwLock::new(buckets.to_owned()));
+        *buckets.tuning.write().unwrap() = BucketTuning::from(&config);
+        buckets
+            .persist_filter_bits
+            .store(config.persist_filter_bits, Ordering::Relaxed);
+        Ok(DataStore::assemble(StoreParts {
+            dir: dir.to_owned(),
+            vlog,
+            key_range,
+            buckets,
+            config,
+            meta,
+            user_meta,
+            active_memtable,
+            read_only_memtables: SkipMap::new(),
+            clock,
+            orphans: Vec::new(),
+        }))
+    }
+
+    /// Sets up the keyring of `buckets` with the keys of `config`
+    ///
+    /// The key recorded in the manifest stays active, a store that is not
+    /// encrypted yet is encrypted with the last key of `config` if it has any
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the store is encrypted with a key `config` does not have
+    fn load_keyring(buckets: &mut BucketMap, config: &Config, meta: &mut Meta) -> Result<(), Error> {
+        let keyring = Keyring::new(&config.encryption_keys);
+        let active = buckets
+            .manifest
+            .active_key()
+            .or_else(|| config.encryption_keys.last().map(|key| key.id()));
+        if let Some(key_id) = active {
+            keyring.set_active(key_id)?;
+            meta.features |= META_FEATURE_ENCRYPTION;
+        }
+        buckets.keyring = Arc::new(keyring);
+        Ok(())
+    }
+
+    /// Builds [`DataStore`] around state recovered or created on open
+    ///
+    /// Both open paths go through here so fields added to the store are set up once
+    fn assemble(parts: StoreParts) -> DataStore<'static, Key> {
+        let StoreParts {
+            dir,
+            vlog,
+            key_range,
+            buckets,
+            config,
+            meta,
+            user_meta,
+            active_memtable,
+            read_only_memtables,
+            clock,
+            orphans,
+        } = parts;
+        let bucket_tuning = buckets.tuning.clone();
+        let persist_filter_bits = buckets.persist_filter_bits.clone();
+        let (flush_signal_tx, flush_signal_rx) = watch::channel(FlushSignal::default());
+        let buckets = Arc::new(RwLock::new(buckets));
+        key_range.filter_cache.set_capacity(config.filter_memory_cap);
+        key_range
+            .filter_cache
+            .set_load_on_read(config.optimize_filters_for_hits);
         let key_range = Arc::new(key_range);
         let read_only_memtables = Arc::new(read_only_memtables);
-        let gc_table = Arc::new(RwLock::new(active_memtable.to_owned()));
+        // a clone of the active memtable would share its entries, GC inserts
+        // of earlier writes that land late would overwrite newer entries
+        let mut gc_table = MemTable::with_specified_capacity_and_rate(
+            active_memtable.size_unit(),
+            active_memtable.capacity(),
+            active_memtable.false_positive_rate(),
+        );
+        for e in active_memtable.entries.iter() {
+            let val = e.value();
+            gc_table.insert(&Entry::new(
+                e.key().to_vec(),
+                val.val_offset,
+                val.created_at,
+                val.is_tombstone,
+            ));
+        }
+        let gc_table = Arc::new(RwLock::new(gc_table));
         let gc_log = Arc::new(RwLock::new(vlog.to_owned()));
-        let flusher = Flusher::new(read_only_memtables.clone(), buckets.clone(), key_range.clone());
+        let slow_log = SlowLog::new(config.slow_op_threshold, config.slow_background_op_threshold);
+        let mut flusher = Flusher::new(read_only_memtables.clone(), buckets.clone(), key_range.clone());
+        flusher.slow_log = slow_log.clone();
         let gc_updated_entries = Arc::new(RwLock::new(SkipMap::new()));
-        Ok(DataStore {
+        let head_checkpoint = HeadCheckpoint::new(clock.now(), vlog.size);
+        let commit_watermark = CommitWatermark::new(Version::from(clock.latest()).sequence());
+        let mut compactor = Compactor::new(
+            config.enable_ttl,
+            TtlParams {
+                entry_ttl: config.entry_ttl,
+                tombstone_ttl: config.tombstone_ttl,
+            },
+            IntervalParams {
+                background_interval: config.background_compaction_interval,
+                flush_listener_interval: config.compactor_flush_listener_interval,
+                tombstone_compaction_interval: config.tombstone_compaction_interval,
+            },
+            config.compaction_strategy,
+            compactors::CompactionReason::MaxSize,
+            config.false_positive_rate,
+            clock.clone(),
+        );
+        compactor.config.slow_log = slow_log.clone();
+        let quotas = QuotaTracker::new(config.comparator, &config.prefix_quotas);
+        compactor.config.quotas = quotas.clone();
+        compactor.config.comparator = config.comparator;
+        compactor.config.verify_output = config.verify_compaction;
+        let row_cache = RowCache::new(config.row_cache_size);
+        compactor.config.row_cache = row_cache.clone();
+        compactor.config.dead_offsets = DeadOffsets::new(
+            vlog.liveness.clone(),
+            vlog.content.path.with_file_name(VLOG_LIVENESS_FILE_NAME),
+        );
+        let dead_offsets = compactor.config.dead_offsets.clone();
+        let val_log = Arc::new(RwLock::new(vlog));
+        let manual_background = compactor.config.manual_background.clone();
+        manual_background.store(config.manual_background_mode, Ordering::Relaxed);
+        flusher.write_amp = compactor.config.write_amp.clone();
+        let mut gc = GC::new(
+            config.online_gc_interval,
+            config.gc_chunk_size,
+            gc_table.clone(),
+            gc_log.clone(),
+            val_log.clone(),
+            gc_updated_entries.clone(),
+            clock.clone(),
+        );
+        gc.config.dead_offsets = dead_offsets;
+        gc.config.manual_background = manual_background.clone();
+        let dedup = gc.config.dedup.clone();
+        dedup.set_min_size(config.value_dedup_min_size);
+        DataStore {
             keyspace: DEFAULT_DB_NAME,
-            active_memtable,
-            val_log: vlog,
+            active_memtable: Arc::new(std::sync::RwLock::new(active_memtable)),
+            val_log,
+            write_gate: Default::default(),
             buckets,
-            dir: dir.clone(),
+            dir,
             key_range,
-            compactor: Compactor::new(
-                config.enable_ttl,
-                TtlParams {
-                    entry_ttl: config.entry_ttl,
-                    tombstone_ttl: config.tombstone_ttl,
-                },
-                IntervalParams {
-                    background_interval: config.background_compaction_interval,
-                    flush_listener_interval: config.compactor_flush_listener_interval,
-                    tombstone_compaction_interval: config.tombstone_compaction_interval,
-                },
-                config.compaction_strategy,
-                compactors::CompactionReason::MaxSize,
-                config.false_positive_rate,
-            ),
-            meta,
+            compactor,
+            meta: Arc::new(std::sync::Mutex::new(meta)),
+            user_meta,
             flusher,
             read_only_memtables,
-            range_iterator: None,
             flush_signal_tx,
             flush_signal_rx,
-            gc: GC::new(
-                config.online_gc_interval,
-                config.gc_chunk_size,
-                gc_table.clone(),
-                gc_log.clone(),
-                gc_updated_entries.clone(),
-            ),
+            gc,
             gc_log,
-            gc_table,
+            gc_table: std::sync::RwLock::new(gc_table),
             gc_updated_entries,
-            flush_stream: HashSet::new(),
+            health: HealthMonitor::new(
+                config.degraded_failure_threshold,
+                config.read_only_failure_threshold,
+            ),
+            row_cache,
+            head_checkpoint: Arc::new(Mutex::new(head_checkpoint)),
+            clock,
+            orphans: OrphanFiles::new(orphans, config.orphan_file_grace_period),
+            bucket_tuning,
+            persist_filter_bits,
+            manual_background,
+            write_buffer_manager: WriteBufferManager::new(config.total_write_buffer_size),
+            rotations: Default::default(),
+            memtable_age: MemTableAgeCheck::new(config.memtable_max_age),
+            sstable_probes: Default::default(),
+            sstable_lookups: Default::default(),
+            slow_log,
+            read_profiler: ReadProfiler::new(config.read_sample_every, config.read_profile_window),
+            scrubber: Scrubber::new(config.scrub_rate, config.scrub_action),
+            ttl_sweeper: TtlSweeper::from(&config),
+            commit_watermark,
+            dedup,
+            quotas,
+            interceptors: Default::default(),
+            io_latency: Default::default(),
             config,
-        })
-    }
-
-    fn get_bucket_id_from_full_bucket_path(full_path: impl P) -> String {
-        let full_path_as_str = full_path.as_ref().to_string_lossy().to_string();
-        let mut bucket_id = String::new();
-        // Find the last occurrence of "bucket" in the file path
-        if let Some(idx) = full_path_as_str.rfind("bucket") {
-            // Extract the substring starting from the index after the last occurrence of "bucket"
-            let uuid_part = &full_path_as_str[idx + "bucket".len()..];
-            if let Some(end_idx) = uuid_part.find('/') {
-                // Extract the UUID
-                let uuid = &uuid_part[..end_idx];
-                bucket_id = uuid.to_string();
-            }
         }
-        bucket_id
     }
 }
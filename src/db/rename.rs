@@ -0,0 +1,89 @@
+use super::{DataStore, WriteBatch, WriteReceipt};
+use crate::err::Error;
+use crate::types::{Key, ValOffset};
+use crate::vlog::ValueExpiry;
+use bytes::Bytes;
+
+impl DataStore<'static, Key> {
+    /// Moves the value of `old_key` to `new_key` atomically
+    ///
+    /// The tombstone of `old_key` and the entry of `new_key` are committed
+    /// as one [`DataStore::write_batch`] batch, so readers see either key
+    /// but never both or neither. The new entry points at the value already
+    /// in the value log instead of writing it again. If write interceptors
+    /// are set, the value is read back and written again so interceptors see
+    /// it. Values built by appends, values with a TTL, which keep it, and
+    /// values GC is checking are written again as well.
+    ///
+    /// A value previously stored under `new_key` is replaced. Returns receipt
+    /// of the batch, renaming a key to itself writes nothing and returns the
+    /// receipt of [`DataStore::unwritten_receipt`]
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.put("twitter", "elon musk").await.unwrap(); // handle error
+    ///     store.rename("twitter", "x").await.unwrap(); // handle error
+    ///     assert!(store.get("twitter").await.unwrap().is_none());
+    ///     assert_eq!(store.get("x").await.unwrap().unwrap().val, b"elon musk");
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFoundInDB`] without writing anything if `old_key`
+    /// is absent or deleted, or error if a key is invalid or an IO error occured
+    pub async fn rename<T: AsRef<[u8]>>(&self, old_key: T, new_key: T) -> Result<WriteReceipt, Error> {
+        self.validate_size(old_key.as_ref(), None::<T>)?;
+        self.validate_size(new_key.as_ref(), None::<T>)?;
+        if self.health.is_read_only() {
+            return Err(Error::StoreReadOnly);
+        }
+        let old = self.config.comparator.encode(old_key.as_ref()).into_owned();
+        let new = self.config.comparator.encode(new_key.as_ref()).into_owned();
+        let mut timer = self.slow_log.foreground("rename");
+        // the value is located before the batch moving it is written
+        let _gate = self.write_gate.write().await;
+
+        if !self.gc_updated_entries.read().await.is_empty() {
+            self.sync_gc_update_with_store().await?
+        }
+        self.key_range.update_key_range().await;
+        let payload = match self.locate(&old, &mut timer, false).await? {
+            Some(val) if !val.is_tombstone => val.val_offset,
+            _ => return Err(Error::NotFoundInDB),
+        };
+        timer.finish();
+        if old == new {
+            return Ok(self.unwritten_receipt().await);
+        }
+        let mut batch = WriteBatch::new();
+        batch.delete(old_key).value_ref(new_key, payload);
+        self.apply_batch(batch).await
+    }
+
+    /// Returns value stored at `offset` of the value log
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::NotFoundInDB`] if no value is stored there or it expired,
+    /// or error if an IO error occured
+    pub(crate) async fn payload_value(&self, offset: ValOffset) -> Result<Bytes, Error> {
+        self.io_latency.read().await;
+        match self.val_log.read().await.get_with_expiry(offset).await? {
+            (_, true, _) => Err(Error::NotFoundInDB),
+            (_, false, expires_at) if ValueExpiry::has_passed(expires_at, self.clock.now()) => {
+                Err(Error::NotFoundInDB)
+            }
+            (value, false, _) => Ok(value),
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use super::DataStore;
+use crate::{
+    bucket::BucketID,
+    compactors::CompactingTables,
+    consts::SCRUB_PASS_INTERVAL,
+    err::Error::{self, *},
+    fs::{FileAsync, FileNode, FileType},
+    sst::{Summary, Table, TableProperties},
+    types::{BucketMapHandle, Key, KeyRangeHandle},
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::fs;
+
+/// What the scrubber does with an SSTable whose data file fails its checksum
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScrubAction {
+    /// Logs the corruption, the SSTable keeps serving reads
+    #[default]
+    Report,
+
+    /// Removes the SSTable from the store and moves its directory under the
+    /// quarantine directory for inspection
+    Quarantine,
+}
+
+/// Outcome of a scrub pass returned by `DataStore::scrub`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ScrubReport {
+    /// Number of SSTables whose data file was checked
+    pub sstables_checked: usize,
+
+    /// Bytes of SSTable data files read
+    pub bytes_checked: usize,
+
+    /// Directories of SSTables that failed their checksum
+    pub corrupted: Vec<PathBuf>,
+
+    /// Directories of corrupted SSTables moved under the quarantine directory
+    pub quarantined: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+struct ScrubberInner {
+    /// Bytes read per second by background passes, 0 disables them
+    rate: usize,
+    action: ScrubAction,
+    corrupted: usize,
+}
+
+/// Verifies checksums of SSTable data files, coldest SSTables first since
+/// corruption of hot ones is the most likely to be noticed anyway
+#[derive(Clone, Debug)]
+pub(crate) struct Scrubber {
+    inner: Arc<Mutex<ScrubberInner>>,
+}
+
+impl Scrubber {
+    /// Creates new `Scrubber` reading `rate` bytes per second in the background
+    pub fn new(rate: usize, action: ScrubAction) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(ScrubberInner {
+                rate,
+                action,
+                corrupted: 0,
+            })),
+        }
+    }
+
+    /// Changes rate of background passes and what is done with corrupted SSTables
+    pub fn set(&self, rate: usize, action: ScrubAction) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.rate = rate;
+        inner.action = action;
+    }
+
+    /// Returns number of corrupted SSTables found since the store was opened
+    pub fn corrupted(&self) -> usize {
+        self.inner.lock().unwrap().corrupted
+    }
+
+    /// Returns rate of background passes and what is done with corrupted SSTables
+    pub fn settings(&self) -> (usize, ScrubAction) {
+        let inner = self.inner.lock().unwrap();
+        (inner.rate, inner.action)
+    }
+
+    /// Checks every live SSTable once
+    ///
+    /// SSTables being compacted and SSTables written before checksums were
+    /// recorded are skipped. Reads are spread out to the configured rate if
+    /// `throttle` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn run_pass(
+        &self,
+        buckets: &BucketMapHandle,
+        key_range: &KeyRangeHandle,
+        compacting: &CompactingTables,
+        quarantine_dir: &Path,
+        throttle: bool,
+    ) -> Result<ScrubReport, Error> {
+        let mut tables: Vec<(BucketID, Table)> = Vec::new();
+        for (bucket_id, bucket) in buckets.read().await.buckets.iter() {
+            for table in bucket.sstables.read().await.iter() {
+                tables.push((*bucket_id, table.to_owned()));
+            }
+        }
+        tables.sort_by_key(|(_, table)| table.get_hotness());
+
+        let mut report = ScrubReport::default();
+        for (bucket_id, table) in tables {
+            if compacting.contains(table.id()) {
+                continue;
+            }
+            let Some((intact, bytes)) = Self::verify(&table).await? else {
+                continue;
+            };
+            report.sstables_checked += 1;
+            report.bytes_checked += bytes;
+            let (rate, action) = self.settings();
+            if !intact {
+                log::error!("{}", SSTableChecksumMismatch(table.dir.to_owned()));
+                self.inner.lock().unwrap().corrupted += 1;
+                report.corrupted.push(table.dir.to_owned());
+                if action == ScrubAction::Quarantine
+                    && Self::quarantine(buckets, key_range, compacting, quarantine_dir, bucket_id, &table)
+                        .await?
+                {
+                    report.quarantined.push(table.dir.to_owned());
+                }
+            }
+            if throttle && rate > 0 {
+                tokio::time::sleep(Duration::from_secs_f64(bytes as f64 / rate as f64)).await;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Returns whether data file of `table` matches its checksum along with
+    /// bytes read, `None` if there is nothing to check
+    async fn verify(table: &Table) -> Result<Option<(bool, usize)>, Error> {
+        let properties = match table.properties() {
+            Some(properties) => properties.to_owned(),
+            None => {
+                let mut summary = Summary::with_path(&table.files.summary);
+                summary.cipher = table.file_cipher(&FileType::Summary);
+                match summary.recover().await {
+                    Ok(_) => summary.properties,
+                    // compacted away since the pass started
+                    Err(_) if fs::metadata(&table.dir).await.is_err() => return Ok(None),
+                    Err(err) => return Err(err),
+                }
+            }
+        };
+        let TableProperties {
+            data_checksum: Some(checksum),
+            data_size,
+            ..
+        } = properties
+        else {
+            return Ok(None);
+        };
+        let mut data = match fs::read(&table.data_file.path).await {
+            Ok(data) => data,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(FileRead {
+                    path: table.data_file.path.to_owned(),
+                    error,
+                })
+            }
+        };
+        if let Some(cipher) = table.file_cipher(&FileType::Data) {
+            cipher.apply(0, &mut data);
+        }
+        let intact = data.len() == data_size && crc32fast::hash(&data) == checksum;
+        Ok(Some((intact, data.len())))
+    }
+
+    /// Removes `table` from the store and moves it under `quarantine_dir`
+    ///
+    /// Returns `false` if the table is no longer live or is being compacted
+    async fn quarantine(
+        buckets: &BucketMapHandle,
+        key_range: &KeyRangeHandle,
+        compacting: &CompactingTables,
+        quarantine_dir: &Path,
+        bucket_id: BucketID,
+        table: &Table,
+    ) -> Result<bool, Error> {
+        let mut bucket_map = buckets.write().await;
+        let is_live = match bucket_map.buckets.get(&bucket_id) {
+            Some(bucket) => bucket.sstables.read().await.iter().any(|s| s.id() == table.id()),
+            None => false,
+        };
+        if !is_live || compacting.contains(table.id()) {
+            return Ok(false);
+        }
+        // drop the table from the manifest first so a crash midway never loads it again
+        bucket_map.publish(&[], &[table.dir.to_owned()]).await?;
+        key_range.remove(table.id()).await;
+
+        FileNode::create_dir_all(quarantine_dir).await?;
+        let name = table.dir.file_name().unwrap_or_default().to_string_lossy();
+        let target = quarantine_dir.join(format!("{}_{}", bucket_id, name));
+        // the directory cannot be renamed on Windows while its files are open
+        table.data_file.file.node.close().await;
+        table.index_file.file.node.close().await;
+        fs::rename(&table.dir, &target)
+            .await
+            .map_err(|error| FileRename { path: target, error })?;
+        bucket_map
+            .delete_ssts(&vec![(bucket_id, vec![table.to_owned()])])
+            .await?;
+        Ok(true)
+    }
+
+    /// Runs a throttled pass every `SCRUB_PASS_INTERVAL` while a rate is set
+    pub fn spawn_scrubber(
+        &self,
+        buckets: BucketMapHandle,
+        key_range: KeyRangeHandle,
+        compacting: CompactingTables,
+        manual_background: Arc<AtomicBool>,
+        quarantine_dir: PathBuf,
+    ) {
+        let scrubber = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let (rate, _) = scrubber.settings();
+                if rate > 0 && !manual_background.load(Ordering::Relaxed) {
+                    let res = scrubber
+                        .run_pass(&buckets, &key_range, &compacting, &quarantine_dir, true)
+                        .await;
+                    if let Err(err) = res {
+                        log::error!("{}", err);
+                    }
+                }
+                tokio::time::sleep(SCRUB_PASS_INTERVAL).await;
+            }
+        });
+    }
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Verifies checksums of every SSTable data file right away
+    ///
+    /// Unlike background passes, reads are not throttled. Corrupted SSTables
+    /// are reported or quarantined as set with `with_scrubber`, SSTables
+    /// written before checksums were recorded are skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn scrub(&self) -> Result<ScrubReport, Error> {
+        self.scrubber
+            .run_pass(
+                &self.buckets,
+                &self.key_range,
+                &self.compactor.config.compacting,
+                &self.dir.quarantine,
+                false,
+            )
+            .await
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{
+    clock::ClockHandle,
+    consts::{HEAD_ENTRY_KEY, MEMTABLE_AGE_CHECK_IDLE_INTERVAL},
+    flush::Flusher,
+    health::HealthMonitor,
+    memtable::{Entry, MemTable, RotationTracker},
+    types::{ActiveMemTable, FlushSender, ImmutableMemTables, Key, MetaHandle},
+    vlog::ValueLog,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tokio::sync::{Notify, RwLock};
+
+/// Moves the active memtable to read-only memtables and flushes them
+///
+/// Holds everything sealing touches besides the active memtable, so the
+/// memtable age check can seal the memtable of a store nobody is using
+#[derive(Clone)]
+pub(crate) struct MemTableSealer {
+    pub(crate) read_only_memtables: ImmutableMemTables<Key>,
+    pub(crate) rotations: Arc<Mutex<RotationTracker>>,
+    pub(crate) meta: MetaHandle,
+    pub(crate) gc_log: Arc<RwLock<ValueLog>>,
+    pub(crate) clock: ClockHandle,
+    pub(crate) flusher: Flusher,
+    pub(crate) flush_signal_tx: FlushSender,
+    pub(crate) health: HealthMonitor,
+    pub(crate) manual_background: Arc<AtomicBool>,
+}
+
+impl MemTableSealer {
+    /// Moves `active` to read-only memtables, the memtable replacing it gets
+    /// `capacity`. `forced` if the write buffer budget seals it before it is full
+    ///
+    /// Value log head is moved to the most recent entry of the sealed memtable
+    /// and persisted in meta, the new head is returned
+    ///
+    /// `active` has to stay locked until this returns, readers see entries of
+    /// the sealed memtable either in the active memtable or in the read-only set
+    pub(crate) fn seal(&self, active: &mut MemTable<Key>, capacity: usize, forced: bool) -> usize {
+        self.rotations.lock().unwrap().record(active.size, forced);
+        let meta = {
+            let mut meta = self.meta.lock().unwrap();
+            // entries sharing a value point back at an earlier payload, the head never moves back
+            let head_offset = active.get_most_recent_offset().max(meta.v_log_head);
+            meta.set_head(head_offset);
+            meta.update_last_modified();
+            meta.to_owned()
+        };
+        let head_offset = meta.v_log_head;
+
+        let gc_log = Arc::clone(&self.gc_log);
+        tokio::spawn(async move {
+            (gc_log.write().await).head_offset = head_offset;
+        });
+        let is_tombstone = false;
+        let head_entry = Entry::new(
+            HEAD_ENTRY_KEY.to_vec(),
+            head_offset,
+            self.clock.tick(),
+            is_tombstone,
+        );
+        // Sealed table is published with a single insert after the active
+        // memtable has been swapped, readers see its entries either in the
+        // active memtable or in the read-only set, never both or neither
+        let sealed = active.seal(&head_entry, capacity);
+        Self::write_meta_background(meta);
+
+        self.read_only_memtables.insert(sealed.id, sealed);
+        head_offset
+    }
+
+    /// Flushes read-only memtables to disk using background tokio tasks
+    ///
+    /// Memtables are left for [`crate::db::DataStore::tick_flush`] in manual background mode
+    pub(crate) fn flush_read_only(&self) {
+        if self.manual_background.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut flusher = self.flusher.clone();
+        for table in self.read_only_memtables.iter() {
+            let key = table.key().to_owned();
+            let value = table.value().to_owned();
+            let tx = self.flush_signal_tx.clone();
+            let health = self.health.clone();
+            // NOTE: If the put method returns before the flush task finishes executing,
+            // the task will continue to run independently of the original function call.
+            // Memtables already being flushed are skipped by the flusher.
+            // TODO: See if we can introduce semaphors to prevent overloading the system
+            flusher.flush_handler(key, value, tx, health);
+        }
+    }
+
+    /// Writes `meta` to disk in background
+    pub(crate) fn write_meta_background(mut meta: crate::meta::Meta) {
+        tokio::spawn(async move {
+            if let Err(err) = meta.write().await {
+                log::error!("{}", err)
+            }
+        });
+    }
+}
+
+/// Seals and flushes the active memtable once it holds entries older than
+/// `memtable_max_age`, even if the store takes no writes
+#[derive(Clone, Debug)]
+pub(crate) struct MemTableAgeCheck {
+    max_age: Arc<Mutex<Duration>>,
+
+    /// Wakes the check up once `max_age` changes
+    changed: Arc<Notify>,
+}
+
+impl MemTableAgeCheck {
+    /// Creates new `MemTableAgeCheck` sealing memtables older than `max_age`, zero disables it
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age: Arc::new(Mutex::new(max_age)),
+            changed: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Changes the age memtables are sealed at
+    pub fn set(&self, max_age: Duration) {
+        *self.max_age.lock().unwrap() = max_age;
+        self.changed.notify_one();
+    }
+
+    /// Returns the age memtables are sealed at, zero if the check is off
+    pub fn max_age(&self) -> Duration {
+        *self.max_age.lock().unwrap()
+    }
+
+    /// Checks the age of `active` four times per `max_age`
+    ///
+    /// Memtables are left for [`crate::db::DataStore::tick_memtable_age`] in
+    /// manual background mode. The task stops once the store is dropped
+    pub fn spawn_age_check(&self, active: &ActiveMemTable<Key>, sealer: MemTableSealer) {
+        let check = self.clone();
+        let active = Arc::downgrade(active);
+        tokio::spawn(async move {
+            loop {
+                let max_age = check.max_age();
+                // the setting is checked again hourly while the check is off
+                let wait = if max_age.is_zero() {
+                    MEMTABLE_AGE_CHECK_IDLE_INTERVAL
+                } else {
+                    max_age / 4
+                };
+                let _ = tokio::time::timeout(wait, check.changed.notified()).await;
+                let Some(active) = active.upgrade() else {
+                    return;
+                };
+                let max_age = check.max_age();
+                if max_age.is_zero() || sealer.manual_background.load(Ordering::Relaxed) {
+                    continue;
+                }
+                {
+                    let mut memtable = active.write().unwrap();
+                    if memtable.entries.is_empty() || memtable.age() < max_age {
+                        continue;
+                    }
+                    // the store passes a new write buffer size on its next rotation
+                    let capacity = memtable.capacity();
+                    sealer.seal(&mut memtable, capacity, false);
+                }
+                sealer.flush_read_only();
+            }
+        });
+    }
+}
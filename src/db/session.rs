@@ -0,0 +1,153 @@
+use super::{DataStore, Db, WriteBatch, WriteReceipt};
+use crate::consts::DEFAULT_SESSION_READ_TIMEOUT;
+use crate::err::Error;
+use crate::memtable::{UserEntry, UserEntryRef};
+use crate::types::{Key, SeqNumber};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLockReadGuard;
+
+/// Handle whose reads observe its own writes
+///
+/// Tracks the sequence number of the latest write made through it and only
+/// reads once the store has applied at least that sequence. Writes are in a
+/// memtable or a published SSTable once their sequence number is returned,
+/// and a flush publishes its SSTable before the memtable is dropped, so reads
+/// never miss a write of the session while it is being flushed
+///
+/// Clones share the tracked sequence. A session can also be made to read
+/// writes of another one by passing the sequence number along, see
+/// [`Session::observe`]. Reads wait for the store to apply observed writes
+/// at most [`Session::with_read_timeout`], 10 seconds by default
+///
+/// # Examples
+/// ```
+/// # use tempfile::tempdir;
+/// use velarixdb::db::Db;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let root = tempdir().unwrap();
+///     let path = root.path().join("velarixdb");
+///     let db = Db::open("big_tech", path).await.unwrap(); // handle IO error
+///
+///     let session = db.session();
+///     let receipt = session.put("apple", "tim cook").await.unwrap(); // handle error
+///     assert_eq!(session.sequence(), receipt.seq);
+///
+///     let entry = session.get("apple").await.unwrap(); // handle error
+///     assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "tim cook");
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Session {
+    db: Db,
+    seq: Arc<AtomicU64>,
+
+    /// How long a read waits for the store to apply observed writes
+    read_timeout: Duration,
+}
+
+impl Session {
+    /// Creates new `Session` over `db` that has not written anything yet
+    pub(crate) fn new(db: Db) -> Self {
+        Self {
+            db,
+            seq: Default::default(),
+            read_timeout: DEFAULT_SESSION_READ_TIMEOUT,
+        }
+    }
+
+    /// Sets how long reads wait for the store to apply writes the session
+    /// observed before they fail
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Returns sequence number every read of the session observes
+    pub fn sequence(&self) -> SeqNumber {
+        self.seq.load(Ordering::Acquire)
+    }
+
+    /// Makes later reads observe writes up to `seq`, e.g. returned by a write
+    /// of another session on the same store
+    pub fn observe(&self, seq: SeqNumber) {
+        self.seq.fetch_max(seq, Ordering::AcqRel);
+    }
+
+    /// Records sequence number of the write behind a receipt returned to the session
+    fn record_receipt(&self, res: Result<WriteReceipt, Error>) -> Result<WriteReceipt, Error> {
+        if let Ok(receipt) = &res {
+            self.observe(receipt.seq);
+        }
+        res
+    }
+
+    /// Returns the store once it applied every write observed by the session
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::SequenceWaitTimedOut`] if the store did not apply
+    /// them within the read timeout
+    async fn read_store(&self) -> Result<RwLockReadGuard<'_, DataStore<'static, Key>>, Error> {
+        self.db.wait_for_applied(self.sequence(), self.read_timeout).await
+    }
+
+    /// Inserts a new entry into the store, see [`DataStore::put`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the store is read-only
+    pub async fn put(&self, key: impl AsRef<[u8]>, val: impl AsRef<[u8]>) -> Result<WriteReceipt, Error> {
+        self.record_receipt(self.db.put(key, val).await)
+    }
+
+    /// Updates an existing entry, see [`DataStore::update`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the key was not found
+    pub async fn update(&self, key: impl AsRef<[u8]>, val: impl AsRef<[u8]>) -> Result<WriteReceipt, Error> {
+        self.record_receipt(self.db.update(key, val).await)
+    }
+
+    /// Deletes an entry, see [`DataStore::delete`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured
+    pub async fn delete<T: AsRef<[u8]>>(&self, key: T) -> Result<WriteReceipt, Error> {
+        self.record_receipt(self.db.delete(key).await)
+    }
+
+    /// Applies every operation of `batch` atomically, see [`DataStore::write_batch`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured, an entry is invalid or the store is read-only
+    pub async fn write_batch(&self, batch: WriteBatch) -> Result<WriteReceipt, Error> {
+        self.record_receipt(self.db.write_batch(batch).await)
+    }
+
+    /// Retrieves an entry, see [`DataStore::get`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the store did not apply the
+    /// writes observed by the session within the read timeout
+    pub async fn get<T: AsRef<[u8]>>(&self, key: T) -> Result<Option<UserEntry>, Error> {
+        self.read_store().await?.get(key).await
+    }
+
+    /// Retrieves an entry without copying its value, see [`DataStore::get_ref`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the store did not apply the
+    /// writes observed by the session within the read timeout
+    pub async fn get_ref<T: AsRef<[u8]>>(&self, key: T) -> Result<Option<UserEntryRef>, Error> {
+        self.read_store().await?.get_ref(key).await
+    }
+}
@@ -0,0 +1,112 @@
+use super::DataStore;
+use crate::{
+    compactors::{BucketWriteStats, CompactionStatus},
+    memtable::WriteBufferManager,
+    types::Key,
+};
+use std::time::Duration;
+
+/// Snapshot of store statistics
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// Bytes held by bloom filters of live SSTables
+    pub filter_memory: usize,
+
+    /// Number of live SSTables whose bloom filter was evicted to
+    /// stay under the filter memory cap, lookups on them use the index only
+    pub evicted_filters: usize,
+
+    /// Bytes held by the active memtable and read-only memtables yet to be flushed
+    pub write_buffer_memory: usize,
+
+    /// Capacity in bytes of the active memtable
+    pub memtable_capacity: usize,
+
+    /// Bytes taken by entries of the active memtable
+    pub memtable_size: usize,
+
+    /// Capacity in bytes of memtables from the next rotation on, see
+    /// [`DataStore::set_write_buffer_size`]
+    pub next_memtable_capacity: usize,
+
+    /// Number of memtables sealed since open, by filling up, by flushes or
+    /// by the total write buffer budget
+    pub memtable_rotations: usize,
+
+    /// Number of memtables sealed early since open because memtables
+    /// together went over the total write buffer budget
+    pub forced_memtable_rotations: usize,
+
+    /// Average time memtables took writes before being sealed
+    pub avg_memtable_fill_time: Duration,
+
+    /// Average bytes of entries held by memtables when sealed
+    pub avg_rotation_bytes: usize,
+
+    /// Number of SSTables probed by gets, SSTables that can only hold
+    /// entries older than one already found are skipped
+    pub sstable_probes: usize,
+
+    /// Number of value log records compaction dropped from SSTables that
+    /// GC has not freed yet
+    pub dead_vlog_entries: usize,
+
+    /// Estimated bytes of the dead value log records, sizes are averaged over
+    /// records written to the same 1MB segment of value log
+    ///
+    /// Dead records and segment sizes are persisted next to value log, the
+    /// estimate survives reopen. GC collects the segment with the most of
+    /// these bytes first
+    pub reclaimable_vlog_bytes: usize,
+
+    /// Number of SSTables found failing their checksum by the scrubber
+    pub corrupted_sstables: usize,
+
+    /// Bytes of values not written to the value log since open because an
+    /// identical value was shared instead
+    pub dedup_saved_bytes: usize,
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Returns statistics of the store
+    pub fn stats(&self) -> Stats {
+        let active = self.active_memtable.read().unwrap();
+        let rotations = self.rotations.lock().unwrap();
+        Stats {
+            filter_memory: self.key_range.filter_cache.memory_usage(),
+            evicted_filters: self.key_range.filter_cache.evicted(),
+            sstable_probes: self.sstable_probes.load(std::sync::atomic::Ordering::Relaxed),
+            dead_vlog_entries: self.compactor.config.dead_offsets.len(),
+            reclaimable_vlog_bytes: self.compactor.config.dead_offsets.reclaimable_bytes(),
+            corrupted_sstables: self.scrubber.corrupted(),
+            dedup_saved_bytes: self.dedup.saved_bytes(),
+            write_buffer_memory: WriteBufferManager::memory_usage(&active, &self.read_only_memtables),
+            memtable_capacity: active.size_unit().as_bytes(active.capacity()),
+            memtable_size: active.size,
+            next_memtable_capacity: self.config.write_buffer_size,
+            memtable_rotations: rotations.rotations(),
+            forced_memtable_rotations: rotations.forced(),
+            avg_memtable_fill_time: rotations.avg_fill_time(),
+            avg_rotation_bytes: rotations.avg_bytes(),
+        }
+    }
+
+    /// Returns whether compaction is idle, running or failed along with
+    /// figures of the last run, manual and background runs alike
+    pub fn compaction_status(&self) -> CompactionStatus {
+        self.compactor.config.progress.snapshot()
+    }
+
+    /// Returns bytes written to SSTables by flushes and compactions per byte
+    /// flushed since the store was opened, 1.0 until anything is flushed
+    pub fn write_amplification(&self) -> f64 {
+        self.compactor.config.write_amp.write_amplification()
+    }
+
+    /// Returns bytes read, bytes written and merges of every bucket
+    /// compacted since the store was opened
+    pub fn bucket_write_stats(&self) -> Vec<BucketWriteStats> {
+        self.compactor.config.write_amp.buckets()
+    }
+}
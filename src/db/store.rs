@@ -1,30 +1,54 @@
+use super::WriteReceipt;
+use crate::bench::IoLatency;
+use crate::bucket::BucketTuning;
+use crate::cache::RowCache;
 use crate::cfg::Config;
-use crate::compactors::{CompactionReason, Compactor};
+use crate::clock::{ClockHandle, Version};
+use crate::compactors::{CompState, CompactionReason, CompactionStatus, Compactor};
+use crate::comparator::Comparator;
 use crate::consts::{
-    BUCKETS_DIRECTORY_NAME, HEAD_ENTRY_KEY, HEAD_KEY_SIZE, KB, MAX_KEY_SIZE, MAX_VALUE_SIZE,
-    META_DIRECTORY_NAME, TOMB_STONE_MARKER, VALUE_LOG_DIRECTORY_NAME, VLOG_START_OFFSET,
+    BUCKETS_DIRECTORY_NAME, CLOSE_FLUSH_POLL_INTERVAL, HEAD_KEY_SIZE, HOTNESS_SAMPLE_EVERY, KB, MAX_KEY_SIZE,
+    MAX_VALUE_SIZE, META_DIRECTORY_NAME, QUARANTINE_DIRECTORY_NAME, TOMB_STONE_MARKER,
+    VALUE_LOG_DIRECTORY_NAME, VLOG_START_OFFSET,
 };
+use crate::db::checkpoint::HeadCheckpoint;
+use crate::db::commit::CommitWatermark;
+use crate::db::intercept::{InterceptedWrite, WriteInterceptors};
 use crate::db::keyspace::is_valid_keyspace_name;
-use crate::flush::Flusher;
-use crate::fs::P;
-use crate::gc::garbage_collector::GC;
-use crate::index::Index;
+use crate::db::orphans::OrphanFiles;
+use crate::db::read_profile::ReadProfiler;
+use crate::db::scrub::Scrubber;
+use crate::db::seal::{MemTableAgeCheck, MemTableSealer};
+use crate::db::ttl_sweep::TtlSweeper;
+use crate::encryption::EncryptionKey;
+use crate::flush::{FlushReport, Flusher};
+use crate::fs::{FileAsync, FileNode, P};
+use crate::gc::garbage_collector::{GCTable, GC};
+use crate::health::{Health, HealthMonitor};
 use crate::key_range::KeyRange;
-use crate::memtable::{Entry, MemTable, UserEntry, K};
-use crate::meta::Meta;
-use crate::range::RangeIterator;
+use crate::memtable::{
+    Entry, MemTable, MemtableId, RotationTracker, SkipMapValue, UserEntry, UserEntryRef, WriteBufferManager,
+    K,
+};
+use crate::meta::{Meta, UserMeta};
+use crate::quota::QuotaTracker;
+use crate::slow_log::{OpTimer, Phase, SlowLog};
 use crate::sst::Table;
 use crate::types::{
-    Bool, BucketMapHandle, CreatedAt, FlushSignal, GCUpdatedEntries, ImmutableMemTables, Key, KeyRangeHandle,
-    MemtableFlushStream,
+    ActiveMemTable, BucketMapHandle, CreatedAt, FlushReceiver, FlushSender, GCUpdatedEntries,
+    ImmutableMemTables, Key, KeyRangeHandle, MetaHandle, SeqNumber, ValOffset,
 };
 use crate::util;
-use crate::vlog::ValueLog;
-use chrono::Utc;
+use crate::vlog::{RecordType, ValueDedup, ValueExpiry, ValueLog};
+use std::borrow::Cow;
+use std::future::Future;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::fs::{self};
 use tokio::sync::{Mutex, RwLock};
+use tokio::time::sleep;
 
 use super::recovery::CreateOrRecoverStoreParams;
 
@@ -40,11 +64,22 @@ where
     /// Directory to be used by store
     pub(crate) dir: DirPath,
 
-    /// Active memtable that accepts reads and writes using a lock free skipmap
-    pub(crate) active_memtable: MemTable<Key>,
+    /// Active memtable that accepts reads and writes using a lock free skipmap,
+    /// shared with the memtable age check
+    pub(crate) active_memtable: ActiveMemTable<Key>,
 
     /// Value log to persist entries and for crash recovery
-    pub(crate) val_log: ValueLog,
+    ///
+    /// Writers hold it from the append until their entries are in the active
+    /// memtable, memtables receive entries in the order value log does. GC
+    /// holds it while it appends moved entries
+    pub(crate) val_log: Arc<RwLock<ValueLog>>,
+
+    /// Held shared by writes and exclusively by writes that read stored
+    /// entries before they append, appends, renames, increments and batches
+    /// with merges or range deletes, so no other write lands in between.
+    /// Reads, flushes, compaction and GC do not take it
+    pub(crate) write_gate: RwLock<()>,
 
     /// Bucket Map that groups sstables by size
     pub(crate) buckets: BucketMapHandle,
@@ -55,8 +90,11 @@ where
     /// Handles compaction of sstables
     pub(crate) compactor: Compactor,
 
-    /// Keeps track of store metadata
-    pub(crate) meta: Meta,
+    /// Keeps track of store metadata, shared with the memtable age check
+    pub(crate) meta: MetaHandle,
+
+    /// Metadata set by users
+    pub(crate) user_meta: UserMeta,
 
     /// Handles flushing of memtables to disk
     pub(crate) flusher: Flusher,
@@ -67,17 +105,14 @@ where
     /// Garbage Collector to remove osbolete entries from disk
     pub(crate) gc: GC,
 
-    /// Handles range queries
-    pub(crate) range_iterator: Option<RangeIterator<'a>>,
-
     /// Stores read only memtables yet to be flushed
     pub(crate) read_only_memtables: ImmutableMemTables<Key>,
 
-    /// Sends singnal to subscribers whenever a flush happens
-    pub(crate) flush_signal_tx: async_broadcast::Sender<FlushSignal>,
+    /// Bumps the flush generation whenever a flush happens
+    pub(crate) flush_signal_tx: FlushSender,
 
-    /// Flush listeners receiver
-    pub(crate) flush_signal_rx: async_broadcast::Receiver<FlushSignal>,
+    /// Flush listeners receiver, tracks the last flush generation seen
+    pub(crate) flush_signal_rx: FlushReceiver,
 
     /// Stores valid entries gotten from garbage collection but yet to be synced with
     /// memtable
@@ -85,13 +120,76 @@ where
 
     /// GC Table is synced with active memtable in case GC is triggered, we don't need to  use main
     /// active memtable as this can impact performance
-    pub(crate) gc_table: Arc<RwLock<MemTable<Key>>>,
+    ///
+    /// NOTE: std lock, it only guards swapping the table when the active memtable is sealed
+    pub(crate) gc_table: std::sync::RwLock<GCTable>,
 
     /// GC Log is similar to value log but with lock
     pub(crate) gc_log: Arc<RwLock<ValueLog>>,
 
-    /// keeps track of memtable going through flush
-    pub(crate) flush_stream: MemtableFlushStream,
+    /// Tracks background task failures and switches store to read-only if they persist
+    pub(crate) health: HealthMonitor,
+
+    /// Caches recently read values by key, invalidated on writes
+    pub(crate) row_cache: RowCache,
+
+    /// Tracks the last value log head checkpoint, held while a checkpoint is written
+    pub(crate) head_checkpoint: Arc<Mutex<HeadCheckpoint>>,
+
+    /// Source of entry timestamps, shared with background tasks
+    pub(crate) clock: ClockHandle,
+
+    pub(crate) orphans: OrphanFiles,
+
+    pub(crate) bucket_tuning: Arc<std::sync::RwLock<BucketTuning>>,
+
+    /// Whether filter bits are written with new SSTables, shared with the bucket map
+    pub(crate) persist_filter_bits: Arc<AtomicBool>,
+
+    /// Whether background flushes, compactions and GC only run when ticked, shared with compactor and GC
+    pub(crate) manual_background: Arc<AtomicBool>,
+
+    /// Flushes memtables early once they hold more than the total write buffer budget
+    pub(crate) write_buffer_manager: WriteBufferManager,
+
+    /// Rotations of the active memtable since open
+    pub(crate) rotations: Arc<std::sync::Mutex<RotationTracker>>,
+
+    /// Seals the active memtable once it outlives `memtable_max_age`, even without writes
+    pub(crate) memtable_age: MemTableAgeCheck,
+
+    /// Number of SSTables probed by gets
+    pub(crate) sstable_probes: AtomicUsize,
+
+    /// Number of gets that searched SSTables, used to sample read hits
+    pub(crate) sstable_lookups: AtomicUsize,
+
+    /// Thresholds for logging slow operations, shared with flusher and compactor
+    pub(crate) slow_log: SlowLog,
+
+    /// Samples gets to profile read traffic
+    pub(crate) read_profiler: ReadProfiler,
+
+    /// Verifies SSTable checksums in the background
+    pub(crate) scrubber: Scrubber,
+
+    /// Drops and compacts expired SSTables in the background
+    pub(crate) ttl_sweeper: TtlSweeper,
+
+    /// Sequence number up to which writes are synced to disk
+    pub(crate) commit_watermark: CommitWatermark,
+
+    /// Payloads shared by keys holding identical values, shared with GC
+    pub(crate) dedup: ValueDedup,
+
+    /// Caps and usage of key prefixes, shared with the compactor
+    pub(crate) quotas: QuotaTracker,
+
+    /// Hooks inspecting puts and deletes before they reach the value log
+    pub(crate) interceptors: WriteInterceptors,
+
+    /// Artificial latency added to foreground IO by benchmarks
+    pub(crate) io_latency: IoLatency,
     // TODO: pub block_cache: BlockCache
 }
 
@@ -101,6 +199,7 @@ pub struct DirPath {
     pub val_log: PathBuf,
     pub buckets: PathBuf,
     pub meta: PathBuf,
+    pub quarantine: PathBuf,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -111,6 +210,40 @@ pub enum SizeUnit {
     Gigabytes,
 }
 
+/// Whether a key has an entry, as returned by [`DataStore::contains_key`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyStatus {
+    /// Newest entry of the key holds a value
+    Present,
+
+    /// Newest entry of the key holds a value that may have a TTL of its own
+    /// which passed, [`DataStore::get`] tells whether it is still live
+    ///
+    /// Reported instead of `Present` once the store wrote a value with
+    /// [`WriteBatch::put_with_ttl`], entries do not record whether their
+    /// value has a TTL
+    MaybeExpired,
+
+    /// Newest entry of the key is a tombstone not yet dropped by compaction
+    Tombstoned,
+
+    /// Key has no entry
+    Absent,
+}
+
+/// What a write reads of the entry it replaces, see [`DataStore::write_entry_reading_old`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ReadOld {
+    /// Entry is not read
+    Skip,
+
+    /// Entry is returned along with the receipt of the write
+    Return,
+
+    /// Write fails without writing anything if the key is absent or deleted
+    RequireLive,
+}
+
 impl SizeUnit {
     pub(crate) const fn as_bytes(&self, value: usize) -> usize {
         match self {
@@ -148,6 +281,65 @@ impl DataStore<'static, Key> {
         Ok(store)
     }
 
+    /// Same as [`DataStore::open`], but keys are ordered with `comparator`
+    ///
+    /// The comparator is recorded when the keyspace is created, it has to be
+    /// passed on every later open
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the keyspace was created
+    /// with another comparator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keyspace name is invalid.
+    pub async fn open_with_comparator(
+        keyspace: &'static str,
+        dir: impl P,
+        comparator: Comparator,
+    ) -> Result<DataStore<'static, Key>, crate::err::Error> {
+        assert!(is_valid_keyspace_name(keyspace));
+        let config = Config {
+            comparator,
+            ..Config::default()
+        };
+        let mut store = Self::create_or_recover(DirPath::build(dir), SizeUnit::Bytes, config).await?;
+        store.keyspace = keyspace;
+        store.start_background_tasks();
+        Ok(store)
+    }
+
+    /// Same as [`DataStore::open`], but files are encrypted with `keys`
+    ///
+    /// The last key encrypts a new keyspace, a keyspace that is encrypted
+    /// already keeps its active key, see [`DataStore::rotate_encryption_key`].
+    /// Every key listed by [`DataStore::encryption_keys_in_use`] has to be passed
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or a key files are encrypted
+    /// with is missing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the keyspace name is invalid.
+    pub async fn open_encrypted(
+        keyspace: &'static str,
+        dir: impl P,
+        keys: Vec<EncryptionKey>,
+    ) -> Result<DataStore<'static, Key>, crate::err::Error> {
+        assert!(is_valid_keyspace_name(keyspace));
+        let config = Config {
+            encryption_keys: keys,
+            ..Config::default()
+        };
+        let mut store = Self::create_or_recover(DirPath::build(dir), SizeUnit::Bytes, config).await?;
+        store.keyspace = keyspace;
+        store.start_background_tasks();
+        Ok(store)
+    }
+
     /// Same as [`Datastore::open`], but does not start background tasks.
     ///
     /// Open a keyspace without background tasks for testing.
@@ -172,21 +364,55 @@ impl DataStore<'static, Key> {
     /// and should not be user-facing.
     pub(crate) fn start_background_tasks(&self) {
         // NOTE: we only incrememnt the ref counter not a deep clone
-        self.compactor
-            .spawn_compaction_worker(self.buckets.clone(), self.key_range.clone());
+        self.compactor.spawn_compaction_worker(
+            self.buckets.clone(),
+            self.key_range.clone(),
+            self.health.clone(),
+        );
 
         self.compactor.start_flush_listener(
             self.flush_signal_rx.clone(),
             self.buckets.clone(),
             self.key_range.clone(),
+            self.health.clone(),
+        );
+
+        self.gc.start_gc_worker(
+            self.key_range.clone(),
+            self.read_only_memtables.clone(),
+            self.health.clone(),
+        );
+
+        self.orphans.spawn_deleter();
+
+        self.scrubber.spawn_scrubber(
+            self.buckets.clone(),
+            self.key_range.clone(),
+            self.compactor.config.compacting.clone(),
+            self.manual_background.clone(),
+            self.dir.quarantine.clone(),
+        );
+
+        self.ttl_sweeper.spawn_sweeper(
+            self.buckets.clone(),
+            self.key_range.clone(),
+            self.compactor.clone(),
         );
 
-        self.gc
-            .start_gc_worker(self.key_range.clone(), self.read_only_memtables.clone());
+        self.memtable_age
+            .spawn_age_check(&self.active_memtable, self.sealer());
     }
 
     /// Inserts a new entry into the store
     ///
+    /// Returns a [`WriteReceipt`] with sequence number, value log offset,
+    /// memtable and timestamp of the write, see [`DataStore::latest_sequence`]
+    ///
+    /// The active memtable is sealed first if the entry would not fit in it,
+    /// entries too large for an empty memtable are rejected with
+    /// [`crate::err::Error::EntryLargerThanBuffer`]. Write interceptors see the
+    /// write first and may modify or reject it, see `with_write_interceptor`
+    ///
     /// # Examples
     /// ```
     /// # use tempfile::tempdir;
@@ -195,7 +421,7 @@ impl DataStore<'static, Key> {
     /// async fn main() {
     ///     let root = tempdir().unwrap();
     ///     let path = root.path().join("velarixdb");
-    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
     ///
     ///     let res1 = store.put("apple", "tim cook").await;
     ///     let res2 = store.put("google", "sundar pichai").await;
@@ -213,11 +439,99 @@ impl DataStore<'static, Key> {
     /// }
     /// ```
     pub async fn put(
-        &mut self,
+        &self,
+        key: impl AsRef<[u8]>,
+        val: impl AsRef<[u8]>,
+    ) -> Result<WriteReceipt, crate::err::Error> {
+        let (key, val) = self.intercept_entry(key.as_ref(), val.as_ref()).await?;
+        self.write_entry(&key, &val).await
+    }
+
+    /// Inserts a new entry into the store and returns the value it replaced
+    ///
+    /// The previous value is looked up in memtables before SSTables while the
+    /// value log is held for the write, so no other write lands between the
+    /// lookup and the write. Returns receipt of the write along with the
+    /// previous entry, `None` if the key was absent or deleted
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the write is invalid or rejected
+    pub async fn put_get_old(
+        &self,
         key: impl AsRef<[u8]>,
         val: impl AsRef<[u8]>,
-    ) -> Result<Bool, crate::err::Error> {
-        self.validate_size(key.as_ref(), Some(val.as_ref()))?;
+    ) -> Result<(WriteReceipt, Option<UserEntry>), crate::err::Error> {
+        let (key, val) = self.intercept_entry(key.as_ref(), val.as_ref()).await?;
+        self.write_entry_reading_old(&key, &val, ReadOld::Return).await
+    }
+
+    /// Returns key and value of a write once interceptors accepted them
+    async fn intercept_entry<'k>(
+        &self,
+        key: &'k [u8],
+        val: &'k [u8],
+    ) -> Result<(Cow<'k, [u8]>, Cow<'k, [u8]>), crate::err::Error> {
+        if self.interceptors.is_empty() {
+            return Ok((Cow::Borrowed(key), Cow::Borrowed(val)));
+        }
+        let write = InterceptedWrite::from_entry(key, val);
+        Ok(match self.interceptors.intercept(write).await? {
+            InterceptedWrite::Put { key, value } => (Cow::Owned(key), Cow::Owned(value)),
+            InterceptedWrite::Delete { key } => {
+                (Cow::Owned(key), Cow::Borrowed(TOMB_STONE_MARKER.as_bytes()))
+            }
+        })
+    }
+
+    /// Writes `val` to `key` once interceptors accepted it, a tombstone marker deletes the key
+    async fn write_entry(&self, key: &[u8], val: &[u8]) -> Result<WriteReceipt, crate::err::Error> {
+        let (receipt, _) = self.write_entry_reading_old(key, val, ReadOld::Skip).await?;
+        Ok(receipt)
+    }
+
+    /// Writes `val` to `key` like [`DataStore::write_entry`], reading the
+    /// entry it replaces as `read_old` asks while the value log is held
+    async fn write_entry_reading_old(
+        &self,
+        key: &[u8],
+        val: &[u8],
+        read_old: ReadOld,
+    ) -> Result<(WriteReceipt, Option<UserEntry>), crate::err::Error> {
+        let _gate = self.write_gate.read().await;
+        self.apply_entry(key, val, read_old).await
+    }
+
+    /// Inserts `val` at `key` like [`DataStore::put`], for writes holding `write_gate` exclusively
+    pub(crate) async fn put_holding_gate(
+        &self,
+        key: &[u8],
+        val: &[u8],
+    ) -> Result<WriteReceipt, crate::err::Error> {
+        let (key, val) = self.intercept_entry(key, val).await?;
+        let (receipt, _) = self.apply_entry(&key, &val, ReadOld::Skip).await?;
+        Ok(receipt)
+    }
+
+    /// Writes `val` to `key` like [`DataStore::write_entry_reading_old`], callers hold `write_gate`
+    async fn apply_entry(
+        &self,
+        key: &[u8],
+        val: &[u8],
+        read_old: ReadOld,
+    ) -> Result<(WriteReceipt, Option<UserEntry>), crate::err::Error> {
+        self.validate_size(key, Some(val))?;
+        if self.health.is_read_only() {
+            return Err(crate::err::Error::StoreReadOnly);
+        }
+        let key = self.config.comparator.encode(key.as_ref());
+        // the entry has to fit an empty memtable along with the head entry written when it is sealed
+        let size = MemTable::entry_size(key.len()) + MemTable::entry_size(HEAD_KEY_SIZE);
+        let capacity = self.active_memtable.read().unwrap().capacity();
+        if size > capacity {
+            return Err(crate::err::Error::EntryLargerThanBuffer { size, capacity });
+        }
+        let mut timer = self.slow_log.foreground("put");
 
         if !self.gc_updated_entries.read().await.is_empty() {
             self.sync_gc_update_with_store().await?
@@ -225,21 +539,119 @@ impl DataStore<'static, Key> {
 
         // This ensures sstables in key range whose filter is newly loaded(after crash) are mapped to the sstables
         self.key_range.update_key_range().await;
-        let is_tombstone = std::str::from_utf8(val.as_ref()).unwrap() == TOMB_STONE_MARKER;
-        let created_at = Utc::now();
-        let v_offset = self
-            .val_log
-            .append(key.as_ref(), val.as_ref(), created_at, is_tombstone)
+        let is_tombstone = val == TOMB_STONE_MARKER.as_bytes();
+        let value = (!is_tombstone).then_some(val);
+        let phase_start = Instant::now();
+        let mut val_log = self.val_log.write().await;
+        // usage is read while the value log is held, so no other write changes it before this one
+        let quota_deltas = self
+            .quota_deltas(&[(key.as_ref(), value)], &val_log, &mut timer)
             .await?;
+        self.quotas.check(&quota_deltas)?;
+        let old = match read_old {
+            ReadOld::Skip => None,
+            ReadOld::Return | ReadOld::RequireLive => self
+                .get_uncached_in(key.as_ref(), Some(&val_log), &mut timer, false)
+                .await?
+                .map(|(entry, _)| UserEntry::from(entry)),
+        };
+        if read_old == ReadOld::RequireLive && old.is_none() {
+            return Err(crate::err::Error::NotFoundInDB);
+        }
+        let created_at = self.clock.tick();
+        let (hash, shared) = match is_tombstone {
+            true => (None, None),
+            false => self.dedup.find(&val_log, val).await?,
+        };
+        self.io_latency.write().await;
+        let v_offset = match shared {
+            // the entry reads its value from the shared payload
+            Some(payload) => {
+                val_log
+                    .append_record(
+                        key.as_ref(),
+                        &ValueDedup::encode_ref(payload),
+                        created_at,
+                        RecordType::ValueRef,
+                    )
+                    .await?;
+                payload
+            }
+            None => {
+                val_log
+                    .append(key.as_ref(), val, created_at, is_tombstone)
+                    .await?
+            }
+        };
+        timer.record(Phase::VLog, phase_start);
         let entry = Entry::new(key.as_ref().to_vec(), v_offset, created_at, is_tombstone);
 
-        if self.active_memtable.is_full(HEAD_KEY_SIZE) {
-            self.migrate_memtable_to_read_only();
+        let phase_start = Instant::now();
+        let memtable_id = self.insert_into_active_memtable(&mut val_log, &entry);
+        timer.record(Phase::Memtable, phase_start);
+        let mut receipt = WriteReceipt::new(v_offset, memtable_id, created_at);
+        // GC has to see the entry before a reference is attached, see `ValueDedup::attach`
+        self.gc_table().write().await.insert(&entry);
+        match (hash, shared) {
+            (Some(hash), Some(payload)) => {
+                if !self
+                    .dedup
+                    .attach(hash, payload, key.to_vec(), created_at, val.len())
+                {
+                    // GC claimed the payload meanwhile, the value is written on its own
+                    let created_at = self.clock.tick();
+                    let v_offset = val_log.append(key.as_ref(), val, created_at, false).await?;
+                    let entry = Entry::new(key.as_ref().to_vec(), v_offset, created_at, false);
+                    let memtable_id = self.insert_into_active_memtable(&mut val_log, &entry);
+                    self.gc_table().write().await.insert(&entry);
+                    self.dedup.register(hash, v_offset);
+                    receipt = WriteReceipt::new(v_offset, memtable_id, created_at);
+                }
+            }
+            _ => {
+                if let Some(hash) = hash {
+                    self.dedup.register(hash, v_offset);
+                }
+            }
         }
-        self.active_memtable.insert(&entry);
-        let gc_table = Arc::clone(&self.gc_table);
-        tokio::spawn(async move { gc_table.write().await.insert(&entry) });
-        Ok(true)
+        self.enforce_write_buffer_budget(&mut val_log);
+        self.row_cache.invalidate(key.as_ref());
+        self.quotas.apply(&quota_deltas);
+        self.maintain_after_write(val_log).await;
+        timer.finish();
+        Ok((receipt, old))
+    }
+
+    /// Sets capacity of memtables in kilobytes
+    ///
+    /// The active memtable keeps its capacity, the memtable replacing it on
+    /// its next rotation gets the new one
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is less than 50 kilobytes
+    pub fn set_write_buffer_size(&mut self, size: usize) {
+        assert!(
+            size >= 50,
+            "write_buffer_size should not be less than 50 Kilobytes"
+        );
+        self.config.write_buffer_size = SizeUnit::Kilobytes.as_bytes(size);
+    }
+
+    /// Returns sequence number of the latest write
+    ///
+    /// Sequence numbers returned by writes increase across restarts, a write
+    /// whose sequence number is at most the latest one has been applied
+    pub fn latest_sequence(&self) -> SeqNumber {
+        Version::from(self.clock.latest()).sequence()
+    }
+
+    /// Returns receipt of a write that wrote nothing, at the latest sequence
+    /// number and pointing at the end of the value log and the active memtable
+    pub(crate) async fn unwritten_receipt(&self) -> WriteReceipt {
+        let vlog_offset = self.val_log.read().await.size;
+        let memtable_id = self.active_memtable.read().unwrap().id;
+        WriteReceipt::new(vlog_offset, memtable_id, self.clock.latest())
     }
 
     /// Moves active memtable to read-only memtables
@@ -247,35 +659,109 @@ impl DataStore<'static, Key> {
     /// Marks the active memtable as read only,
     /// updates store metadata and moves the memtable
     /// to read-only memtables
-    pub(crate) fn migrate_memtable_to_read_only(&mut self) {
-        let head_offset = self.active_memtable.get_most_recent_offset();
-
-        self.val_log.set_head(head_offset);
-        self.meta.set_head(head_offset);
-        self.meta.update_last_modified();
+    ///
+    /// Returns the value log head the memtable was sealed at
+    pub(crate) fn migrate_memtable_to_read_only(&self) -> usize {
+        self.seal_memtable(false)
+    }
 
-        let gc_log = Arc::clone(&self.gc_log);
-        tokio::spawn(async move {
-            (gc_log.write().await).head_offset = head_offset;
-        });
-        let is_tombstone = false;
-        let head_entry = Entry::new(HEAD_ENTRY_KEY.to_vec(), head_offset, Utc::now(), is_tombstone);
-        self.active_memtable.insert(&head_entry);
-        self.active_memtable.mark_readonly();
-        self.update_meta_background();
-
-        if self.read_only_memtables.is_empty() {
-            self.flush_stream.clear();
-        }
-        self.read_only_memtables.insert(
-            MemTable::generate_table_id(),
-            Arc::new(self.active_memtable.to_owned()),
+    /// Moves active memtable to read-only memtables, `forced` if the write
+    /// buffer budget seals it before it is full
+    fn seal_memtable(&self, forced: bool) -> usize {
+        let head_offset = self.sealer().seal(
+            &mut self.active_memtable.write().unwrap(),
+            self.config.write_buffer_size,
+            forced,
         );
+        self.settle_sealed_memtable();
+        head_offset
+    }
 
+    /// Flushes read-only memtables once there are too many and starts a new
+    /// GC table, after the active memtable was sealed
+    fn settle_sealed_memtable(&self) {
         if self.read_only_memtables.len() >= self.config.max_buffer_write_number {
             self.flush_read_only_memtables();
         }
-        self.reset_memtables();
+        self.reset_gc_table();
+    }
+
+    /// Returns handles of the state sealing the active memtable touches
+    pub(crate) fn sealer(&self) -> MemTableSealer {
+        MemTableSealer {
+            read_only_memtables: self.read_only_memtables.clone(),
+            rotations: self.rotations.clone(),
+            meta: self.meta.clone(),
+            gc_log: self.gc_log.clone(),
+            clock: self.clock.clone(),
+            flusher: self.flusher.clone(),
+            flush_signal_tx: self.flush_signal_tx.clone(),
+            health: self.health.clone(),
+            manual_background: self.manual_background.clone(),
+        }
+    }
+
+    /// Inserts `entry` into the active memtable, sealing it first if the entry does not fit
+    ///
+    /// Callers hold `val_log` since appending the entry. Returns id of the memtable holding the entry
+    pub(crate) fn insert_into_active_memtable(
+        &self,
+        val_log: &mut ValueLog,
+        entry: &Entry<Key, ValOffset>,
+    ) -> MemtableId {
+        if !self.active_memtable.read().unwrap().fits(entry.key.len()) {
+            val_log.set_head(self.migrate_memtable_to_read_only());
+        }
+        let mut active = self.active_memtable.write().unwrap();
+        active.insert(entry);
+        active.id
+    }
+
+    /// Inserts `entries` of a batch into the active memtable and drops their
+    /// keys from the row cache, readers see all of them or none
+    ///
+    /// The active memtable is held until every entry is in, it is sealed in
+    /// between if the next entry does not fit. Callers hold `val_log` since
+    /// appending the entries. Returns id of the memtable holding the last entry
+    pub(crate) fn insert_batch_into_active_memtable(
+        &self,
+        val_log: &mut ValueLog,
+        entries: &[Entry<Key, ValOffset>],
+    ) -> MemtableId {
+        let mut sealed = false;
+        let mut active = self.active_memtable.write().unwrap();
+        for entry in entries {
+            if !active.fits(entry.key.len()) {
+                val_log.set_head(
+                    self.sealer()
+                        .seal(&mut active, self.config.write_buffer_size, false),
+                );
+                sealed = true;
+            }
+            active.insert(entry);
+            self.row_cache.invalidate(&entry.key);
+        }
+        let memtable_id = active.id;
+        drop(active);
+        if sealed {
+            self.settle_sealed_memtable();
+        }
+        memtable_id
+    }
+
+    /// Flushes memtables early if together they exceed the total write buffer budget
+    pub(crate) fn enforce_write_buffer_budget(&self, val_log: &mut ValueLog) {
+        let (active_size, total) = {
+            let active = self.active_memtable.read().unwrap();
+            let total = WriteBufferManager::memory_usage(&active, &self.read_only_memtables);
+            (active.size, total)
+        };
+        if !self.write_buffer_manager.should_flush(active_size, total) {
+            return;
+        }
+        val_log.set_head(self.seal_memtable(true));
+        // migration only flushes once `max_buffer_write_number` is reached
+        self.flush_read_only_memtables();
     }
 
     /// Synchronize GC table with active memtable
@@ -288,10 +774,16 @@ impl DataStore<'static, Key> {
     ///
     /// Returns error, if an IO error occured.
     #[doc(hidden)]
-    pub(crate) async fn sync_gc_update_with_store(&mut self) -> Result<(), crate::err::Error> {
+    pub(crate) async fn sync_gc_update_with_store(&self) -> Result<(), crate::err::Error> {
+        // entries moved by GC are ordered with appends of writers
+        let mut val_log = self.val_log.write().await;
         let gc_entries_reader = self.gc_updated_entries.read().await;
+        // another writer synced them while this one waited
+        if gc_entries_reader.is_empty() {
+            return Ok(());
+        }
         for e in gc_entries_reader.iter() {
-            self.active_memtable.insert(&Entry::new(
+            self.active_memtable.write().unwrap().insert(&Entry::new(
                 e.key().to_vec(),
                 e.value().val_offset,
                 e.value().created_at,
@@ -299,28 +791,27 @@ impl DataStore<'static, Key> {
             ));
         }
         gc_entries_reader.clear();
+        drop(gc_entries_reader);
+        // records moved by GC are freed next, a checkpoint must not point at them
+        if self.meta.lock().unwrap().checkpoint_offset > 0 {
+            self.checkpoint_head(&val_log).await?;
+        }
         let (updated_head, updated_tail) = self.gc.free_unused_space().await?;
-        self.meta.set_head(updated_head);
-        self.meta.set_tail(updated_tail);
-        self.meta.update_last_modified();
-        self.val_log.set_head(updated_head);
-        self.val_log.set_tail(updated_tail);
+        let mut meta = self.meta.lock().unwrap();
+        meta.set_head(updated_head);
+        meta.set_tail(updated_tail);
+        meta.update_last_modified();
+        drop(meta);
+        val_log.set_head(updated_head);
+        val_log.set_tail(updated_tail);
         Ok(())
     }
 
-    /// Updates metadata in background
-    #[doc(hidden)]
-    pub(crate) fn update_meta_background(&self) {
-        let meta = Arc::new(Mutex::new(self.meta.to_owned()));
-        tokio::spawn(async move {
-            if let Err(err) = meta.lock().await.write().await {
-                log::error!("{}", err)
-            }
-        });
-    }
-
     /// Removes an entry from the store
     ///
+    /// The tombstone is written without reading the key first, so deleting an
+    /// absent key succeeds, see [`DataStore::delete_if_exists`] to only delete
+    /// existing keys
     ///
     /// # Examples
     ///
@@ -332,7 +823,7 @@ impl DataStore<'static, Key> {
     ///  async fn main() {
     ///  let root = tempdir().unwrap();
     ///  let path = root.path().join("velarixdb");
-    ///  let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///  let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
     ///
     ///   store.put("apple", "tim cook").await.unwrap(); // handle error
     ///   // Retrieve entry
@@ -348,43 +839,64 @@ impl DataStore<'static, Key> {
     /// }
     ///
     /// ```
-    pub async fn delete<T: AsRef<[u8]>>(&mut self, key: T) -> Result<bool, crate::err::Error> {
+    pub async fn delete<T: AsRef<[u8]>>(&self, key: T) -> Result<WriteReceipt, crate::err::Error> {
         self.validate_size(key.as_ref(), None::<T>)?;
-        self.get(key.as_ref()).await?;
-        let value = TOMB_STONE_MARKER;
-        self.put(key.as_ref(), value).await
+        self.put(key.as_ref(), TOMB_STONE_MARKER).await
+    }
+
+    /// Removes an entry from the store and returns the removed entry, see [`DataStore::put_get_old`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured or the delete is invalid or rejected
+    pub async fn delete_get_old<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+    ) -> Result<(WriteReceipt, Option<UserEntry>), crate::err::Error> {
+        self.validate_size(key.as_ref(), None::<T>)?;
+        self.put_get_old(key.as_ref(), TOMB_STONE_MARKER).await
+    }
+
+    /// Removes an entry from the store if the key exists, which costs a read
+    ///
+    /// The key is looked up while the value log is held for the delete, so no
+    /// other write lands between the lookup and the delete
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::err::Error::NotFoundInDB`] without writing anything if
+    /// the key is absent or already deleted
+    pub async fn delete_if_exists<T: AsRef<[u8]>>(&self, key: T) -> Result<WriteReceipt, crate::err::Error> {
+        self.validate_size(key.as_ref(), None::<T>)?;
+        let (key, val) = self
+            .intercept_entry(key.as_ref(), TOMB_STONE_MARKER.as_bytes())
+            .await?;
+        let (receipt, _) = self
+            .write_entry_reading_old(&key, &val, ReadOld::RequireLive)
+            .await?;
+        Ok(receipt)
     }
 
     /// Flushes read-only memtable to disk using a background tokio task
-    pub(crate) fn flush_read_only_memtables(&mut self) {
-        for table in self.read_only_memtables.iter() {
-            let key = table.key().to_owned();
-            let value = table.value().to_owned();
-            if self.flush_stream.contains(&key) {
-                continue;
-            }
-            let mut flusher = self.flusher.clone();
-            let tx = self.flush_signal_tx.clone();
-            // NOTE: If the put method returns before the code inside tokio::spawn finishes executing,
-            // the tokio::spawn task will continue to run independently of the original function call.
-            // This is because tokio::spawn creates a new asynchronous task that is managed by the Tokio runtime.
-            // The spawned task is executed concurrently and its lifecycle is not tied to the function that spawned it.
-            // TODO: See if we can introduce semaphors to prevent overloading the system
-            self.flush_stream.insert(key.to_vec());
-            tokio::spawn(async move {
-                flusher.flush_handler(key, value, tx);
-            });
-        }
-    }
-
-    /// Resets both active memtable and GC table to new
-    pub(crate) fn reset_memtables(&mut self) {
-        let capacity = self.active_memtable.capacity();
-        let size_unit = self.active_memtable.size_unit();
-        let false_positive_rate = self.active_memtable.false_positive_rate();
-        self.active_memtable =
-            MemTable::with_specified_capacity_and_rate(size_unit, capacity, false_positive_rate);
-        self.gc_table = Arc::new(RwLock::new(MemTable::with_specified_capacity_and_rate(
+    ///
+    /// Memtables are left for [`DataStore::tick_flush`] in manual background mode
+    pub(crate) fn flush_read_only_memtables(&self) {
+        self.sealer().flush_read_only();
+    }
+
+    /// Returns GC table entries are inserted into
+    pub(crate) fn gc_table(&self) -> GCTable {
+        Arc::clone(&self.gc_table.read().unwrap())
+    }
+
+    /// Resets GC table to new
+    pub(crate) fn reset_gc_table(&self) {
+        let active = self.active_memtable.read().unwrap();
+        let capacity = active.capacity();
+        let size_unit = active.size_unit();
+        let false_positive_rate = active.false_positive_rate();
+        drop(active);
+        *self.gc_table.write().unwrap() = Arc::new(RwLock::new(MemTable::with_specified_capacity_and_rate(
             size_unit,
             capacity,
             false_positive_rate,
@@ -406,7 +918,7 @@ impl DataStore<'static, Key> {
     /// async fn main() {
     ///  let root = tempdir().unwrap();
     ///  let path = root.path().join("velarixdb");
-    ///  let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///  let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
     ///
     ///  let res1 = store.put("apple", "tim cook").await;
     ///  let res2 = store.put("google", "sundar pichai").await;
@@ -440,67 +952,227 @@ impl DataStore<'static, Key> {
     /// }
     /// ```
     pub async fn get<T: AsRef<[u8]>>(&self, key: T) -> Result<Option<UserEntry>, crate::err::Error> {
+        Ok(self.get_ref(key).await?.map(UserEntry::from))
+    }
+
+    /// Retrieves an entry without copying its value
+    ///
+    /// The returned value shares the buffer read from the value log or held by
+    /// the row cache, which avoids copying large values on every read
+    ///
+    /// # Examples
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use velarixdb::db::DataStore;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     store.put("apple", "tim cook").await.unwrap(); // handle error
+    ///     let entry = store.get_ref("apple").await.unwrap(); // handle error
+    ///     assert_eq!(&entry.unwrap().val[..], b"tim cook");
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if IO error occurs
+    pub async fn get_ref<T: AsRef<[u8]>>(&self, key: T) -> Result<Option<UserEntryRef>, crate::err::Error> {
         self.validate_size(key.as_ref(), None::<T>)?;
+        let user_key = key.as_ref();
+        let key = self.config.comparator.encode(user_key);
+        let sampled = self.read_profiler.sample();
 
-        if let Some(val) = self.search_gc_entries(key.as_ref()).await? {
-            return Ok(Some(val));
+        if let Some(entry) = self.row_cache.get(key.as_ref()) {
+            if sampled {
+                self.read_profiler.record_read(user_key, true);
+            }
+            return Ok(Some(entry));
+        }
+        if sampled {
+            self.read_profiler.record_read(user_key, false);
+        }
+        // taken before the lookup, a write invalidating a key meanwhile keeps the value uncached
+        let generation = self.row_cache.generation();
+        let mut timer = self.slow_log.foreground("get");
+        let entry = self.get_uncached(key.as_ref(), &mut timer, sampled).await?;
+        // entries with a TTL of their own are not cached, the cache would serve them once expired
+        if let Some((e, None)) = entry.as_ref() {
+            self.row_cache
+                .insert_if_unchanged(key.as_ref(), e.to_owned(), generation);
         }
+        timer.finish();
+        Ok(entry.map(|(entry, _)| entry))
+    }
 
-        let mut offset = VLOG_START_OFFSET;
-        let mut insert_time = util::default_datetime();
-        let lowest_insert_time = util::default_datetime();
-        if let Some(val) = self.active_memtable.get(key.as_ref()) {
-            if val.is_tombstone {
-                return Ok(None);
-            }
-            self.get_value_from_vlog(val.val_offset, val.created_at).await
-        } else {
-            let mut is_deleted = false;
-            for table in self.read_only_memtables.iter() {
-                if let Some(val) = table.value().get(key.as_ref()) {
-                    if val.created_at > insert_time {
-                        offset = val.val_offset;
-                        insert_time = val.created_at;
-                        is_deleted = val.is_tombstone
-                    }
-                }
-            }
-            if self.found_in_table(insert_time, lowest_insert_time) {
-                if is_deleted {
-                    return Ok(None);
-                }
-                self.get_value_from_vlog(offset, insert_time).await
-            } else {
-                let ssts = &self.key_range.filter_sstables_by_key_range(key.as_ref()).await?;
-                if ssts.is_empty() {
-                    return Ok(None);
-                }
-                self.search_key_in_sstables(key, ssts.to_vec()).await
+    /// Retrieves an entry from memtables, sstables and value log without
+    /// consulting the row cache
+    ///
+    /// Returns the entry along with the time it expires at, see [`WriteBatch::put_with_ttl`]
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if IO error occurs
+    pub(crate) async fn get_uncached<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+        timer: &mut OpTimer,
+        sampled: bool,
+    ) -> Result<Option<(UserEntryRef, Option<CreatedAt>)>, crate::err::Error> {
+        self.get_uncached_in(key, None, timer, sampled).await
+    }
+
+    /// Retrieves an entry like [`DataStore::get_uncached`], reading its value
+    /// through `val_log` if the caller already holds the value log
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if IO error occurs
+    pub(crate) async fn get_uncached_in<T: AsRef<[u8]>>(
+        &self,
+        key: T,
+        val_log: Option<&ValueLog>,
+        timer: &mut OpTimer,
+        sampled: bool,
+    ) -> Result<Option<(UserEntryRef, Option<CreatedAt>)>, crate::err::Error> {
+        let found = match self.search_gc_entries(key.as_ref()).await {
+            Some(val) => Some(val),
+            None => self.locate(key.as_ref(), timer, sampled).await?,
+        };
+        match found {
+            Some(val) if !val.is_tombstone => {
+                self.get_value_from_vlog(val.val_offset, val.created_at, val_log, timer)
+                    .await
             }
+            _ => Ok(None),
         }
     }
 
-    /// Searches for entries from gc yet be synced to active memtable
+    /// Returns whether `key` has an entry without reading its value
     ///
+    /// The search stops at the newest entry of the key found in memtables or
+    /// SSTables, the value log is never read. Tombstones are only reported
+    /// until compaction drops them, after which the key is absent. Values
+    /// whose TTL may have passed are reported as [`KeyStatus::MaybeExpired`]
     ///
     /// # Errors
     ///
-    /// Returns error, if IO error occurs
-    async fn search_gc_entries(&self, key: impl AsRef<[u8]>) -> Result<Option<UserEntry>, crate::err::Error> {
+    /// Returns error, if the key is invalid or an IO error occurs
+    pub async fn contains_key<T: AsRef<[u8]>>(&self, key: T) -> Result<KeyStatus, crate::err::Error> {
+        self.validate_size(key.as_ref(), None::<T>)?;
+        let key = self.config.comparator.encode(key.as_ref());
+        // cached entries are live and have no TTL of their own, writes invalidate them
+        if self.row_cache.get(key.as_ref()).is_some() {
+            return Ok(KeyStatus::Present);
+        }
+        let live = match self.meta.lock().unwrap().expiring_values {
+            true => KeyStatus::MaybeExpired,
+            false => KeyStatus::Present,
+        };
         let gc_entries = self.gc_updated_entries.read().await;
-        if !gc_entries.is_empty() {
-            if let Some(e) = gc_entries.get(key.as_ref()) {
-                let val = e.value();
-                if val.is_tombstone {
-                    return Ok(None);
-                }
-                return self.get_value_from_vlog(val.val_offset, val.created_at).await;
+        if gc_entries
+            .get(key.as_ref())
+            .is_some_and(|e| !e.value().is_tombstone)
+        {
+            return Ok(live);
+        }
+        drop(gc_entries);
+        let mut timer = self.slow_log.foreground("contains_key");
+        let status = match self.locate(key.as_ref(), &mut timer, false).await? {
+            Some(val) if val.is_tombstone => KeyStatus::Tombstoned,
+            Some(_) => live,
+            None => KeyStatus::Absent,
+        };
+        timer.finish();
+        Ok(status)
+    }
+
+    /// Returns newest entry of `key` in memtables and SSTables, without reading its value
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if IO error occurs
+    pub(crate) async fn locate(
+        &self,
+        key: &[u8],
+        timer: &mut OpTimer,
+        sampled: bool,
+    ) -> Result<Option<SkipMapValue<ValOffset>>, crate::err::Error> {
+        let phase_start = Instant::now();
+        let newest = Self::search_memtables(&self.active_memtable, &self.read_only_memtables, key);
+        timer.record(Phase::Memtable, phase_start);
+        if newest.is_some() {
+            return Ok(newest);
+        }
+        let phase_start = Instant::now();
+        let ssts = self.key_range.filter_sstables_by_key_range(key).await?;
+        timer.record(Phase::Filter, phase_start);
+        if ssts.is_empty() {
+            return Ok(None);
+        }
+        self.search_sstables(key, ssts, timer, sampled).await
+    }
+
+    /// Returns newest entry of `key` in the `active` memtable or the `read_only` memtables
+    ///
+    /// The active memtable is searched first. Rotation publishes a sealed
+    /// memtable to `read_only` before it releases the active one, so an entry
+    /// moved after the active memtable was searched is found in `read_only`
+    pub(crate) fn search_memtables(
+        active: &ActiveMemTable<Key>,
+        read_only: &ImmutableMemTables<Key>,
+        key: &[u8],
+    ) -> Option<SkipMapValue<ValOffset>> {
+        let active = active.read().unwrap().get(key);
+        if active.is_some() {
+            return active;
+        }
+        read_only
+            .iter()
+            .filter_map(|table| table.value().get(key))
+            .max_by_key(|val| val.created_at)
+    }
+
+    /// Searches `ssts` picked from key ranges for the newest entry of `key`
+    ///
+    /// Compaction can delete an SSTable after its key range was read, the
+    /// merged SSTable holding its entries is installed first so the search is
+    /// retried once with the SSTables of fresh key ranges
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if IO error occurs
+    pub(crate) async fn search_sstables(
+        &self,
+        key: &[u8],
+        ssts: Vec<Table>,
+        timer: &mut OpTimer,
+        sampled: bool,
+    ) -> Result<Option<SkipMapValue<ValOffset>>, crate::err::Error> {
+        match self.search_key_in_sstables(key, ssts, timer, sampled).await {
+            Err(err) if err.is_file_not_found() => {
+                let ssts = self.key_range.filter_sstables_by_key_range(key).await?;
+                self.search_key_in_sstables(key, ssts, timer, sampled).await
             }
+            res => res,
         }
-        Ok(None)
     }
 
-    /// Removes an entry from the store
+    /// Searches for entries from gc yet be synced to active memtable
+    ///
+    /// Returns the entry GC moved `key` to, its value is not read
+    async fn search_gc_entries(&self, key: impl AsRef<[u8]>) -> Option<SkipMapValue<ValOffset>> {
+        let gc_entries = self.gc_updated_entries.read().await;
+        gc_entries.get(key.as_ref()).map(|e| e.value().to_owned())
+    }
+
+    /// Updates an existing entry of the store
+    ///
+    /// The key is looked up while the value log is held for the write, so no
+    /// other write lands between the lookup and the write
     ///
     /// # Examples
     ///
@@ -511,7 +1183,7 @@ impl DataStore<'static, Key> {
     /// async fn main() {
     ///     let root = tempdir().unwrap();
     ///     let path = root.path().join("velarixdb");
-    ///     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
     ///
     ///     store.put("apple", "tim cook").await.unwrap(); // handle error
     ///
@@ -525,14 +1197,22 @@ impl DataStore<'static, Key> {
     ///     assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "elon musk")
     /// }
     /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`crate::err::Error::NotFoundInDB`] without writing anything if
+    /// the key is absent or deleted, or error if an IO error occured
     pub async fn update(
-        &mut self,
+        &self,
         key: impl AsRef<[u8]>,
         value: impl AsRef<[u8]>,
-    ) -> Result<bool, crate::err::Error> {
+    ) -> Result<WriteReceipt, crate::err::Error> {
         self.validate_size(key.as_ref(), Some(value.as_ref()))?;
-        self.get(key.as_ref()).await?;
-        self.put(key, value).await
+        let (key, value) = self.intercept_entry(key.as_ref(), value.as_ref()).await?;
+        let (receipt, _) = self
+            .write_entry_reading_old(&key, &value, ReadOld::RequireLive)
+            .await?;
+        Ok(receipt)
     }
 
     /// Validate key and value sizes.
@@ -569,9 +1249,11 @@ impl DataStore<'static, Key> {
 
     /// Search for a key across SSTables
     ///
-    /// [`Index`] is used to locate block is sstables that
-    /// can possibly contain the key
+    /// [`crate::index::Index`] is used to locate block is sstables that
+    /// can possibly contain the key. SSTables are probed newest first and
+    /// the search stops once the remaining ones only hold older entries
     ///
+    /// Returns newest entry of the key, its value is not read
     ///
     /// # Errors
     ///
@@ -579,17 +1261,32 @@ impl DataStore<'static, Key> {
     pub(crate) async fn search_key_in_sstables(
         &self,
         key: impl AsRef<[u8]>,
-        ssts: Vec<Table>,
-    ) -> Result<Option<UserEntry>, crate::err::Error> {
+        mut ssts: Vec<Table>,
+        timer: &mut OpTimer,
+        sampled: bool,
+    ) -> Result<Option<SkipMapValue<ValOffset>>, crate::err::Error> {
         let mut insert_time = util::default_datetime();
         let lowest_insert_date = util::default_datetime();
         let mut offset = VLOG_START_OFFSET;
         let mut is_deleted = false;
+        let mut hit: Option<&Table> = None;
+        Table::sort_newest_first(&mut ssts);
         for sst in ssts.iter() {
-            let index = Index::new(sst.index_file.path.to_owned(), sst.index_file.file.to_owned());
-            let block_handle = index.get(key.as_ref()).await?;
-            if block_handle.is_some() {
-                let sst_res = sst.get(block_handle.unwrap(), &key).await?;
+            if sst.newest_entry().is_some_and(|newest| newest <= insert_time) {
+                break;
+            }
+            self.sstable_probes.fetch_add(1, Ordering::Relaxed);
+            if sampled {
+                self.read_profiler.record_sstable(&sst.dir);
+            }
+            let phase_start = Instant::now();
+            let block_handle = sst.get_block_offset(key.as_ref()).await?;
+            timer.record(Phase::Index, phase_start);
+            if let Some(block_handle) = block_handle {
+                let phase_start = Instant::now();
+                self.io_latency.read().await;
+                let sst_res = sst.get(block_handle, &key).await?;
+                timer.record(Phase::Data, phase_start);
 
                 if sst_res.as_ref().is_some() {
                     let (val_offset, created_at, is_tombstone) = sst_res.unwrap();
@@ -598,28 +1295,34 @@ impl DataStore<'static, Key> {
                         offset = val_offset;
                         insert_time = created_at;
                         is_deleted = is_tombstone;
+                        hit = Some(sst);
                     }
                 }
             }
         }
-        if self.found_in_table(insert_time, lowest_insert_date) {
-            if is_deleted {
-                return Ok(None);
+        if let Some(sst) = hit {
+            let lookups = self.sstable_lookups.fetch_add(1, Ordering::Relaxed);
+            if lookups.is_multiple_of(HOTNESS_SAMPLE_EVERY) {
+                sst.increase_hotness(1);
             }
-            return self.get_value_from_vlog(offset, insert_time).await;
+        }
+        if self.found_in_table(insert_time, lowest_insert_date) {
+            return Ok(Some(SkipMapValue::new(offset, insert_time, is_deleted)));
         }
         Ok(None)
     }
 
     /// Checks if insert time is greater than the least
     /// possible insert time meaning the key was found
-    pub fn found_in_table(&self, insert_time: CreatedAt, lowest_insert_date: CreatedAt) -> bool {
+    pub(crate) fn found_in_table(&self, insert_time: CreatedAt, lowest_insert_date: CreatedAt) -> bool {
         insert_time > lowest_insert_date
     }
 
     /// Retrieves value from Value Log
     ///
-    /// Returns value from value log using the provided offset
+    /// Returns value from value log using the provided offset along with the
+    /// time it expires at, a value that expired reads as deleted. The value is
+    /// read through `val_log` if the caller already holds the value log
     ///
     ///
     /// # Errors
@@ -629,52 +1332,177 @@ impl DataStore<'static, Key> {
         &self,
         offset: usize,
         created_at: CreatedAt,
-    ) -> Result<Option<UserEntry>, crate::err::Error> {
-        let res = self.val_log.get(offset).await?;
-        if let Some((value, is_tombstone)) = res {
-            if is_tombstone {
-                return Ok(None);
-            }
-            return Ok(Some(UserEntry::new(value, created_at)));
+        val_log: Option<&ValueLog>,
+        timer: &mut OpTimer,
+    ) -> Result<Option<(UserEntryRef, Option<CreatedAt>)>, crate::err::Error> {
+        let phase_start = Instant::now();
+        self.io_latency.read().await;
+        let res = match val_log {
+            Some(val_log) => val_log.get_with_expiry(offset).await,
+            None => self.val_log.read().await.get_with_expiry(offset).await,
+        };
+        timer.record(Phase::VLog, phase_start);
+        match res? {
+            (_, true, _) => Ok(None),
+            (_, false, expires_at) if ValueExpiry::has_passed(expires_at, self.clock.now()) => Ok(None),
+            (value, false, expires_at) => Ok(Some((UserEntryRef::new(value, created_at), expires_at))),
         }
-        Ok(None)
     }
 
     /// Flushes all memtable (active and read-only) to disk
     ///
+    /// Memtables a background flush is writing are left to it, they stay
+    /// read-only until that flush succeeds
     ///
     /// # Errors
     ///
     /// Returns error, if an IO error occurs or key was not found
-    #[doc(hidden)]
-    #[cfg(test)]
     pub(crate) async fn force_flush(&mut self) -> Result<(), crate::err::Error> {
-        use crossbeam_skiplist::SkipMap;
-
-        self.active_memtable.mark_readonly();
-
-        self.read_only_memtables.insert(
-            MemTable::generate_table_id(),
-            Arc::new(self.active_memtable.to_owned()),
-        );
+        {
+            let mut active = self.active_memtable.write().unwrap();
+            if !active.entries.is_empty() {
+                self.rotations.lock().unwrap().record(active.size, false);
+                active.mark_readonly();
+                let sealed = active.take_with_capacity(self.config.write_buffer_size);
+                self.read_only_memtables.insert(sealed.id, Arc::new(sealed));
+            }
+        }
         let immutable_tables = self.read_only_memtables.to_owned();
-        let mut flusher = Flusher::new(
-            Arc::clone(&self.read_only_memtables),
-            Arc::clone(&self.buckets),
-            Arc::clone(&self.key_range),
-        );
+        let mut flusher = self.flusher.clone();
         for table in immutable_tables.iter() {
-            if self.flush_stream.contains(table.key()) {
+            if !flusher.start_flush(*table.key()) {
                 continue;
             }
-            self.flush_stream.insert(table.key().to_vec());
-            flusher.flush(table.value().to_owned()).await?;
+            let timer = self.slow_log.background("flush");
+            let res = flusher.flush(table.value().to_owned()).await;
+            timer.finish();
+            if res.is_ok() {
+                // removed in place since the flusher shares the read-only memtables
+                self.read_only_memtables.remove(table.key());
+            }
+            flusher.finish_flush(*table.key());
+            res?;
         }
-        self.active_memtable.clear();
-        self.read_only_memtables = Arc::new(SkipMap::new());
         Ok(())
     }
 
+    /// Flushes active and read-only memtables to disk in a background task
+    ///
+    /// The returned future resolves once every memtable sealed by this call, or
+    /// sealed before it, is on disk. Memtables already being flushed in the
+    /// background are waited for rather than flushed twice, they are not
+    /// counted in the report
+    ///
+    /// # Errors
+    ///
+    /// The future returns error, if an IO error occured
+    pub fn flush_memtable_async(
+        &self,
+    ) -> impl Future<Output = Result<FlushReport, crate::err::Error>> + Send + 'static {
+        if !self.active_memtable.read().unwrap().entries.is_empty() {
+            let head_offset = self.migrate_memtable_to_read_only();
+            // a write or GC may hold the value log, meta records the head either way
+            if let Ok(mut val_log) = self.val_log.try_write() {
+                val_log.set_head(head_offset);
+            }
+        }
+        self.flush_read_only_job()
+    }
+
+    /// Flushes read-only memtables in a background task, see [`DataStore::flush_memtable_async`]
+    pub(crate) fn flush_read_only_job(
+        &self,
+    ) -> impl Future<Output = Result<FlushReport, crate::err::Error>> + Send + 'static {
+        let tables: Vec<_> = self
+            .read_only_memtables
+            .iter()
+            .map(|table| (table.key().to_owned(), table.value().to_owned()))
+            .collect();
+        let mut flusher = self.flusher.clone();
+        let tx = self.flush_signal_tx.clone();
+        let health = self.health.clone();
+        let job = tokio::spawn(async move {
+            let mut report = FlushReport::default();
+            for (table_id, table) in tables {
+                // a memtable no longer in the set was flushed in the background
+                while flusher.read_only_memtable.contains_key(&table_id) {
+                    if !flusher.start_flush(table_id) {
+                        sleep(CLOSE_FLUSH_POLL_INTERVAL).await;
+                        continue;
+                    }
+                    let entries = table.entries.len();
+                    let dirs = flusher
+                        .flush_started(table_id, Arc::clone(&table), &tx, &health)
+                        .await?;
+                    report.memtables += 1;
+                    report.entries += entries;
+                    report.sstables.extend(dirs);
+                    report.memtable_ids.push(table_id);
+                }
+            }
+            Ok(report)
+        });
+        async move { job.await.map_err(|_| crate::err::Error::TokioJoin)? }
+    }
+
+    /// Flushes every memtable and marks the store as cleanly shut down
+    ///
+    /// The next open skips replaying value log, as long as nothing was
+    /// written after `close`. The store can still be used afterwards
+    ///
+    /// # Errors
+    ///
+    /// Returns error, if an IO error occured.
+    pub async fn close(&mut self) -> Result<(), crate::err::Error> {
+        // background flushes finish first, failed ones are flushed again below
+        while self.flusher.flushes_in_flight() > 0 {
+            sleep(CLOSE_FLUSH_POLL_INTERVAL).await;
+        }
+        // a head checkpoint being written would overwrite meta written below
+        drop(self.head_checkpoint.lock().await);
+        // entries moved by GC are only held in memory
+        for e in self.gc_updated_entries.write().await.iter() {
+            self.active_memtable.write().unwrap().insert(&Entry::new(
+                e.key().to_vec(),
+                e.value().val_offset,
+                e.value().created_at,
+                e.value().is_tombstone,
+            ));
+        }
+        self.gc_updated_entries.write().await.clear();
+        // head points at the most recent flushed entry, replay skips it
+        let head_offset = self
+            .read_only_memtables
+            .iter()
+            .map(|table| table.value().get_most_recent_offset())
+            .chain([
+                self.active_memtable.read().unwrap().get_most_recent_offset(),
+                self.meta.lock().unwrap().v_log_head,
+            ])
+            .max()
+            .unwrap_or_default();
+        self.force_flush().await?;
+        if self.read_profile().sampled_reads > 0 {
+            self.save_access_profile().await?;
+        }
+
+        self.compactor.config.dead_offsets.persist().await?;
+        let mut val_log = self.val_log.write().await;
+        let v_log_size = val_log.content.file.node.size().await;
+        val_log.set_head(head_offset);
+        self.gc_log.write().await.head_offset = head_offset;
+        let mut meta = {
+            let mut meta = self.meta.lock().unwrap();
+            meta.set_head(head_offset);
+            meta.update_last_modified();
+            meta.to_owned()
+        };
+        // only the copy written here claims a clean shutdown, later writes to meta must not
+        meta.mark_clean_shutdown(v_log_size, self.config.fingerprint());
+        meta.write().await?;
+        meta.file_handle.file.node.sync_all().await
+    }
+
     /// Creates or opens a keyspace in the specified directory.
     ///
     /// # Errors
@@ -685,6 +1513,7 @@ impl DataStore<'static, Key> {
         size_unit: SizeUnit,
         config: Config,
     ) -> Result<DataStore<'static, Key>, crate::err::Error> {
+        FileNode::set_open_files_limit(config.open_files_limit);
         let vlog_path = &dir.val_log.to_owned(); // value log file path
         let vlog_exist = vlog_path
             .try_exists()
@@ -693,6 +1522,7 @@ impl DataStore<'static, Key> {
         let params = CreateOrRecoverStoreParams {
             buckets_path: &dir.buckets,
             meta: Meta::new(&dir.meta).await?,
+            user_meta: UserMeta::open(&dir.meta).await?,
             dir: &dir,
             vlog: ValueLog::new(vlog_path).await?,
             key_range: KeyRange::default(),
@@ -717,8 +1547,8 @@ impl DataStore<'static, Key> {
     /// # Errors
     ///
     /// Returns error, if trigger failed
-    pub async fn run_compaction(&mut self) -> Result<(), crate::err::Error> {
-        self.compactor.reason = CompactionReason::Manual;
+    pub async fn run_compaction(&self) -> Result<(), crate::err::Error> {
+        *self.compactor.reason.lock().await = CompactionReason::Manual;
         Compactor::handle_compaction(
             Arc::clone(&self.buckets),
             Arc::clone(&self.key_range),
@@ -727,9 +1557,44 @@ impl DataStore<'static, Key> {
         .await
     }
 
+    /// Runs a manual compaction in a background task
+    ///
+    /// The compaction waits for a running background compaction to finish,
+    /// background compactions skip their turn while it runs. The returned
+    /// future resolves with the compaction status once it completes
+    ///
+    /// # Errors
+    ///
+    /// The future returns error, if compaction failed
+    pub fn compact_async(
+        &self,
+    ) -> impl Future<Output = Result<CompactionStatus, crate::err::Error>> + Send + 'static {
+        let reason = Arc::clone(&self.compactor.reason);
+        let buckets = Arc::clone(&self.buckets);
+        let key_range = Arc::clone(&self.key_range);
+        let cfg = self.compactor.config.to_owned();
+        let comp_state = Arc::clone(&self.compactor.is_active);
+        let job = tokio::spawn(async move {
+            loop {
+                let mut state = comp_state.lock().await;
+                if let CompState::Sleep = *state {
+                    *state = CompState::Active;
+                    break;
+                }
+                drop(state);
+                sleep(CLOSE_FLUSH_POLL_INTERVAL).await;
+            }
+            *reason.lock().await = CompactionReason::Manual;
+            let res = Compactor::handle_compaction(buckets, key_range, &cfg).await;
+            *comp_state.lock().await = CompState::Sleep;
+            res.map(|_| cfg.progress.snapshot())
+        });
+        async move { job.await.map_err(|_| crate::err::Error::TokioJoin)? }
+    }
+
     /// Returns length of entries in active memtable
     pub fn len_of_entries_in_memtable(&self) -> usize {
-        self.active_memtable.entries.len()
+        self.active_memtable.read().unwrap().entries.len()
     }
 
     /// Get [`DataStore`] directories
@@ -737,9 +1602,21 @@ impl DataStore<'static, Key> {
         self.dir.to_owned()
     }
 
-    /// Checks if `range_iterator` is set for [`DataStore`]
-    pub async fn is_range_iterator_set(&mut self) -> Bool {
-        self.range_iterator.is_some()
+    /// Returns health of the [`DataStore`]
+    ///
+    /// Background flush and compaction failures are tracked here,
+    /// after repeated failures the store is marked degraded and then
+    /// read-only, in which case writes return [`crate::err::Error::StoreReadOnly`]
+    ///
+    /// It also reports whether flush, compaction and GC tasks are alive, when
+    /// each last ran and how it went, along with the flush queue depth, so a
+    /// wedged background loop shows up as a stale `last_run_at`
+    pub fn health(&self) -> Health {
+        Health {
+            pending_flushes: self.read_only_memtables.len(),
+            flushes_in_flight: self.flusher.flushes_in_flight(),
+            ..self.health.snapshot()
+        }
     }
 }
 impl DirPath {
@@ -748,11 +1625,13 @@ impl DirPath {
         let val_log = root.as_ref().join(VALUE_LOG_DIRECTORY_NAME);
         let buckets = root.as_ref().join(BUCKETS_DIRECTORY_NAME);
         let meta = root.as_ref().join(META_DIRECTORY_NAME);
+        let quarantine = root.as_ref().join(QUARANTINE_DIRECTORY_NAME);
         Self {
             root: root.as_ref().to_path_buf(),
             val_log,
             buckets,
             meta,
+            quarantine,
         }
     }
 }
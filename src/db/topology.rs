@@ -0,0 +1,45 @@
+use super::DataStore;
+use crate::{bucket::BucketID, types::Key};
+
+/// Snapshot of a bucket returned by [`DataStore::buckets`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BucketStats {
+    /// Id of the bucket
+    pub id: BucketID,
+
+    /// Bytes of SSTables in the bucket
+    pub size: usize,
+
+    /// Number of SSTables in the bucket
+    pub sstable_count: usize,
+
+    /// Average size of SSTables in the bucket in bytes
+    pub avg_sstable_size: usize,
+
+    /// Prefix the bucket is dedicated to, see `with_pinned_prefix`
+    pub pinned_prefix: Option<Key>,
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Returns a snapshot of every bucket, in the order SSTables are fitted into them
+    ///
+    /// SSTables of about the same size are grouped in a bucket and merged
+    /// together once the bucket holds enough of them
+    pub async fn buckets(&self) -> Vec<BucketStats> {
+        let buckets = self.buckets.read().await;
+        let tuning = buckets.tuning();
+        let mut stats = Vec::with_capacity(buckets.buckets.len());
+        for (id, bucket) in buckets.buckets.iter() {
+            let sstable_count = bucket.sstables.read().await.len();
+            stats.push(BucketStats {
+                id: *id,
+                size: bucket.avarage_size * sstable_count,
+                sstable_count,
+                avg_sstable_size: bucket.avarage_size,
+                pinned_prefix: tuning.pin_of(*id).map(|pin| pin.prefix.to_owned()),
+            });
+        }
+        stats
+    }
+}
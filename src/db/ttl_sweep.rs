@@ -0,0 +1,364 @@
+use super::DataStore;
+use crate::{
+    bucket::BucketID,
+    cfg::Config,
+    compactors::{self, CompState, CompactionInput, CompactionJob, Compactor, SizedTierRunner, TtlParams},
+    consts::{CLOSE_FLUSH_POLL_INTERVAL, DEFAULT_TTL_SWEEP_INTERVAL, HEAD_ENTRY_KEY, TAIL_ENTRY_KEY},
+    err::Error,
+    fs::FileType,
+    sst::{Summary, Table, TableProperties},
+    types::{BucketMapHandle, CreatedAt, Key, KeyRangeHandle},
+};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::Duration,
+};
+use tokio::fs;
+
+/// Outcome of a TTL sweep returned by `DataStore::sweep_expired`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SweepReport {
+    /// Number of SSTables whose expiry was checked
+    pub sstables_checked: usize,
+
+    /// Directories of SSTables dropped whole since every entry expired
+    pub dropped: Vec<PathBuf>,
+
+    /// Directories of mostly expired SSTables compacted with their bucket
+    pub compacted: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+struct SweeperInner {
+    /// Entry and tombstone TTLs, `None` if TTL is disabled
+    ttl: Option<TtlParams>,
+
+    /// Time between background passes, zero disables them
+    interval: Duration,
+
+    /// Estimated share of expired entries that gets an SSTable compacted
+    ratio: f64,
+}
+
+/// Drops SSTables whose entries all expired and compacts the ones mostly
+/// expired, so expired entries don't wait for compaction to touch them
+///
+/// Expiry is told from the oldest and newest entry recorded in SSTable
+/// properties, SSTables are not read unless they are dropped or compacted
+#[derive(Clone, Debug)]
+pub(crate) struct TtlSweeper {
+    inner: Arc<Mutex<SweeperInner>>,
+}
+
+impl From<&Config> for TtlSweeper {
+    fn from(config: &Config) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(SweeperInner {
+                ttl: Self::ttl_of(config),
+                interval: config.ttl_sweep_interval,
+                ratio: config.expired_compaction_ratio,
+            })),
+        }
+    }
+}
+
+impl TtlSweeper {
+    /// Returns TTLs set in `config`, `None` if TTL is disabled
+    pub fn ttl_of(config: &Config) -> Option<TtlParams> {
+        config.enable_ttl.then_some(TtlParams {
+            entry_ttl: config.entry_ttl,
+            tombstone_ttl: config.tombstone_ttl,
+        })
+    }
+
+    /// Changes TTLs expiry is checked against, `None` disables sweeping
+    pub fn set_ttl(&self, ttl: Option<TtlParams>) {
+        self.inner.lock().unwrap().ttl = ttl;
+    }
+
+    /// Changes time between background passes and share of expired entries
+    /// that gets an SSTable compacted
+    pub fn set(&self, interval: Duration, ratio: f64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.interval = interval;
+        inner.ratio = ratio;
+    }
+
+    /// Returns TTLs, time between background passes and compaction ratio
+    pub fn settings(&self) -> (Option<TtlParams>, Duration, f64) {
+        let inner = self.inner.lock().unwrap();
+        (inner.ttl.to_owned(), inner.interval, inner.ratio)
+    }
+
+    /// Checks expiry of every live SSTable once
+    ///
+    /// Waits for a running compaction to finish, compactions skip their turn
+    /// while the pass runs. SSTables being compacted are skipped, so are mostly
+    /// expired SSTables alone in their bucket until they expire whole.
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error or a compaction fails
+    pub async fn run_pass(
+        &self,
+        buckets: &BucketMapHandle,
+        key_range: &KeyRangeHandle,
+        compactor: &Compactor,
+    ) -> Result<SweepReport, Error> {
+        let (Some(ttl), _, ratio) = self.settings() else {
+            return Ok(SweepReport::default());
+        };
+        loop {
+            let mut state = compactor.is_active.lock().await;
+            if let CompState::Sleep = *state {
+                *state = CompState::Active;
+                break;
+            }
+            drop(state);
+            tokio::time::sleep(CLOSE_FLUSH_POLL_INTERVAL).await;
+        }
+        let res = Self::sweep(buckets, key_range, compactor, ttl, ratio).await;
+        *compactor.is_active.lock().await = CompState::Sleep;
+        res
+    }
+
+    async fn sweep(
+        buckets: &BucketMapHandle,
+        key_range: &KeyRangeHandle,
+        compactor: &Compactor,
+        ttl: TtlParams,
+        ratio: f64,
+    ) -> Result<SweepReport, Error> {
+        let mut config = compactor.config.to_owned();
+        config.use_ttl = true;
+        config.entry_ttl = ttl.entry_ttl;
+        config.tombstone_ttl = ttl.tombstone_ttl;
+        let now = config.clock.now();
+
+        let mut tables: Vec<(BucketID, Table)> = Vec::new();
+        for (bucket_id, bucket) in buckets.read().await.buckets.iter() {
+            for table in bucket.sstables.read().await.iter() {
+                tables.push((*bucket_id, table.to_owned()));
+            }
+        }
+        let mut report = SweepReport::default();
+        let mut expiring: HashMap<BucketID, Vec<PathBuf>> = HashMap::new();
+        for (bucket_id, table) in tables {
+            if config.compacting.contains(table.id()) {
+                continue;
+            }
+            let Some(properties) = Self::properties(&table).await? else {
+                continue;
+            };
+            report.sstables_checked += 1;
+            if fully_expired(&properties, &ttl, now) {
+                if Self::drop_table(buckets, key_range, &config, bucket_id, &table).await? {
+                    report.dropped.push(table.dir.to_owned());
+                }
+            } else if expired_share(&properties, &ttl, now) >= ratio {
+                expiring.entry(bucket_id).or_default().push(table.dir.to_owned());
+            }
+        }
+
+        let max_tables = buckets.read().await.tuning().max_tables_to_merge;
+        let mut job = CompactionJob::default();
+        for (bucket_id, dirs) in expiring {
+            let Some(bucket) = buckets.read().await.buckets.get(&bucket_id).cloned() else {
+                continue;
+            };
+            // expiring tables are merged with the oldest of the others
+            let mut sstables = dirs.to_owned();
+            for table in bucket.sstables.read().await.iter() {
+                if !sstables.contains(&table.dir) {
+                    sstables.push(table.dir.to_owned());
+                }
+            }
+            sstables.truncate(max_tables.max(2));
+            if sstables.len() < 2 {
+                continue;
+            }
+            report
+                .compacted
+                .extend(dirs.into_iter().filter(|dir| sstables.contains(dir)));
+            job.inputs.push(CompactionInput {
+                bucket: bucket_id,
+                sstables,
+            });
+        }
+        if !job.inputs.is_empty() {
+            let mut runner = SizedTierRunner::new(Arc::clone(buckets), Arc::clone(key_range), &config);
+            runner.run_job(&job).await?;
+        }
+        Ok(report)
+    }
+
+    /// Returns properties recorded when `table` was written, `None` if it
+    /// was compacted away since the pass started
+    async fn properties(table: &Table) -> Result<Option<TableProperties>, Error> {
+        if let Some(properties) = table.properties() {
+            return Ok(Some(properties.to_owned()));
+        }
+        let mut summary = Summary::with_path(&table.files.summary);
+        summary.cipher = table.file_cipher(&FileType::Summary);
+        match summary.recover().await {
+            Ok(_) => Ok(Some(summary.properties)),
+            Err(_) if fs::metadata(&table.dir).await.is_err() => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Removes `table` from the store and hands value log offsets of its
+    /// entries to GC
+    ///
+    /// Returns `false` if the table is no longer live or is being compacted
+    async fn drop_table(
+        buckets: &BucketMapHandle,
+        key_range: &KeyRangeHandle,
+        config: &compactors::Config,
+        bucket_id: BucketID,
+        table: &Table,
+    ) -> Result<bool, Error> {
+        let mut bucket_map = buckets.write().await;
+        let is_live = match bucket_map.buckets.get(&bucket_id) {
+            Some(bucket) => bucket.sstables.read().await.iter().any(|s| s.id() == table.id()),
+            None => false,
+        };
+        if !is_live || config.compacting.contains(table.id()) {
+            return Ok(false);
+        }
+        let mut expired = table.to_owned();
+        expired.load_entries_from_file().await?;
+        // drop the table from the manifest first so a crash midway never loads it again
+        bucket_map.publish(&[], &[table.dir.to_owned()]).await?;
+        key_range.remove(table.id()).await;
+
+        let mut offsets = Vec::new();
+        for entry in expired.entries.iter() {
+            if !entry.value().is_tombstone {
+                config.quotas.invalidate(entry.key());
+                config.row_cache.invalidate(entry.key());
+            }
+            // head and tail entries hold offsets of other records, not their own
+            if entry.key() != HEAD_ENTRY_KEY && entry.key() != TAIL_ENTRY_KEY {
+                offsets.push(entry.value().val_offset);
+            }
+        }
+        config.dead_offsets.record(offsets);
+        if let Err(err) = config.dead_offsets.persist().await {
+            log::warn!("Value log liveness could not be persisted: {}", err);
+        }
+        expired.entries.clear();
+        bucket_map.delete_ssts(&vec![(bucket_id, vec![expired])]).await?;
+        Ok(true)
+    }
+
+    /// Runs a pass every `interval` while TTL is enabled and an interval is set
+    pub fn spawn_sweeper(&self, buckets: BucketMapHandle, key_range: KeyRangeHandle, compactor: Compactor) {
+        let sweeper = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let (_, interval, _) = sweeper.settings();
+                // settings are checked again hourly while sweeping is off
+                let wait = if interval.is_zero() {
+                    DEFAULT_TTL_SWEEP_INTERVAL
+                } else {
+                    interval
+                };
+                tokio::time::sleep(wait).await;
+                let (ttl, interval, _) = sweeper.settings();
+                if ttl.is_none()
+                    || interval.is_zero()
+                    || compactor.config.manual_background.load(Ordering::Relaxed)
+                {
+                    continue;
+                }
+                if let Err(err) = sweeper.run_pass(&buckets, &key_range, &compactor).await {
+                    log::error!("{}", err);
+                }
+            }
+        });
+    }
+}
+
+/// Returns `true` if every entry of an SSTable with `properties` expired,
+/// tombstones included
+fn fully_expired(properties: &TableProperties, ttl: &TtlParams, now: CreatedAt) -> bool {
+    let Some(newest) = properties.newest_entry else {
+        return false;
+    };
+    let mut max_ttl = ttl.entry_ttl;
+    if properties.tombstone_count > 0 {
+        max_ttl = max_ttl.max(ttl.tombstone_ttl);
+    }
+    now.timestamp_millis() > newest.timestamp_millis() + max_ttl.as_millis() as i64
+}
+
+/// Returns estimated share of expired entries of an SSTable with `properties`
+///
+/// Entries are assumed to be written evenly between the oldest and the newest one
+fn expired_share(properties: &TableProperties, ttl: &TtlParams, now: CreatedAt) -> f64 {
+    let (Some(oldest), Some(newest)) = (properties.oldest_entry, properties.newest_entry) else {
+        return 0.0;
+    };
+    let cutoff = now.timestamp_millis() - ttl.entry_ttl.as_millis() as i64;
+    let (oldest, newest) = (oldest.timestamp_millis(), newest.timestamp_millis());
+    if cutoff <= oldest {
+        return 0.0;
+    }
+    if cutoff > newest {
+        return 1.0;
+    }
+    (cutoff - oldest) as f64 / (newest - oldest).max(1) as f64
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Drops SSTables whose entries all expired and compacts SSTables whose
+    /// entries mostly expired right away
+    ///
+    /// Does nothing unless TTL is enabled. Background passes run as set with
+    /// `with_ttl_sweeper`.
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error or a compaction fails
+    pub async fn sweep_expired(&self) -> Result<SweepReport, Error> {
+        self.ttl_sweeper
+            .run_pass(&self.buckets, &self.key_range, &self.compactor)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    #[test]
+    fn test_expiry_from_table_properties() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let ttl = TtlParams {
+            entry_ttl: 4 * day,
+            tombstone_ttl: 10 * day,
+        };
+        let at = |days: i64| DateTime::from_timestamp_millis(days * 86_400_000).unwrap();
+        let mut properties = TableProperties {
+            oldest_entry: Some(at(100)),
+            newest_entry: Some(at(110)),
+            ..Default::default()
+        };
+        assert_eq!(expired_share(&properties, &ttl, at(104)), 0.0);
+        assert_eq!(expired_share(&properties, &ttl, at(109)), 0.5);
+        assert!(!fully_expired(&properties, &ttl, at(114)));
+        assert!(fully_expired(&properties, &ttl, at(115)));
+
+        // tombstones are kept for longer
+        properties.tombstone_count = 1;
+        assert!(!fully_expired(&properties, &ttl, at(115)));
+        assert!(fully_expired(&properties, &ttl, at(121)));
+
+        assert_eq!(expired_share(&TableProperties::default(), &ttl, at(200)), 0.0);
+    }
+}
@@ -0,0 +1,101 @@
+use super::{DataStore, WriteReceipt};
+use crate::err::Error;
+use crate::types::Key;
+use serde::{de::DeserializeOwned, Serialize};
+
+impl DataStore<'static, Key> {
+    /// Serializes `value` as JSON and inserts it into the store
+    ///
+    /// # Examples
+    /// ```
+    /// # use tempfile::tempdir;
+    /// use serde::{Deserialize, Serialize};
+    /// use velarixdb::db::DataStore;
+    ///
+    /// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    /// struct BigTech {
+    ///     name: String,
+    ///     rank: i32,
+    /// }
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let root = tempdir().unwrap();
+    ///     let path = root.path().join("velarixdb");
+    ///     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    ///
+    ///     let google = BigTech {
+    ///         name: String::from("Google"),
+    ///         rank: 50,
+    ///     };
+    ///     store.put_json("google", &google).await.unwrap(); // handle error
+    ///
+    ///     let entry: Option<BigTech> = store.get_json("google").await.unwrap();
+    ///     assert_eq!(entry, Some(google));
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ValueEncode`] if `value` cannot be serialized
+    /// or error from [`DataStore::put`]
+    pub async fn put_json<T: Serialize + ?Sized>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: &T,
+    ) -> Result<WriteReceipt, Error> {
+        let encoded = serde_json::to_vec(value).map_err(|err| Error::ValueEncode(Box::new(err)))?;
+        self.put(key, encoded).await
+    }
+
+    /// Retrieves an entry and deserializes it from JSON
+    ///
+    /// Returns `None` if key was not found
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ValueDecode`] if the stored value is not valid JSON for `T`
+    /// or error from [`DataStore::get`]
+    pub async fn get_json<T: DeserializeOwned>(&self, key: impl AsRef<[u8]>) -> Result<Option<T>, Error> {
+        match self.get(key).await? {
+            Some(entry) => serde_json::from_slice(&entry.val)
+                .map(Some)
+                .map_err(|err| Error::ValueDecode(Box::new(err))),
+            None => Ok(None),
+        }
+    }
+
+    /// Serializes `value` with bincode and inserts it into the store
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ValueEncode`] if `value` cannot be serialized
+    /// or error from [`DataStore::put`]
+    #[cfg(feature = "bincode")]
+    pub async fn put_bincode<T: Serialize + ?Sized>(
+        &self,
+        key: impl AsRef<[u8]>,
+        value: &T,
+    ) -> Result<WriteReceipt, Error> {
+        let encoded = bincode::serialize(value).map_err(|err| Error::ValueEncode(err))?;
+        self.put(key, encoded).await
+    }
+
+    /// Retrieves an entry and deserializes it with bincode
+    ///
+    /// Returns `None` if key was not found
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::ValueDecode`] if the stored value cannot be decoded as `T`
+    /// or error from [`DataStore::get`]
+    #[cfg(feature = "bincode")]
+    pub async fn get_bincode<T: DeserializeOwned>(&self, key: impl AsRef<[u8]>) -> Result<Option<T>, Error> {
+        match self.get(key).await? {
+            Some(entry) => bincode::deserialize(&entry.val)
+                .map(Some)
+                .map_err(|err| Error::ValueDecode(err)),
+            None => Ok(None),
+        }
+    }
+}
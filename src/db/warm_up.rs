@@ -0,0 +1,322 @@
+use super::{quota::prefix_end, DataStore};
+use crate::{
+    comparator::Comparator,
+    consts::{ACCESS_PROFILE_FILE_NAME, DEFAULT_WARM_UP_HOT_PREFIXES, SIZE_OF_U32, SIZE_OF_U64},
+    err::Error::{self, *},
+    key_range::Range,
+    sst::SstId,
+    types::{FileNumber, Key, KeyRangeHandle},
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// What `DataStore::warm_up` loads, everything is loaded without throttling by default
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WarmUpOptions {
+    /// Restores bloom filters of SSTables not read since open
+    pub filters: bool,
+
+    /// Caches index lookups of the smallest and biggest key of every SSTable
+    pub index_samples: bool,
+
+    /// Reads blocks holding keys of this many of the hottest prefixes saved
+    /// with `DataStore::save_access_profile`, 0 disables it
+    pub hot_prefixes: usize,
+
+    /// Orders SSTables by the saved access profile, hottest first, instead of
+    /// by reads sampled since open
+    pub use_access_profile: bool,
+
+    /// Bytes read per second, 0 means no limit
+    pub rate: usize,
+}
+
+impl Default for WarmUpOptions {
+    fn default() -> Self {
+        Self {
+            filters: true,
+            index_samples: true,
+            hot_prefixes: DEFAULT_WARM_UP_HOT_PREFIXES,
+            use_access_profile: true,
+            rate: 0,
+        }
+    }
+}
+
+/// Outcome of `DataStore::warm_up`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WarmUpReport {
+    /// Number of SSTables whose filter was restored
+    pub filters_loaded: usize,
+
+    /// Number of index lookups cached
+    pub index_lookups: usize,
+
+    /// Number of prefixes whose blocks were read
+    pub hot_prefixes: usize,
+
+    /// Bytes of filter, index and data files read
+    pub bytes_read: usize,
+}
+
+/// Read traffic saved across restarts to guide warm-up
+///
+/// SSTables are saved by file number since their directory changes when
+/// they are installed or the store is moved
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct AccessProfile {
+    /// Sampled gets that probed each SSTable, hottest first
+    sstables: Vec<(FileNumber, u64)>,
+
+    /// Sampled gets of keys starting with each prefix, hottest first
+    prefixes: Vec<(Key, u64)>,
+}
+
+impl AccessProfile {
+    /// Opens access profile saved in the meta directory `dir`, a missing file holds no traffic
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error or the file is corrupted
+    pub async fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let path = dir.as_ref().join(ACCESS_PROFILE_FILE_NAME);
+        match fs::read(&path).await {
+            Ok(bytes) => Self::deserialize(&bytes).ok_or(MetaCorrupted(path)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(FileRead { path, error }),
+        }
+    }
+
+    /// Writes the profile to a temporary file in the meta directory `dir` and
+    /// renames it over the old one
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn write<P: AsRef<Path>>(&self, dir: P) -> Result<(), Error> {
+        let path = dir.as_ref().join(ACCESS_PROFILE_FILE_NAME);
+        let tmp_path = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path).await.map_err(|error| FileCreation {
+            path: tmp_path.to_owned(),
+            error,
+        })?;
+        file.write_all(&self.serialize())
+            .await
+            .map_err(|error| FileWrite {
+                path: tmp_path.to_owned(),
+                error,
+            })?;
+        file.sync_all().await.map_err(FileSync)?;
+        fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|error| FileRename { path, error })
+    }
+
+    /// Returns sampled gets that probed SSTable `id`
+    fn reads_of(&self, id: SstId) -> u64 {
+        self.sstables
+            .iter()
+            .find(|(number, _)| *number == id.file_number())
+            .map_or(0, |(_, reads)| *reads)
+    }
+
+    /// Serializes SSTable counts then prefix counts, each as a count followed
+    /// by entries, prefixes are length prefixed
+    fn serialize(&self) -> Vec<u8> {
+        let mut serialized_data = Vec::new();
+        serialized_data.extend_from_slice(&(self.sstables.len() as u32).to_le_bytes());
+        for (number, reads) in self.sstables.iter() {
+            serialized_data.extend_from_slice(&number.to_le_bytes());
+            serialized_data.extend_from_slice(&reads.to_le_bytes());
+        }
+        serialized_data.extend_from_slice(&(self.prefixes.len() as u32).to_le_bytes());
+        for (prefix, reads) in self.prefixes.iter() {
+            serialized_data.extend_from_slice(&(prefix.len() as u32).to_le_bytes());
+            serialized_data.extend_from_slice(prefix);
+            serialized_data.extend_from_slice(&reads.to_le_bytes());
+        }
+        serialized_data
+    }
+
+    /// Parses bytes written by `serialize`, returns `None` if they are malformed
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        let mut offset = 0;
+        let mut read_bytes = |len: usize| -> Option<&[u8]> {
+            let bytes = bytes.get(offset..offset + len)?;
+            offset += len;
+            Some(bytes)
+        };
+        let mut profile = Self::default();
+        let count = u32::from_le_bytes(read_bytes(SIZE_OF_U32)?.try_into().ok()?);
+        for _ in 0..count {
+            let number = FileNumber::from_le_bytes(read_bytes(SIZE_OF_U64)?.try_into().ok()?);
+            let reads = u64::from_le_bytes(read_bytes(SIZE_OF_U64)?.try_into().ok()?);
+            profile.sstables.push((number, reads));
+        }
+        let count = u32::from_le_bytes(read_bytes(SIZE_OF_U32)?.try_into().ok()?);
+        for _ in 0..count {
+            let len = u32::from_le_bytes(read_bytes(SIZE_OF_U32)?.try_into().ok()?);
+            let prefix = read_bytes(len as usize)?.to_vec();
+            let reads = u64::from_le_bytes(read_bytes(SIZE_OF_U64)?.try_into().ok()?);
+            profile.prefixes.push((prefix, reads));
+        }
+        (offset == bytes.len()).then_some(profile)
+    }
+}
+
+impl<'a> DataStore<'a, Key> {
+    /// Saves read traffic sampled in the current window so `warm_up` can use
+    /// it after a restart, `close` saves it as well once a get was sampled
+    ///
+    /// Sampling is disabled by default, see `with_read_sampling`
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn save_access_profile(&self) -> Result<(), Error> {
+        let profile = self.read_profile();
+        AccessProfile {
+            sstables: profile
+                .sstables
+                .iter()
+                .filter_map(|(dir, reads)| Some((SstId::of(dir)?.file_number(), *reads as u64)))
+                .collect(),
+            prefixes: profile
+                .prefixes
+                .into_iter()
+                .map(|(prefix, reads)| (prefix, reads as u64))
+                .collect(),
+        }
+        .write(&self.dir.meta)
+        .await
+    }
+
+    /// Loads filters, index lookups and blocks of hot keys ahead of reads so
+    /// the first reads after open don't pay for cold caches
+    ///
+    /// SSTables are warmed up from the hottest to the coldest. Reads are
+    /// spread out to `options.rate` bytes per second if set, so warm-up can
+    /// run alongside foreground traffic.
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error or the saved access profile
+    /// is corrupted
+    pub async fn warm_up(&self, options: WarmUpOptions) -> Result<WarmUpReport, Error> {
+        warm_up(&self.key_range, &self.dir.meta, self.config.comparator, options).await
+    }
+}
+
+/// Warms up SSTables of `key_range`, see `DataStore::warm_up`
+///
+/// Takes the handles it needs rather than the store so `Db` does not hold
+/// the store while reads are throttled
+pub(super) async fn warm_up(
+    key_range: &KeyRangeHandle,
+    meta_dir: &Path,
+    comparator: Comparator,
+    options: WarmUpOptions,
+) -> Result<WarmUpReport, Error> {
+    let profile = if options.use_access_profile {
+        AccessProfile::open(meta_dir).await?
+    } else {
+        AccessProfile::default()
+    };
+    let mut ranges: Vec<Range> = key_range.key_ranges.read().await.values().cloned().collect();
+    ranges
+        .sort_by_key(|range| std::cmp::Reverse((profile.reads_of(range.sst.id()), range.sst.get_hotness())));
+
+    let mut report = WarmUpReport::default();
+    let mut index_sizes: HashMap<PathBuf, usize> = HashMap::new();
+    for range in ranges.iter() {
+        let mut bytes = 0;
+        if options.filters {
+            if let Some(size) = key_range.restore_filter(range.sst.id()).await? {
+                report.filters_loaded += 1;
+                bytes += size;
+            }
+        }
+        if options.index_samples {
+            for key in [&range.smallest_key, &range.biggest_key] {
+                if range.sst.index_cache.get(key).is_none() {
+                    range.sst.get_block_offset(key).await?;
+                    report.index_lookups += 1;
+                    bytes += index_size(&mut index_sizes, &range.sst.files.index).await;
+                }
+            }
+        }
+        report.bytes_read += bytes;
+        throttle(options.rate, bytes).await;
+    }
+
+    for (prefix, _) in profile.prefixes.iter().take(options.hot_prefixes) {
+        let start = comparator.encode_prefix(prefix).into_owned();
+        let end = prefix_end(&start);
+        let mut bytes = 0;
+        for table in key_range.tables_overlapping(&start, &end).await {
+            bytes += table.read_blocks(&start, &end).await?;
+        }
+        report.hot_prefixes += 1;
+        report.bytes_read += bytes;
+        throttle(options.rate, bytes).await;
+    }
+    Ok(report)
+}
+
+/// Returns size of index file at `path`, every lookup reads up to all of it
+async fn index_size(sizes: &mut HashMap<PathBuf, usize>, path: &Path) -> usize {
+    if let Some(size) = sizes.get(path) {
+        return *size;
+    }
+    let size = fs::metadata(path)
+        .await
+        .map_or(0, |metadata| metadata.len() as usize);
+    sizes.insert(path.to_path_buf(), size);
+    size
+}
+
+/// Waits long enough for `bytes` to be read at `rate` bytes per second
+async fn throttle(rate: usize, bytes: usize) {
+    if rate > 0 && bytes > 0 {
+        tokio::time::sleep(Duration::from_secs_f64(bytes as f64 / rate as f64)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_access_profile_round_trip() {
+        let root = tempdir().unwrap();
+        assert_eq!(
+            AccessProfile::open(root.path()).await.unwrap(),
+            AccessProfile::default()
+        );
+
+        let profile = AccessProfile {
+            sstables: vec![(7, 10), (3, 2)],
+            prefixes: vec![(b"user".to_vec(), 9), (Vec::new(), 1)],
+        };
+        profile.write(root.path()).await.unwrap();
+        let reopened = AccessProfile::open(root.path()).await.unwrap();
+        assert_eq!(reopened, profile);
+        assert_eq!(reopened.reads_of(SstId::new(7)), 10);
+        assert_eq!(reopened.reads_of(SstId::new(8)), 0);
+
+        fs::write(root.path().join(ACCESS_PROFILE_FILE_NAME), [1, 0, 0, 0])
+            .await
+            .unwrap();
+        assert!(matches!(
+            AccessProfile::open(root.path()).await,
+            Err(Error::MetaCorrupted(_))
+        ));
+    }
+}
@@ -0,0 +1,204 @@
+use super::{EncryptionKey, KeyId};
+use crate::{consts::SIZE_OF_U32, fs::FileType};
+use aes::{
+    cipher::{InnerIvInit, StreamCipher, StreamCipherSeek},
+    Aes256,
+};
+use std::{fmt::Debug, sync::Arc};
+
+/// AES-256 in counter mode with a big endian counter over the whole nonce
+type Keystream = ctr::Ctr128BE<Aes256>;
+
+/// Block level state of [`Keystream`]
+type KeystreamCore = ctr::CtrCore<Aes256, ctr::flavors::Ctr128BE>;
+
+/// Size of a nonce
+const NONCE_LEN: usize = 16;
+
+/// Key and nonce an SSTable or an epoch of value log is encrypted with,
+/// recorded in the manifest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct FileKey {
+    /// Key the file is encrypted with
+    pub key_id: KeyId,
+
+    /// Random nonce, files never share one
+    pub nonce: [u8; NONCE_LEN],
+}
+
+impl FileKey {
+    /// Number of bytes a serialized `FileKey` takes
+    pub const LEN: usize = SIZE_OF_U32 + NONCE_LEN;
+
+    /// Returns key of a new file encrypted with key `key_id`
+    pub fn new(key_id: KeyId) -> Self {
+        Self {
+            key_id,
+            nonce: rand::random(),
+        }
+    }
+
+    /// Serializes key id followed by nonce
+    pub fn serialize(&self) -> [u8; Self::LEN] {
+        let mut serialized_data = [0; Self::LEN];
+        serialized_data[..SIZE_OF_U32].copy_from_slice(&self.key_id.to_le_bytes());
+        serialized_data[SIZE_OF_U32..].copy_from_slice(&self.nonce);
+        serialized_data
+    }
+
+    /// Parses bytes written by `serialize`, returns `None` if there are too few
+    pub fn deserialize(bytes: &[u8]) -> Option<Self> {
+        let bytes = bytes.get(..Self::LEN)?;
+        Some(Self {
+            key_id: KeyId::from_le_bytes(bytes[..SIZE_OF_U32].try_into().ok()?),
+            nonce: bytes[SIZE_OF_U32..].try_into().ok()?,
+        })
+    }
+}
+
+/// Part of a file encrypted with one key and nonce
+#[derive(Clone)]
+struct Segment {
+    /// Offset the segment starts at, it ends where the next one starts
+    start: u64,
+
+    /// Block cipher of the key
+    cipher: Aes256,
+
+    /// Counter block at offset 0
+    iv: [u8; NONCE_LEN],
+}
+
+/// Encrypts and decrypts a file in place at any offset
+///
+/// A file is made of segments encrypted with keys of their own, e.g. epochs
+/// of value log. Bytes before the first segment are not encrypted, they were
+/// written before the store was
+#[derive(Clone, Default)]
+pub(crate) struct FileCipher {
+    segments: Arc<Vec<Segment>>,
+}
+
+impl FileCipher {
+    /// Returns cipher of a file whose segments start at the given offsets,
+    /// in ascending order, and are encrypted with the given keys and nonces
+    pub fn new(segments: Vec<(u64, &EncryptionKey, [u8; NONCE_LEN])>) -> Self {
+        let segments = segments
+            .into_iter()
+            .map(|(start, key, iv)| Segment {
+                start,
+                cipher: key.cipher().to_owned(),
+                iv,
+            })
+            .collect();
+        Self {
+            segments: Arc::new(segments),
+        }
+    }
+
+    /// Encrypts or decrypts `buf`, which is found at `offset` of the file
+    pub fn apply(&self, offset: u64, buf: &mut [u8]) {
+        let end = offset + buf.len() as u64;
+        for (i, segment) in self.segments.iter().enumerate() {
+            let segment_end = self.segments.get(i + 1).map_or(u64::MAX, |next| next.start);
+            let (from, to) = (offset.max(segment.start), end.min(segment_end));
+            if from >= to {
+                continue;
+            }
+            let core = KeystreamCore::inner_iv_init(segment.cipher.to_owned(), &segment.iv.into());
+            let mut keystream = Keystream::from_core(core);
+            keystream.seek(from);
+            keystream.apply_keystream(&mut buf[(from - offset) as usize..(to - offset) as usize]);
+        }
+    }
+}
+
+impl Debug for FileCipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let starts: Vec<u64> = self.segments.iter().map(|segment| segment.start).collect();
+        f.debug_struct("FileCipher").field("segments", &starts).finish()
+    }
+}
+
+/// Cipher of the files of an SSTable
+#[derive(Clone, Debug)]
+pub(crate) struct TableCipher {
+    /// Key and nonce recorded for the SSTable in the manifest
+    pub key: FileKey,
+
+    /// Ciphers of the data, index, filter and summary files
+    files: [FileCipher; 4],
+}
+
+impl TableCipher {
+    /// Returns cipher of an SSTable encrypted with `key`, whose key material is `encryption_key`
+    pub fn new(key: FileKey, encryption_key: &EncryptionKey) -> Self {
+        // files of the table share its nonce, the first byte tells them apart
+        let file = |tag: u8| {
+            let mut iv = key.nonce;
+            iv[0] ^= tag;
+            FileCipher::new(vec![(0, encryption_key, iv)])
+        };
+        Self {
+            key,
+            files: [file(1), file(2), file(3), file(4)],
+        }
+    }
+
+    /// Returns cipher of the file of `file_type`
+    ///
+    /// # Panics
+    ///
+    /// Panics if SSTables have no file of `file_type`
+    pub fn file(&self, file_type: &FileType) -> FileCipher {
+        match file_type {
+            FileType::Data => self.files[0].to_owned(),
+            FileType::Index => self.files[1].to_owned(),
+            FileType::Filter => self.files[2].to_owned(),
+            FileType::Summary => self.files[3].to_owned(),
+            FileType::ValueLog | FileType::Meta => unreachable!("SSTables have no {:?} file", file_type),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_cipher_applies_at_any_offset() {
+        let key = EncryptionKey::new(1, [7; 32]);
+        let other = EncryptionKey::new(2, [9; 32]);
+        let cipher = FileCipher::new(vec![(10, &key, [1; NONCE_LEN]), (100, &other, [2; NONCE_LEN])]);
+        let plaintext: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        let mut encrypted = plaintext.to_owned();
+        cipher.apply(0, &mut encrypted);
+
+        // bytes before the first segment stay as they are
+        assert_eq!(encrypted[..10], plaintext[..10]);
+        assert_ne!(encrypted[10..100], plaintext[10..100]);
+        assert_ne!(encrypted[100..], plaintext[100..]);
+
+        // any slice decrypts on its own, across segments too
+        for (start, end) in [(0, 200), (13, 14), (50, 150), (99, 101), (117, 200)] {
+            let mut slice = encrypted[start..end].to_vec();
+            cipher.apply(start as u64, &mut slice);
+            assert_eq!(slice, plaintext[start..end]);
+        }
+    }
+
+    #[test]
+    fn test_table_files_use_keystreams_of_their_own() {
+        let key = EncryptionKey::new(1, [7; 32]);
+        let table = TableCipher::new(FileKey::new(1), &key);
+        let mut data = vec![0; 32];
+        let mut index = vec![0; 32];
+        table.file(&FileType::Data).apply(0, &mut data);
+        table.file(&FileType::Index).apply(0, &mut index);
+        assert_ne!(data, index);
+
+        let file_key = FileKey::new(3);
+        assert_eq!(FileKey::deserialize(&file_key.serialize()), Some(file_key));
+        assert_eq!(FileKey::deserialize(&file_key.serialize()[1..]), None);
+    }
+}
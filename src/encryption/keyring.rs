@@ -0,0 +1,134 @@
+use super::{FileCipher, FileKey, KeyId, TableCipher};
+use crate::err::Error;
+use aes::{cipher::KeyInit, Aes256};
+use std::{collections::BTreeMap, fmt::Debug, sync::RwLock};
+
+/// AES-256 key files of a store are encrypted with
+///
+/// Keys are told apart by their id, the key material is never written to disk
+#[derive(Clone)]
+pub struct EncryptionKey {
+    id: KeyId,
+    cipher: Aes256,
+}
+
+impl EncryptionKey {
+    /// Creates key `id` from 32 bytes of key material
+    pub fn new(id: KeyId, key: [u8; 32]) -> Self {
+        Self {
+            id,
+            cipher: Aes256::new(&key.into()),
+        }
+    }
+
+    /// Returns id of the key
+    pub fn id(&self) -> KeyId {
+        self.id
+    }
+
+    /// Returns block cipher of the key
+    pub(crate) fn cipher(&self) -> &Aes256 {
+        &self.cipher
+    }
+}
+
+impl Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptionKey").field("id", &self.id).finish()
+    }
+}
+
+#[derive(Debug, Default)]
+struct KeyringInner {
+    /// Keys the store was opened or rotated with
+    keys: BTreeMap<KeyId, EncryptionKey>,
+
+    /// Key new files are encrypted with, `None` if the store is not encrypted
+    active: Option<KeyId>,
+}
+
+/// Keys of a store, shared by everything that writes or opens its files
+#[derive(Debug, Default)]
+pub(crate) struct Keyring {
+    inner: RwLock<KeyringInner>,
+}
+
+impl Keyring {
+    /// Creates keyring holding `keys`, none of them is active yet
+    pub fn new(keys: &[EncryptionKey]) -> Self {
+        let keys = keys.iter().map(|key| (key.id, key.to_owned())).collect();
+        Self {
+            inner: RwLock::new(KeyringInner { keys, active: None }),
+        }
+    }
+
+    /// Returns id of the key new files are encrypted with, `None` if the store is not encrypted
+    pub fn active(&self) -> Option<KeyId> {
+        self.inner.read().unwrap().active
+    }
+
+    /// Makes key `id` the one new files are encrypted with
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the keyring does not hold key `id`
+    pub fn set_active(&self, id: KeyId) -> Result<(), Error> {
+        let mut inner = self.inner.write().unwrap();
+        if !inner.keys.contains_key(&id) {
+            return Err(Error::EncryptionKeyNotFound(id));
+        }
+        inner.active = Some(id);
+        Ok(())
+    }
+
+    /// Adds `key`, replacing a key with the same id, and makes it the one new files are encrypted with
+    pub fn activate(&self, key: EncryptionKey) {
+        let mut inner = self.inner.write().unwrap();
+        inner.active = Some(key.id);
+        inner.keys.insert(key.id, key);
+    }
+
+    /// Returns cipher of a new SSTable, encrypted with the active key and a
+    /// nonce of its own, `None` if the store is not encrypted
+    pub fn new_table(&self) -> Option<TableCipher> {
+        let inner = self.inner.read().unwrap();
+        let id = inner.active?;
+        Some(TableCipher::new(FileKey::new(id), &inner.keys[&id]))
+    }
+
+    /// Returns cipher of an SSTable encrypted with `key`
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the keyring does not hold the key
+    pub fn table(&self, key: FileKey) -> Result<TableCipher, Error> {
+        let inner = self.inner.read().unwrap();
+        let encryption_key = inner
+            .keys
+            .get(&key.key_id)
+            .ok_or(Error::EncryptionKeyNotFound(key.key_id))?;
+        Ok(TableCipher::new(key, encryption_key))
+    }
+
+    /// Returns cipher of value log whose epochs start at the given offsets and
+    /// are encrypted with the given keys, `None` if it has no epochs
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the keyring does not hold a key of the epochs
+    pub fn value_log(&self, epochs: &[(u64, FileKey)]) -> Result<Option<FileCipher>, Error> {
+        if epochs.is_empty() {
+            return Ok(None);
+        }
+        let inner = self.inner.read().unwrap();
+        let mut segments = Vec::with_capacity(epochs.len());
+        for (start, key) in epochs {
+            let encryption_key = inner
+                .keys
+                .get(&key.key_id)
+                .ok_or(Error::EncryptionKeyNotFound(key.key_id))?;
+            segments.push((*start, encryption_key, key.nonce));
+        }
+        Ok(Some(FileCipher::new(segments)))
+    }
+}
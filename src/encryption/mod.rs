@@ -0,0 +1,28 @@
+//! # Encryption at rest
+//!
+//! SSTable and value log files of an encrypted store are encrypted with
+//! AES-256 in counter mode. The keystream is taken at the absolute offset of
+//! every byte, so any part of a file is read and decrypted on its own and
+//! encrypted files keep the size and offsets of their plaintext.
+//!
+//! Every SSTable is encrypted with the key that was active when it was
+//! written and a random nonce, both recorded in the manifest. The value log is
+//! split in epochs, records appended from the start offset of an epoch are
+//! encrypted with its key and nonce, also recorded in the manifest. A new
+//! epoch starts every time the store is opened and every time the key is
+//! rotated, so a keystream is never used twice.
+//!
+//! Rotating the key does not rewrite anything: flush and compaction write
+//! SSTables with the new key and GC appends the values it moves with it, the
+//! old key is no longer used once the SSTables and value log epochs that
+//! hold it are gone.
+//!
+//! Meta, manifest and the other files of the meta directory are not encrypted
+mod cipher;
+mod keyring;
+pub(crate) use cipher::{FileCipher, FileKey, TableCipher};
+pub use keyring::EncryptionKey;
+pub(crate) use keyring::Keyring;
+
+/// Identifies an [`EncryptionKey`], recorded in the manifest for every file encrypted with it
+pub type KeyId = u32;
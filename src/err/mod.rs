@@ -40,6 +40,12 @@ pub enum Error {
     #[error("Failed to clear file: `{path}`: {error}")]
     FileClear { path: PathBuf, error: io::Error },
 
+    #[error("Failed to truncate file: `{path}`: {error}")]
+    FileTruncate { path: PathBuf, error: io::Error },
+
+    #[error("Failed to rename file to `{path}`: {error}")]
+    FileRename { path: PathBuf, error: io::Error },
+
     #[error("Failed to read file `{path}`: {error}")]
     FileRead { path: PathBuf, error: io::Error },
 
@@ -76,6 +82,24 @@ pub enum Error {
         error: uuid::Error,
     },
 
+    #[error("Manifest file is corrupted: `{0}`")]
+    ManifestCorrupted(PathBuf),
+
+    #[error("Store was created with comparator `{recorded}` but opened with `{requested}`")]
+    ComparatorMismatch { recorded: String, requested: String },
+
+    #[error("Meta file is corrupted: `{0}`")]
+    MetaCorrupted(PathBuf),
+
+    #[error("Store holds files encrypted with key `{0}`, which it was not opened with")]
+    EncryptionKeyNotFound(crate::encryption::KeyId),
+
+    #[error("Store uses features `{0:#04b}` this version does not support")]
+    UnsupportedStoreFeatures(u8),
+
+    #[error("Store was written with format version `{found}`, this version supports up to `{supported}`")]
+    UnsupportedFormatVersion { found: u32, supported: u32 },
+
     #[error("Invalid sstable directory error: `{input_string}`")]
     InvalidSSTableDirectory { input_string: String },
 
@@ -103,6 +127,24 @@ pub enum Error {
     #[error("Key does not exist in value log")]
     KeyNotFoundInValueLog,
 
+    /// Record extended by an append is missing from value log
+    #[error("Record at offset `{0}` extended by an append is missing from value log")]
+    BrokenAppendChain(usize),
+
+    /// Value log ends before the record at an offset does, e.g. a stale offset
+    /// into a value log that was truncated
+    #[error("Value log record at offset `{offset}` runs past the end of `{path}`")]
+    VLogTruncated { path: PathBuf, offset: usize },
+
+    /// Record at an offset of the value log has an impossible header or does
+    /// not match its checksum, e.g. a stale offset into space freed by garbage collection
+    #[error("Value log record at offset `{offset}` of `{path}` is corrupted")]
+    VLogRecordCorrupted { path: PathBuf, offset: usize },
+
+    /// Record at an offset of the value log deletes its key
+    #[error("Value log record at offset `{0}` is a tombstone")]
+    VLogTombstone(usize),
+
     #[error("Key not found, reason: ")]
     KeyNotFound(#[source] Box<Self>),
 
@@ -130,6 +172,9 @@ pub enum Error {
     #[error("Value too large, value must not exceed 2^32 bytes")]
     ValMaxSizeExceeded,
 
+    #[error("Entry needs `{size}` bytes of memtable but memtable capacity is `{capacity}` bytes")]
+    EntryLargerThanBuffer { size: usize, capacity: usize },
+
     #[error("Filter not found")]
     FilterNotFound,
 
@@ -157,12 +202,6 @@ pub enum Error {
     #[error("Range scan error `{0}`")]
     RangeScan(Box<Self>),
 
-    #[error("Flush signal channel was overloaded with signals, please check all signal consumers or try again later")]
-    FlushSignalChannelOverflow,
-
-    #[error("GC update channel was overloaded with data, please check all  consumers")]
-    GCUpdateChannelOverflow,
-
     #[error("Flush signal channel has been closed")]
     FlushSignalChannelClosed,
 
@@ -188,4 +227,79 @@ pub enum Error {
 
     #[error("Entries cannot be empty during flush")]
     EntriesCannotBeEmptyDuringFlush,
+
+    #[error("Store is read-only after repeated background failures, check `DataStore::health` for details")]
+    StoreReadOnly,
+
+    #[error("Failed to encode value: {0}")]
+    ValueEncode(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("Failed to decode value: {0}")]
+    ValueDecode(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("Failed to export entries: {0}")]
+    Export(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("Failed to import entries: {0}")]
+    Import(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("Failed to migrate entries: {0}")]
+    Migrate(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error(
+        "Source entries are not in the order of the store comparator at key `{}`",
+        String::from_utf8_lossy(.0)
+    )]
+    SourceNotSorted(Vec<u8>),
+
+    #[error(
+        "Quota of prefix `{}` exceeded, write would leave {bytes} bytes in {keys} keys",
+        String::from_utf8_lossy(.prefix)
+    )]
+    QuotaExceeded {
+        prefix: Vec<u8>,
+        bytes: usize,
+        keys: usize,
+    },
+
+    #[error("Value of `{0}` bytes is not a counter, counters are 8 bytes")]
+    NotACounter(usize),
+
+    #[error("Counter would overflow")]
+    CounterOverflow,
+
+    #[error("Write rejected by interceptor: {0}")]
+    WriteRejected(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("No write with sequence number `{0}` was applied yet")]
+    SequenceNotApplied(u64),
+
+    #[error("No write with sequence number `{0}` was applied before the timeout")]
+    SequenceWaitTimedOut(u64),
+
+    #[error("SSTable `{0}` does not match its checksum")]
+    SSTableChecksumMismatch(PathBuf),
+
+    #[error("SSTable `{0}` holds keys out of order")]
+    SSTableKeysOutOfOrder(PathBuf),
+
+    #[error(
+        "Compaction output `{}` is missing key `{}` of its inputs",
+        .dir.display(),
+        String::from_utf8_lossy(.key)
+    )]
+    CompactionLostKey { dir: PathBuf, key: Vec<u8> },
+}
+
+impl Error {
+    /// Returns `true` if a file was missing when it was opened or read, e.g. an
+    /// SSTable deleted by compaction after a read picked it
+    pub(crate) fn is_file_not_found(&self) -> bool {
+        match self {
+            Error::FileOpen { error, .. } | Error::FileRead { error, .. } => {
+                error.kind() == io::ErrorKind::NotFound
+            }
+            _ => false,
+        }
+    }
 }
@@ -3,9 +3,11 @@ use crate::types::ByteSerializedEntry;
 use crate::types::Key;
 use crate::types::SkipMapEntries;
 use crate::{
-    consts::{FILTER_FILE_NAME, SIZE_OF_U32, SIZE_OF_U64},
+    consts::{SIZE_OF_U32, SIZE_OF_U64},
+    encryption::FileCipher,
     err::Error,
     fs::{FileAsync, FilterFileNode, FilterFs},
+    sst::SstId,
     util,
 };
 use bit_vec::BitVec;
@@ -36,8 +38,8 @@ pub type NoOfElements = u32;
 /// For more understanding <https://brilliant.org/wiki/bloom-filter/>
 #[derive(Debug)]
 pub struct BloomFilter {
-    /// SSTable directory for bloom filter. Not set until a flush happens
-    pub sst_dir: Option<PathBuf>,
+    /// SSTable the bloom filter belongs to. Not set until a flush happens
+    pub sst_id: Option<SstId>,
 
     /// Number of hash function used by filter
     pub no_of_hash_func: usize,
@@ -55,6 +57,9 @@ pub struct BloomFilter {
 
     /// File path for file that stores filter metadata
     pub file_path: Option<PathBuf>,
+
+    /// Cipher of the filter file, `None` if it is not encrypted
+    pub(crate) cipher: Option<FileCipher>,
 }
 
 impl BloomFilter {
@@ -73,16 +78,20 @@ impl BloomFilter {
         Self {
             no_of_elements: AtomicU32::new(0),
             no_of_hash_func,
-            sst_dir: None,
+            sst_id: None,
             bit_vec: Arc::new(Mutex::new(bv)),
             false_positive_rate,
             file_path: None,
+            cipher: None,
         }
     }
 
     /// Adds key to filter
     pub(crate) fn set(&mut self, key: impl Hash + Copy) {
         let mut bits = self.bit_vec.lock().expect("Failed to lock file");
+        if bits.is_empty() {
+            return;
+        }
         for i in 0..self.no_of_hash_func {
             let hash = self.calculate_hash(key, i);
             let index = (hash % bits.len() as u64) as usize;
@@ -92,8 +101,14 @@ impl BloomFilter {
     }
 
     /// Checks if a key exists or not
+    ///
+    /// A filter without bits (e.g. evicted to cap filter memory) cannot
+    /// rule out any key
     pub(crate) fn contains(&self, key: impl Hash + Copy) -> bool {
         let bits = self.bit_vec.lock().expect("Failed to lock file");
+        if bits.is_empty() {
+            return true;
+        }
         for i in 0..self.no_of_hash_func {
             let hash = self.calculate_hash(key, i);
             let index = (hash % bits.len() as u64) as usize;
@@ -105,18 +120,23 @@ impl BloomFilter {
     }
     /// Writes filter metadata to disk
     ///
-    /// Writes filter to disk, note, this does not include the
-    /// `bit_vec``, bit vec re-computed during crash recovery
+    /// The `bit_vec` is written after the metadata if `persist_bits` is set,
+    /// otherwise it is re-computed from the SSTable entries during crash recovery
     ///
     /// # Errors
     ///
     /// Returns IO error in case write fails
-    pub async fn write(&mut self, dir: impl AsRef<Path> + Send + Sync) -> Result<(), Error> {
-        let file_path = dir.as_ref().join(format!("{}.db", FILTER_FILE_NAME));
+    pub async fn write(
+        &mut self,
+        file_path: impl AsRef<Path> + Send + Sync,
+        persist_bits: bool,
+    ) -> Result<(), Error> {
+        let file_path = file_path.as_ref().to_path_buf();
         let file = FilterFileNode::new(file_path.to_owned(), crate::fs::FileType::Filter)
             .await
             .unwrap();
-        let serialized_data = self.serialize();
+        file.node.set_cipher(self.cipher.to_owned()).await?;
+        let serialized_data = self.serialize(persist_bits);
         file.node.write_all(&serialized_data).await?;
         self.file_path = Some(file_path.to_owned());
         Ok(())
@@ -129,35 +149,45 @@ impl BloomFilter {
 
     /// Retrieves filter meta data from disk
     ///
+    /// Returns `true` if the bits were persisted with the metadata, otherwise
+    /// they are cleared and have to be rebuilt from the SSTable entries
+    ///
     /// # Errors
     ///
     /// Returns IO error in case recovery fails
-    pub async fn recover_meta(&mut self) -> Result<(), Error> {
+    pub async fn recover_meta(&mut self) -> Result<bool, Error> {
         if self.file_path.is_none() {
             return Err(FilterFilePathNotProvided);
         };
-        let (false_pos, no_hash_func, no_elements) =
-            FilterFileNode::recover(self.file_path.as_ref().unwrap()).await?;
+        let (false_pos, no_hash_func, no_elements, bits) =
+            FilterFileNode::recover(self.file_path.as_ref().unwrap(), self.cipher.to_owned()).await?;
         self.false_positive_rate = false_pos;
         self.no_of_hash_func = no_hash_func as usize;
         self.no_of_elements = AtomicU32::new(no_elements);
-        let no_of_bits = Self::calculate_no_of_bits(
-            self.no_of_elements.load(Ordering::Relaxed) as usize,
-            self.false_positive_rate,
-        );
-        self.bit_vec = Arc::new(Mutex::new(BitVec::from_elem(no_of_bits as usize, false)));
-        Ok(())
+        let recovered = bits.is_some();
+        let bits = bits.unwrap_or_else(|| {
+            let no_of_bits = Self::calculate_no_of_bits(no_elements as usize, self.false_positive_rate);
+            BitVec::from_elem(no_of_bits as usize, false)
+        });
+        self.bit_vec = Arc::new(Mutex::new(bits));
+        Ok(recovered)
     }
 
     /// Serializes `BloomFilter` attributes
     ///
     /// Converts `BloomFilter` atttributes such as no_of_hash_func, no_of_elements and
-    /// false positive floating point into byte vector
+    /// false positive floating point into byte vector, followed by the number of bits
+    /// and the bits themselves if `persist_bits` is set
     ///
     /// Returns the byte vector
-    fn serialize(&self) -> ByteSerializedEntry {
+    fn serialize(&self, persist_bits: bool) -> ByteSerializedEntry {
+        let bits = self.bit_vec.lock().expect("Failed to lock file");
         // No of Hash Function + No of Elements  + False Positive
-        let entry_len = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64;
+        let mut entry_len = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64;
+        if persist_bits {
+            // No of Bits + Bits
+            entry_len += SIZE_OF_U32 + bits.len().div_ceil(8);
+        }
 
         let mut serialized_data = Vec::with_capacity(entry_len);
 
@@ -168,12 +198,27 @@ impl BloomFilter {
 
         serialized_data.extend_from_slice(&util::float_to_le_bytes(self.false_positive_rate));
 
+        if persist_bits {
+            serialized_data.extend_from_slice(&(bits.len() as u32).to_le_bytes());
+
+            serialized_data.extend_from_slice(&bits.to_bytes());
+        }
+
         serialized_data
     }
 
-    /// Sets the sst_dir field for [`BloomFilter`]
-    pub fn set_sstable_path(&mut self, path: impl AsRef<Path>) {
-        self.sst_dir = Some(path.as_ref().to_path_buf());
+    /// Drops the bits of every clone of the filter, keeping its metadata
+    ///
+    /// The filter then reports every key as possibly present until it is
+    /// rebuilt on the next lookup from the file at `file_path`
+    pub fn unload(&mut self) {
+        *self.bit_vec.lock().expect("Failed to lock file") = BitVec::new();
+        self.sst_id = None;
+    }
+
+    /// Sets the sst_id field for [`BloomFilter`]
+    pub fn set_sst_id(&mut self, id: SstId) {
+        self.sst_id = Some(id);
     }
 
     /// Resets the [`BloomFilter`] instance
@@ -185,12 +230,13 @@ impl BloomFilter {
         let no_of_hash_func = self.no_of_hash_func;
         let bit_vec = BitVec::from_elem(bits.len(), false);
         Self {
-            sst_dir: None,
+            sst_id: None,
             no_of_hash_func,
             no_of_elements: AtomicU32::new(0),
             bit_vec: Arc::new(Mutex::new(bit_vec)),
             false_positive_rate: self.false_positive_rate,
             file_path: None,
+            cipher: None,
         }
     }
 
@@ -206,16 +252,20 @@ impl BloomFilter {
         self.bit_vec.lock().unwrap().len()
     }
 
+    /// Returns bytes held by the bit vector
+    pub fn memory_size(&self) -> usize {
+        self.num_bits().div_ceil(8)
+    }
+
     /// Returns the current number of hash functions.
     pub fn num_of_hash_functions(&self) -> usize {
         // Retrieve the element count atomically.
         self.no_of_hash_func
     }
 
-    /// Get SSTable path
-    pub fn get_sst_dir(&self) -> &PathBuf {
-        // Retrieve the element count atomically.
-        self.sst_dir.as_ref().unwrap()
+    /// Get SSTable id
+    pub fn get_sst_id(&self) -> SstId {
+        self.sst_id.unwrap()
     }
 
     /// Generates the hashed version of a provided key
@@ -243,12 +293,13 @@ impl Clone for BloomFilter {
     fn clone(&self) -> Self {
         // Implement custom logic here if needed
         BloomFilter {
-            sst_dir: self.sst_dir.clone(),
+            sst_id: self.sst_id,
             no_of_hash_func: self.no_of_hash_func,
             no_of_elements: AtomicU32::load(&self.no_of_elements, Ordering::Relaxed).into(),
             bit_vec: self.bit_vec.clone(),
             false_positive_rate: self.false_positive_rate,
             file_path: self.file_path.to_owned(),
+            cipher: self.cipher.to_owned(),
         }
     }
 }
@@ -256,12 +307,13 @@ impl Clone for BloomFilter {
 impl Default for BloomFilter {
     fn default() -> Self {
         Self {
-            sst_dir: None,
+            sst_id: None,
             no_of_hash_func: Default::default(),
             no_of_elements: AtomicU32::new(0),
             bit_vec: Arc::new(Mutex::new(BitVec::new())),
             false_positive_rate: Default::default(),
             file_path: None,
+            cipher: None,
         }
     }
 }
@@ -290,6 +342,34 @@ mod tests {
         assert!(bloom_filter.contains(k));
     }
 
+    #[tokio::test]
+    async fn test_recover_persisted_bits() {
+        let root = tempfile::tempdir().unwrap();
+        let mut filter = BloomFilter::new(0.01, 100);
+        for i in 0..100 {
+            filter.set(i);
+        }
+        filter.write(root.path().join("filter.db"), true).await.unwrap();
+        let mut recovered = BloomFilter {
+            file_path: filter.file_path.to_owned(),
+            ..Default::default()
+        };
+        assert!(recovered.recover_meta().await.unwrap());
+        assert_eq!(recovered.num_elements(), 100);
+        assert_eq!(
+            *recovered.bit_vec.lock().unwrap(),
+            *filter.bit_vec.lock().unwrap()
+        );
+
+        // filters written without bits are rebuilt from entries
+        let root = tempfile::tempdir().unwrap();
+        filter.write(root.path().join("filter.db"), false).await.unwrap();
+        recovered.file_path = filter.file_path.to_owned();
+        assert!(!recovered.recover_meta().await.unwrap());
+        assert_eq!(recovered.num_bits(), filter.num_bits());
+        assert!(!recovered.contains(1));
+    }
+
     #[test]
     fn test_number_of_elements() {
         let false_positive_rate = 0.01;
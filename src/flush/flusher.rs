@@ -1,10 +1,20 @@
-use crate::consts::FLUSH_SIGNAL;
+use crate::compactors::WriteAmpTracker;
 use crate::flush::flusher::Error::FilterNotProvidedForFlush;
 use crate::flush::flusher::Error::TableSummaryIsNone;
-use crate::types::{self, BucketMapHandle, FlushSignal, ImmutableMemTables, KeyRangeHandle};
-use crate::{err::Error, memtable::MemTable};
+use crate::health::{BackgroundTask, HealthMonitor};
+use crate::key_range::Range;
+use crate::slow_log::SlowLog;
+use crate::types::{
+    self, BucketMapHandle, FlushGeneration, FlushSender, ImmutableMemTables, KeyRangeHandle,
+    MemtableFlushStream,
+};
+use crate::{
+    err::Error,
+    memtable::{MemTable, MemtableId},
+};
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 
 type K = types::Key;
 pub type InActiveMemtable = Arc<MemTable<K>>;
@@ -15,6 +25,43 @@ pub struct Flusher {
     pub(crate) read_only_memtable: ImmutableMemTables<K>,
     pub(crate) bucket_map: BucketMapHandle,
     pub(crate) key_range: KeyRangeHandle,
+
+    /// Ids of memtables currently being flushed, shared by every clone
+    pub(crate) in_flight: Arc<Mutex<MemtableFlushStream>>,
+
+    /// Thresholds for logging slow flushes
+    pub(crate) slow_log: SlowLog,
+
+    /// Records bytes flushed, shared with the compactor
+    pub(crate) write_amp: WriteAmpTracker,
+}
+
+/// Outcome of a flush returned by `DataStore::flush_memtable_async`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FlushReport {
+    /// Number of memtables written to disk by the flush
+    pub memtables: usize,
+
+    /// Number of entries written to disk by the flush
+    pub entries: usize,
+
+    /// Directories of the SSTables created by the flush
+    pub sstables: Vec<PathBuf>,
+
+    /// Ids of the memtables written to disk by the flush, in the order they were flushed
+    pub memtable_ids: Vec<MemtableId>,
+}
+
+/// Sent to flush listeners after every successful flush
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FlushSignal {
+    /// Number of memtables flushed since open
+    pub generation: FlushGeneration,
+
+    /// Memtable flushed last, `None` until a memtable is flushed
+    pub memtable_id: Option<MemtableId>,
 }
 
 impl Flusher {
@@ -27,14 +74,37 @@ impl Flusher {
             read_only_memtable,
             bucket_map,
             key_range,
+            in_flight: Arc::new(Mutex::new(MemtableFlushStream::new())),
+            slow_log: SlowLog::default(),
+            write_amp: WriteAmpTracker::default(),
         }
     }
 
+    /// Marks memtable `table_id` as being flushed
+    ///
+    /// Returns `false` if a flush of the memtable is already in flight
+    pub(crate) fn start_flush(&self, table_id: MemtableId) -> bool {
+        self.in_flight.lock().unwrap().insert(table_id)
+    }
+
+    /// Marks flush of memtable `table_id` as finished, successful or not
+    pub(crate) fn finish_flush(&self, table_id: MemtableId) {
+        self.in_flight.lock().unwrap().remove(&table_id);
+    }
+
+    /// Returns number of memtables currently being flushed
+    pub fn flushes_in_flight(&self) -> usize {
+        self.in_flight.lock().unwrap().len()
+    }
+
     /// Handles a single flush operation
     ///
     /// This method writes memtable to the right bucket and update the
-    /// `KeyRange` with the new sstable
-    pub async fn flush(&mut self, table: InActiveMemtable) -> Result<(), Error> {
+    /// `KeyRange` with the new sstable, entries under pinned prefixes are
+    /// written to sstables of their own buckets
+    ///
+    /// Returns directories of the new sstables
+    pub async fn flush(&mut self, table: InActiveMemtable) -> Result<Vec<PathBuf>, Error> {
         let flush_data = self;
         let table_reader = table;
         if table_reader.entries.is_empty() {
@@ -43,60 +113,93 @@ impl Flusher {
             ));
         }
         let mut bucket_lock = flush_data.bucket_map.write().await;
-        let sst = bucket_lock
-            .insert_to_appropriate_bucket(Arc::new(Box::new(table_reader.as_ref().to_owned())))
+        let ssts = bucket_lock
+            .insert_flushed(Arc::new(Box::new(table_reader.as_ref().to_owned())))
             .await?;
+        let dirs: Vec<PathBuf> = ssts.iter().map(|sst| sst.dir.to_owned()).collect();
+        bucket_lock.publish(&dirs, &[]).await?;
         drop(table_reader);
-        if sst.summary.is_none() {
-            return Err(TableSummaryIsNone);
-        }
-        if sst.filter.is_none() {
-            return Err(FilterNotProvidedForFlush);
+        let mut ranges = Vec::with_capacity(ssts.len());
+        for sst in ssts {
+            if sst.summary.is_none() {
+                return Err(TableSummaryIsNone);
+            }
+            if sst.filter.is_none() {
+                return Err(FilterNotProvidedForFlush);
+            }
+            //IMPORTANT: Don't keep sst entries in memory
+            sst.entries.clear();
+            let summary = sst.summary.clone().unwrap();
+            flush_data.write_amp.record_flush(sst.size);
+            ranges.push(Range::new(summary.smallest_key, summary.biggest_key, sst));
         }
-        //IMPORTANT: Don't keep sst entries in memory
-        sst.entries.clear();
-        let summary = sst.summary.clone().unwrap();
-        flush_data
-            .key_range
-            .set(sst.dir.to_owned(), summary.smallest_key, summary.biggest_key, sst)
-            .await;
-        Ok(())
+        flush_data.key_range.install(ranges, &[]).await;
+        Ok(dirs)
     }
 
     /// Flushes memtable to disk in background
     ///
     /// Handles flushing memtable to disk in background and
-    /// removes it from the read only memtables
+    /// removes it from the read only memtables. Returns `false`
+    /// without spawning if the memtable is already being flushed
     ///
-    /// It also notifies flush listener
+    /// It also bumps the flush generation watched by the flush listener
+    /// and reports the outcome to the health monitor
     pub fn flush_handler(
         &mut self,
-        table_id: impl 'static + AsRef<[u8]> + Send + Sync + Debug,
+        table_id: MemtableId,
         table_to_flush: InActiveMemtable,
-        flush_tx: async_broadcast::Sender<FlushSignal>,
-    ) {
-        let tx = flush_tx.clone();
-        let buckets = self.bucket_map.clone();
-        let key_range = self.key_range.clone();
-        let read_only_memtable = self.read_only_memtable.clone();
+        flush_tx: FlushSender,
+        health: HealthMonitor,
+    ) -> bool {
+        if !self.start_flush(table_id) {
+            return false;
+        }
+        let mut flusher = self.clone();
         tokio::spawn(async move {
-            let mut flusher = Flusher::new(read_only_memtable.clone(), buckets, key_range);
-            match flusher.flush(table_to_flush).await {
-                Ok(_) => {
-                    read_only_memtable.remove(&table_id.as_ref().to_vec());
-                    if let Err(err) = tx.try_broadcast(FLUSH_SIGNAL) {
-                        match err {
-                            async_broadcast::TrySendError::Full(_) => {
-                                log::info!("{}", Error::FlushSignalChannelOverflow.to_string())
-                            }
-                            _ => log::error!("{}", err),
-                        }
-                    }
-                }
-                Err(err) => {
-                    log::error!("{}", err.to_string())
-                }
-            }
+            let _alive = health.task_started(BackgroundTask::Flush);
+            let _ = flusher
+                .flush_started(table_id, table_to_flush, &flush_tx, &health)
+                .await;
         });
+        true
+    }
+
+    /// Flushes memtable `table_id` already marked as being flushed
+    ///
+    /// On success the memtable is removed from the read only memtables and the
+    /// flush generation is bumped, on failure it is left in place to be flushed again.
+    /// Either way the flush is marked finished and the outcome reported to the health monitor
+    pub(crate) async fn flush_started(
+        &mut self,
+        table_id: MemtableId,
+        table_to_flush: InActiveMemtable,
+        flush_tx: &FlushSender,
+        health: &HealthMonitor,
+    ) -> Result<Vec<PathBuf>, Error> {
+        let timer = self.slow_log.background("flush");
+        let res = self.flush(table_to_flush).await;
+        timer.finish();
+        match &res {
+            Ok(dirs) => {
+                log::debug!("Flushed {} to {:?}", table_id, dirs);
+                health.record_success(BackgroundTask::Flush);
+                // removed before the flush is marked finished so it is never flushed twice
+                self.read_only_memtable.remove(&table_id);
+                self.finish_flush(table_id);
+                // never blocks or fails, flushes not yet seen by the listener are coalesced
+                flush_tx.send_modify(|signal| {
+                    signal.generation += 1;
+                    signal.memtable_id = Some(table_id);
+                });
+            }
+            Err(err) => {
+                // memtable is left in place to be flushed again
+                self.finish_flush(table_id);
+                log::error!("Flush of {} failed: {}", table_id, err);
+                health.record_failure(BackgroundTask::Flush, err);
+            }
+        }
+        res
     }
 }
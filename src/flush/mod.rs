@@ -1,2 +1,4 @@
 mod flusher;
+pub use crate::flush::flusher::FlushReport;
+pub use crate::flush::flusher::FlushSignal;
 pub use crate::flush::flusher::Flusher;
@@ -0,0 +1,323 @@
+use super::FileType;
+use crate::encryption::FileCipher;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::{self, SeekFrom},
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    sync::{Arc, Mutex, OnceLock, Weak},
+    task::{ready, Context, Poll},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf},
+    sync::RwLock,
+};
+
+/// Most bytes encrypted for one write, a larger buffer is written in parts
+const MAX_ENCRYPTED_WRITE: usize = 2 * 1024 * 1024;
+
+/// Open file of a [`super::FileNode`], closed by the [`FileHandleCache`]
+/// once too many files are open and reopened on next use
+///
+/// Reads and writes through the slot are decrypted and encrypted with its
+/// cipher, if any. Files are opened for appending, so the slot tracks the
+/// offset of the next read and the end writes are appended at
+#[derive(Debug)]
+pub struct FileSlot {
+    file: Option<File>,
+
+    /// Cipher of the file, `None` if it is not encrypted
+    cipher: Option<FileCipher>,
+
+    /// Offset of the next read
+    pos: u64,
+
+    /// Offset the next write is appended at
+    len: u64,
+}
+
+impl FileSlot {
+    /// Returns `true` if the file is open
+    pub fn is_open(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Replaces the file handle with a newly opened one
+    pub(crate) fn reopen(&mut self, file: File) {
+        self.file = Some(file);
+        self.pos = 0;
+    }
+
+    /// Closes the file handle
+    pub(crate) fn close(&mut self) {
+        self.file = None;
+    }
+
+    /// Returns cipher of the file, `None` if it is not encrypted
+    pub(crate) fn cipher(&self) -> Option<FileCipher> {
+        self.cipher.to_owned()
+    }
+
+    /// Encrypts and decrypts the file, which is `len` bytes long, with `cipher`
+    pub(crate) fn set_cipher(&mut self, cipher: Option<FileCipher>, len: u64) {
+        self.cipher = cipher;
+        self.len = len;
+    }
+
+    /// Truncates or extends the file to `len` bytes
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error in case it occurs
+    pub(crate) async fn set_len(&mut self, len: u64) -> io::Result<()> {
+        let file: &File = self;
+        file.set_len(len).await?;
+        self.len = len;
+        Ok(())
+    }
+}
+
+impl From<File> for FileSlot {
+    fn from(file: File) -> Self {
+        Self {
+            file: Some(file),
+            cipher: None,
+            pos: 0,
+            len: 0,
+        }
+    }
+}
+
+impl AsyncRead for FileSlot {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut **self).poll_read(cx, buf))?;
+        let read = &mut buf.filled_mut()[filled..];
+        if let Some(cipher) = self.cipher.as_ref() {
+            cipher.apply(self.pos, read);
+        }
+        self.pos += read.len() as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for FileSlot {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let written = match self.cipher.to_owned() {
+            Some(cipher) => {
+                let mut encrypted = buf[..buf.len().min(MAX_ENCRYPTED_WRITE)].to_vec();
+                cipher.apply(self.len, &mut encrypted);
+                ready!(Pin::new(&mut **self).poll_write(cx, &encrypted))?
+            }
+            None => ready!(Pin::new(&mut **self).poll_write(cx, buf))?,
+        };
+        // appended, the next read starts at the end
+        self.len += written as u64;
+        self.pos = self.len;
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut **self).poll_shutdown(cx)
+    }
+}
+
+impl AsyncSeek for FileSlot {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        Pin::new(&mut **self).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let pos = ready!(Pin::new(&mut **self).poll_complete(cx))?;
+        self.pos = pos;
+        Poll::Ready(Ok(pos))
+    }
+}
+
+impl Deref for FileSlot {
+    type Target = File;
+
+    /// # Panics
+    ///
+    /// Panics if the file was closed, file nodes reopen it before handing it out
+    fn deref(&self) -> &File {
+        self.file.as_ref().expect("file handle used after it was closed")
+    }
+}
+
+impl DerefMut for FileSlot {
+    fn deref_mut(&mut self) -> &mut File {
+        self.file.as_mut().expect("file handle used after it was closed")
+    }
+}
+
+#[derive(Debug, Default)]
+struct FileHandleCacheInner {
+    /// Tracked handles mapped to their last access tick
+    handles: HashMap<usize, (Weak<RwLock<FileSlot>>, u64)>,
+
+    /// Maps access tick to handle, the first entry is the least recently used handle
+    recency: BTreeMap<u64, usize>,
+
+    /// Maximum number of open handles, 0 means no limit
+    capacity: usize,
+
+    /// Monotonic counter incremented on every access
+    tick: u64,
+}
+
+/// Bounds the number of SSTable files kept open
+///
+/// Every SSTable holds a data and an index file, without a bound open
+/// descriptors grow with the number of SSTables. Once more than `capacity`
+/// files are open the least recently used ones are closed, they are reopened
+/// transparently on next use. Files in use are never closed.
+///
+/// Descriptors are a process resource so one cache is shared by every store
+#[derive(Debug, Default)]
+pub(crate) struct FileHandleCache {
+    inner: Mutex<FileHandleCacheInner>,
+}
+
+impl FileHandleCache {
+    /// Returns the cache shared by every store in the process
+    pub fn global() -> &'static FileHandleCache {
+        static CACHE: OnceLock<FileHandleCache> = OnceLock::new();
+        CACHE.get_or_init(FileHandleCache::default)
+    }
+
+    /// Returns `true` if files of `file_type` are tracked, value log and
+    /// metadata files are few and always kept open
+    pub fn tracks(file_type: &FileType) -> bool {
+        matches!(file_type, FileType::Data | FileType::Index)
+    }
+
+    /// Sets maximum number of open handles, closing handles if needed
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.capacity = capacity;
+        inner.evict_to_capacity();
+    }
+
+    /// Marks `handle` as recently used, closing least recently used handles
+    /// if too many are open
+    pub fn touch(&self, handle: &Arc<RwLock<FileSlot>>) {
+        let id = Arc::as_ptr(handle) as usize;
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        match inner.handles.insert(id, (Arc::downgrade(handle), tick)) {
+            Some((_, previous_tick)) => {
+                inner.recency.remove(&previous_tick);
+                inner.recency.insert(tick, id);
+            }
+            None => {
+                inner.recency.insert(tick, id);
+                inner.evict_to_capacity();
+            }
+        }
+    }
+
+    /// Returns number of tracked open handles
+    #[cfg(test)]
+    pub fn open_handles(&self) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .handles
+            .values()
+            .filter(|(handle, _)| handle.strong_count() > 0)
+            .count()
+    }
+}
+
+impl FileHandleCacheInner {
+    /// Closes least recently used handles until at most `capacity` are open
+    ///
+    /// Dropped handles are forgotten first, handles locked by a reader or
+    /// writer are skipped
+    fn evict_to_capacity(&mut self) {
+        if self.capacity == 0 || self.handles.len() <= self.capacity {
+            return;
+        }
+        self.handles.retain(|_, (handle, _)| handle.strong_count() > 0);
+        let live: HashSet<u64> = self.handles.values().map(|(_, tick)| *tick).collect();
+        self.recency.retain(|tick, _| live.contains(tick));
+
+        let mut excess = self.handles.len().saturating_sub(self.capacity);
+        let candidates: Vec<(u64, usize)> = self.recency.iter().map(|(tick, id)| (*tick, *id)).collect();
+        for (tick, id) in candidates {
+            if excess == 0 {
+                break;
+            }
+            let closed = match self.handles.get(&id).and_then(|(handle, _)| handle.upgrade()) {
+                Some(handle) => match handle.try_write() {
+                    Ok(mut slot) => {
+                        slot.close();
+                        true
+                    }
+                    Err(_) => false,
+                },
+                None => true,
+            };
+            if closed {
+                self.handles.remove(&id);
+                self.recency.remove(&tick);
+                excess -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    async fn open_slot(path: &std::path::Path) -> Arc<RwLock<FileSlot>> {
+        let file = File::create(path).await.unwrap();
+        Arc::new(RwLock::new(FileSlot::from(file)))
+    }
+
+    #[tokio::test]
+    async fn test_file_handle_cache_closes_least_recently_used() {
+        let root = tempdir().unwrap();
+        let cache = FileHandleCache::default();
+        cache.set_capacity(2);
+        let first = open_slot(&root.path().join("first")).await;
+        let second = open_slot(&root.path().join("second")).await;
+        let third = open_slot(&root.path().join("third")).await;
+        cache.touch(&first);
+        cache.touch(&second);
+        cache.touch(&first);
+
+        // second is the least recently used
+        cache.touch(&third);
+        assert_eq!(cache.open_handles(), 2);
+        assert!(first.read().await.is_open());
+        assert!(!second.read().await.is_open());
+        assert!(third.read().await.is_open());
+
+        // handles in use are skipped
+        let guard = first.write().await;
+        cache.touch(&second);
+        drop(guard);
+        assert!(first.read().await.is_open());
+        assert!(!third.read().await.is_open());
+
+        // dropped handles make room without closing others
+        drop(first);
+        let fourth = open_slot(&root.path().join("fourth")).await;
+        cache.touch(&fourth);
+        assert!(fourth.read().await.is_open());
+        assert_eq!(cache.open_handles(), 2);
+    }
+}
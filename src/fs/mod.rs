@@ -1,19 +1,20 @@
 use crate::{
     consts::{EOF, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8},
+    encryption::FileCipher,
     err::Error::{self, *},
     filter::{FalsePositive, NoHashFunc, NoOfElements},
     index::RangeOffset,
     key_range::{BiggestKey, SmallestKey},
     load_buffer,
     memtable::{Entry, SkipMapValue},
-    types::{
-        CreatedAt, IsTombStone, Key, LastModified, NoBytesRead, SkipMapEntries, VLogHead, VLogTail,
-        ValOffset, Value,
-    },
+    sst::TableProperties,
+    types::{CreatedAt, IsTombStone, Key, NoBytesRead, SkipMapEntries, ValOffset},
     util,
-    vlog::ValueLogEntry,
+    vlog::{RecordHeader, RecordType, ValueLogEntry},
 };
 use async_trait::async_trait;
+use bit_vec::BitVec;
+use bytes::Bytes;
 use crossbeam_skiplist::SkipMap;
 use std::{
     fmt::Debug,
@@ -22,6 +23,10 @@ use std::{
     path::{Path, PathBuf},
     sync::Arc,
 };
+mod handles;
+pub(crate) use handles::FileHandleCache;
+pub use handles::FileSlot;
+
 use tokio::{
     fs::{self, File, OpenOptions},
     io::{self, AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
@@ -75,11 +80,13 @@ pub trait FileAsync: F {
 
     async fn clear(&self) -> Result<(), Error>;
 
+    async fn truncate(&self, len: usize) -> Result<(), Error>;
+
     async fn remove_dir_all(&self) -> Result<(), Error>;
 
-    async fn w_lock(&self) -> WGuard<File>;
+    async fn w_lock(&self) -> Result<WGuard<FileSlot>, Error>;
 
-    async fn r_lock(&self) -> RGuard<File>;
+    async fn r_lock(&self) -> Result<RGuard<FileSlot>, Error>;
 
     async fn size(&self) -> usize {
         return self.metadata().await.unwrap().len() as usize;
@@ -109,19 +116,31 @@ pub trait DataFs: F {
 #[async_trait]
 pub trait VLogFs: F {
     async fn new(path: impl P, file_type: FileType) -> Result<Self, Error>;
-    async fn get(&self, start_offset: usize) -> Result<Option<(Key, bool)>, Error>;
-    async fn recover(&self, start_offset: usize) -> Result<Vec<ValueLogEntry>, Error>;
+    /// Returns value and type of the record at `start_offset`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::VLogTruncated`] if the file ends before the record does,
+    /// [`Error::VLogRecordCorrupted`] if its header is impossible or it does not
+    /// match its checksum, or error in case there is an IO error
+    async fn get(&self, start_offset: usize) -> Result<(Bytes, RecordType), Error>;
+    async fn recover(&self, start_offset: usize) -> Result<(Vec<ValueLogEntry>, NoBytesRead), Error>;
     async fn read_chunk_to_garbage_collect(
         &self,
         bytes_to_collect: usize,
         offset: u64,
     ) -> Result<(Vec<ValueLogEntry>, NoBytesRead), Error>;
+    async fn read_bytes(&self, start_offset: usize, len: usize) -> Result<Vec<u8>, Error>;
 }
 
 #[async_trait]
 pub trait FilterFs: F {
     async fn new(path: impl P, file_type: FileType) -> Result<Self, Error>;
-    async fn recover(path: impl P) -> Result<(FalsePositive, NoHashFunc, NoOfElements), Error>;
+    /// Returns filter metadata and bits, bits are `None` if they were not persisted
+    async fn recover(
+        path: impl P,
+        cipher: Option<FileCipher>,
+    ) -> Result<(FalsePositive, NoHashFunc, NoOfElements, Option<BitVec>), Error>;
 }
 
 #[async_trait]
@@ -129,26 +148,30 @@ pub trait FilterFs: F {
 pub trait IndexFs: F {
     async fn new(path: impl P, file_type: FileType) -> Result<Self, Error>;
     async fn get_from_index(&self, searched_key: &[u8]) -> Result<Option<u32>, Error>;
-    #[allow(dead_code)] // will be used for range queries(future)
-    async fn get_block_range(&self, start_key: &[u8], end_key: &[u8]) -> Result<RangeOffset, Error>;
+    /// Returns byte range of the data file blocks that can hold keys within
+    /// `[start_key, end_key]`, `None` if every block ends before `start_key`
+    async fn get_block_range(&self, start_key: &[u8], end_key: &[u8]) -> Result<Option<RangeOffset>, Error>;
 }
 
 #[async_trait]
 pub trait SummaryFs: F {
     async fn new(path: impl P, file_type: FileType) -> Result<Self, Error>;
-    async fn recover(path: impl P) -> Result<(SmallestKey, BiggestKey), Error>;
+    /// Returns key range along with the properties recorded when the SSTable was written
+    async fn recover(
+        path: impl P,
+        cipher: Option<FileCipher>,
+    ) -> Result<(SmallestKey, BiggestKey, TableProperties), Error>;
 }
 
 #[async_trait]
 pub trait MetaFs: F {
     async fn new(path: impl P, file_type: FileType) -> Result<Self, Error>;
-    async fn recover(path: impl P) -> Result<(VLogHead, VLogTail, CreatedAt, LastModified), Error>;
 }
 
 #[derive(Debug, Clone)]
 pub struct FileNode {
     pub file_path: PathBuf,
-    pub file: Arc<RwLock<File>>,
+    pub file: Arc<RwLock<FileSlot>>,
     pub file_type: FileType,
 }
 
@@ -157,11 +180,52 @@ impl ThreadSharable for FileNode {}
 impl FileNode {
     pub async fn new(path: impl P, file_type: FileType) -> Result<Self, Error> {
         let file = FileNode::create(path.as_ref()).await?;
-        Ok(Self {
+        let node = Self {
             file_type,
-            file: Arc::new(RwLock::new(file)),
+            file: Arc::new(RwLock::new(FileSlot::from(file))),
             file_path: path.as_ref().to_path_buf(),
-        })
+        };
+        node.touch_handle();
+        Ok(node)
+    }
+
+    /// Marks file handle as recently used if the handle cache bounds files of its type
+    fn touch_handle(&self) {
+        if FileHandleCache::tracks(&self.file_type) {
+            FileHandleCache::global().touch(&self.file);
+        }
+    }
+
+    /// Closes the file handle, it is reopened on next use
+    ///
+    /// Windows refuses to rename or delete a directory holding open files
+    pub(crate) async fn close(&self) {
+        self.file.write().await.close();
+    }
+
+    /// Sets maximum number of SSTable files kept open by every store in the process,
+    /// least recently used files are closed beyond it and reopened on next use.
+    /// A limit of 0 keeps every file open
+    pub(crate) fn set_open_files_limit(limit: usize) {
+        FileHandleCache::global().set_capacity(limit);
+    }
+
+    /// Encrypts what is written to the file and decrypts what is read from
+    /// it with `cipher`, `None` if the file is not encrypted
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub(crate) async fn set_cipher(&self, cipher: Option<FileCipher>) -> Result<(), Error> {
+        let mut file = self.w_lock().await?;
+        let len = file.metadata().await.map_err(GetFileMetaData)?.len();
+        file.set_cipher(cipher, len);
+        Ok(())
+    }
+
+    /// Returns cipher of the file, `None` if it is not encrypted
+    pub(crate) async fn cipher(&self) -> Option<FileCipher> {
+        self.file.read().await.cipher()
     }
 }
 
@@ -192,7 +256,7 @@ impl FileAsync for FileNode {
     }
 
     async fn metadata(&self) -> Result<Metadata, Error> {
-        let file = self.r_lock().await;
+        let file = self.r_lock().await?;
         Ok(file.metadata().await.map_err(GetFileMetaData)?)
     }
 
@@ -204,7 +268,7 @@ impl FileAsync for FileNode {
     }
 
     async fn read_buf(&self, buf: &mut Buf) -> Result<usize, Error> {
-        let mut file = self.w_lock().await;
+        let mut file = self.w_lock().await?;
         Ok(file.read(buf).await.map_err(|err| FileRead {
             path: self.file_path.clone(),
             error: err,
@@ -212,7 +276,7 @@ impl FileAsync for FileNode {
     }
 
     async fn write_all(&self, buf: &Buf) -> Result<(), Error> {
-        let mut file = self.w_lock().await;
+        let mut file = self.w_lock().await?;
         Ok(file.write_all(buf).await.map_err(|err| FileWrite {
             path: self.file_path.clone(),
             error: err,
@@ -220,25 +284,33 @@ impl FileAsync for FileNode {
     }
 
     async fn clear(&self) -> Result<(), Error> {
-        let file = self.w_lock().await;
+        let mut file = self.w_lock().await?;
         Ok(file.set_len(0).await.map_err(|err| FileClear {
             path: self.file_path.clone(),
             error: err,
         })?)
     }
 
+    async fn truncate(&self, len: usize) -> Result<(), Error> {
+        let mut file = self.w_lock().await?;
+        Ok(file.set_len(len as u64).await.map_err(|err| FileTruncate {
+            path: self.file_path.clone(),
+            error: err,
+        })?)
+    }
+
     async fn sync_all(&self) -> Result<(), Error> {
-        let file = self.w_lock().await;
+        let file = self.w_lock().await?;
         Ok(file.sync_all().await.map_err(Error::FileSync)?)
     }
 
     async fn flush(&self) -> Result<(), Error> {
-        let mut file = self.w_lock().await;
+        let mut file = self.w_lock().await?;
         Ok(file.flush().await.map_err(Error::FileSync)?)
     }
 
     async fn seek(&self, start_offset: u64) -> Result<u64, Error> {
-        let mut file = self.w_lock().await;
+        let mut file = self.w_lock().await?;
         Ok(file.seek(SeekFrom::Start(start_offset)).await.map_err(FileSeek)?)
     }
 
@@ -246,12 +318,33 @@ impl FileAsync for FileNode {
         Ok(fs::remove_dir_all(&self.file_path).await.map_err(DirDelete)?)
     }
 
-    async fn w_lock(&self) -> WGuard<File> {
-        self.file.write().await
+    async fn w_lock(&self) -> Result<WGuard<FileSlot>, Error> {
+        let mut slot = self.file.write().await;
+        if !slot.is_open() {
+            // closed by the handle cache, existing content is only read or appended to
+            let file = OpenOptions::new()
+                .read(true)
+                .append(true)
+                .open(&self.file_path)
+                .await
+                .map_err(|err| FileOpen {
+                    path: self.file_path.to_owned(),
+                    error: err,
+                })?;
+            slot.reopen(file);
+        }
+        self.touch_handle();
+        Ok(slot)
     }
 
-    async fn r_lock(&self) -> RGuard<File> {
-        self.file.read().await
+    async fn r_lock(&self) -> Result<RGuard<FileSlot>, Error> {
+        let slot = self.file.read().await;
+        if slot.is_open() {
+            self.touch_handle();
+            return Ok(slot);
+        }
+        drop(slot);
+        Ok(self.w_lock().await?.downgrade())
     }
 }
 
@@ -272,7 +365,7 @@ impl DataFs for DataFileNode {
         let entries = Arc::new(SkipMap::new());
         let mut total_bytes_read = 0;
         let path = &self.node.file_path;
-        let mut file = self.node.file.write().await;
+        let mut file = self.node.w_lock().await?;
         file.seek(std::io::SeekFrom::Start(0)).await.map_err(FileSeek)?;
 
         loop {
@@ -319,7 +412,7 @@ impl DataFs for DataFileNode {
                 key,
                 SkipMapValue::new(
                     value_offset as usize,
-                    util::milliseconds_to_datetime(created_at),
+                    util::timestamp_to_datetime(created_at),
                     is_tombstone,
                 ),
             );
@@ -333,7 +426,7 @@ impl DataFs for DataFileNode {
         searched_key: &[u8],
     ) -> Result<Option<(ValOffset, CreatedAt, IsTombStone)>, Error> {
         let path = &self.node.file_path;
-        let mut file = self.node.file.write().await;
+        let mut file = self.node.w_lock().await?;
         file.seek(std::io::SeekFrom::Start(offset.into()))
             .await
             .map_err(FileSeek)?;
@@ -374,12 +467,17 @@ impl DataFs for DataFileNode {
             let created_at = u64::from_le_bytes(created_at_bytes);
             let value_offset = u32::from_le_bytes(val_offset_bytes);
             let is_tombstone = is_tombstone_byte[0] == 1;
-            if key == searched_key {
-                return Ok(Some((
-                    value_offset as usize,
-                    util::milliseconds_to_datetime(created_at),
-                    is_tombstone,
-                )));
+            match key.as_slice().cmp(searched_key) {
+                std::cmp::Ordering::Equal => {
+                    return Ok(Some((
+                        value_offset as usize,
+                        util::timestamp_to_datetime(created_at),
+                        is_tombstone,
+                    )))
+                }
+                // keys are sorted, the searched key would have been in this block
+                std::cmp::Ordering::Greater => return Ok(None),
+                std::cmp::Ordering::Less => {}
             }
         }
     }
@@ -389,17 +487,20 @@ impl DataFs for DataFileNode {
         range_offset: RangeOffset,
     ) -> Result<Vec<Entry<Key, ValOffset>>, Error> {
         let mut entries = Vec::new();
-        let mut total_bytes_read = 0;
+        let mut position = range_offset.start_offset as usize;
         let path = &self.node.file_path;
-        let mut file = self.node.file.write().await;
+        let mut file = self.node.w_lock().await?;
         file.seek(std::io::SeekFrom::Start((range_offset.start_offset) as u64))
             .await
             .map_err(FileSeek)?;
 
         loop {
+            if position >= range_offset.end_offset as usize {
+                return Ok(entries);
+            }
             let mut key_len_bytes = [0; SIZE_OF_U32];
             let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
-            total_bytes_read += bytes_read;
+            position += bytes_read;
             if bytes_read == 0 {
                 return Ok(entries);
             }
@@ -407,28 +508,28 @@ impl DataFs for DataFileNode {
             let key_len = u32::from_le_bytes(key_len_bytes);
             let mut key = vec![0; key_len as usize];
             bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
-            total_bytes_read += bytes_read;
+            position += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
 
             let mut val_offset_bytes = [0; SIZE_OF_U32];
             bytes_read = load_buffer!(file, &mut val_offset_bytes, path.to_owned())?;
-            total_bytes_read += bytes_read;
+            position += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
 
             let mut created_at_bytes = [0; SIZE_OF_U64];
             bytes_read = load_buffer!(file, &mut created_at_bytes, path.to_owned())?;
-            total_bytes_read += bytes_read;
+            position += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
 
             let mut is_tombstone_byte = [0; SIZE_OF_U8];
             bytes_read = load_buffer!(file, &mut is_tombstone_byte, path.to_owned())?;
-            total_bytes_read += bytes_read;
+            position += bytes_read;
             if bytes_read == 0 {
                 return Err(FileNode::unexpected_eof());
             }
@@ -439,13 +540,9 @@ impl DataFs for DataFileNode {
             entries.push(Entry::new(
                 key,
                 value_offset,
-                util::milliseconds_to_datetime(created_at),
+                util::timestamp_to_datetime(created_at),
                 is_tombstone,
             ));
-
-            if total_bytes_read as u32 >= range_offset.end_offset {
-                return Ok(entries);
-            }
         }
     }
 }
@@ -463,113 +560,130 @@ impl VLogFs for VLogFileNode {
         let node = FileNode::new(path, file_type).await?;
         Ok(VLogFileNode { node })
     }
-    async fn get(&self, start_offset: usize) -> Result<Option<(Value, bool)>, Error> {
+    async fn get(&self, start_offset: usize) -> Result<(Bytes, RecordType), Error> {
         let path = &self.node.file_path;
-
-        let mut file = self.node.file.write().await;
+        let truncated = |err: Error| match err {
+            UnexpectedEOF(_) => VLogTruncated {
+                path: path.to_owned(),
+                offset: start_offset,
+            },
+            err => err,
+        };
+
+        let corrupted = || VLogRecordCorrupted {
+            path: path.to_owned(),
+            offset: start_offset,
+        };
+
+        let mut file = self.node.w_lock().await?;
+        let file_len = file.metadata().await.map_err(GetFileMetaData)?.len() as usize;
         file.seek(std::io::SeekFrom::Start((start_offset) as u64))
             .await
             .map_err(FileSeek)?;
 
-        let mut key_len_bytes = [0; SIZE_OF_U32];
-        let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
+        // the record is read with one call, every file read is a round trip to the blocking pool
+        let mut record = vec![0; RecordHeader::LEN];
+        let bytes_read = load_buffer!(file, &mut record, path.to_owned())?;
         if bytes_read == 0 {
-            return Ok(None);
+            return Err(truncated(FileNode::unexpected_eof()));
         }
+        FileNode::read_remaining(&mut file, &mut record[bytes_read..], path)
+            .await
+            .map_err(truncated)?;
 
-        let key_len = u32::from_le_bytes(key_len_bytes);
-        let mut val_len_bytes = [0; SIZE_OF_U32];
-        bytes_read = load_buffer!(file, &mut val_len_bytes, path.to_owned())?;
-        if bytes_read == 0 {
-            return Err(FileNode::unexpected_eof());
+        // lengths are checked before the record is allocated, a stale offset
+        // would otherwise allocate whatever its bytes decode to
+        let header = RecordHeader::decode(&record);
+        if header.is_impossible() {
+            return Err(corrupted());
         }
-
-        let val_len = u32::from_le_bytes(val_len_bytes);
-        let mut creation_date_bytes = [0; SIZE_OF_U64];
-        bytes_read = load_buffer!(file, &mut creation_date_bytes, path.to_owned())?;
-        if bytes_read == 0 {
-            return Err(FileNode::unexpected_eof());
+        if start_offset + header.record_len() > file_len {
+            return Err(truncated(FileNode::unexpected_eof()));
         }
 
-        let _ = u64::from_le_bytes(creation_date_bytes);
-        let mut istombstone_bytes = [0; SIZE_OF_U8];
-        let mut bytes_read = load_buffer!(file, &mut istombstone_bytes, path.to_owned())?;
-        if bytes_read == 0 {
-            return Err(FileNode::unexpected_eof());
-        }
-
-        let is_tombstone = istombstone_bytes[0] == 1;
-        let mut key = vec![0; key_len as usize];
-        bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
-        if bytes_read == 0 {
-            return Err(FileNode::unexpected_eof());
-        }
-        let mut value = vec![0; val_len as usize];
-        bytes_read = load_buffer!(file, &mut value, path.to_owned())?;
-        if bytes_read == 0 {
-            return Err(FileNode::unexpected_eof());
+        record.resize(header.record_len(), 0);
+        FileNode::read_remaining(&mut file, &mut record[RecordHeader::LEN..], path)
+            .await
+            .map_err(truncated)?;
+        if !header.checksum_matches(&record) {
+            return Err(corrupted());
         }
-
-        Ok(Some((value, is_tombstone)))
+        // keep the allocation, only the value is returned
+        record.truncate(RecordHeader::LEN + header.key_len + header.val_len);
+        record.drain(..RecordHeader::LEN + header.key_len);
+        Ok((Bytes::from(record), header.record_type))
     }
 
-    async fn recover(&self, start_offset: usize) -> Result<Vec<ValueLogEntry>, Error> {
+    async fn recover(&self, start_offset: usize) -> Result<(Vec<ValueLogEntry>, NoBytesRead), Error> {
         let path = &self.node.file_path;
         let mut entries = Vec::new();
-        let mut file = self.node.file.write().await;
+        let mut file = self.node.w_lock().await?;
+        let file_len = file.metadata().await.map_err(GetFileMetaData)?.len() as usize;
         file.seek(std::io::SeekFrom::Start((start_offset) as u64))
             .await
             .map_err(FileSeek)?;
-
-        loop {
-            let mut key_len_bytes = [0; SIZE_OF_U32];
-            let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                return Ok(entries);
-            }
-
-            let key_len = u32::from_le_bytes(key_len_bytes);
-            let mut val_len_bytes = [0; SIZE_OF_U32];
-            bytes_read = load_buffer!(file, &mut val_len_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-
-            let val_len = u32::from_le_bytes(val_len_bytes);
-            let mut creation_date_bytes = [0; SIZE_OF_U64];
-            bytes_read = load_buffer!(file, &mut creation_date_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-
-            let created_at = u64::from_le_bytes(creation_date_bytes);
-            let mut istombstone_bytes = [0; SIZE_OF_U8];
-            let mut bytes_read = load_buffer!(file, &mut istombstone_bytes, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
+        let mut reader = io::BufReader::new(&mut *file);
+        let read_err = |err| FileRead {
+            path: path.to_owned(),
+            error: err,
+        };
+
+        // Replay stops at the first record that runs past the end of file, torn by
+        // a crash during append, or that `get` would refuse, i.e. has an impossible
+        // header or does not match its checksum. The caller truncates from there
+        let mut offset = start_offset;
+        let mut header_bytes = [0; RecordHeader::LEN];
+        while offset + RecordHeader::LEN <= file_len {
+            reader.read_exact(&mut header_bytes).await.map_err(read_err)?;
+            let header = RecordHeader::decode(&header_bytes);
+            let record_len = header.record_len();
+            if header.is_impossible() || offset + record_len > file_len {
+                break;
             }
-
-            let is_tombstone = istombstone_bytes[0] == 1;
-            let mut key = vec![0; key_len as usize];
-            bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
+            if header.record_type == RecordType::Hole {
+                // the value is a punched hole, it is skipped instead of read
+                io::copy(
+                    &mut (&mut reader).take((record_len - RecordHeader::LEN) as u64),
+                    &mut io::sink(),
+                )
+                .await
+                .map_err(read_err)?;
+                entries.push(ValueLogEntry {
+                    ksize: header.key_len,
+                    vsize: header.val_len,
+                    key: Vec::new(),
+                    value: Vec::new(),
+                    created_at: util::timestamp_to_datetime(header.created_at),
+                    is_tombstone: false,
+                    record_type: header.record_type,
+                    checksummed: header.checksummed,
+                });
+                offset += record_len;
+                continue;
             }
-
-            let mut value = vec![0; val_len as usize];
-            bytes_read = load_buffer!(file, &mut value, path.to_owned())?;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
+            let mut record = vec![0; record_len];
+            record[..RecordHeader::LEN].copy_from_slice(&header_bytes);
+            reader
+                .read_exact(&mut record[RecordHeader::LEN..])
+                .await
+                .map_err(read_err)?;
+            if !header.checksum_matches(&record) {
+                break;
             }
+            let key_end = RecordHeader::LEN + header.key_len;
             entries.push(ValueLogEntry {
-                ksize: key_len as usize,
-                vsize: val_len as usize,
-                key,
-                value,
-                created_at: util::milliseconds_to_datetime(created_at),
-                is_tombstone,
-            })
+                ksize: header.key_len,
+                vsize: header.val_len,
+                key: record[RecordHeader::LEN..key_end].to_vec(),
+                value: record[key_end..key_end + header.val_len].to_vec(),
+                created_at: util::timestamp_to_datetime(header.created_at),
+                is_tombstone: header.record_type == RecordType::Delete,
+                record_type: header.record_type,
+                checksummed: header.checksummed,
+            });
+            offset += record_len;
         }
+        Ok((entries, offset - start_offset))
     }
 
     async fn read_chunk_to_garbage_collect(
@@ -579,64 +693,62 @@ impl VLogFs for VLogFileNode {
     ) -> Result<(Vec<ValueLogEntry>, NoBytesRead), Error> {
         let path = &self.node.file_path;
         let mut entries = Vec::new();
-        let mut file = self.node.file.write().await;
+        let mut file = self.node.w_lock().await?;
         file.seek(std::io::SeekFrom::Start(offset))
             .await
             .map_err(FileSeek)?;
         let mut total_bytes_read: usize = 0;
         loop {
-            let mut key_len_bytes = [0; SIZE_OF_U32];
-            let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
-            total_bytes_read += bytes_read;
+            let mut record = vec![0; RecordHeader::LEN];
+            let bytes_read = load_buffer!(file, &mut record, path.to_owned())?;
             if bytes_read == 0 {
                 return Ok((entries, total_bytes_read));
             }
-            let key_len = u32::from_le_bytes(key_len_bytes);
-
-            let mut val_len_bytes = [0; SIZE_OF_U32];
-            bytes_read = load_buffer!(file, &mut val_len_bytes, path.to_owned())?;
-            total_bytes_read += bytes_read;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-
-            let val_len = u32::from_le_bytes(val_len_bytes);
-            let mut creation_date_bytes = [0; SIZE_OF_U64];
-            bytes_read = load_buffer!(file, &mut creation_date_bytes, path.to_owned())?;
-            total_bytes_read += bytes_read;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-            let created_at = u64::from_le_bytes(creation_date_bytes);
-
-            let mut istombstone_bytes = [0; SIZE_OF_U8];
-            let mut bytes_read = load_buffer!(file, &mut istombstone_bytes, path.to_owned())?;
-            total_bytes_read += bytes_read;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
-            }
-
-            let is_tombstone = istombstone_bytes[0] == 1;
-            let mut key = vec![0; key_len as usize];
-            bytes_read = load_buffer!(file, &mut key, path.to_owned())?;
-            total_bytes_read += bytes_read;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
+            FileNode::read_remaining(&mut file, &mut record[bytes_read..], path).await?;
+            let header = RecordHeader::decode(&record);
+            let record_offset = offset as usize + total_bytes_read;
+            total_bytes_read += header.record_len();
+
+            if header.record_type == RecordType::Hole {
+                // the value is a punched hole, it is skipped instead of read
+                file.seek(std::io::SeekFrom::Current(
+                    (header.record_len() - RecordHeader::LEN) as i64,
+                ))
+                .await
+                .map_err(FileSeek)?;
+                entries.push(ValueLogEntry {
+                    ksize: header.key_len,
+                    vsize: header.val_len,
+                    key: Vec::new(),
+                    value: Vec::new(),
+                    created_at: util::timestamp_to_datetime(header.created_at),
+                    is_tombstone: false,
+                    record_type: header.record_type,
+                    checksummed: header.checksummed,
+                });
+                if total_bytes_read >= bytes_to_collect {
+                    return Ok((entries, total_bytes_read));
+                }
+                continue;
             }
-
-            let mut value = vec![0; val_len as usize];
-            bytes_read = load_buffer!(file, &mut value, path.to_owned())?;
-            total_bytes_read += bytes_read;
-            if bytes_read == 0 {
-                return Err(FileNode::unexpected_eof());
+            record.resize(header.record_len(), 0);
+            FileNode::read_remaining(&mut file, &mut record[RecordHeader::LEN..], path).await?;
+            if !header.checksum_matches(&record) {
+                return Err(VLogRecordCorrupted {
+                    path: path.to_owned(),
+                    offset: record_offset,
+                });
             }
+            let key_end = RecordHeader::LEN + header.key_len;
             entries.push(ValueLogEntry {
-                ksize: key_len as usize,
-                vsize: val_len as usize,
-                key,
-                value,
-                created_at: util::milliseconds_to_datetime(created_at),
-                is_tombstone,
+                ksize: header.key_len,
+                vsize: header.val_len,
+                key: record[RecordHeader::LEN..key_end].to_vec(),
+                value: record[key_end..key_end + header.val_len].to_vec(),
+                created_at: util::timestamp_to_datetime(header.created_at),
+                is_tombstone: header.record_type == RecordType::Delete,
+                record_type: header.record_type,
+                checksummed: header.checksummed,
             });
 
             // Ensure the size read from value log is approximately bytes expected to be garbage collected
@@ -645,6 +757,24 @@ impl VLogFs for VLogFileNode {
             }
         }
     }
+
+    async fn read_bytes(&self, start_offset: usize, len: usize) -> Result<Vec<u8>, Error> {
+        let path = &self.node.file_path;
+        let mut file = self.node.w_lock().await?;
+        file.seek(std::io::SeekFrom::Start(start_offset as u64))
+            .await
+            .map_err(FileSeek)?;
+        let mut buf = Vec::with_capacity(len);
+        (&mut *file)
+            .take(len as u64)
+            .read_to_end(&mut buf)
+            .await
+            .map_err(|err| FileRead {
+                path: path.to_owned(),
+                error: err,
+            })?;
+        Ok(buf)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -663,7 +793,7 @@ impl IndexFs for IndexFileNode {
     async fn get_from_index(&self, searched_key: &[u8]) -> Result<Option<u32>, Error> {
         let path = &self.node.file_path;
         let block_offset: i32 = -1;
-        let mut file = self.node.file.write().await;
+        let mut file = self.node.w_lock().await?;
         file.seek(std::io::SeekFrom::Start(0_u64))
             .await
             .map_err(FileSeek)?;
@@ -705,10 +835,12 @@ impl IndexFs for IndexFileNode {
         }
     }
 
-    async fn get_block_range(&self, start_key: &[u8], end_key: &[u8]) -> Result<RangeOffset, Error> {
+    async fn get_block_range(&self, start_key: &[u8], end_key: &[u8]) -> Result<Option<RangeOffset>, Error> {
         let path = &self.node.file_path;
-        let mut range_offset = RangeOffset::new(0, 0);
-        let mut file = self.node.file.write().await;
+        let mut range_offset: Option<RangeOffset> = None;
+        // set once a block whose last key is at least `end_key` is in range
+        let mut reached_end = false;
+        let mut file = self.node.w_lock().await?;
         file.seek(std::io::SeekFrom::Start(0_u64))
             .await
             .map_err(FileSeek)?;
@@ -717,6 +849,7 @@ impl IndexFs for IndexFileNode {
             let mut key_len_bytes = [0; SIZE_OF_U32];
             let mut bytes_read = load_buffer!(file, &mut key_len_bytes, path.to_owned())?;
             if bytes_read == 0 {
+                // range runs to the end of data file
                 return Ok(range_offset);
             }
 
@@ -733,16 +866,21 @@ impl IndexFs for IndexFileNode {
                 return Err(FileNode::unexpected_eof());
             }
             let offset = u32::from_le_bytes(key_offset_bytes);
-            match key.cmp(&start_key.to_vec()) {
-                std::cmp::Ordering::Greater => match key.cmp(&end_key.to_vec()) {
-                    std::cmp::Ordering::Greater => {
-                        range_offset.end_offset = offset;
-                        return Ok(range_offset);
-                    }
-                    _ => range_offset.end_offset = offset,
-                },
-                _ => range_offset.start_offset = offset,
+            if reached_end {
+                if let Some(range) = range_offset.as_mut() {
+                    range.end_offset = offset;
+                }
+                return Ok(range_offset);
+            }
+            // index keys are the last key of each block, blocks ending
+            // before `start_key` hold nothing in range
+            if range_offset.is_none() {
+                if key.as_slice() < start_key {
+                    continue;
+                }
+                range_offset = Some(RangeOffset::new(offset, u32::MAX));
             }
+            reached_end = key.as_slice() >= end_key;
         }
     }
 }
@@ -761,10 +899,16 @@ impl FilterFs for FilterFileNode {
         Ok(FilterFileNode { node })
     }
 
-    async fn recover(path: impl P) -> Result<(FalsePositive, NoHashFunc, NoOfElements), Error> {
-        let mut file = FileNode::open(path.as_ref())
-            .await
-            .map_err(|_| FilterFileOpen(path.as_ref().to_owned()))?;
+    async fn recover(
+        path: impl P,
+        cipher: Option<FileCipher>,
+    ) -> Result<(FalsePositive, NoHashFunc, NoOfElements, Option<BitVec>), Error> {
+        let mut file = FileSlot::from(
+            FileNode::open(path.as_ref())
+                .await
+                .map_err(|_| FilterFileOpen(path.as_ref().to_owned()))?,
+        );
+        file.set_cipher(cipher, 0);
         let mut no_hash_func_bytes = [0; SIZE_OF_U32];
         let mut bytes_read = load_buffer!(file, &mut no_hash_func_bytes, path.as_ref().to_path_buf())?;
         if bytes_read == 0 {
@@ -788,7 +932,32 @@ impl FilterFs for FilterFileNode {
         if false_positive_rate.is_none() {
             return Err(FileNode::unexpected_eof());
         }
-        return Ok((false_positive_rate.unwrap(), no_of_hash_func, no_of_elements));
+
+        // bits follow the metadata, filters written without them are rebuilt from entries
+        let mut no_of_bits_bytes = [0; SIZE_OF_U32];
+        bytes_read = load_buffer!(file, &mut no_of_bits_bytes, path.as_ref().to_path_buf())?;
+        if bytes_read == 0 {
+            return Ok((
+                false_positive_rate.unwrap(),
+                no_of_hash_func,
+                no_of_elements,
+                None,
+            ));
+        }
+        let no_of_bits = u32::from_le_bytes(no_of_bits_bytes) as usize;
+        let mut bytes = vec![0; no_of_bits.div_ceil(8)];
+        file.read_exact(&mut bytes).await.map_err(|err| FileRead {
+            path: path.as_ref().to_path_buf(),
+            error: err,
+        })?;
+        let mut bits = BitVec::from_bytes(&bytes);
+        bits.truncate(no_of_bits);
+        Ok((
+            false_positive_rate.unwrap(),
+            no_of_hash_func,
+            no_of_elements,
+            Some(bits),
+        ))
     }
 }
 
@@ -804,44 +973,6 @@ impl MetaFs for MetaFileNode {
         let node = FileNode::new(path, file_type).await?;
         Ok(MetaFileNode { node })
     }
-    async fn recover(path: impl P) -> Result<(VLogHead, VLogTail, CreatedAt, LastModified), Error> {
-        let mut file = FileNode::open(path.as_ref())
-            .await
-            .map_err(|_| FilterFileOpen(path.as_ref().to_owned()))?;
-        let mut head_offset_bytes = [0; SIZE_OF_U32];
-        let mut bytes_read = load_buffer!(file, &mut head_offset_bytes, path.as_ref().to_path_buf())?;
-        if bytes_read == 0 {
-            return Err(FileNode::unexpected_eof());
-        }
-        let head_offset = u32::from_le_bytes(head_offset_bytes);
-
-        let mut tail_offset_bytes = [0; SIZE_OF_U32];
-        bytes_read = load_buffer!(file, &mut tail_offset_bytes, path.as_ref().to_path_buf())?;
-        if bytes_read == 0 {
-            return Err(FileNode::unexpected_eof());
-        }
-        let tail_offset = u32::from_le_bytes(tail_offset_bytes);
-
-        let mut creation_date_bytes = [0; SIZE_OF_U64];
-        bytes_read = load_buffer!(file, &mut creation_date_bytes, path.as_ref().to_owned())?;
-        if bytes_read == 0 {
-            return Err(FileNode::unexpected_eof());
-        }
-        let created_at = u64::from_le_bytes(creation_date_bytes);
-
-        let mut last_modified_date_bytes = [0; SIZE_OF_U64];
-        bytes_read = load_buffer!(file, &mut last_modified_date_bytes, path.as_ref().to_owned())?;
-        if bytes_read == 0 {
-            return Err(FileNode::unexpected_eof());
-        }
-        let last_modified = u64::from_le_bytes(last_modified_date_bytes);
-        return Ok((
-            head_offset as usize,
-            tail_offset as usize,
-            util::milliseconds_to_datetime(created_at),
-            util::milliseconds_to_datetime(last_modified),
-        ));
-    }
 }
 
 #[derive(Debug, Clone)]
@@ -857,10 +988,16 @@ impl SummaryFs for SummaryFileNode {
         let node = FileNode::new(path, file_type).await?;
         Ok(SummaryFileNode { node })
     }
-    async fn recover(path: impl P) -> Result<(SmallestKey, BiggestKey), Error> {
-        let mut file = FileNode::open(path.as_ref())
-            .await
-            .map_err(|_| FilterFileOpen(path.as_ref().to_owned()))?;
+    async fn recover(
+        path: impl P,
+        cipher: Option<FileCipher>,
+    ) -> Result<(SmallestKey, BiggestKey, TableProperties), Error> {
+        let mut file = FileSlot::from(
+            FileNode::open(path.as_ref())
+                .await
+                .map_err(|_| FilterFileOpen(path.as_ref().to_owned()))?,
+        );
+        file.set_cipher(cipher, 0);
         let mut smallest_key_len_bytes = [0; SIZE_OF_U32];
         let mut bytes_read = load_buffer!(file, &mut smallest_key_len_bytes, path.as_ref().to_owned())?;
         if bytes_read == 0 {
@@ -885,7 +1022,54 @@ impl SummaryFs for SummaryFileNode {
         if bytes_read == 0 {
             return Err(FileNode::unexpected_eof());
         }
-        return Ok((smallest_key, biggest_key));
+
+        // properties follow the keys, each field is missing from summaries
+        // written before it was introduced
+        let mut properties = TableProperties::default();
+        let mut entry_count_bytes = [0; SIZE_OF_U32];
+        bytes_read = load_buffer!(file, &mut entry_count_bytes, path.as_ref().to_owned())?;
+        if bytes_read == 0 {
+            return Ok((smallest_key, biggest_key, properties));
+        }
+        let mut tombstone_count_bytes = [0; SIZE_OF_U32];
+        bytes_read = load_buffer!(file, &mut tombstone_count_bytes, path.as_ref().to_owned())?;
+        if bytes_read == 0 {
+            return Err(FileNode::unexpected_eof());
+        }
+        properties.entry_count = u32::from_le_bytes(entry_count_bytes) as usize;
+        properties.tombstone_count = u32::from_le_bytes(tombstone_count_bytes) as usize;
+
+        let mut newest_entry_bytes = [0; SIZE_OF_U64];
+        bytes_read = load_buffer!(file, &mut newest_entry_bytes, path.as_ref().to_owned())?;
+        if bytes_read == 0 {
+            return Ok((smallest_key, biggest_key, properties));
+        }
+        properties.newest_entry = FileNode::timestamp_from_le_bytes(newest_entry_bytes);
+
+        let mut data_size_bytes = [0; SIZE_OF_U64];
+        bytes_read = load_buffer!(file, &mut data_size_bytes, path.as_ref().to_owned())?;
+        if bytes_read == 0 {
+            return Ok((smallest_key, biggest_key, properties));
+        }
+        properties.data_size = u64::from_le_bytes(data_size_bytes) as usize;
+
+        let mut oldest_entry_bytes = [0; SIZE_OF_U64];
+        bytes_read = load_buffer!(file, &mut oldest_entry_bytes, path.as_ref().to_owned())?;
+        if bytes_read == 0 {
+            return Err(FileNode::unexpected_eof());
+        }
+        properties.oldest_entry = FileNode::timestamp_from_le_bytes(oldest_entry_bytes);
+
+        let mut data_checksum_bytes = [0; SIZE_OF_U32];
+        bytes_read = load_buffer!(file, &mut data_checksum_bytes, path.as_ref().to_owned())?;
+        if bytes_read == 0 {
+            return Ok((smallest_key, biggest_key, properties));
+        }
+        properties.data_checksum = match u32::from_le_bytes(data_checksum_bytes) {
+            0 => None,
+            checksum => Some(checksum),
+        };
+        Ok((smallest_key, biggest_key, properties))
     }
 }
 
@@ -893,4 +1077,24 @@ impl FileNode {
     fn unexpected_eof() -> Error {
         UnexpectedEOF(io::Error::new(io::ErrorKind::UnexpectedEof, EOF))
     }
+
+    /// Fills `buf` from the current position, a file ending first is an unexpected EOF
+    async fn read_remaining(file: &mut FileSlot, buf: &mut [u8], path: &Path) -> Result<(), Error> {
+        match file.read_exact(buf).await {
+            Ok(_) => Ok(()),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Err(FileNode::unexpected_eof()),
+            Err(err) => Err(FileRead {
+                path: path.to_path_buf(),
+                error: err,
+            }),
+        }
+    }
+
+    /// Decodes a timestamp written by `util::datetime_to_timestamp`, 0 means missing
+    fn timestamp_from_le_bytes(bytes: [u8; SIZE_OF_U64]) -> Option<CreatedAt> {
+        match u64::from_le_bytes(bytes) {
+            0 => None,
+            timestamp => Some(util::timestamp_to_datetime(timestamp)),
+        }
+    }
 }
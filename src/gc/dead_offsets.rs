@@ -0,0 +1,309 @@
+use super::liveness::{segment_of, SegmentWrites, VLogLiveness};
+use crate::consts::{
+    GC_BATCH_GARBAGE_RATIO, GC_MAX_BATCH_CHUNKS, GC_SEGMENT_GARBAGE_RATIO, SIZE_OF_U32, SIZE_OF_U64,
+    VLOG_SEGMENT_SIZE,
+};
+use crate::err::Error::{self, *};
+use crate::types::ValOffset;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// Value log writes per segment and dead offsets as persisted
+type Persisted = (Vec<(usize, SegmentWrites)>, Vec<ValOffset>);
+
+/// Value log offsets of entries compaction dropped from merged SSTables
+///
+/// Once merged SSTables replace the old ones, nothing refers to these records
+/// anymore. GC treats them as invalid without looking their keys up and frees
+/// them with the rest of its chunk.
+///
+/// Dead records are counted per segment of value log, GC collects the
+/// segment with the most dead bytes in place once it is mostly dead and
+/// frees value log from the tail otherwise, see `DeadOffsets::most_garbage_segment`.
+///
+/// Offsets are persisted along with value log writes per segment, see
+/// `DeadOffsets::persist`, so the estimate survives a restart
+#[derive(Debug, Clone, Default)]
+pub struct DeadOffsets {
+    offsets: Arc<Mutex<BTreeSet<ValOffset>>>,
+
+    /// Number of dead offsets in each segment of value log
+    segments: Arc<Mutex<BTreeMap<usize, usize>>>,
+
+    /// Segments GC found it cannot collect in place, they are left to tail GC
+    passed_over: Arc<Mutex<BTreeSet<usize>>>,
+
+    /// Writes to value log, shared with it
+    liveness: VLogLiveness,
+
+    /// File offsets and writes are persisted to, `None` if they are kept in memory only
+    path: Option<PathBuf>,
+
+    /// Orders writes of the file
+    persist_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl DeadOffsets {
+    /// Creates `DeadOffsets` estimating sizes of dead records from writes
+    /// counted by `liveness`, both are persisted to `path`
+    pub fn new(liveness: VLogLiveness, path: PathBuf) -> Self {
+        Self {
+            liveness,
+            path: Some(path),
+            ..Default::default()
+        }
+    }
+
+    /// Records `offsets` as dead
+    pub fn record(&self, offsets: impl IntoIterator<Item = ValOffset>) {
+        let mut recorded = self.offsets.lock().unwrap();
+        let mut segments = self.segments.lock().unwrap();
+        for offset in offsets {
+            if recorded.insert(offset) {
+                *segments.entry(segment_of(offset)).or_default() += 1;
+            }
+        }
+    }
+
+    /// Returns `true` if record at `offset` is dead
+    pub fn contains(&self, offset: ValOffset) -> bool {
+        self.offsets.lock().unwrap().contains(&offset)
+    }
+
+    /// Forgets offsets before `end`, GC calls this once it frees up to `end`
+    pub fn release_before(&self, end: ValOffset) {
+        let mut offsets = self.offsets.lock().unwrap();
+        let mut segments = self.segments.lock().unwrap();
+        let kept = offsets.split_off(&end);
+        for offset in offsets.iter() {
+            let segment = segment_of(*offset);
+            if let Some(count) = segments.get_mut(&segment) {
+                *count -= 1;
+                if *count == 0 {
+                    segments.remove(&segment);
+                }
+            }
+        }
+        *offsets = kept;
+        self.passed_over
+            .lock()
+            .unwrap()
+            .retain(|segment| *segment >= segment_of(end));
+        self.liveness.release_before(end);
+    }
+
+    /// Forgets offsets within `[start, end)` along with the segment of `start`,
+    /// GC calls this once it collected the records of that segment in place
+    pub fn release_within(&self, start: ValOffset, end: ValOffset) {
+        let mut offsets = self.offsets.lock().unwrap();
+        let released: Vec<_> = offsets.range(start..end).copied().collect();
+        for offset in released.iter() {
+            offsets.remove(offset);
+        }
+        drop(offsets);
+        let segment = segment_of(start);
+        self.segments.lock().unwrap().remove(&segment);
+        self.passed_over.lock().unwrap().remove(&segment);
+        self.liveness.release_segment(segment);
+    }
+
+    /// Leaves `segment` to tail GC, it is not returned by `most_garbage_segment` again
+    pub fn pass_over(&self, segment: usize) {
+        self.passed_over.lock().unwrap().insert(segment);
+    }
+
+    /// Returns number of dead records not freed yet
+    pub fn len(&self) -> usize {
+        self.offsets.lock().unwrap().len()
+    }
+
+    /// Returns estimated bytes of dead records not freed yet
+    pub fn reclaimable_bytes(&self) -> usize {
+        let segments = self.segments.lock().unwrap().clone();
+        segments
+            .into_iter()
+            .map(|(segment, count)| count * self.liveness.avg_record_size(segment).unwrap_or(0))
+            .sum()
+    }
+
+    /// Returns estimated bytes of dead records starting within `[start, end)`
+    pub fn reclaimable_within(&self, start: ValOffset, end: ValOffset) -> usize {
+        let offsets = self.offsets.lock().unwrap();
+        let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+        for offset in offsets.range(start..end) {
+            *counts.entry(segment_of(*offset)).or_default() += 1;
+        }
+        drop(offsets);
+        counts
+            .into_iter()
+            .map(|(segment, count)| count * self.liveness.avg_record_size(segment).unwrap_or(0))
+            .sum()
+    }
+
+    /// Returns records of the segment with the most estimated dead bytes as
+    /// `[start, end)`, `end` bounding where the last of them starts
+    ///
+    /// Only segments past the one holding `tail` whose records all lie
+    /// before `head` are ranked, records from head on are replayed on
+    /// recovery, which must not find them moved. `None` if the segment is
+    /// not mostly dead, GC frees value log from the tail then
+    pub fn most_garbage_segment(&self, tail: ValOffset, head: ValOffset) -> Option<(ValOffset, ValOffset)> {
+        let segments = self.segments.lock().unwrap().clone();
+        let passed_over = self.passed_over.lock().unwrap().clone();
+        let (segment, dead) = segments
+            .into_iter()
+            .filter(|(segment, _)| {
+                *segment > segment_of(tail)
+                    && (segment + 1) * VLOG_SEGMENT_SIZE <= head
+                    && !passed_over.contains(segment)
+            })
+            .map(|(segment, count)| {
+                (
+                    segment,
+                    count * self.liveness.avg_record_size(segment).unwrap_or(0),
+                )
+            })
+            .max_by_key(|(_, dead)| *dead)?;
+        if (dead as f64) < VLOG_SEGMENT_SIZE as f64 * GC_SEGMENT_GARBAGE_RATIO {
+            return None;
+        }
+        let start = self.liveness.first_record(segment)?;
+        Some((start, (segment + 1) * VLOG_SEGMENT_SIZE))
+    }
+
+    /// Writes dead offsets and value log writes per segment to a temporary
+    /// file and renames it over the old one
+    ///
+    /// Offsets and writes recorded since the last call are lost on a crash,
+    /// GC then looks their keys up and estimates underestimate the garbage
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error in case it occurs
+    pub async fn persist(&self) -> Result<(), Error> {
+        let Some(path) = self.path.to_owned() else {
+            return Ok(());
+        };
+        let _persisting = self.persist_lock.lock().await;
+        let serialized = self.serialize();
+        let tmp_path = path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path).await.map_err(|error| FileCreation {
+            path: tmp_path.to_owned(),
+            error,
+        })?;
+        file.write_all(&serialized).await.map_err(|error| FileWrite {
+            path: tmp_path.to_owned(),
+            error,
+        })?;
+        file.sync_all().await.map_err(FileSync)?;
+        fs::rename(&tmp_path, &path)
+            .await
+            .map_err(|error| FileRename { path, error })
+    }
+
+    /// Restores offsets and writes persisted before a restart
+    ///
+    /// Offsets before `tail`, which GC freed since, and from `size` on, which
+    /// a torn value log no longer holds, are dropped. A missing or corrupted
+    /// file is ignored, the estimate starts over then
+    pub async fn load(&self, tail: ValOffset, size: usize) {
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        let bytes = match fs::read(path).await {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return,
+            Err(err) => {
+                log::warn!("Value log liveness {:?} could not be read: {}", path, err);
+                return;
+            }
+        };
+        let Some((writes, offsets)) = Self::deserialize(&bytes) else {
+            log::warn!("Value log liveness {:?} is corrupted, estimates start over", path);
+            return;
+        };
+        self.liveness
+            .restore(writes.into_iter().filter(|(_, writes)| writes.first < size));
+        self.record(offsets.into_iter().filter(|offset| *offset < size));
+        self.release_before(tail);
+    }
+
+    /// Serializes writes per segment and dead offsets, each preceded by their
+    /// count, followed by a checksum of everything before it
+    fn serialize(&self) -> Vec<u8> {
+        let writes = self.liveness.snapshot();
+        let offsets = self.offsets.lock().unwrap().clone();
+        let mut serialized_data = Vec::new();
+        serialized_data.extend_from_slice(&(writes.len() as u64).to_le_bytes());
+        for (segment, writes) in writes {
+            for field in [segment, writes.records, writes.bytes, writes.first] {
+                serialized_data.extend_from_slice(&(field as u64).to_le_bytes());
+            }
+        }
+        serialized_data.extend_from_slice(&(offsets.len() as u64).to_le_bytes());
+        for offset in offsets {
+            serialized_data.extend_from_slice(&(offset as u64).to_le_bytes());
+        }
+        let checksum = crc32fast::hash(&serialized_data);
+        serialized_data.extend_from_slice(&checksum.to_le_bytes());
+        serialized_data
+    }
+
+    /// Parses bytes written by `serialize`, returns `None` if they are malformed
+    fn deserialize(bytes: &[u8]) -> Option<Persisted> {
+        let body_len = bytes.len().checked_sub(SIZE_OF_U32)?;
+        let (body, checksum) = bytes.split_at(body_len);
+        if crc32fast::hash(body) != u32::from_le_bytes(checksum.try_into().ok()?) {
+            return None;
+        }
+        let mut words = body.chunks_exact(SIZE_OF_U64);
+        if !words.remainder().is_empty() {
+            return None;
+        }
+        let mut next =
+            || -> Option<usize> { Some(u64::from_le_bytes(words.next()?.try_into().ok()?) as usize) };
+        let mut writes = Vec::new();
+        for _ in 0..next()? {
+            let segment = next()?;
+            let records = next()?;
+            let bytes = next()?;
+            let first = next()?;
+            writes.push((
+                segment,
+                SegmentWrites {
+                    records,
+                    bytes,
+                    first,
+                },
+            ));
+        }
+        let mut offsets = Vec::new();
+        for _ in 0..next()? {
+            offsets.push(next()?);
+        }
+        next().is_none().then_some((writes, offsets))
+    }
+
+    /// Returns bytes GC should collect from `tail` in one run, value log ending at `end`
+    ///
+    /// Used when no segment is collected in place, the chunk right after the
+    /// tail is always taken. Following chunks are taken along while they are mostly dead,
+    /// they cost little to collect since dead records are not looked up
+    pub fn gc_batch_len(&self, tail: ValOffset, end: ValOffset, chunk_size: usize) -> usize {
+        let mut len = chunk_size;
+        for _ in 1..GC_MAX_BATCH_CHUNKS {
+            let start = tail + len;
+            if start + chunk_size > end {
+                break;
+            }
+            let dead = self.reclaimable_within(start, start + chunk_size);
+            if (dead as f64) < chunk_size as f64 * GC_BATCH_GARBAGE_RATIO {
+                break;
+            }
+            len += chunk_size;
+        }
+        len
+    }
+}
@@ -1,37 +1,47 @@
 // NOTE: GarbageCollector is only supported on Linux based OS for now because File Systems for other OS does not
 // support the FILE_PUNCH_HOLE command which is crucial for reclaiming unused spaces on the disk
 
+#[cfg(target_os = "linux")]
 extern crate libc;
+#[cfg(target_os = "linux")]
 extern crate nix;
-use crate::consts::{TAIL_ENTRY_KEY, TOMB_STONE_MARKER};
+use crate::clock::ClockHandle;
+use crate::consts::{SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, TAIL_ENTRY_KEY, TOMB_STONE_MARKER};
 use crate::err::Error;
-use crate::fs::P;
+use crate::fs::{FileAsync, VLogFs, P};
+use crate::gc::{segment_of, DeadOffsets};
+use crate::health::{BackgroundTask, HealthMonitor};
 use crate::index::Index;
 use crate::memtable::{Entry, MemTable, SkipMapValue, K};
 use crate::sst::Table;
 use crate::types::{CreatedAt, ImmutableMemTables, Key, KeyRangeHandle, ValOffset, Value};
-use crate::vlog::{ValueLog, ValueLogEntry};
+use crate::vlog::{RecordType, ValueDedup, ValueExpiry, ValueLog, ValueLogEntry};
 use crate::{err, util};
-use chrono::Utc;
 use crossbeam_skiplist::SkipMap;
 use err::Error::*;
 use futures::future::join_all;
+#[cfg(target_os = "linux")]
 use nix::libc::{c_int, off_t};
+#[cfg(target_os = "linux")]
 use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use tokio::sync::{Mutex, RwLock};
 use tokio::time::sleep;
 
+#[cfg(target_os = "linux")]
 extern "C" {
     fn fallocate(fd: libc::c_int, mode: c_int, offset: off_t, len: off_t) -> c_int;
 }
 
+#[cfg(target_os = "linux")]
 const FALLOC_FL_PUNCH_HOLE: c_int = 0x2;
+#[cfg(target_os = "linux")]
 const FALLOC_FL_KEEP_SIZE: c_int = 0x1;
 
 /// Alias for thread-safe memtable type for garbage collector
-type GCTable = Arc<RwLock<MemTable<Key>>>;
+pub(crate) type GCTable = Arc<RwLock<MemTable<Key>>>;
 
 /// Alias for thread-safe log for garbage collector
 type GCLog = Arc<RwLock<ValueLog>>;
@@ -39,6 +49,10 @@ type GCLog = Arc<RwLock<ValueLog>>;
 /// Alias for thread-safe valid entries to re-insert
 type ValidEntries = Arc<RwLock<Vec<(Key, Value, ValOffset)>>>;
 
+/// Alias for thread-safe live records to rewrite along with other keys sharing their
+/// value and the time the value expires at, an empty value is rewritten as a tombstone
+type LiveEntries = Arc<RwLock<Vec<(Key, Value, Vec<Key>, Option<CreatedAt>)>>>;
+
 /// Alias thread-safe valid etries synced to disk
 type SyncedEntries = Arc<RwLock<Vec<(Key, Value, ValOffset)>>>;
 
@@ -80,6 +94,22 @@ pub struct GC {
 pub(crate) struct Config {
     pub online_gc_interval: std::time::Duration,
     pub gc_chunk_size: usize,
+
+    /// Clock used to timestamp re-inserted entries
+    pub clock: ClockHandle,
+
+    /// Records compaction already found dead, they are not looked up
+    pub dead_offsets: DeadOffsets,
+
+    /// GC worker skips its runs while set, shared with the compactor
+    pub manual_background: Arc<AtomicBool>,
+
+    /// Payloads shared by several keys, shared with the store
+    pub dedup: ValueDedup,
+
+    /// Value log the store appends through, GC holds it while it appends
+    /// moved entries so both copies agree on where value log ends
+    pub store_log: GCLog,
 }
 
 /// Marks area of value log file
@@ -91,6 +121,10 @@ pub struct PunchMarker {
 
     /// Length of holes to punch
     pub(crate) punch_hole_length: usize,
+
+    /// Area lies past the tail, it is replaced by a `RecordType::Hole` record
+    /// and the tail is left where it is
+    pub(crate) in_place: bool,
 }
 
 impl GC {
@@ -100,7 +134,9 @@ impl GC {
         gc_chunk_size: usize,
         table: GCTable,
         vlog: GCLog,
+        store_log: GCLog,
         gc_updated_entries: GCUpdatedEntries<Key>,
+        clock: ClockHandle,
     ) -> Self {
         Self {
             table,
@@ -110,12 +146,22 @@ impl GC {
             config: Config {
                 online_gc_interval,
                 gc_chunk_size,
+                clock,
+                dead_offsets: DeadOffsets::default(),
+                manual_background: Arc::new(AtomicBool::new(false)),
+                dedup: ValueDedup::default(),
+                store_log,
             },
         }
     }
 
     /// Continues to check if it's time to run GC (works in background)
-    pub fn start_gc_worker(&self, key_range: KeyRangeHandle, read_only_memtables: ImmutableMemTables<Key>) {
+    pub fn start_gc_worker(
+        &self,
+        key_range: KeyRangeHandle,
+        read_only_memtables: ImmutableMemTables<Key>,
+        health: HealthMonitor,
+    ) {
         let cfg = self.config.to_owned();
         // NOTE: These are reference counter incrementation not deep clone
         let memtable = self.table.clone();
@@ -127,8 +173,12 @@ impl GC {
         let gc_updated_entries_ref = self.gc_updated_entries.clone();
         let punch_marker_ref = self.punch_marker.clone();
         tokio::spawn(async move {
+            let _alive = health.task_started(BackgroundTask::Gc);
             loop {
                 sleep_gc_task(cfg.online_gc_interval).await;
+                if cfg.manual_background.load(Ordering::Relaxed) {
+                    continue;
+                }
                 // if last valid entries is not synced with store memtable yet don't
                 // run another garbage collection
                 if !gc_updated_entries_ref.read().await.is_empty() {
@@ -146,10 +196,12 @@ impl GC {
                 .await;
                 match res {
                     Ok(_) => {
-                        log::info!("GC successful, awaiting sync")
+                        log::info!("GC successful, awaiting sync");
+                        health.record_success(BackgroundTask::Gc);
                     }
                     Err(err) => {
-                        log::error!("GC Error {}", err.to_string());
+                        log::error!("GC Error {}", err);
+                        health.record_failure(BackgroundTask::Gc, &err);
                     }
                 }
             }
@@ -162,6 +214,11 @@ impl GC {
     /// and invalid entries. Re-inserts valid entries while
     /// it filters out invalid entries
     ///
+    /// The segment past the tail with the most bytes compaction found dead is
+    /// collected first, in place, see `DeadOffsets::most_garbage_segment`.
+    /// Otherwise GC collects from the tail and following chunks compaction
+    /// found mostly dead are collected in the same run, see `DeadOffsets::gc_batch_len`
+    ///
     /// # Error
     ///
     /// Returns error in case there was a failure at any point
@@ -178,11 +235,70 @@ impl GC {
         let valid_entries = Arc::new(RwLock::new(Vec::new()));
         let synced_entries = Arc::new(RwLock::new(Vec::new()));
         let vlog_reader = vlog.read().await;
-        let chunk_res = vlog_reader.read_chunk_to_garbage_collect(cfg.gc_chunk_size).await;
+        cfg.dedup.load(&vlog_reader).await?;
+        let (tail, head) = (vlog_reader.tail_offset, vlog_reader.head_offset);
+        // the segment with the most garbage is collected in place if it is mostly dead
+        let segment = cfg.dead_offsets.most_garbage_segment(tail, head);
+        let (chunk_start, chunk_res) = match segment {
+            Some((start, end)) => {
+                let chunk_res = vlog_reader
+                    .content
+                    .file
+                    .read_chunk_to_garbage_collect(end - start, start as u64)
+                    .await;
+                (start, chunk_res)
+            }
+            None => {
+                // the store appends through its own handle, the size of this one is stale
+                let vlog_end = vlog_reader.content.file.node.size().await;
+                let bytes_to_collect = cfg.dead_offsets.gc_batch_len(tail, vlog_end, cfg.gc_chunk_size);
+                (
+                    tail,
+                    vlog_reader.read_chunk_to_garbage_collect(bytes_to_collect).await,
+                )
+            }
+        };
         drop(vlog_reader);
+        let in_place = segment.is_some();
         match chunk_res {
             Ok((entries, total_bytes_read)) => {
-                let tasks = entries.into_iter().map(|entry| {
+                let chunk_end = chunk_start + total_bytes_read;
+                if in_place && !GC::can_collect_in_place(&entries, chunk_start, chunk_end, head) {
+                    cfg.dead_offsets.pass_over(segment_of(chunk_start));
+                    return Ok(());
+                }
+                // puts no longer share payloads of the chunk while it is checked
+                let Some(claimed) = cfg.dedup.claim(chunk_start, chunk_end) else {
+                    // a rename is moving a payload of the chunk to another key, the chunk is checked next pass
+                    return Ok(());
+                };
+                let mut offset = chunk_start;
+                let mut live_entries = Vec::new();
+                for entry in entries {
+                    let record_offset = offset;
+                    offset += entry.record_size();
+                    // compaction already dropped every reference to this record unless
+                    // other keys share it or an append extends it, batch markers hold no
+                    // user entry and references are rewritten along with the payload they point at
+                    if (cfg.dead_offsets.contains(record_offset)
+                        && !cfg.dedup.is_shared(record_offset)
+                        && !cfg.dedup.is_linked(record_offset))
+                        || matches!(
+                            entry.record_type,
+                            RecordType::BatchBegin
+                                | RecordType::BatchCommit
+                                | RecordType::ValueRef
+                                | RecordType::Hole
+                        )
+                    {
+                        invalid_entries.write().await.push(entry);
+                    } else {
+                        let linked = cfg.dedup.is_linked(record_offset);
+                        live_entries.push((entry, cfg.dedup.referrers(record_offset), linked));
+                    }
+                }
+                let now = cfg.clock.now();
+                let tasks = live_entries.into_iter().map(|(entry, referrers, linked)| {
                     // NOTE: These are reference counter incrementation not deep clone
                     let invalid_entries_ref = invalid_entries.clone();
                     let valid_entries_ref = valid_entries.clone();
@@ -200,17 +316,69 @@ impl GC {
                             read_only_memtables_ref.clone(),
                         )
                         .await;
+                        // keys sharing the payload keep it alive as long as they still refer to it
+                        let mut sharers = Vec::new();
+                        for (key, created_at) in referrers {
+                            let res = GC::get(
+                                &key,
+                                table_ref.clone(),
+                                key_range_ref.clone(),
+                                vlog_ref.clone(),
+                                read_only_memtables_ref.clone(),
+                            )
+                            .await;
+                            match res {
+                                Ok((_, creation_time)) if creation_time == created_at => sharers.push(key),
+                                Ok(_) | Err(NotFoundInDB) => {}
+                                Err(err) => return Err(err),
+                            }
+                        }
                         match most_recent_value {
                             Ok((value, creation_time)) => {
-                                if entry.created_at < creation_time
-                                    || value == TOMB_STONE_MARKER.as_bytes().to_vec()
-                                {
-                                    invalid_entries_ref.write().await.push(entry);
+                                let is_tombstone = value == TOMB_STONE_MARKER.as_bytes().to_vec();
+                                if entry.created_at < creation_time || is_tombstone {
+                                    if linked && !is_tombstone {
+                                        // the chain extending this record is written again in full
+                                        GC::collapse_chain(
+                                            valid_entries_ref.clone(),
+                                            entry.key.to_owned(),
+                                            value,
+                                        )
+                                        .await;
+                                    }
+                                    GC::handle_shared_entry(
+                                        invalid_entries_ref,
+                                        valid_entries_ref,
+                                        entry,
+                                        sharers,
+                                    )
+                                    .await;
+                                } else if entry.record_type == RecordType::Expiring {
+                                    // an expired value is rewritten as the tombstone it reads as
+                                    let (expiry, _) = ValueExpiry::decode(&entry.value);
+                                    let live = match ValueExpiry::has_passed(Some(expiry.expires_at), now) {
+                                        true => (entry.key, Vec::new(), Vec::new(), None),
+                                        false => (entry.key, value, sharers, Some(expiry.expires_at)),
+                                    };
+                                    valid_entries_ref.write().await.push(live);
                                 } else {
-                                    valid_entries_ref.write().await.push((entry.key, value));
+                                    valid_entries_ref
+                                        .write()
+                                        .await
+                                        .push((entry.key, value, sharers, None));
                                 }
                                 Ok(())
                             }
+                            Err(NotFoundInDB) => {
+                                GC::handle_shared_entry(
+                                    invalid_entries_ref,
+                                    valid_entries_ref,
+                                    entry,
+                                    sharers,
+                                )
+                                .await;
+                                Ok(())
+                            }
                             Err(err) => GC::handle_deleted_entries(invalid_entries_ref, entry, err).await,
                         }
                     })
@@ -226,10 +394,20 @@ impl GC {
                 }
                 // no entries to garbage collect, return early
                 if invalid_entries.read().await.is_empty() {
+                    cfg.dedup.unclaim(claimed);
                     return Ok(());
                 }
-                let new_tail_offset = vlog.read().await.tail_offset + total_bytes_read;
-                let v_offset = GC::write_tail_to_disk(Arc::clone(&vlog), new_tail_offset).await?;
+                // collecting in place leaves the tail where it is
+                let new_tail_offset = match in_place {
+                    true => vlog.read().await.tail_offset,
+                    false => vlog.read().await.tail_offset + total_bytes_read,
+                };
+                // writers wait while moved entries are appended, the store's copy of
+                // value log learns where they end before writers append again
+                let mut store_log = cfg.store_log.write().await;
+                vlog.write().await.size = store_log.size;
+                let created_at = cfg.clock.tick();
+                let v_offset = GC::write_tail_to_disk(Arc::clone(&vlog), new_tail_offset, created_at).await?;
 
                 synced_entries.write().await.push((
                     TAIL_ENTRY_KEY.to_vec(),
@@ -237,39 +415,82 @@ impl GC {
                     v_offset,
                 ));
 
-                GC::write_valid_entries_to_vlog(valid_entries, synced_entries.to_owned(), Arc::clone(&vlog))
-                    .await?;
+                GC::write_valid_entries_to_vlog(
+                    valid_entries,
+                    synced_entries.to_owned(),
+                    Arc::clone(&vlog),
+                    created_at,
+                    &cfg.dedup,
+                )
+                .await?;
                 // call fsync on vlog to guarantee persistence to disk
                 vlog.write().await.sync_to_disk().await?;
+                store_log.size = vlog.read().await.size;
+                drop(store_log);
 
                 GC::write_valid_entries_to_store(
                     synced_entries.to_owned(),
                     memtable.clone(),
                     gc_updated_entries,
                     vlog.clone(),
+                    created_at,
                 )
                 .await?;
 
                 // Don't free space or update tail immediatley until store active memtable is
                 // synced with gc table (handled seperately) but update punch hole marker
                 let mut marker_lock = punch_marker.lock().await;
-                marker_lock.punch_hole_start_offset = vlog.read().await.tail_offset;
+                marker_lock.punch_hole_start_offset = chunk_start;
                 marker_lock.punch_hole_length = total_bytes_read;
+                marker_lock.in_place = in_place;
+                drop(marker_lock);
+                // records of the chunk are freed along with the punched hole
+                match in_place {
+                    true => cfg.dead_offsets.release_within(chunk_start, chunk_end),
+                    false => cfg.dead_offsets.release_before(new_tail_offset),
+                }
+                cfg.dedup.release(chunk_start, chunk_end);
+                if let Err(err) = cfg.dead_offsets.persist().await {
+                    log::warn!("Value log liveness could not be persisted: {}", err);
+                }
             }
             Err(err) => return Err(err),
         };
         Ok(())
     }
 
+    /// Returns `true` if records `entries` read from `[start, end)` can be
+    /// collected in place
+    ///
+    /// Records must lie before `head`, recovery replays value log from there.
+    /// References are dropped with the chunk and written again along with
+    /// the payload they point at, which has to be in the chunk as well
+    pub(crate) fn can_collect_in_place(
+        entries: &[ValueLogEntry],
+        start: ValOffset,
+        end: ValOffset,
+        head: ValOffset,
+    ) -> bool {
+        end <= head
+            && entries
+                .iter()
+                .filter(|entry| entry.record_type == RecordType::ValueRef)
+                .all(|entry| (start..end).contains(&ValueDedup::decode_ref(&entry.value)))
+    }
+
     /// Inserts tail entry to value log
-    pub(crate) async fn write_tail_to_disk(vlog: GCLog, new_tail_offset: usize) -> Result<ValOffset, Error> {
+    pub(crate) async fn write_tail_to_disk(
+        vlog: GCLog,
+        new_tail_offset: usize,
+        created_at: CreatedAt,
+    ) -> Result<ValOffset, Error> {
         vlog.write()
             .await
-            .append(
+            .append_record(
                 &TAIL_ENTRY_KEY.to_vec(),
                 &new_tail_offset.to_le_bytes().to_vec(),
-                Utc::now(),
-                false,
+                created_at,
+                RecordType::Tail,
             )
             .await
     }
@@ -281,6 +502,7 @@ impl GC {
         table: GCTable,
         gc_updated_entries: GCUpdatedEntries<Key>,
         vlog: GCLog,
+        created_at: CreatedAt,
     ) -> Result<(), Error> {
         gc_updated_entries.write().await.clear();
         for (key, value, existing_v_offset) in valid_entries.to_owned().read().await.iter() {
@@ -290,6 +512,7 @@ impl GC {
                 *existing_v_offset,
                 table.clone(),
                 gc_updated_entries.clone(),
+                created_at,
             )
             .await;
             // update  vlog head to the most recent entry offset
@@ -301,17 +524,63 @@ impl GC {
     }
 
     /// Adds valid entries to value log
+    ///
+    /// A value shared by several keys is written once, the other keys get
+    /// references to it
     pub(crate) async fn write_valid_entries_to_vlog(
-        valid_entries: Arc<RwLock<Vec<(Key, Value)>>>,
+        valid_entries: LiveEntries,
         synced_entries: SyncedEntries,
         vlog: GCLog,
+        created_at: CreatedAt,
+        dedup: &ValueDedup,
     ) -> Result<(), Error> {
-        for (key, value) in valid_entries.to_owned().read().await.iter() {
-            let v_offset = vlog.write().await.append(&key, &value, Utc::now(), false).await?;
+        for (key, value, sharers, expires_at) in valid_entries.to_owned().read().await.iter() {
+            let v_offset = match (value.is_empty(), expires_at) {
+                (true, _) => {
+                    vlog.write()
+                        .await
+                        .append(&key[..], TOMB_STONE_MARKER.as_bytes(), created_at, true)
+                        .await?
+                }
+                (false, Some(expires_at)) => {
+                    let expiry = ValueExpiry {
+                        expires_at: *expires_at,
+                    };
+                    vlog.write()
+                        .await
+                        .append_record(&key[..], &expiry.encode(value), created_at, RecordType::Expiring)
+                        .await?
+                }
+                (false, None) => vlog.write().await.append(&key, &value, created_at, false).await?,
+            };
             synced_entries
                 .write()
                 .await
                 .push((key.to_owned(), value.to_owned(), v_offset));
+            for sharer in sharers {
+                vlog.write()
+                    .await
+                    .append_record(
+                        &sharer[..],
+                        &ValueDedup::encode_ref(v_offset),
+                        created_at,
+                        RecordType::ValueRef,
+                    )
+                    .await?;
+                synced_entries
+                    .write()
+                    .await
+                    .push((sharer.to_owned(), value.to_owned(), v_offset));
+            }
+            // puts only share values without a TTL
+            if value.is_empty() || expires_at.is_some() {
+                continue;
+            }
+            let referrers = sharers
+                .iter()
+                .map(|sharer| (sharer.to_owned(), created_at))
+                .collect();
+            dedup.relocate(value, v_offset, referrers);
         }
         Ok(())
     }
@@ -324,7 +593,7 @@ impl GC {
     /// # Errors
     ///
     /// Returns error in case of IO error
-    pub(crate) async fn free_unused_space(&mut self) -> std::result::Result<(Head, Tail), Error> {
+    pub(crate) async fn free_unused_space(&self) -> std::result::Result<(Head, Tail), Error> {
         if !self.gc_updated_entries.read().await.is_empty() {
             return Err(GCErrorAttemptToRemoveUnsyncedEntries);
         }
@@ -332,13 +601,24 @@ impl GC {
         let marker_lock = self.punch_marker.lock().await;
         #[cfg(target_os = "linux")]
         {
-            GC::punch_holes(
-                vlog_path,
-                marker_lock.punch_hole_start_offset as i64,
-                marker_lock.punch_hole_length as i64,
-            )
-            .await?;
-            (self.vlog.write().await).tail_offset += marker_lock.punch_hole_length;
+            if marker_lock.in_place {
+                let cipher = self.vlog.read().await.content.file.node.cipher().await;
+                GC::write_hole(
+                    vlog_path,
+                    marker_lock.punch_hole_start_offset,
+                    marker_lock.punch_hole_length,
+                    cipher,
+                )
+                .await?;
+            } else {
+                GC::punch_holes(
+                    vlog_path,
+                    marker_lock.punch_hole_start_offset as i64,
+                    marker_lock.punch_hole_length as i64,
+                )
+                .await?;
+                (self.vlog.write().await).tail_offset += marker_lock.punch_hole_length;
+            }
             let vlog_reader = self.vlog.read().await;
             Ok((vlog_reader.head_offset, vlog_reader.tail_offset))
         }
@@ -349,13 +629,61 @@ impl GC {
                 GCErrorUnsupportedPlatform(String::from("File system does not support file punch hole",))
             );
             // Even though punch wasn't successful due to OS incompatability, valid entires has been
-            // synced to disk so we can update tail offset
-            (self.vlog.write().await).tail_offset += marker_lock.punch_hole_length;
+            // synced to disk so we can update tail offset. Records collected in place stay as they are
+            if !marker_lock.in_place {
+                (self.vlog.write().await).tail_offset += marker_lock.punch_hole_length;
+            }
             let vlog_reader = self.vlog.read().await;
             Ok((vlog_reader.head_offset, vlog_reader.tail_offset))
         }
     }
 
+    /// Replaces `len` bytes of records at `offset` of value log with a
+    /// `RecordType::Hole` record and punches a hole in its value
+    ///
+    /// The header is encrypted with `cipher` if value log is encrypted
+    ///
+    /// The header is synced before the hole is punched, readers of value log
+    /// skip the hole as one record
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case the header could not be written or punch failed
+    #[cfg(target_os = "linux")]
+    pub(crate) async fn write_hole(
+        file_path: impl 'static + P,
+        offset: ValOffset,
+        len: usize,
+        cipher: Option<crate::encryption::FileCipher>,
+    ) -> std::result::Result<(), Error> {
+        let header_len = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8;
+        let mut header = Vec::with_capacity(header_len);
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&((len - header_len) as u32).to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes());
+        header.push(RecordType::Hole.as_byte());
+        if let Some(cipher) = cipher {
+            cipher.apply(offset as u64, &mut header);
+        }
+        let path = file_path.as_ref().to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            use std::os::unix::fs::FileExt;
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .map_err(|error| Error::FileOpen {
+                    path: path.to_owned(),
+                    error,
+                })?;
+            file.write_all_at(&header, offset as u64)
+                .map_err(|error| Error::FileWrite { path, error })?;
+            file.sync_all().map_err(FileSync)
+        })
+        .await
+        .map_err(|_| TokioJoin)??;
+        GC::punch_holes(file_path, (offset + header_len) as i64, (len - header_len) as i64).await
+    }
+
     /// Punch holes in value log file
     ///
     /// Deallocates space (i.e., creates a hole) in the byte range
@@ -364,7 +692,7 @@ impl GC {
     /// # Errors
     ///
     /// Returns error in case punch failed
-    #[allow(dead_code)] // will show unused on non-linux environment
+    #[cfg(target_os = "linux")]
     pub(crate) async fn punch_holes(
         file_path: impl 'static + P,
         offset: off_t,
@@ -407,9 +735,9 @@ impl GC {
         val_offset: ValOffset,
         memtable: GCTable,
         gc_updated_entries: GCUpdatedEntries<Key>,
+        created_at: CreatedAt,
     ) {
         let is_tombstone = value.as_ref().is_empty();
-        let created_at = Utc::now();
         let v_offset = val_offset;
         let entry = Entry::new(key.as_ref(), v_offset, created_at, is_tombstone);
         memtable.write().await.insert(&entry);
@@ -488,8 +816,8 @@ impl GC {
             let index = Index::new(sst.index_file.path.to_owned(), sst.index_file.file.to_owned());
             let block_handle = index.get(&key).await?;
 
-            if block_handle.is_some() {
-                let sst_res = sst.get(block_handle.unwrap(), &key).await?;
+            if let Some(block_handle) = block_handle {
+                let sst_res = sst.get(block_handle, &key).await?;
 
                 if sst_res.as_ref().is_some() {
                     let (val_offset, created_at, is_tombstone) = sst_res.unwrap();
@@ -526,14 +854,42 @@ impl GC {
         offset: ValOffset,
         creation_at: CreatedAt,
     ) -> Result<(Value, CreatedAt), Error> {
-        let res = val_log.read().await.get(offset).await?;
-        if let Some((value, is_tombstone)) = res {
-            if is_tombstone {
-                return Err(NotFoundInDB);
-            }
-            return Ok((value, creation_at));
+        match val_log.read().await.get_value(offset).await {
+            // does not copy if the buffer is not shared
+            Ok(value) => Ok((Vec::from(value), creation_at)),
+            Err(VLogTombstone(_)) => Err(NotFoundInDB),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Handles records whose own key no longer refers to them
+    ///
+    /// The value is rewritten under the first key still sharing it, the
+    /// record is invalid if there is none
+    pub(crate) async fn handle_shared_entry(
+        invalid_entries: Arc<RwLock<Vec<ValueLogEntry>>>,
+        valid_entries: LiveEntries,
+        entry: ValueLogEntry,
+        mut sharers: Vec<Key>,
+    ) {
+        if sharers.is_empty() {
+            invalid_entries.write().await.push(entry);
+            return;
+        }
+        let key = sharers.remove(0);
+        valid_entries
+            .write()
+            .await
+            .push((key, entry.value, sharers, None));
+    }
+
+    /// Rewrites latest value of `key`, which an append chain resolved, unless
+    /// another record of the chain did already
+    pub(crate) async fn collapse_chain(valid_entries: LiveEntries, key: Key, value: Value) {
+        let mut valid_entries = valid_entries.write().await;
+        if !valid_entries.iter().any(|(valid_key, ..)| *valid_key == key) {
+            valid_entries.push((key, value, Vec::new(), None));
         }
-        Err(NotFoundInDB)
     }
 
     /// Handles entries marked as tombstone
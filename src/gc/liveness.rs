@@ -0,0 +1,108 @@
+use crate::consts::VLOG_SEGMENT_SIZE;
+use crate::types::ValOffset;
+use std::collections::{btree_map::Entry, BTreeMap};
+use std::sync::{Arc, Mutex};
+
+/// Records and bytes written to one segment of value log
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct SegmentWrites {
+    pub(crate) records: usize,
+    pub(crate) bytes: usize,
+
+    /// Offset of the first record counted, the records from it on that
+    /// start in the segment can be collected in place
+    pub(crate) first: ValOffset,
+}
+
+/// Writes to each `VLOG_SEGMENT_SIZE` segment of value log, used to estimate
+/// the size of records compaction found dead and rank segments by garbage
+///
+/// Records count toward the segment they start in. Counts are persisted
+/// along with dead offsets, see `DeadOffsets::persist`, segments whose
+/// writes were not are estimated from the average record size of the others
+#[derive(Debug, Clone, Default)]
+pub struct VLogLiveness {
+    segments: Arc<Mutex<BTreeMap<usize, SegmentWrites>>>,
+}
+
+impl VLogLiveness {
+    /// Records a write of `size` bytes at `offset`
+    pub fn record_write(&self, offset: ValOffset, size: usize) {
+        let mut segments = self.segments.lock().unwrap();
+        let segment = segments.entry(segment_of(offset)).or_insert(SegmentWrites {
+            first: offset,
+            ..Default::default()
+        });
+        segment.records += 1;
+        segment.bytes += size;
+    }
+
+    /// Returns estimated size of records starting in `segment`, `None` if
+    /// no write to value log was counted
+    pub fn avg_record_size(&self, segment: usize) -> Option<usize> {
+        let segments = self.segments.lock().unwrap();
+        let writes = match segments.get(&segment) {
+            Some(writes) => *writes,
+            None => segments
+                .values()
+                .fold(SegmentWrites::default(), |total, writes| SegmentWrites {
+                    records: total.records + writes.records,
+                    bytes: total.bytes + writes.bytes,
+                    first: total.first,
+                }),
+        };
+        (writes.records > 0).then(|| writes.bytes / writes.records)
+    }
+
+    /// Returns offset of the first record counted in `segment`
+    pub fn first_record(&self, segment: usize) -> Option<ValOffset> {
+        self.segments
+            .lock()
+            .unwrap()
+            .get(&segment)
+            .map(|writes| writes.first)
+    }
+
+    /// Forgets segments ending at or before `end`, GC calls this once it frees up to `end`
+    pub fn release_before(&self, end: ValOffset) {
+        let mut segments = self.segments.lock().unwrap();
+        *segments = segments.split_off(&segment_of(end));
+    }
+
+    /// Forgets `segment`, GC calls this once it collected it in place
+    pub fn release_segment(&self, segment: usize) {
+        self.segments.lock().unwrap().remove(&segment);
+    }
+
+    /// Returns counted writes of every segment
+    pub(crate) fn snapshot(&self) -> Vec<(usize, SegmentWrites)> {
+        let segments = self.segments.lock().unwrap();
+        segments
+            .iter()
+            .map(|(segment, writes)| (*segment, *writes))
+            .collect()
+    }
+
+    /// Adds writes counted before a restart
+    pub(crate) fn restore(&self, restored: impl IntoIterator<Item = (usize, SegmentWrites)>) {
+        let mut segments = self.segments.lock().unwrap();
+        for (segment, restored) in restored {
+            match segments.entry(segment) {
+                Entry::Vacant(entry) => {
+                    entry.insert(restored);
+                }
+                Entry::Occupied(mut entry) => {
+                    let writes = entry.get_mut();
+                    writes.records += restored.records;
+                    writes.bytes += restored.bytes;
+                    writes.first = writes.first.min(restored.first);
+                }
+            }
+        }
+    }
+}
+
+/// Returns segment holding `offset`
+pub(crate) fn segment_of(offset: ValOffset) -> usize {
+    offset / VLOG_SEGMENT_SIZE
+}
@@ -1 +1,6 @@
+mod dead_offsets;
 pub(crate) mod garbage_collector;
+mod liveness;
+
+pub(crate) use dead_offsets::DeadOffsets;
+pub(crate) use liveness::{segment_of, VLogLiveness};
@@ -0,0 +1,8 @@
+mod monitor;
+pub use monitor::BackgroundError;
+pub use monitor::BackgroundTask;
+pub use monitor::Health;
+pub use monitor::HealthMonitor;
+pub use monitor::HealthState;
+pub use monitor::TaskHeartbeat;
+pub use monitor::TaskOutcome;
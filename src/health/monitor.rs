@@ -0,0 +1,343 @@
+use crate::{consts::HEALTH_ERROR_HISTORY_SIZE, err::Error, types::CreatedAt};
+use chrono::Utc;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, RwLock},
+};
+
+/// Health states of the store
+///
+/// `Healthy` means background tasks are succeeding
+/// `Degraded` means a background task keeps failing but writes are still accepted
+/// `ReadOnly` means background tasks failed too many times in a row, writes
+/// are rejected until the store is reopened
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthState {
+    Healthy,
+    Degraded,
+    ReadOnly,
+}
+
+/// Background tasks tracked by the health monitor
+///
+/// Only flush and compaction failures move the store between health states,
+/// a failing GC run leaves the value log larger but loses no data
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BackgroundTask {
+    Flush,
+    Compaction,
+    Gc,
+}
+
+/// Outcome of the last run of a background task
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskOutcome {
+    Succeeded,
+    Failed,
+}
+
+/// Liveness and last run of a background task
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TaskHeartbeat {
+    /// Whether the task is running, compaction and GC loops stay alive until the
+    /// store is dropped while flush tasks are spawned on demand and are only
+    /// alive while flushing
+    pub alive: bool,
+
+    /// Time the last run finished, `None` if the task has not run since open
+    pub last_run_at: Option<CreatedAt>,
+
+    /// Outcome of the last run, `None` if the task has not run since open
+    pub last_outcome: Option<TaskOutcome>,
+
+    /// Number of failed runs since open
+    pub error_count: usize,
+}
+
+/// Error reported by a background task
+#[derive(Clone, Debug)]
+pub struct BackgroundError {
+    pub task: BackgroundTask,
+    pub message: String,
+    pub occurred_at: CreatedAt,
+}
+
+/// Snapshot of the store health returned by `DataStore::health`
+#[derive(Clone, Debug)]
+pub struct Health {
+    /// Current health state
+    pub state: HealthState,
+
+    /// Number of flushes that failed in a row
+    pub consecutive_flush_failures: usize,
+
+    /// Number of compactions that failed in a row
+    pub consecutive_compaction_failures: usize,
+
+    /// Most recent background errors, oldest first
+    pub last_errors: Vec<BackgroundError>,
+
+    /// Heartbeat of memtable flushes
+    pub flush: TaskHeartbeat,
+
+    /// Heartbeat of background compaction, manual compactions are not included
+    pub compaction: TaskHeartbeat,
+
+    /// Heartbeat of online garbage collection
+    pub gc: TaskHeartbeat,
+
+    /// Number of read-only memtables waiting to be flushed, including those being flushed
+    pub pending_flushes: usize,
+
+    /// Number of memtables currently being flushed
+    pub flushes_in_flight: usize,
+}
+
+/// Heartbeat of a task along with the number of its instances running
+#[derive(Debug, Default)]
+struct TaskState {
+    running: usize,
+    heartbeat: TaskHeartbeat,
+}
+
+/// Shared state behind `HealthMonitor`
+#[derive(Debug)]
+struct HealthInner {
+    state: HealthState,
+    consecutive_flush_failures: usize,
+    consecutive_compaction_failures: usize,
+    last_errors: VecDeque<BackgroundError>,
+    tasks: HashMap<BackgroundTask, TaskState>,
+    degraded_threshold: usize,
+    read_only_threshold: usize,
+}
+
+/// Tracks failures of background tasks and moves the store
+/// between health states
+#[derive(Clone, Debug)]
+pub struct HealthMonitor {
+    inner: Arc<RwLock<HealthInner>>,
+}
+
+impl HealthMonitor {
+    /// Creates new `HealthMonitor`
+    pub fn new(degraded_threshold: usize, read_only_threshold: usize) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(HealthInner {
+                state: HealthState::Healthy,
+                consecutive_flush_failures: 0,
+                consecutive_compaction_failures: 0,
+                last_errors: VecDeque::with_capacity(HEALTH_ERROR_HISTORY_SIZE),
+                tasks: HashMap::new(),
+                degraded_threshold,
+                read_only_threshold,
+            })),
+        }
+    }
+
+    /// Updates number of consecutive failures needed to enter
+    /// `Degraded` and `ReadOnly` states
+    pub fn set_thresholds(&self, degraded_threshold: usize, read_only_threshold: usize) {
+        let mut inner = self.inner.write().unwrap();
+        inner.degraded_threshold = degraded_threshold;
+        inner.read_only_threshold = read_only_threshold;
+        inner.transition();
+    }
+
+    /// Records a failed run of `task`
+    pub fn record_failure(&self, task: BackgroundTask, err: &Error) {
+        let mut inner = self.inner.write().unwrap();
+        match task {
+            BackgroundTask::Flush => inner.consecutive_flush_failures += 1,
+            BackgroundTask::Compaction => inner.consecutive_compaction_failures += 1,
+            BackgroundTask::Gc => {}
+        }
+        let occurred_at = Utc::now();
+        let heartbeat = &mut inner.tasks.entry(task).or_default().heartbeat;
+        heartbeat.last_run_at = Some(occurred_at);
+        heartbeat.last_outcome = Some(TaskOutcome::Failed);
+        heartbeat.error_count += 1;
+        if inner.last_errors.len() == HEALTH_ERROR_HISTORY_SIZE {
+            inner.last_errors.pop_front();
+        }
+        inner.last_errors.push_back(BackgroundError {
+            task,
+            message: err.to_string(),
+            occurred_at,
+        });
+        let previous = inner.state;
+        inner.transition();
+        if inner.state != previous {
+            log::error!("Store health changed from {:?} to {:?}", previous, inner.state);
+        }
+    }
+
+    /// Records a successful run of `task`
+    pub fn record_success(&self, task: BackgroundTask) {
+        let mut inner = self.inner.write().unwrap();
+        match task {
+            BackgroundTask::Flush => inner.consecutive_flush_failures = 0,
+            BackgroundTask::Compaction => inner.consecutive_compaction_failures = 0,
+            BackgroundTask::Gc => {}
+        }
+        let heartbeat = &mut inner.tasks.entry(task).or_default().heartbeat;
+        heartbeat.last_run_at = Some(Utc::now());
+        heartbeat.last_outcome = Some(TaskOutcome::Succeeded);
+        inner.transition();
+    }
+
+    /// Marks an instance of `task` as running until the returned guard is dropped
+    ///
+    /// Spawned tasks hold the guard for their whole lifetime so a task that
+    /// panicked or returned is no longer reported alive
+    pub(crate) fn task_started(&self, task: BackgroundTask) -> TaskGuard {
+        self.inner.write().unwrap().tasks.entry(task).or_default().running += 1;
+        TaskGuard {
+            monitor: self.clone(),
+            task,
+        }
+    }
+
+    /// Returns `true` if writes should be rejected
+    pub fn is_read_only(&self) -> bool {
+        self.inner.read().unwrap().state == HealthState::ReadOnly
+    }
+
+    /// Returns a snapshot of the current health
+    pub fn snapshot(&self) -> Health {
+        let inner = self.inner.read().unwrap();
+        Health {
+            state: inner.state,
+            consecutive_flush_failures: inner.consecutive_flush_failures,
+            consecutive_compaction_failures: inner.consecutive_compaction_failures,
+            last_errors: inner.last_errors.iter().cloned().collect(),
+            flush: inner.heartbeat(BackgroundTask::Flush),
+            compaction: inner.heartbeat(BackgroundTask::Compaction),
+            gc: inner.heartbeat(BackgroundTask::Gc),
+            pending_flushes: 0,
+            flushes_in_flight: 0,
+        }
+    }
+}
+
+/// Keeps a background task reported alive, see `HealthMonitor::task_started`
+#[derive(Debug)]
+pub(crate) struct TaskGuard {
+    monitor: HealthMonitor,
+    task: BackgroundTask,
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        // the lock is poisoned only if a panic happened while holding it
+        if let Ok(mut inner) = self.monitor.inner.write() {
+            if let Some(state) = inner.tasks.get_mut(&self.task) {
+                state.running = state.running.saturating_sub(1);
+            }
+        }
+    }
+}
+
+impl HealthInner {
+    /// Moves to the state matching the current failure counts
+    ///
+    /// `ReadOnly` is final, the store has to be reopened to accept writes again
+    fn transition(&mut self) {
+        if self.state == HealthState::ReadOnly {
+            return;
+        }
+        let failures = self
+            .consecutive_flush_failures
+            .max(self.consecutive_compaction_failures);
+        self.state = if failures >= self.read_only_threshold {
+            HealthState::ReadOnly
+        } else if failures >= self.degraded_threshold {
+            HealthState::Degraded
+        } else {
+            HealthState::Healthy
+        };
+    }
+
+    /// Returns heartbeat of `task` with liveness taken from its running instances
+    fn heartbeat(&self, task: BackgroundTask) -> TaskHeartbeat {
+        self.tasks
+            .get(&task)
+            .map(|state| TaskHeartbeat {
+                alive: state.running > 0,
+                ..state.heartbeat.clone()
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_transitions() {
+        let monitor = HealthMonitor::new(2, 4);
+        assert_eq!(monitor.snapshot().state, HealthState::Healthy);
+
+        monitor.record_failure(BackgroundTask::Flush, &Error::TableSummaryIsNone);
+        assert_eq!(monitor.snapshot().state, HealthState::Healthy);
+
+        monitor.record_failure(BackgroundTask::Flush, &Error::TableSummaryIsNone);
+        assert_eq!(monitor.snapshot().state, HealthState::Degraded);
+
+        // success on another task does not clear flush failures
+        monitor.record_success(BackgroundTask::Compaction);
+        assert_eq!(monitor.snapshot().state, HealthState::Degraded);
+
+        monitor.record_success(BackgroundTask::Flush);
+        let health = monitor.snapshot();
+        assert_eq!(health.state, HealthState::Healthy);
+        assert_eq!(health.consecutive_flush_failures, 0);
+        assert_eq!(health.last_errors.len(), 2);
+
+        for _ in 0..4 {
+            monitor.record_failure(BackgroundTask::Compaction, &Error::TableSummaryIsNone);
+        }
+        assert!(monitor.is_read_only());
+
+        // read-only is not left on success
+        monitor.record_success(BackgroundTask::Compaction);
+        assert_eq!(monitor.snapshot().state, HealthState::ReadOnly);
+    }
+
+    #[test]
+    fn test_health_error_history_is_bounded() {
+        let monitor = HealthMonitor::new(usize::MAX, usize::MAX);
+        for _ in 0..HEALTH_ERROR_HISTORY_SIZE + 5 {
+            monitor.record_failure(BackgroundTask::Flush, &Error::TableSummaryIsNone);
+        }
+        let health = monitor.snapshot();
+        assert_eq!(health.last_errors.len(), HEALTH_ERROR_HISTORY_SIZE);
+        assert_eq!(health.consecutive_flush_failures, HEALTH_ERROR_HISTORY_SIZE + 5);
+        assert_eq!(health.last_errors[0].task, BackgroundTask::Flush);
+    }
+
+    #[test]
+    fn test_health_tracks_task_heartbeats() {
+        let monitor = HealthMonitor::new(usize::MAX, usize::MAX);
+        assert_eq!(monitor.snapshot().gc, TaskHeartbeat::default());
+
+        let guard = monitor.task_started(BackgroundTask::Gc);
+        monitor.record_failure(BackgroundTask::Gc, &Error::TableSummaryIsNone);
+        let gc = monitor.snapshot().gc;
+        assert!(gc.alive);
+        assert_eq!(gc.last_outcome, Some(TaskOutcome::Failed));
+        assert_eq!(gc.error_count, 1);
+        // GC failures do not affect the health state
+        assert_eq!(monitor.snapshot().consecutive_flush_failures, 0);
+
+        monitor.record_success(BackgroundTask::Gc);
+        drop(guard);
+        let gc = monitor.snapshot().gc;
+        assert!(!gc.alive);
+        assert_eq!(gc.last_outcome, Some(TaskOutcome::Succeeded));
+        assert_eq!(gc.error_count, 1);
+        assert!(gc.last_run_at.is_some());
+        assert!(!monitor.snapshot().compaction.alive);
+    }
+}
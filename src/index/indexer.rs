@@ -57,7 +57,7 @@ use std::path::{Path, PathBuf};
 use Error::*;
 type Offset = u32;
 type KeyLength = u32;
-type BlockOffset = u32;
+pub type BlockOffset = u32;
 
 /// Represents index file  
 #[derive(Debug, Clone)]
@@ -103,7 +103,7 @@ pub struct RangeOffset {
     /// Block to start reading from in case of range queries
     pub start_offset: Offset,
 
-    /// Block to stop reading from in case of range queries
+    /// Offset reading stops at, `u32::MAX` reads to the end of data file
     pub end_offset: Offset,
 }
 
@@ -170,7 +170,12 @@ impl Index {
         self.file.file.get_from_index(searched_key.as_ref()).await
     }
 
-    // pub(crate) async fn get_block_offset_range(&self, start_key: &[u8], end_key: &[u8]) -> Result<RangeOffset, Error> {
-    //     self.file.file.get_block_range(start_key, end_key).await
-    // }
+    /// Retrieves byte range of the blocks holding keys within `[start_key, end_key]`
+    pub(crate) async fn get_block_offset_range(
+        &self,
+        start_key: &[u8],
+        end_key: &[u8],
+    ) -> Result<Option<RangeOffset>, Error> {
+        self.file.file.get_block_range(start_key, end_key).await
+    }
 }
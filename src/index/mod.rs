@@ -1,4 +1,5 @@
 mod indexer;
+pub use indexer::BlockOffset;
 pub use indexer::Index;
 pub use indexer::IndexFile;
 pub use indexer::RangeOffset;
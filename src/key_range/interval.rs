@@ -0,0 +1,289 @@
+//! # Interval Tree
+//!
+//! Stores the `[smallest_key, biggest_key]` interval of every sstable so point lookups
+//! and range scans only visit sstables whose key range can contain the searched keys.
+//!
+//! The tree is kept as a vector of intervals sorted by their lower bound and treated as
+//! an implicit balanced binary search tree (the root of `[lo, hi)` is the middle element).
+//! Every node is augmented with the biggest upper bound found in its subtree, which lets
+//! queries prune whole subtrees in `O(log n + k)` where `k` is the number of matches.
+//!
+//! Insertions and removals rebuild the augmentation in `O(n)`, this is fine since they only
+//! happen during flush and compaction while lookups happen on every read.
+//!
+//! [`PartitionedIntervalTree`] keeps one tree per partition (the bucket an sstable belongs to)
+//! so flush and compaction only rebuild the tree of the bucket they touch, and queries cost
+//! `O(b log n + k)` for `b` buckets.
+
+use crate::types::Key;
+use std::{collections::HashMap, hash::Hash};
+
+/// Each interval stored in the [`IntervalTree`]
+#[derive(Clone, Debug)]
+struct Node<V> {
+    start: Key,
+    end: Key,
+    value: V,
+}
+
+/// Augmented interval tree for sstable key ranges
+#[derive(Clone, Debug)]
+pub struct IntervalTree<V> {
+    /// Intervals sorted by lower bound
+    nodes: Vec<Node<V>>,
+
+    /// Biggest upper bound in the subtree rooted at each index
+    max_end: Vec<Key>,
+}
+
+impl<V> Default for IntervalTree<V> {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            max_end: Vec::new(),
+        }
+    }
+}
+
+impl<V: Clone + PartialEq> IntervalTree<V> {
+    /// Inserts `value` covering the `[start, end]` interval
+    pub fn insert<T: AsRef<[u8]>>(&mut self, start: T, end: T, value: V) {
+        let start = start.as_ref().to_vec();
+        let pos = self.nodes.partition_point(|n| n.start <= start);
+        self.nodes.insert(
+            pos,
+            Node {
+                start,
+                end: end.as_ref().to_vec(),
+                value,
+            },
+        );
+        self.rebuild();
+    }
+
+    /// Removes every interval mapped to `value`
+    ///
+    /// Returns `true` if an interval was removed
+    pub fn remove(&mut self, value: &V) -> bool {
+        let len = self.nodes.len();
+        self.nodes.retain(|n| n.value != *value);
+        if self.nodes.len() == len {
+            return false;
+        }
+        self.rebuild();
+        true
+    }
+
+    /// Returns values whose interval contains `point`
+    pub fn stab<T: AsRef<[u8]>>(&self, point: T) -> Vec<V> {
+        self.overlapping(point.as_ref(), point.as_ref())
+    }
+
+    /// Returns values whose interval overlaps `[start, end]`
+    pub fn overlapping<T: AsRef<[u8]>>(&self, start: T, end: T) -> Vec<V> {
+        let mut found = Vec::new();
+        self.query(0, self.nodes.len(), start.as_ref(), end.as_ref(), &mut found);
+        found
+    }
+
+    /// Returns number of intervals in the tree
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns `true` if the tree has no interval
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn query(&self, lo: usize, hi: usize, start: &[u8], end: &[u8], found: &mut Vec<V>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        // No interval in this subtree reaches `start`
+        if self.max_end[mid].as_slice() < start {
+            return;
+        }
+        self.query(lo, mid, start, end, found);
+        let node = &self.nodes[mid];
+        // Every interval to the right starts after `end`
+        if node.start.as_slice() > end {
+            return;
+        }
+        if node.end.as_slice() >= start {
+            found.push(node.value.to_owned());
+        }
+        self.query(mid + 1, hi, start, end, found);
+    }
+
+    fn rebuild(&mut self) {
+        self.max_end = vec![Vec::new(); self.nodes.len()];
+        self.build(0, self.nodes.len());
+    }
+
+    fn build(&mut self, lo: usize, hi: usize) -> Option<Key> {
+        if lo >= hi {
+            return None;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let mut max_end = self.nodes[mid].end.to_owned();
+        for child in [self.build(lo, mid), self.build(mid + 1, hi)]
+            .into_iter()
+            .flatten()
+        {
+            if child > max_end {
+                max_end = child;
+            }
+        }
+        self.max_end[mid] = max_end.to_owned();
+        Some(max_end)
+    }
+}
+
+/// Interval trees partitioned by a key, such as the bucket of each sstable
+#[derive(Clone, Debug)]
+pub struct PartitionedIntervalTree<P, V> {
+    partitions: HashMap<P, IntervalTree<V>>,
+}
+
+impl<P, V> Default for PartitionedIntervalTree<P, V> {
+    fn default() -> Self {
+        Self {
+            partitions: HashMap::new(),
+        }
+    }
+}
+
+impl<P: Eq + Hash, V: Clone + PartialEq> PartitionedIntervalTree<P, V> {
+    /// Creates new `PartitionedIntervalTree`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts `value` covering the `[start, end]` interval into `partition`
+    pub fn insert<T: AsRef<[u8]>>(&mut self, partition: P, start: T, end: T, value: V) {
+        self.partitions
+            .entry(partition)
+            .or_default()
+            .insert(start, end, value);
+    }
+
+    /// Removes every interval of `partition` mapped to `value`
+    ///
+    /// Returns `true` if an interval was removed
+    pub fn remove(&mut self, partition: &P, value: &V) -> bool {
+        let Some(tree) = self.partitions.get_mut(partition) else {
+            return false;
+        };
+        let removed = tree.remove(value);
+        if tree.is_empty() {
+            self.partitions.remove(partition);
+        }
+        removed
+    }
+
+    /// Returns values whose interval contains `point`
+    pub fn stab<T: AsRef<[u8]>>(&self, point: T) -> Vec<V> {
+        self.partitions
+            .values()
+            .flat_map(|tree| tree.stab(point.as_ref()))
+            .collect()
+    }
+
+    /// Returns values whose interval overlaps `[start, end]` across every partition
+    pub fn overlapping<T: AsRef<[u8]>>(&self, start: T, end: T) -> Vec<V> {
+        self.partitions
+            .values()
+            .flat_map(|tree| tree.overlapping(start.as_ref(), end.as_ref()))
+            .collect()
+    }
+
+    /// Returns number of intervals in every partition
+    pub fn len(&self) -> usize {
+        self.partitions.values().map(|tree| tree.len()).sum()
+    }
+
+    /// Returns number of partitions holding at least one interval
+    pub fn partitions(&self) -> usize {
+        self.partitions.len()
+    }
+
+    /// Returns `true` if no partition has an interval
+    pub fn is_empty(&self) -> bool {
+        self.partitions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stab() {
+        let mut tree = IntervalTree::default();
+        tree.insert("a", "f", 1);
+        tree.insert("c", "d", 2);
+        tree.insert("g", "k", 3);
+        tree.insert("b", "z", 4);
+
+        let mut found = tree.stab("e");
+        found.sort();
+        assert_eq!(found, vec![1, 4]);
+
+        let mut found = tree.stab("h");
+        found.sort();
+        assert_eq!(found, vec![3, 4]);
+
+        assert!(tree.stab("0").is_empty());
+    }
+
+    #[test]
+    fn test_overlapping() {
+        let mut tree = IntervalTree::default();
+        tree.insert("a", "c", 1);
+        tree.insert("d", "f", 2);
+        tree.insert("g", "i", 3);
+
+        let mut found = tree.overlapping("c", "d");
+        found.sort();
+        assert_eq!(found, vec![1, 2]);
+        assert_eq!(tree.overlapping("j", "z"), Vec::<i32>::new());
+        assert_eq!(tree.overlapping("a", "z").len(), 3);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut tree = IntervalTree::default();
+        tree.insert("a", "c", 1);
+        tree.insert("b", "z", 2);
+        assert_eq!(tree.len(), 2);
+
+        assert!(tree.remove(&2));
+        assert!(!tree.remove(&2));
+        assert_eq!(tree.len(), 1);
+        assert!(tree.stab("x").is_empty());
+        assert_eq!(tree.stab("b"), vec![1]);
+    }
+
+    #[test]
+    fn test_partitioned() {
+        let mut tree = PartitionedIntervalTree::new();
+        tree.insert("bucket_1", "a", "f", 1);
+        tree.insert("bucket_1", "g", "k", 2);
+        tree.insert("bucket_2", "b", "z", 3);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.partitions(), 2);
+
+        let mut found = tree.stab("h");
+        found.sort();
+        assert_eq!(found, vec![2, 3]);
+
+        // removal only touches the given partition
+        assert!(!tree.remove(&"bucket_1", &3));
+        assert!(tree.remove(&"bucket_2", &3));
+        assert_eq!(tree.partitions(), 1);
+        assert_eq!(tree.overlapping("a", "z").len(), 2);
+        assert!(tree.stab("x").is_empty());
+    }
+}
@@ -1,6 +1,6 @@
+mod interval;
 mod range;
 pub use range::BiggestKey;
 pub use range::KeyRange;
-#[cfg(test)]
 pub use range::Range;
 pub use range::SmallestKey;
@@ -1,12 +1,13 @@
 use tokio::sync::RwLock;
 
+use super::interval::PartitionedIntervalTree;
 use crate::{
+    cache::FilterCache,
     err::Error,
-    sst::Table,
+    sst::{SstId, Table},
     types::{self},
 };
 use std::{
-    cmp::Ordering,
     collections::HashMap,
     path::{Path, PathBuf},
     sync::Arc,
@@ -20,13 +21,21 @@ pub type SmallestKey = types::Key;
 
 #[derive(Clone, Debug)]
 pub struct KeyRange {
-    /// HashMap to map SSTable directory path to its key range
-    pub key_ranges: Arc<RwLock<HashMap<PathBuf, Range>>>,
+    /// HashMap to map SSTable id to its key range
+    pub key_ranges: Arc<RwLock<HashMap<SstId, Range>>>,
 
-    /// Maps SSTable path to its key range (for sstables
+    /// Maps SSTable id to its key range (for sstables
     /// whose filters are just restored yet to be move to
     /// `key_ranges`)
-    pub restored_ranges: Arc<RwLock<HashMap<PathBuf, Range>>>,
+    pub restored_ranges: Arc<RwLock<HashMap<SstId, Range>>>,
+
+    /// Interval trees over the smallest and biggest key of each
+    /// SSTable in `key_ranges`, partitioned by bucket directory,
+    /// maps intervals to SSTable id
+    pub intervals: Arc<RwLock<PartitionedIntervalTree<PathBuf, SstId>>>,
+
+    /// Accounts memory held by filters of SSTables in `key_ranges`
+    pub filter_cache: FilterCache,
 }
 
 /// Represents smallest and largest key in an sstable
@@ -57,33 +66,104 @@ impl KeyRange {
         Self {
             key_ranges: Arc::new(RwLock::new(HashMap::new())),
             restored_ranges: Arc::new(RwLock::new(HashMap::new())),
+            intervals: Arc::new(RwLock::new(PartitionedIntervalTree::new())),
+            filter_cache: FilterCache::default(),
         }
     }
-    /// Maps SSTable path to its key range
-    pub async fn set<P: AsRef<Path> + Send + Sync, T: AsRef<[u8]>>(
-        &self,
-        sst_dir: P,
-        smallest_key: T,
-        biggest_key: T,
-        table: Table,
-    ) -> bool {
-        self.key_ranges
-            .write()
-            .await
-            .insert(
-                sst_dir.as_ref().to_path_buf(),
-                Range::new(smallest_key.as_ref(), biggest_key.as_ref(), table),
-            )
-            .is_some()
+    /// Maps `table` to its key range
+    ///
+    /// Returns `true` if a range of the table was replaced
+    pub async fn set<T: AsRef<[u8]>>(&self, smallest_key: T, biggest_key: T, table: Table) -> bool {
+        let range = Range::new(smallest_key.as_ref(), biggest_key.as_ref(), table);
+        self.install(vec![range], &[]).await > 0
     }
 
     /// Removes an entry from the `key_ranges` hash map
-    pub async fn remove<P: AsRef<Path> + Send + Sync>(&self, sst_path: P) -> bool {
-        self.key_ranges.write().await.remove(sst_path.as_ref()).is_some()
+    pub async fn remove(&self, id: SstId) -> bool {
+        let existed = self.key_ranges.read().await.contains_key(&id);
+        if existed {
+            self.install(vec![], &[id]).await;
+        }
+        existed
+    }
+
+    /// Installs `added` ranges and drops `removed` tables in one update
+    ///
+    /// Range, interval and filter of every table change together under the
+    /// write locks, so the read path sees either none or all of a flush or
+    /// compaction. Locks are taken in `restored_ranges`, `key_ranges`,
+    /// `intervals` order, same as readers
+    ///
+    /// Returns number of tables whose range was replaced
+    pub async fn install(&self, added: Vec<Range>, removed: &[SstId]) -> usize {
+        let mut restored_ranges = self.restored_ranges.write().await;
+        let mut key_ranges = self.key_ranges.write().await;
+        let mut intervals = self.intervals.write().await;
+        for id in removed {
+            restored_ranges.remove(id);
+            self.filter_cache.remove(*id);
+            if let Some(range) = key_ranges.remove(id) {
+                intervals.remove(&Self::partition_of(&range.sst.dir), id);
+            }
+        }
+        let mut replaced = 0;
+        for mut range in added {
+            let id = range.sst.id();
+            // the table may have been installed at another path since it was registered
+            if let Some(previous) = key_ranges.get(&id) {
+                intervals.remove(&Self::partition_of(&previous.sst.dir), &id);
+            }
+            // a filter restored for the previous install is stale now
+            restored_ranges.remove(&id);
+            intervals.insert(
+                Self::partition_of(&range.sst.dir),
+                &range.smallest_key,
+                &range.biggest_key,
+                id,
+            );
+            if self.filter_cache.loads_on_read() {
+                // filter is rebuilt from disk on the first lookup of the table
+                if let Some(filter) = range
+                    .sst
+                    .filter
+                    .as_mut()
+                    .filter(|filter| filter.file_path.is_some())
+                {
+                    filter.unload();
+                }
+            }
+            match range.sst.filter.as_ref() {
+                Some(filter) => self.filter_cache.insert(id, filter, &range.sst.hotness),
+                None => self.filter_cache.remove(id),
+            }
+            if key_ranges.insert(id, range).is_some() {
+                replaced += 1;
+            }
+        }
+        replaced
+    }
+
+    /// Returns bucket directory of SSTable at `sst_dir`, used to partition intervals
+    fn partition_of(sst_dir: &Path) -> PathBuf {
+        sst_dir.parent().unwrap_or(sst_dir).to_path_buf()
     }
 
-    /// Returns `Table`  vector whose last key is greater than the
-    /// supplied key parameter
+    /// Returns ranges of SSTables whose keys overlap `[start_key, end_key]`
+    ///
+    /// Candidates are selected with the interval trees in `O(b log n + k)` for `b` buckets
+    async fn overlapping_ranges<T: AsRef<[u8]>>(&self, start_key: T, end_key: T) -> Vec<Range> {
+        let key_ranges = self.key_ranges.read().await;
+        self.intervals
+            .read()
+            .await
+            .overlapping(start_key.as_ref(), end_key.as_ref())
+            .iter()
+            .filter_map(|id| key_ranges.get(id).cloned())
+            .collect()
+    }
+
+    /// Returns `Table` vector whose key range contains the
+    /// supplied key parameter and whose filter contains the key
     ///
     /// # Errors
     ///
@@ -97,46 +177,31 @@ impl KeyRange {
         if has_restored_ranges {
             filtered_ssts = self.check_restored_key_ranges(key.as_ref()).await?;
         }
-        let mut restored_range_map: HashMap<PathBuf, Range> = HashMap::new();
-        for (_, range) in self.key_ranges.read().await.iter() {
-            if has_restored_ranges
-                && self
-                    .restored_ranges
-                    .read()
-                    .await
-                    .contains_key(range.sst.dir.as_path())
-            {
+        let mut restored_range_map: HashMap<SstId, Range> = HashMap::new();
+        for range in self.overlapping_ranges(key.as_ref(), key.as_ref()).await.iter() {
+            if has_restored_ranges && self.restored_ranges.read().await.contains_key(&range.sst.id) {
                 continue;
             }
 
-            let searched_key = key.as_ref().to_vec();
-            if searched_key >= range.smallest_key && searched_key <= range.biggest_key {
-                //  If an sstable does not have a bloom filter then
-                //  it means there has been a crash and we need to restore
-                //  filter from disk using filter metadata stored on sstable
-                if range.sst.filter.as_ref().unwrap().sst_dir.is_none() {
-                    let mut mut_range = range.to_owned();
-                    let mut filter = mut_range.sst.filter.as_ref().unwrap().to_owned();
-
-                    filter.recover_meta().await?;
-                    filter.sst_dir = Some(mut_range.sst.dir.to_owned());
-                    mut_range.sst.load_entries_from_file().await?;
-                    filter.build_filter_from_entries(&mut_range.sst.entries);
-                    // Don't keep sst entries in memory
-                    mut_range.sst.entries.clear();
-                    mut_range.sst.filter = Some(filter.to_owned());
-                    restored_range_map.insert(mut_range.sst.dir.to_owned(), mut_range.to_owned());
-
-                    if filter.contains(key.as_ref()) {
-                        filtered_ssts.push(mut_range.sst);
-                        continue;
-                    }
-                }
+            //  If an sstable does not have a bloom filter then
+            //  it means there has been a crash and we need to restore
+            //  filter from disk using filter metadata stored on sstable,
+            //  bits are only rebuilt from entries if they were not persisted
+            if range.sst.filter.as_ref().unwrap().sst_id.is_none() {
+                let mut mut_range = range.to_owned();
+                Self::restore(&mut mut_range).await?;
+                restored_range_map.insert(mut_range.sst.id, mut_range.to_owned());
 
-                if range.sst.filter.as_ref().unwrap().contains(key.as_ref()) {
-                    filtered_ssts.push(range.sst.to_owned())
+                if mut_range.sst.filter.as_ref().unwrap().contains(key.as_ref()) {
+                    filtered_ssts.push(mut_range.sst);
+                    continue;
                 }
             }
+
+            self.filter_cache.touch(range.sst.id);
+            if range.sst.filter.as_ref().unwrap().contains(key.as_ref()) {
+                filtered_ssts.push(range.sst.to_owned())
+            }
         }
         if !restored_range_map.is_empty() {
             // store the key ranges with sstables that contains
@@ -153,6 +218,59 @@ impl KeyRange {
         Ok(filtered_ssts)
     }
 
+    /// Rebuilds filter of `range` from its filter file, or from the SSTable
+    /// entries if its bits were not persisted
+    async fn restore(range: &mut Range) -> Result<(), Error> {
+        let mut filter = range.sst.filter.as_ref().unwrap().to_owned();
+        let bits_recovered = filter.recover_meta().await?;
+        filter.set_sst_id(range.sst.id);
+        if !bits_recovered {
+            range.sst.load_entries_from_file().await?;
+            filter.build_filter_from_entries(&range.sst.entries);
+            // Don't keep sst entries in memory
+            range.sst.entries.clear();
+        }
+        range.sst.filter = Some(filter);
+        Ok(())
+    }
+
+    /// Restores filter of SSTable `id` from disk right away if it is not loaded
+    ///
+    /// Returns bytes held by the restored filter, `None` if the filter was
+    /// already loaded or the table is no longer live
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn restore_filter(&self, id: SstId) -> Result<Option<usize>, Error> {
+        let Some(mut range) = self.key_ranges.read().await.get(&id).cloned() else {
+            return Ok(None);
+        };
+        let unloaded = range
+            .sst
+            .filter
+            .as_ref()
+            .is_some_and(|filter| filter.sst_id.is_none());
+        if !unloaded {
+            return Ok(None);
+        }
+        Self::restore(&mut range).await?;
+        let filter = range.sst.filter.to_owned().unwrap();
+
+        let mut restored_ranges = self.restored_ranges.write().await;
+        let mut key_ranges = self.key_ranges.write().await;
+        match key_ranges.get_mut(&id) {
+            // replaced or compacted away while the filter was read
+            Some(current) if current.sst.dir == range.sst.dir => {
+                restored_ranges.remove(&id);
+                self.filter_cache.insert(id, &filter, &range.sst.hotness);
+                *current = range;
+                Ok(Some(filter.memory_size()))
+            }
+            _ => Ok(None),
+        }
+    }
+
     /// Returns `Table` vector whose last key is greater than or equal to
     /// the supplied key parameter
     ///
@@ -164,13 +282,12 @@ impl KeyRange {
     pub async fn check_restored_key_ranges<K: AsRef<[u8]>>(&self, key: K) -> Result<Vec<Table>, Error> {
         let mut filtered_ssts: Vec<Table> = Vec::new();
         let key_ranges = self.restored_ranges.read().await;
-        for (_, range) in key_ranges.iter() {
-            let searched_key = key.as_ref().to_vec();
-            if searched_key >= range.smallest_key
-                && searched_key <= range.biggest_key
-                && range.sst.filter.as_ref().unwrap().contains(key.as_ref())
-            {
-                filtered_ssts.push(range.sst.to_owned())
+        // restored sstables are still registered in the interval trees
+        for id in self.intervals.read().await.stab(key.as_ref()) {
+            if let Some(range) = key_ranges.get(&id) {
+                if range.sst.filter.as_ref().unwrap().contains(key.as_ref()) {
+                    filtered_ssts.push(range.sst.to_owned())
+                }
             }
         }
         Ok(filtered_ssts)
@@ -178,36 +295,42 @@ impl KeyRange {
 
     /// Moves entries in `restored_ranges` with sstables whose filters are just restored
     /// to `key_ranges`
+    ///
+    /// Ranges of tables removed since their filter was restored are dropped
     pub async fn update_key_range(&self) {
-        let restored_ranges = self.restored_ranges.read().await;
-        if !restored_ranges.is_empty() {
-            for (path, range) in restored_ranges.iter() {
-                self.key_ranges
-                    .write()
-                    .await
-                    .insert(path.to_owned(), range.to_owned());
+        let mut restored_ranges = self.restored_ranges.write().await;
+        if restored_ranges.is_empty() {
+            return;
+        }
+        let mut key_ranges = self.key_ranges.write().await;
+        for (id, range) in restored_ranges.drain() {
+            let Some(current) = key_ranges.get_mut(&id) else {
+                continue;
+            };
+            if current.sst.dir != range.sst.dir {
+                continue;
+            }
+            if let Some(filter) = range.sst.filter.as_ref() {
+                self.filter_cache.insert(id, filter, &range.sst.hotness);
             }
-            drop(restored_ranges);
-            self.restored_ranges.write().await.clear();
+            *current = range;
         }
     }
 
     /// Returns SSTables whose keys overlap with the key range supplied
     pub async fn range_query_scan<T: AsRef<[u8]>>(&self, start_key: T, end_key: T) -> Vec<Range> {
-        self.key_ranges
-            .read()
+        self.overlapping_ranges(start_key, end_key).await
+    }
+
+    /// Returns `Table` vector whose keys overlap with the key range supplied
+    ///
+    /// Used by the compactor to find SSTables outside a merge that share its
+    /// keys, and by warm up to read the blocks of hot prefixes
+    pub async fn tables_overlapping<T: AsRef<[u8]>>(&self, start_key: T, end_key: T) -> Vec<Table> {
+        self.overlapping_ranges(start_key, end_key)
             .await
-            .iter()
-            .filter(|(_, range)| {
-                // Check minimum range
-                (range.smallest_key.as_slice().cmp(start_key.as_ref()) == Ordering::Less
-                    || range.smallest_key.as_slice().cmp(start_key.as_ref()) == Ordering::Equal)
-
-                    // Check maximum range
-                    || (range.biggest_key.as_slice().cmp(end_key.as_ref()) == Ordering::Greater
-                        || range.biggest_key.as_slice().cmp(end_key.as_ref()) == Ordering::Equal)
-            })
-            .map(|(_, path)| path.to_owned())
+            .into_iter()
+            .map(|range| range.sst)
             .collect()
     }
 }
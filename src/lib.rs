@@ -139,7 +139,7 @@
 //! async fn main() {
 //!     let root = tempdir().unwrap();
 //!     let path = root.path().join("velarix");
-//!     let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+//!     let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
 //!
 //!     #[derive(Serialize, Deserialize)]
 //!     struct BigTech {
@@ -171,24 +171,34 @@
     html_favicon_url = "https://firebasestorage.googleapis.com/v0/b/generalsapi.appspot.com/o/Screenshot%202024-07-23%20at%2023.41.43.png?alt=media&token=109ab2a9-25d1-4a36-9d7e-7f8cfeb6ce6b"
 )]
 
+pub mod bench;
 mod block;
 mod bucket;
+mod cache;
 mod cfg;
+mod clock;
+mod comparator;
 // contains compaction strategies
 pub mod compactors;
 mod consts;
 pub mod db;
+mod encryption;
 mod err;
 mod filter;
 mod flush;
 mod fs;
 mod gc;
+mod health;
 mod index;
 mod key_range;
 mod r#macro;
 mod memtable;
 mod meta;
+// moves data from other embedded databases into a store
+pub mod migrate;
+mod quota;
 mod range;
+mod slow_log;
 mod sst;
 mod tests;
 mod types;
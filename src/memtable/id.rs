@@ -0,0 +1,33 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Next id handed out, shared by every store of the process
+static NEXT_MEMTABLE_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Identifies a memtable from the time it is created until it is flushed
+///
+/// Ids increase in the order memtables are created, which is the order the
+/// memtables of a store are sealed in. They are unique within the process
+/// and not persisted
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MemtableId(u64);
+
+impl MemtableId {
+    /// Returns an id bigger than every id returned before
+    pub(crate) fn next() -> Self {
+        Self(NEXT_MEMTABLE_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the id as a number
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for MemtableId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "memtable-{}", self.0)
+    }
+}
@@ -6,17 +6,19 @@
 //! Once the read-only memtable vector exceeds the `max_buffer_write_number` all memtable in the vector is flushed to to the disk concurrently
 
 use crate::bucket::InsertableToBucket;
-use crate::consts::{SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8};
+use crate::clock::Version;
+use crate::consts::{HEAD_KEY_SIZE, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8};
 use crate::db::SizeUnit;
 use crate::err::Error;
 use crate::filter::BloomFilter;
+use crate::memtable::MemtableId;
 use crate::types::{CreatedAt, IsTombStone, Key, SkipMapEntries, ValOffset, Value};
+use bytes::Bytes;
 use chrono::Utc;
 use crossbeam_skiplist::SkipMap;
-use rand::distributions::Alphanumeric;
-use rand::Rng;
 use std::cmp::Ordering;
 use std::fmt::Debug;
+use std::time::{Duration, Instant};
 use Error::*;
 
 use std::{hash::Hash, sync::Arc};
@@ -36,7 +38,7 @@ pub struct Entry<Key: K, V: Ord> {
 }
 
 /// Entry returned to user upon retreival
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct UserEntry {
     pub val: Value,
     pub created_at: CreatedAt,
@@ -47,6 +49,44 @@ impl UserEntry {
     pub fn new(val: Value, created_at: CreatedAt) -> Self {
         Self { val, created_at }
     }
+
+    /// Returns version of the entry
+    ///
+    /// Of two writes to the same key the one with the higher version wins,
+    /// even if both happened within the same millisecond
+    pub fn version(&self) -> Version {
+        Version::from(self.created_at)
+    }
+}
+
+/// Entry returned to user upon retreival, sharing the value buffer
+/// instead of copying it
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserEntryRef {
+    pub val: Bytes,
+    pub created_at: CreatedAt,
+}
+
+impl UserEntryRef {
+    /// Creates new `UserEntryRef`
+    pub fn new(val: impl Into<Bytes>, created_at: CreatedAt) -> Self {
+        Self {
+            val: val.into(),
+            created_at,
+        }
+    }
+
+    /// Returns version of the entry, see [`UserEntry::version`]
+    pub fn version(&self) -> Version {
+        Version::from(self.created_at)
+    }
+}
+
+impl From<UserEntryRef> for UserEntry {
+    fn from(entry: UserEntryRef) -> Self {
+        // does not copy if the buffer is not shared
+        UserEntry::new(Vec::from(entry.val), entry.created_at)
+    }
 }
 
 /// Value in SkipMap
@@ -72,6 +112,9 @@ impl<V: Ord> SkipMapValue<V> {
 /// flushed to disk
 #[derive(Clone, Debug)]
 pub struct MemTable<Key: K> {
+    /// Identifies the memtable until it is flushed, it is kept when the memtable is sealed
+    pub id: MemtableId,
+
     /// Lock-free skipmap from crossbeam
     pub entries: SkipMapEntries<Key>,
 
@@ -82,6 +125,7 @@ pub struct MemTable<Key: K> {
     pub size: usize,
 
     /// Date created
+    #[allow(dead_code)]
     pub created_at: CreatedAt,
 
     /// Signifies if we can continue to write
@@ -91,6 +135,9 @@ pub struct MemTable<Key: K> {
     /// Most recent entry inserted to memtable
     pub most_recent_entry: Entry<Key, ValOffset>,
 
+    /// Time the first entry was inserted, `None` while the memtable is empty
+    pub first_write_at: Option<Instant>,
+
     /// Memtable configuration
     pub config: Config,
 }
@@ -146,9 +193,8 @@ impl Entry<Key, ValOffset> {
             is_tombstone,
         }
     }
-    pub(crate) fn has_expired(&self, ttl: std::time::Duration) -> bool {
-        let current_time = Utc::now();
-        let current_timestamp = current_time.timestamp_millis() as u64;
+    pub(crate) fn has_expired(&self, ttl: std::time::Duration, now: CreatedAt) -> bool {
+        let current_timestamp = now.timestamp_millis() as u64;
         current_timestamp > (self.created_at.timestamp_millis() as u64 + ttl.as_millis() as u64)
     }
 }
@@ -168,6 +214,7 @@ impl InsertableToBucket for MemTable<Key> {
     }
 }
 
+#[allow(dead_code)] // some methods are only used by tests since recovery is crate private
 impl MemTable<Key> {
     /// Created new `MemTable`
     pub fn new(capacity: usize, false_positive_rate: f64) -> Self {
@@ -193,6 +240,7 @@ impl MemTable<Key> {
         let now = Utc::now();
         let config = Config::new(size_unit, capacity, false_positive_rate);
         Self {
+            id: MemtableId::next(),
             entries: Arc::new(entries),
             bloom_filter: bf,
             size: 0,
@@ -200,12 +248,14 @@ impl MemTable<Key> {
             created_at: now,
             read_only: false,
             most_recent_entry: Entry::new(vec![], 0, Utc::now(), false),
+            first_write_at: None,
         }
     }
 
     /// Inserts an entry to the `MemTable`
     pub fn insert(&mut self, entry: &Entry<Key, ValOffset>) {
-        let entry_length_byte = entry.key.len() + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8;
+        let entry_length_byte = Self::entry_size(entry.key.len());
+        self.first_write_at.get_or_insert_with(Instant::now);
         if !self.bloom_filter.contains(&entry.key) {
             self.bloom_filter.set(&entry.key);
             self.entries.insert(
@@ -228,6 +278,12 @@ impl MemTable<Key> {
         }
         self.size += entry_length_byte;
     }
+    /// Returns time since the first entry was inserted, zero while the memtable is empty
+    pub fn age(&self) -> Duration {
+        self.first_write_at
+            .map_or(Duration::ZERO, |first_write_at| first_write_at.elapsed())
+    }
+
     /// Returns value for an entry or `None`
     pub fn get<EntryKey: K>(&self, key: EntryKey) -> Option<SkipMapValue<ValOffset>> {
         if self.bloom_filter.contains(&key.as_ref().to_vec()) {
@@ -261,10 +317,8 @@ impl MemTable<Key> {
     }
 
     /// Used to generate id for read-only `MemTable`
-    pub fn generate_table_id() -> Vec<u8> {
-        let rng = rand::thread_rng();
-        let id: String = rng.sample_iter(&Alphanumeric).take(5).map(char::from).collect();
-        id.as_bytes().to_vec()
+    pub fn generate_table_id() -> MemtableId {
+        MemtableId::next()
     }
 
     /// Inserts an entry with tombstone to `entries` map
@@ -284,7 +338,18 @@ impl MemTable<Key> {
     }
     /// Returns `true` if `Memtable` is full
     pub fn is_full(&mut self, key_len: usize) -> bool {
-        self.size + key_len + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8 >= self.capacity()
+        self.size + Self::entry_size(key_len) >= self.capacity()
+    }
+
+    /// Returns bytes of memtable taken by an entry whose key is `key_len` bytes
+    pub fn entry_size(key_len: usize) -> usize {
+        key_len + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8
+    }
+
+    /// Returns `true` if an entry whose key is `key_len` bytes fits along with
+    /// the head entry inserted when the `MemTable` is sealed
+    pub fn fits(&self, key_len: usize) -> bool {
+        self.size + Self::entry_size(key_len) + Self::entry_size(HEAD_KEY_SIZE) <= self.capacity()
     }
 
     /// Seals  Memtable as read-only
@@ -340,11 +405,44 @@ impl MemTable<Key> {
         self.size = 0;
         self.bloom_filter = BloomFilter::new(self.config.false_pos_rate, max_no_of_entries);
     }
+
+    /// Replaces `MemTable` with an empty one of the same configuration
+    /// and returns the previous table
+    ///
+    /// The table is moved out rather than cloned, so making it read-only
+    /// does not copy its bloom filter on the write path
+    pub fn take(&mut self) -> Self {
+        self.take_with_capacity(self.capacity())
+    }
+
+    /// Replaces `MemTable` with an empty one of `capacity` and returns the previous table
+    ///
+    /// `capacity` is in the size unit of the table, the rest of the
+    /// configuration is kept
+    pub fn take_with_capacity(&mut self, capacity: usize) -> Self {
+        let empty =
+            Self::with_specified_capacity_and_rate(self.size_unit(), capacity, self.false_positive_rate());
+        std::mem::replace(self, empty)
+    }
+
+    /// Seals `MemTable` and returns it ready to be published as read-only
+    ///
+    /// The table is moved out and `head_entry` is inserted into it before
+    /// it is shared, so readers only ever see the active table or the fully
+    /// sealed one, never a table that is half way through rotation. The new
+    /// active table gets `capacity`, so capacity changes are adopted on rotation
+    pub fn seal(&mut self, head_entry: &Entry<Key, ValOffset>, capacity: usize) -> Arc<Self> {
+        let mut sealed = self.take_with_capacity(capacity);
+        sealed.insert(head_entry);
+        sealed.mark_readonly();
+        Arc::new(sealed)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::consts::HEAD_ENTRY_KEY;
     use std::{sync::Mutex, thread};
 
     #[test]
@@ -586,6 +684,8 @@ mod tests {
         assert_ne!(id1, id2);
         assert_ne!(id2, id3);
         assert_ne!(id1, id3);
+        // ids follow the order memtables are sealed in
+        assert!(id1 < id2 && id2 < id3);
     }
 
     #[test]
@@ -638,4 +738,68 @@ mod tests {
             .is_full(key.len() + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8 + memtable.capacity());
         assert!(is_full);
     }
+
+    #[test]
+    fn test_fits() {
+        let buffer_size = 51200;
+        let false_pos_rate = 1e-300;
+        let mut memtable = MemTable::new(buffer_size, false_pos_rate);
+        let capacity = memtable.capacity();
+        let key_len = capacity - MemTable::entry_size(HEAD_KEY_SIZE) - MemTable::entry_size(0);
+        assert!(memtable.fits(key_len));
+        assert!(!memtable.fits(key_len + 1));
+
+        memtable.insert(&Entry::new(vec![1; 100], 1, Utc::now(), false));
+        assert!(!memtable.fits(key_len));
+        assert!(memtable.fits(key_len - MemTable::entry_size(100)));
+    }
+
+    #[test]
+    fn test_seal() {
+        let buffer_size = 51200;
+        let false_pos_rate = 1e-4;
+        let mut memtable = MemTable::new(buffer_size, false_pos_rate);
+        let entry = Entry::new(vec![1, 2, 3, 4], 400, Utc::now(), false);
+        memtable.insert(&entry);
+        let head = Entry::new(HEAD_ENTRY_KEY.to_vec(), 400, Utc::now(), false);
+
+        let sealed = memtable.seal(&head, buffer_size * 2);
+        assert!(sealed.read_only);
+        assert!(sealed.get(&entry.key).is_some());
+        assert!(sealed.get(HEAD_ENTRY_KEY).is_some());
+
+        // head entry never reaches the new active table
+        assert!(!memtable.read_only);
+        assert!(memtable.entries.is_empty());
+        assert!(memtable.get(HEAD_ENTRY_KEY).is_none());
+
+        // new active table adopts the given capacity
+        assert_eq!(sealed.capacity(), buffer_size);
+        assert_eq!(memtable.capacity(), buffer_size * 2);
+    }
+
+    #[test]
+    fn test_take() {
+        let buffer_size = 51200;
+        let false_pos_rate = 1e-4;
+        let mut memtable = MemTable::new(buffer_size, false_pos_rate);
+        let entry = Entry::new(vec![1, 2, 3, 4], 400, Utc::now(), false);
+        memtable.insert(&entry);
+        memtable.mark_readonly();
+
+        let taken = memtable.take();
+        assert!(taken.read_only);
+        assert!(taken.get(&entry.key).is_some());
+        assert_eq!(taken.get_most_recent_offset(), 400);
+
+        // replacement is empty and keeps the configuration
+        assert!(!memtable.read_only);
+        assert!(memtable.entries.is_empty());
+        assert_eq!(memtable.size, 0);
+        assert_eq!(memtable.capacity(), taken.capacity());
+        assert_eq!(memtable.false_positive_rate(), false_pos_rate);
+        // entries are not shared with the taken table
+        memtable.insert(&Entry::new(vec![5], 500, Utc::now(), false));
+        assert!(taken.get(vec![5]).is_none());
+    }
 }
@@ -1,6 +1,13 @@
+mod id;
 mod mem;
+mod rotation;
+mod write_buffer;
+pub use id::MemtableId;
 pub use mem::Entry;
 pub use mem::MemTable;
 pub use mem::SkipMapValue;
 pub use mem::UserEntry;
+pub use mem::UserEntryRef;
 pub use mem::K;
+pub(crate) use rotation::RotationTracker;
+pub(crate) use write_buffer::WriteBufferManager;
@@ -0,0 +1,88 @@
+use std::time::{Duration, Instant};
+
+/// Records rotations of the active memtable since the store was opened
+#[derive(Clone, Debug)]
+pub(crate) struct RotationTracker {
+    /// Time the active memtable started taking writes
+    active_since: Instant,
+
+    /// Number of memtables sealed
+    rotations: usize,
+
+    /// Number of memtables sealed by the write buffer budget before they were full
+    forced: usize,
+
+    /// Time sealed memtables took writes, summed
+    fill_time: Duration,
+
+    /// Bytes of entries of sealed memtables, summed
+    bytes: usize,
+}
+
+impl Default for RotationTracker {
+    fn default() -> Self {
+        Self {
+            active_since: Instant::now(),
+            rotations: 0,
+            forced: 0,
+            fill_time: Duration::ZERO,
+            bytes: 0,
+        }
+    }
+}
+
+impl RotationTracker {
+    /// Records the active memtable sealed with `bytes` of entries, `forced`
+    /// if the write buffer budget sealed it early
+    pub fn record(&mut self, bytes: usize, forced: bool) {
+        let now = Instant::now();
+        self.fill_time += now.duration_since(self.active_since);
+        self.active_since = now;
+        self.rotations += 1;
+        self.forced += forced as usize;
+        self.bytes += bytes;
+    }
+
+    /// Returns number of memtables sealed
+    pub fn rotations(&self) -> usize {
+        self.rotations
+    }
+
+    /// Returns number of memtables sealed by the write buffer budget
+    pub fn forced(&self) -> usize {
+        self.forced
+    }
+
+    /// Returns average time memtables took writes before being sealed, zero until one is sealed
+    pub fn avg_fill_time(&self) -> Duration {
+        match self.rotations {
+            0 => Duration::ZERO,
+            rotations => self.fill_time / rotations as u32,
+        }
+    }
+
+    /// Returns average bytes of entries of sealed memtables, zero until one is sealed
+    pub fn avg_bytes(&self) -> usize {
+        self.bytes.checked_div(self.rotations).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_tracker_averages_rotations() {
+        let mut tracker = RotationTracker::default();
+        assert_eq!(tracker.avg_fill_time(), Duration::ZERO);
+        assert_eq!(tracker.avg_bytes(), 0);
+
+        std::thread::sleep(Duration::from_millis(10));
+        tracker.record(100, false);
+        tracker.record(300, true);
+        assert_eq!(tracker.rotations(), 2);
+        assert_eq!(tracker.forced(), 1);
+        assert_eq!(tracker.avg_bytes(), 200);
+        assert!(tracker.avg_fill_time() >= Duration::from_millis(5));
+    }
+}
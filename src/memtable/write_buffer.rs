@@ -0,0 +1,76 @@
+use super::MemTable;
+use crate::types::{ImmutableMemTables, Key};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Bounds memory held by the active and read-only memtables together
+///
+/// A single memtable is rotated once it reaches `write_buffer_size`, but
+/// read-only memtables waiting to be flushed are not bounded by it. The
+/// manager flushes early once the total crosses a budget, it is cheap to
+/// clone so several keyspaces can share one budget.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct WriteBufferManager {
+    /// Budget in bytes, 0 means unbounded
+    budget: Arc<AtomicUsize>,
+}
+
+impl WriteBufferManager {
+    /// Creates new `WriteBufferManager`, a `budget` of 0 disables it
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget: Arc::new(AtomicUsize::new(budget)),
+        }
+    }
+
+    /// Returns budget in bytes
+    pub fn budget(&self) -> usize {
+        self.budget.load(Ordering::Relaxed)
+    }
+
+    /// Sets budget in bytes, visible to every clone
+    pub fn set_budget(&self, budget: usize) {
+        self.budget.store(budget, Ordering::Relaxed);
+    }
+
+    /// Returns bytes held by `active` and `read_only` memtables
+    pub fn memory_usage(active: &MemTable<Key>, read_only: &ImmutableMemTables<Key>) -> usize {
+        active.size + read_only.iter().map(|table| table.value().size).sum::<usize>()
+    }
+
+    /// Returns `true` if the active memtable should be rotated and flushed
+    ///
+    /// Like RocksDB, the active memtable is flushed once it takes most of the
+    /// budget, or half of it while read-only memtables fill the rest. Smaller
+    /// active memtables are left to grow, flushing them would only produce
+    /// tiny SSTables while bigger ones are still being written
+    pub fn should_flush(&self, active_size: usize, total: usize) -> bool {
+        let budget = self.budget();
+        if budget == 0 {
+            return false;
+        }
+        active_size > budget / 8 * 7 || (total >= budget && active_size >= budget / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_flush() {
+        let manager = WriteBufferManager::default();
+        assert!(!manager.should_flush(usize::MAX, usize::MAX));
+
+        manager.clone().set_budget(800);
+        assert_eq!(manager.budget(), 800);
+        assert!(!manager.should_flush(100, 300));
+        // active memtable takes most of the budget
+        assert!(manager.should_flush(701, 701));
+        // read-only memtables fill the rest
+        assert!(manager.should_flush(400, 800));
+        assert!(!manager.should_flush(399, 1200));
+    }
+}
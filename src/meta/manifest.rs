@@ -0,0 +1,731 @@
+use crate::{
+    bucket::{Bucket, BucketID},
+    comparator::Comparator,
+    consts::{MANIFEST_FILE_NAME, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8},
+    encryption::{FileKey, KeyId},
+    err::Error::{self, *},
+    types::FileNumber,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Component, Path, PathBuf},
+};
+use tokio::{fs, io::AsyncWriteExt};
+use uuid::Uuid;
+
+/// Live tables mapped to the id of their bucket, nil if it is not known
+type LiveTables = BTreeMap<String, BucketID>;
+
+/// Size of a bucket id in the manifest
+const SIZE_OF_BUCKET_ID: usize = 16;
+
+/// Keys the files of an encrypted store are encrypted with
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct EncryptionRecord {
+    /// Key new files are encrypted with
+    active_key: KeyId,
+
+    /// Keys of SSTables by relative path, tables written before the store was
+    /// encrypted have none. Keys of tables that are not live yet are only
+    /// written once the tables are
+    tables: BTreeMap<String, FileKey>,
+
+    /// Start offsets of value log epochs and their keys, in ascending order
+    vlog_epochs: Vec<(u64, FileKey)>,
+}
+
+impl EncryptionRecord {
+    /// Drops value log epochs that end before `tail`, returns `true` if any was dropped
+    fn drop_epochs_before(&mut self, tail: u64) -> bool {
+        let obsolete = self
+            .vlog_epochs
+            .windows(2)
+            .take_while(|epochs| epochs[1].0 <= tail)
+            .count();
+        self.vlog_epochs.drain(..obsolete);
+        obsolete > 0
+    }
+}
+
+/// Records which SSTables are live
+///
+/// Flush and compaction write SSTables first and record them in the manifest
+/// afterwards, compaction also drops the merged SSTables in the same write.
+/// SSTable directories that are found on disk at open but are not recorded
+/// are leftovers of an interrupted flush or compaction and are not loaded.
+///
+/// Stores created before the manifest existed have none, every SSTable found
+/// on disk is adopted the first time they are opened
+///
+/// The manifest also hands out SSTable file numbers, they only ever grow so
+/// two SSTables never share a directory name, and records the comparator
+/// the store was created with and the bucket of every SSTable, so buckets
+/// are known without parsing directory names. Manifests of encrypted stores
+/// record the keys SSTables and value log epochs are encrypted with, see
+/// [`crate::encryption`]
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    /// Path of the manifest file
+    pub path: PathBuf,
+
+    /// Buckets directory, recorded paths are relative to it
+    pub root: PathBuf,
+
+    /// Relative paths of live SSTable directories and their buckets
+    tables: LiveTables,
+
+    /// `false` until the manifest is written for a store that had none
+    tracked: bool,
+
+    /// Number given to the next SSTable
+    next_file_number: FileNumber,
+
+    /// Name of the comparator keys are ordered with, `None` until it is checked
+    /// for a store whose manifest predates comparators
+    comparator: Option<String>,
+
+    /// `true` if the manifest was created by this open
+    created: bool,
+
+    /// Keys of the files of an encrypted store, `None` if it is not encrypted
+    encryption: Option<EncryptionRecord>,
+}
+
+impl Manifest {
+    /// Opens manifest of the buckets directory `root`
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error or the manifest is corrupted
+    pub async fn open<P: AsRef<Path>>(root: P) -> Result<Self, Error> {
+        let root = root.as_ref().to_path_buf();
+        let path = root.join(MANIFEST_FILE_NAME);
+        let mut manifest = Self {
+            path: path.to_owned(),
+            root: root.to_owned(),
+            tables: BTreeMap::new(),
+            tracked: true,
+            next_file_number: 0,
+            comparator: None,
+            created: false,
+            encryption: None,
+        };
+        match fs::read(&path).await {
+            Ok(bytes) => {
+                (
+                    manifest.tables,
+                    manifest.next_file_number,
+                    manifest.comparator,
+                    manifest.encryption,
+                ) = Self::deserialize(&bytes).ok_or(ManifestCorrupted(path))?;
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                let mut entries = fs::read_dir(&root).await.map_err(|error| DirOpen {
+                    path: root.to_owned(),
+                    error,
+                })?;
+                let is_empty = entries
+                    .next_entry()
+                    .await
+                    .map_err(|error| DirOpen { path: root, error })?
+                    .is_none();
+                if is_empty {
+                    manifest.write().await?;
+                    manifest.created = true;
+                } else {
+                    manifest.tracked = false;
+                }
+            }
+            Err(error) => return Err(FileRead { path, error }),
+        }
+        Ok(manifest)
+    }
+
+    /// Returns `true` if SSTable directory `dir` is recorded as live
+    ///
+    /// Every SSTable is live until a store without manifest has one written
+    pub fn is_live<P: AsRef<Path>>(&self, dir: P) -> bool {
+        !self.tracked || self.tables.contains_key(&self.relative(dir.as_ref()))
+    }
+
+    /// Returns id of the bucket recorded for SSTable directory `dir`
+    ///
+    /// `None` if the table is not live or was recorded before bucket ids were
+    pub fn bucket_of<P: AsRef<Path>>(&self, dir: P) -> Option<BucketID> {
+        self.tables
+            .get(&self.relative(dir.as_ref()))
+            .filter(|id| !id.is_nil())
+            .copied()
+    }
+
+    /// Returns `true` if the manifest has been written
+    pub fn is_tracked(&self) -> bool {
+        self.tracked
+    }
+
+    /// Returns absolute paths of live SSTable directories
+    pub fn live_tables(&self) -> Vec<PathBuf> {
+        self.tables
+            .keys()
+            .map(|table| {
+                table
+                    .split('/')
+                    .fold(self.root.to_owned(), |path, c| path.join(c))
+            })
+            .collect()
+    }
+
+    /// Returns a new SSTable file number
+    ///
+    /// The number is persisted with the next `apply`, numbers of SSTables that were
+    /// written but never recorded are reserved again at recovery from their directories
+    pub fn allocate_file_number(&mut self) -> FileNumber {
+        let number = self.next_file_number;
+        self.next_file_number += 1;
+        number
+    }
+
+    /// Makes sure `number`, found on disk, is never handed out again
+    pub fn reserve_file_number(&mut self, number: FileNumber) {
+        self.next_file_number = self.next_file_number.max(number.saturating_add(1));
+    }
+
+    /// Records `comparator` for a new store, otherwise checks it matches the
+    /// one the store was created with
+    ///
+    /// Stores created before comparators were recorded ordered keys bytewise
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the comparator differs or there is an IO error
+    pub async fn check_comparator(&mut self, comparator: Comparator) -> Result<(), Error> {
+        let recorded = match &self.comparator {
+            Some(name) => name.to_owned(),
+            None if self.created => comparator.name().to_string(),
+            None => Comparator::Bytewise.name().to_string(),
+        };
+        if recorded != comparator.name() {
+            return Err(ComparatorMismatch {
+                recorded,
+                requested: comparator.name().to_string(),
+            });
+        }
+        if self.comparator.is_none() {
+            self.comparator = Some(recorded);
+            // an untracked manifest is written once existing SSTables are adopted
+            if self.tracked {
+                self.write().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns id of the key new files are encrypted with, `None` if the store is not encrypted
+    pub fn active_key(&self) -> Option<KeyId> {
+        self.encryption.as_ref().map(|record| record.active_key)
+    }
+
+    /// Returns key SSTable directory `dir` is encrypted with, `None` if it is not encrypted
+    pub(crate) fn table_key<P: AsRef<Path>>(&self, dir: P) -> Option<FileKey> {
+        let record = self.encryption.as_ref()?;
+        record.tables.get(&self.relative(dir.as_ref())).copied()
+    }
+
+    /// Records `key` for SSTable directory `dir`, it is written with the
+    /// `apply` that makes the table live
+    pub(crate) fn record_table_key<P: AsRef<Path>>(&mut self, dir: P, key: FileKey) {
+        let table = self.relative(dir.as_ref());
+        if let Some(record) = self.encryption.as_mut() {
+            record.tables.insert(table, key);
+        }
+    }
+
+    /// Returns start offsets of value log epochs and their keys, in ascending order
+    pub(crate) fn vlog_epochs(&self) -> &[(u64, FileKey)] {
+        self.encryption
+            .as_ref()
+            .map_or(&[], |record| record.vlog_epochs.as_slice())
+    }
+
+    /// Starts a value log epoch at `start` encrypted with `key`, whose key
+    /// becomes the one new files are encrypted with
+    ///
+    /// Epochs that end before `tail` hold no live record and are dropped
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub(crate) async fn start_vlog_epoch(
+        &mut self,
+        start: u64,
+        key: FileKey,
+        tail: u64,
+    ) -> Result<(), Error> {
+        // the epoch only starts once it is on disk
+        let previous = self.encryption.to_owned();
+        let record = self.encryption.get_or_insert_with(Default::default);
+        record.active_key = key.key_id;
+        // an epoch that starts where the new one does holds nothing
+        record.vlog_epochs.retain(|(epoch_start, _)| *epoch_start < start);
+        record.vlog_epochs.push((start, key));
+        record.drop_epochs_before(tail);
+        if let Err(err) = self.write().await {
+            self.encryption = previous;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Drops value log epochs that end before `tail`, they hold no live record
+    ///
+    /// `tail` has to be on disk already, records from the tail on are read
+    /// again after a crash
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub(crate) async fn drop_vlog_epochs_before(&mut self, tail: u64) -> Result<(), Error> {
+        let previous = self.encryption.to_owned();
+        let Some(record) = self.encryption.as_mut() else {
+            return Ok(());
+        };
+        if !record.drop_epochs_before(tail) {
+            return Ok(());
+        }
+        if let Err(err) = self.write().await {
+            self.encryption = previous;
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Returns ids of the keys live SSTables and value log epochs are
+    /// encrypted with, along with the active key
+    pub(crate) fn keys_in_use(&self) -> BTreeSet<KeyId> {
+        let Some(record) = self.encryption.as_ref() else {
+            return BTreeSet::new();
+        };
+        self.tables
+            .keys()
+            .filter_map(|table| record.tables.get(table))
+            .chain(record.vlog_epochs.iter().map(|(_, key)| key))
+            .map(|key| key.key_id)
+            .chain([record.active_key])
+            .collect()
+    }
+
+    /// Records `added` SSTables as live and `removed` ones as obsolete in a single write
+    ///
+    /// Bucket of an added SSTable is taken from the name of its parent directory
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn apply<P: AsRef<Path>>(&mut self, added: &[P], removed: &[P]) -> Result<(), Error> {
+        for dir in removed {
+            let table = self.relative(dir.as_ref());
+            self.tables.remove(&table);
+            if let Some(record) = self.encryption.as_mut() {
+                record.tables.remove(&table);
+            }
+        }
+        for dir in added {
+            let table = self.relative(dir.as_ref());
+            let bucket = dir
+                .as_ref()
+                .parent()
+                .and_then(|bucket| Bucket::id_from_dir(bucket).ok())
+                .unwrap_or_else(Uuid::nil);
+            self.tables.insert(table, bucket);
+        }
+        self.write().await?;
+        self.tracked = true;
+        Ok(())
+    }
+
+    /// Writes manifest to a temporary file and renames it over the old one,
+    /// so a crash leaves either the old or the new manifest
+    async fn write(&self) -> Result<(), Error> {
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path).await.map_err(|error| FileCreation {
+            path: tmp_path.to_owned(),
+            error,
+        })?;
+        file.write_all(&self.serialize())
+            .await
+            .map_err(|error| FileWrite {
+                path: tmp_path.to_owned(),
+                error,
+            })?;
+        file.sync_all().await.map_err(FileSync)?;
+        fs::rename(&tmp_path, &self.path)
+            .await
+            .map_err(|error| FileRename {
+                path: self.path.to_owned(),
+                error,
+            })?;
+        // persist the rename itself
+        #[cfg(unix)]
+        if let Some(parent) = self.path.parent() {
+            fs::File::open(parent)
+                .await
+                .map_err(|error| FileOpen {
+                    path: parent.to_path_buf(),
+                    error,
+                })?
+                .sync_all()
+                .await
+                .map_err(FileSync)?;
+        }
+        Ok(())
+    }
+
+    /// Returns `dir` relative to the buckets directory with `/` separators
+    ///
+    /// `dir` spelled with a root that differs from `root`, e.g. in case or with a
+    /// `\\?\` prefix on Windows, keeps its bucket and SSTable directory names only
+    fn relative(&self, dir: &Path) -> String {
+        let names = |path: &Path| {
+            path.components()
+                .filter_map(|c| match c {
+                    Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let names = match dir.strip_prefix(&self.root) {
+            Ok(relative) => names(relative),
+            Err(_) => {
+                let names = names(dir);
+                names[names.len().saturating_sub(2)..].to_vec()
+            }
+        };
+        names.join("/")
+    }
+
+    /// Serializes live tables as a count followed by length prefixed paths,
+    /// then the next file number, the length prefixed comparator name, empty
+    /// if not known, and the bucket id of every table in the same order
+    ///
+    /// Manifests of encrypted stores go on with the active key id, a flag
+    /// byte for every table in the same order followed by its key if set, and
+    /// the count of value log epochs followed by their start offsets and keys
+    fn serialize(&self) -> Vec<u8> {
+        let mut serialized_data = Vec::new();
+        serialized_data.extend_from_slice(&(self.tables.len() as u32).to_le_bytes());
+        for table in self.tables.keys() {
+            serialized_data.extend_from_slice(&(table.len() as u32).to_le_bytes());
+            serialized_data.extend_from_slice(table.as_bytes());
+        }
+        serialized_data.extend_from_slice(&self.next_file_number.to_le_bytes());
+        let comparator = self.comparator.as_deref().unwrap_or_default();
+        serialized_data.extend_from_slice(&(comparator.len() as u32).to_le_bytes());
+        serialized_data.extend_from_slice(comparator.as_bytes());
+        for bucket in self.tables.values() {
+            serialized_data.extend_from_slice(bucket.as_bytes());
+        }
+        if let Some(record) = self.encryption.as_ref() {
+            serialized_data.extend_from_slice(&record.active_key.to_le_bytes());
+            for table in self.tables.keys() {
+                match record.tables.get(table) {
+                    Some(key) => {
+                        serialized_data.push(1);
+                        serialized_data.extend_from_slice(&key.serialize());
+                    }
+                    None => serialized_data.push(0),
+                }
+            }
+            serialized_data.extend_from_slice(&(record.vlog_epochs.len() as u32).to_le_bytes());
+            for (start, key) in record.vlog_epochs.iter() {
+                serialized_data.extend_from_slice(&start.to_le_bytes());
+                serialized_data.extend_from_slice(&key.serialize());
+            }
+        }
+        serialized_data
+    }
+
+    /// Parses bytes written by `serialize`, returns `None` if they are malformed
+    ///
+    /// Manifests written before file numbers existed end after the tables,
+    /// numbering then starts past the SSTables found at recovery. Manifests
+    /// written before comparators were recorded end after the file number,
+    /// those written before bucket ids were recorded after the comparator,
+    /// those of stores that are not encrypted after the bucket ids
+    #[allow(clippy::type_complexity)]
+    fn deserialize(
+        bytes: &[u8],
+    ) -> Option<(LiveTables, FileNumber, Option<String>, Option<EncryptionRecord>)> {
+        let read_u32 = |offset: usize| -> Option<usize> {
+            let buf = bytes.get(offset..offset + SIZE_OF_U32)?;
+            Some(u32::from_le_bytes(buf.try_into().ok()?) as usize)
+        };
+        let count = read_u32(0)?;
+        let mut offset = SIZE_OF_U32;
+        let mut names = Vec::with_capacity(count.min(bytes.len()));
+        for _ in 0..count {
+            let len = read_u32(offset)?;
+            offset += SIZE_OF_U32;
+            let table = std::str::from_utf8(bytes.get(offset..offset + len)?).ok()?;
+            names.push(table.to_string());
+            offset += len;
+        }
+        let mut tables: LiveTables = names.iter().map(|name| (name.to_owned(), Uuid::nil())).collect();
+        let next_file_number = match bytes.get(offset..) {
+            Some([]) => return Some((tables, 0, None, None)),
+            Some(buf) if buf.len() >= SIZE_OF_U64 => {
+                FileNumber::from_le_bytes(buf[..SIZE_OF_U64].try_into().ok()?)
+            }
+            _ => return None,
+        };
+        offset += SIZE_OF_U64;
+        if offset == bytes.len() {
+            return Some((tables, next_file_number, None, None));
+        }
+        let len = read_u32(offset)?;
+        offset += SIZE_OF_U32;
+        let comparator = std::str::from_utf8(bytes.get(offset..offset + len)?).ok()?;
+        let comparator = (!comparator.is_empty()).then(|| comparator.to_string());
+        offset += len;
+        if offset == bytes.len() {
+            return Some((tables, next_file_number, comparator, None));
+        }
+        let ids = bytes.get(offset..offset + names.len() * SIZE_OF_BUCKET_ID)?;
+        for (name, id) in names.iter().zip(ids.chunks_exact(SIZE_OF_BUCKET_ID)) {
+            tables.insert(name.to_owned(), Uuid::from_slice(id).ok()?);
+        }
+        offset += ids.len();
+        if offset == bytes.len() {
+            return Some((tables, next_file_number, comparator, None));
+        }
+
+        let mut record = EncryptionRecord {
+            active_key: read_u32(offset)? as KeyId,
+            ..Default::default()
+        };
+        offset += SIZE_OF_U32;
+        for name in names {
+            let has_key = *bytes.get(offset)? == 1;
+            offset += SIZE_OF_U8;
+            if has_key {
+                record
+                    .tables
+                    .insert(name, FileKey::deserialize(bytes.get(offset..)?)?);
+                offset += FileKey::LEN;
+            }
+        }
+        let epochs = read_u32(offset)?;
+        offset += SIZE_OF_U32;
+        for _ in 0..epochs {
+            let start = u64::from_le_bytes(bytes.get(offset..offset + SIZE_OF_U64)?.try_into().ok()?);
+            offset += SIZE_OF_U64;
+            record
+                .vlog_epochs
+                .push((start, FileKey::deserialize(bytes.get(offset..)?)?));
+            offset += FileKey::LEN;
+        }
+        if offset != bytes.len() {
+            return None;
+        }
+        Some((tables, next_file_number, comparator, Some(record)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_manifest_apply_and_reopen() {
+        let root = tempdir().unwrap();
+        let buckets = root.path().to_path_buf();
+        let mut manifest = Manifest::open(&buckets).await.unwrap();
+        assert!(manifest.is_tracked());
+        assert!(!manifest.is_live(buckets.join("bucket_1/sstable_1")));
+
+        let first = buckets.join("bucket_1/sstable_1");
+        let second = buckets.join("bucket_1/sstable_2");
+        manifest.apply(&[&first, &second], &[]).await.unwrap();
+        let merged = buckets.join("bucket_2/sstable_3");
+        manifest.apply(&[&merged], &[&first]).await.unwrap();
+
+        let reopened = Manifest::open(&buckets).await.unwrap();
+        assert!(!reopened.is_live(&first));
+        assert!(reopened.is_live(&second));
+        assert!(reopened.is_live(&merged));
+        assert_eq!(reopened.live_tables(), vec![second, merged]);
+    }
+
+    #[tokio::test]
+    async fn test_manifest_matches_tables_under_differently_spelled_root() {
+        let root = tempdir().unwrap();
+        let buckets = root.path().to_path_buf();
+        let mut manifest = Manifest::open(&buckets).await.unwrap();
+        let table = buckets.join("bucket_1").join("sstable_1");
+        manifest.apply(&[&table], &[]).await.unwrap();
+
+        // e.g. the same directory reached through a root in another case
+        let elsewhere = PathBuf::from("ELSEWHERE").join("bucket_1").join("sstable_1");
+        assert!(manifest.is_live(elsewhere));
+        assert!(!manifest.is_live(PathBuf::from("ELSEWHERE").join("bucket_1").join("sstable_2")));
+    }
+
+    #[tokio::test]
+    async fn test_manifest_adopts_existing_tables() {
+        let root = tempdir().unwrap();
+        let buckets = root.path().to_path_buf();
+        fs::create_dir_all(buckets.join("bucket_1/sstable_1"))
+            .await
+            .unwrap();
+
+        // store without manifest, everything on disk is live
+        let mut manifest = Manifest::open(&buckets).await.unwrap();
+        assert!(!manifest.is_tracked());
+        assert!(manifest.is_live(buckets.join("bucket_1/sstable_1")));
+
+        manifest
+            .apply::<PathBuf>(&[buckets.join("bucket_1/sstable_1")], &[])
+            .await
+            .unwrap();
+        assert!(manifest.is_tracked());
+        assert!(!manifest.is_live(buckets.join("bucket_1/sstable_2")));
+    }
+
+    #[tokio::test]
+    async fn test_manifest_file_numbers_survive_reopen() {
+        let root = tempdir().unwrap();
+        let buckets = root.path().to_path_buf();
+        let mut manifest = Manifest::open(&buckets).await.unwrap();
+        assert_eq!(manifest.allocate_file_number(), 0);
+        assert_eq!(manifest.allocate_file_number(), 1);
+        manifest
+            .apply::<PathBuf>(&[buckets.join("bucket_1/sstable_000001")], &[])
+            .await
+            .unwrap();
+
+        let mut reopened = Manifest::open(&buckets).await.unwrap();
+        assert_eq!(reopened.allocate_file_number(), 2);
+        // a table written but not recorded before a crash keeps its number
+        reopened.reserve_file_number(7);
+        reopened.reserve_file_number(3);
+        assert_eq!(reopened.allocate_file_number(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_manifest_without_file_number() {
+        let root = tempdir().unwrap();
+        let table = b"bucket_1/sstable_1720785462309";
+        let mut bytes = 1u32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&(table.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(table);
+        fs::write(root.path().join(MANIFEST_FILE_NAME), bytes)
+            .await
+            .unwrap();
+
+        let mut manifest = Manifest::open(root.path()).await.unwrap();
+        assert!(manifest.is_live(root.path().join("bucket_1/sstable_1720785462309")));
+        manifest.reserve_file_number(1720785462309);
+        assert_eq!(manifest.allocate_file_number(), 1720785462310);
+    }
+
+    #[tokio::test]
+    async fn test_manifest_records_comparator() {
+        let root = tempdir().unwrap();
+        let mut manifest = Manifest::open(root.path()).await.unwrap();
+        manifest.check_comparator(Comparator::Reverse).await.unwrap();
+
+        let mut reopened = Manifest::open(root.path()).await.unwrap();
+        reopened.check_comparator(Comparator::Reverse).await.unwrap();
+        assert!(matches!(
+            reopened.check_comparator(Comparator::Bytewise).await,
+            Err(ComparatorMismatch { .. })
+        ));
+
+        // a manifest without comparator belongs to a bytewise store
+        let legacy = tempdir().unwrap();
+        Manifest::open(legacy.path()).await.unwrap();
+        let mut manifest = Manifest::open(legacy.path()).await.unwrap();
+        assert!(manifest
+            .check_comparator(Comparator::CaseInsensitive)
+            .await
+            .is_err());
+        manifest.check_comparator(Comparator::Bytewise).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_manifest_records_bucket_ids() {
+        let root = tempdir().unwrap();
+        let buckets = root.path().to_path_buf();
+        let id = Uuid::new_v4();
+        let table = buckets.join(format!("bucket{}", id)).join("sstable_1");
+        let unnamed = buckets.join("bucket_1").join("sstable_2");
+        let mut manifest = Manifest::open(&buckets).await.unwrap();
+        manifest.check_comparator(Comparator::Reverse).await.unwrap();
+        manifest.apply(&[&table, &unnamed], &[]).await.unwrap();
+
+        let reopened = Manifest::open(&buckets).await.unwrap();
+        assert_eq!(reopened.bucket_of(&table), Some(id));
+        assert_eq!(reopened.bucket_of(&unnamed), None);
+        assert_eq!(reopened.comparator.as_deref(), Some("reverse"));
+
+        // a manifest ending after the comparator predates bucket ids
+        let legacy = tempdir().unwrap();
+        let name = format!("bucket{}/sstable_1", id);
+        let mut bytes = 1u32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+        bytes.extend_from_slice(&2u64.to_le_bytes());
+        bytes.extend_from_slice(&7u32.to_le_bytes());
+        bytes.extend_from_slice(b"reverse");
+        fs::write(legacy.path().join(MANIFEST_FILE_NAME), bytes)
+            .await
+            .unwrap();
+        let manifest = Manifest::open(legacy.path()).await.unwrap();
+        assert!(manifest.is_live(legacy.path().join(&name)));
+        assert_eq!(manifest.bucket_of(legacy.path().join(&name)), None);
+        assert_eq!(manifest.comparator.as_deref(), Some("reverse"));
+    }
+
+    #[tokio::test]
+    async fn test_manifest_records_encryption_keys() {
+        let root = tempdir().unwrap();
+        let buckets = root.path().to_path_buf();
+        let old = buckets.join("bucket_1").join("sstable_1");
+        let new = buckets.join("bucket_1").join("sstable_2");
+        let mut manifest = Manifest::open(&buckets).await.unwrap();
+        assert!(manifest.keys_in_use().is_empty());
+        manifest.start_vlog_epoch(0, FileKey::new(1), 0).await.unwrap();
+        manifest.record_table_key(&old, FileKey::new(1));
+        manifest.apply(&[&old], &[]).await.unwrap();
+        manifest.start_vlog_epoch(100, FileKey::new(2), 0).await.unwrap();
+        manifest.record_table_key(&new, FileKey::new(2));
+        manifest.apply(&[&new], &[&old]).await.unwrap();
+
+        let mut reopened = Manifest::open(&buckets).await.unwrap();
+        assert_eq!(reopened.active_key(), Some(2));
+        assert_eq!(reopened.table_key(&new), manifest.table_key(&new));
+        assert_eq!(reopened.table_key(&old), None);
+        assert_eq!(reopened.vlog_epochs(), manifest.vlog_epochs());
+        assert_eq!(reopened.keys_in_use(), BTreeSet::from([1, 2]));
+
+        // the first epoch ends before the tail, nothing needs key 1 afterwards
+        reopened.drop_vlog_epochs_before(99).await.unwrap();
+        assert_eq!(reopened.keys_in_use(), BTreeSet::from([1, 2]));
+        reopened.drop_vlog_epochs_before(100).await.unwrap();
+        assert_eq!(reopened.keys_in_use(), BTreeSet::from([2]));
+        let reopened = Manifest::open(&buckets).await.unwrap();
+        assert_eq!(reopened.vlog_epochs().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_manifest_corrupted() {
+        let root = tempdir().unwrap();
+        fs::write(root.path().join(MANIFEST_FILE_NAME), [5, 0, 0, 0, 1])
+            .await
+            .unwrap();
+        assert!(matches!(
+            Manifest::open(root.path()).await,
+            Err(ManifestCorrupted(_))
+        ));
+    }
+}
@@ -1,11 +1,13 @@
 use crate::{
-    consts::{META_FILE_NAME, SIZE_OF_U32, SIZE_OF_U64},
-    err::Error,
+    consts::{FORMAT_VERSION, META_FILE_NAME, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8},
+    err::Error::{self, *},
     fs::{FileAsync, FileNode, MetaFileNode, MetaFs},
-    types::{ByteSerializedEntry, CreatedAt, LastModified, VLogHead, VLogTail},
+    types::{ByteSerializedEntry, ConfigHash, CreatedAt, FormatVersion, LastModified, VLogHead, VLogTail},
+    util,
 };
 use chrono::Utc;
 use std::path::{Path, PathBuf};
+use tokio::fs;
 
 /// Meta file
 #[derive(Debug, Clone)]
@@ -25,14 +27,56 @@ impl<F: MetaFs> MetaFile<F> {
 }
 
 /// metadata for `DataStore`
+///
+/// Fields after `last_modified` were added later, meta files written before
+/// them recover with their defaults, i.e. format version 0, no clean shutdown,
+/// no comparator, no features and no head checkpoint
 #[derive(Debug, Clone)]
 pub struct Meta {
     /// Handles file operations
     pub file_handle: MetaFile<MetaFileNode>,
+
+    /// Value log tail
     pub v_log_tail: VLogHead,
+
+    /// Last checkpointed value log head, recovery replays value log from it
     pub v_log_head: VLogTail,
+
+    /// Time the store was created
     pub created_at: CreatedAt,
+
+    /// Time meta was last updated
     pub last_modified: LastModified,
+
+    /// On-disk format version the store was last written with
+    pub format_version: FormatVersion,
+
+    /// Fingerprint of the configuration the store was last closed with
+    pub config_hash: ConfigHash,
+
+    /// `true` if the store was closed with everything flushed
+    pub clean_shutdown: bool,
+
+    /// Value log size when the store was closed
+    pub shutdown_v_log_size: usize,
+
+    /// Name of the comparator keys are ordered with, `None` until a store
+    /// created before it was recorded is opened again
+    pub comparator: Option<String>,
+
+    /// Flags of on-disk features the store uses, e.g. `META_FEATURE_COMPRESSION`
+    pub features: u8,
+
+    /// Value log head when the last head checkpoint was taken, the checkpoint
+    /// only holds while `v_log_head` is still at it
+    pub checkpoint_head: usize,
+
+    /// Value log offset the last head checkpoint covers, zero if there is none
+    pub checkpoint_offset: usize,
+
+    /// `true` once a value with a TTL of its own was written, stores whose
+    /// meta predates it are taken to have such values
+    pub expiring_values: bool,
 }
 
 impl Meta {
@@ -52,6 +96,15 @@ impl Meta {
             v_log_head: 0,
             created_at,
             last_modified,
+            format_version: FORMAT_VERSION,
+            config_hash: 0,
+            clean_shutdown: false,
+            shutdown_v_log_size: 0,
+            comparator: None,
+            features: 0,
+            checkpoint_head: 0,
+            checkpoint_offset: 0,
+            expiring_values: false,
         })
     }
     /// Writes `Meta` to disk
@@ -74,25 +127,71 @@ impl Meta {
         self.last_modified = Utc::now();
     }
 
+    /// Records that the store was closed with value log of `v_log_size` bytes
+    /// fully flushed
+    pub fn mark_clean_shutdown(&mut self, v_log_size: usize, config_hash: ConfigHash) {
+        self.clean_shutdown = true;
+        self.shutdown_v_log_size = v_log_size;
+        self.config_hash = config_hash;
+    }
+
+    /// Returns `true` if the store was closed cleanly and value log of
+    /// `v_log_size` bytes was not written to since, so there is nothing to replay
+    pub fn is_clean_shutdown(&self, v_log_size: usize) -> bool {
+        self.clean_shutdown && self.shutdown_v_log_size == v_log_size
+    }
+
+    /// Records a head checkpoint covering value log up to `offset`, taken
+    /// while value log head was at `head`
+    pub fn set_checkpoint(&mut self, head: usize, offset: usize) {
+        self.checkpoint_head = head;
+        self.checkpoint_offset = offset;
+    }
+
+    /// Returns `true` if the recorded head checkpoint was taken at the
+    /// current value log head
+    pub fn has_checkpoint(&self) -> bool {
+        self.checkpoint_offset > 0 && self.checkpoint_head == self.v_log_head
+    }
+
     /// Recovers `Meta` from disk
     ///
     /// # Error
     ///
-    /// Returns IO error in case it occurs
+    /// Returns IO error in case it occurs or meta is corrupted
     pub async fn recover(&mut self) -> Result<(), Error> {
-        let (head, tail, created_at, last_modified) =
-            MetaFileNode::recover(self.file_handle.path.to_owned()).await?;
-        self.v_log_head = head;
-        self.v_log_tail = tail;
-        self.created_at = created_at;
-        self.last_modified = last_modified;
+        let path = self.file_handle.path.to_owned();
+        let bytes = fs::read(&path).await.map_err(|error| FileRead {
+            path: path.to_owned(),
+            error,
+        })?;
+        *self = Self::deserialize(&bytes)
+            .ok_or(MetaCorrupted(path))?
+            .with_handle(self.file_handle.to_owned());
         Ok(())
     }
 
     /// Serializes `Meta` into byte vector
     pub(crate) fn serialize(&self) -> ByteSerializedEntry {
-        // head offset + tail offset + created_at + last_modified
-        let entry_len = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U64;
+        let comparator = self.comparator.as_deref().unwrap_or_default();
+        // head offset + tail offset + created_at + last_modified + format version
+        // + config hash + clean shutdown + value log size at shutdown
+        // + comparator name length + comparator name + features
+        // + checkpoint head + checkpoint offset + expiring values
+        let entry_len = SIZE_OF_U32
+            + SIZE_OF_U32
+            + SIZE_OF_U64
+            + SIZE_OF_U64
+            + SIZE_OF_U32
+            + SIZE_OF_U64
+            + SIZE_OF_U8
+            + SIZE_OF_U64
+            + SIZE_OF_U32
+            + comparator.len()
+            + SIZE_OF_U8
+            + SIZE_OF_U64
+            + SIZE_OF_U64
+            + SIZE_OF_U8;
 
         let mut serialized_data = Vec::with_capacity(entry_len);
 
@@ -104,6 +203,143 @@ impl Meta {
 
         serialized_data.extend_from_slice(&(self.last_modified.timestamp_millis() as u64).to_le_bytes());
 
+        serialized_data.extend_from_slice(&self.format_version.to_le_bytes());
+
+        serialized_data.extend_from_slice(&self.config_hash.to_le_bytes());
+
+        serialized_data.push(self.clean_shutdown as u8);
+
+        serialized_data.extend_from_slice(&(self.shutdown_v_log_size as u64).to_le_bytes());
+
+        serialized_data.extend_from_slice(&(comparator.len() as u32).to_le_bytes());
+
+        serialized_data.extend_from_slice(comparator.as_bytes());
+
+        serialized_data.push(self.features);
+
+        serialized_data.extend_from_slice(&(self.checkpoint_head as u64).to_le_bytes());
+
+        serialized_data.extend_from_slice(&(self.checkpoint_offset as u64).to_le_bytes());
+
+        serialized_data.push(self.expiring_values as u8);
+
         serialized_data
     }
+
+    /// Parses bytes written by `serialize`, returns `None` if they are malformed
+    ///
+    /// The returned `Meta` still needs its file handle, see `with_handle`
+    fn deserialize(bytes: &[u8]) -> Option<MetaFields> {
+        let read_u32 = |offset: usize| -> Option<u32> {
+            Some(u32::from_le_bytes(
+                bytes.get(offset..offset + SIZE_OF_U32)?.try_into().ok()?,
+            ))
+        };
+        let read_u64 = |offset: usize| -> Option<u64> {
+            Some(u64::from_le_bytes(
+                bytes.get(offset..offset + SIZE_OF_U64)?.try_into().ok()?,
+            ))
+        };
+        let legacy_len = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U64;
+        let mut fields = MetaFields {
+            v_log_head: read_u32(0)? as usize,
+            v_log_tail: read_u32(SIZE_OF_U32)? as usize,
+            created_at: util::milliseconds_to_datetime(read_u64(SIZE_OF_U32 * 2)?),
+            last_modified: util::milliseconds_to_datetime(read_u64(SIZE_OF_U32 * 2 + SIZE_OF_U64)?),
+            format_version: 0,
+            config_hash: 0,
+            clean_shutdown: false,
+            shutdown_v_log_size: 0,
+            comparator: None,
+            features: 0,
+            checkpoint_head: 0,
+            checkpoint_offset: 0,
+            expiring_values: true,
+        };
+        if bytes.len() == legacy_len {
+            return Some(fields);
+        }
+        let mut offset = legacy_len;
+        fields.format_version = read_u32(offset)?;
+        offset += SIZE_OF_U32;
+        fields.config_hash = read_u64(offset)?;
+        offset += SIZE_OF_U64;
+        fields.clean_shutdown = match bytes.get(offset)? {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        offset += SIZE_OF_U8;
+        fields.shutdown_v_log_size = read_u64(offset)? as usize;
+        offset += SIZE_OF_U64;
+        // meta written before comparator and features were recorded ends here
+        if bytes.len() == offset {
+            return Some(fields);
+        }
+        let comparator_len = read_u32(offset)? as usize;
+        offset += SIZE_OF_U32;
+        let comparator = bytes.get(offset..offset + comparator_len)?;
+        fields.comparator = (!comparator.is_empty()).then(|| String::from_utf8_lossy(comparator).to_string());
+        offset += comparator_len;
+        fields.features = *bytes.get(offset)?;
+        offset += SIZE_OF_U8;
+        // meta written before head checkpoints were recorded ends here
+        if bytes.len() == offset {
+            return Some(fields);
+        }
+        fields.checkpoint_head = read_u64(offset)? as usize;
+        offset += SIZE_OF_U64;
+        fields.checkpoint_offset = read_u64(offset)? as usize;
+        offset += SIZE_OF_U64;
+        // meta written before expiring values were recorded ends here
+        if bytes.len() == offset {
+            return Some(fields);
+        }
+        fields.expiring_values = match bytes.get(offset)? {
+            0 => false,
+            1 => true,
+            _ => return None,
+        };
+        offset += SIZE_OF_U8;
+        (bytes.len() == offset).then_some(fields)
+    }
+}
+
+/// Fields of `Meta` read from disk
+struct MetaFields {
+    v_log_head: VLogHead,
+    v_log_tail: VLogTail,
+    created_at: CreatedAt,
+    last_modified: LastModified,
+    format_version: FormatVersion,
+    config_hash: ConfigHash,
+    clean_shutdown: bool,
+    shutdown_v_log_size: usize,
+    comparator: Option<String>,
+    features: u8,
+    checkpoint_head: usize,
+    checkpoint_offset: usize,
+    expiring_values: bool,
+}
+
+impl MetaFields {
+    /// Returns `Meta` with these fields written through `file_handle`
+    fn with_handle(self, file_handle: MetaFile<MetaFileNode>) -> Meta {
+        Meta {
+            file_handle,
+            v_log_tail: self.v_log_tail,
+            v_log_head: self.v_log_head,
+            created_at: self.created_at,
+            last_modified: self.last_modified,
+            format_version: self.format_version,
+            config_hash: self.config_hash,
+            clean_shutdown: self.clean_shutdown,
+            shutdown_v_log_size: self.shutdown_v_log_size,
+            comparator: self.comparator,
+            features: self.features,
+            checkpoint_head: self.checkpoint_head,
+            checkpoint_offset: self.checkpoint_offset,
+            expiring_values: self.expiring_values,
+        }
+    }
 }
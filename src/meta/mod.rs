@@ -1,2 +1,6 @@
+mod manifest;
 mod meta_manager;
+mod user_meta;
+pub use manifest::Manifest;
 pub use meta_manager::Meta;
+pub(crate) use user_meta::UserMeta;
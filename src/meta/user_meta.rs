@@ -0,0 +1,170 @@
+use crate::{
+    consts::{SIZE_OF_U32, USER_META_FILE_NAME},
+    err::Error::{self, *},
+};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// Metadata set by users, e.g. the schema version of their values
+///
+/// Every change rewrites the file to a temporary file and renames it over the
+/// old one, so a crash leaves either the old or the new map
+#[derive(Debug, Clone)]
+pub(crate) struct UserMeta {
+    /// Path of the user metadata file
+    path: PathBuf,
+
+    /// Metadata by key
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl UserMeta {
+    /// Opens user metadata of the meta directory `dir`, a missing file holds no metadata
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error or the file is corrupted
+    pub async fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let path = dir.as_ref().join(USER_META_FILE_NAME);
+        let entries = match fs::read(&path).await {
+            Ok(bytes) => Self::deserialize(&bytes).ok_or(MetaCorrupted(path.to_owned()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(FileRead { path, error }),
+        };
+        Ok(Self { path, entries })
+    }
+
+    /// Returns metadata stored at `key`
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
+    }
+
+    /// Stores `value` at `key` and persists the map
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error, the map in memory is left unchanged then
+    pub async fn set(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        let previous = self.entries.insert(key.to_vec(), value.to_vec());
+        if let Err(err) = self.write().await {
+            match previous {
+                Some(previous) => self.entries.insert(key.to_vec(), previous),
+                None => self.entries.remove(key),
+            };
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Removes metadata stored at `key` and persists the map
+    ///
+    /// Returns `false` without writing if there was none
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error, the map in memory is left unchanged then
+    pub async fn remove(&mut self, key: &[u8]) -> Result<bool, Error> {
+        let Some(previous) = self.entries.remove(key) else {
+            return Ok(false);
+        };
+        if let Err(err) = self.write().await {
+            self.entries.insert(key.to_vec(), previous);
+            return Err(err);
+        }
+        Ok(true)
+    }
+
+    /// Writes the map to a temporary file and renames it over the old one
+    async fn write(&self) -> Result<(), Error> {
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path).await.map_err(|error| FileCreation {
+            path: tmp_path.to_owned(),
+            error,
+        })?;
+        file.write_all(&self.serialize())
+            .await
+            .map_err(|error| FileWrite {
+                path: tmp_path.to_owned(),
+                error,
+            })?;
+        file.sync_all().await.map_err(FileSync)?;
+        fs::rename(&tmp_path, &self.path)
+            .await
+            .map_err(|error| FileRename {
+                path: self.path.to_owned(),
+                error,
+            })
+    }
+
+    /// Serializes entries as a count followed by length prefixed keys and values
+    fn serialize(&self) -> Vec<u8> {
+        let mut serialized_data = Vec::new();
+        serialized_data.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for (key, value) in self.entries.iter() {
+            serialized_data.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            serialized_data.extend_from_slice(key);
+            serialized_data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            serialized_data.extend_from_slice(value);
+        }
+        serialized_data
+    }
+
+    /// Parses bytes written by `serialize`, returns `None` if they are malformed
+    fn deserialize(bytes: &[u8]) -> Option<BTreeMap<Vec<u8>, Vec<u8>>> {
+        let mut offset = 0;
+        let mut read_bytes = |len: usize| -> Option<&[u8]> {
+            let bytes = bytes.get(offset..offset + len)?;
+            offset += len;
+            Some(bytes)
+        };
+        let count = u32::from_le_bytes(read_bytes(SIZE_OF_U32)?.try_into().ok()?);
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            let key_len = u32::from_le_bytes(read_bytes(SIZE_OF_U32)?.try_into().ok()?);
+            let key = read_bytes(key_len as usize)?.to_vec();
+            let value_len = u32::from_le_bytes(read_bytes(SIZE_OF_U32)?.try_into().ok()?);
+            let value = read_bytes(value_len as usize)?.to_vec();
+            entries.insert(key, value);
+        }
+        (offset == bytes.len()).then_some(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_user_meta_persists_changes() {
+        let root = tempdir().unwrap();
+        let mut meta = UserMeta::open(root.path()).await.unwrap();
+        assert!(meta.get(b"schema").is_none());
+        meta.set(b"schema", b"v1").await.unwrap();
+        meta.set(b"owner", b"billing").await.unwrap();
+        meta.set(b"schema", b"v2").await.unwrap();
+        assert!(meta.remove(b"owner").await.unwrap());
+        assert!(!meta.remove(b"owner").await.unwrap());
+
+        let meta = UserMeta::open(root.path()).await.unwrap();
+        assert_eq!(meta.get(b"schema"), Some(&b"v2"[..]));
+        assert!(meta.get(b"owner").is_none());
+        // no temporary file is left behind
+        assert!(!root
+            .path()
+            .join(USER_META_FILE_NAME)
+            .with_extension("tmp")
+            .exists());
+
+        fs::write(root.path().join(USER_META_FILE_NAME), [1, 0, 0])
+            .await
+            .unwrap();
+        assert!(matches!(
+            UserMeta::open(root.path()).await,
+            Err(Error::MetaCorrupted(_))
+        ));
+    }
+}
@@ -0,0 +1,88 @@
+//! Helpers streaming existing databases into a [`DataStore`]
+//!
+//! Entries are written through [`DataStore::write_batch`] in batches of
+//! `MIGRATION_BATCH_SIZE`, so a failed migration leaves whole batches behind
+//! and can be resumed by running it again. Sources are enabled by features:
+//!
+//! - `sled`: [`from_sled`]
+//! - `redb`: [`from_redb`]
+//! - `rocksdb-sst`: [`from_rocksdb_sst`], reads SSTable files without linking RocksDB
+//!
+//! Neither sled nor redb record when an entry was written and RocksDB only
+//! keeps sequence numbers, so migrated entries are versioned at the time of
+//! the migration. For RocksDB the newest version of every key wins, as it
+//! would on read.
+
+#[cfg(any(feature = "sled", feature = "redb", feature = "rocksdb-sst"))]
+use crate::{
+    consts::MIGRATION_BATCH_SIZE,
+    db::{DataStore, WriteBatch},
+    err::Error,
+    types::Key,
+};
+
+#[cfg(feature = "redb")]
+mod redb;
+#[cfg(feature = "rocksdb-sst")]
+mod rocksdb;
+#[cfg(feature = "sled")]
+mod sled;
+
+#[cfg(feature = "redb")]
+pub use self::redb::from_redb;
+#[cfg(feature = "rocksdb-sst")]
+pub use self::rocksdb::from_rocksdb_sst;
+#[cfg(feature = "sled")]
+pub use self::sled::from_sled;
+
+/// Stages migrated entries and writes them to the store a batch at a time
+#[cfg(any(feature = "sled", feature = "redb", feature = "rocksdb-sst"))]
+struct BatchWriter<'s> {
+    store: &'s mut DataStore<'static, Key>,
+    batch: WriteBatch,
+    written: usize,
+}
+
+#[cfg(any(feature = "sled", feature = "redb", feature = "rocksdb-sst"))]
+impl<'s> BatchWriter<'s> {
+    fn new(store: &'s mut DataStore<'static, Key>) -> Self {
+        Self {
+            store,
+            batch: WriteBatch::new(),
+            written: 0,
+        }
+    }
+
+    /// Stages insert of `key` with `value`
+    async fn put(&mut self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+        self.batch.put(key, value);
+        self.write_if_full().await
+    }
+
+    /// Stages removal of `key`, used for deletes still recorded by the source
+    #[cfg(feature = "rocksdb-sst")]
+    async fn delete(&mut self, key: &[u8]) -> Result<(), Error> {
+        self.batch.delete(key);
+        self.write_if_full().await
+    }
+
+    async fn write_if_full(&mut self) -> Result<(), Error> {
+        if self.batch.len() >= MIGRATION_BATCH_SIZE {
+            self.write().await?;
+        }
+        Ok(())
+    }
+
+    async fn write(&mut self) -> Result<(), Error> {
+        let batch = std::mem::take(&mut self.batch);
+        self.written += batch.len();
+        self.store.write_batch(batch).await?;
+        Ok(())
+    }
+
+    /// Writes staged entries, returns number of entries migrated
+    async fn finish(mut self) -> Result<usize, Error> {
+        self.write().await?;
+        Ok(self.written)
+    }
+}
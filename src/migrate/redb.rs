@@ -0,0 +1,34 @@
+use super::BatchWriter;
+use crate::{db::DataStore, err::Error, types::Key};
+use ::redb::{ReadableTable, TableDefinition};
+
+/// Copies every entry of redb table `table` into `store`
+///
+/// The table has to map `&[u8]` keys to `&[u8]` values, entries are read
+/// from a single read transaction so writes to `db` during the migration
+/// are not picked up
+///
+/// Returns number of entries migrated
+///
+/// # Errors
+///
+/// Returns [`Error::Migrate`] if the table cannot be read or holds other
+/// types, or error from [`DataStore::write_batch`]
+pub async fn from_redb(
+    db: &::redb::Database,
+    table: &str,
+    store: &mut DataStore<'static, Key>,
+) -> Result<usize, Error> {
+    let migrate_err = |err: ::redb::Error| Error::Migrate(Box::new(err));
+    let definition: TableDefinition<&[u8], &[u8]> = TableDefinition::new(table);
+    let txn = db.begin_read().map_err(|err| migrate_err(err.into()))?;
+    let source = txn
+        .open_table(definition)
+        .map_err(|err| migrate_err(err.into()))?;
+    let mut writer = BatchWriter::new(store);
+    for entry in source.iter().map_err(|err| migrate_err(err.into()))? {
+        let (key, value) = entry.map_err(|err| migrate_err(err.into()))?;
+        writer.put(key.value(), value.value()).await?;
+    }
+    writer.finish().await
+}
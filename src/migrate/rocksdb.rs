@@ -0,0 +1,424 @@
+use super::BatchWriter;
+use crate::{db::DataStore, err::Error, types::Key};
+use std::{borrow::Cow, collections::HashMap, path::Path};
+use tokio::fs;
+
+/// Magic number ending block-based SSTables written with format version 1 and later
+const BLOCK_BASED_MAGIC: u64 = 0x88e2_41b7_85f4_cff7;
+
+/// Magic number ending block-based SSTables written with format version 0
+const LEGACY_BLOCK_BASED_MAGIC: u64 = 0xdb47_7524_8b80_fb57;
+
+/// Footer size with format version 1 and later
+const FOOTER_SIZE: usize = 53;
+
+/// Footer size with format version 0
+const LEGACY_FOOTER_SIZE: usize = 48;
+
+/// Bytes of the footer holding block handles, padded
+const FOOTER_HANDLES_SIZE: usize = 40;
+
+/// Marks part 2 of the footer from format version 6 on
+const EXTENDED_MAGIC: [u8; 4] = [0x3e, 0x00, 0x7a, 0x00];
+
+/// Compression type and checksum following every block
+const BLOCK_TRAILER_SIZE: usize = 5;
+
+const NO_COMPRESSION: u8 = 0x0;
+const SNAPPY_COMPRESSION: u8 = 0x1;
+
+const TYPE_DELETION: u8 = 0x0;
+const TYPE_VALUE: u8 = 0x1;
+const TYPE_SINGLE_DELETION: u8 = 0x7;
+const TYPE_DELETION_WITH_TIMESTAMP: u8 = 0x14;
+
+/// Size of sequence number and value type appended to user keys
+const INTERNAL_KEY_TRAILER_SIZE: usize = 8;
+
+const INDEX_BINARY_SEARCH: u32 = 0;
+const INDEX_HASH_SEARCH: u32 = 1;
+const INDEX_BINARY_SEARCH_WITH_FIRST_KEY: u32 = 3;
+
+const PROPERTIES_BLOCK: &[u8] = b"rocksdb.properties";
+const INDEX_BLOCK: &[u8] = b"rocksdb.index";
+const PROP_COMPARATOR: &[u8] = b"rocksdb.comparator";
+const PROP_INDEX_TYPE: &[u8] = b"rocksdb.block.based.table.index.type";
+const PROP_INDEX_DELTA_ENCODED: &[u8] = b"rocksdb.index.value.is.delta.encoded";
+const PROP_RANGE_DELETIONS: &[u8] = b"rocksdb.num.range-deletions";
+
+/// Comparators keys can be copied as is with
+const SUPPORTED_COMPARATORS: [&[u8]; 2] = [
+    b"leveldb.BytewiseComparator",
+    b"rocksdb.ReverseBytewiseComparator",
+];
+
+/// Copies every entry of the RocksDB SSTable at `path` into `store`
+///
+/// Block-based SSTables with uncompressed or Snappy compressed blocks are
+/// read without linking RocksDB, the file is read into memory whole. Only
+/// the newest version of each key is copied, deletes are copied as deletes
+/// so older versions migrated from other files are shadowed as in RocksDB.
+/// Files are thus migrated from oldest to newest: from the bottommost level
+/// up, and level 0 files in the order they were written.
+///
+/// Returns number of puts and deletes migrated
+///
+/// # Errors
+///
+/// Returns [`Error::Migrate`] if the file cannot be read or uses a feature
+/// that cannot be copied as is: compression other than Snappy, partitioned
+/// indexes, range deletions, merge operands, blob references or user-defined
+/// timestamps. Returns error from [`DataStore::write_batch`] otherwise.
+pub async fn from_rocksdb_sst(
+    path: impl AsRef<Path>,
+    store: &mut DataStore<'static, Key>,
+) -> Result<usize, Error> {
+    let data = fs::read(path.as_ref())
+        .await
+        .map_err(|err| Error::Migrate(Box::new(err)))?;
+    let table = SstFile::open(&data)?;
+    let mut writer = BatchWriter::new(store);
+    let mut last_key: Option<Vec<u8>> = None;
+    for handle in table.data_blocks()? {
+        let block = table.block(handle)?;
+        for (internal_key, value) in BlockEntries::parse(&block)?.collect::<Result<Vec<_>, _>>()? {
+            if internal_key.len() < INTERNAL_KEY_TRAILER_SIZE {
+                return Err(corrupt("internal key is shorter than its trailer"));
+            }
+            let (key, trailer) = internal_key.split_at(internal_key.len() - INTERNAL_KEY_TRAILER_SIZE);
+            // versions of a key are ordered newest first
+            if last_key.as_deref() == Some(key) {
+                continue;
+            }
+            match trailer[0] {
+                TYPE_VALUE => writer.put(key, &value).await?,
+                TYPE_DELETION | TYPE_SINGLE_DELETION | TYPE_DELETION_WITH_TIMESTAMP => {
+                    writer.delete(key).await?
+                }
+                other => return Err(unsupported(format!("entries of type {:#x}", other))),
+            }
+            last_key = Some(key.to_vec());
+        }
+    }
+    writer.finish().await
+}
+
+fn corrupt(reason: &str) -> Error {
+    Error::Migrate(format!("RocksDB SSTable is corrupted: {}", reason).into())
+}
+
+fn unsupported(feature: String) -> Error {
+    Error::Migrate(format!("RocksDB SSTable uses {} which cannot be migrated", feature).into())
+}
+
+/// Location of a block within the file, trailer excluded
+#[derive(Clone, Copy, Debug)]
+struct BlockHandle {
+    offset: u64,
+    size: u64,
+}
+
+impl BlockHandle {
+    fn decode(input: &mut &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            offset: read_varint64(input)?,
+            size: read_varint64(input)?,
+        })
+    }
+}
+
+/// Block-based SSTable held in memory
+struct SstFile<'d> {
+    data: &'d [u8],
+    index: BlockHandle,
+    index_type: u32,
+    delta_encoded_index: bool,
+}
+
+impl<'d> SstFile<'d> {
+    /// Reads footer and properties of the SSTable in `data`
+    fn open(data: &'d [u8]) -> Result<Self, Error> {
+        let len = data.len();
+        if len < LEGACY_FOOTER_SIZE {
+            return Err(corrupt("file is shorter than its footer"));
+        }
+        let magic = read_fixed64(&data[len - 8..]);
+        let (metaindex, index) = if magic == LEGACY_BLOCK_BASED_MAGIC {
+            let mut handles = &data[len - LEGACY_FOOTER_SIZE..len - 8];
+            (
+                BlockHandle::decode(&mut handles)?,
+                Some(BlockHandle::decode(&mut handles)?),
+            )
+        } else if magic == BLOCK_BASED_MAGIC {
+            if len < FOOTER_SIZE {
+                return Err(corrupt("file is shorter than its footer"));
+            }
+            let footer = &data[len - FOOTER_SIZE..];
+            let format_version = read_fixed32(&footer[1 + FOOTER_HANDLES_SIZE..]);
+            if format_version < 6 {
+                let mut handles = &footer[1..1 + FOOTER_HANDLES_SIZE];
+                (
+                    BlockHandle::decode(&mut handles)?,
+                    Some(BlockHandle::decode(&mut handles)?),
+                )
+            } else {
+                // metaindex block precedes the footer and holds the index handle
+                if footer[1..5] != EXTENDED_MAGIC {
+                    return Err(corrupt("footer misses its extended magic"));
+                }
+                let size = read_fixed32(&footer[13..17]) as usize;
+                let offset = (len - FOOTER_SIZE)
+                    .checked_sub(size + BLOCK_TRAILER_SIZE)
+                    .ok_or_else(|| corrupt("metaindex block is larger than the file"))?;
+                let metaindex = BlockHandle {
+                    offset: offset as u64,
+                    size: size as u64,
+                };
+                (metaindex, None)
+            }
+        } else {
+            return Err(Error::Migrate("file is not a RocksDB block-based SSTable".into()));
+        };
+
+        let mut table = Self {
+            data,
+            index: BlockHandle { offset: 0, size: 0 },
+            index_type: INDEX_BINARY_SEARCH,
+            delta_encoded_index: false,
+        };
+        let meta_blocks = table.named_entries(metaindex)?;
+        table.index = match index {
+            Some(index) => index,
+            None => {
+                let mut handle = meta_blocks
+                    .get(INDEX_BLOCK)
+                    .map(Vec::as_slice)
+                    .ok_or_else(|| corrupt("metaindex block misses the index handle"))?;
+                BlockHandle::decode(&mut handle)?
+            }
+        };
+        if let Some(mut handle) = meta_blocks.get(PROPERTIES_BLOCK).map(Vec::as_slice) {
+            let properties = table.named_entries(BlockHandle::decode(&mut handle)?)?;
+            table.apply_properties(&properties)?;
+        }
+        Ok(table)
+    }
+
+    fn apply_properties(&mut self, properties: &HashMap<Vec<u8>, Vec<u8>>) -> Result<(), Error> {
+        if let Some(comparator) = properties.get(PROP_COMPARATOR) {
+            if !SUPPORTED_COMPARATORS.contains(&comparator.as_slice()) {
+                return Err(unsupported(format!(
+                    "comparator {}",
+                    String::from_utf8_lossy(comparator)
+                )));
+            }
+        }
+        if let Some(mut count) = properties.get(PROP_RANGE_DELETIONS).map(Vec::as_slice) {
+            if read_varint64(&mut count)? > 0 {
+                return Err(unsupported("range deletions".to_string()));
+            }
+        }
+        if let Some(index_type) = properties.get(PROP_INDEX_TYPE) {
+            if index_type.len() < 4 {
+                return Err(corrupt("index type property is truncated"));
+            }
+            self.index_type = read_fixed32(index_type);
+        }
+        if ![
+            INDEX_BINARY_SEARCH,
+            INDEX_HASH_SEARCH,
+            INDEX_BINARY_SEARCH_WITH_FIRST_KEY,
+        ]
+        .contains(&self.index_type)
+        {
+            return Err(unsupported(format!("index type {}", self.index_type)));
+        }
+        if let Some(mut delta_encoded) = properties.get(PROP_INDEX_DELTA_ENCODED).map(Vec::as_slice) {
+            self.delta_encoded_index = read_varint64(&mut delta_encoded)? != 0;
+        }
+        Ok(())
+    }
+
+    /// Returns contents of block at `handle`, decompressed
+    fn block(&self, handle: BlockHandle) -> Result<Cow<'d, [u8]>, Error> {
+        let start = handle.offset as usize;
+        let end = start
+            .checked_add(handle.size as usize)
+            .filter(|end| end + BLOCK_TRAILER_SIZE <= self.data.len())
+            .ok_or_else(|| corrupt("block handle points past the end of the file"))?;
+        let raw = &self.data[start..end];
+        match self.data[end] {
+            NO_COMPRESSION => Ok(Cow::Borrowed(raw)),
+            SNAPPY_COMPRESSION => snap::raw::Decoder::new()
+                .decompress_vec(raw)
+                .map(Cow::Owned)
+                .map_err(|err| Error::Migrate(Box::new(err))),
+            other => Err(unsupported(format!("compression type {:#x}", other))),
+        }
+    }
+
+    /// Returns entries of a meta block at `handle` keyed by name
+    fn named_entries(&self, handle: BlockHandle) -> Result<HashMap<Vec<u8>, Vec<u8>>, Error> {
+        let block = self.block(handle)?;
+        BlockEntries::parse(&block)?.collect()
+    }
+
+    /// Returns handles of data blocks in key order
+    fn data_blocks(&self) -> Result<Vec<BlockHandle>, Error> {
+        let block = self.block(self.index)?;
+        let mut input = entries_of(&block)?;
+        let mut key = Vec::new();
+        let mut previous: Option<BlockHandle> = None;
+        let mut handles = Vec::new();
+        while !input.is_empty() {
+            let shared = read_varint32(&mut input)? as usize;
+            let non_shared = read_varint32(&mut input)? as usize;
+            let value_len = match self.delta_encoded_index {
+                true => None,
+                false => Some(read_varint32(&mut input)? as usize),
+            };
+            read_key(&mut input, &mut key, shared, non_shared)?;
+            let mut value = match value_len {
+                Some(len) => read_bytes(&mut input, len)?,
+                None => input,
+            };
+            // entries sharing key bytes with the previous one only hold the size difference
+            let handle = match (self.delta_encoded_index && shared != 0, previous) {
+                (true, Some(previous)) => {
+                    let delta = decode_signed(read_varint64(&mut value)?);
+                    BlockHandle {
+                        offset: previous.offset + previous.size + BLOCK_TRAILER_SIZE as u64,
+                        size: (previous.size as i64 + delta) as u64,
+                    }
+                }
+                (true, None) => return Err(corrupt("index starts with a delta encoded handle")),
+                (false, _) => BlockHandle::decode(&mut value)?,
+            };
+            if self.index_type == INDEX_BINARY_SEARCH_WITH_FIRST_KEY {
+                let first_key_len = read_varint32(&mut value)? as usize;
+                read_bytes(&mut value, first_key_len)?;
+            }
+            if self.delta_encoded_index {
+                input = value;
+            }
+            previous = Some(handle);
+            handles.push(handle);
+        }
+        Ok(handles)
+    }
+}
+
+/// Returns entries of `block`, restart points and hash index excluded
+fn entries_of(block: &[u8]) -> Result<&[u8], Error> {
+    if block.len() < 4 {
+        return Err(corrupt("block is shorter than its footer"));
+    }
+    let packed = read_fixed32(&block[block.len() - 4..]);
+    let num_restarts = (packed & 0x7fff_ffff) as usize;
+    let mut end = block.len() - 4;
+    // data blocks may end with a hash index before the packed footer
+    if packed >> 31 == 1 {
+        end = end
+            .checked_sub(2)
+            .ok_or_else(|| corrupt("block hash index is truncated"))?;
+        let num_buckets = u16::from_le_bytes([block[end], block[end + 1]]) as usize;
+        end = end
+            .checked_sub(num_buckets)
+            .ok_or_else(|| corrupt("block hash index is truncated"))?;
+    }
+    end = end
+        .checked_sub(num_restarts * 4)
+        .ok_or_else(|| corrupt("block restart points are truncated"))?;
+    Ok(&block[..end])
+}
+
+/// Iterates over key value pairs of a data or meta block
+struct BlockEntries<'b> {
+    input: &'b [u8],
+    key: Vec<u8>,
+}
+
+impl<'b> BlockEntries<'b> {
+    fn parse(block: &'b [u8]) -> Result<Self, Error> {
+        Ok(Self {
+            input: entries_of(block)?,
+            key: Vec::new(),
+        })
+    }
+
+    fn read_entry(&mut self) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let shared = read_varint32(&mut self.input)? as usize;
+        let non_shared = read_varint32(&mut self.input)? as usize;
+        let value_len = read_varint32(&mut self.input)? as usize;
+        read_key(&mut self.input, &mut self.key, shared, non_shared)?;
+        let value = read_bytes(&mut self.input, value_len)?;
+        Ok((self.key.to_owned(), value.to_vec()))
+    }
+}
+
+impl Iterator for BlockEntries<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.input.is_empty() {
+            return None;
+        }
+        let entry = self.read_entry();
+        if entry.is_err() {
+            self.input = &[];
+        }
+        Some(entry)
+    }
+}
+
+/// Rebuilds `key` from `shared` bytes of the previous key and `non_shared` bytes of `input`
+fn read_key(input: &mut &[u8], key: &mut Vec<u8>, shared: usize, non_shared: usize) -> Result<(), Error> {
+    if shared > key.len() {
+        return Err(corrupt("key shares more bytes than the previous key holds"));
+    }
+    let suffix = read_bytes(input, non_shared)?;
+    key.truncate(shared);
+    key.extend_from_slice(suffix);
+    Ok(())
+}
+
+fn read_bytes<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error> {
+    if input.len() < len {
+        return Err(corrupt("entry is truncated"));
+    }
+    let (bytes, rest) = input.split_at(len);
+    *input = rest;
+    Ok(bytes)
+}
+
+fn read_varint64(input: &mut &[u8]) -> Result<u64, Error> {
+    let mut result = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = input
+            .split_first()
+            .ok_or_else(|| corrupt("varint is truncated"))?;
+        *input = rest;
+        result |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    Err(corrupt("varint is too long"))
+}
+
+fn read_varint32(input: &mut &[u8]) -> Result<u32, Error> {
+    u32::try_from(read_varint64(input)?).map_err(|_| corrupt("varint does not fit 32 bits"))
+}
+
+/// Decodes a zigzag encoded signed varint
+fn decode_signed(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+fn read_fixed32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+fn read_fixed64(bytes: &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
@@ -0,0 +1,21 @@
+use super::BatchWriter;
+use crate::{db::DataStore, err::Error, types::Key};
+
+/// Copies every entry of sled `tree` into `store`
+///
+/// A `sled::Db` can be passed as is to migrate its default tree, other
+/// trees are opened with `sled::Db::open_tree` and migrated one by one
+///
+/// Returns number of entries migrated
+///
+/// # Errors
+///
+/// Returns [`Error::Migrate`] if `tree` cannot be read or error from [`DataStore::write_batch`]
+pub async fn from_sled(tree: &::sled::Tree, store: &mut DataStore<'static, Key>) -> Result<usize, Error> {
+    let mut writer = BatchWriter::new(store);
+    for entry in tree.iter() {
+        let (key, value) = entry.map_err(|err| Error::Migrate(Box::new(err)))?;
+        writer.put(&key, &value).await?;
+    }
+    writer.finish().await
+}
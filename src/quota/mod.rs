@@ -0,0 +1,220 @@
+use crate::{comparator::Comparator, err::Error};
+use std::sync::{Arc, Mutex};
+
+/// Caps on entries stored under a key prefix, such as the keys of one tenant
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrefixQuota {
+    /// Keys starting with these bytes are charged to the quota
+    pub prefix: Vec<u8>,
+
+    /// Maximum bytes of keys and values stored under the prefix
+    pub max_bytes: usize,
+
+    /// Maximum number of keys stored under the prefix
+    pub max_keys: usize,
+}
+
+/// Bytes and keys stored under a prefix with a quota
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PrefixUsage {
+    /// Prefix the quota applies to
+    pub prefix: Vec<u8>,
+
+    /// Bytes of keys and values of live entries under the prefix
+    pub bytes: usize,
+
+    /// Number of live keys under the prefix
+    pub keys: usize,
+
+    /// Cap on `bytes`
+    pub max_bytes: usize,
+
+    /// Cap on `keys`
+    pub max_keys: usize,
+}
+
+/// Change a write makes to usage of the prefixes its key falls under
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct UsageDelta {
+    pub bytes: isize,
+    pub keys: isize,
+}
+
+impl UsageDelta {
+    /// Returns change from replacing a value of `previous` bytes of key with `current` bytes,
+    /// `None` standing for an absent or deleted key
+    pub fn new(key_len: usize, previous: Option<usize>, current: Option<usize>) -> Self {
+        let size = |value: Option<usize>| value.map_or(0, |len| (key_len + len) as isize);
+        Self {
+            bytes: size(current) - size(previous),
+            keys: current.is_some() as isize - previous.is_some() as isize,
+        }
+    }
+}
+
+/// Quota of a prefix along with its usage
+#[derive(Debug)]
+struct TrackedPrefix {
+    quota: PrefixQuota,
+
+    /// Bytes and keys under the prefix, `None` until counted from stored entries
+    usage: Option<(usize, usize)>,
+}
+
+/// Tracks usage of prefixes with a quota, shared with the compactor
+///
+/// Usage is counted from stored entries the first time it is needed and
+/// updated by writes afterwards. Compaction dropping expired entries under a
+/// prefix has it counted again since values are not read by compaction
+#[derive(Clone, Debug, Default)]
+pub(crate) struct QuotaTracker {
+    comparator: Comparator,
+    prefixes: Arc<Mutex<Vec<TrackedPrefix>>>,
+}
+
+impl QuotaTracker {
+    /// Creates new `QuotaTracker` for keys ordered by `comparator`
+    pub fn new(comparator: Comparator, quotas: &[PrefixQuota]) -> Self {
+        let tracker = Self {
+            comparator,
+            prefixes: Default::default(),
+        };
+        for quota in quotas {
+            tracker.set(quota.to_owned());
+        }
+        tracker
+    }
+
+    /// Returns key as seen by the user, so keys a comparator treats as the
+    /// same are charged to the same prefixes
+    pub fn canonical(&self, key: &[u8]) -> Vec<u8> {
+        self.comparator.decode(self.comparator.encode(key).into_owned())
+    }
+
+    /// Sets caps of `quota.prefix`, usage of a prefix already tracked is kept
+    pub fn set(&self, mut quota: PrefixQuota) {
+        quota.prefix = self.canonical(&quota.prefix);
+        let mut prefixes = self.prefixes.lock().unwrap();
+        match prefixes.iter_mut().find(|p| p.quota.prefix == quota.prefix) {
+            Some(tracked) => tracked.quota = quota,
+            None => prefixes.push(TrackedPrefix { quota, usage: None }),
+        }
+    }
+
+    /// Returns `true` if no prefix has a quota
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.lock().unwrap().is_empty()
+    }
+
+    /// Returns `true` if `key`, as seen by the user, is charged to any quota
+    pub fn tracks(&self, key: &[u8]) -> bool {
+        self.prefixes
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|p| key.starts_with(&p.quota.prefix))
+    }
+
+    /// Returns prefixes whose usage has to be counted from stored entries
+    pub fn uncounted(&self) -> Vec<Vec<u8>> {
+        self.prefixes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|p| p.usage.is_none())
+            .map(|p| p.quota.prefix.to_owned())
+            .collect()
+    }
+
+    /// Records `bytes` and `keys` counted under `prefix`
+    pub fn set_usage(&self, prefix: &[u8], bytes: usize, keys: usize) {
+        let mut prefixes = self.prefixes.lock().unwrap();
+        if let Some(tracked) = prefixes.iter_mut().find(|p| p.quota.prefix == prefix) {
+            tracked.usage = Some((bytes, keys));
+        }
+    }
+
+    /// Returns bytes and keys under `prefix` once `deltas` are applied, along with their sum
+    fn usage_after(prefix: &TrackedPrefix, deltas: &[(Vec<u8>, UsageDelta)]) -> (usize, usize, UsageDelta) {
+        let (bytes, keys) = prefix.usage.unwrap_or_default();
+        let total = deltas
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix.quota.prefix))
+            .fold(UsageDelta::default(), |total, (_, delta)| UsageDelta {
+                bytes: total.bytes + delta.bytes,
+                keys: total.keys + delta.keys,
+            });
+        let bytes = (bytes as isize + total.bytes).max(0) as usize;
+        let keys = (keys as isize + total.keys).max(0) as usize;
+        (bytes, keys, total)
+    }
+
+    /// Checks that `deltas` keep every prefix within its quota
+    ///
+    /// Writes that do not grow usage are always allowed, so a tenant over its
+    /// quota after the quota was lowered can still shrink
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::QuotaExceeded`] naming the first prefix going over its quota
+    pub fn check(&self, deltas: &[(Vec<u8>, UsageDelta)]) -> Result<(), Error> {
+        if deltas.is_empty() {
+            return Ok(());
+        }
+        for prefix in self.prefixes.lock().unwrap().iter() {
+            let (bytes, keys, total) = Self::usage_after(prefix, deltas);
+            let over_bytes = total.bytes > 0 && bytes > prefix.quota.max_bytes;
+            let over_keys = total.keys > 0 && keys > prefix.quota.max_keys;
+            if over_bytes || over_keys {
+                return Err(Error::QuotaExceeded {
+                    prefix: prefix.quota.prefix.to_owned(),
+                    bytes,
+                    keys,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies `deltas` of a completed write to prefixes whose usage is counted
+    pub fn apply(&self, deltas: &[(Vec<u8>, UsageDelta)]) {
+        if deltas.is_empty() {
+            return;
+        }
+        for prefix in self.prefixes.lock().unwrap().iter_mut() {
+            if prefix.usage.is_some() {
+                let (bytes, keys, _) = Self::usage_after(prefix, deltas);
+                prefix.usage = Some((bytes, keys));
+            }
+        }
+    }
+
+    /// Has prefixes of `encoded_key` counted again, called when compaction drops an expired entry
+    pub fn invalidate(&self, encoded_key: &[u8]) {
+        let key = self.comparator.decode(encoded_key.to_vec());
+        for prefix in self.prefixes.lock().unwrap().iter_mut() {
+            if key.starts_with(&prefix.quota.prefix) {
+                prefix.usage = None;
+            }
+        }
+    }
+
+    /// Returns usage of every prefix with a quota, prefixes yet to be counted are left out
+    pub fn usage(&self) -> Vec<PrefixUsage> {
+        self.prefixes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|p| {
+                p.usage.map(|(bytes, keys)| PrefixUsage {
+                    prefix: p.quota.prefix.to_owned(),
+                    bytes,
+                    keys,
+                    max_bytes: p.quota.max_bytes,
+                    max_keys: p.quota.max_keys,
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,225 @@
+use crate::consts::{HEAD_ENTRY_KEY, TAIL_ENTRY_KEY};
+use crate::key_range::Range;
+use crate::memtable::{Entry, SkipMapValue};
+use crate::types::{CreatedAt, Key, SkipMapEntries, ValOffset};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Decides which merged entries are left out of the output
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Suppression {
+    /// Drops every tombstone, scans have no use for them while compaction
+    /// keeps unexpired ones to shadow older versions in other sstables
+    pub drop_tombstones: bool,
+
+    /// Tombstones older than this are dropped, `None` keeps them
+    pub tombstone_ttl: Option<Duration>,
+
+    /// Entries older than this are dropped, `None` keeps them
+    pub entry_ttl: Option<Duration>,
+
+    /// Time expiry is measured against
+    pub now: CreatedAt,
+}
+
+impl Suppression {
+    /// Returns suppression used by scans, tombstones are always dropped
+    pub fn for_scan(entry_ttl: Option<Duration>, now: CreatedAt) -> Self {
+        Self {
+            drop_tombstones: true,
+            tombstone_ttl: None,
+            entry_ttl,
+            now,
+        }
+    }
+
+    /// Returns suppression used by compaction, only expired tombstones are dropped
+    pub fn for_compaction(tombstone_ttl: Duration, entry_ttl: Option<Duration>, now: CreatedAt) -> Self {
+        Self {
+            drop_tombstones: false,
+            tombstone_ttl: Some(tombstone_ttl),
+            entry_ttl,
+            now,
+        }
+    }
+
+    /// Returns `true` if `entry` should be left out of the output
+    pub fn suppresses(&self, entry: &Entry<Key, ValOffset>) -> bool {
+        if entry.is_tombstone {
+            self.drop_tombstones
+                || self
+                    .tombstone_ttl
+                    .is_some_and(|ttl| entry.has_expired(ttl, self.now))
+        } else {
+            self.entry_ttl.is_some_and(|ttl| entry.has_expired(ttl, self.now))
+        }
+    }
+}
+
+/// Merges entries of memtables and sstables keeping the most recent version
+/// of each key, then yields them in ascending key order
+///
+/// Versions are resolved before suppression so a suppressed tombstone still
+/// hides older versions of its key
+#[derive(Debug, Default)]
+pub struct MergeIterator {
+    entries: BTreeMap<Key, Entry<Key, ValOffset>>,
+    suppression: Suppression,
+
+    /// Value log offsets of versions replaced by a newer version of their key
+    superseded: Vec<ValOffset>,
+}
+
+impl MergeIterator {
+    /// Creates new `MergeIterator` dropping entries matched by `suppression`
+    pub fn new(suppression: Suppression) -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            suppression,
+            superseded: Vec::new(),
+        }
+    }
+
+    /// Merges every entry, keeping the newest version of a key
+    ///
+    /// On equal timestamps the version merged first wins, so sources
+    /// should be merged newest first
+    pub fn merge_all(&mut self, entries: &SkipMapEntries<Key>) {
+        for e in entries.iter() {
+            self.merge_entry(e.key(), e.value());
+        }
+    }
+
+    /// Merges entries within `[start, end]`, skipping value log head and tail entries
+    pub fn merge_range(&mut self, entries: &SkipMapEntries<Key>, start: &[u8], end: &[u8]) {
+        for e in entries.range(start.to_vec()..=end.to_vec()) {
+            if e.key() == HEAD_ENTRY_KEY || e.key() == TAIL_ENTRY_KEY {
+                continue;
+            }
+            self.merge_entry(e.key(), e.value());
+        }
+    }
+
+    fn merge_entry(&mut self, key: &Key, value: &SkipMapValue<ValOffset>) {
+        let SkipMapValue {
+            val_offset,
+            created_at,
+            is_tombstone,
+        } = value.to_owned();
+        let newer = self
+            .entries
+            .get(key)
+            .is_none_or(|existing| created_at > existing.created_at);
+        // head and tail entries hold offsets of other records, not their own
+        let owns_record = key != HEAD_ENTRY_KEY && key != TAIL_ENTRY_KEY;
+        if !newer {
+            if owns_record {
+                self.superseded.push(val_offset);
+            }
+            return;
+        }
+        let replaced = self.entries.insert(
+            key.to_owned(),
+            Entry::new(key.to_owned(), val_offset, created_at, is_tombstone),
+        );
+        if let Some(replaced) = replaced.filter(|_| owns_record) {
+            self.superseded.push(replaced.val_offset);
+        }
+    }
+
+    /// Returns value log offsets of versions merged so far that lost to a newer
+    /// version of their key, value log head and tail entries are left out
+    pub(crate) fn take_superseded(&mut self) -> Vec<ValOffset> {
+        std::mem::take(&mut self.superseded)
+    }
+
+    /// Returns `true` if sstable in `range` cannot contribute to the scan of `[start, end]`
+    ///
+    /// Without levels sstables carry no ordering between each other, so pruning
+    /// is limited to sstables whose overlap with the scan is a single key that is
+    /// either resolved by memtables, which are newer than every sstable, or ruled
+    /// out by the sstable bloom filter
+    pub(crate) fn is_shadowed(&self, range: &Range, start: &[u8], end: &[u8]) -> bool {
+        let lo = range.smallest_key.as_slice().max(start);
+        let hi = range.biggest_key.as_slice().min(end);
+        if lo > hi {
+            return true;
+        }
+        if lo != hi {
+            return false;
+        }
+        self.entries.contains_key(lo)
+            || range
+                .sst
+                .filter
+                .as_ref()
+                .is_some_and(|filter| filter.sst_id.is_some() && !filter.contains(lo))
+    }
+}
+
+impl Iterator for MergeIterator {
+    type Item = Entry<Key, ValOffset>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((_, entry)) = self.entries.pop_first() {
+            if !self.suppression.suppresses(&entry) {
+                return Some(entry);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_skiplist::SkipMap;
+    use std::sync::Arc;
+
+    fn entries(items: &[(&str, usize, i64, bool)]) -> SkipMapEntries<Key> {
+        let map = Arc::new(SkipMap::new());
+        for (key, offset, millis, is_tombstone) in items {
+            let created_at = CreatedAt::from_timestamp_millis(*millis).unwrap();
+            map.insert(
+                key.as_bytes().to_vec(),
+                SkipMapValue::new(*offset, created_at, *is_tombstone),
+            );
+        }
+        map
+    }
+
+    #[test]
+    fn test_merge_iterator_suppresses_tombstones_and_expired_entries() {
+        let newer = entries(&[("apple", 10, 5_000, true), ("google", 11, 5_000, false)]);
+        let older = entries(&[
+            ("apple", 0, 1_000, false),
+            ("nvidia", 1, 1_000, false),
+            ("tesla", 2, 1_000, true),
+        ]);
+        let now = CreatedAt::from_timestamp_millis(6_000).unwrap();
+
+        let mut scan = MergeIterator::new(Suppression::for_scan(Some(Duration::from_secs(2)), now));
+        scan.merge_all(&older);
+        scan.merge_all(&newer);
+        // deleted apple and expired nvidia are dropped
+        let keys: Vec<Key> = scan.map(|e| e.key).collect();
+        assert_eq!(keys, vec![b"google".to_vec()]);
+
+        let mut compaction =
+            MergeIterator::new(Suppression::for_compaction(Duration::from_secs(3), None, now));
+        compaction.merge_all(&newer);
+        compaction.merge_all(&older);
+        // older apple lost to the tombstone
+        assert_eq!(compaction.take_superseded(), vec![0]);
+        // unexpired tombstone is kept, expired one is dropped
+        let merged: Vec<(Key, ValOffset)> = compaction.map(|e| (e.key, e.val_offset)).collect();
+        assert_eq!(
+            merged,
+            vec![
+                (b"apple".to_vec(), 10),
+                (b"google".to_vec(), 11),
+                (b"nvidia".to_vec(), 1)
+            ]
+        );
+    }
+}
@@ -1,2 +1,4 @@
+mod merge;
 mod range_iterator;
-pub use range_iterator::RangeIterator;
+pub use merge::{MergeIterator, Suppression};
+pub use range_iterator::{FetchedEntry, RangeIterator};
@@ -1,13 +1,22 @@
+use super::{MergeIterator, Suppression};
+use crate::comparator::Comparator;
 use crate::db::DataStore;
 use crate::err::Error;
+use crate::index::Index;
 use crate::memtable::Entry;
-use crate::types::{Key, ValOffset, Value};
-use crate::vlog::ValueLog;
+use crate::slow_log::{OpTimer, Phase};
+use crate::sst::Table;
+use crate::types::{ActiveMemTable, CreatedAt, ImmutableMemTables, Key, ValOffset};
+use crate::vlog::{ReadAheadBuffer, ValueExpiry, ValueLog};
+use bytes::Bytes;
+use chrono::Utc;
+use std::sync::Arc;
+use std::time::Instant;
 
 #[derive(Debug, Clone)]
 pub struct FetchedEntry {
     pub key: Key,
-    pub val: Value,
+    pub val: Bytes,
 }
 
 #[derive(Debug, Clone)]
@@ -20,6 +29,27 @@ pub struct RangeIterator<'a> {
     pub prefetch_entries: Vec<FetchedEntry>,
     pub keys: Vec<Entry<Key, ValOffset>>,
     pub v_log: ValueLog,
+
+    /// Bytes of value log to read at once when offsets are ascending, 0 disables read-ahead
+    pub read_ahead_size: usize,
+
+    /// Value log chunk read ahead of the current entry
+    pub(crate) read_ahead: Option<ReadAheadBuffer>,
+
+    /// Whether this scan reads ahead, see [`RangeIterator::with_read_ahead`]
+    pub(crate) read_ahead_enabled: bool,
+
+    /// Number of reads issued to the value log
+    pub(crate) vlog_reads: usize,
+
+    /// Number of overlapping sstables skipped without being read
+    pub(crate) skipped_sstables: usize,
+
+    /// Comparator keys were encoded with, returned keys are decoded with it
+    pub(crate) comparator: Comparator,
+
+    /// Time the scan started, values that expired by then are left out
+    pub(crate) now: CreatedAt,
 }
 
 impl<'a> RangeIterator<'a> {
@@ -30,6 +60,7 @@ impl<'a> RangeIterator<'a> {
         prefetch_entries_size: usize,
         keys: Vec<Entry<Key, ValOffset>>,
         v_log: ValueLog,
+        read_ahead_size: usize,
     ) -> Self {
         Self {
             start,
@@ -40,29 +71,264 @@ impl<'a> RangeIterator<'a> {
             prefetch_entries: Vec::new(),
             keys,
             v_log,
+            read_ahead_size,
+            read_ahead: None,
+            read_ahead_enabled: true,
+            vlog_reads: 0,
+            skipped_sstables: 0,
+            comparator: Comparator::default(),
+            now: Utc::now(),
+        }
+    }
+
+    /// Sets whether this scan reads value log ahead when values are stored in
+    /// key order, enabled by default if `vlog_read_ahead_size` is set
+    ///
+    /// Scans fetching few entries or entries written out of order gain
+    /// nothing from it and can turn it off to save the extra IO
+    pub fn with_read_ahead(mut self, enable: bool) -> Self {
+        self.read_ahead_enabled = enable;
+        if !enable {
+            self.read_ahead = None;
+        }
+        self
+    }
+
+    /// Returns next entry in the range or `None` once the range is exhausted
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn next(&mut self) -> Result<Option<FetchedEntry>, Error> {
+        while self.current < self.keys.len() {
+            let entry = self.keys[self.current].to_owned();
+            self.current += 1;
+            if let Some((val, is_tombstone)) = self.fetch_value(entry.val_offset).await? {
+                if !is_tombstone {
+                    return Ok(Some(FetchedEntry {
+                        key: self.comparator.decode(entry.key),
+                        val,
+                    }));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Fetches value at `offset`
+    ///
+    /// If the following entries have ascending offsets within `read_ahead_size`
+    /// a chunk of value log is read once and next values are decoded from it,
+    /// the OS is also told the chunk after it will be needed
+    async fn fetch_value(&mut self, offset: ValOffset) -> Result<Option<(Bytes, bool)>, Error> {
+        if let Some(res) = self.read_ahead.as_ref().and_then(|buf| buf.get(offset, self.now)) {
+            return Ok(Some(res));
+        }
+        self.vlog_reads += 1;
+        if self.should_read_ahead(offset) {
+            let buf = self.v_log.read_ahead(offset, self.read_ahead_size).await?;
+            self.v_log
+                .will_need(offset + self.read_ahead_size, self.read_ahead_size)
+                .await?;
+            let res = buf.get(offset, self.now);
+            self.read_ahead = Some(buf);
+            if res.is_some() {
+                return Ok(res);
+            }
+            // value is bigger than the read-ahead buffer
+        }
+        let (value, is_tombstone, expires_at) = self.v_log.get_with_expiry(offset).await?;
+        Ok(Some((
+            value,
+            is_tombstone || ValueExpiry::has_passed(expires_at, self.now),
+        )))
+    }
+
+    /// Returns `true` if the next entry is stored right after `offset` in value log
+    fn should_read_ahead(&self, offset: ValOffset) -> bool {
+        if !self.read_ahead_enabled || self.read_ahead_size == 0 {
+            return false;
+        }
+        match self.keys.get(self.current) {
+            Some(next) => next.val_offset > offset && next.val_offset - offset < self.read_ahead_size,
+            None => false,
         }
     }
 }
 
 impl<'a> DataStore<'a, Key> {
-    // TODO: range query, add next and previous method
-    pub async fn seek(&self, _: &'a [u8], _: &'a [u8]) -> Result<RangeIterator, Error> {
-        let range_iterator = RangeIterator::<'a>::new(
-            &[1],
-            &[2],
+    /// Returns iterator over entries whose keys are within `[start, end]`
+    /// in the order of the store comparator
+    ///
+    /// Deleted keys are left out, as are entries older than `entry_ttl` if TTL
+    /// is enabled and entries whose own TTL passed
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn seek(&self, start: &'a [u8], end: &'a [u8]) -> Result<RangeIterator<'a>, Error> {
+        let comparator = self.config.comparator;
+        let (encoded_start, encoded_end) = (comparator.encode(start), comparator.encode(end));
+        let mut timer = self.slow_log.foreground("scan");
+        let (merger, skipped_sstables) = self
+            .merge_keys_in_range(encoded_start.as_ref(), encoded_end.as_ref(), &mut timer)
+            .await?;
+        let mut range_iterator = RangeIterator::<'a>::new(
+            start,
+            end,
             self.config.allow_prefetch,
             self.config.prefetch_size,
-            Merger::new().entries,
-            self.val_log.clone(),
+            merger.collect(),
+            self.val_log.read().await.clone(),
+            self.config.vlog_read_ahead_size,
         );
+        range_iterator.skipped_sstables = skipped_sstables;
+        range_iterator.comparator = comparator;
+        range_iterator.now = self.clock.now();
+        timer.finish();
         Ok(range_iterator)
     }
-}
-pub struct Merger {
-    entries: Vec<Entry<Key, ValOffset>>,
-}
-impl Merger {
-    fn new() -> Self {
-        Self { entries: Vec::new() }
+
+    /// Returns number of keys within `[start, end]`
+    ///
+    /// If `exact` is true keys are merged from memtables and sstables without
+    /// reading values, leaving out the same entries as [`DataStore::seek`].
+    /// Otherwise the count is estimated from memtables and, for sstables, the
+    /// share of the data file the index maps to the range and the recorded
+    /// entry and tombstone counts. Estimates count every version of a key
+    /// spread over several sstables
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn count_range(&self, start: &[u8], end: &[u8], exact: bool) -> Result<usize, Error> {
+        let comparator = self.config.comparator;
+        let (encoded_start, encoded_end) = (comparator.encode(start), comparator.encode(end));
+        let (start_key, end_key) = (encoded_start.as_ref(), encoded_end.as_ref());
+        let mut timer = self.slow_log.foreground("count");
+        if exact {
+            let (merger, _) = self.merge_keys_in_range(start_key, end_key, &mut timer).await?;
+            timer.finish();
+            return Ok(merger.count());
+        }
+        let phase_start = Instant::now();
+        let mut merger = MergeIterator::new(Suppression::for_scan(None, self.clock.now()));
+        Self::merge_memtables(
+            &mut merger,
+            &self.active_memtable,
+            &self.read_only_memtables,
+            start_key,
+            end_key,
+        );
+        let mut count = merger.count();
+        timer.record(Phase::Memtable, phase_start);
+        let phase_start = Instant::now();
+        for range in self.key_range.range_query_scan(start_key, end_key).await {
+            count += Self::estimate_count_in_table(&range.sst, start_key, end_key).await?;
+        }
+        timer.record(Phase::Index, phase_start);
+        timer.finish();
+        Ok(count)
+    }
+
+    /// Estimates number of live entries of `sst` within `[start_key, end_key]`
+    ///
+    /// Assumes entries are spread evenly over the data file and tombstones over the entries
+    async fn estimate_count_in_table(sst: &Table, start_key: &[u8], end_key: &[u8]) -> Result<usize, Error> {
+        let Some(properties) = sst.summary.as_ref().map(|summary| &summary.properties) else {
+            return Ok(0);
+        };
+        let live = properties.entry_count.saturating_sub(properties.tombstone_count);
+        // sstables written before data size was recorded count as a whole
+        if properties.data_size == 0 {
+            return Ok(live);
+        }
+        let index = Index::new(sst.index_file.path.to_owned(), sst.index_file.file.to_owned());
+        let Some(range_offset) = index.get_block_offset_range(start_key, end_key).await? else {
+            return Ok(0);
+        };
+        let end = (range_offset.end_offset as usize).min(properties.data_size);
+        let covered = end.saturating_sub(range_offset.start_offset as usize);
+        Ok((live * covered).div_ceil(properties.data_size))
+    }
+
+    /// Merges entries within `[start_key, end_key]` of the `active` memtable and
+    /// the `read_only` memtables into `merger`
+    ///
+    /// The active memtable is taken first. A rotation sealing it afterwards
+    /// publishes the same entries to `read_only`, they are merged once more
+    /// but never missed
+    pub(crate) fn merge_memtables(
+        merger: &mut MergeIterator,
+        active: &ActiveMemTable<Key>,
+        read_only: &ImmutableMemTables<Key>,
+        start_key: &[u8],
+        end_key: &[u8],
+    ) {
+        let active = Arc::clone(&active.read().unwrap().entries);
+        merger.merge_range(&active, start_key, end_key);
+        for table in read_only.iter() {
+            merger.merge_range(&table.value().entries, start_key, end_key);
+        }
+    }
+
+    /// Merges keys within `[start_key, end_key]` of memtables and sstables
+    ///
+    /// Returns the merged keys and number of overlapping sstables skipped without being read
+    ///
+    /// Compaction can delete an sstable after its key range was read, the merged
+    /// sstable holding its keys is installed first so the merge is retried once
+    pub(crate) async fn merge_keys_in_range(
+        &self,
+        start_key: &[u8],
+        end_key: &[u8],
+        timer: &mut OpTimer,
+    ) -> Result<(MergeIterator, usize), Error> {
+        match self.merge_keys_in_snapshot(start_key, end_key, timer).await {
+            Err(err) if err.is_file_not_found() => {
+                self.merge_keys_in_snapshot(start_key, end_key, timer).await
+            }
+            res => res,
+        }
+    }
+
+    /// Merges keys within `[start_key, end_key]` of memtables and the sstables
+    /// key ranges currently map to it, see [`DataStore::merge_keys_in_range`]
+    async fn merge_keys_in_snapshot(
+        &self,
+        start_key: &[u8],
+        end_key: &[u8],
+        timer: &mut OpTimer,
+    ) -> Result<(MergeIterator, usize), Error> {
+        let entry_ttl = self.config.enable_ttl.then_some(self.config.entry_ttl);
+        let phase_start = Instant::now();
+        let mut merger = MergeIterator::new(Suppression::for_scan(entry_ttl, self.clock.now()));
+        Self::merge_memtables(
+            &mut merger,
+            &self.active_memtable,
+            &self.read_only_memtables,
+            start_key,
+            end_key,
+        );
+        timer.record(Phase::Memtable, phase_start);
+        // decide before merging sstables so only memtable keys count as resolved
+        let phase_start = Instant::now();
+        let mut skipped_sstables = 0;
+        let mut ssts = Vec::new();
+        for range in self.key_range.range_query_scan(start_key, end_key).await {
+            if merger.is_shadowed(&range, start_key, end_key) {
+                skipped_sstables += 1;
+            } else {
+                ssts.push(range.sst);
+            }
+        }
+        timer.record(Phase::Index, phase_start);
+        // only blocks that can hold keys in range are read
+        let phase_start = Instant::now();
+        for sst in ssts {
+            merger.merge_range(&sst.range(start_key, end_key).await?, start_key, end_key);
+        }
+        timer.record(Phase::Data, phase_start);
+        Ok((merger, skipped_sstables))
     }
 }
@@ -0,0 +1,175 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Phases of an operation timed for slow operation reports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Phase {
+    /// Searching or inserting into memtables
+    Memtable,
+
+    /// Checking bloom filters of SSTables
+    Filter,
+
+    /// Locating blocks with SSTable indexes
+    Index,
+
+    /// Reading SSTable data blocks
+    Data,
+
+    /// Reading or appending to the value log
+    VLog,
+}
+
+impl Phase {
+    const ALL: [Phase; 5] = [
+        Phase::Memtable,
+        Phase::Filter,
+        Phase::Index,
+        Phase::Data,
+        Phase::VLog,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Phase::Memtable => "memtable",
+            Phase::Filter => "filter",
+            Phase::Index => "index",
+            Phase::Data => "data",
+            Phase::VLog => "vlog",
+        }
+    }
+}
+
+/// Durations after which operations are logged as slow, zero disables logging
+#[derive(Clone, Copy, Debug, Default)]
+struct Thresholds {
+    /// Gets, puts and scans
+    foreground: Duration,
+
+    /// Flushes and compactions
+    background: Duration,
+}
+
+/// Thresholds for slow operation logging, shared with flusher and compactor
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SlowLog {
+    inner: Arc<Mutex<Thresholds>>,
+}
+
+impl SlowLog {
+    /// Creates new `SlowLog`
+    pub fn new(foreground: Duration, background: Duration) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Thresholds {
+                foreground,
+                background,
+            })),
+        }
+    }
+
+    /// Sets threshold of gets, puts and scans
+    pub fn set_foreground(&self, threshold: Duration) {
+        self.inner.lock().unwrap().foreground = threshold;
+    }
+
+    /// Sets threshold of flushes and compactions
+    pub fn set_background(&self, threshold: Duration) {
+        self.inner.lock().unwrap().background = threshold;
+    }
+
+    /// Starts timing a get, put or scan
+    pub fn foreground(&self, op: &'static str) -> OpTimer {
+        OpTimer::start(op, self.inner.lock().unwrap().foreground)
+    }
+
+    /// Starts timing a flush or compaction
+    pub fn background(&self, op: &'static str) -> OpTimer {
+        OpTimer::start(op, self.inner.lock().unwrap().background)
+    }
+}
+
+/// Times an operation and its phases
+///
+/// A structured warning is logged on `finish` if the operation took at least
+/// the threshold, time not spent in a timed phase is reported as `other`
+#[derive(Debug)]
+pub(crate) struct OpTimer {
+    op: &'static str,
+    started: Instant,
+    threshold: Duration,
+    phases: [Duration; Phase::ALL.len()],
+}
+
+impl OpTimer {
+    fn start(op: &'static str, threshold: Duration) -> Self {
+        Self {
+            op,
+            started: Instant::now(),
+            threshold,
+            phases: Default::default(),
+        }
+    }
+
+    /// Adds time elapsed since `since` to `phase`
+    pub fn record(&mut self, phase: Phase, since: Instant) {
+        self.phases[phase as usize] += since.elapsed();
+    }
+
+    /// Returns a report of the operation if it was slow
+    fn report(&self, elapsed: Duration) -> Option<String> {
+        if self.threshold.is_zero() || elapsed < self.threshold {
+            return None;
+        }
+        let timed: Duration = self.phases.iter().sum();
+        let mut report = format!("slow operation op={} total_us={}", self.op, elapsed.as_micros());
+        for phase in Phase::ALL {
+            let spent = self.phases[phase as usize];
+            if !spent.is_zero() {
+                report.push_str(&format!(" {}_us={}", phase.name(), spent.as_micros()));
+            }
+        }
+        report.push_str(&format!(
+            " other_us={} threshold_us={}",
+            elapsed.saturating_sub(timed).as_micros(),
+            self.threshold.as_micros()
+        ));
+        Some(report)
+    }
+
+    /// Logs a warning if the operation was slow
+    pub fn finish(self) {
+        if let Some(report) = self.report(self.started.elapsed()) {
+            log::warn!("{}", report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_op_timer_reports_slow_ops_only() {
+        let slow_log = SlowLog::new(Duration::from_millis(10), Duration::ZERO);
+        let mut timer = slow_log.foreground("get");
+        let since = Instant::now() - Duration::from_millis(4);
+        timer.record(Phase::Index, since);
+        assert!(timer.report(Duration::from_millis(5)).is_none());
+
+        let report = timer.report(Duration::from_millis(20)).unwrap();
+        assert!(report.starts_with("slow operation op=get total_us=20000 index_us="));
+        assert!(!report.contains("vlog_us"));
+        assert!(report.ends_with("threshold_us=10000"));
+
+        // zero threshold disables logging
+        let timer = slow_log.background("flush");
+        assert!(timer.report(Duration::from_secs(60)).is_none());
+        slow_log.set_background(Duration::from_secs(1));
+        assert!(slow_log
+            .background("flush")
+            .report(Duration::from_secs(60))
+            .is_some());
+    }
+}
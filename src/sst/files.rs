@@ -0,0 +1,237 @@
+use super::SstId;
+use crate::{
+    consts::{
+        DATA_FILE_NAME, DESCRIPTOR_FILE_NAME, FILTER_FILE_NAME, INDEX_FILE_NAME, SIZE_OF_U32, SIZE_OF_U64,
+        SUMMARY_FILE_NAME,
+    },
+    err::Error::{self, *},
+    types::FileNumber,
+};
+use std::path::{Path, PathBuf};
+use tokio::{fs, io::AsyncWriteExt};
+
+/// Files of an SSTable
+///
+/// SSTables name their files after their id, e.g. `sstable_000001.data`, and
+/// list them in a descriptor written along with them, so discovery never has
+/// to guess which file is which. SSTables written before descriptors existed
+/// have none, their files have fixed names, e.g. `data.db`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct TableFiles {
+    /// Data blocks
+    pub data: PathBuf,
+
+    /// Index of data blocks
+    pub index: PathBuf,
+
+    /// Bloom filter
+    pub filter: PathBuf,
+
+    /// Smallest and biggest key and table properties
+    pub summary: PathBuf,
+
+    /// Descriptor listing the files above, `None` for SSTables written before descriptors
+    pub descriptor: Option<PathBuf>,
+}
+
+impl TableFiles {
+    /// Returns files of SSTable `id` in `dir`, named after the table
+    pub fn named(dir: &Path, id: SstId) -> Self {
+        let file = |kind: &str| dir.join(format!("{}.{}", id, kind));
+        Self {
+            data: file(DATA_FILE_NAME),
+            index: file(INDEX_FILE_NAME),
+            filter: file(FILTER_FILE_NAME),
+            summary: file(SUMMARY_FILE_NAME),
+            descriptor: Some(dir.join(DESCRIPTOR_FILE_NAME)),
+        }
+    }
+
+    /// Returns files of an SSTable in `dir` written before descriptors existed
+    pub fn legacy(dir: &Path) -> Self {
+        let file = |kind: &str| dir.join(format!("{}.db", kind));
+        Self {
+            data: file(DATA_FILE_NAME),
+            index: file(INDEX_FILE_NAME),
+            filter: file(FILTER_FILE_NAME),
+            summary: file(SUMMARY_FILE_NAME),
+            descriptor: None,
+        }
+    }
+
+    /// Returns the same files once their directory is moved to `dir`
+    pub fn moved_to(&self, dir: &Path) -> Self {
+        let moved = |path: &Path| dir.join(path.file_name().unwrap_or_default());
+        Self {
+            data: moved(&self.data),
+            index: moved(&self.index),
+            filter: moved(&self.filter),
+            summary: moved(&self.summary),
+            descriptor: self.descriptor.as_deref().map(moved),
+        }
+    }
+
+    /// Returns paths of every file, descriptor last
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![
+            self.data.to_owned(),
+            self.index.to_owned(),
+            self.filter.to_owned(),
+            self.summary.to_owned(),
+        ];
+        paths.extend(self.descriptor.to_owned());
+        paths
+    }
+
+    /// Returns id and files of the SSTable in `dir`
+    ///
+    /// Files are read from the descriptor, or take fixed names if the SSTable
+    /// has none, and every one of them has to exist
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the descriptor is malformed, belongs to another
+    /// SSTable, a file is missing or there is an IO error
+    pub async fn discover(dir: &Path) -> Result<(SstId, Self), Error> {
+        let invalid = || InvalidSSTableDirectory {
+            input_string: dir.to_string_lossy().to_string(),
+        };
+        let id = SstId::of(dir).ok_or_else(invalid)?;
+        let descriptor = dir.join(DESCRIPTOR_FILE_NAME);
+        let files = match fs::read(&descriptor).await {
+            Ok(bytes) => {
+                let (number, names) = Self::deserialize(&bytes).ok_or_else(invalid)?;
+                if number != id.file_number() {
+                    return Err(invalid());
+                }
+                let [data, index, filter, summary] = names.map(|name| dir.join(name));
+                Self {
+                    data,
+                    index,
+                    filter,
+                    summary,
+                    descriptor: Some(descriptor),
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::legacy(dir),
+            Err(error) => {
+                return Err(FileRead {
+                    path: descriptor,
+                    error,
+                })
+            }
+        };
+        for path in files.paths() {
+            if !fs::metadata(&path).await.is_ok_and(|metadata| metadata.is_file()) {
+                log::error!(
+                    "SSTable {:?} is missing {:?}",
+                    dir,
+                    path.file_name().unwrap_or_default()
+                );
+                return Err(invalid());
+            }
+        }
+        Ok((id, files))
+    }
+
+    /// Writes descriptor of SSTable `id`, nothing for SSTables without one
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn write_descriptor(&self, id: SstId) -> Result<(), Error> {
+        let Some(path) = self.descriptor.as_ref() else {
+            return Ok(());
+        };
+        let mut file = fs::File::create(path).await.map_err(|error| FileCreation {
+            path: path.to_owned(),
+            error,
+        })?;
+        file.write_all(&self.serialize(id))
+            .await
+            .map_err(|error| FileWrite {
+                path: path.to_owned(),
+                error,
+            })?;
+        file.sync_all().await.map_err(FileSync)
+    }
+
+    /// Serializes file number of SSTable `id` followed by length prefixed names
+    /// of data, index, filter and summary files
+    fn serialize(&self, id: SstId) -> Vec<u8> {
+        let mut serialized_data = id.file_number().to_le_bytes().to_vec();
+        for path in [&self.data, &self.index, &self.filter, &self.summary] {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            serialized_data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            serialized_data.extend_from_slice(name.as_bytes());
+        }
+        serialized_data
+    }
+
+    /// Parses bytes written by `serialize`, returns `None` if they are malformed
+    fn deserialize(bytes: &[u8]) -> Option<(FileNumber, [String; 4])> {
+        let number = FileNumber::from_le_bytes(bytes.get(..SIZE_OF_U64)?.try_into().ok()?);
+        let mut offset = SIZE_OF_U64;
+        let mut names: [String; 4] = Default::default();
+        for name in names.iter_mut() {
+            let len = u32::from_le_bytes(bytes.get(offset..offset + SIZE_OF_U32)?.try_into().ok()?) as usize;
+            offset += SIZE_OF_U32;
+            *name = std::str::from_utf8(bytes.get(offset..offset + len)?)
+                .ok()?
+                .to_string();
+            // names are plain file names, never paths out of the SSTable directory
+            if Path::new(name.as_str()).file_name() != Some(name.as_ref()) {
+                return None;
+            }
+            offset += len;
+        }
+        (offset == bytes.len()).then_some((number, names))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sst::Table;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_table_files_discovery() {
+        let root = tempdir().unwrap();
+        let dir = root.path().join(Table::dir_name(7));
+        fs::create_dir_all(&dir).await.unwrap();
+        let id = SstId::of(&dir).unwrap();
+        let files = TableFiles::named(&dir, id);
+        assert_eq!(files.data, dir.join("sstable_000007.data"));
+        files.write_descriptor(id).await.unwrap();
+
+        // every listed file has to exist
+        assert!(TableFiles::discover(&dir).await.is_err());
+        for path in files.paths() {
+            if !path.exists() {
+                fs::write(&path, []).await.unwrap();
+            }
+        }
+        assert_eq!(TableFiles::discover(&dir).await.unwrap(), (id, files.to_owned()));
+
+        // a descriptor copied from another table is rejected
+        let other = root.path().join(Table::dir_name(8));
+        fs::create_dir_all(&other).await.unwrap();
+        for path in files.paths() {
+            fs::copy(&path, other.join(path.file_name().unwrap()))
+                .await
+                .unwrap();
+        }
+        assert!(TableFiles::discover(&other).await.is_err());
+
+        // tables without descriptor use fixed names
+        let legacy = root.path().join(Table::dir_name(9));
+        fs::create_dir_all(&legacy).await.unwrap();
+        for path in TableFiles::legacy(&legacy).paths() {
+            fs::write(&path, []).await.unwrap();
+        }
+        let (_, discovered) = TableFiles::discover(&legacy).await.unwrap();
+        assert_eq!(discovered.data, legacy.join("data.db"));
+        assert!(discovered.descriptor.is_none());
+    }
+}
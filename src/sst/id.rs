@@ -0,0 +1,38 @@
+use crate::types::FileNumber;
+use std::{fmt, path::Path};
+
+use super::Table;
+
+/// Stable identity of an SSTable, the file number its directory is named after
+///
+/// Unlike the directory path it stays the same when a table is installed
+/// from its temporary directory or the store is moved, so it is what key
+/// ranges, filters and buckets refer to tables by
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SstId(FileNumber);
+
+impl SstId {
+    /// Creates new `SstId` of the SSTable numbered `number`
+    #[cfg(test)]
+    pub fn new(number: FileNumber) -> Self {
+        Self(number)
+    }
+
+    /// Returns id of the SSTable in `dir`, temporary directories included
+    ///
+    /// Returns `None` if the directory name does not hold a file number
+    pub fn of<P: AsRef<Path>>(dir: P) -> Option<Self> {
+        Table::file_number_of(dir).map(Self)
+    }
+
+    /// Returns file number of the SSTable
+    pub fn file_number(&self) -> FileNumber {
+        self.0
+    }
+}
+
+impl fmt::Display for SstId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", Table::dir_name(self.0))
+    }
+}
@@ -1,5 +1,11 @@
+mod files;
+mod id;
 mod table;
+pub(crate) use files::TableFiles;
+pub(crate) use id::SstId;
 #[cfg(test)]
 pub use table::DataFile;
+pub(crate) use table::Hotness;
 pub(crate) use table::Summary;
 pub(crate) use table::Table;
+pub(crate) use table::TableProperties;
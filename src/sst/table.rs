@@ -34,29 +34,36 @@
 //! - The `Block` stores entries until it is 4KB in size and then writes to data file
 //! - TODO: In the future we will introduce Snappy Compression to reduce the size on the disk and also introduce checksum to ensure the data has not been corrupted
 
+use super::{SstId, TableFiles};
 use crate::{
     block::Block,
     bucket::InsertableToBucket,
-    consts::{
-        DATA_FILE_NAME, INDEX_FILE_NAME, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, SIZE_OF_USIZE,
-        SUMMARY_FILE_NAME,
-    },
+    cache::IndexCache,
+    consts::{SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, SIZE_OF_USIZE, SSTABLE_DIRECTORY_PREFIX},
+    encryption::{FileCipher, TableCipher},
     err::Error,
     filter::BloomFilter,
-    fs::{DataFileNode, DataFs, FileAsync, FileNode, IndexFileNode, IndexFs, SummaryFileNode, SummaryFs},
-    index::{Index, IndexFile, RangeOffset},
+    fs::{
+        DataFileNode, DataFs, FileAsync, FileNode, FileType, IndexFileNode, IndexFs, SummaryFileNode,
+        SummaryFs,
+    },
+    index::{BlockOffset, Index, IndexFile, RangeOffset},
     key_range::{BiggestKey, SmallestKey},
     memtable::{Entry, SkipMapValue},
-    types::{ByteSerializedEntry, CreatedAt, IsTombStone, Key, SkipMapEntries, ValOffset},
+    types::{ByteSerializedEntry, CreatedAt, FileNumber, IsTombStone, Key, SkipMapEntries, ValOffset},
     util,
 };
 use chrono::Utc;
 use crossbeam_skiplist::SkipMap;
 use std::{
     path::{Path, PathBuf},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     time::SystemTime,
 };
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use Error::*;
 
 /// DataFile
@@ -76,14 +83,42 @@ impl<F: DataFs> DataFile<F> {
     }
 }
 
+/// Sampled read hits of an SSTable, shared by every clone of the table
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Hotness(Arc<AtomicU64>);
+
+impl Hotness {
+    /// Creates new `Hotness` starting at `hits`
+    #[cfg(test)]
+    pub fn new(hits: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(hits)))
+    }
+
+    /// Returns sampled read hits
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Adds `hits` sampled read hits
+    pub fn add(&self, hits: u64) {
+        self.0.fetch_add(hits, Ordering::Relaxed);
+    }
+}
+
 /// An SSTable
 #[derive(Debug, Clone)]
 pub struct Table {
+    /// Stable identity of the sstable, see [`SstId`]
+    pub(crate) id: SstId,
+
     /// Directory sstable files are stored at
     pub(crate) dir: PathBuf,
 
-    /// How often is this sstable used?
-    pub(crate) hotness: u64,
+    /// Paths of the sstable files, see [`TableFiles`]
+    pub(crate) files: TableFiles,
+
+    /// How often is this sstable read? Sampled read hits, not persisted
+    pub(crate) hotness: Hotness,
 
     /// Size of the sstable
     pub(crate) size: usize,
@@ -107,6 +142,12 @@ pub struct Table {
 
     /// Stores the summary including biggest and smallest key
     pub(crate) summary: Option<Summary>,
+
+    /// Caches index lookups of hot keys
+    pub(crate) index_cache: IndexCache,
+
+    /// Cipher of the sstable files, `None` if they are not encrypted
+    pub(crate) cipher: Option<TableCipher>,
 }
 
 /// Defines trait to make `Table` insertable to bucket
@@ -125,55 +166,100 @@ impl InsertableToBucket for Table {
 }
 
 impl Table {
-    /// Creates a new `Table`
-    pub async fn new<P: AsRef<Path> + Send + Sync>(dir: P) -> Result<Table, Error> {
-        let (data_file_path, index_file_path, created_at) = Table::generate_file_path(dir.as_ref()).await?;
-        let data_file = DataFileNode::new(data_file_path.to_owned(), crate::fs::FileType::Data)
+    /// Creates a new `Table` whose files are encrypted with `cipher`, if any
+    pub(crate) async fn new<P: AsRef<Path> + Send + Sync>(
+        dir: P,
+        cipher: Option<TableCipher>,
+    ) -> Result<Table, Error> {
+        let id = SstId::of(dir.as_ref()).ok_or_else(|| InvalidSSTableDirectory {
+            input_string: dir.as_ref().to_string_lossy().to_string(),
+        })?;
+        let (files, created_at) = Table::generate_file_path(dir.as_ref(), id).await?;
+        let data_file = DataFileNode::new(files.data.to_owned(), crate::fs::FileType::Data)
             .await
             .unwrap();
-        let index_file = IndexFileNode::new(index_file_path.to_owned(), crate::fs::FileType::Index)
+        let index_file = IndexFileNode::new(files.index.to_owned(), crate::fs::FileType::Index)
             .await
             .unwrap();
+        let file_cipher = |file_type| {
+            cipher
+                .as_ref()
+                .map(|cipher: &TableCipher| cipher.file(&file_type))
+        };
+        data_file.node.set_cipher(file_cipher(FileType::Data)).await?;
+        index_file.node.set_cipher(file_cipher(FileType::Index)).await?;
 
         Ok(Self {
+            id,
             dir: dir.as_ref().to_path_buf(),
             hotness: Default::default(),
-            index_file: IndexFile::new(index_file_path, index_file),
-            data_file: DataFile::new(data_file_path, data_file),
+            index_file: IndexFile::new(files.index.to_owned(), index_file),
+            data_file: DataFile::new(files.data.to_owned(), data_file),
+            files,
             created_at,
             entries: Arc::new(SkipMap::new()),
             size: Default::default(),
             filter: None,
             summary: None,
+            index_cache: IndexCache::default(),
+            cipher,
         })
     }
-    pub fn increase_hotness(&mut self) {
-        self.hotness += 1;
+
+    /// Adds `hits` sampled read hits to `Table` `hotness`
+    pub fn increase_hotness(&self, hits: u64) {
+        self.hotness.add(hits);
+    }
+
+    /// Returns cipher of the file of `file_type`, `None` if `Table` is not encrypted
+    pub(crate) fn file_cipher(&self, file_type: &FileType) -> Option<FileCipher> {
+        self.cipher.as_ref().map(|cipher| cipher.file(file_type))
     }
+
     /// Returns `Table` `data_file` path
     pub fn get_data_file_path(&self) -> PathBuf {
         self.data_file.path.clone()
     }
 
+    /// Returns stable identity of `Table`
+    pub(crate) fn id(&self) -> SstId {
+        self.id
+    }
+
     /// Returns `Table` `hotness`
     pub fn get_hotness(&self) -> u64 {
-        self.hotness
+        self.hotness.get()
+    }
+
+    /// Returns directory name of the SSTable numbered `number`
+    pub(crate) fn dir_name(number: FileNumber) -> String {
+        format!("{}_{:06}", SSTABLE_DIRECTORY_PREFIX, number)
+    }
+
+    /// Parses file number from SSTable directory `dir`, temporary directories included
+    ///
+    /// Directories named after their creation timestamp by older versions parse
+    /// as well, their timestamp is taken as file number
+    pub(crate) fn file_number_of<P: AsRef<Path>>(dir: P) -> Option<FileNumber> {
+        dir.as_ref()
+            .file_stem()?
+            .to_str()
+            .and_then(|name| util::strip_prefix_ignore_case(name, SSTABLE_DIRECTORY_PREFIX))?
+            .strip_prefix('_')?
+            .parse()
+            .ok()
     }
 
     /// Creates table directory
     ///
-    /// Returns data and index file name
-    pub async fn generate_file_path<P: AsRef<Path> + Send + Sync>(
+    /// Returns paths of the files of table `id`
+    pub(crate) async fn generate_file_path<P: AsRef<Path> + Send + Sync>(
         dir: P,
-    ) -> Result<(PathBuf, PathBuf, CreatedAt), Error> {
+        id: SstId,
+    ) -> Result<(TableFiles, CreatedAt), Error> {
         let created_at = Utc::now();
         FileNode::create_dir_all(dir.as_ref()).await?;
-        let data_file_name = format!("{}.db", DATA_FILE_NAME);
-        let index_file_name = format!("{}.db", INDEX_FILE_NAME);
-
-        let data_file_path = dir.as_ref().join(data_file_name);
-        let index_file_path = dir.as_ref().join(index_file_name);
-        Ok((data_file_path, index_file_path, created_at))
+        Ok((TableFiles::named(dir.as_ref(), id), created_at))
     }
 
     /// Returns a key from a block in sstable data file
@@ -192,6 +278,26 @@ impl Table {
             .await
     }
 
+    /// Returns offset of the block that can hold `searched_key`
+    ///
+    /// Lookups are cached so repeated reads of hot keys skip the index file
+    ///
+    /// # Errors
+    ///
+    /// Returns IO error in case it occurs
+    pub(crate) async fn get_block_offset<K: AsRef<[u8]>>(
+        &self,
+        searched_key: K,
+    ) -> Result<Option<BlockOffset>, Error> {
+        if let Some(offset) = self.index_cache.get(searched_key.as_ref()) {
+            return Ok(offset);
+        }
+        let index = Index::new(self.index_file.path.to_owned(), self.index_file.file.to_owned());
+        let offset = index.get(searched_key.as_ref()).await?;
+        self.index_cache.insert(searched_key.as_ref(), offset);
+        Ok(offset)
+    }
+
     /// Build  `entries` from sstable data file
     ///
     /// # Errors
@@ -205,32 +311,42 @@ impl Table {
     }
 
     /// Returns new `Table` using the supplied parameters
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
     pub(crate) async fn build_from<P: AsRef<Path> + Send + Sync + Clone>(
+        id: SstId,
         dir: P,
-        data_file_path: P,
-        index_file_path: P,
-    ) -> Table {
+        files: TableFiles,
+        cipher: Option<TableCipher>,
+    ) -> Result<Table, Error> {
         let mut table = Table {
+            id,
             dir: dir.as_ref().to_path_buf(),
-            hotness: 1,
+            hotness: Hotness::default(),
             created_at: Utc::now(),
             data_file: DataFile {
-                file: DataFileNode::new(data_file_path.to_owned(), crate::fs::FileType::Data)
+                file: DataFileNode::new(files.data.to_owned(), crate::fs::FileType::Data)
                     .await
                     .unwrap(),
-                path: data_file_path.as_ref().to_path_buf(),
+                path: files.data.to_owned(),
             },
             index_file: IndexFile {
-                file: IndexFileNode::new(index_file_path.to_owned(), crate::fs::FileType::Index)
+                file: IndexFileNode::new(files.index.to_owned(), crate::fs::FileType::Index)
                     .await
                     .unwrap(),
-                path: index_file_path.as_ref().to_path_buf(),
+                path: files.index.to_owned(),
             },
+            files,
             size: Default::default(),
             entries: Arc::new(SkipMap::new()),
             filter: None,
             summary: None,
+            index_cache: IndexCache::default(),
+            cipher,
         };
+        table.set_file_ciphers().await?;
         table.size = table.data_file.file.node.size().await;
         let modified_time = table
             .data_file
@@ -244,18 +360,37 @@ impl Table {
         let epoch = SystemTime::UNIX_EPOCH;
         let elapsed_nanos = modified_time.duration_since(epoch).unwrap().as_nanos() as u64;
         table.created_at = util::milliseconds_to_datetime(elapsed_nanos / 1_000_000);
-        table
+        Ok(table)
+    }
+
+    /// Encrypts and decrypts data and index files with the ciphers of `Table`
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    async fn set_file_ciphers(&self) -> Result<(), Error> {
+        self.data_file
+            .file
+            .node
+            .set_cipher(self.file_cipher(&FileType::Data))
+            .await?;
+        self.index_file
+            .file
+            .node
+            .set_cipher(self.file_cipher(&FileType::Index))
+            .await
     }
 
     /// Writes SSTable files to disk
     ///
     /// After successful write, the summary and bloom filter
-    /// for the table is set and stored in memory
+    /// for the table is set and stored in memory. Filter bits are written
+    /// with the filter metadata if `persist_filter_bits` is set
     ///
     /// Errors
     ///
     /// Returns error in case of IO error
-    pub(crate) async fn write_to_file(&mut self) -> Result<(), Error> {
+    pub(crate) async fn write_to_file(&mut self, persist_filter_bits: bool) -> Result<(), Error> {
         if self.filter.is_none() {
             return Err(FilterNotProvidedForFlush);
         }
@@ -265,27 +400,33 @@ impl Table {
         let index_file = &self.index_file;
         let mut blocks: Vec<Block> = Vec::new();
         let mut index = Index::new(self.index_file.path.clone(), index_file.file.clone());
-        let mut summary = Summary::new(self.dir.to_owned());
+        let mut summary = Summary::with_path(&self.files.summary);
+        summary.cipher = self.file_cipher(&FileType::Summary);
 
         let smallest_entry = self.entries.front();
         let biggest_entry = self.entries.back();
 
         summary.smallest_key = smallest_entry.unwrap().key().to_vec();
         summary.biggest_key = biggest_entry.unwrap().key().to_vec();
-
-        // write summary to disk
-        summary.write_to_file().await?;
-        self.summary = Some(summary);
+        summary.properties = TableProperties {
+            entry_count: self.entries.len(),
+            tombstone_count: self.entries.iter().filter(|e| e.value().is_tombstone).count(),
+            data_size: 0,
+            oldest_entry: self.entries.iter().map(|e| e.value().created_at).min(),
+            newest_entry: self.entries.iter().map(|e| e.value().created_at).max(),
+            data_checksum: None,
+        };
 
         // write filter to disk
-        self.filter.as_mut().unwrap().write(self.dir.to_owned()).await?;
-        self.filter
-            .as_mut()
-            .unwrap()
-            .set_sstable_path(&self.data_file.path);
+        let filter_cipher = self.file_cipher(&FileType::Filter);
+        let filter = self.filter.as_mut().unwrap();
+        filter.cipher = filter_cipher;
+        filter.write(&self.files.filter, persist_filter_bits).await?;
+        self.filter.as_mut().unwrap().set_sst_id(self.id);
 
         // write data blocks
         let mut current_block = Block::new();
+        let mut checksum = crc32fast::Hasher::new();
         if self.size > 0 {
             self.reset_size();
         }
@@ -314,14 +455,86 @@ impl Table {
         }
 
         for block in blocks.iter() {
-            self.write_block(block, &mut index).await?;
+            self.write_block(block, &mut index, &mut checksum).await?;
         }
 
         // Incase we have some entries left in current block, write them to disk
         if !current_block.entries.is_empty() {
-            self.write_block(&current_block, &mut index).await?;
+            self.write_block(&current_block, &mut index, &mut checksum)
+                .await?;
         }
         index.write_to_file().await?;
+
+        // write summary to disk once the data file size is known
+        summary.properties.data_size = self.size;
+        summary.properties.data_checksum = Some(checksum.finalize());
+        summary.write_to_file().await?;
+        self.summary = Some(summary);
+        // written last, every file it lists is complete
+        self.files.write_descriptor(self.id).await
+    }
+
+    /// Moves SSTable written to a temporary directory to `dir`
+    ///
+    /// Every file is synced before the directory is renamed, so a crash leaves
+    /// either the temporary directory, which recovery ignores, or a complete SSTable
+    ///
+    /// Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn install<P: AsRef<Path> + Send + Sync>(&mut self, dir: P) -> Result<(), Error> {
+        self.data_file.file.node.sync_all().await?;
+        self.index_file.file.node.sync_all().await?;
+        for (path, file_type) in [
+            (&self.files.filter, crate::fs::FileType::Filter),
+            (&self.files.summary, crate::fs::FileType::Summary),
+        ] {
+            FileNode::new(path.to_owned(), file_type)
+                .await?
+                .sync_all()
+                .await?;
+        }
+        // the directory cannot be renamed on Windows while its files are open
+        self.data_file.file.node.close().await;
+        self.index_file.file.node.close().await;
+        tokio::fs::rename(&self.dir, dir.as_ref())
+            .await
+            .map_err(|error| FileRename {
+                path: dir.as_ref().to_path_buf(),
+                error,
+            })?;
+        // persist the rename itself
+        #[cfg(unix)]
+        if let Some(parent) = dir.as_ref().parent() {
+            tokio::fs::File::open(parent)
+                .await
+                .map_err(|error| FileOpen {
+                    path: parent.to_path_buf(),
+                    error,
+                })?
+                .sync_all()
+                .await
+                .map_err(FileSync)?;
+        }
+
+        self.files = self.files.moved_to(dir.as_ref());
+        self.data_file = DataFile::new(
+            self.files.data.to_owned(),
+            DataFileNode::new(self.files.data.to_owned(), crate::fs::FileType::Data).await?,
+        );
+        self.index_file = IndexFile::new(
+            self.files.index.to_owned(),
+            IndexFileNode::new(self.files.index.to_owned(), crate::fs::FileType::Index).await?,
+        );
+        self.set_file_ciphers().await?;
+        self.dir = dir.as_ref().to_path_buf();
+        if let Some(summary) = self.summary.as_mut() {
+            summary.path = self.files.summary.to_owned();
+        }
+        if let Some(filter) = self.filter.as_mut() {
+            filter.file_path = Some(self.files.filter.to_owned());
+            filter.set_sst_id(self.id);
+        }
         Ok(())
     }
 
@@ -330,23 +543,116 @@ impl Table {
     /// Errors
     ///
     /// Returns error in case of IO error
-    async fn write_block(&mut self, block: &Block, table_index: &mut Index) -> Result<(), Error> {
+    async fn write_block(
+        &mut self,
+        block: &Block,
+        table_index: &mut Index,
+        checksum: &mut crc32fast::Hasher,
+    ) -> Result<(), Error> {
         let offset = self.size;
         let last_entry = block.get_last_entry();
         table_index.insert(last_entry.key_prefix, last_entry.key, offset as u32);
-        let bytes_written = block.write_to_file(self.data_file.file.node.clone()).await?;
+        let bytes_written = block
+            .write_to_file(self.data_file.file.node.clone(), checksum)
+            .await?;
         self.size += bytes_written;
         Ok(())
     }
 
-    /// Retreives entries within a specific block range
+    /// Retreives entries of the blocks that can hold keys within `[start, end]`
+    ///
+    /// Only blocks located through the index are read, entries of those blocks
+    /// outside the range are returned too
     ///
     /// Errors
     ///
     /// Returns error in case of IO error
-    #[allow(dead_code)]
-    pub(crate) async fn range(&self, range_offset: RangeOffset) -> Result<Vec<Entry<Key, usize>>, Error> {
-        self.data_file.file.load_entries_within_range(range_offset).await
+    pub(crate) async fn range(&self, start: &[u8], end: &[u8]) -> Result<SkipMapEntries<Key>, Error> {
+        let entries = Arc::new(SkipMap::new());
+        let index = Index::new(self.index_file.path.to_owned(), self.index_file.file.to_owned());
+        let Some(range_offset) = index.get_block_offset_range(start, end).await? else {
+            return Ok(entries);
+        };
+        for e in self
+            .data_file
+            .file
+            .load_entries_within_range(range_offset)
+            .await?
+        {
+            entries.insert(
+                e.key,
+                SkipMapValue::new(e.val_offset, e.created_at, e.is_tombstone),
+            );
+        }
+        Ok(entries)
+    }
+
+    /// Reads data file blocks that can hold keys within `[start, end]` so the
+    /// OS caches them
+    ///
+    /// Returns bytes read
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error
+    pub(crate) async fn read_blocks(&self, start: &[u8], end: &[u8]) -> Result<usize, Error> {
+        let index = Index::new(self.index_file.path.to_owned(), self.index_file.file.to_owned());
+        let Some(range_offset) = index.get_block_offset_range(start, end).await? else {
+            return Ok(0);
+        };
+        let path = &self.data_file.path;
+        let mut file = tokio::fs::File::open(path).await.map_err(|error| FileOpen {
+            path: path.to_owned(),
+            error,
+        })?;
+        file.seek(std::io::SeekFrom::Start(range_offset.start_offset as u64))
+            .await
+            .map_err(FileSeek)?;
+        let len = range_offset.end_offset.saturating_sub(range_offset.start_offset);
+        let mut blocks = Vec::new();
+        file.take(len as u64)
+            .read_to_end(&mut blocks)
+            .await
+            .map_err(|error| FileRead {
+                path: path.to_owned(),
+                error,
+            })
+    }
+
+    /// Reads data file of `Table` back and checks it against its summary, the
+    /// data must match the recorded size and checksum, if any, and keys must be
+    /// strictly ascending
+    ///
+    /// Returns entries in the order they were written
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case of IO error or if a check fails
+    pub(crate) async fn read_verified(&self) -> Result<Vec<Entry<Key, ValOffset>>, Error> {
+        let path = &self.data_file.path;
+        let mut data = tokio::fs::read(path).await.map_err(|error| FileRead {
+            path: path.to_owned(),
+            error,
+        })?;
+        if let Some(cipher) = self.file_cipher(&FileType::Data) {
+            cipher.apply(0, &mut data);
+        }
+        if let Some(properties) = self.properties() {
+            if let Some(checksum) = properties.data_checksum {
+                if data.len() != properties.data_size || crc32fast::hash(&data) != checksum {
+                    return Err(SSTableChecksumMismatch(self.dir.to_owned()));
+                }
+            }
+        }
+        let entries = self
+            .data_file
+            .file
+            .load_entries_within_range(RangeOffset::new(0, u32::MAX))
+            .await?;
+        if entries.windows(2).any(|pair| pair[0].key >= pair[1].key) {
+            return Err(SSTableKeysOutOfOrder(self.dir.to_owned()));
+        }
+        Ok(entries)
     }
 
     pub(crate) fn reset_size(&mut self) {
@@ -358,6 +664,28 @@ impl Table {
         self.size
     }
 
+    /// Returns timestamp of the newest entry in `Table` if its summary recorded one
+    pub(crate) fn newest_entry(&self) -> Option<CreatedAt> {
+        self.summary.as_ref().and_then(|s| s.properties.newest_entry)
+    }
+
+    /// Returns properties recorded when `Table` was written
+    pub(crate) fn properties(&self) -> Option<&TableProperties> {
+        self.summary.as_ref().map(|s| &s.properties)
+    }
+
+    /// Sorts tables by their newest entry, most recent first
+    ///
+    /// Tables whose newest entry is unknown could hold any entry so they come first
+    pub(crate) fn sort_newest_first(tables: &mut [Table]) {
+        tables.sort_by(|a, b| match (a.newest_entry(), b.newest_entry()) {
+            (None, None) => std::cmp::Ordering::Equal,
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (Some(a), Some(b)) => b.cmp(&a),
+        });
+    }
+
     /// Set `entries` field in `Table`
     pub(crate) fn set_entries(&mut self, entries: Arc<SkipMap<Key, SkipMapValue<ValOffset>>>) {
         self.entries = entries;
@@ -385,17 +713,69 @@ pub struct Summary {
 
     /// Biggest key in `Table`
     pub biggest_key: BiggestKey,
+
+    /// Statistics recorded when `Table` was written
+    pub properties: TableProperties,
+
+    /// Cipher of the summary file, `None` if it is not encrypted
+    pub(crate) cipher: Option<FileCipher>,
+}
+
+/// Statistics of an SSTable recorded at flush or compaction, stored after the
+/// keys in the summary file
+///
+/// Fields were added over time, SSTables written before a field existed
+/// report 0 or `None` for it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TableProperties {
+    /// Number of entries in `Table`
+    pub entry_count: usize,
+
+    /// Number of tombstones in `Table`
+    pub tombstone_count: usize,
+
+    /// Bytes written to the data file
+    pub data_size: usize,
+
+    /// Timestamp of the oldest entry in `Table`
+    pub oldest_entry: Option<CreatedAt>,
+
+    /// Timestamp of the newest entry in `Table`
+    pub newest_entry: Option<CreatedAt>,
+
+    /// CRC32 of the data file, checked by the scrubber
+    pub data_checksum: Option<u32>,
 }
 
 impl Summary {
-    /// Create new `Summary`
+    /// Create new `Summary` of an SSTable in `path` written before descriptors, see [`TableFiles`]
+    #[cfg(test)]
     pub fn new<P: AsRef<Path> + Send + Sync>(path: P) -> Self {
-        let file_path = path.as_ref().join(format!("{}.db", SUMMARY_FILE_NAME));
+        Self::with_path(
+            path.as_ref()
+                .join(format!("{}.db", crate::consts::SUMMARY_FILE_NAME)),
+        )
+    }
+
+    /// Create new `Summary` stored at `file_path`
+    pub fn with_path<P: AsRef<Path>>(file_path: P) -> Self {
         Self {
-            path: file_path,
+            path: file_path.as_ref().to_path_buf(),
             biggest_key: vec![],
             smallest_key: vec![],
+            properties: TableProperties::default(),
+            cipher: None,
+        }
+    }
+
+    /// Returns share of entries in `Table` that are tombstones
+    ///
+    /// Tables written before counts were recorded report 0.0
+    pub fn tombstone_ratio(&self) -> f64 {
+        if self.properties.entry_count == 0 {
+            return 0.0;
         }
+        self.properties.tombstone_count as f64 / self.properties.entry_count as f64
     }
 
     /// Writes `Summary` to file
@@ -407,6 +787,7 @@ impl Summary {
         let file = SummaryFileNode::new(self.path.to_owned(), crate::fs::FileType::Summary)
             .await
             .unwrap();
+        file.node.set_cipher(self.cipher.to_owned()).await?;
         let serialized_data = self.serialize();
         file.node.write_all(&serialized_data).await?;
         Ok(())
@@ -418,15 +799,26 @@ impl Summary {
     ///
     /// Returns IO error in case it occurs
     pub async fn recover(&mut self) -> Result<(), Error> {
-        let (smallest_key, biggest_key) = SummaryFileNode::recover(self.path.to_owned()).await?;
+        let (smallest_key, biggest_key, properties) =
+            SummaryFileNode::recover(self.path.to_owned(), self.cipher.to_owned()).await?;
         self.smallest_key = smallest_key;
         self.biggest_key = biggest_key;
+        self.properties = properties;
         Ok(())
     }
 
     /// Serializes `Summary` to byte vector
     pub(crate) fn serialize(&self) -> ByteSerializedEntry {
-        let entry_len = SIZE_OF_U32 + SIZE_OF_U32 + self.biggest_key.len() + self.smallest_key.len();
+        let entry_len = SIZE_OF_U32
+            + SIZE_OF_U32
+            + self.biggest_key.len()
+            + self.smallest_key.len()
+            + SIZE_OF_U32
+            + SIZE_OF_U32
+            + SIZE_OF_U64
+            + SIZE_OF_U64
+            + SIZE_OF_U64
+            + SIZE_OF_U32;
         let mut serialized_data = Vec::with_capacity(entry_len);
 
         serialized_data.extend_from_slice(&(self.smallest_key.len() as u32).to_le_bytes());
@@ -437,6 +829,25 @@ impl Summary {
 
         serialized_data.extend_from_slice(&self.biggest_key);
 
+        // properties are appended in the order they were introduced so older
+        // summaries are a prefix of newer ones
+        let properties = &self.properties;
+        serialized_data.extend_from_slice(&(properties.entry_count as u32).to_le_bytes());
+
+        serialized_data.extend_from_slice(&(properties.tombstone_count as u32).to_le_bytes());
+
+        // 0 is read back as a missing timestamp
+        let newest_entry = properties.newest_entry.map_or(0, util::datetime_to_timestamp);
+        serialized_data.extend_from_slice(&newest_entry.to_le_bytes());
+
+        serialized_data.extend_from_slice(&(properties.data_size as u64).to_le_bytes());
+
+        let oldest_entry = properties.oldest_entry.map_or(0, util::datetime_to_timestamp);
+        serialized_data.extend_from_slice(&oldest_entry.to_le_bytes());
+
+        // 0 is read back as a missing checksum
+        serialized_data.extend_from_slice(&properties.data_checksum.unwrap_or(0).to_le_bytes());
+
         serialized_data
     }
 }
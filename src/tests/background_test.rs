@@ -0,0 +1,441 @@
+#[cfg(test)]
+mod tests {
+    use crate::consts::{SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, VLOG_SEGMENT_SIZE};
+    use crate::db::{
+        BackgroundTask, DataStore, HealthState, MockClock, ScrubAction, TaskOutcome, WriteBatch,
+    };
+    use crate::err::Error;
+    use crate::gc::garbage_collector::GC;
+    use crate::memtable::MemTable;
+    use crate::tests::fixture::StoreDir;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn datastore_lists_live_files() {
+        let dir = StoreDir::new("store_test_live_files");
+        let mut store = dir.open().await;
+        for table in 0..2 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{}_{:02}", table, i), "value")
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+
+        let live = store.live_files().await.unwrap();
+        assert_eq!(live.sstables.len(), 2);
+        for (table, sst) in live.sstables.iter().enumerate() {
+            // data, index, filter, summary and descriptor
+            assert_eq!(sst.files.len(), 5);
+            assert!(sst.files.iter().all(|f| f.starts_with(&sst.dir) && f.exists()));
+            assert!(sst.size > 0);
+            // the first table also holds the value log head and tail entries
+            assert!(sst.entry_count >= 20);
+            assert!(sst.smallest_key <= format!("key_{}_00", table).into_bytes());
+            assert!(sst.biggest_key >= format!("key_{}_19", table).into_bytes());
+        }
+        assert_eq!(live.vlog_segments.len(), 1);
+        let vlog = &live.vlog_segments[0];
+        assert!(vlog.path.exists());
+        assert_eq!(vlog.size, store.val_log.read().await.size);
+        assert_eq!(vlog.head_offset, store.val_log.read().await.head_offset);
+
+        // filters of recovered sstables are not loaded yet
+        drop(store);
+        let store = dir.open().await;
+        let recovered = store.live_files().await.unwrap();
+        assert_eq!(recovered.sstables.len(), 2);
+        for (sst, recovered) in live.sstables.iter().zip(recovered.sstables.iter()) {
+            assert_eq!(sst.dir, recovered.dir);
+            assert_eq!(sst.entry_count, recovered.entry_count);
+            assert_eq!(sst.oldest_entry, recovered.oldest_entry);
+            assert_eq!(sst.newest_entry, recovered.newest_entry);
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_batches_gc_of_mostly_dead_chunks() {
+        let dir = StoreDir::new("store_test_gc_batch");
+        let mut store = dir.open().await.with_tombstone_compaction_ratio(0.5);
+        let value = "v".repeat(96);
+        for i in 0..40 {
+            store.put(format!("key_{:02}", i), &value).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 0..40 {
+            store.delete(format!("key_{:02}", i)).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        assert_eq!(store.stats().reclaimable_vlog_bytes, 0);
+
+        store.run_compaction().await.unwrap();
+        let stats = store.stats();
+        assert_eq!(stats.dead_vlog_entries, 40);
+        // sizes are averaged over puts and the smaller tombstones, records end with a checksum
+        let put_size = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8 + 6 + value.len() + SIZE_OF_U32;
+        assert!(stats.reclaimable_vlog_bytes > 40 * (put_size / 2));
+        assert!(stats.reclaimable_vlog_bytes < 40 * put_size);
+
+        store.gc.config.gc_chunk_size = 1000;
+        GC::gc_handler(
+            &store.gc.config,
+            store.gc_table(),
+            Arc::clone(&store.gc_log),
+            Arc::clone(&store.key_range),
+            Arc::clone(&store.read_only_memtables),
+            Arc::clone(&store.gc_updated_entries),
+            Arc::clone(&store.gc.punch_marker),
+        )
+        .await
+        .unwrap();
+        // chunks after the first were dead so they were collected in the same run
+        assert!(store.gc.punch_marker.lock().await.punch_hole_length > 1000);
+        assert_eq!(store.stats().dead_vlog_entries, 0);
+        assert_eq!(store.stats().reclaimable_vlog_bytes, 0);
+        assert!(store.get("key_03").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_gc_collects_most_dead_segment_in_place() {
+        let dir = StoreDir::new("store_test_gc_in_place");
+        let mut store = DataStore::open("test", dir.path.clone())
+            .await
+            .unwrap()
+            .with_manual_background_mode(true)
+            .with_tombstone_compaction_ratio(0.5);
+        // about 1000 records per segment of value log
+        let value = "v".repeat(1000);
+        for i in 0..3000 {
+            store.put(format!("key_{:04}", i), &value).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        // most of the second segment dies, the first stays live
+        for i in 1040..2020 {
+            store.delete(format!("key_{:04}", i)).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        store.run_compaction().await.unwrap();
+        let reclaimable = store.stats().reclaimable_vlog_bytes;
+        assert!(reclaimable > VLOG_SEGMENT_SIZE / 2);
+
+        let tail = store.val_log.read().await.tail_offset;
+        store.tick_gc().await.unwrap();
+        let marker = store.gc.punch_marker.lock().await.to_owned();
+        assert!(marker.in_place);
+        assert!(marker.punch_hole_start_offset >= VLOG_SEGMENT_SIZE);
+        // records count toward the segment they start in
+        assert!(
+            marker.punch_hole_start_offset + marker.punch_hole_length
+                < 2 * VLOG_SEGMENT_SIZE + value.len() * 2
+        );
+        // the tail is left where it was
+        assert_eq!(store.val_log.read().await.tail_offset, tail);
+        assert!(store.stats().reclaimable_vlog_bytes < reclaimable - VLOG_SEGMENT_SIZE / 2);
+        for i in [0, 1030, 1039, 2020, 2999] {
+            let key = format!("key_{:04}", i);
+            assert_eq!(
+                store.get(&key).await.unwrap().unwrap().val,
+                value.as_bytes().to_vec()
+            );
+        }
+        assert!(store.get("key_1500").await.unwrap().is_none());
+
+        // tail GC reads the hole left behind as a single dead record
+        store.close().await.unwrap();
+        let mut store = DataStore::open("test", dir.path)
+            .await
+            .unwrap()
+            .with_manual_background_mode(true);
+        store.gc.config.gc_chunk_size = 2 * VLOG_SEGMENT_SIZE;
+        store.tick_gc().await.unwrap();
+        assert!(store.val_log.read().await.tail_offset > tail);
+        assert!(store.get("key_1500").await.unwrap().is_none());
+        assert_eq!(
+            store.get("key_2999").await.unwrap().unwrap().val,
+            value.as_bytes().to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_persists_reclaimable_vlog_bytes() {
+        let dir = StoreDir::new("store_test_persist_liveness");
+        let mut store = dir.open().await.with_tombstone_compaction_ratio(0.5);
+        let value = "v".repeat(100);
+        for i in 0..40 {
+            store.put(format!("key_{:02}", i), &value).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 0..40 {
+            store.delete(format!("key_{:02}", i)).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        store.run_compaction().await.unwrap();
+        let stats = store.stats();
+        assert_eq!(stats.dead_vlog_entries, 40);
+        assert!(stats.reclaimable_vlog_bytes > 0);
+        store.close().await.unwrap();
+
+        let store = dir.open().await;
+        let reopened = store.stats();
+        assert_eq!(reopened.dead_vlog_entries, stats.dead_vlog_entries);
+        assert_eq!(reopened.reclaimable_vlog_bytes, stats.reclaimable_vlog_bytes);
+    }
+
+    #[tokio::test]
+    async fn datastore_reports_background_task_heartbeats() {
+        let dir = StoreDir::new("store_test_heartbeat");
+        let store = dir.open().await;
+        let health = store.health();
+        assert!(!health.compaction.alive);
+        assert!(!health.gc.alive);
+        assert!(health.flush.last_run_at.is_none());
+
+        store.start_background_tasks();
+        tokio::task::yield_now().await;
+        let health = store.health();
+        assert!(health.compaction.alive);
+        assert!(health.gc.alive);
+        assert!(!health.flush.alive);
+        assert_eq!(health.pending_flushes, 0);
+
+        store.put("apple", "tim cook").await.unwrap();
+        store.active_memtable.write().unwrap().mark_readonly();
+        store.read_only_memtables.insert(
+            MemTable::generate_table_id(),
+            Arc::new(store.active_memtable.write().unwrap().take()),
+        );
+        assert_eq!(store.health().pending_flushes, 1);
+
+        store.flush_read_only_memtables();
+        while store.health().flushes_in_flight > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        let health = store.health();
+        assert_eq!(health.flush.last_outcome, Some(TaskOutcome::Succeeded));
+        assert_eq!(health.flush.error_count, 0);
+        assert_eq!(health.pending_flushes, 0);
+        assert_eq!(health.flushes_in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn datastore_read_only_after_repeated_background_failures() {
+        let dir = StoreDir::new("store_test_health");
+        let store = dir
+            .open()
+            .await
+            .with_degraded_failure_threshold(1)
+            .with_read_only_failure_threshold(2);
+        assert_eq!(store.health().state, HealthState::Healthy);
+        store.put("apple", "tim cook").await.unwrap();
+
+        store
+            .health
+            .record_failure(BackgroundTask::Flush, &Error::TableSummaryIsNone);
+        assert_eq!(store.health().state, HealthState::Degraded);
+        assert!(store.put("google", "sundar pichai").await.is_ok());
+
+        store
+            .health
+            .record_failure(BackgroundTask::Flush, &Error::TableSummaryIsNone);
+        let health = store.health();
+        assert_eq!(health.state, HealthState::ReadOnly);
+        assert_eq!(health.consecutive_flush_failures, 2);
+        assert_eq!(health.last_errors.len(), 2);
+
+        // writes are rejected but reads are still served
+        let res = store.put("nvidia", "jensen huang").await;
+        assert!(matches!(res, Err(Error::StoreReadOnly)));
+        assert!(matches!(store.delete("apple").await, Err(Error::StoreReadOnly)));
+        let entry = store.get("apple").await.unwrap();
+        assert_eq!(entry.unwrap().val, b"tim cook".to_vec());
+    }
+
+    #[tokio::test]
+    async fn datastore_runs_background_work_only_when_ticked() {
+        let dir = StoreDir::new("store_test_manual_background");
+        let clock = MockClock::new(chrono::Utc::now());
+        let store = DataStore::open("test", dir.path)
+            .await
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_manual_background_mode(true)
+            .with_max_buffer_write_number(1)
+            .with_tables_to_merge(2, 32);
+        for table in 0..2 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{:02}", i), format!("value_{}", table))
+                    .await
+                    .unwrap();
+            }
+            store.migrate_memtable_to_read_only();
+            // sstables keep millisecond timestamps, versions must not tie
+            clock.advance(std::time::Duration::from_secs(1));
+        }
+        tokio::task::yield_now().await;
+        // sealed memtables wait for a flush tick
+        assert_eq!(store.read_only_memtables.len(), 2);
+        assert!(store.live_files().await.unwrap().sstables.is_empty());
+
+        let report = store.tick_flush().await.unwrap();
+        assert_eq!(report.memtables, 2);
+        assert!(store.read_only_memtables.is_empty());
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 2);
+        assert!(store.compaction_status().last_run_at.is_none());
+
+        let status = store.tick_compaction().await.unwrap();
+        assert_eq!(status.tables_merged, 2);
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 1);
+
+        store.tick_gc().await.unwrap();
+        assert!(store.gc_updated_entries.read().await.is_empty());
+        assert_eq!(
+            store.get("key_07").await.unwrap().unwrap().val,
+            b"value_1".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_inserts_writes_into_gc_table_before_returning() {
+        let dir = StoreDir::new("store_test_gc_table_inserts");
+        let store = dir.open().await;
+        store.put("apple", "tim cook").await.unwrap();
+        store.append("apple", ";steve jobs").await.unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put("google", "sundar pichai").delete("nvidia");
+        let receipt = store.write_batch(batch).await.unwrap();
+
+        // GC sees every write as soon as it returns
+        let gc_table = store.gc_table();
+        let gc_table = gc_table.read().await;
+        let apple = store.get("apple").await.unwrap().unwrap();
+        assert_eq!(gc_table.get(b"apple").unwrap().created_at, apple.created_at);
+        assert!(gc_table.get(b"google").is_some());
+        let nvidia = gc_table.get(b"nvidia").unwrap();
+        assert!(nvidia.is_tombstone);
+        assert!(nvidia.created_at < receipt.timestamp);
+    }
+
+    #[tokio::test]
+    async fn datastore_gc_keeps_shared_values() {
+        let dir = StoreDir::new("store_test_value_dedup_gc");
+        // small enough for every record to fit one GC chunk
+        let blob = vec![b'x'; 200];
+        let store = DataStore::open("test", dir.path)
+            .await
+            .unwrap()
+            .with_manual_background_mode(true)
+            .with_value_dedup(64);
+        store.put("a", &blob).await.unwrap();
+        store.put("b", &blob).await.unwrap();
+        store.put("c", &blob).await.unwrap();
+        // the key stored with the payload no longer refers to it
+        store.put("a", "other").await.unwrap();
+        for _ in 0..3 {
+            store.tick_gc().await.unwrap();
+        }
+        assert!(store.val_log.read().await.tail_offset > 0);
+        assert_eq!(store.get("a").await.unwrap().unwrap().val, b"other".to_vec());
+        assert_eq!(store.get("b").await.unwrap().unwrap().val, blob);
+        assert_eq!(store.get("c").await.unwrap().unwrap().val, blob);
+
+        // the moved payload is shared by later puts
+        store.put("d", &blob).await.unwrap();
+        assert_eq!(store.get("d").await.unwrap().unwrap().val, blob);
+        store.delete("b").await.unwrap();
+        store.delete("c").await.unwrap();
+        for _ in 0..3 {
+            store.tick_gc().await.unwrap();
+        }
+        assert!(store.get("b").await.unwrap().is_none());
+        assert_eq!(store.get("d").await.unwrap().unwrap().val, blob);
+    }
+
+    #[tokio::test]
+    async fn datastore_renames_keys_of_payloads_checked_by_gc() {
+        let dir = StoreDir::new("store_test_rename_claimed");
+        let blob = vec![b'x'; 1000];
+        let store = DataStore::open("test", dir.path)
+            .await
+            .unwrap()
+            .with_manual_background_mode(true);
+        store.put("old", &blob).await.unwrap();
+        let end = store.val_log.read().await.size;
+
+        // GC cannot claim a payload a rename is about to share
+        let pin = store.dedup.pin(0).unwrap();
+        assert!(store.dedup.claim(0, end).is_none());
+        drop(pin);
+
+        // a payload GC is checking is written again
+        let claimed = store.dedup.claim(0, end).unwrap();
+        let size = store.val_log.read().await.size;
+        let seq = store.rename("old", "new").await.unwrap().seq;
+        assert!(store.val_log.read().await.size - size > blob.len());
+        assert_eq!(store.latest_sequence(), seq);
+        store.dedup.unclaim(claimed);
+        assert!(store.get("old").await.unwrap().is_none());
+        assert_eq!(store.get("new").await.unwrap().unwrap().val, blob);
+    }
+
+    #[tokio::test]
+    async fn datastore_scrubs_and_quarantines_corrupted_sstables() {
+        let dir = StoreDir::new("store_test_scrub");
+        let clock = MockClock::new(chrono::Utc::now());
+        let store = DataStore::open("test", dir.path.to_owned())
+            .await
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_manual_background_mode(true)
+            .with_max_buffer_write_number(1);
+        for table in 0..2 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{}_{:02}", table, i), "value")
+                    .await
+                    .unwrap();
+            }
+            store.migrate_memtable_to_read_only();
+            clock.advance(std::time::Duration::from_secs(1));
+        }
+        store.tick_flush().await.unwrap();
+        let report = store.scrub().await.unwrap();
+        assert_eq!(report.sstables_checked, 2);
+        assert!(report.bytes_checked > 0);
+        assert!(report.corrupted.is_empty());
+
+        // flip a byte in the data file of one sstable
+        let sstables = store.live_files().await.unwrap().sstables;
+        let corrupted = sstables[0].dir.to_owned();
+        let data_path = sstables[0]
+            .files
+            .iter()
+            .find(|f| f.extension().is_some_and(|ext| ext == "data"))
+            .unwrap()
+            .to_owned();
+        let mut data = std::fs::read(&data_path).unwrap();
+        data[0] ^= 0xff;
+        std::fs::write(&data_path, data).unwrap();
+
+        let report = store.scrub().await.unwrap();
+        assert_eq!(report.corrupted, vec![corrupted.to_owned()]);
+        assert!(report.quarantined.is_empty());
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 2);
+
+        let store = store.with_scrubber(0, ScrubAction::Quarantine);
+        let report = store.scrub().await.unwrap();
+        assert_eq!(report.quarantined, vec![corrupted.to_owned()]);
+        assert_eq!(store.stats().corrupted_sstables, 2);
+        let live = store.live_files().await.unwrap().sstables;
+        assert_eq!(live.len(), 1);
+        assert_ne!(live[0].dir, corrupted);
+        assert!(!corrupted.exists());
+        assert_eq!(std::fs::read_dir(dir.path.join("quarantine")).unwrap().count(), 1);
+
+        let report = store.scrub().await.unwrap();
+        assert_eq!(report.sstables_checked, 1);
+        assert!(report.corrupted.is_empty());
+    }
+}
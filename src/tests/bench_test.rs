@@ -0,0 +1,88 @@
+#[cfg(test)]
+mod tests {
+    use crate::bench::{self, BenchConfig};
+    use crate::db::Db;
+    use crate::tests::fixture::StoreDir;
+
+    #[tokio::test]
+    async fn bench_runs_workloads() {
+        let dir = StoreDir::new("bench_workloads");
+        let db = Db::from(dir.open().await);
+        let config = BenchConfig {
+            num: 300,
+            reads: 200,
+            ..Default::default()
+        };
+        let workloads = [
+            bench::Workload::FillSeq,
+            bench::Workload::FillRandom,
+            bench::Workload::ReadRandom,
+            bench::Workload::ReadWhileWriting,
+        ];
+        let report = bench::run(&db, &config, &workloads).await.unwrap();
+        let names: Vec<_> = report.workloads.iter().map(|w| w.workload).collect();
+        assert_eq!(names, workloads);
+        assert_eq!(report.workloads[0].ops, 300);
+        assert_eq!(report.workloads[0].bytes, 300 * (16 + 100));
+        // every key was written by the fill workloads
+        assert_eq!(report.workloads[2].found, 200);
+        assert_eq!(report.workloads[3].found, 200);
+        assert!(report.workloads.iter().all(|w| w.p50 <= w.p99 && w.p99 <= w.max));
+        assert!(report.to_string().contains("readwhilewriting"));
+        assert!(serde_json::to_string(&report).is_ok());
+    }
+
+    #[tokio::test]
+    async fn bench_replays_same_operations() {
+        let config = BenchConfig {
+            num: 100,
+            ..Default::default()
+        };
+        let mut runs = Vec::new();
+        for name in ["bench_replay_a", "bench_replay_b"] {
+            let dir = StoreDir::new(name);
+            let db = Db::from(dir.open().await);
+            bench::run(&db, &config, &[bench::Workload::FillRandom])
+                .await
+                .unwrap();
+            let mut entries = Vec::new();
+            for i in 0..100 {
+                entries.push(db.get(format!("{:016}", i)).await.unwrap().map(|e| e.val));
+            }
+            runs.push(entries);
+        }
+        assert_eq!(runs[0], runs[1]);
+        // some keys are written more than once and others not at all
+        assert!(runs[0].iter().any(|e| e.is_none()));
+    }
+
+    #[tokio::test]
+    async fn bench_injects_io_latency() {
+        let dir = StoreDir::new("bench_latency");
+        let db = Db::from(dir.open().await);
+        let config = BenchConfig {
+            num: 20,
+            reads: 20,
+            read_latency: std::time::Duration::from_millis(2),
+            write_latency: std::time::Duration::from_millis(3),
+            ..Default::default()
+        };
+        let report = bench::run(
+            &db,
+            &config,
+            &[bench::Workload::FillSeq, bench::Workload::ReadRandom],
+        )
+        .await
+        .unwrap();
+        assert!(report.workloads[0].elapsed >= std::time::Duration::from_millis(60));
+        // values are read from the value log
+        assert!(report.workloads[1].p50 >= std::time::Duration::from_millis(2));
+
+        // latency is removed once the run is over
+        let started = std::time::Instant::now();
+        for i in 0..20 {
+            db.get(format!("{:016}", i)).await.unwrap();
+        }
+        assert!(started.elapsed() < std::time::Duration::from_millis(40));
+    }
+}
@@ -2,7 +2,7 @@
 mod tests {
     use crate::tests::workload::{FilterWorkload, SSTContructor};
     use crate::{
-        bucket::{Bucket, BucketMap},
+        bucket::{Bucket, BucketMap, BucketTuning},
         consts::{BUCKET_HIGH, MIN_TRESHOLD},
         err::Error,
     };
@@ -26,6 +26,29 @@ mod tests {
         assert!(new_bucket.sstables.read().await.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_bucket_id_from_dir() {
+        let root = tempdir().unwrap();
+        // parent directories named like buckets do not confuse parsing
+        let path = root.path().join("bucket_store").join("buckets");
+        let new_bucket = Bucket::new(path.to_owned()).await.unwrap();
+        assert_eq!(Bucket::id_from_dir(&new_bucket.dir).unwrap(), new_bucket.id);
+
+        let relative = std::path::Path::new("buckets").join(new_bucket.dir.file_name().unwrap());
+        assert_eq!(Bucket::id_from_dir(relative).unwrap(), new_bucket.id);
+        let upper = new_bucket
+            .dir
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_uppercase();
+        assert_eq!(Bucket::id_from_dir(path.join(upper)).unwrap(), new_bucket.id);
+        assert!(matches!(
+            Bucket::id_from_dir(path.join("bucket_store")),
+            Err(Error::InvaidUUIDParseString { .. })
+        ));
+    }
+
     #[tokio::test]
     async fn test_bucket_from_with_empty() {
         let root = tempdir().unwrap();
@@ -101,11 +124,19 @@ mod tests {
         for s in sst_samples {
             new_bucket.sstables.write().await.push(s)
         }
-        assert!(new_bucket.sstable_count_exceeds_threshhold().await);
+        assert!(
+            new_bucket
+                .sstable_count_exceeds_threshhold(&BucketTuning::default())
+                .await
+        );
 
         new_bucket.sstables.write().await.clear();
 
-        assert!(!(new_bucket.sstable_count_exceeds_threshhold().await));
+        assert!(
+            !(new_bucket
+                .sstable_count_exceeds_threshhold(&BucketTuning::default())
+                .await)
+        );
     }
 
     #[tokio::test]
@@ -130,7 +161,7 @@ mod tests {
             new_bucket.sstables.write().await.push(s)
         }
         let expected_avg = all_sstable_size / sst_count as usize;
-        let extracted_ssts = new_bucket.extract_sstables().await;
+        let extracted_ssts = new_bucket.extract_sstables(&BucketTuning::default()).await;
         assert!(extracted_ssts.is_ok());
         let (ssts, avg) = extracted_ssts.unwrap();
         assert_eq!(avg, expected_avg);
@@ -148,20 +179,26 @@ mod tests {
         }
         let mut sst_within_size_range = SSTContructor::generate_ssts(1).await[0].to_owned();
         new_bucket.avarage_size = sst_within_size_range.size();
-        let fits_into_bucket =
-            new_bucket.fits_into_bucket(Arc::new(Box::new(sst_within_size_range.to_owned())));
+        let fits_into_bucket = new_bucket.fits_into_bucket(
+            Arc::new(Box::new(sst_within_size_range.to_owned())),
+            &BucketTuning::default(),
+        );
         // size of sstable is not less than bucket low
         assert!(fits_into_bucket);
         // increase sstable size to be greater than bucket high range
         sst_within_size_range.size = ((new_bucket.avarage_size as f64 * BUCKET_HIGH) * 2.0) as usize;
-        let fits_into_bucket =
-            new_bucket.fits_into_bucket(Arc::new(Box::new(sst_within_size_range.to_owned())));
+        let fits_into_bucket = new_bucket.fits_into_bucket(
+            Arc::new(Box::new(sst_within_size_range.to_owned())),
+            &BucketTuning::default(),
+        );
         // sstable size is greater than bucket high range
         assert!(!fits_into_bucket);
         // increase bucket average
         new_bucket.avarage_size = ((new_bucket.avarage_size as f64 * BUCKET_HIGH) * 2.0) as usize;
-        let fits_into_bucket =
-            new_bucket.fits_into_bucket(Arc::new(Box::new(sst_within_size_range.to_owned())));
+        let fits_into_bucket = new_bucket.fits_into_bucket(
+            Arc::new(Box::new(sst_within_size_range.to_owned())),
+            &BucketTuning::default(),
+        );
         // sstable size is within bucket range
         assert!(fits_into_bucket);
     }
@@ -287,6 +324,11 @@ mod tests {
         let is_balanced = bucket_map.is_balanced().await;
         assert!(!is_balanced);
 
+        // buckets are balanced once they need more tables to be compacted
+        bucket_map.tuning.write().unwrap().min_tables_to_merge = sst_count as usize + 1;
+        assert!(bucket_map.is_balanced().await);
+        *bucket_map.tuning.write().unwrap() = BucketTuning::default();
+
         // test empty map
         bucket_map.buckets.clear();
         let is_balanced = bucket_map.is_balanced().await;
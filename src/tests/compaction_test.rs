@@ -0,0 +1,637 @@
+#[cfg(test)]
+mod tests {
+    use crate::compactors::{
+        BucketInfo, CompactionFilter, CompactionInput, CompactionJob, CompactionStats, CompactionStrategy,
+        FilterDecision, FilteredEntry,
+    };
+    use crate::db::{CompactionState, DataStore, FlushReport, MockClock};
+    use crate::err::Error;
+    use crate::gc::garbage_collector::GC;
+    use crate::sst::SstId;
+    use crate::tests::fixture::StoreDir;
+    use crate::types::CreatedAt;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn datastore_flags_live_files_being_compacted() {
+        let dir = StoreDir::new("store_test_live_files_compacting");
+        let mut store = dir.open().await;
+        for table in 0..2 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{}_{:02}", table, i), "value")
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+        let live = store.live_files().await.unwrap();
+        assert!(live.sstables.iter().all(|sst| !sst.being_compacted));
+
+        let guard = store
+            .compactor
+            .config
+            .compacting
+            .track(vec![SstId::of(&live.sstables[0].dir).unwrap()]);
+        let live = store.live_files().await.unwrap();
+        assert!(live.sstables[0].being_compacted);
+        assert!(!live.sstables[1].being_compacted);
+
+        drop(guard);
+        let live = store.live_files().await.unwrap();
+        assert!(live.sstables.iter().all(|sst| !sst.being_compacted));
+    }
+
+    #[tokio::test]
+    async fn datastore_plans_compaction_without_running_it() {
+        let dir = StoreDir::new("store_test_compaction_plan");
+        let mut store = dir.open().await.with_tables_to_merge(2, 32);
+        assert!(store.compaction_plan().await.unwrap().is_empty());
+        for table in 0..2 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{:02}", i), format!("value_{}", table))
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+
+        let live = store.live_files().await.unwrap();
+        let plan = store.compaction_plan().await.unwrap();
+        assert_eq!(plan.buckets.len(), 1);
+        let bucket = &plan.buckets[0];
+        let mut planned = bucket.sstables.to_owned();
+        planned.sort();
+        let mut expected: Vec<PathBuf> = live.sstables.iter().map(|sst| sst.dir.to_owned()).collect();
+        expected.sort();
+        assert_eq!(planned, expected);
+        assert!(bucket.input_size > 0);
+        assert_eq!(plan.estimated_read_bytes, bucket.input_size);
+        assert!(bucket.estimated_output_size <= plan.estimated_write_bytes);
+
+        // planning leaves sstables untouched
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 2);
+        store.run_compaction().await.unwrap();
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 1);
+        assert!(store.compaction_plan().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn datastore_reports_compaction_status() {
+        let dir = StoreDir::new("store_test_compaction_status");
+        let mut store = dir.open().await.with_tables_to_merge(2, 32);
+        let status = store.compaction_status();
+        assert_eq!(status.state, CompactionState::Idle);
+        assert!(status.last_run_at.is_none());
+
+        for table in 0..2 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{:02}", i), format!("value_{}", table))
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+        store.run_compaction().await.unwrap();
+        let status = store.compaction_status();
+        assert_eq!(status.state, CompactionState::Idle);
+        assert!(status.last_run_at.is_some());
+        assert_eq!(status.tables_merged, 2);
+        let live = store.live_files().await.unwrap();
+        assert!(status.bytes_written > 0 && status.bytes_written <= live.sstables[0].size);
+        assert!(status.last_error.is_none());
+
+        // a run with nothing to merge resets the figures
+        store.run_compaction().await.unwrap();
+        assert_eq!(store.compaction_status().tables_merged, 0);
+    }
+
+    #[tokio::test]
+    async fn datastore_awaits_flush_and_compaction_jobs() {
+        let dir = StoreDir::new("store_test_async_jobs");
+        let store = dir.open().await.with_tables_to_merge(2, 32);
+
+        // the head entry written on open is flushed first
+        store.flush_memtable_async().await.unwrap();
+        assert_eq!(
+            store.flush_memtable_async().await.unwrap(),
+            FlushReport::default()
+        );
+        for table in 0..2 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{:02}", i), format!("value_{}", table))
+                    .await
+                    .unwrap();
+            }
+            let job = store.flush_memtable_async();
+            // the store stays usable while the flush runs
+            assert!(store.active_memtable.read().unwrap().entries.is_empty());
+            let report = job.await.unwrap();
+            assert_eq!(report.memtables, 1);
+            assert_eq!(report.memtable_ids.len(), 1);
+            // the head entry is flushed along with the writes
+            assert_eq!(report.entries, 21);
+            assert_eq!(report.sstables.len(), 1);
+            assert!(report.sstables[0].exists());
+            assert!(store.read_only_memtables.is_empty());
+        }
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 3);
+
+        let status = store.compact_async().await.unwrap();
+        assert_eq!(status.state, CompactionState::Idle);
+        assert_eq!(status.tables_merged, 3);
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 1);
+        assert_eq!(
+            store.get("key_07").await.unwrap().unwrap().val,
+            b"value_1".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_compacts_tombstone_heavy_sstables() {
+        let dir = StoreDir::new("store_test_tombstone_compaction");
+        let mut store = dir.open().await;
+        for i in 0..10 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 0..10 {
+            store.delete(format!("key_{}", i)).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+
+        // two sstables are below the size based threshold
+        assert!(store.compaction_plan().await.unwrap().is_empty());
+        drop(store);
+
+        // tombstone counts are read back from the summary
+        let store = dir.open().await.with_tombstone_compaction_ratio(0.5);
+        let plan = store.compaction_plan().await.unwrap();
+        assert_eq!(plan.buckets.len(), 1);
+        assert_eq!(plan.buckets[0].sstables.len(), 2);
+
+        store.run_compaction().await.unwrap();
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 1);
+        assert!(store.compaction_plan().await.unwrap().is_empty());
+        assert!(store.get("key_3").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_hands_entries_dropped_by_compaction_to_gc() {
+        let dir = StoreDir::new("store_test_dead_offsets");
+        let mut store = dir.open().await.with_tombstone_compaction_ratio(0.5);
+        for i in 0..10 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 0..10 {
+            store.delete(format!("key_{}", i)).await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        assert_eq!(store.stats().dead_vlog_entries, 0);
+
+        // values shadowed by tombstones are left out of the merged sstable
+        store.run_compaction().await.unwrap();
+        assert_eq!(store.stats().dead_vlog_entries, 10);
+
+        let tail = store.gc_log.read().await.tail_offset;
+        GC::gc_handler(
+            &store.gc.config,
+            store.gc_table(),
+            Arc::clone(&store.gc_log),
+            Arc::clone(&store.key_range),
+            Arc::clone(&store.read_only_memtables),
+            Arc::clone(&store.gc_updated_entries),
+            Arc::clone(&store.gc.punch_marker),
+        )
+        .await
+        .unwrap();
+        // gc freed the dead records along with the rest of its chunk
+        assert_eq!(store.stats().dead_vlog_entries, 0);
+        assert!(store.gc.punch_marker.lock().await.punch_hole_length > 0);
+        assert_eq!(store.gc.punch_marker.lock().await.punch_hole_start_offset, tail);
+        assert!(store.get("key_3").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_verifies_compaction_output() {
+        let dir = StoreDir::new("store_test_verify_compaction");
+        let mut store = dir
+            .open()
+            .await
+            .with_tombstone_compaction_ratio(0.3)
+            .with_compaction_verification(true);
+        for i in 0..10 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 0..5 {
+            store.delete(format!("key_{}", i)).await.unwrap();
+        }
+        store.put("key_7", "updated").await.unwrap();
+        store.force_flush().await.unwrap();
+
+        // keys shadowed by tombstones are dropped, every other key is read back
+        store.run_compaction().await.unwrap();
+        let files = store.live_files().await.unwrap();
+        assert_eq!(files.sstables.len(), 1);
+        assert!(store.get("key_3").await.unwrap().is_none());
+        assert_eq!(store.get("key_6").await.unwrap().unwrap().val, b"value".to_vec());
+        assert_eq!(
+            store.get("key_7").await.unwrap().unwrap().val,
+            b"updated".to_vec()
+        );
+
+        let mut table = None;
+        for bucket in store.buckets.read().await.buckets.values() {
+            table = bucket.sstables.read().await.first().cloned();
+        }
+        let table = table.unwrap();
+        let entries = table.read_verified().await.unwrap();
+        assert!(entries.windows(2).all(|pair| pair[0].key < pair[1].key));
+
+        let mut data = std::fs::read(&table.data_file.path).unwrap();
+        data[0] ^= 0xff;
+        std::fs::write(&table.data_file.path, data).unwrap();
+        assert!(matches!(
+            table.read_verified().await,
+            Err(Error::SSTableChecksumMismatch(dir)) if dir == table.dir
+        ));
+    }
+
+    /// Merges the two newest sstables of buckets holding at least three
+    #[derive(Debug)]
+    struct MergeNewestPair;
+
+    impl CompactionStrategy for MergeNewestPair {
+        fn pick_compaction(&self, buckets: &[BucketInfo], stats: &CompactionStats) -> Option<CompactionJob> {
+            assert_eq!(
+                stats.sstable_count,
+                buckets.iter().map(|b| b.sstables.len()).sum::<usize>()
+            );
+            let inputs: Vec<CompactionInput> = buckets
+                .iter()
+                .filter(|b| b.sstables.len() >= 3)
+                .map(|b| CompactionInput {
+                    bucket: b.id,
+                    sstables: b.sstables[b.sstables.len() - 2..]
+                        .iter()
+                        .map(|sst| sst.dir.to_owned())
+                        .collect(),
+                })
+                .collect();
+            (!inputs.is_empty()).then_some(CompactionJob { inputs })
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_compacts_with_custom_strategy() {
+        let dir = StoreDir::new("store_test_custom_strategy");
+        let mut store = dir.open().await.with_custom_compaction_strategy(MergeNewestPair);
+        for table in 0..3 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{:02}", i), format!("value_{}", table))
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+        let live = store.live_files().await.unwrap();
+        assert_eq!(live.sstables.len(), 3);
+        let mut dirs: Vec<PathBuf> = live.sstables.iter().map(|sst| sst.dir.to_owned()).collect();
+        dirs.sort();
+
+        let plan = store.compaction_plan().await.unwrap();
+        assert_eq!(plan.buckets.len(), 1);
+        let mut planned = plan.buckets[0].sstables.to_owned();
+        planned.sort();
+        assert_eq!(planned, dirs[1..].to_vec());
+
+        store.run_compaction().await.unwrap();
+        let live = store.live_files().await.unwrap();
+        assert_eq!(live.sstables.len(), 2);
+        assert!(live.sstables.iter().any(|sst| sst.dir == dirs[0]));
+        assert!(store.compaction_plan().await.unwrap().is_empty());
+        assert_eq!(
+            store.get("key_05").await.unwrap().unwrap().val,
+            b"value_2".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_keeps_expired_tombstones_shadowing_sstables_outside_merge() {
+        let dir = StoreDir::new("store_test_tombstones_outside_merge");
+        let clock = MockClock::new(chrono::Utc::now());
+        let mut store = dir
+            .open()
+            .await
+            .with_clock(clock.clone())
+            .with_custom_compaction_strategy(MergeNewestPair);
+        store.compactor.config.tombstone_ttl = std::time::Duration::from_secs(60);
+        for i in 0..20 {
+            store.put(format!("key_{:02}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        store.delete("key_05").await.unwrap();
+        store.delete("key_99").await.unwrap();
+        for i in 40..58 {
+            store.put(format!("key_{:02}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 60..80 {
+            store.put(format!("key_{:02}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        clock.advance(std::time::Duration::from_secs(120));
+
+        // the oldest sstable is left out of the merge but overlaps it
+        store.run_compaction().await.unwrap();
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 2);
+        assert!(store.get("key_05").await.unwrap().is_none());
+        // only the tombstone of a key outside the oldest sstable is dropped
+        assert_eq!(store.stats().dead_vlog_entries, 1);
+    }
+
+    #[tokio::test]
+    async fn datastore_reports_write_amplification_per_bucket() {
+        let dir = StoreDir::new("store_test_write_amplification");
+        let clock = MockClock::new(chrono::Utc::now());
+        let store = DataStore::open("test", dir.path)
+            .await
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_manual_background_mode(true)
+            .with_max_buffer_write_number(1)
+            .with_tables_to_merge(2, 32);
+        assert_eq!(store.write_amplification(), 1.0);
+        for table in 0..2 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{:02}", i), format!("value_{}", table))
+                    .await
+                    .unwrap();
+            }
+            store.migrate_memtable_to_read_only();
+            clock.advance(std::time::Duration::from_secs(1));
+        }
+        store.tick_flush().await.unwrap();
+        // flushes alone do not amplify writes
+        assert_eq!(store.write_amplification(), 1.0);
+        assert!(store.bucket_write_stats().is_empty());
+
+        store.tick_compaction().await.unwrap();
+        let buckets = store.bucket_write_stats();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].merges, 1);
+        // both flushed sstables were merged, so everything flushed was read once
+        let flushed = buckets[0].bytes_read;
+        let written = buckets[0].bytes_written;
+        assert!(flushed > 0 && written > 0);
+        let expected = (flushed + written) as f64 / flushed as f64;
+        assert_eq!(store.write_amplification(), expected);
+    }
+
+    #[tokio::test]
+    async fn datastore_keeps_pinned_prefixes_in_own_buckets() {
+        let dir = StoreDir::new("store_test_pinned_prefix");
+        let mut store = dir
+            .open()
+            .await
+            .with_pinned_prefix("tenant_a/")
+            .with_tables_to_merge(2, 32);
+        for table in 0..2 {
+            for i in 0..10 {
+                store
+                    .put(format!("tenant_a/{:02}", i), format!("a_{}", table))
+                    .await
+                    .unwrap();
+                store
+                    .put(format!("tenant_b/{:02}", i), format!("b_{}", table))
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+        let buckets = store.buckets().await;
+        let pinned: Vec<_> = buckets.iter().filter(|b| b.pinned_prefix.is_some()).collect();
+        assert_eq!(pinned.len(), 1);
+        assert_eq!(pinned[0].pinned_prefix, Some(b"tenant_a/".to_vec()));
+        assert_eq!(pinned[0].sstable_count, 2);
+        assert_eq!(buckets.iter().map(|b| b.sstable_count).sum::<usize>(), 4);
+        assert!(buckets
+            .iter()
+            .all(|b| b.size == b.avg_sstable_size * b.sstable_count));
+        let pinned_id = pinned[0].id;
+
+        // merged tables of the dedicated bucket stay in it
+        store.run_compaction().await.unwrap();
+        let buckets = store.buckets().await;
+        let pinned = buckets.iter().find(|b| b.id == pinned_id).unwrap();
+        assert_eq!(pinned.sstable_count, 1);
+        assert_eq!(buckets.iter().map(|b| b.sstable_count).sum::<usize>(), 2);
+        for i in 0..10 {
+            let a = store.get(format!("tenant_a/{:02}", i)).await.unwrap().unwrap();
+            assert_eq!(a.val, b"a_1".to_vec());
+            let b = store.get(format!("tenant_b/{:02}", i)).await.unwrap().unwrap();
+            assert_eq!(b.val, b"b_1".to_vec());
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // the bucket is recognized again after restart
+        let store = dir.open().await.with_pinned_prefix("tenant_a/");
+        let buckets = store.buckets().await;
+        let pinned = buckets.iter().find(|b| b.id == pinned_id).unwrap();
+        assert_eq!(pinned.pinned_prefix, Some(b"tenant_a/".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn datastore_sweeps_expired_sstables() {
+        let dir = StoreDir::new("store_test_ttl_sweep");
+        let start = chrono::DateTime::from_timestamp_millis(4_102_444_800_000).unwrap();
+        let clock = MockClock::new(start);
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        let mut store = dir
+            .open()
+            .await
+            .with_clock(clock.clone())
+            .with_enable_ttl(true)
+            .with_entry_ttl(3 * day)
+            .with_ttl_sweeper(std::time::Duration::ZERO, 0.5);
+        for i in 0..10 {
+            store.put(format!("a_{}", i), "old").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 0..10 {
+            if i == 5 {
+                clock.advance(2 * day);
+            }
+            store.put(format!("b_{}", i), "half").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        clock.advance(2 * day);
+        for i in 0..10 {
+            store.put(format!("c_{}", i), "new").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        let files = store.live_files().await.unwrap();
+        let dir_of = |key: &[u8]| {
+            files
+                .sstables
+                .iter()
+                .find(|sst| sst.smallest_key == key)
+                .unwrap()
+                .dir
+                .to_owned()
+        };
+
+        let report = store.sweep_expired().await.unwrap();
+        assert_eq!(report.sstables_checked, 3);
+        assert_eq!(report.dropped, vec![dir_of(b"a_0")]);
+        assert_eq!(report.compacted, vec![dir_of(b"b_0")]);
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 1);
+        assert!(store.get("a_3").await.unwrap().is_none());
+        assert!(store.get("b_2").await.unwrap().is_none());
+        assert_eq!(store.get("b_7").await.unwrap().unwrap().val, b"half".to_vec());
+        assert_eq!(store.get("c_1").await.unwrap().unwrap().val, b"new".to_vec());
+
+        let report = store.sweep_expired().await.unwrap();
+        assert!(report.dropped.is_empty() && report.compacted.is_empty());
+
+        // nothing is swept without TTL
+        store = store.with_enable_ttl(false);
+        clock.advance(30 * day);
+        assert_eq!(store.sweep_expired().await.unwrap().sstables_checked, 0);
+    }
+
+    /// Forgets every key of `tenant_a` and drops scratch entries
+    #[derive(Debug)]
+    struct ForgetTenant;
+
+    impl CompactionFilter for ForgetTenant {
+        fn filter(&self, entry: &FilteredEntry, _now: CreatedAt) -> FilterDecision {
+            if entry.key.starts_with(b"tenant_a/") {
+                FilterDecision::Tombstone
+            } else if entry.key.starts_with(b"tmp/") {
+                FilterDecision::Remove
+            } else {
+                FilterDecision::Keep
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_applies_compaction_filter() {
+        use crate::bucket::InsertableToBucket;
+        let dir = StoreDir::new("store_test_compaction_filter");
+        let store = DataStore::open("test", dir.path)
+            .await
+            .unwrap()
+            .with_manual_background_mode(true)
+            .with_max_buffer_write_number(1)
+            .with_tables_to_merge(2, 32)
+            .with_row_cache_size(64)
+            .with_compaction_filter(ForgetTenant);
+        for key in ["tenant_a/1", "tenant_b/1", "tmp/1"] {
+            store.put(key, "old").await.unwrap();
+        }
+        store.migrate_memtable_to_read_only();
+        for key in ["tenant_a/2", "tenant_b/2"] {
+            store.put(key, "new").await.unwrap();
+        }
+        store.migrate_memtable_to_read_only();
+        store.tick_flush().await.unwrap();
+        assert!(store.get("tenant_a/1").await.unwrap().is_some());
+
+        // filtered entries are evicted from the row cache as well
+        store.tick_compaction().await.unwrap();
+        assert!(store.get("tenant_a/1").await.unwrap().is_none());
+        assert!(store.get("tenant_a/2").await.unwrap().is_none());
+        assert!(store.get("tmp/1").await.unwrap().is_none());
+        assert_eq!(
+            store.get("tenant_b/1").await.unwrap().unwrap().val,
+            b"old".to_vec()
+        );
+        assert_eq!(
+            store.get("tenant_b/2").await.unwrap().unwrap().val,
+            b"new".to_vec()
+        );
+
+        // tenant keys are kept as tombstones, scratch entries are gone
+        let ranges = store.key_range.key_ranges.read().await;
+        assert_eq!(ranges.len(), 1);
+        let mut table = ranges.values().next().unwrap().sst.to_owned();
+        table.load_entries_from_file().await.unwrap();
+        let entries = table.get_entries();
+        assert!(
+            entries
+                .get(b"tenant_a/1".as_slice())
+                .unwrap()
+                .value()
+                .is_tombstone
+        );
+        assert!(entries.get(b"tmp/1".as_slice()).is_none());
+    }
+
+    #[cfg(feature = "compaction-hooks")]
+    #[tokio::test]
+    async fn datastore_calls_compaction_hooks() {
+        use crate::db::{CompactionHook, CompactionTrace};
+        use std::collections::BTreeMap;
+
+        #[derive(Debug, Default)]
+        struct Recorder(std::sync::Mutex<Vec<CompactionTrace>>);
+        impl CompactionHook for Arc<Recorder> {
+            fn on_merge(&self, trace: &CompactionTrace) {
+                self.0.lock().unwrap().push(trace.to_owned());
+            }
+        }
+
+        let dir = StoreDir::new("store_test_compaction_hooks");
+        let clock = MockClock::new(chrono::Utc::now());
+        let recorder = Arc::new(Recorder::default());
+        let store = DataStore::open("test", dir.path)
+            .await
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_manual_background_mode(true)
+            .with_max_buffer_write_number(1)
+            .with_tables_to_merge(2, 32)
+            .with_compaction_hook(recorder.clone());
+        for i in 0..20 {
+            store.put(format!("key_{:02}", i), "old").await.unwrap();
+        }
+        store.migrate_memtable_to_read_only();
+        clock.advance(std::time::Duration::from_secs(1));
+        for i in 0..10 {
+            store.put(format!("key_{:02}", i), "new").await.unwrap();
+        }
+        store.delete("key_15").await.unwrap();
+        store.migrate_memtable_to_read_only();
+        store.tick_flush().await.unwrap();
+        store.tick_compaction().await.unwrap();
+
+        let traces = recorder.0.lock().unwrap();
+        assert_eq!(traces.len(), 1);
+        assert_eq!(traces[0].inputs.len(), 2);
+        // no key is lost and the newest version of every key wins
+        let mut newest = BTreeMap::new();
+        for entry in traces[0].inputs.iter().flatten() {
+            let current = newest.entry(entry.key.to_owned()).or_insert(entry);
+            if entry.created_at > current.created_at {
+                *current = entry;
+            }
+        }
+        let output: Vec<_> = traces[0].output.iter().collect();
+        assert_eq!(output, newest.into_values().collect::<Vec<_>>());
+        // unexpired tombstones are kept
+        assert!(output
+            .iter()
+            .any(|e| e.key == b"key_15".to_vec() && e.is_tombstone));
+    }
+}
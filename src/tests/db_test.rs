@@ -0,0 +1,269 @@
+#[cfg(test)]
+mod tests {
+    use crate::db::Db;
+    use crate::err::Error;
+    use crate::tests::fixture::StoreDir;
+    use futures::future::join_all;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn session_reads_own_writes_during_flushes() {
+        let dir = StoreDir::new("store_test_session");
+        let db = Db::from(dir.open().await);
+        let flusher = db.clone();
+        let flushes = tokio::spawn(async move {
+            for _ in 0..20 {
+                flusher.flush_memtable_async().await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+        let session = db.session();
+        for i in 0..200 {
+            let key = format!("key_{:03}", i);
+            let seq = session.put(&key, format!("value_{}", i)).await.unwrap().seq;
+            assert_eq!(session.sequence(), seq);
+            let entry = session.get(&key).await.unwrap().unwrap();
+            assert_eq!(entry.val, format!("value_{}", i).into_bytes());
+            if i % 10 == 0 {
+                session.delete(&key).await.unwrap();
+                assert!(session.get(&key).await.unwrap().is_none());
+            }
+        }
+        flushes.await.unwrap();
+
+        // a session observing the sequence of another reads its writes
+        let other = db.session();
+        other.observe(session.sequence());
+        assert_eq!(other.sequence(), session.sequence());
+        assert!(other.get("key_199").await.unwrap().is_some());
+
+        // a sequence the store never applies fails the read instead of waiting forever
+        let ahead = db
+            .session()
+            .with_read_timeout(std::time::Duration::from_millis(50));
+        ahead.observe(u64::MAX);
+        assert!(matches!(
+            ahead.get("key_199").await,
+            Err(Error::SequenceWaitTimedOut(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn db_waits_for_writes_to_be_committed() {
+        let dir = StoreDir::new("store_test_commit_watermark");
+        let store = dir.open().await;
+        let committed = store.last_committed_seq();
+        let seq = store.put("apple", "tim cook").await.unwrap().seq;
+        assert_eq!(store.last_committed_seq(), committed);
+        let res = store.wait_for_seq(seq + 1).await;
+        assert!(matches!(res, Err(Error::SequenceNotApplied(s)) if s == seq + 1));
+        store.wait_for_seq(seq).await.unwrap();
+        assert_eq!(store.last_committed_seq(), seq);
+
+        // one sync commits every write applied before it
+        let first = store.put("google", "sundar pichai").await.unwrap().seq;
+        let second = store.put("meta", "mark zuckerberg").await.unwrap().seq;
+        store.wait_for_seq(first).await.unwrap();
+        assert_eq!(store.last_committed_seq(), second);
+
+        // a handle waits for writes not applied yet
+        let db = Db::from(store);
+        let waiter = tokio::spawn({
+            let db = db.clone();
+            async move {
+                db.wait_for_seq(second + 1, std::time::Duration::from_secs(10))
+                    .await
+            }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+        let third = db.put("nvidia", "jensen huang").await.unwrap().seq;
+        waiter.await.unwrap().unwrap();
+        assert_eq!(db.last_committed_seq().await, third);
+
+        // nobody writes the awaited sequence number
+        let res = db
+            .wait_for_seq(third + 100, std::time::Duration::from_millis(50))
+            .await;
+        assert!(matches!(res, Err(Error::SequenceWaitTimedOut(s)) if s == third + 100));
+        db.close().await.unwrap();
+
+        // recovered writes count as committed
+        let store = dir.open().await;
+        assert!(store.last_committed_seq() >= third);
+    }
+
+    #[tokio::test]
+    async fn db_serves_reads_while_memtables_are_flushed() {
+        let dir = StoreDir::new("store_test_reads_during_flush");
+        let db = Db::from(dir.open().await);
+        for i in 0..500 {
+            db.put(format!("key_{}", i), format!("value_{}", i))
+                .await
+                .unwrap();
+        }
+        db.store.write().await.migrate_memtable_to_read_only();
+
+        // a flush only needs shared access, a read in progress does not hold it up
+        let reader = db.store.read().await;
+        let report = tokio::time::timeout(std::time::Duration::from_secs(10), db.tick_flush())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(report.memtables, 1);
+        drop(reader);
+
+        for i in 500..1000 {
+            db.put(format!("key_{}", i), format!("value_{}", i))
+                .await
+                .unwrap();
+        }
+        let flush = tokio::spawn({
+            let db = db.clone();
+            async move { db.flush().await }
+        });
+        // every key stays visible while its memtable moves to an SSTable
+        while !flush.is_finished() {
+            for i in (0..1000).step_by(97) {
+                let entry = db.get(format!("key_{}", i)).await.unwrap().unwrap();
+                assert_eq!(entry.val, format!("value_{}", i).into_bytes());
+            }
+        }
+        flush.await.unwrap().unwrap();
+        assert_eq!(db.live_files().await.unwrap().sstables.len(), 2);
+        assert_eq!(
+            db.get("key_999").await.unwrap().unwrap().val,
+            b"value_999".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn db_applies_writes_concurrently() {
+        let dir = StoreDir::new("store_test_concurrent_writes");
+        let db = Db::from(dir.open().await);
+        // small memtables are sealed while writers race
+        db.set_write_buffer_size(50).await;
+
+        // writes share the store with a read in progress
+        let reader = db.store.read().await;
+        tokio::time::timeout(std::time::Duration::from_secs(10), db.put("key", "value"))
+            .await
+            .unwrap()
+            .unwrap();
+        drop(reader);
+
+        let writers = (0..8).map(|writer| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                for i in 0..200 {
+                    db.put(format!("key_{}_{}", writer, i), format!("value_{}_{}", writer, i))
+                        .await
+                        .unwrap();
+                    db.increment("counter", 1).await.unwrap();
+                }
+            })
+        });
+        for writer in join_all(writers).await {
+            writer.unwrap();
+        }
+        // increments look the counter up, no other write lands in between
+        assert_eq!(db.counter("counter").await.unwrap(), 8 * 200);
+        db.close().await.unwrap();
+        drop(db);
+
+        // memtables hold entries in value log order, recovery finds every write
+        let store = dir.open().await;
+        for writer in 0..8 {
+            for i in (0..200).step_by(13) {
+                let entry = store.get(format!("key_{}_{}", writer, i)).await.unwrap().unwrap();
+                assert_eq!(entry.val, format!("value_{}_{}", writer, i).into_bytes());
+            }
+        }
+        assert_eq!(store.counter("counter").await.unwrap(), 8 * 200);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn db_runs_background_work_and_quota_writes_alongside_reads() {
+        let dir = StoreDir::new("store_test_shared_background_work");
+        let store = dir
+            .open()
+            .await
+            .with_manual_background_mode(true)
+            .with_prefix_quota("tenant/", 1024, 5);
+        let db = Db::from(store);
+        db.put("log", "boot;").await.unwrap();
+        db.flush().await.unwrap();
+
+        // flushes, compactions, GC and writes share the store with a read in progress
+        let reader = db.store.read().await;
+        tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            db.append("log", "login;").await.unwrap();
+            db.increment("counter", 1).await.unwrap();
+            db.flush().await.unwrap();
+            db.tick_compaction().await.unwrap();
+            db.tick_gc().await.unwrap();
+        })
+        .await
+        .unwrap();
+        drop(reader);
+
+        // quotas are checked while writes wait for each other, racing writes cannot overshoot them
+        let writers = (0..10).map(|i| {
+            let db = db.clone();
+            tokio::spawn(async move { db.put(format!("tenant/{}", i), "value").await })
+        });
+        let written = join_all(writers)
+            .await
+            .into_iter()
+            .filter(|res| res.as_ref().unwrap().is_ok())
+            .count();
+        assert_eq!(written, 5);
+        let usage = db.quota_usage().await.unwrap();
+        assert_eq!(usage[0].keys, 5);
+
+        // appends read the value they extend, no other write lands in between
+        let appenders = (0..8).map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move { db.append("log", "x").await.unwrap() })
+        });
+        for appender in join_all(appenders).await {
+            appender.unwrap();
+        }
+        let entry = db.get("log").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"boot;login;xxxxxxxx".to_vec());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn db_row_cache_serves_latest_value_under_concurrent_writes() {
+        let dir = StoreDir::new("store_test_row_cache_concurrent_writes");
+        let db = Db::from(dir.open().await.with_row_cache_size(64));
+        db.put("key", "value_0").await.unwrap();
+
+        let done = Arc::new(AtomicBool::new(false));
+        // readers cache values while the writer replaces them
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let db = db.clone();
+                let done = Arc::clone(&done);
+                tokio::spawn(async move {
+                    while !done.load(Ordering::Acquire) {
+                        assert!(db.get("key").await.unwrap().is_some());
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect();
+        for i in 1..500 {
+            let value = format!("value_{}", i);
+            db.put("key", &value).await.unwrap();
+            // a value read before the write is not left in the cache
+            tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            assert_eq!(db.get("key").await.unwrap().unwrap().val, value.into_bytes());
+        }
+        done.store(true, Ordering::Release);
+        for reader in join_all(readers).await {
+            reader.unwrap();
+        }
+    }
+}
@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+    use crate::db::{DataStore, EncryptionKey};
+    use crate::err::Error;
+    use crate::tests::fixture::StoreDir;
+    use std::path::Path;
+
+    /// Returns `true` if a file under `dir` holds `needle`
+    fn files_contain(dir: &Path, needle: &[u8]) -> bool {
+        std::fs::read_dir(dir).unwrap().any(|entry| {
+            let path = entry.unwrap().path();
+            match path.is_dir() {
+                true => files_contain(&path, needle),
+                false => std::fs::read(&path)
+                    .unwrap()
+                    .windows(needle.len())
+                    .any(|window| window == needle),
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn datastore_encrypts_files_and_rotates_key_lazily() {
+        let dir = StoreDir::new("store_test_encryption");
+        let old_key = EncryptionKey::new(1, [1; 32]);
+        let new_key = EncryptionKey::new(2, [2; 32]);
+        let mut store = DataStore::open_encrypted("test", dir.path.clone(), vec![old_key.clone()])
+            .await
+            .unwrap()
+            .with_manual_background_mode(true)
+            .with_tables_to_merge(2, 32);
+        for i in 0..200 {
+            store
+                .put(format!("key_{:03}", i), format!("secret_{:03}", i))
+                .await
+                .unwrap();
+        }
+        store.force_flush().await.unwrap();
+        store.close().await.unwrap();
+        drop(store);
+        assert!(!files_contain(&dir.path, b"secret_"));
+        assert!(!files_contain(&dir.path, b"key_0"));
+
+        assert!(matches!(
+            DataStore::open_without_background("test", dir.path.clone()).await,
+            Err(Error::EncryptionKeyNotFound(1))
+        ));
+
+        let mut store = DataStore::open_encrypted("test", dir.path.clone(), vec![old_key.clone()])
+            .await
+            .unwrap()
+            .with_manual_background_mode(true)
+            .with_tables_to_merge(2, 32);
+        store.rotate_encryption_key(new_key.clone()).await.unwrap();
+        assert_eq!(store.encryption_keys_in_use().await.unwrap(), vec![1, 2]);
+        for i in 0..200 {
+            store
+                .put(format!("key_{:03}", i), format!("rotated_{:03}", i))
+                .await
+                .unwrap();
+        }
+        store.force_flush().await.unwrap();
+        // compaction writes the merged table with the new key, GC moves the
+        // tail past records written with the old one
+        store.run_compaction().await.unwrap();
+        for _ in 0..8 {
+            store.tick_gc().await.unwrap();
+        }
+        assert_eq!(store.encryption_keys_in_use().await.unwrap(), vec![2]);
+        store.close().await.unwrap();
+        drop(store);
+        assert!(!files_contain(&dir.path, b"rotated_"));
+
+        let store = DataStore::open_encrypted("test", dir.path.clone(), vec![new_key])
+            .await
+            .unwrap()
+            .with_manual_background_mode(true);
+        for i in 0..200 {
+            assert_eq!(
+                store.get(format!("key_{:03}", i)).await.unwrap().unwrap().val,
+                format!("rotated_{:03}", i).into_bytes()
+            );
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use crate::db::DataStore;
+use crate::types::Key;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// Sets up logging for a test
+pub fn setup() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+/// Copies directory `from` with everything under it to `to`
+pub fn copy_dir(from: &Path, to: &Path) {
+    std::fs::create_dir_all(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let target = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            std::fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
+
+/// Directory of a store under test, removed along with the store files once dropped
+pub struct StoreDir {
+    pub root: TempDir,
+    pub path: PathBuf,
+}
+
+impl StoreDir {
+    /// Creates store directory `name` in a temporary directory
+    pub fn new(name: &str) -> Self {
+        setup();
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join(name);
+        Self { root, path }
+    }
+
+    /// Opens the store without background tasks, reopens it if it was opened before
+    pub async fn open(&self) -> DataStore<'static, Key> {
+        DataStore::open_without_background("test", self.path.clone())
+            .await
+            .unwrap()
+    }
+}
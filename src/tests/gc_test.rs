@@ -47,7 +47,7 @@ mod tests {
         #[allow(unused_variables)] // for non linux based envinronment
         let res = GC::gc_handler(
             &config,
-            Arc::clone(&storage_reader.gc_table),
+            storage_reader.gc_table(),
             Arc::clone(&storage_reader.gc_log),
             Arc::clone(&storage_reader.key_range),
             Arc::clone(&storage_reader.read_only_memtables),
@@ -84,7 +84,7 @@ mod tests {
         let config = storage_reader.gc.config.clone();
         let _res = GC::gc_handler(
             &config,
-            Arc::clone(&storage_reader.gc_table),
+            storage_reader.gc_table(),
             Arc::clone(&storage_reader.gc_log),
             Arc::clone(&storage_reader.key_range),
             Arc::clone(&storage_reader.read_only_memtables),
@@ -124,7 +124,7 @@ mod tests {
 
         let _ = GC::gc_handler(
             &config,
-            Arc::clone(&storage_reader.gc_table),
+            storage_reader.gc_table(),
             Arc::clone(&storage_reader.gc_log),
             Arc::clone(&storage_reader.key_range),
             Arc::clone(&storage_reader.read_only_memtables),
@@ -162,7 +162,7 @@ mod tests {
 
         let _ = GC::gc_handler(
             &config,
-            Arc::clone(&storage_reader.gc_table),
+            storage_reader.gc_table(),
             Arc::clone(&storage_reader.gc_log),
             Arc::clone(&storage_reader.key_range),
             Arc::clone(&storage_reader.read_only_memtables),
@@ -203,7 +203,7 @@ mod tests {
         config.gc_chunk_size = bytes_to_scan_for_garbage_colection;
         let _ = GC::gc_handler(
             &config,
-            Arc::clone(&storage_reader.gc_table),
+            storage_reader.gc_table(),
             Arc::clone(&storage_reader.gc_log),
             Arc::clone(&storage_reader.key_range),
             Arc::clone(&storage_reader.read_only_memtables),
@@ -252,7 +252,7 @@ mod tests {
         let initial_head_offset = storage_reader.gc_log.read().await.head_offset;
         let _ = GC::gc_handler(
             &storage_reader.gc.config.clone(),
-            Arc::clone(&storage_reader.gc_table),
+            storage_reader.gc_table(),
             Arc::clone(&storage_reader.gc_log),
             Arc::clone(&storage_reader.key_range),
             Arc::clone(&storage_reader.read_only_memtables),
@@ -291,7 +291,7 @@ mod tests {
 
         let _ = GC::gc_handler(
             &config,
-            Arc::clone(&storage_reader.gc_table),
+            storage_reader.gc_table(),
             Arc::clone(&storage_reader.gc_log),
             Arc::clone(&storage_reader.key_range),
             Arc::clone(&storage_reader.read_only_memtables),
@@ -305,7 +305,7 @@ mod tests {
     }
 
     #[tokio::test]
-
+    #[cfg(target_os = "linux")]
     async fn datastore_gc_test_punch_hole() {
         #[cfg(target_os = "linux")]
         {
@@ -0,0 +1,331 @@
+#[cfg(test)]
+mod tests {
+    use crate::db::{ExportFormat, Inconsistency};
+    use crate::err::Error;
+    use crate::tests::fixture::{setup, StoreDir};
+
+    async fn export_import_round_trip(format: ExportFormat, name: &str) {
+        let source_dir = StoreDir::new(&format!("{}_source", name));
+        let source = source_dir.open().await;
+        source.put("apple", "tim cook").await.unwrap();
+        source.put("google", "sundar pichai").await.unwrap();
+        source.put("nvidia", "jensen huang").await.unwrap();
+        // bytes that would break plain text formats
+        source
+            .put(b"key,\n\"quoted\"", [0u8, 255, b',', b'\n'])
+            .await
+            .unwrap();
+        source.delete("google").await.unwrap();
+
+        let mut exported = Vec::new();
+        let count = source.export(b"a", b"m", format, &mut exported).await.unwrap();
+        assert_eq!(count, 2);
+
+        let target_dir = StoreDir::new(&format!("{}_target", name));
+        let target = target_dir.open().await;
+        let count = target.import(exported.as_slice(), format).await.unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(
+            target.get("apple").await.unwrap().unwrap().val,
+            b"tim cook".to_vec()
+        );
+        assert_eq!(
+            target.get(b"key,\n\"quoted\"").await.unwrap().unwrap().val,
+            vec![0u8, 255, b',', b'\n']
+        );
+        assert!(target.get("google").await.unwrap().is_none());
+        assert!(target.get("nvidia").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_exports_and_imports_entries() {
+        setup();
+        export_import_round_trip(ExportFormat::JsonLines, "store_test_export_jsonl").await;
+        export_import_round_trip(ExportFormat::Csv, "store_test_export_csv").await;
+    }
+
+    #[cfg(feature = "parquet")]
+    #[tokio::test]
+    async fn datastore_exports_and_imports_parquet() {
+        setup();
+        export_import_round_trip(ExportFormat::Parquet, "store_test_export_parquet").await;
+    }
+
+    #[tokio::test]
+    async fn datastore_rejects_malformed_import() {
+        let dir = StoreDir::new("store_test_malformed_import");
+        let store = dir.open().await;
+        let input =
+            "{\"key\":\"YXBwbGU=\",\"value\":\"dGltIGNvb2s=\"}\n{\"key\":\"not base64!\",\"value\":\"\"}\n";
+        let res = store.import(input.as_bytes(), ExportFormat::JsonLines).await;
+        assert!(matches!(res, Err(Error::Import(_))));
+        // entries before the malformed one are kept
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"tim cook".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_checks_consistency_with_sorted_source() {
+        let dir = StoreDir::new("store_test_consistency");
+        let mut store = dir.open().await;
+        for i in 0..10 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        store.put("key_3", "changed").await.unwrap();
+        store.delete("key_5").await.unwrap();
+
+        let source: Vec<(String, &str)> = (0..10)
+            .filter(|i| *i != 7)
+            .map(|i| (format!("key_{}", i), "value"))
+            .chain([("key_99".to_string(), "value")])
+            .collect();
+        let report = store
+            .assert_consistent_with(b"key_0", b"key_99", source.clone())
+            .await
+            .unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.source_entries, 10);
+        assert_eq!(report.store_entries, 9);
+        assert_eq!(report.matched, 7);
+        assert_eq!((report.missing, report.mismatched, report.unexpected), (2, 1, 1));
+        assert_eq!(
+            report.samples,
+            vec![
+                Inconsistency::Mismatched(b"key_3".to_vec()),
+                Inconsistency::Missing(b"key_5".to_vec()),
+                Inconsistency::Unexpected(b"key_7".to_vec()),
+                Inconsistency::Missing(b"key_99".to_vec()),
+            ]
+        );
+
+        let source = [("key_3", "changed"), ("key_4", "value")];
+        let report = store
+            .assert_consistent_with(b"key_3", b"key_4", source)
+            .await
+            .unwrap();
+        assert!(report.is_consistent());
+        assert_eq!(report.matched, 2);
+
+        let source = [("key_4", "value"), ("key_3", "changed")];
+        let res = store.assert_consistent_with(b"key_3", b"key_4", source).await;
+        assert!(matches!(res, Err(Error::SourceNotSorted(key)) if key == b"key_3"));
+    }
+
+    #[cfg(feature = "sled")]
+    #[tokio::test]
+    async fn datastore_migrates_from_sled() {
+        let dir = StoreDir::new("store_test_from_sled");
+        let mut store = dir.open().await;
+        let source = sled::Config::new().temporary(true).open().unwrap();
+        for i in 0..600 {
+            source
+                .insert(format!("key_{:03}", i), format!("value_{}", i).as_bytes())
+                .unwrap();
+        }
+        let migrated = crate::migrate::from_sled(&source, &mut store).await.unwrap();
+        assert_eq!(migrated, 600);
+        for i in [0, 255, 256, 599] {
+            assert_eq!(
+                store.get(format!("key_{:03}", i)).await.unwrap().unwrap().val,
+                format!("value_{}", i).into_bytes()
+            );
+        }
+    }
+
+    #[cfg(feature = "redb")]
+    #[tokio::test]
+    async fn datastore_migrates_from_redb() {
+        let dir = StoreDir::new("store_test_from_redb");
+        let mut store = dir.open().await;
+        let source = redb::Database::create(dir.root.path().join("source.redb")).unwrap();
+        let definition: redb::TableDefinition<&[u8], &[u8]> = redb::TableDefinition::new("users");
+        let txn = source.begin_write().unwrap();
+        {
+            let mut table = txn.open_table(definition).unwrap();
+            table.insert(b"apple".as_slice(), b"tim cook".as_slice()).unwrap();
+            table
+                .insert(b"google".as_slice(), b"sundar pichai".as_slice())
+                .unwrap();
+        }
+        txn.commit().unwrap();
+
+        assert_eq!(
+            crate::migrate::from_redb(&source, "users", &mut store)
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            store.get("google").await.unwrap().unwrap().val,
+            b"sundar pichai".to_vec()
+        );
+        let res = crate::migrate::from_redb(&source, "missing", &mut store).await;
+        assert!(matches!(res, Err(Error::Migrate(_))));
+    }
+
+    /// Writes RocksDB block-based SSTables the way RocksDB lays them out
+    #[cfg(feature = "rocksdb-sst")]
+    mod rocksdb_sst {
+        pub fn internal_key(key: &str, seq: u64, value_type: u8) -> Vec<u8> {
+            let mut internal = key.as_bytes().to_vec();
+            internal.extend_from_slice(&((seq << 8) | value_type as u64).to_le_bytes());
+            internal
+        }
+
+        pub fn varint(out: &mut Vec<u8>, mut value: u64) {
+            while value >= 0x80 {
+                out.push(value as u8 | 0x80);
+                value >>= 7;
+            }
+            out.push(value as u8);
+        }
+
+        pub fn handle(offset: u64, size: u64) -> Vec<u8> {
+            let mut out = Vec::new();
+            varint(&mut out, offset);
+            varint(&mut out, size);
+            out
+        }
+
+        /// Prefix compresses `entries`, values lengths are left out for delta encoded index blocks
+        pub fn block(entries: &[(Vec<u8>, Vec<u8>)], restart_interval: usize, value_len: bool) -> Vec<u8> {
+            let mut out = Vec::new();
+            let mut restarts = Vec::new();
+            let mut last: &[u8] = &[];
+            for (i, (key, value)) in entries.iter().enumerate() {
+                let shared = if i % restart_interval == 0 {
+                    restarts.push(out.len() as u32);
+                    0
+                } else {
+                    key.iter().zip(last).take_while(|(a, b)| a == b).count()
+                };
+                varint(&mut out, shared as u64);
+                varint(&mut out, (key.len() - shared) as u64);
+                if value_len {
+                    varint(&mut out, value.len() as u64);
+                }
+                out.extend_from_slice(&key[shared..]);
+                out.extend_from_slice(value);
+                last = key;
+            }
+            for restart in &restarts {
+                out.extend_from_slice(&restart.to_le_bytes());
+            }
+            out.extend_from_slice(&(restarts.len() as u32).to_le_bytes());
+            out
+        }
+
+        /// Appends `contents` with its trailer to `file`, returns offset and size
+        pub fn push_block(file: &mut Vec<u8>, contents: &[u8], snappy: bool) -> (u64, u64) {
+            let offset = file.len() as u64;
+            let contents = match snappy {
+                true => snap::raw::Encoder::new().compress_vec(contents).unwrap(),
+                false => contents.to_vec(),
+            };
+            file.extend_from_slice(&contents);
+            file.push(snappy as u8);
+            file.extend_from_slice(&[0; 4]);
+            (offset, contents.len() as u64)
+        }
+
+        /// Ends `file` with metaindex block pointing at `properties` and a format version 5 footer
+        pub fn finish(mut file: Vec<u8>, index: (u64, u64), properties: &[(&str, Vec<u8>)]) -> Vec<u8> {
+            let properties: Vec<_> = properties
+                .iter()
+                .map(|(name, value)| (name.as_bytes().to_vec(), value.to_owned()))
+                .collect();
+            let (offset, size) = push_block(&mut file, &block(&properties, 1, true), false);
+            let metaindex = vec![(b"rocksdb.properties".to_vec(), handle(offset, size))];
+            let (offset, size) = push_block(&mut file, &block(&metaindex, 1, true), false);
+            let mut handles = handle(offset, size);
+            handles.extend(handle(index.0, index.1));
+            handles.resize(40, 0);
+            file.push(1);
+            file.extend_from_slice(&handles);
+            file.extend_from_slice(&5u32.to_le_bytes());
+            file.extend_from_slice(&0x88e2_41b7_85f4_cff7u64.to_le_bytes());
+            file
+        }
+    }
+
+    #[cfg(feature = "rocksdb-sst")]
+    #[tokio::test]
+    async fn datastore_migrates_from_rocksdb_sst() {
+        use rocksdb_sst::*;
+        let dir = StoreDir::new("store_test_from_rocksdb");
+        let mut store = dir.open().await;
+        store.put("key_2", "stale").await.unwrap();
+
+        let first = vec![
+            (internal_key("key_1", 9, 1), b"new".to_vec()),
+            (internal_key("key_1", 2, 1), b"old".to_vec()),
+            (internal_key("key_2", 5, 0), vec![]),
+            (internal_key("key_3", 1, 1), b"three".to_vec()),
+        ];
+        let second = vec![
+            (internal_key("key_4", 3, 1), b"four".to_vec()),
+            (internal_key("key_5", 4, 7), vec![]),
+        ];
+        let mut file = Vec::new();
+        let (offset, first_size) = push_block(&mut file, &block(&first, 2, true), true);
+        let (_, second_size) = push_block(&mut file, &block(&second, 2, true), false);
+        // second handle only holds the size difference to the first
+        let mut delta = Vec::new();
+        let size_delta = second_size as i64 - first_size as i64;
+        let zigzag = ((size_delta << 1) ^ (size_delta >> 63)) as u64;
+        varint(&mut delta, zigzag);
+        let index_entries = vec![
+            (internal_key("key_3", 1, 1), handle(offset, first_size)),
+            (internal_key("key_5", 4, 7), delta),
+        ];
+        let index = push_block(&mut file, &block(&index_entries, 2, false), false);
+        let file = finish(
+            file,
+            index,
+            &[
+                ("rocksdb.comparator", b"leveldb.BytewiseComparator".to_vec()),
+                ("rocksdb.index.value.is.delta.encoded", vec![1]),
+            ],
+        );
+        let sst = dir.root.path().join("000042.sst");
+        tokio::fs::write(&sst, file).await.unwrap();
+
+        let migrated = crate::migrate::from_rocksdb_sst(&sst, &mut store).await.unwrap();
+        assert_eq!(migrated, 5);
+        assert_eq!(store.get("key_1").await.unwrap().unwrap().val, b"new".to_vec());
+        assert!(store.get("key_2").await.unwrap().is_none());
+        assert_eq!(store.get("key_3").await.unwrap().unwrap().val, b"three".to_vec());
+        assert_eq!(store.get("key_4").await.unwrap().unwrap().val, b"four".to_vec());
+        assert!(store.get("key_5").await.unwrap().is_none());
+    }
+
+    #[cfg(feature = "rocksdb-sst")]
+    #[tokio::test]
+    async fn datastore_rejects_unsupported_rocksdb_sst() {
+        use rocksdb_sst::*;
+        let dir = StoreDir::new("store_test_rocksdb_unsupported");
+        let mut store = dir.open().await;
+
+        let mut file = Vec::new();
+        let entries = vec![(internal_key("key_1", 1, 1), b"one".to_vec())];
+        let (offset, size) = push_block(&mut file, &block(&entries, 16, true), false);
+        let index_entries = vec![(internal_key("key_1", 1, 1), handle(offset, size))];
+        let index = push_block(&mut file, &block(&index_entries, 1, true), false);
+        let file = finish(file, index, &[("rocksdb.num.range-deletions", vec![1])]);
+        let sst = dir.root.path().join("000007.sst");
+        tokio::fs::write(&sst, file).await.unwrap();
+        let res = crate::migrate::from_rocksdb_sst(&sst, &mut store).await;
+        assert!(matches!(res, Err(Error::Migrate(_))));
+
+        let garbage = dir.root.path().join("000008.sst");
+        tokio::fs::write(&garbage, b"definitely not an sstable, just some bytes")
+            .await
+            .unwrap();
+        let res = crate::migrate::from_rocksdb_sst(&garbage, &mut store).await;
+        assert!(matches!(res, Err(Error::Migrate(_))));
+        assert!(store.get("key_1").await.unwrap().is_none());
+    }
+}
@@ -2,6 +2,7 @@
 mod tests {
     use crate::key_range::KeyRange;
     use crate::tests::*;
+    use std::path::PathBuf;
     use std::time::Duration;
     use workload::SSTContructor;
 
@@ -11,12 +12,12 @@ mod tests {
         let biggest_key = "biggest_key";
 
         let fake_sstable = SSTContructor::generate_ssts(1).await[0].to_owned();
-        let fake_sst_dir = fake_sstable.dir.to_owned();
+        let fake_sst_id = fake_sstable.id();
         let new_range = crate::key_range::Range::new(smallest_key, biggest_key, fake_sstable);
 
         assert_eq!(new_range.biggest_key, biggest_key.as_bytes().to_vec());
         assert_eq!(new_range.smallest_key, smallest_key.as_bytes().to_vec());
-        assert_eq!(new_range.sst.dir, fake_sst_dir);
+        assert_eq!(new_range.sst.id(), fake_sst_id);
     }
 
     #[tokio::test]
@@ -40,10 +41,7 @@ mod tests {
         let smallest_key = "smallest_key";
         let biggest_key = "biggest_key";
         let fake_sstable = SSTContructor::generate_ssts(1).await[0].to_owned();
-        let fake_sst_dir = fake_sstable.dir.to_owned();
-        key_range
-            .set(fake_sst_dir, smallest_key, biggest_key, fake_sstable)
-            .await;
+        key_range.set(smallest_key, biggest_key, fake_sstable).await;
         assert_eq!(key_range.key_ranges.read().await.len(), 1);
     }
 
@@ -54,16 +52,34 @@ mod tests {
         let smallest_key = "smallest_key";
         let biggest_key = "biggest_key";
         let fake_sstable = SSTContructor::generate_ssts(1).await[0].to_owned();
-        let fake_sst_dir = fake_sstable.dir.to_owned();
-        key_range
-            .set(fake_sst_dir.to_owned(), smallest_key, biggest_key, fake_sstable)
-            .await;
+        let fake_sst_id = fake_sstable.id();
+        key_range.set(smallest_key, biggest_key, fake_sstable).await;
         assert_eq!(key_range.key_ranges.read().await.len(), 1);
 
-        key_range.remove(fake_sst_dir).await;
+        key_range.remove(fake_sst_id).await;
         assert!(key_range.key_ranges.read().await.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_key_range_keeps_identity_across_moves() {
+        let key_range = KeyRange::new();
+        let fake_sstable = SSTContructor::generate_ssts(1).await[0].to_owned();
+        let fake_sst_id = fake_sstable.id();
+        key_range.set("a", "f", fake_sstable.to_owned()).await;
+
+        // same table installed at another path replaces its range
+        let mut moved = fake_sstable.to_owned();
+        moved.dir = PathBuf::from("moved/bucket").join(moved.dir.file_name().unwrap());
+        assert_eq!(moved.id(), fake_sst_id);
+        assert!(key_range.set("a", "f", moved).await);
+        assert_eq!(key_range.key_ranges.read().await.len(), 1);
+        assert_eq!(key_range.range_query_scan("b", "c").await.len(), 1);
+
+        key_range.remove(fake_sst_id).await;
+        assert!(key_range.key_ranges.read().await.is_empty());
+        assert!(key_range.range_query_scan("b", "c").await.is_empty());
+    }
+
     #[tokio::test]
     async fn test_key_range_filters_by_biggest_key() {
         let key_range = KeyRange::new();
@@ -77,10 +93,8 @@ mod tests {
         let smallest_key = binding.key();
         let binding = entries.back().unwrap();
         let biggest_key = binding.key();
-        let fake_sst_dir = fake_sstable.dir.to_owned();
-        key_range
-            .set(fake_sst_dir.to_owned(), smallest_key, biggest_key, fake_sstable)
-            .await;
+        let fake_sst_id = fake_sstable.id();
+        key_range.set(smallest_key, biggest_key, fake_sstable).await;
         assert_eq!(key_range.key_ranges.read().await.len(), 1);
 
         // Insert second sstable
@@ -91,15 +105,8 @@ mod tests {
         let smallest_key2 = binding.key();
         let binding = entries2.back().unwrap();
         let biggest_key2 = binding.key();
-        let fake_sst_dir2 = fake_sstable2.dir.to_owned();
-        key_range
-            .set(
-                fake_sst_dir2.to_owned(),
-                smallest_key2,
-                biggest_key2,
-                fake_sstable2,
-            )
-            .await;
+        let fake_sst_id2 = fake_sstable2.id();
+        key_range.set(smallest_key2, biggest_key2, fake_sstable2).await;
         assert_eq!(key_range.key_ranges.read().await.len(), 2);
 
         // Searched for the first smallest key
@@ -108,7 +115,7 @@ mod tests {
         assert!(!retrieved_sstables.as_ref().unwrap().is_empty());
         let mut sst_is_within_range = false;
         for sst in retrieved_sstables.unwrap() {
-            if sst.dir == fake_sst_dir {
+            if sst.id() == fake_sst_id {
                 sst_is_within_range = true
             }
         }
@@ -120,7 +127,7 @@ mod tests {
         assert!(!retrieved_sstables.as_ref().unwrap().is_empty());
         let mut sst_is_within_range = false;
         for sst in retrieved_sstables.unwrap() {
-            if sst.dir == fake_sst_dir2 {
+            if sst.id() == fake_sst_id2 {
                 sst_is_within_range = true
             }
         }
@@ -140,24 +147,16 @@ mod tests {
         let smallest_key = binding.key();
         let binding = entries.back().unwrap();
         let biggest_key = binding.key();
-        let fake_sst_dir = fake_sstable.dir.to_owned();
-        key_range
-            .set(fake_sst_dir.to_owned(), smallest_key, biggest_key, fake_sstable)
-            .await;
+        let fake_sst_id = fake_sstable.id();
+        key_range.set(smallest_key, biggest_key, fake_sstable).await;
         assert_eq!(key_range.key_ranges.read().await.len(), 1);
 
         // Bloom Filter should be empty
         let range = key_range.key_ranges.read().await;
-        let sst_with_empty_filter = range.get(&fake_sst_dir).unwrap();
+        let sst_with_empty_filter = range.get(&fake_sst_id).unwrap();
 
         // Ensure Bloom Filter does not exist for this sstable
-        assert!(sst_with_empty_filter
-            .sst
-            .filter
-            .clone()
-            .unwrap()
-            .sst_dir
-            .is_none());
+        assert!(sst_with_empty_filter.sst.filter.clone().unwrap().sst_id.is_none());
 
         // Searched for the first smallest key
         let retrieved_sstables = key_range.filter_sstables_by_key_range(smallest_key).await;
@@ -165,10 +164,10 @@ mod tests {
         assert!(!retrieved_sstables.as_ref().unwrap().is_empty());
         let mut sst_found = false;
         for sst in retrieved_sstables.unwrap() {
-            if sst.dir == fake_sst_dir {
+            if sst.id() == fake_sst_id {
                 sst_found = true;
                 // Bloom Filter should have be restored
-                assert!(sst.filter.clone().unwrap().sst_dir.is_some());
+                assert!(sst.filter.clone().unwrap().sst_id.is_some());
             }
         }
         // SSTable should have been found
@@ -188,10 +187,7 @@ mod tests {
         let smallest_key = binding.key();
         let binding = entries.back().unwrap();
         let biggest_key = binding.key();
-        let fake_sst_dir = fake_sstable.dir.to_owned();
-        key_range
-            .set(fake_sst_dir.to_owned(), smallest_key, biggest_key, fake_sstable)
-            .await;
+        key_range.set(smallest_key, biggest_key, fake_sstable).await;
         assert_eq!(key_range.key_ranges.read().await.len(), 1);
         let fake_key = "***Not Found***";
 
@@ -213,24 +209,16 @@ mod tests {
         let smallest_key = binding.key();
         let binding = entries.back().unwrap();
         let biggest_key = binding.key();
-        let fake_sst_dir = fake_sstable.dir.to_owned();
-        key_range
-            .set(fake_sst_dir.to_owned(), smallest_key, biggest_key, fake_sstable)
-            .await;
+        let fake_sst_id = fake_sstable.id();
+        key_range.set(smallest_key, biggest_key, fake_sstable).await;
         assert_eq!(key_range.key_ranges.read().await.len(), 1);
 
         // Bloom Filter should be empty
         let range = key_range.key_ranges.read().await;
-        let sst_with_empty_filter = range.get(&fake_sst_dir).unwrap();
+        let sst_with_empty_filter = range.get(&fake_sst_id).unwrap();
 
         // Ensure Bloom Filter does not exist for this sstable
-        assert!(sst_with_empty_filter
-            .sst
-            .filter
-            .clone()
-            .unwrap()
-            .sst_dir
-            .is_none());
+        assert!(sst_with_empty_filter.sst.filter.clone().unwrap().sst_id.is_none());
 
         // Ensure restored ranges is loaded
         let retrieved_sstables = key_range.filter_sstables_by_key_range(smallest_key).await;
@@ -247,10 +235,10 @@ mod tests {
         assert!(!retrieved_sstables.as_ref().unwrap().is_empty());
         let mut sst_found = false;
         for sst in retrieved_sstables.unwrap() {
-            if sst.dir == fake_sst_dir {
+            if sst.id() == fake_sst_id {
                 sst_found = true;
                 // Bloom Filter should have be restored
-                assert!(sst.filter.clone().unwrap().sst_dir.is_some());
+                assert!(sst.filter.clone().unwrap().sst_id.is_some());
             }
         }
         // SSTable should have been found
@@ -270,24 +258,16 @@ mod tests {
         let smallest_key = binding.key();
         let binding = entries.back().unwrap();
         let biggest_key = binding.key();
-        let fake_sst_dir = fake_sstable.dir.to_owned();
-        key_range
-            .set(fake_sst_dir.to_owned(), smallest_key, biggest_key, fake_sstable)
-            .await;
+        let fake_sst_id = fake_sstable.id();
+        key_range.set(smallest_key, biggest_key, fake_sstable).await;
         assert_eq!(key_range.key_ranges.read().await.len(), 1);
 
         // Bloom Filter should be empty
         let range = key_range.key_ranges.read().await;
-        let sst_with_empty_filter = range.get(&fake_sst_dir).unwrap();
+        let sst_with_empty_filter = range.get(&fake_sst_id).unwrap();
 
         // Ensure Bloom Filter does not exist for this sstable
-        assert!(sst_with_empty_filter
-            .sst
-            .filter
-            .clone()
-            .unwrap()
-            .sst_dir
-            .is_none());
+        assert!(sst_with_empty_filter.sst.filter.clone().unwrap().sst_id.is_none());
 
         // Ensure restored ranges is loaded
         let retrieved_sstables = key_range.filter_sstables_by_key_range(smallest_key).await;
@@ -321,13 +301,81 @@ mod tests {
         let smallest_key = binding.key();
         let binding = entries.back().unwrap();
         let biggest_key = binding.key();
-        let fake_sst_dir = fake_sstable.dir.to_owned();
-        key_range
-            .set(fake_sst_dir.to_owned(), smallest_key, biggest_key, fake_sstable)
-            .await;
+        let fake_sst_id = fake_sstable.id();
+        key_range.set(smallest_key, biggest_key, fake_sstable).await;
 
         let range = key_range.range_query_scan(smallest_key, biggest_key).await;
         assert_eq!(range.len(), 1);
-        assert_eq!(range.first().unwrap().sst.dir, fake_sst_dir);
+        assert_eq!(range.first().unwrap().sst.id(), fake_sst_id);
+    }
+
+    #[tokio::test]
+    async fn test_key_range_install_swaps_tables_at_once() {
+        let key_range = KeyRange::new();
+        let ssts = SSTContructor::generate_ssts(3).await;
+        key_range.set("a", "f", ssts[0].to_owned()).await;
+        key_range.set("d", "k", ssts[1].to_owned()).await;
+
+        let merged = crate::key_range::Range::new("a", "k", ssts[2].to_owned());
+        assert_eq!(
+            key_range
+                .install(vec![merged], &[ssts[0].id(), ssts[1].id()])
+                .await,
+            0
+        );
+        let tables = key_range.tables_overlapping("a", "z").await;
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0].id(), ssts[2].id());
+        assert_eq!(key_range.key_ranges.read().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_key_range_drops_restored_ranges_of_removed_tables() {
+        let key_range = KeyRange::new();
+        let ssts = SSTContructor::generate_ssts(2).await;
+        key_range.set("a", "f", ssts[0].to_owned()).await;
+        key_range.set("g", "k", ssts[1].to_owned()).await;
+        for sst in ssts.iter() {
+            let range = key_range
+                .key_ranges
+                .read()
+                .await
+                .get(&sst.id())
+                .unwrap()
+                .to_owned();
+            key_range.restored_ranges.write().await.insert(sst.id(), range);
+        }
+
+        key_range.remove(ssts[0].id()).await;
+        assert_eq!(key_range.restored_ranges.read().await.len(), 1);
+        key_range.update_key_range().await;
+        assert!(key_range.restored_ranges.read().await.is_empty());
+        let ids: Vec<_> = key_range.key_ranges.read().await.keys().cloned().collect();
+        assert_eq!(ids, vec![ssts[1].id()]);
+    }
+
+    #[tokio::test]
+    async fn test_key_range_tables_overlapping() {
+        let key_range = KeyRange::new();
+        let ssts = SSTContructor::generate_ssts(2).await;
+        key_range.set("a", "f", ssts[0].to_owned()).await;
+        key_range.set("d", "k", ssts[1].to_owned()).await;
+
+        let tables = key_range.tables_overlapping("e", "e").await;
+        assert_eq!(tables.len(), 2);
+
+        let tables = key_range.tables_overlapping("g", "z").await;
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables.first().unwrap().dir, ssts[1].dir);
+
+        assert!(key_range.tables_overlapping("l", "z").await.is_empty());
+
+        key_range.remove(ssts[1].id()).await;
+        assert!(key_range.tables_overlapping("g", "z").await.is_empty());
+
+        // resetting a path replaces its previous interval
+        key_range.set("x", "z", ssts[0].to_owned()).await;
+        assert!(key_range.tables_overlapping("a", "f").await.is_empty());
+        assert_eq!(key_range.tables_overlapping("y", "y").await.len(), 1);
     }
 }
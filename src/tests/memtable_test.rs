@@ -0,0 +1,398 @@
+#[cfg(test)]
+mod tests {
+    use crate::consts::HEAD_KEY_SIZE;
+    use crate::db::{DataStore, SizeUnit};
+    use crate::err::Error;
+    use crate::memtable::{Entry, MemTable};
+    use crate::range::{MergeIterator, Suppression};
+    use crate::tests::fixture::StoreDir;
+    use crate::types::Key;
+    use futures::future::join_all;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn datastore_flushes_memtables_over_total_write_buffer_size() {
+        let dir = StoreDir::new("store_test_write_buffer_budget");
+        let store = dir
+            .open()
+            .await
+            .with_max_buffer_write_number(10)
+            .with_total_write_buffer_size(8);
+        let budget = SizeUnit::Kilobytes.as_bytes(8);
+        // far below the 50KB memtable capacity
+        for i in 0..1000 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+            assert!(store.active_memtable.read().unwrap().size <= budget);
+        }
+        for _ in 0..100 {
+            if store.read_only_memtables.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert!(store.read_only_memtables.is_empty());
+        assert!(store.stats().write_buffer_memory <= budget);
+        assert!(!store.live_files().await.unwrap().sstables.is_empty());
+        for i in (0..1000).step_by(97) {
+            assert!(store.get(format!("key_{:04}", i)).await.unwrap().is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_tracks_memtable_rotations() {
+        let dir = StoreDir::new("store_test_memtable_rotations");
+        let store = dir
+            .open()
+            .await
+            .with_max_buffer_write_number(10)
+            .with_write_buffer_size(50);
+        assert_eq!(store.stats().memtable_rotations, 0);
+        assert_eq!(store.stats().avg_rotation_bytes, 0);
+        // fills a few 50KB memtables
+        for i in 0..5000 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        let stats = store.stats();
+        assert!(stats.memtable_rotations > 0);
+        assert_eq!(stats.forced_memtable_rotations, 0);
+        assert!(stats.avg_rotation_bytes > SizeUnit::Kilobytes.as_bytes(40));
+        assert!(stats.avg_rotation_bytes <= SizeUnit::Kilobytes.as_bytes(50));
+        assert!(stats.avg_memtable_fill_time > std::time::Duration::ZERO);
+
+        // the budget seals memtables long before they are full
+        let mut store = store.with_total_write_buffer_size(8);
+        for i in 0..1000 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        let forced = store.stats();
+        assert!(forced.forced_memtable_rotations > 0);
+        assert_eq!(
+            forced.memtable_rotations - stats.memtable_rotations,
+            forced.forced_memtable_rotations
+        );
+        assert!(forced.avg_rotation_bytes < stats.avg_rotation_bytes);
+
+        store.force_flush().await.unwrap();
+        assert_eq!(store.stats().memtable_rotations, forced.memtable_rotations + 1);
+    }
+
+    #[tokio::test]
+    async fn datastore_counts_every_flush_without_a_listener() {
+        let dir = StoreDir::new("store_test_flush_generation");
+        let store = dir.open().await;
+        let mut rx = store.flush_signal_rx.clone();
+        assert!(!rx.has_changed().unwrap());
+        for table in 0..4 {
+            for i in 0..10 {
+                store.put(format!("key_{}_{}", table, i), "value").await.unwrap();
+            }
+            store.migrate_memtable_to_read_only();
+        }
+        store.flush_read_only_memtables();
+        for _ in 0..100 {
+            if store.read_only_memtables.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert!(store.read_only_memtables.is_empty());
+
+        // nobody consumed the notifications yet none of them were lost
+        assert!(rx.has_changed().unwrap());
+        let signal = *rx.borrow_and_update();
+        assert_eq!(signal.generation, 4);
+        assert!(signal.memtable_id.is_some());
+        assert!(!rx.has_changed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn datastore_force_flush_leaves_memtables_of_background_flushes() {
+        let dir = StoreDir::new("store_test_force_flush_in_flight");
+        let mut store = dir.open().await;
+        store.put("apple", "tim cook").await.unwrap();
+        store.migrate_memtable_to_read_only();
+        let owned = *store.read_only_memtables.iter().next().unwrap().key();
+        // a background flush owns the sealed memtable
+        assert!(store.flusher.start_flush(owned));
+        store.put("google", "sundar pichai").await.unwrap();
+
+        store.force_flush().await.unwrap();
+        assert_eq!(store.read_only_memtables.len(), 1);
+        assert!(store.read_only_memtables.contains_key(&owned));
+        // entries of the memtable still being flushed are readable
+        assert!(store.get("apple").await.unwrap().is_some());
+        store.flusher.finish_flush(owned);
+        store.force_flush().await.unwrap();
+        assert!(store.read_only_memtables.is_empty());
+        assert!(store.get("apple").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn datastore_flushes_each_memtable_once_under_concurrent_rotation() {
+        let dir = StoreDir::new("store_test_flush_dedup");
+        let mut store = dir.open().await;
+        for table in 0..3 {
+            for i in 0..10 {
+                store.put(format!("key_{}_{}", table, i), "value").await.unwrap();
+            }
+            store.migrate_memtable_to_read_only();
+        }
+        // every rotation requests a flush of all read-only memtables
+        store.flush_read_only_memtables();
+        store.flush_read_only_memtables();
+        {
+            let table = store.read_only_memtables.front().unwrap();
+            assert!(!store.flusher.flush_handler(
+                table.key().to_owned(),
+                table.value().to_owned(),
+                store.flush_signal_tx.clone(),
+                store.health.clone(),
+            ));
+        }
+        assert!(store.flusher.flushes_in_flight() <= 3);
+        store.flush_read_only_memtables();
+        for _ in 0..100 {
+            if store.read_only_memtables.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert!(store.read_only_memtables.is_empty());
+        assert_eq!(store.flusher.flushes_in_flight(), 0);
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 3);
+        assert_eq!(store.flush_signal_rx.borrow().generation, 3);
+    }
+
+    #[tokio::test]
+    async fn datastore_rotates_memtable_for_entries_that_do_not_fit() {
+        let dir = StoreDir::new("store_test_oversized_entries");
+        let store = dir.open().await;
+        let capacity = store.active_memtable.read().unwrap().capacity();
+
+        let big_key = vec![b'a'; capacity];
+        let res = store.put(&big_key, "value").await;
+        assert!(matches!(
+            res,
+            Err(Error::EntryLargerThanBuffer { capacity: c, .. }) if c == capacity
+        ));
+        assert!(store.get(&big_key).await.unwrap().is_none());
+
+        // the second key would overflow the memtable so it starts a new one
+        let first = vec![b'b'; capacity / 2];
+        let second = vec![b'c'; capacity / 2];
+        store.put(&first, "first").await.unwrap();
+        assert!(store.read_only_memtables.is_empty());
+        store.put(&second, "second").await.unwrap();
+        assert_eq!(store.read_only_memtables.len(), 1);
+        assert!(store.active_memtable.write().unwrap().size() <= capacity);
+        assert_eq!(store.get(&first).await.unwrap().unwrap().val, b"first".to_vec());
+        assert_eq!(store.get(&second).await.unwrap().unwrap().val, b"second".to_vec());
+    }
+
+    #[tokio::test]
+    async fn datastore_adopts_memtable_capacity_on_rotation() {
+        let dir = StoreDir::new("store_test_memtable_capacity");
+        let mut store = dir.open().await;
+        let capacity = store.stats().memtable_capacity;
+        store.put("apple", "tim cook").await.unwrap();
+        assert!(store.stats().memtable_size > 0);
+
+        store.set_write_buffer_size(64);
+        let stats = store.stats();
+        assert_eq!(stats.memtable_capacity, capacity);
+        assert_eq!(stats.next_memtable_capacity, SizeUnit::Kilobytes.as_bytes(64));
+
+        store.force_flush().await.unwrap();
+        let stats = store.stats();
+        assert_eq!(stats.memtable_capacity, SizeUnit::Kilobytes.as_bytes(64));
+        assert_eq!(stats.memtable_size, 0);
+
+        // the new capacity decides when the memtable is rotated
+        let entry_size = MemTable::entry_size("key_0000".len());
+        let fitting = (SizeUnit::Kilobytes.as_bytes(64) - MemTable::entry_size(HEAD_KEY_SIZE)) / entry_size;
+        for i in 0..fitting {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        assert_eq!(store.read_only_memtables.len(), 0);
+        store.put(format!("key_{:04}", fitting), "value").await.unwrap();
+        assert_eq!(store.stats().memtable_size, entry_size);
+    }
+
+    #[tokio::test]
+    async fn datastore_rotation_moves_active_memtable() {
+        let dir = StoreDir::new("store_test_rotation_move");
+        let store = dir.open().await.with_max_buffer_write_number(10);
+        for i in 0..20 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+        }
+        let entries = Arc::clone(&store.active_memtable.read().unwrap().entries);
+        let filter_bits = Arc::clone(&store.active_memtable.read().unwrap().bloom_filter.bit_vec);
+
+        store.migrate_memtable_to_read_only();
+
+        // sealed table owns the same skipmap and filter, nothing was copied
+        assert!(store.active_memtable.read().unwrap().entries.is_empty());
+        assert!(!Arc::ptr_eq(
+            &store.active_memtable.read().unwrap().entries,
+            &entries
+        ));
+        let sealed = store
+            .read_only_memtables
+            .iter()
+            .find(|t| Arc::ptr_eq(&t.value().entries, &entries))
+            .expect("active memtable should be moved to read-only memtables");
+        assert!(sealed.value().read_only);
+        assert!(Arc::ptr_eq(&sealed.value().bloom_filter.bit_vec, &filter_bits));
+    }
+
+    /// Inserts `total` entries into the active memtable shared with readers,
+    /// sealing it every few entries the way `DataStore::seal_memtable` does
+    fn spawn_rotating_writer(
+        store: &DataStore<'static, Key>,
+        written: Arc<AtomicUsize>,
+        total: usize,
+    ) -> tokio::task::JoinHandle<()> {
+        let active = Arc::clone(&store.active_memtable);
+        let sealer = store.sealer();
+        let clock = store.clock.clone();
+        tokio::spawn(async move {
+            for i in 0..total {
+                let entry = Entry::new(format!("key_{:04}", i).into_bytes(), i, clock.tick(), false);
+                active.write().unwrap().insert(&entry);
+                written.store(i + 1, Ordering::SeqCst);
+                if i % 4 == 3 {
+                    let mut memtable = active.write().unwrap();
+                    let capacity = memtable.capacity();
+                    sealer.seal(&mut memtable, capacity, false);
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn datastore_get_during_memtable_rotation() {
+        let dir = StoreDir::new("store_test_rotation_get");
+        let store = dir.open().await.with_manual_background_mode(true);
+        let written = Arc::new(AtomicUsize::new(0));
+        let total = 2000;
+
+        // readers share the memtables with the writer, nothing keeps rotation from running while they read
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let active = Arc::clone(&store.active_memtable);
+                let read_only = Arc::clone(&store.read_only_memtables);
+                let written = Arc::clone(&written);
+                tokio::task::spawn_blocking(move || loop {
+                    let count = written.load(Ordering::SeqCst);
+                    // keys still in the active memtable are the ones rotation moves
+                    for i in (0..count).rev().take(8) {
+                        let key = format!("key_{:04}", i);
+                        let entry = DataStore::search_memtables(&active, &read_only, key.as_bytes());
+                        assert_eq!(
+                            entry.map(|e| e.val_offset),
+                            Some(i),
+                            "{} missing during rotation",
+                            key
+                        );
+                    }
+                    if count == total {
+                        break;
+                    }
+                })
+            })
+            .collect();
+        let writer = spawn_rotating_writer(&store, Arc::clone(&written), total);
+
+        writer.await.unwrap();
+        for res in join_all(readers).await {
+            res.unwrap();
+        }
+        assert!(store.read_only_memtables.len() >= total / 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn datastore_seek_during_memtable_rotation() {
+        let dir = StoreDir::new("store_test_rotation_seek");
+        let store = dir.open().await.with_manual_background_mode(true);
+        let written = Arc::new(AtomicUsize::new(0));
+        let total = 2000;
+
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let active = Arc::clone(&store.active_memtable);
+                let read_only = Arc::clone(&store.read_only_memtables);
+                let written = Arc::clone(&written);
+                tokio::task::spawn_blocking(move || loop {
+                    let count = written.load(Ordering::SeqCst);
+                    let mut merger = MergeIterator::new(Suppression::for_scan(None, chrono::Utc::now()));
+                    DataStore::merge_memtables(&mut merger, &active, &read_only, b"key_0000", b"key_9999");
+                    let keys: Vec<_> = merger.map(|entry| entry.key).collect();
+                    // entries written after the count was taken may show up as well
+                    assert!(keys.len() >= count, "scan missed entries during rotation");
+                    for (i, key) in keys.iter().enumerate() {
+                        assert_eq!(*key, format!("key_{:04}", i).into_bytes());
+                    }
+                    if count == total {
+                        break;
+                    }
+                })
+            })
+            .collect();
+        let writer = spawn_rotating_writer(&store, Arc::clone(&written), total);
+
+        writer.await.unwrap();
+        for res in join_all(readers).await {
+            res.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_seals_memtables_past_max_age() {
+        let dir = StoreDir::new("store_test_memtable_age");
+        let store = DataStore::open("test", dir.path.clone())
+            .await
+            .unwrap()
+            .with_manual_background_mode(true)
+            .with_memtable_max_age(std::time::Duration::from_millis(200));
+        // an empty memtable never expires
+        assert!(!store.tick_memtable_age().await);
+        store.put("apple", "tim cook").await.unwrap();
+        assert!(!store.tick_memtable_age().await);
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        assert!(store.tick_memtable_age().await);
+        assert!(store.active_memtable.read().unwrap().entries.is_empty());
+        assert_eq!(store.read_only_memtables.len(), 1);
+        store.tick_flush().await.unwrap();
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn datastore_flushes_idle_memtables_past_max_age() {
+        let dir = StoreDir::new("store_test_memtable_age_idle");
+        let store = DataStore::open("test", dir.path.clone())
+            .await
+            .unwrap()
+            .with_memtable_max_age(std::time::Duration::from_millis(200));
+        store.put("apple", "tim cook").await.unwrap();
+        let head = store.meta.lock().unwrap().v_log_head;
+
+        // the store takes no writes from here on
+        let mut flushed = false;
+        for _ in 0..50 {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            if !store.live_files().await.unwrap().sstables.is_empty() {
+                flushed = true;
+                break;
+            }
+        }
+        assert!(flushed);
+        assert!(store.active_memtable.read().unwrap().entries.is_empty());
+        assert!(store.meta.lock().unwrap().v_log_head > head);
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"tim cook".to_vec()
+        );
+    }
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use crate::consts::{SIZE_OF_U32, SIZE_OF_U64};
+    use crate::consts::{FORMAT_VERSION, META_FEATURE_ENCRYPTION, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8};
     use crate::meta::Meta;
     use tempfile::tempdir;
 
@@ -83,9 +83,160 @@ mod tests {
         metadata.set_head(new_head);
         metadata.set_tail(new_tail);
 
-        let expected_entry_len = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U64;
+        let expected_entry_len = SIZE_OF_U32
+            + SIZE_OF_U32
+            + SIZE_OF_U64
+            + SIZE_OF_U64
+            + SIZE_OF_U32
+            + SIZE_OF_U64
+            + SIZE_OF_U8
+            + SIZE_OF_U64
+            + SIZE_OF_U32
+            + SIZE_OF_U8
+            + SIZE_OF_U64
+            + SIZE_OF_U64
+            + SIZE_OF_U8;
         let serialized_entry = metadata.serialize();
 
         assert_eq!(serialized_entry.len(), expected_entry_len);
     }
+
+    #[tokio::test]
+    async fn test_meta_recover_shutdown_state() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("meta_shutdown");
+
+        let mut metadata = Meta::new(path.to_owned()).await.unwrap();
+        metadata.set_head(50);
+        metadata.mark_clean_shutdown(100, 42);
+        metadata.write().await.unwrap();
+
+        let mut recovered_meta = Meta::new(path).await.unwrap();
+        recovered_meta.recover().await.unwrap();
+        assert_eq!(recovered_meta.v_log_head, 50);
+        assert_eq!(recovered_meta.format_version, FORMAT_VERSION);
+        assert_eq!(recovered_meta.config_hash, 42);
+        assert!(recovered_meta.is_clean_shutdown(100));
+        // value log written to after close
+        assert!(!recovered_meta.is_clean_shutdown(120));
+    }
+
+    #[tokio::test]
+    async fn test_meta_recover_comparator_and_features() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("meta_features");
+
+        let mut metadata = Meta::new(path.to_owned()).await.unwrap();
+        metadata.mark_clean_shutdown(100, 42);
+        metadata.comparator = Some(String::from("reverse"));
+        metadata.features = META_FEATURE_ENCRYPTION;
+        metadata.write().await.unwrap();
+
+        let mut recovered_meta = Meta::new(path.to_owned()).await.unwrap();
+        recovered_meta.recover().await.unwrap();
+        assert_eq!(recovered_meta.comparator.as_deref(), Some("reverse"));
+        assert_eq!(recovered_meta.features, META_FEATURE_ENCRYPTION);
+
+        // meta written before comparator and features were recorded
+        let serialized = metadata.serialize();
+        let len =
+            serialized.len() - SIZE_OF_U8 - SIZE_OF_U64 * 2 - SIZE_OF_U32 - "reverse".len() - SIZE_OF_U8;
+        std::fs::write(&metadata.file_handle.path, &serialized[..len]).unwrap();
+        let mut recovered_meta = Meta::new(path).await.unwrap();
+        recovered_meta.recover().await.unwrap();
+        assert!(recovered_meta.comparator.is_none());
+        assert_eq!(recovered_meta.features, 0);
+        assert!(recovered_meta.is_clean_shutdown(100));
+    }
+
+    #[tokio::test]
+    async fn test_meta_recover_head_checkpoint() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("meta_checkpoint");
+
+        let mut metadata = Meta::new(path.to_owned()).await.unwrap();
+        metadata.set_head(50);
+        metadata.set_checkpoint(50, 400);
+        metadata.write().await.unwrap();
+
+        let mut recovered_meta = Meta::new(path.to_owned()).await.unwrap();
+        recovered_meta.recover().await.unwrap();
+        assert_eq!(recovered_meta.checkpoint_head, 50);
+        assert_eq!(recovered_meta.checkpoint_offset, 400);
+        assert!(recovered_meta.has_checkpoint());
+        // head moved past the checkpoint
+        recovered_meta.set_head(60);
+        assert!(!recovered_meta.has_checkpoint());
+
+        // meta written before head checkpoints were recorded
+        let serialized = metadata.serialize();
+        std::fs::write(
+            &metadata.file_handle.path,
+            &serialized[..serialized.len() - SIZE_OF_U8 - SIZE_OF_U64 * 2],
+        )
+        .unwrap();
+        let mut recovered_meta = Meta::new(path).await.unwrap();
+        recovered_meta.recover().await.unwrap();
+        assert_eq!(recovered_meta.checkpoint_offset, 0);
+        assert!(!recovered_meta.has_checkpoint());
+    }
+
+    #[tokio::test]
+    async fn test_meta_recover_expiring_values() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("meta_expiring_values");
+
+        let mut metadata = Meta::new(path.to_owned()).await.unwrap();
+        metadata.write().await.unwrap();
+        let mut recovered_meta = Meta::new(path.to_owned()).await.unwrap();
+        recovered_meta.recover().await.unwrap();
+        assert!(!recovered_meta.expiring_values);
+
+        metadata.expiring_values = true;
+        metadata.write().await.unwrap();
+        let mut recovered_meta = Meta::new(path.to_owned()).await.unwrap();
+        recovered_meta.recover().await.unwrap();
+        assert!(recovered_meta.expiring_values);
+
+        // meta written before expiring values were recorded
+        metadata.expiring_values = false;
+        let serialized = metadata.serialize();
+        std::fs::write(
+            &metadata.file_handle.path,
+            &serialized[..serialized.len() - SIZE_OF_U8],
+        )
+        .unwrap();
+        let mut recovered_meta = Meta::new(path).await.unwrap();
+        recovered_meta.recover().await.unwrap();
+        assert!(recovered_meta.expiring_values);
+    }
+
+    #[tokio::test]
+    async fn test_meta_recover_without_shutdown_state() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("meta_legacy");
+
+        let mut metadata = Meta::new(path.to_owned()).await.unwrap();
+        metadata.set_head(50);
+        metadata.set_tail(10);
+        metadata.mark_clean_shutdown(100, 42);
+        // meta written before shutdown state was recorded
+        let legacy_len = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U64;
+        let serialized = metadata.serialize();
+        std::fs::write(&metadata.file_handle.path, &serialized[..legacy_len]).unwrap();
+
+        let mut recovered_meta = Meta::new(path.to_owned()).await.unwrap();
+        recovered_meta.recover().await.unwrap();
+        assert_eq!(recovered_meta.v_log_head, 50);
+        assert_eq!(recovered_meta.v_log_tail, 10);
+        assert_eq!(recovered_meta.format_version, 0);
+        assert!(!recovered_meta.is_clean_shutdown(100));
+
+        std::fs::write(&metadata.file_handle.path, &serialized[..legacy_len + 1]).unwrap();
+        let mut corrupted_meta = Meta::new(path).await.unwrap();
+        assert!(matches!(
+            corrupted_meta.recover().await,
+            Err(crate::err::Error::MetaCorrupted(_))
+        ));
+    }
 }
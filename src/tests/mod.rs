@@ -1,10 +1,22 @@
+mod background_test;
+mod bench_test;
 mod bucket_test;
+mod compaction_test;
+mod db_test;
+mod encryption_test;
+#[cfg(test)]
+mod fixture;
 mod gc_test;
+mod import_test;
 mod key_range_test;
+mod memtable_test;
 mod meta_test;
+mod read_test;
+mod recovery_test;
 mod sized_tier_test;
 mod store_test;
 mod summary_test;
 mod vlog;
 #[cfg(test)]
 mod workload;
+mod write_test;
@@ -0,0 +1,648 @@
+#[cfg(test)]
+mod tests {
+    use crate::consts::HOTNESS_SAMPLE_EVERY;
+    use crate::db::{DataStore, KeyStatus, MockClock, WarmUpOptions, WriteBatch};
+    use crate::sst::SstId;
+    use crate::tests::fixture::StoreDir;
+
+    #[tokio::test]
+    async fn datastore_get_stops_at_newest_sstable_hit() {
+        let dir = StoreDir::new("store_test_newest_first");
+        let mut store = dir.open().await;
+        for version in 0..3 {
+            store.put("apple", format!("version_{}", version)).await.unwrap();
+            store.put(format!("key_{}", version), "value").await.unwrap();
+            store.force_flush().await.unwrap();
+        }
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 3);
+
+        let probes = store.stats().sstable_probes;
+        let entry = store.get("apple").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"version_2".to_vec());
+        assert_eq!(store.stats().sstable_probes - probes, 1);
+
+        // a key only held by the oldest sstable is still found
+        assert!(store.get("key_0").await.unwrap().is_some());
+        drop(store);
+
+        // newest entry timestamps are read back from the summary
+        let store = dir.open().await;
+        let key_ranges = store.key_range.key_ranges.read().await;
+        assert_eq!(key_ranges.len(), 3);
+        assert!(key_ranges
+            .values()
+            .all(|range| range.sst.newest_entry().is_some()));
+    }
+
+    #[tokio::test]
+    async fn datastore_caches_index_lookups_per_sstable() {
+        let dir = StoreDir::new("store_test_index_cache");
+        let mut store = dir.open().await;
+        for i in 0..20 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+
+        for _ in 0..3 {
+            assert!(store.get("key_7").await.unwrap().is_some());
+        }
+        assert!(store.get("key_9").await.unwrap().is_some());
+        let key_ranges = store.key_range.key_ranges.read().await;
+        let range = key_ranges.values().next().unwrap();
+        // lookups are shared by every clone of the table
+        assert_eq!(range.sst.index_cache.len(), 2);
+        assert!(range.sst.index_cache.get("key_7").unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn datastore_caps_filter_memory() {
+        let dir = StoreDir::new("store_test_filter_cap");
+        let mut store = dir.open().await;
+        for table in 0..3 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{}_{}", table, i), format!("value_{}", i))
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+        let stats = store.stats();
+        assert!(stats.filter_memory > 0);
+        assert_eq!(stats.evicted_filters, 0);
+
+        // a cap below a single filter evicts all of them
+        store.key_range.filter_cache.set_capacity(1);
+        let stats = store.stats();
+        assert_eq!(stats.filter_memory, 0);
+        assert_eq!(stats.evicted_filters, 3);
+
+        // lookups fall back to the index
+        for table in 0..3 {
+            for i in 0..20 {
+                let entry = store.get(format!("key_{}_{}", table, i)).await.unwrap().unwrap();
+                assert_eq!(entry.val, format!("value_{}", i).into_bytes());
+            }
+        }
+        assert!(store.get("key_missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_checks_key_existence() {
+        let dir = StoreDir::new("store_test_contains_key");
+        let mut store = dir.open().await;
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.delete("google").await.unwrap();
+
+        assert_eq!(store.contains_key("apple").await.unwrap(), KeyStatus::Present);
+        assert_eq!(store.contains_key("google").await.unwrap(), KeyStatus::Tombstoned);
+        assert_eq!(store.contains_key("nvidia").await.unwrap(), KeyStatus::Absent);
+        assert!(store.contains_key("").await.is_err());
+
+        // SSTables answer from their blocks
+        store.force_flush().await.unwrap();
+        store.put("nvidia", "jensen huang").await.unwrap();
+        store.delete("apple").await.unwrap();
+        store.force_flush().await.unwrap();
+        assert_eq!(store.contains_key("apple").await.unwrap(), KeyStatus::Tombstoned);
+        assert_eq!(store.contains_key("google").await.unwrap(), KeyStatus::Tombstoned);
+        assert_eq!(store.contains_key("nvidia").await.unwrap(), KeyStatus::Present);
+        assert_eq!(store.contains_key("meta").await.unwrap(), KeyStatus::Absent);
+
+        // once a value may expire on its own, live keys can't be told apart from expired ones
+        let clock = MockClock::new(chrono::Utc::now());
+        let store = store.with_clock(clock.clone());
+        let mut batch = WriteBatch::new();
+        batch.put_with_ttl("session", "token", std::time::Duration::from_secs(10));
+        store.write_batch(batch).await.unwrap();
+        clock.advance(std::time::Duration::from_secs(11));
+        assert!(store.get("session").await.unwrap().is_none());
+        assert_eq!(
+            store.contains_key("session").await.unwrap(),
+            KeyStatus::MaybeExpired
+        );
+        assert_eq!(
+            store.contains_key("nvidia").await.unwrap(),
+            KeyStatus::MaybeExpired
+        );
+        assert_eq!(store.contains_key("apple").await.unwrap(), KeyStatus::Tombstoned);
+        assert_eq!(store.contains_key("meta").await.unwrap(), KeyStatus::Absent);
+    }
+
+    #[tokio::test]
+    async fn datastore_persists_filter_bits() {
+        let dir = StoreDir::new("store_test_persist_filter_bits");
+        let mut store = dir.open().await.with_persist_filter_bits(false);
+        store.put("apple", "tim cook").await.unwrap();
+        store.force_flush().await.unwrap();
+        let mut store = store.with_persist_filter_bits(true);
+        store.put("banana", "sundar pichai").await.unwrap();
+        store.force_flush().await.unwrap();
+
+        // metadata is 16 bytes, persisted bits follow it
+        let files = store.live_files().await.unwrap();
+        for sst in files.sstables.iter() {
+            let filter = sst
+                .files
+                .iter()
+                .find(|f| f.extension().unwrap() == "filter")
+                .unwrap();
+            let size = tokio::fs::metadata(&filter).await.unwrap().len();
+            match sst.smallest_key.as_slice() {
+                b"apple" => assert_eq!(size, 16),
+                _ => assert!(size > 16),
+            }
+        }
+        drop(store);
+
+        let store = dir.open().await;
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"tim cook".to_vec()
+        );
+        assert_eq!(
+            store.get("banana").await.unwrap().unwrap().val,
+            b"sundar pichai".to_vec()
+        );
+        assert!(store.get("cherry").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_loads_filters_on_first_read() {
+        let dir = StoreDir::new("store_test_filters_for_hits");
+        let mut store = dir.open().await.with_optimize_filters_for_hits(true);
+        for table in 0..3 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{}_{}", table, i), format!("value_{}", i))
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+        assert_eq!(store.stats().filter_memory, 0);
+
+        let entry = store.get("key_1_7").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"value_7".to_vec());
+        for _ in 0..100 {
+            if !store.key_range.restored_ranges.read().await.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        store.key_range.update_key_range().await;
+
+        // sstables whose key range misses the key keep no filter
+        let loaded_filters = |ranges: &std::collections::HashMap<SstId, crate::key_range::Range>| {
+            ranges
+                .values()
+                .filter(|range| range.sst.filter.as_ref().unwrap().sst_id.is_some())
+                .count()
+        };
+        let loaded = loaded_filters(&*store.key_range.key_ranges.read().await);
+        assert!(loaded > 0 && loaded < 3);
+        assert!(store.stats().filter_memory > 0);
+
+        for table in 0..3 {
+            let entry = store.get(format!("key_{}_3", table)).await.unwrap().unwrap();
+            assert_eq!(entry.val, b"value_3".to_vec());
+        }
+        for _ in 0..100 {
+            if !store.key_range.restored_ranges.read().await.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        store.key_range.update_key_range().await;
+        assert_eq!(loaded_filters(&*store.key_range.key_ranges.read().await), 3);
+        assert!(store.get("key_1_missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_retries_reads_of_sstables_compacted_away() {
+        let dir = StoreDir::new("store_test_vanished_sstable");
+        let mut store = dir.open().await.with_tables_to_merge(2, 32);
+        for table in 0..2 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{:02}", i), format!("value_{}", table))
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+        // a read picks sstables from key ranges before compaction deletes them
+        let stale = store
+            .key_range
+            .filter_sstables_by_key_range(b"key_07")
+            .await
+            .unwrap();
+        assert_eq!(stale.len(), 2);
+        for sst in stale.iter() {
+            sst.data_file.file.node.close().await;
+            sst.index_file.file.node.close().await;
+        }
+        store.run_compaction().await.unwrap();
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 1);
+
+        let mut timer = store.slow_log.foreground("get");
+        let res = store
+            .search_key_in_sstables(b"key_07", stale.to_owned(), &mut timer, false)
+            .await;
+        assert!(res.unwrap_err().is_file_not_found());
+        let val = store
+            .search_sstables(b"key_07", stale, &mut timer, false)
+            .await
+            .unwrap()
+            .unwrap();
+        let entry = store
+            .get_value_from_vlog(val.val_offset, val.created_at, None, &mut timer)
+            .await
+            .unwrap()
+            .unwrap()
+            .0;
+        assert_eq!(&entry.val[..], b"value_1");
+    }
+
+    #[tokio::test]
+    async fn datastore_reads_sstable_blocks_in_range_only() {
+        let dir = StoreDir::new("store_test_block_reads");
+        let mut store = dir.open().await;
+        // even keys only, odd keys fall between stored ones
+        for i in (0..2000).step_by(2) {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        let ranges = store.key_range.range_query_scan("key_0000", "key_1999").await;
+        assert_eq!(ranges.len(), 1);
+        let sst = &ranges[0].sst;
+
+        let entries = sst.range(b"key_1000", b"key_1100").await.unwrap();
+        assert!(entries.len() < 1000);
+        for i in (1000..=1100).step_by(2) {
+            assert!(entries.contains_key(format!("key_{:04}", i).as_bytes()));
+        }
+        // past the last key of the last block
+        assert!(sst.range(b"u", b"z").await.unwrap().is_empty());
+        // head and tail entries are stored with user entries
+        assert_eq!(sst.range(b"a", b"z").await.unwrap().len(), 1002);
+
+        // lookups stop at the end of the block the key would be in
+        let offset = sst.get_block_offset("key_1001").await.unwrap().unwrap();
+        assert!(sst.get(offset, "key_1001").await.unwrap().is_none());
+        assert!(sst.get(offset, "key_1002").await.unwrap().is_some());
+
+        let mut iter = store.seek(b"key_1001", b"key_1009").await.unwrap();
+        let mut keys = Vec::new();
+        while let Some(entry) = iter.next().await.unwrap() {
+            keys.push(entry.key);
+        }
+        assert_eq!(keys.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn datastore_profiles_sampled_reads() {
+        let dir = StoreDir::new("store_test_read_profile");
+        let mut store = dir
+            .open()
+            .await
+            .with_read_sampling(1, std::time::Duration::from_secs(60));
+        store.put("user:1", "tim cook").await.unwrap();
+        store.put("item:1", "iphone").await.unwrap();
+        store.force_flush().await.unwrap();
+        store.put("user:2", "sundar pichai").await.unwrap();
+
+        store.get("user:1").await.unwrap();
+        store.get("user:2").await.unwrap();
+        store.get("item:1").await.unwrap();
+        let profile = store.read_profile();
+        assert_eq!(profile.sampled_reads, 3);
+        assert_eq!(profile.prefixes[0], (b"user".to_vec(), 2));
+        // the memtable served user:2
+        assert_eq!(profile.sstables.len(), 1);
+        assert_eq!(profile.sstables[0].1, 2);
+        assert_eq!(profile.buckets[0].1, 2);
+    }
+
+    #[tokio::test]
+    async fn datastore_warms_up_from_saved_access_profile() {
+        let dir = StoreDir::new("store_test_warm_up");
+        let mut store = dir
+            .open()
+            .await
+            .with_read_sampling(1, std::time::Duration::from_secs(60));
+        for table in ["user", "item", "cart"] {
+            for i in 0..20 {
+                store
+                    .put(format!("{}:{}", table, i), format!("value_{}", i))
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+        for i in 0..5 {
+            store.get(format!("user:{}", i)).await.unwrap().unwrap();
+        }
+        // closing saves the sampled reads
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = dir.open().await.with_optimize_filters_for_hits(true);
+        assert_eq!(store.stats().filter_memory, 0);
+        let options = WarmUpOptions {
+            rate: usize::MAX,
+            ..Default::default()
+        };
+        let report = store.warm_up(options.clone()).await.unwrap();
+        assert_eq!(report.filters_loaded, 3);
+        assert_eq!(report.index_lookups, 6);
+        assert_eq!(report.hot_prefixes, 1);
+        assert!(report.bytes_read > 0);
+        assert!(store.stats().filter_memory > 0);
+
+        // everything is warm already
+        let report = store.warm_up(options).await.unwrap();
+        assert_eq!((report.filters_loaded, report.index_lookups), (0, 0));
+        let entry = store.get("user:3").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"value_3".to_vec());
+        assert!(store.get("user:missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_tracks_sstable_hotness_from_reads() {
+        let dir = StoreDir::new("store_test_hotness");
+        let mut store = dir.open().await;
+        store.put("apple", "tim cook").await.unwrap();
+        store.force_flush().await.unwrap();
+        store.put("banana", "sundar pichai").await.unwrap();
+        store.force_flush().await.unwrap();
+
+        // writes no longer heat up tables
+        let files = store.live_files().await.unwrap();
+        assert!(files.sstables.iter().all(|sst| sst.hotness == 0));
+
+        for _ in 0..4 * HOTNESS_SAMPLE_EVERY {
+            store.get("apple").await.unwrap();
+        }
+        let files = store.live_files().await.unwrap();
+        let hotness = |key: &[u8]| {
+            files
+                .sstables
+                .iter()
+                .find(|sst| sst.smallest_key == key)
+                .unwrap()
+                .hotness
+        };
+        assert_eq!(hotness(b"apple"), 4);
+        assert_eq!(hotness(b"banana"), 0);
+    }
+
+    #[tokio::test]
+    async fn datastore_row_cache_invalidated_by_writes() {
+        let dir = StoreDir::new("store_test_row_cache");
+        let store = dir.open().await.with_row_cache_size(64);
+        store.put("apple", "tim cook").await.unwrap();
+        assert!(store.row_cache.get("apple").is_none());
+
+        let entry = store.get("apple").await.unwrap().unwrap();
+        assert_eq!(
+            store.row_cache.get("apple").map(|e| e.val.to_vec()),
+            Some(entry.val)
+        );
+
+        store.update("apple", "steve jobs").await.unwrap();
+        assert!(store.row_cache.get("apple").is_none());
+        let entry = store.get("apple").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"steve jobs".to_vec());
+
+        store.delete("apple").await.unwrap();
+        assert!(store.row_cache.get("apple").is_none());
+        assert!(store.get("apple").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_get_ref_shares_cached_value() {
+        let dir = StoreDir::new("store_test_get_ref");
+        let store = dir.open().await.with_row_cache_size(64);
+        store.put("apple", "tim cook").await.unwrap();
+
+        let first = store.get_ref("apple").await.unwrap().unwrap();
+        let second = store.get_ref("apple").await.unwrap().unwrap();
+        assert_eq!(&first.val[..], b"tim cook");
+        // the cached buffer is handed out instead of being copied
+        assert_eq!(first.val.as_ptr(), second.val.as_ptr());
+
+        let entry = store.get("apple").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"tim cook".to_vec());
+        assert!(store.get_ref("google").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn datastore_range_scan_reads_ahead() {
+        let dir = StoreDir::new("store_test_range_scan");
+        let mut store = dir.open().await.with_vlog_read_ahead_size(4);
+        for i in 0..20 {
+            store
+                .put(format!("key_{:02}", i), format!("val_{}", i))
+                .await
+                .unwrap();
+        }
+        store.delete("key_07").await.unwrap();
+        store.update("key_09", "updated").await.unwrap();
+
+        let mut iter = store.seek(b"key_05", b"key_14").await.unwrap();
+        let mut fetched = Vec::new();
+        while let Some(entry) = iter.next().await.unwrap() {
+            fetched.push((entry.key, entry.val.to_vec()));
+        }
+        let mut expected = Vec::new();
+        for i in (5..15).filter(|i| *i != 7) {
+            let val = if i == 9 {
+                "updated".to_string()
+            } else {
+                format!("val_{}", i)
+            };
+            expected.push((format!("key_{:02}", i).into_bytes(), val.into_bytes()));
+        }
+        assert_eq!(fetched, expected);
+        assert!(iter.vlog_reads < fetched.len());
+
+        // read-ahead can be turned off for a single scan
+        let mut iter = store
+            .seek(b"key_05", b"key_14")
+            .await
+            .unwrap()
+            .with_read_ahead(false);
+        while iter.next().await.unwrap().is_some() {}
+        assert_eq!(iter.vlog_reads, fetched.len());
+
+        // without read-ahead every value is read on its own
+        store.config.vlog_read_ahead_size = 0;
+        let mut iter = store.seek(b"key_05", b"key_14").await.unwrap();
+        while iter.next().await.unwrap().is_some() {}
+        assert_eq!(iter.vlog_reads, fetched.len());
+    }
+
+    #[tokio::test]
+    async fn datastore_range_scan_skips_shadowed_sstables() {
+        let dir = StoreDir::new("store_test_range_scan_shadowed");
+        let mut store = dir.open().await;
+        store.put("filler", "value").await.unwrap();
+        store.force_flush().await.unwrap();
+        for table in 0..2 {
+            for i in 0..10 {
+                store
+                    .put(format!("m_{:02}", table * 10 + i), "old")
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+        store.update("m_09", "new").await.unwrap();
+
+        // the first m_ sstable only overlaps the scan at m_09, which the memtable resolves
+        let mut iter = store.seek(b"m_09", b"m_15").await.unwrap();
+        let mut fetched = Vec::new();
+        while let Some(entry) = iter.next().await.unwrap() {
+            fetched.push((entry.key, entry.val.to_vec()));
+        }
+        let mut expected = vec![(b"m_09".to_vec(), b"new".to_vec())];
+        for i in 10..16 {
+            expected.push((format!("m_{}", i).into_bytes(), b"old".to_vec()));
+        }
+        assert_eq!(fetched, expected);
+        assert_eq!(iter.skipped_sstables, 1);
+
+        let iter = store.seek(b"m_10", b"m_15").await.unwrap();
+        assert_eq!(iter.skipped_sstables, 0);
+    }
+
+    #[tokio::test]
+    async fn datastore_applies_filter_false_positive_tiers() {
+        let dir = StoreDir::new("store_test_filter_tiers");
+        let clock = MockClock::new(chrono::Utc::now());
+        let store = DataStore::open("test", dir.path)
+            .await
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_manual_background_mode(true)
+            .with_max_buffer_write_number(1)
+            .with_tables_to_merge(2, 32)
+            .with_filter_false_positive_tiers(vec![(1024 * 1024, 0.5), (0, 0.1)]);
+        for table in 0..2 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{:02}", i), format!("value_{}", table))
+                    .await
+                    .unwrap();
+            }
+            store.migrate_memtable_to_read_only();
+            clock.advance(std::time::Duration::from_secs(1));
+        }
+        store.tick_flush().await.unwrap();
+        store.tick_compaction().await.unwrap();
+
+        let mut rates = Vec::new();
+        for bucket in store.buckets.read().await.buckets.values() {
+            for sst in bucket.sstables.read().await.iter() {
+                rates.push(sst.filter.as_ref().unwrap().false_positive_rate);
+            }
+        }
+        // small sstables fall into the lowest tier both at flush and compaction time
+        assert_eq!(rates, vec![0.1]);
+        for i in 0..20 {
+            assert_eq!(
+                store.get(format!("key_{:02}", i)).await.unwrap().unwrap().val,
+                b"value_1".to_vec()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_counts_keys_in_range() {
+        let dir = StoreDir::new("store_test_count_range");
+        let mut store = dir.open().await;
+        for i in 0..1000 {
+            store.put(format!("key_{:04}", i), "value").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        for i in 100..150 {
+            store.delete(format!("key_{:04}", i)).await.unwrap();
+        }
+        store.put("key_0200", "updated").await.unwrap();
+
+        assert_eq!(
+            store.count_range(b"key_0000", b"key_0499", true).await.unwrap(),
+            450
+        );
+        assert_eq!(
+            store.count_range(b"key_0000", b"key_9999", true).await.unwrap(),
+            950
+        );
+        assert_eq!(
+            store.count_range(b"key_2000", b"key_9999", true).await.unwrap(),
+            0
+        );
+
+        // estimates come from the index, memtable tombstones are not subtracted from sstables
+        let estimate = store.count_range(b"key_0000", b"key_0499", false).await.unwrap();
+        assert!((450..=700).contains(&estimate), "estimate {}", estimate);
+        let estimate = store.count_range(b"key_0000", b"key_9999", false).await.unwrap();
+        assert!((950..=1005).contains(&estimate), "estimate {}", estimate);
+        // estimates are block granular, the last block also holds the tail entry
+        let estimate = store.count_range(b"key_2000", b"key_9999", false).await.unwrap();
+        assert!(estimate < 50, "estimate {}", estimate);
+    }
+
+    #[tokio::test]
+    async fn datastore_range_scan_suppresses_deleted_and_expired_entries() {
+        let dir = StoreDir::new("store_test_range_scan_suppressed");
+        let start = chrono::DateTime::from_timestamp_millis(4_102_444_800_000).unwrap();
+        let clock = MockClock::new(start);
+        let mut store = dir
+            .open()
+            .await
+            .with_clock(clock.clone())
+            .with_enable_ttl(true)
+            .with_entry_ttl(std::time::Duration::from_secs(3 * 24 * 60 * 60));
+        for i in 0..10 {
+            store.put(format!("key_{}", i), "old").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        // tombstones flushed to a newer sstable hide the older versions
+        store.delete("key_3").await.unwrap();
+        store.delete("key_4").await.unwrap();
+        store.force_flush().await.unwrap();
+
+        clock.advance(std::time::Duration::from_secs(4 * 24 * 60 * 60));
+        store.put("key_5", "new").await.unwrap();
+        store.put("key_6", "new").await.unwrap();
+
+        // old versions have expired
+        let mut iter = store.seek(b"key_0", b"key_9").await.unwrap();
+        let mut fetched = Vec::new();
+        while let Some(entry) = iter.next().await.unwrap() {
+            fetched.push((entry.key, entry.val.to_vec()));
+        }
+        assert_eq!(
+            fetched,
+            vec![
+                (b"key_5".to_vec(), b"new".to_vec()),
+                (b"key_6".to_vec(), b"new".to_vec())
+            ]
+        );
+
+        store.config.enable_ttl = false;
+        let mut iter = store.seek(b"key_0", b"key_9").await.unwrap();
+        let mut keys = Vec::new();
+        while let Some(entry) = iter.next().await.unwrap() {
+            keys.push(entry.key);
+        }
+        let expected: Vec<Vec<u8>> = [0, 1, 2, 5, 6, 7, 8, 9]
+            .iter()
+            .map(|i| format!("key_{}", i).into_bytes())
+            .collect();
+        assert_eq!(keys, expected);
+    }
+}
@@ -0,0 +1,472 @@
+#[cfg(test)]
+mod tests {
+    use crate::consts::{
+        DEFAULT_RECOVERY_PARALLELISM, FORMAT_VERSION, HEAD_CHECKPOINT_FILE_NAME, META_FEATURE_COMPRESSION,
+        META_FEATURE_VLOG_CHECKSUM,
+    };
+    use crate::db::{Comparator, DataStore, SizeUnit};
+    use crate::err::Error;
+    use crate::fs::FileAsync;
+    use crate::sst::{SstId, Table};
+    use crate::tests::fixture::StoreDir;
+
+    #[tokio::test]
+    async fn datastore_recover_after_torn_vlog_write() {
+        let dir = StoreDir::new("store_test_torn_vlog");
+        let store = dir.open().await;
+        store.put("apple", "tim cook").await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.val_log.read().await.sync_to_disk().await.unwrap();
+        let vlog_size = store.val_log.read().await.size;
+        // half written record header
+        store
+            .val_log
+            .read()
+            .await
+            .content
+            .file
+            .node
+            .write_all(&[7, 0, 0])
+            .await
+            .unwrap();
+        drop(store);
+
+        let store = dir.open().await;
+        assert_eq!(store.val_log.read().await.size, vlog_size);
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"tim cook".to_vec()
+        );
+        assert_eq!(
+            store.get("google").await.unwrap().unwrap().val,
+            b"sundar pichai".to_vec()
+        );
+
+        store.put("nvidia", "jensen huang").await.unwrap();
+        assert_eq!(
+            store.get("nvidia").await.unwrap().unwrap().val,
+            b"jensen huang".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_checkpoints_head_before_memtable_fills() {
+        let dir = StoreDir::new("store_test_head_checkpoint");
+        let mut store = dir.open().await.with_head_checkpoint_size(1);
+        let initial_head = store.val_log.read().await.head_offset;
+
+        // far below write buffer size but above the checkpoint size
+        for i in 0..50 {
+            store
+                .put(format!("key_{}", i), "value of the entry")
+                .await
+                .unwrap();
+        }
+        // checkpoints are written in background and none starts while one is
+        // written, the write after the last one finished starts another if it is due
+        drop(store.head_checkpoint.lock().await);
+        store.put("key_0", "value of the entry").await.unwrap();
+        drop(store.head_checkpoint.lock().await);
+        // checkpoints leave the memtable and head in place
+        assert_eq!(store.val_log.read().await.head_offset, initial_head);
+        assert!(store.read_only_memtables.is_empty());
+        assert!(store.active_memtable.read().unwrap().get("key_0").is_some());
+        let checkpoint_offset = {
+            let meta = store.meta.lock().unwrap();
+            assert!(meta.has_checkpoint());
+            meta.checkpoint_offset
+        };
+        assert!(checkpoint_offset > initial_head);
+        assert!(store.dir.meta.join(HEAD_CHECKPOINT_FILE_NAME).exists());
+        assert!(
+            store.val_log.read().await.size - store.head_checkpoint.lock().await.last_vlog_size
+                < SizeUnit::Kilobytes.as_bytes(1)
+        );
+
+        // checkpoints are skipped when disabled
+        store.config.head_checkpoint_size = 0;
+        for i in 50..60 {
+            store
+                .put(format!("key_{}", i), "value of the entry")
+                .await
+                .unwrap();
+        }
+        assert_eq!(store.meta.lock().unwrap().checkpoint_offset, checkpoint_offset);
+        drop(store);
+
+        // without a clean close recovery loads the checkpoint and replays value log written after it
+        let store = dir.open().await;
+        assert_eq!(store.meta.lock().unwrap().checkpoint_offset, checkpoint_offset);
+        for i in 0..60 {
+            let entry = store.get(format!("key_{}", i)).await.unwrap().unwrap();
+            assert_eq!(entry.val, b"value of the entry".to_vec());
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_reopens_sstable_files_beyond_open_files_limit() {
+        let dir = StoreDir::new("store_test_open_files_limit");
+        let mut store = dir.open().await.with_open_files_limit(4);
+        for table in 0..6 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{}_{}", table, i), format!("value_{}", i))
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 6);
+        store.active_memtable.read().unwrap().entries.clear();
+
+        // every sstable is read at least twice, closing and reopening files in between
+        for _ in 0..2 {
+            for table in 0..6 {
+                let entry = store.get(format!("key_{}_7", table)).await.unwrap().unwrap();
+                assert_eq!(entry.val, b"value_7".to_vec());
+            }
+        }
+        let closed = store
+            .key_range
+            .key_ranges
+            .read()
+            .await
+            .values()
+            .filter(|range| !range.sst.data_file.file.node.file.try_read().unwrap().is_open())
+            .count();
+        assert!(closed > 0);
+        let _ = store.with_open_files_limit(0);
+    }
+
+    #[tokio::test]
+    async fn datastore_recovers_sstables_in_parallel() {
+        let dir = StoreDir::new("store_test_parallel_recovery");
+        let mut store = dir.open().await;
+        for table in 0..6 {
+            for i in 0..20 {
+                store
+                    .put(format!("key_{}_{}", table, i), format!("value_{}", i))
+                    .await
+                    .unwrap();
+            }
+            store.force_flush().await.unwrap();
+        }
+        let mut tables: Vec<_> = store.key_range.key_ranges.read().await.keys().cloned().collect();
+        tables.sort();
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = dir.open().await.with_recovery_parallelism(2);
+        drop(store);
+        // fewer tasks than sstables, so some wait for a slot
+        let store = dir
+            .open()
+            .await
+            .with_recovery_parallelism(DEFAULT_RECOVERY_PARALLELISM);
+        let mut recovered: Vec<_> = store.key_range.key_ranges.read().await.keys().cloned().collect();
+        recovered.sort();
+        assert_eq!(recovered, tables);
+        let buckets = store.buckets.read().await;
+        let mut bucket_tables = 0;
+        for bucket in buckets.buckets.values() {
+            let sstables = bucket.sstables.read().await;
+            // tables of a bucket are kept in the order they were written
+            let numbers: Vec<_> = sstables
+                .iter()
+                .map(|table| Table::file_number_of(&table.dir).unwrap())
+                .collect();
+            assert!(numbers.windows(2).all(|pair| pair[0] < pair[1]));
+            bucket_tables += sstables.len();
+        }
+        assert_eq!(bucket_tables, 6);
+        drop(buckets);
+        for table in 0..6 {
+            let entry = store.get(format!("key_{}_7", table)).await.unwrap().unwrap();
+            assert_eq!(entry.val, b"value_7".to_vec());
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_skips_and_deletes_orphaned_sstables() {
+        let dir = StoreDir::new("store_test_orphans");
+        let mut store = dir.open().await;
+        store.put("apple", "tim cook").await.unwrap();
+        store.force_flush().await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.force_flush().await.unwrap();
+        let live = store.live_files().await.unwrap();
+        assert_eq!(live.sstables.len(), 2);
+
+        // compaction dropped the first sstable from the manifest but crashed before deleting it
+        let obsolete = live.sstables[0].dir.to_owned();
+        store
+            .buckets
+            .write()
+            .await
+            .publish(&[], &[obsolete.to_owned()])
+            .await
+            .unwrap();
+        drop(store);
+
+        let store = dir.open().await;
+        let recovered = store.live_files().await.unwrap();
+        assert_eq!(recovered.sstables.len(), 1);
+        assert_eq!(recovered.sstables[0].dir, live.sstables[1].dir);
+        assert!(store
+            .key_range
+            .key_ranges
+            .read()
+            .await
+            .get(&SstId::of(&obsolete).unwrap())
+            .is_none());
+        assert!(store.get("google").await.unwrap().is_some());
+
+        // orphans are kept until the grace period elapses
+        assert_eq!(store.delete_orphan_files().await.unwrap(), 0);
+        assert!(obsolete.exists());
+        let store = store.with_orphan_file_grace_period(std::time::Duration::from_secs(0));
+        assert_eq!(store.delete_orphan_files().await.unwrap(), 1);
+        assert!(!obsolete.exists());
+        assert!(live.sstables[1].dir.exists());
+    }
+
+    #[tokio::test]
+    async fn datastore_ignores_partially_written_sstables() {
+        let dir = StoreDir::new("store_test_temp_sstables");
+        let mut store = dir.open().await;
+        store.put("apple", "tim cook").await.unwrap();
+        store.force_flush().await.unwrap();
+        let live = store.live_files().await.unwrap();
+        assert_eq!(live.sstables.len(), 1);
+        let sst = &live.sstables[0];
+        // flush renamed its temporary directory away
+        assert!(sst.dir.extension().is_none());
+        drop(store);
+
+        // crash while flushing leaves a temporary directory with a truncated data file
+        let partial = sst.dir.with_extension("tmp");
+        tokio::fs::create_dir_all(&partial).await.unwrap();
+        for file in sst.files.iter() {
+            tokio::fs::copy(file, partial.join(file.file_name().unwrap()))
+                .await
+                .unwrap();
+        }
+        let data = sst
+            .files
+            .iter()
+            .find(|f| f.extension().unwrap() == "data")
+            .unwrap();
+        tokio::fs::write(partial.join(data.file_name().unwrap()), [1, 2, 3])
+            .await
+            .unwrap();
+
+        let store = dir
+            .open()
+            .await
+            .with_orphan_file_grace_period(std::time::Duration::from_secs(0));
+        let recovered = store.live_files().await.unwrap();
+        assert_eq!(recovered.sstables.len(), 1);
+        assert_eq!(recovered.sstables[0].dir, sst.dir);
+        assert!(store.get("apple").await.unwrap().is_some());
+        assert_eq!(store.delete_orphan_files().await.unwrap(), 1);
+        assert!(!partial.exists());
+    }
+
+    #[tokio::test]
+    async fn datastore_numbers_sstables_from_manifest() {
+        let dir = StoreDir::new("store_test_file_numbers");
+        let mut store = dir.open().await;
+        // flushes within the same millisecond still get distinct directories
+        store.put("apple", "tim cook").await.unwrap();
+        store.force_flush().await.unwrap();
+        store.put("google", "sundar pichai").await.unwrap();
+        store.force_flush().await.unwrap();
+        let live = store.live_files().await.unwrap();
+        let mut numbers: Vec<_> = live
+            .sstables
+            .iter()
+            .map(|sst| Table::file_number_of(&sst.dir).unwrap())
+            .collect();
+        numbers.sort();
+        assert_eq!(numbers, vec![0, 1]);
+        assert!(live
+            .sstables
+            .iter()
+            .any(|sst| sst.dir.ends_with("sstable_000001")));
+        drop(store);
+
+        // number of a table left behind by a crash is not handed out again
+        let bucket_dir = live.sstables[0].dir.parent().unwrap().to_owned();
+        tokio::fs::create_dir_all(bucket_dir.join("sstable_000005.tmp"))
+            .await
+            .unwrap();
+        let mut store = dir.open().await;
+        store.put("nvidia", "jensen huang").await.unwrap();
+        store.force_flush().await.unwrap();
+        let live = store.live_files().await.unwrap();
+        assert_eq!(live.sstables.len(), 3);
+        assert!(live
+            .sstables
+            .iter()
+            .any(|sst| Table::file_number_of(&sst.dir) == Some(6)));
+        assert!(store.get("apple").await.unwrap().is_some());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn datastore_reopens_through_symlink() {
+        let dir = StoreDir::new("bucket_store");
+        let mut store = dir.open().await;
+        store.put("apple", "tim cook").await.unwrap();
+        store.force_flush().await.unwrap();
+        drop(store);
+
+        let link = dir.root.path().join("bucket_link");
+        std::os::unix::fs::symlink(&dir.path, &link).unwrap();
+        let store = DataStore::open_without_background("test", link).await.unwrap();
+        assert_eq!(store.live_files().await.unwrap().sstables.len(), 1);
+        let entry = store.get("apple").await.unwrap().unwrap();
+        assert_eq!(std::str::from_utf8(&entry.val).unwrap(), "tim cook");
+    }
+
+    #[tokio::test]
+    async fn datastore_skips_replay_after_clean_shutdown() {
+        let dir = StoreDir::new("store_test_clean_shutdown");
+        let mut store = dir.open().await;
+        for i in 0..10 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+        }
+        store.close().await.unwrap();
+        assert!(!store.meta.lock().unwrap().clean_shutdown);
+        drop(store);
+
+        let store = dir.open().await;
+        // nothing was replayed into memtables, entries are read from sstables
+        assert_eq!(store.len_of_entries_in_memtable(), 0);
+        assert_eq!(store.meta.lock().unwrap().format_version, FORMAT_VERSION);
+        assert_eq!(store.meta.lock().unwrap().config_hash, store.config.fingerprint());
+        assert!(!store.meta.lock().unwrap().clean_shutdown);
+        assert!(store.get("key_9").await.unwrap().is_some());
+
+        // writes after reopen are replayed after a crash, the first one included
+        store.put("after", "close").await.unwrap();
+        store.put("after_2", "close").await.unwrap();
+        drop(store);
+        let store = dir.open().await;
+        assert_eq!(store.len_of_entries_in_memtable(), 2);
+        let entry = store.get("after").await.unwrap().unwrap();
+        assert_eq!(std::str::from_utf8(&entry.val).unwrap(), "close");
+        assert!(store.get("key_0").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn datastore_persists_store_and_user_metadata() {
+        let dir = StoreDir::new("store_test_meta");
+        let mut store = DataStore::open_with_comparator("test", dir.path.to_owned(), Comparator::Reverse)
+            .await
+            .unwrap();
+        let info = store.store_info();
+        assert_eq!(info.format_version, FORMAT_VERSION);
+        assert_eq!(info.comparator, "reverse");
+        assert!(!info.compression && !info.encryption);
+        store.set_meta("schema", "v1").await.unwrap();
+        store.set_meta("owner", "billing").await.unwrap();
+        store.set_meta("schema", "v2").await.unwrap();
+        assert!(store.delete_meta("owner").await.unwrap());
+        assert!(!store.delete_meta("owner").await.unwrap());
+        // user metadata is not an entry
+        assert!(store.get("schema").await.unwrap().is_none());
+        store.close().await.unwrap();
+        drop(store);
+
+        let mut store = DataStore::open_with_comparator("test", dir.path.to_owned(), Comparator::Reverse)
+            .await
+            .unwrap();
+        assert_eq!(store.get_meta("schema"), Some(b"v2".to_vec()));
+        assert!(store.get_meta("owner").is_none());
+        let recovered = store.store_info();
+        assert_eq!(recovered.comparator, info.comparator);
+        // meta records times in milliseconds
+        assert_eq!(
+            recovered.created_at.timestamp_millis(),
+            info.created_at.timestamp_millis()
+        );
+        assert_eq!(store.meta.lock().unwrap().comparator.as_deref(), Some("reverse"));
+
+        // stores using features this version cannot read are not opened
+        store.meta.lock().unwrap().features = META_FEATURE_COMPRESSION;
+        store.close().await.unwrap();
+        drop(store);
+        assert!(matches!(
+            DataStore::open_with_comparator("test", dir.path, Comparator::Reverse).await,
+            Err(Error::UnsupportedStoreFeatures(META_FEATURE_COMPRESSION))
+        ));
+    }
+
+    #[tokio::test]
+    async fn datastore_writes_vlog_checksums_of_new_stores() {
+        let dir = StoreDir::new("store_test_vlog_checksums");
+        let store = dir.open().await;
+        assert_ne!(
+            store.meta.lock().unwrap().features & META_FEATURE_VLOG_CHECKSUM,
+            0
+        );
+        store.put("apple", "tim cook").await.unwrap();
+        drop(store);
+
+        // reopened before meta was written, the tail record tells checksums are on
+        let mut store = dir.open().await;
+        assert!(store.val_log.read().await.checksums);
+        store.put("google", "sundar").await.unwrap();
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = dir.open().await;
+        assert!(store.val_log.read().await.checksums);
+        let entries = store.val_log.write().await.recover(0).await.unwrap();
+        assert!(entries.iter().all(|e| e.checksummed));
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"tim cook".to_vec()
+        );
+        assert_eq!(
+            store.get("google").await.unwrap().unwrap().val,
+            b"sundar".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_gates_and_upgrades_format_version() {
+        let dir = StoreDir::new("store_test_format");
+        let mut store = dir.open().await;
+        store.put("apple", "tim cook").await.unwrap();
+        // pretend the store predates format versioning
+        store.meta.lock().unwrap().format_version = 0;
+        store.close().await.unwrap();
+        drop(store);
+
+        // older stores open as they are until upgraded
+        let mut store = dir.open().await;
+        assert_eq!(store.store_info().format_version, 0);
+        assert_eq!(store.upgrade_format().await.unwrap(), FORMAT_VERSION);
+        assert_eq!(store.upgrade_format().await.unwrap(), FORMAT_VERSION);
+        drop(store);
+
+        let mut store = dir.open().await;
+        assert_eq!(store.store_info().format_version, FORMAT_VERSION);
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().val,
+            b"tim cook".to_vec()
+        );
+
+        // stores written by a newer version are not opened
+        store.meta.lock().unwrap().format_version = FORMAT_VERSION + 1;
+        store.close().await.unwrap();
+        drop(store);
+        assert!(matches!(
+            DataStore::open_without_background("test", dir.path).await,
+            Err(Error::UnsupportedFormatVersion { found, supported })
+                if found == FORMAT_VERSION + 1 && supported == FORMAT_VERSION
+        ));
+    }
+}
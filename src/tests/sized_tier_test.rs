@@ -209,6 +209,40 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_merge_ssts_in_buckets_reads_flushed_sstables() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("flushed_bucket");
+        let bucket = Bucket::new(path.to_owned()).await.unwrap();
+        let sst_samples = SSTContructor::generate_ssts(3).await;
+        let mut expected_keys = std::collections::HashSet::new();
+        for s in sst_samples.iter() {
+            let mut loaded = s.to_owned();
+            loaded.load_entries_from_file().await.unwrap();
+            expected_keys.extend(loaded.entries.iter().map(|e| e.key().to_owned()));
+            // flushed sstables keep their entries on disk only
+            assert!(s.entries.is_empty());
+            bucket.sstables.write().await.push(s.to_owned())
+        }
+
+        let root = tempdir().unwrap();
+        let path = root.path().join("bucket_map_new");
+        let mut bucket_map = BucketMap::new(path.to_owned()).await.unwrap();
+        bucket_map.buckets.insert(uuid::Uuid::new_v4(), bucket.to_owned());
+        let config = &generate_config();
+        let mut sized_tier_compaction_runner = SizedTierRunner::new(
+            Arc::new(RwLock::new(bucket_map)),
+            Arc::new(KeyRange::default()),
+            config,
+        );
+        let merged = sized_tier_compaction_runner
+            .merge_ssts_in_buckets(&[bucket])
+            .await
+            .unwrap();
+        // entries of the first sstable used to be left out of the merge
+        assert_eq!(merged[0].sstable.get_entries().len(), expected_keys.len());
+    }
+
     #[tokio::test]
     async fn test_run_compaction() {
         let root = tempdir().unwrap();
@@ -238,7 +272,6 @@ mod tests {
             sst.filter = Some(filter);
             key_range
                 .set(
-                    s.dir.to_owned(),
                     sst.entries.front().unwrap().key(),
                     sst.entries.back().unwrap().key(),
                     sst.to_owned(),
@@ -259,7 +292,6 @@ mod tests {
             sst.filter = Some(filter);
             key_range
                 .set(
-                    s.dir.to_owned(),
                     sst.entries.front().unwrap().key(),
                     sst.entries.back().unwrap().key(),
                     sst.to_owned(),
@@ -280,7 +312,6 @@ mod tests {
             sst.filter = Some(filter);
             key_range
                 .set(
-                    s.dir.to_owned(),
                     sst.entries.front().unwrap().key(),
                     sst.entries.back().unwrap().key(),
                     sst.to_owned(),
@@ -301,7 +332,6 @@ mod tests {
             sst.filter = Some(filter);
             key_range
                 .set(
-                    s.dir.to_owned(),
                     sst.entries.front().unwrap().key(),
                     sst.entries.back().unwrap().key(),
                     sst.to_owned(),
@@ -322,7 +352,6 @@ mod tests {
             sst.filter = Some(filter);
             key_range
                 .set(
-                    s.dir.to_owned(),
                     sst.entries.front().unwrap().key(),
                     sst.entries.back().unwrap().key(),
                     sst.to_owned(),
@@ -432,7 +461,6 @@ mod tests {
             sst.filter = Some(filter);
             key_range
                 .set(
-                    s.dir.to_owned(),
                     sst.entries.front().unwrap().key(),
                     sst.entries.back().unwrap().key(),
                     sst.to_owned(),
@@ -453,7 +481,6 @@ mod tests {
             sst.filter = Some(filter);
             key_range
                 .set(
-                    s.dir.to_owned(),
                     sst.entries.front().unwrap().key(),
                     sst.entries.back().unwrap().key(),
                     sst.to_owned(),
@@ -474,7 +501,6 @@ mod tests {
             sst.filter = Some(filter);
             key_range
                 .set(
-                    s.dir.to_owned(),
                     sst.entries.front().unwrap().key(),
                     sst.entries.back().unwrap().key(),
                     sst.to_owned(),
@@ -495,7 +521,6 @@ mod tests {
             sst.filter = Some(filter);
             key_range
                 .set(
-                    s.dir.to_owned(),
                     sst.entries.front().unwrap().key(),
                     sst.entries.back().unwrap().key(),
                     sst.to_owned(),
@@ -516,7 +541,6 @@ mod tests {
             sst.filter = Some(filter);
             key_range
                 .set(
-                    s.dir.to_owned(),
                     sst.entries.front().unwrap().key(),
                     sst.entries.back().unwrap().key(),
                     sst.to_owned(),
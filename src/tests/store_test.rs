@@ -1,48 +1,97 @@
 #[cfg(test)]
 mod tests {
-    use crate::db::DataStore;
-    use crate::tests::*;
+    use crate::db::{Comparator, DataStore, MockClock};
+    use crate::err::Error;
+    use crate::tests::fixture::{copy_dir, StoreDir};
+    use crate::tests::workload::Workload;
     use futures::future::join_all;
-    use std::path::PathBuf;
+    use std::path::Path;
     use std::sync::Arc;
-    use tempfile::tempdir;
     use tokio::sync::RwLock;
-    use workload::Workload;
 
-    fn setup() {
-        let _ = env_logger::builder().is_test(true).try_init();
-    }
     #[tokio::test]
     async fn datastore_create_new() {
-        setup();
-        let root = tempdir().unwrap();
-        let path = root.path().join("store_test_1");
-        let store = DataStore::open_without_background("test", path.clone()).await;
+        let dir = StoreDir::new("store_test_1");
+        let store = DataStore::open_without_background("test", dir.path.clone()).await;
         assert!(store.is_ok())
     }
 
     #[tokio::test]
     async fn datastore_recover() {
-        setup();
-        let path = PathBuf::new().join("src/tests/fixtures/data");
+        // recovery writes a manifest, open a copy so the checked-in fixture stays untouched
+        let dir = StoreDir::new("data");
+        copy_dir(Path::new("src/tests/fixtures/data"), &dir.path);
 
-        let store = DataStore::open_without_background("test", path.clone())
-            .await
-            .unwrap();
+        let store = dir.open().await;
 
         assert!(!store.buckets.read().await.buckets.is_empty());
         assert!(!store.key_range.key_ranges.read().await.is_empty());
-        assert!(!store.active_memtable.entries.is_empty());
+        assert!(!store.active_memtable.read().unwrap().entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn datastore_timestamps_entries_with_configured_clock() {
+        let dir = StoreDir::new("store_test_clock");
+        let start = chrono::DateTime::from_timestamp_millis(4_102_444_800_000).unwrap();
+        let clock = MockClock::new(start);
+        let store = dir.open().await.with_clock(clock.clone());
+
+        store.put("apple", "tim cook").await.unwrap();
+        let entry = store.get("apple").await.unwrap().unwrap();
+        assert_eq!(entry.created_at, start);
+
+        clock.advance(std::time::Duration::from_secs(60));
+        store.update("apple", "steve jobs").await.unwrap();
+        let entry = store.get("apple").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"steve jobs".to_vec());
+        assert_eq!(entry.created_at, start + chrono::Duration::seconds(60));
+    }
+
+    #[tokio::test]
+    async fn datastore_versions_writes_within_same_millisecond() {
+        let dir = StoreDir::new("store_test_hlc");
+        let start = chrono::DateTime::from_timestamp_millis(4_102_444_800_000).unwrap();
+        let clock = MockClock::new(start);
+        let mut store = dir.open().await.with_clock(clock.clone());
+
+        store.put("apple", "tim cook").await.unwrap();
+        let first = store.get("apple").await.unwrap().unwrap();
+        // clock did not move, the later write still wins
+        store.put("apple", "steve jobs").await.unwrap();
+        let second = store.get("apple").await.unwrap().unwrap();
+        assert_eq!(second.val, b"steve jobs".to_vec());
+        assert_eq!(first.version().physical, second.version().physical);
+        assert!(second.version() > first.version());
+
+        // versions survive a flush
+        store.force_flush().await.unwrap();
+        let flushed = store.get_ref("apple").await.unwrap().unwrap();
+        assert_eq!(flushed.version(), second.version());
+        assert_eq!(&flushed.val[..], b"steve jobs");
+    }
+
+    #[tokio::test]
+    async fn datastore_returns_increasing_sequence_numbers() {
+        let dir = StoreDir::new("store_test_sequence_numbers");
+        let mut store = dir.open().await;
+        let first = store.put("apple", "tim cook").await.unwrap().seq;
+        let second = store.put("google", "sundar pichai").await.unwrap().seq;
+        let third = store.delete("apple").await.unwrap().seq;
+        assert!(first < second && second < third);
+        assert!(store.latest_sequence() >= third);
+        store.close().await.unwrap();
+        drop(store);
+
+        // sequence numbers keep increasing after a restart
+        let store = dir.open().await;
+        assert!(store.latest_sequence() >= third);
+        assert!(store.put("nvidia", "jensen huang").await.unwrap().seq > third);
     }
 
     #[tokio::test]
     async fn datastore_put_test() {
-        setup();
-        let root = tempdir().unwrap();
-        let path = root.path().join("store_test_2");
-        let store = DataStore::open_without_background("test", path.clone())
-            .await
-            .unwrap();
+        let dir = StoreDir::new("store_test_2");
+        let store = dir.open().await;
         let workload_size = 10000;
         let key_len = 5;
         let val_len = 5;
@@ -56,7 +105,7 @@ mod tests {
             let key = e.0.to_owned();
             let val = e.1.to_owned();
             tokio::spawn(async move {
-                let mut writer = store_inner.write().await;
+                let writer = store_inner.write().await;
                 writer.put(key, val).await
             })
         });
@@ -65,16 +114,14 @@ mod tests {
         for tokio_res in all_results {
             assert!(tokio_res.is_ok());
             assert!(tokio_res.as_ref().unwrap().is_ok());
-            assert!(tokio_res.unwrap().unwrap());
+            assert!(tokio_res.unwrap().unwrap().seq > 0);
         }
     }
 
     #[tokio::test]
     async fn datastore_test_put_and_get() {
-        setup();
-        let root = tempdir().unwrap();
-        let path = root.path().join("store_test_get_put");
-        let store = DataStore::open_without_background("test", path).await.unwrap();
+        let dir = StoreDir::new("store_test_get_put");
+        let store = dir.open().await;
         let workload_size = 5000;
         let key_len = 5;
         let val_len = 5;
@@ -87,7 +134,7 @@ mod tests {
             let key = e.0.to_owned();
             let val = e.1.to_owned();
             tokio::spawn(async move {
-                let mut value = store_inner.write().await;
+                let value = store_inner.write().await;
                 value.put(key, val).await
             })
         });
@@ -96,7 +143,7 @@ mod tests {
         for tokio_res in all_results {
             assert!(tokio_res.is_ok());
             assert!(tokio_res.as_ref().unwrap().is_ok());
-            assert!(tokio_res.unwrap().unwrap());
+            assert!(tokio_res.unwrap().unwrap().seq > 0);
         }
 
         let read_tasks = read_workload.keys().map(|e| {
@@ -123,12 +170,8 @@ mod tests {
 
     #[tokio::test]
     async fn datastore_test_put_and_get_concurrent() {
-        setup();
-        let root = tempdir().unwrap();
-        let path = root.path().join("store_test_4");
-        let store = DataStore::open_without_background("test", path.clone())
-            .await
-            .unwrap();
+        let dir = StoreDir::new("store_test_4");
+        let store = dir.open().await;
         let workload_size = 1;
         let key_len = 5;
         let val_len = 5;
@@ -146,7 +189,7 @@ mod tests {
         entry4.val = b"val4".to_vec();
         entry5.val = b"val5".to_vec();
 
-        let concurrent_write_workload = vec![entry1, entry2, entry3, entry4, entry5.to_owned()];
+        let concurrent_write_workload = [entry1, entry2, entry3, entry4, entry5.to_owned()];
         let store_ref = Arc::new(RwLock::new(store));
 
         let concurrent_write_tasks = concurrent_write_workload.iter().map(|e| {
@@ -154,7 +197,7 @@ mod tests {
             let key = e.key.to_owned();
             let val = e.val.to_owned();
             tokio::spawn(async move {
-                let mut value = store_inner.write().await;
+                let value = store_inner.write().await;
                 value.put(key, val).await
             })
         });
@@ -163,7 +206,7 @@ mod tests {
         for tokio_res in all_results {
             assert!(tokio_res.is_ok());
             assert!(tokio_res.as_ref().unwrap().is_ok());
-            assert!(tokio_res.unwrap().unwrap());
+            assert!(tokio_res.unwrap().unwrap().seq > 0);
         }
 
         let res = store_ref
@@ -178,12 +221,8 @@ mod tests {
 
     #[tokio::test]
     async fn datastore_test_seqential_put() {
-        setup();
-        let root = tempdir().unwrap();
-        let path = root.path().join("store_test_5");
-        let mut store = DataStore::open_without_background("test", path.clone())
-            .await
-            .unwrap();
+        let dir = StoreDir::new("store_test_5");
+        let store = dir.open().await;
         let workload_size = 10000;
         let key_len = 5;
         let val_len = 5;
@@ -195,18 +234,14 @@ mod tests {
             let val = e.1.to_owned();
             let res = store.put(key, val).await;
             assert!(res.is_ok());
-            assert!(res.unwrap());
+            assert!(res.unwrap().seq > 0);
         }
     }
 
     #[tokio::test]
     async fn datastore_test_sequential_put_and_get() {
-        setup();
-        let root = tempdir().unwrap();
-        let path = root.path().join("store_test_6");
-        let mut store = DataStore::open_without_background("test", path.clone())
-            .await
-            .unwrap();
+        let dir = StoreDir::new("store_test_6");
+        let store = dir.open().await;
         let workload_size = 5000;
         let key_len = 5;
         let val_len = 5;
@@ -217,7 +252,7 @@ mod tests {
         for e in write_workload.iter() {
             let res = store.put(e.key.to_owned(), e.val.to_owned()).await;
             assert!(res.is_ok());
-            assert!(res.unwrap());
+            assert!(res.unwrap().seq > 0);
         }
         for e in read_workload.iter() {
             let res = store.get(&e.key).await;
@@ -228,12 +263,8 @@ mod tests {
 
     #[tokio::test]
     async fn datastore_not_found() {
-        setup();
-        let root = tempdir().unwrap();
-        let path = root.path().join("store_test_7");
-        let store = DataStore::open_without_background("test", path.clone())
-            .await
-            .unwrap();
+        let dir = StoreDir::new("store_test_7");
+        let store = dir.open().await;
         let workload_size = 10000;
         let key_len = 5;
         let val_len = 5;
@@ -255,12 +286,8 @@ mod tests {
 
     #[tokio::test]
     async fn datastore_compaction() {
-        setup();
-        let root = tempdir().unwrap();
-        let path = root.path().join("store_test_8");
-        let store = DataStore::open_without_background("test", path.clone())
-            .await
-            .unwrap();
+        let dir = StoreDir::new("store_test_8");
+        let store = dir.open().await;
         let workload_size = 10000;
         let key_len = 5;
         let val_len = 5;
@@ -293,7 +320,7 @@ mod tests {
 
         let res = store_ref.write().await.delete(key1).await;
         assert!(res.is_ok());
-        assert!(res.unwrap());
+        assert!(res.unwrap().seq > 0);
 
         let res = store_ref.read().await.get(key1).await;
         assert!(res.is_ok());
@@ -317,12 +344,8 @@ mod tests {
 
     #[tokio::test]
     async fn datastore_update() {
-        setup();
-        let root = tempdir().unwrap();
-        let path = root.path().join("store_test_9");
-        let store = DataStore::open_without_background("test", path.clone())
-            .await
-            .unwrap();
+        let dir = StoreDir::new("store_test_9");
+        let store = dir.open().await;
         let workload_size = 10000;
         let key_len = 5;
         let val_len = 5;
@@ -345,7 +368,7 @@ mod tests {
 
         let res = store_ref.write().await.update(key1, &updated_value).await;
         assert!(res.is_ok());
-        assert!(res.unwrap());
+        assert!(res.unwrap().seq > 0);
 
         let res = store_ref.read().await.get(key1).await;
         assert!(res.is_ok());
@@ -370,12 +393,8 @@ mod tests {
 
     #[tokio::test]
     async fn datastore_deletion() {
-        setup();
-        let root = tempdir().unwrap();
-        let path = root.path().join("store_test_10");
-        let store = DataStore::open_without_background("test", path.clone())
-            .await
-            .unwrap();
+        let dir = StoreDir::new("store_test_10");
+        let store = dir.open().await;
         let workload_size = 10000;
         let key_len = 5;
         let val_len = 5;
@@ -396,7 +415,7 @@ mod tests {
 
         let res = store_ref.write().await.delete(key1).await;
         assert!(res.is_ok());
-        assert!(res.unwrap());
+        assert!(res.unwrap().seq > 0);
 
         let res = store_ref.write().await.force_flush().await;
         assert!(res.is_ok());
@@ -412,4 +431,43 @@ mod tests {
         assert!(res.is_ok());
         assert!(res.unwrap().is_none());
     }
+
+    #[tokio::test]
+    async fn datastore_orders_keys_with_comparator() {
+        let dir = StoreDir::new("store_test_comparator");
+        let mut store = DataStore::open_with_comparator("test", dir.path.to_owned(), Comparator::Reverse)
+            .await
+            .unwrap();
+        for key in ["apple", "banana", "cherry", "date"] {
+            store.put(key, "fruit").await.unwrap();
+        }
+        store.force_flush().await.unwrap();
+        store.put("blueberry", "fruit").await.unwrap();
+        assert!(store.get("banana").await.unwrap().is_some());
+
+        let mut iter = store.seek(b"cherry", b"apple").await.unwrap();
+        let mut keys = Vec::new();
+        while let Some(entry) = iter.next().await.unwrap() {
+            keys.push(String::from_utf8(entry.key).unwrap());
+        }
+        assert_eq!(keys, vec!["cherry", "blueberry", "banana", "apple"]);
+        drop(iter);
+        drop(store);
+
+        let res = DataStore::open_without_background("test", dir.path.to_owned()).await;
+        assert!(matches!(res, Err(Error::ComparatorMismatch { .. })));
+        let store = DataStore::open_with_comparator("test", dir.path.to_owned(), Comparator::Reverse)
+            .await
+            .unwrap();
+        assert!(store.get("date").await.unwrap().is_some());
+
+        let dir = StoreDir::new("store_test_case_insensitive");
+        let store = DataStore::open_with_comparator("test", dir.path, Comparator::CaseInsensitive)
+            .await
+            .unwrap();
+        store.put("Apple", "tim cook").await.unwrap();
+        store.put("APPLE", "steve jobs").await.unwrap();
+        let entry = store.get("apple").await.unwrap().unwrap();
+        assert_eq!(entry.val, b"steve jobs".to_vec());
+    }
 }
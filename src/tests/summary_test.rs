@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
-    use crate::consts::{SIZE_OF_U32, SUMMARY_FILE_NAME};
-    use crate::sst::Summary;
+    use crate::consts::{SIZE_OF_U32, SIZE_OF_U64, SUMMARY_FILE_NAME};
+    use crate::sst::{Summary, TableProperties};
     use crate::tests::workload::SSTContructor;
     use tempfile::tempdir;
 
@@ -12,8 +12,8 @@ mod tests {
 
         let summary = Summary::new(path.to_owned());
 
-        assert_eq!(summary.smallest_key, vec![]);
-        assert_eq!(summary.biggest_key, vec![]);
+        assert_eq!(summary.smallest_key, Vec::<u8>::new());
+        assert_eq!(summary.biggest_key, Vec::<u8>::new());
         assert_eq!(summary.path, path.join(format!("{}.db", SUMMARY_FILE_NAME)));
     }
 
@@ -31,8 +31,14 @@ mod tests {
     #[tokio::test]
     async fn test_summary_write() {
         let sst = SSTContructor::generate_ssts(1).await[0].to_owned();
+        // write to a copy so the checked-in fixture stays untouched
+        let root = tempdir().unwrap();
+        let file_name = format!("{}.db", SUMMARY_FILE_NAME);
+        tokio::fs::copy(sst.dir.join(&file_name), root.path().join(&file_name))
+            .await
+            .unwrap();
 
-        let mut recovered_summary = Summary::new(sst.dir.to_owned());
+        let mut recovered_summary = Summary::new(root.path().to_owned());
         let res = recovered_summary.recover().await;
         assert!(res.is_ok());
         assert!(recovered_summary.write_to_file().await.is_ok())
@@ -47,10 +53,53 @@ mod tests {
         summary.biggest_key = vec![1, 2, 3];
         summary.smallest_key = vec![0, 2, 3];
 
-        let expected_entry_len =
-            SIZE_OF_U32 + SIZE_OF_U32 + summary.biggest_key.len() + summary.smallest_key.len();
+        let expected_entry_len = SIZE_OF_U32
+            + SIZE_OF_U32
+            + summary.biggest_key.len()
+            + summary.smallest_key.len()
+            + SIZE_OF_U32
+            + SIZE_OF_U32
+            + SIZE_OF_U64
+            + SIZE_OF_U64
+            + SIZE_OF_U64
+            + SIZE_OF_U32;
         let serialized_entry = summary.serialize();
 
         assert_eq!(serialized_entry.len(), expected_entry_len);
     }
+
+    #[tokio::test]
+    async fn test_summary_recover_properties() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("summary_properties");
+        tokio::fs::create_dir_all(&path).await.unwrap();
+
+        let mut summary = Summary::new(path.to_owned());
+        summary.smallest_key = b"apple".to_vec();
+        summary.biggest_key = b"nvidia".to_vec();
+        summary.properties = TableProperties {
+            entry_count: 20,
+            tombstone_count: 3,
+            data_size: 4096,
+            oldest_entry: chrono::DateTime::from_timestamp_millis(1_000),
+            newest_entry: chrono::DateTime::from_timestamp_millis(5_000),
+            data_checksum: Some(0xdead_beef),
+        };
+        summary.write_to_file().await.unwrap();
+        let mut recovered = Summary::new(path.to_owned());
+        recovered.recover().await.unwrap();
+        assert_eq!(recovered.properties, summary.properties);
+
+        // summaries written before properties were recorded only hold the keys
+        let old_path = root.path().join("summary_keys_only");
+        tokio::fs::create_dir_all(&old_path).await.unwrap();
+        let keys_len = SIZE_OF_U32 + SIZE_OF_U32 + b"apple".len() + b"nvidia".len();
+        let mut old = Summary::new(old_path.to_owned());
+        tokio::fs::write(&old.path, &summary.serialize()[..keys_len])
+            .await
+            .unwrap();
+        old.recover().await.unwrap();
+        assert_eq!(old.biggest_key, b"nvidia".to_vec());
+        assert_eq!(old.properties, TableProperties::default());
+    }
 }
@@ -1,7 +1,9 @@
 #[cfg(test)]
 mod tests {
-    use crate::consts::{SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8};
-    use crate::vlog::{ValueLog, ValueLogEntry};
+    use crate::consts::{SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, WRITE_BUFFER_SIZE};
+    use crate::db::{DataStore, SizeUnit};
+    use crate::fs::FileAsync;
+    use crate::vlog::{AppendLink, RecordHeader, RecordType, ValueLog, ValueLogEntry};
     use chrono::Utc;
     use tempfile::tempdir;
 
@@ -62,16 +64,14 @@ mod tests {
         // get first key
         let recvoered = vlog.get(start_offset1).await;
         assert!(recvoered.is_ok());
-        assert!(recvoered.as_ref().unwrap().is_some());
-        let (value, is_tomb) = recvoered.unwrap().unwrap();
+        let (value, is_tomb) = recvoered.unwrap();
         assert_eq!(value, val1.as_bytes().to_vec());
         assert_eq!(is_tomb, is_tombstone);
 
         // get second key
         let recvoered = vlog.get(start_offset2).await;
         assert!(recvoered.is_ok());
-        assert!(recvoered.as_ref().unwrap().is_some());
-        let (value, is_tomb) = recvoered.unwrap().unwrap();
+        let (value, is_tomb) = recvoered.unwrap();
         assert_eq!(value, val2.as_bytes().to_vec());
         assert_eq!(is_tomb, is_tombstone_true);
     }
@@ -119,6 +119,214 @@ mod tests {
         assert_eq!(entries.unwrap().len(), 2)
     }
 
+    #[tokio::test]
+    async fn test_recover_truncates_torn_record() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_recover_torn");
+
+        let mut vlog = ValueLog::new(path.to_owned()).await.unwrap();
+        let time = Utc::now();
+        vlog.append("key1", "val1", time, false).await.unwrap();
+        vlog.append("key2", "val2", time, false).await.unwrap();
+        let valid_len = vlog.size;
+
+        // simulate a crash in the middle of an append
+        let torn = ValueLogEntry::new(4, 4, b"key3".to_vec(), b"val3".to_vec(), time, false).serialize();
+        vlog.content
+            .file
+            .node
+            .write_all(&torn[..torn.len() - 3])
+            .await
+            .unwrap();
+
+        let mut vlog = ValueLog::new(path.to_owned()).await.unwrap();
+        assert_eq!(vlog.size, valid_len + torn.len() - 3);
+        let entries = vlog.recover(0).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(vlog.size, valid_len);
+        assert_eq!(vlog.content.file.node.size().await, valid_len);
+
+        // appends continue after the last complete record
+        let offset = vlog.append("key3", "val3", time, false).await.unwrap();
+        assert_eq!(offset, valid_len);
+        let entries = vlog.recover(0).await.unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].value, b"val3".to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_recover_truncates_at_invalid_record() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_recover_invalid");
+
+        let mut vlog = ValueLog::new(path.to_owned()).await.unwrap();
+        vlog.checksums = true;
+        let time = Utc::now();
+        vlog.append("key1", "val1", time, false).await.unwrap();
+        let valid_len = vlog.size;
+        let unknown = vlog
+            .append_record(&b"key2"[..], &b"val2"[..], time, RecordType::Unknown(100))
+            .await
+            .unwrap();
+        vlog.append("key3", "val3", time, false).await.unwrap();
+
+        // records after an invalid one are dropped along with it
+        let mut reopened = ValueLog::new(path.to_owned()).await.unwrap();
+        let entries = reopened.recover(0).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(reopened.size, unknown);
+        assert_eq!(reopened.content.file.node.size().await, valid_len);
+
+        // so are records that do not match their checksum
+        reopened.checksums = true;
+        let corrupted = reopened.append("key2", "val2", time, false).await.unwrap();
+        reopened.append("key3", "val3", time, false).await.unwrap();
+        let mut bytes = std::fs::read(&reopened.content.path).unwrap();
+        bytes[corrupted + RecordHeader::LEN] ^= 1;
+        std::fs::write(&reopened.content.path, bytes).unwrap();
+        let mut vlog = ValueLog::new(path.to_owned()).await.unwrap();
+        assert_eq!(vlog.recover(0).await.unwrap().len(), 1);
+        assert_eq!(vlog.size, valid_len);
+
+        // and records whose lengths run past the end of value log
+        let mut header = Vec::new();
+        header.extend_from_slice(&4u32.to_le_bytes());
+        header.extend_from_slice(&u32::MAX.to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes());
+        header.push(RecordType::Put.as_byte());
+        vlog.content.file.node.write_all(&header).await.unwrap();
+        let mut vlog = ValueLog::new(path).await.unwrap();
+        assert_eq!(vlog.recover(0).await.unwrap().len(), 1);
+        assert_eq!(vlog.size, valid_len);
+    }
+
+    #[tokio::test]
+    async fn test_get_torn_record() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_get_torn");
+
+        let mut vlog = ValueLog::new(path).await.unwrap();
+        let time = Utc::now();
+        let offset = vlog.append("key1", "val1", time, false).await.unwrap();
+        let torn = ValueLogEntry::new(4, 4, b"key2".to_vec(), b"val2".to_vec(), time, false).serialize();
+        let torn_offset = vlog.size;
+        vlog.content
+            .file
+            .node
+            .write_all(&torn[..torn.len() - 3])
+            .await
+            .unwrap();
+
+        let (value, _) = vlog.get(offset).await.unwrap();
+        assert_eq!(&value[..], b"val1");
+        assert!(matches!(
+            vlog.get(torn_offset).await,
+            Err(crate::err::Error::VLogTruncated { offset, .. }) if offset == torn_offset
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_separates_tombstone_from_invalid_records() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_get_invalid");
+
+        let mut vlog = ValueLog::new(path).await.unwrap();
+        let time = Utc::now();
+        let put = vlog.append("key1", "val1", time, false).await.unwrap();
+        let deleted = vlog.append("key1", "*", time, true).await.unwrap();
+        assert_eq!(&vlog.get_value(put).await.unwrap()[..], b"val1");
+        assert!(matches!(
+            vlog.get_value(deleted).await,
+            Err(crate::err::Error::VLogTombstone(offset)) if offset == deleted
+        ));
+
+        // zeroed space, e.g. freed by garbage collection
+        let zeroed = vlog.size;
+        let header_len = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8;
+        vlog.content
+            .file
+            .node
+            .write_all(&vec![0; header_len])
+            .await
+            .unwrap();
+        vlog.size += header_len;
+        assert!(matches!(
+            vlog.get(zeroed).await,
+            Err(crate::err::Error::VLogRecordCorrupted { offset, .. }) if offset == zeroed
+        ));
+
+        let unknown = vlog
+            .append_record(&b"key2"[..], &b"val2"[..], time, RecordType::Unknown(100))
+            .await
+            .unwrap();
+        assert!(matches!(
+            vlog.get_value(unknown).await,
+            Err(crate::err::Error::VLogRecordCorrupted { offset, .. }) if offset == unknown
+        ));
+
+        // stale offset past the end of value log
+        let end = vlog.size;
+        assert!(matches!(
+            vlog.get(end).await,
+            Err(crate::err::Error::VLogTruncated { offset, .. }) if offset == end
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_rejects_lengths_past_end_of_file() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_get_lengths");
+
+        let mut vlog = ValueLog::new(path).await.unwrap();
+        vlog.append("key1", "val1", Utc::now(), false).await.unwrap();
+
+        // a stale offset whose bytes decode to a huge value is refused before it is allocated
+        let stale = vlog.size;
+        let mut header = Vec::new();
+        header.extend_from_slice(&4u32.to_le_bytes());
+        header.extend_from_slice(&u32::MAX.to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes());
+        header.push(RecordType::Put.as_byte());
+        header.extend_from_slice(b"key2");
+        vlog.content.file.node.write_all(&header).await.unwrap();
+        vlog.size += header.len();
+        assert!(matches!(
+            vlog.get(stale).await,
+            Err(crate::err::Error::VLogTruncated { offset, .. }) if offset == stale
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_verifies_checksum() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_get_checksum");
+
+        let mut vlog = ValueLog::new(path).await.unwrap();
+        let time = Utc::now();
+        let plain = vlog.append("key1", "val1", time, false).await.unwrap();
+        vlog.checksums = true;
+        let checked = vlog.append("key2", "val2", time, false).await.unwrap();
+        let intact = vlog.append("key3", "val3", time, false).await.unwrap();
+        assert_eq!(intact - checked, RecordHeader::LEN + 8 + SIZE_OF_U32);
+
+        // records written before checksums were enabled are still read
+        assert_eq!(&vlog.get_value(plain).await.unwrap()[..], b"val1");
+        assert_eq!(&vlog.get_value(checked).await.unwrap()[..], b"val2");
+
+        // flip a bit of the value of the second record
+        let mut bytes = std::fs::read(&vlog.content.path).unwrap();
+        bytes[checked + RecordHeader::LEN + 4] ^= 1;
+        std::fs::write(&vlog.content.path, bytes).unwrap();
+        assert!(matches!(
+            vlog.get(checked).await,
+            Err(crate::err::Error::VLogRecordCorrupted { offset, .. }) if offset == checked
+        ));
+        let buffer = vlog.read_ahead(0, vlog.size).await.unwrap();
+        assert!(buffer.get(checked, time).is_none());
+        assert_eq!(&buffer.get(intact, time).unwrap().0[..], b"val3");
+        assert_eq!(&vlog.get_value(intact).await.unwrap()[..], b"val3");
+    }
+
     #[tokio::test]
     async fn test_read_chunk_to_garbage_collect() {
         let root = tempdir().unwrap();
@@ -227,4 +435,148 @@ mod tests {
 
         assert_eq!(serialized_entry.len(), expected_entry_len);
     }
+
+    #[tokio::test]
+    async fn test_append_record_type() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_record_type");
+
+        let mut vlog = ValueLog::new(path).await.unwrap();
+        let time = Utc::now();
+        vlog.append_record("tail", "tail", time, RecordType::Tail)
+            .await
+            .unwrap();
+        vlog.append("key1", "val1", time, false).await.unwrap();
+        vlog.append("key2", "*", time, true).await.unwrap();
+        vlog.append_record("head", "head", time, RecordType::Head)
+            .await
+            .unwrap();
+
+        let entries = vlog.recover(0).await.unwrap();
+        let record_types: Vec<RecordType> = entries.iter().map(|e| e.record_type).collect();
+        assert_eq!(
+            record_types,
+            vec![
+                RecordType::Tail,
+                RecordType::Put,
+                RecordType::Delete,
+                RecordType::Head
+            ]
+        );
+        assert!(entries[2].is_tombstone);
+        assert!(!entries[0].is_tombstone);
+    }
+
+    #[tokio::test]
+    async fn test_record_type_tags() {
+        for tag in 0..=7 {
+            assert_eq!(RecordType::from(tag).as_byte(), tag);
+        }
+        assert_eq!(RecordType::from(0), RecordType::Put);
+        assert_eq!(RecordType::from(1), RecordType::Delete);
+        assert_eq!(RecordType::from(6), RecordType::ValueRef);
+        assert_eq!(RecordType::from(7), RecordType::Append);
+        assert_eq!(RecordType::from(42), RecordType::Unknown(42));
+        assert!(RecordType::Delete.is_user_entry());
+        assert!(!RecordType::Head.is_user_entry());
+        assert!(RecordType::Append.is_user_entry());
+    }
+
+    #[tokio::test]
+    async fn test_get_resolves_append_chain() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_append_chain");
+
+        let mut vlog = ValueLog::new(path).await.unwrap();
+        let time = Utc::now();
+        let base = vlog.append("key", "base", time, false).await.unwrap();
+        let link = AppendLink {
+            previous: Some(base),
+            depth: 1,
+        };
+        let first = vlog
+            .append_record(&b"key"[..], &link.encode(b"-one")[..], time, RecordType::Append)
+            .await
+            .unwrap();
+        let link = AppendLink {
+            previous: Some(first),
+            depth: 2,
+        };
+        let second = vlog
+            .append_record(&b"key"[..], &link.encode(b"-two")[..], time, RecordType::Append)
+            .await
+            .unwrap();
+        let (value, is_tombstone) = vlog.get(second).await.unwrap();
+        assert_eq!(&value[..], b"base-one-two");
+        assert!(!is_tombstone);
+
+        let link = AppendLink {
+            previous: None,
+            depth: 1,
+        };
+        let fresh = vlog
+            .append_record(&b"other"[..], &link.encode(b"only")[..], time, RecordType::Append)
+            .await
+            .unwrap();
+        assert_eq!(&vlog.get(fresh).await.unwrap().0[..], b"only");
+
+        let deleted = vlog.append("gone", "*", time, true).await.unwrap();
+        let link = AppendLink {
+            previous: Some(deleted),
+            depth: 1,
+        };
+        let broken = vlog
+            .append_record(&b"gone"[..], &link.encode(b"x")[..], time, RecordType::Append)
+            .await
+            .unwrap();
+        assert!(matches!(
+            vlog.get(broken).await,
+            Err(crate::err::Error::BrokenAppendChain(offset)) if offset == deleted
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_recover_memtable_interprets_record_types() {
+        let root = tempdir().unwrap();
+        let path = root.path().join("vlog_recover_record_types");
+
+        let mut vlog = ValueLog::new(path.to_owned()).await.unwrap();
+        let time = Utc::now();
+        let head_offset = vlog.append("flushed", "val", time, false).await.unwrap();
+        vlog.append("apple", "tim cook", time, false).await.unwrap();
+        vlog.append_record("head", "head", time, RecordType::Head)
+            .await
+            .unwrap();
+        vlog.append_record("", "", time, RecordType::BatchBegin)
+            .await
+            .unwrap();
+        vlog.append("google", "sundar pichai", time, false).await.unwrap();
+        vlog.append_record("", "", time, RecordType::BatchCommit)
+            .await
+            .unwrap();
+        vlog.append_record("", "", time, RecordType::BatchBegin)
+            .await
+            .unwrap();
+        vlog.append("nvidia", "jensen huang", time, false).await.unwrap();
+
+        let (active_memtable, read_only_memtables) = DataStore::recover_memtable(
+            SizeUnit::Bytes,
+            WRITE_BUFFER_SIZE,
+            1e-4,
+            path,
+            head_offset,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(read_only_memtables.is_empty());
+        assert!(active_memtable.get("apple").is_some());
+        // committed batch is replayed
+        assert!(active_memtable.get("google").is_some());
+        // checkpoints and uncommitted batches are not
+        assert!(active_memtable.get("head").is_none());
+        assert!(active_memtable.get("nvidia").is_none());
+        assert!(active_memtable.get("flushed").is_none());
+    }
 }
@@ -1,6 +1,6 @@
 use crate::filter::BloomFilter;
 use crate::memtable::SkipMapValue;
-use crate::sst::{DataFile, Summary};
+use crate::sst::{DataFile, Hotness, SstId, Summary, TableFiles};
 use crate::{
     db::DataStore,
     err::Error,
@@ -99,7 +99,7 @@ impl Workload {
             tokio::spawn(async move {
                 let key_str = std::str::from_utf8(&key).unwrap();
                 let val_str = std::str::from_utf8(&val).unwrap();
-                let mut value = s_engine.write().await;
+                let value = s_engine.write().await;
                 value.put(key_str, val_str).await
             })
         });
@@ -273,8 +273,11 @@ SSTContructor::new(
         for i in 0..number {
             let idx = i as usize;
             ssts.push(Table {
+                id: SstId::of(&sst_contructor[idx].dir).unwrap(),
                 dir: sst_contructor[idx].dir.to_owned(),
-                hotness: 100,
+                files: TableFiles::legacy(&sst_contructor[idx].dir),
+                hotness: Hotness::new(100),
+                cipher: None,
                 size: 4096,
                 created_at: Utc::now(),
                 data_file: DataFile {
@@ -284,7 +287,8 @@ SSTContructor::new(
                             file: Arc::new(RwLock::new(
                                 File::open(sst_contructor[idx].data_path.to_owned())
                                     .await
-                                    .unwrap(),
+                                    .unwrap()
+                                    .into(),
                             )),
                             file_type: FileType::Data,
                         },
@@ -298,7 +302,8 @@ SSTContructor::new(
                             file: Arc::new(RwLock::new(
                                 File::open(sst_contructor[idx].index_path.to_owned())
                                     .await
-                                    .unwrap(),
+                                    .unwrap()
+                                    .into(),
                             )),
                             file_type: FileType::Index,
                         },
@@ -311,6 +316,7 @@ SSTContructor::new(
                     ..Default::default()
                 }),
                 summary: Some(Summary::new(sst_contructor[idx].summary_path.to_owned())),
+                index_cache: Default::default(),
             })
         }
         ssts
@@ -0,0 +1,751 @@
+#[cfg(test)]
+mod tests {
+    use crate::consts::APPEND_CHAIN_MAX_DEPTH;
+    use crate::db::{DataStore, Db, InterceptedWrite, MockClock, WriteBatch, WriteInterceptor};
+    use crate::err::Error;
+    use crate::gc::garbage_collector::GC;
+    use crate::tests::fixture::StoreDir;
+    use serde::{Deserialize, Serialize};
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn datastore_writes_batches_atomically() {
+        let dir = StoreDir::new("store_test_write_batch");
+        let mut store = dir.open().await;
+        let before = store.put("apple", "tim cook").await.unwrap().seq;
+
+        let mut batch = WriteBatch::new();
+        batch
+            .put("google", "sundar pichai")
+            .delete("apple")
+            .put("nvidia", "jensen")
+            .put("nvidia", "jensen huang")
+            .delete("missing");
+        assert_eq!(batch.len(), 5);
+        let seq = store.write_batch(batch).await.unwrap().seq;
+        assert!(seq > before);
+        assert_eq!(store.latest_sequence(), seq);
+
+        // an invalid entry rejects the whole batch
+        let mut batch = WriteBatch::new();
+        batch.put("amazon", "andy jassy").put("", "empty key");
+        assert!(store.write_batch(batch).await.is_err());
+        assert!(store.get("amazon").await.unwrap().is_none());
+
+        assert!(store.get("apple").await.unwrap().is_none());
+        assert_eq!(
+            store.get("google").await.unwrap().unwrap().val,
+            b"sundar pichai".to_vec()
+        );
+        assert_eq!(
+            store.get("nvidia").await.unwrap().unwrap().val,
+            b"jensen huang".to_vec()
+        );
+        store.close().await.unwrap();
+        drop(store);
+
+        // batch entries survive a restart
+        let store = dir.open().await;
+        assert!(store.get("apple").await.unwrap().is_none());
+        assert_eq!(
+            store.get("google").await.unwrap().unwrap().val,
+            b"sundar pichai".to_vec()
+        );
+        assert_eq!(
+            store.get("nvidia").await.unwrap().unwrap().val,
+            b"jensen huang".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_batches_ttl_puts_merges_and_range_deletes() {
+        let dir = StoreDir::new("store_test_batch_ops");
+        let clock = MockClock::new(chrono::Utc::now());
+        let store = dir.open().await.with_clock(clock.clone());
+        store.put("log", "boot;").await.unwrap();
+        for key in ["range_b", "range_d", "range_z"] {
+            store.put(key, "value").await.unwrap();
+        }
+
+        let mut batch = WriteBatch::new();
+        batch
+            .put_with_ttl("session", "token", std::time::Duration::from_secs(10))
+            .merge("session", ";refreshed")
+            .merge("log", "login;")
+            .merge("fresh", "first")
+            .put("range_c", "value")
+            .delete_range("range_a", "range_e")
+            .put("range_e", "value");
+        store.write_batch(batch).await.unwrap();
+        assert_eq!(
+            store.get("session").await.unwrap().unwrap().val,
+            b"token;refreshed"
+        );
+        assert_eq!(store.get("log").await.unwrap().unwrap().val, b"boot;login;");
+        assert_eq!(store.get("fresh").await.unwrap().unwrap().val, b"first");
+        // keys stored or put earlier in the batch within the range are deleted
+        let mut iter = store.seek(b"range_a", b"range_z").await.unwrap();
+        let mut keys = Vec::new();
+        while let Some(entry) = iter.next().await.unwrap() {
+            keys.push(String::from_utf8(entry.key).unwrap());
+        }
+        assert_eq!(keys, vec!["range_e", "range_z"]);
+
+        // appends keep the TTL of the value they extend
+        store.append("session", ";more").await.unwrap();
+        assert_eq!(
+            store.get("session").await.unwrap().unwrap().val,
+            b"token;refreshed;more"
+        );
+        clock.advance(std::time::Duration::from_secs(11));
+        assert!(store.get("session").await.unwrap().is_none());
+        let mut iter = store.seek(b"a", b"z").await.unwrap();
+        while let Some(entry) = iter.next().await.unwrap() {
+            assert_ne!(entry.key, b"session");
+        }
+        assert!(matches!(
+            store.rename("session", "moved").await,
+            Err(Error::NotFoundInDB)
+        ));
+        // an expired value is replaced instead of extended
+        store.append("session", "new").await.unwrap();
+        assert_eq!(store.get("session").await.unwrap().unwrap().val, b"new");
+        assert_eq!(store.get("log").await.unwrap().unwrap().val, b"boot;login;");
+    }
+
+    #[tokio::test]
+    async fn datastore_recovers_batch_ops_after_crash() {
+        let dir = StoreDir::new("store_test_batch_ops_crash");
+        let clock = MockClock::new(chrono::Utc::now());
+        let store = dir.open().await.with_clock(clock.clone());
+        store.put("log", "boot;").await.unwrap();
+        store.put("range_b", "value").await.unwrap();
+        let mut batch = WriteBatch::new();
+        batch
+            .put_with_ttl("session", "token", std::time::Duration::from_secs(10))
+            .merge("log", "login;")
+            .delete_range("range_a", "range_c");
+        store.write_batch(batch).await.unwrap();
+        // dropped without closing, the batch is replayed from the value log
+        drop(store);
+
+        let store = dir.open().await.with_clock(clock.clone());
+        assert_eq!(store.get("session").await.unwrap().unwrap().val, b"token");
+        assert_eq!(store.get("log").await.unwrap().unwrap().val, b"boot;login;");
+        assert!(store.get("range_b").await.unwrap().is_none());
+
+        clock.advance(std::time::Duration::from_secs(11));
+        assert!(store.get("session").await.unwrap().is_none());
+        // GC rewrites the expired value as a tombstone
+        GC::gc_handler(
+            &store.gc.config,
+            store.gc_table(),
+            Arc::clone(&store.gc_log),
+            Arc::clone(&store.key_range),
+            Arc::clone(&store.read_only_memtables),
+            Arc::clone(&store.gc_updated_entries),
+            Arc::clone(&store.gc.punch_marker),
+        )
+        .await
+        .unwrap();
+        let moved = store.gc_updated_entries.read().await;
+        assert!(moved.get(b"session".as_slice()).unwrap().value().is_tombstone);
+        assert!(!moved.get(b"log".as_slice()).unwrap().value().is_tombstone);
+        drop(moved);
+        store.sync_gc_update_with_store().await.unwrap();
+        assert!(store.get("session").await.unwrap().is_none());
+        assert_eq!(store.get("log").await.unwrap().unwrap().val, b"boot;login;");
+    }
+
+    #[tokio::test]
+    async fn datastore_deletes_without_reading() {
+        let dir = StoreDir::new("store_test_blind_delete");
+        let store = dir.open().await;
+        store.put("apple", "tim cook").await.unwrap();
+
+        // deleting an absent key still writes a tombstone
+        let vlog_size = store.val_log.read().await.size;
+        let seq = store.delete("missing").await.unwrap().seq;
+        assert_eq!(store.latest_sequence(), seq);
+        assert!(store.val_log.read().await.size > vlog_size);
+
+        let vlog_size = store.val_log.read().await.size;
+        let res = store.delete_if_exists("missing").await;
+        assert!(matches!(res, Err(Error::NotFoundInDB)));
+        assert_eq!(store.val_log.read().await.size, vlog_size);
+        assert_eq!(store.latest_sequence(), seq);
+
+        assert!(store.delete_if_exists("apple").await.unwrap().seq > seq);
+        assert!(store.get("apple").await.unwrap().is_none());
+        assert!(matches!(
+            store.delete_if_exists("apple").await,
+            Err(Error::NotFoundInDB)
+        ));
+    }
+
+    #[tokio::test]
+    async fn datastore_returns_old_values() {
+        let dir = StoreDir::new("store_test_get_old");
+        let mut store = dir.open().await;
+
+        let (receipt, old) = store.put_get_old("apple", "tim cook").await.unwrap();
+        assert!(old.is_none());
+        assert_eq!(store.latest_sequence(), receipt.seq);
+        let (_, old) = store.put_get_old("apple", "steve jobs").await.unwrap();
+        let old = old.unwrap();
+        assert_eq!(old.val, b"tim cook");
+        assert_eq!(old.version().sequence(), receipt.seq);
+        assert_eq!(old.created_at, receipt.timestamp);
+
+        // previous values are found in SSTables as well
+        store.force_flush().await.unwrap();
+        let (_, old) = store.delete_get_old("apple").await.unwrap();
+        assert_eq!(old.unwrap().val, b"steve jobs");
+        assert!(store.get("apple").await.unwrap().is_none());
+        let (_, old) = store.delete_get_old("apple").await.unwrap();
+        assert!(old.is_none());
+        let (_, old) = store.put_get_old("apple", "tim cook").await.unwrap();
+        assert!(old.is_none());
+        assert!(store.put_get_old("", "empty").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn datastore_reads_old_values_atomically_with_writes() {
+        let dir = StoreDir::new("store_test_get_old_concurrent");
+        let store = Arc::new(dir.open().await);
+
+        let writes = (0..16).map(|i| {
+            let store = Arc::clone(&store);
+            tokio::spawn(async move { store.put_get_old("apple", format!("value_{i}")).await })
+        });
+        let mut replaced = Vec::new();
+        for write in writes.collect::<Vec<_>>() {
+            let (_, old) = write.await.unwrap().unwrap();
+            replaced.push(old.map(|entry| entry.val));
+        }
+        // every value is replaced by exactly one write, the last one is left
+        replaced.push(Some(store.get("apple").await.unwrap().unwrap().val));
+        assert_eq!(replaced.iter().filter(|old| old.is_none()).count(), 1);
+        let mut values: Vec<_> = replaced.into_iter().flatten().collect();
+        values.sort();
+        values.dedup();
+        assert_eq!(values.len(), 16);
+
+        // update only writes keys that exist
+        let vlog_size = store.val_log.read().await.size;
+        assert!(matches!(
+            store.update("missing", "value").await,
+            Err(Error::NotFoundInDB)
+        ));
+        assert_eq!(store.val_log.read().await.size, vlog_size);
+        store.update("apple", "value_16").await.unwrap();
+        assert_eq!(store.get("apple").await.unwrap().unwrap().val, b"value_16");
+    }
+
+    #[tokio::test]
+    async fn datastore_returns_write_receipts() {
+        let dir = StoreDir::new("store_test_write_receipts");
+        let store = dir.open().await;
+
+        let put = store.put("apple", "tim cook").await.unwrap();
+        assert_eq!(put.seq, store.latest_sequence());
+        assert_eq!(put.memtable_id, store.active_memtable.read().unwrap().id);
+        assert_eq!(
+            &store
+                .val_log
+                .read()
+                .await
+                .get_value(put.vlog_offset)
+                .await
+                .unwrap()[..],
+            b"tim cook"
+        );
+        assert_eq!(
+            store.get("apple").await.unwrap().unwrap().created_at,
+            put.timestamp
+        );
+
+        let delete = store.delete("apple").await.unwrap();
+        assert!(delete.seq > put.seq);
+        assert!(delete.vlog_offset > put.vlog_offset);
+        assert!(matches!(
+            store.val_log.read().await.get_value(delete.vlog_offset).await,
+            Err(Error::VLogTombstone(_))
+        ));
+
+        // the memtable keeps its id once sealed
+        store.migrate_memtable_to_read_only();
+        assert!(store.read_only_memtables.contains_key(&put.memtable_id));
+        let next = store.put("google", "sundar pichai").await.unwrap();
+        assert!(next.memtable_id > put.memtable_id);
+    }
+
+    #[tokio::test]
+    async fn datastore_returns_receipts_of_every_write() {
+        let dir = StoreDir::new("store_test_write_receipts_all");
+        let store = dir.open().await;
+        store.put("apple", "tim cook").await.unwrap();
+        let active = store.active_memtable.read().unwrap().id;
+
+        let update = store.update("apple", "steve jobs").await.unwrap();
+        assert_eq!(update.memtable_id, active);
+        let value = store.val_log.read().await.get_value(update.vlog_offset).await;
+        assert_eq!(&value.unwrap()[..], b"steve jobs");
+
+        let append = store.append("apple", ";tim cook").await.unwrap();
+        assert!(append.seq > update.seq);
+        assert_eq!(append.memtable_id, active);
+        assert!(append.vlog_offset > update.vlog_offset);
+
+        // batches point at their commit record, which is written last
+        let size = store.val_log.read().await.size;
+        let mut batch = WriteBatch::new();
+        batch.put("google", "sundar pichai").put("nvidia", "jensen huang");
+        let batch = store.write_batch(batch).await.unwrap();
+        assert_eq!(batch.seq, store.latest_sequence());
+        assert_eq!(batch.memtable_id, active);
+        assert!(batch.vlog_offset > size);
+        assert!(batch.vlog_offset < store.val_log.read().await.size);
+        assert!(store.get("nvidia").await.unwrap().unwrap().created_at < batch.timestamp);
+
+        let rename = store.rename("google", "alphabet").await.unwrap();
+        assert!(rename.seq > batch.seq);
+        assert!(rename.vlog_offset > batch.vlog_offset);
+
+        // writes that write nothing point at the end of the value log
+        let unwritten = store.write_batch(WriteBatch::new()).await.unwrap();
+        assert_eq!(unwritten.seq, rename.seq);
+        assert_eq!(unwritten.vlog_offset, store.val_log.read().await.size);
+        assert_eq!(store.rename("alphabet", "alphabet").await.unwrap(), unwritten);
+    }
+
+    #[tokio::test]
+    async fn datastore_deletes_many_keys() {
+        let dir = StoreDir::new("store_test_delete_many");
+        let mut store = dir.open().await;
+        for i in 0..10 {
+            store.put(format!("key_{}", i), "value").await.unwrap();
+        }
+        let vlog_size = store.val_log.read().await.size;
+        let mut keys: Vec<String> = (0..5).map(|i| format!("key_{}", i)).collect();
+        keys.push("missing".to_string());
+        let seq = store.delete_many(&keys).await.unwrap().seq;
+        assert_eq!(store.latest_sequence(), seq);
+        assert!(store.val_log.read().await.size > vlog_size);
+
+        // an invalid key rejects every delete
+        assert!(store.delete_many(["key_5", ""]).await.is_err());
+        assert_eq!(store.delete_many(Vec::<&str>::new()).await.unwrap().seq, seq);
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = dir.open().await;
+        for i in 0..10 {
+            let entry = store.get(format!("key_{}", i)).await.unwrap();
+            assert_eq!(entry.is_some(), i >= 5);
+        }
+    }
+
+    /// Rejects `json/` values that are not objects, lowercases `user/` keys and logs every write
+    #[derive(Debug, Default)]
+    struct SchemaAudit {
+        log: Arc<Mutex<Vec<InterceptedWrite>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl WriteInterceptor for SchemaAudit {
+        async fn intercept(
+            &self,
+            write: &mut InterceptedWrite,
+        ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            if let InterceptedWrite::Put { key, value } = write {
+                if key.starts_with(b"json/") && !value.starts_with(b"{") {
+                    return Err("value is not a JSON object".into());
+                }
+                if key.starts_with(b"user/") {
+                    key.make_ascii_lowercase();
+                }
+            }
+            self.log.lock().unwrap().push(write.to_owned());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn datastore_applies_write_interceptors() {
+        let dir = StoreDir::new("store_test_write_interceptor");
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let store = dir
+            .open()
+            .await
+            .with_write_interceptor(SchemaAudit { log: log.clone() });
+
+        store.put("json/a", "{\"a\": 1}").await.unwrap();
+        let res = store.put("json/b", "not json").await;
+        assert!(matches!(res, Err(Error::WriteRejected(_))));
+        assert!(store.get("json/b").await.unwrap().is_none());
+
+        store.put("user/ALICE", "admin").await.unwrap();
+        assert!(store.get("user/ALICE").await.unwrap().is_none());
+        assert_eq!(store.get("user/alice").await.unwrap().unwrap().val, b"admin");
+        store.delete("user/alice").await.unwrap();
+        assert!(store.get("user/alice").await.unwrap().is_none());
+
+        // a rejected operation rejects the whole batch
+        let mut batch = WriteBatch::new();
+        batch.put("user/BOB", "guest").put("json/c", "[]");
+        assert!(matches!(
+            store.write_batch(batch).await,
+            Err(Error::WriteRejected(_))
+        ));
+        assert!(store.get("user/bob").await.unwrap().is_none());
+        let mut batch = WriteBatch::new();
+        batch.put("user/BOB", "guest").delete("json/a");
+        store.write_batch(batch).await.unwrap();
+        assert!(store.get("user/bob").await.unwrap().is_some());
+        assert!(store.get("json/a").await.unwrap().is_none());
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                InterceptedWrite::Put {
+                    key: b"json/a".to_vec(),
+                    value: b"{\"a\": 1}".to_vec()
+                },
+                InterceptedWrite::Put {
+                    key: b"user/alice".to_vec(),
+                    value: b"admin".to_vec()
+                },
+                InterceptedWrite::Delete {
+                    key: b"user/alice".to_vec()
+                },
+                InterceptedWrite::Put {
+                    key: b"user/bob".to_vec(),
+                    value: b"guest".to_vec()
+                },
+                InterceptedWrite::Put {
+                    key: b"user/bob".to_vec(),
+                    value: b"guest".to_vec()
+                },
+                InterceptedWrite::Delete {
+                    key: b"json/a".to_vec()
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn datastore_deduplicates_identical_values() {
+        let dir = StoreDir::new("store_test_value_dedup");
+        let blob = vec![b'x'; 1000];
+        let mut store = DataStore::open("test", dir.path.to_owned())
+            .await
+            .unwrap()
+            .with_manual_background_mode(true)
+            .with_value_dedup(64);
+        store.put("a", &blob).await.unwrap();
+        let size = store.val_log.read().await.size;
+        store.put("b", &blob).await.unwrap();
+        store.put("c", &blob).await.unwrap();
+        // small values are stored on their own
+        store.put("d", "tiny").await.unwrap();
+        store.put("e", "tiny").await.unwrap();
+        assert!(store.val_log.read().await.size - size < blob.len());
+        assert_eq!(store.stats().dedup_saved_bytes, 2 * blob.len());
+        for key in ["a", "b", "c"] {
+            assert_eq!(store.get(key).await.unwrap().unwrap().val, blob);
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = DataStore::open("test", dir.path)
+            .await
+            .unwrap()
+            .with_manual_background_mode(true)
+            .with_value_dedup(64);
+        for key in ["a", "b", "c"] {
+            assert_eq!(store.get(key).await.unwrap().unwrap().val, blob);
+        }
+        // payloads written before the restart are shared too
+        store.put("f", &blob).await.unwrap();
+        assert_eq!(store.stats().dedup_saved_bytes, blob.len());
+        assert_eq!(store.get("f").await.unwrap().unwrap().val, blob);
+    }
+
+    #[tokio::test]
+    async fn datastore_renames_keys() {
+        let dir = StoreDir::new("store_test_rename");
+        let blob = vec![b'x'; 1000];
+        let mut store = DataStore::open("test", dir.path.to_owned())
+            .await
+            .unwrap()
+            .with_manual_background_mode(true);
+        store.put("old", &blob).await.unwrap();
+        store.put("new", "replaced").await.unwrap();
+        let size = store.val_log.read().await.size;
+        let seq = store.rename("old", "new").await.unwrap().seq;
+        assert_eq!(store.latest_sequence(), seq);
+        // the value is not written again
+        assert!(store.val_log.read().await.size - size < blob.len());
+        assert!(store.get("old").await.unwrap().is_none());
+        assert_eq!(store.get("new").await.unwrap().unwrap().val, blob);
+        assert!(matches!(
+            store.rename("old", "other").await,
+            Err(Error::NotFoundInDB)
+        ));
+        assert_eq!(store.rename("new", "new").await.unwrap().seq, seq);
+
+        store.put("flushed", "value").await.unwrap();
+        store.force_flush().await.unwrap();
+        store.rename("flushed", "moved").await.unwrap();
+        assert!(store.get("flushed").await.unwrap().is_none());
+        assert_eq!(store.get("moved").await.unwrap().unwrap().val, b"value".to_vec());
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = DataStore::open("test", dir.path)
+            .await
+            .unwrap()
+            .with_manual_background_mode(true);
+        assert!(store.get("old").await.unwrap().is_none());
+        assert_eq!(store.get("new").await.unwrap().unwrap().val, blob);
+        assert_eq!(store.get("moved").await.unwrap().unwrap().val, b"value".to_vec());
+        // GC keeps the value alive for the new key
+        store.put("filler", "value").await.unwrap();
+        for _ in 0..3 {
+            store.tick_gc().await.unwrap();
+        }
+        assert_eq!(store.get("new").await.unwrap().unwrap().val, blob);
+        assert_eq!(store.get("moved").await.unwrap().unwrap().val, b"value".to_vec());
+    }
+
+    #[tokio::test]
+    async fn datastore_appends_to_values() {
+        let dir = StoreDir::new("store_test_append");
+        let blob = vec![b'x'; 1000];
+        let mut store = DataStore::open("test", dir.path.to_owned())
+            .await
+            .unwrap()
+            .with_manual_background_mode(true);
+        store.put("blob", &blob).await.unwrap();
+        let size = store.val_log.read().await.size;
+        let seq = store.append("blob", "-tail").await.unwrap().seq;
+        assert_eq!(store.latest_sequence(), seq);
+        // the value is not written again
+        assert!(store.val_log.read().await.size - size < blob.len());
+        let mut expected = blob.to_owned();
+        expected.extend_from_slice(b"-tail");
+        assert_eq!(store.get("blob").await.unwrap().unwrap().val, expected);
+
+        store.append("absent", "first").await.unwrap();
+        assert_eq!(store.get("absent").await.unwrap().unwrap().val, b"first".to_vec());
+        store.put("deleted", "gone").await.unwrap();
+        store.delete("deleted").await.unwrap();
+        store.append("deleted", "back").await.unwrap();
+        assert_eq!(store.get("deleted").await.unwrap().unwrap().val, b"back".to_vec());
+
+        // chains longer than the limit are written again in full
+        let mut log = Vec::new();
+        for i in 0..(APPEND_CHAIN_MAX_DEPTH + 4) {
+            let line = format!("{};", i);
+            store.append("log", &line).await.unwrap();
+            log.extend_from_slice(line.as_bytes());
+        }
+        assert_eq!(store.get("log").await.unwrap().unwrap().val, log);
+        store.force_flush().await.unwrap();
+        store.append("log", "flushed;").await.unwrap();
+        log.extend_from_slice(b"flushed;");
+        assert_eq!(store.get("log").await.unwrap().unwrap().val, log);
+        store.rename("log", "moved").await.unwrap();
+        assert_eq!(store.get("moved").await.unwrap().unwrap().val, log);
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = DataStore::open("test", dir.path)
+            .await
+            .unwrap()
+            .with_manual_background_mode(true);
+        assert_eq!(store.get("blob").await.unwrap().unwrap().val, expected);
+        assert_eq!(store.get("moved").await.unwrap().unwrap().val, log);
+        // GC keeps records extended by appends alive
+        store.put("filler", "value").await.unwrap();
+        for _ in 0..3 {
+            store.tick_gc().await.unwrap();
+        }
+        store.put("filler", "value").await.unwrap();
+        assert_eq!(store.get("blob").await.unwrap().unwrap().val, expected);
+        assert_eq!(store.get("absent").await.unwrap().unwrap().val, b"first".to_vec());
+        assert_eq!(store.get("moved").await.unwrap().unwrap().val, log);
+    }
+
+    #[tokio::test]
+    async fn datastore_increments_counters() {
+        let dir = StoreDir::new("store_test_increment");
+        let mut store = DataStore::open("test", dir.path.to_owned())
+            .await
+            .unwrap()
+            .with_manual_background_mode(true);
+        assert_eq!(store.counter("hits").await.unwrap(), 0);
+        assert_eq!(store.increment("hits", 5).await.unwrap(), 5);
+        assert_eq!(store.increment("hits", -7).await.unwrap(), -2);
+        assert_eq!(
+            store.get("hits").await.unwrap().unwrap().val,
+            (-2i64).to_le_bytes().to_vec()
+        );
+        store.put("name", "not a counter").await.unwrap();
+        assert!(matches!(
+            store.increment("name", 1).await,
+            Err(Error::NotACounter(13))
+        ));
+        store.put("max", i64::MAX.to_le_bytes()).await.unwrap();
+        assert!(matches!(
+            store.increment("max", 1).await,
+            Err(Error::CounterOverflow)
+        ));
+        store.delete("hits").await.unwrap();
+        assert_eq!(store.increment("hits", 1).await.unwrap(), 1);
+        store.close().await.unwrap();
+        drop(store);
+
+        let db = Db::from(dir.open().await);
+        let tasks = (0..10).map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move { db.increment("hits", 1).await.unwrap() })
+        });
+        for task in tasks.collect::<Vec<_>>() {
+            task.await.unwrap();
+        }
+        // increments through a shared handle are never lost
+        assert_eq!(db.counter("hits").await.unwrap(), 11);
+    }
+
+    #[tokio::test]
+    async fn datastore_enforces_prefix_quotas() {
+        let dir = StoreDir::new("store_test_prefix_quotas");
+        let store = dir.open().await.with_prefix_quota("tenant_a/", 40, 2);
+        // key of 10 bytes and value of 5 bytes
+        store.put("tenant_a/1", "apple").await.unwrap();
+        store.put("tenant_a/2", "mango").await.unwrap();
+        let res = store.put("tenant_a/3", "grape").await;
+        assert!(matches!(
+            res,
+            Err(Error::QuotaExceeded { ref prefix, bytes: 45, keys: 3 }) if prefix == b"tenant_a/"
+        ));
+        assert!(store.get("tenant_a/3").await.unwrap().is_none());
+        // other prefixes are not capped
+        store.put("tenant_b/3", "grape").await.unwrap();
+
+        // overwrites are charged the difference
+        store.put("tenant_a/2", "kiwi").await.unwrap();
+        let res = store.put("tenant_a/2", "dragonfruit_pear").await;
+        assert!(matches!(res, Err(Error::QuotaExceeded { bytes: 41, .. })));
+        assert_eq!(
+            store.get("tenant_a/2").await.unwrap().unwrap().val,
+            b"kiwi".to_vec()
+        );
+
+        let mut batch = WriteBatch::new();
+        batch
+            .delete("tenant_a/1")
+            .put("tenant_a/3", "grape")
+            .put("tenant_a/4", "lemon");
+        assert!(matches!(
+            store.write_batch(batch).await,
+            Err(Error::QuotaExceeded { keys: 3, .. })
+        ));
+        assert!(store.get("tenant_a/1").await.unwrap().is_some());
+
+        store.delete("tenant_a/1").await.unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put("tenant_a/3", "grape").delete("tenant_a/missing");
+        store.write_batch(batch).await.unwrap();
+        let usage = store.quota_usage().await.unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!((usage[0].bytes, usage[0].keys), (29, 2));
+        assert_eq!((usage[0].max_bytes, usage[0].max_keys), (40, 2));
+    }
+
+    #[tokio::test]
+    async fn datastore_counts_quota_usage_from_stored_entries() {
+        let dir = StoreDir::new("store_test_quota_usage_recount");
+        let start = chrono::DateTime::from_timestamp_millis(4_102_444_800_000).unwrap();
+        let clock = MockClock::new(start);
+        let mut store = dir
+            .open()
+            .await
+            .with_clock(clock.clone())
+            .with_manual_background_mode(true)
+            .with_max_buffer_write_number(1)
+            .with_tables_to_merge(2, 32);
+        store.compactor.config.use_ttl = true;
+        store.compactor.config.entry_ttl = std::time::Duration::from_secs(3 * 24 * 60 * 60);
+        for i in 0..10 {
+            store.put(format!("tenant/{}", i), "value").await.unwrap();
+        }
+        store.delete("tenant/9").await.unwrap();
+        store.migrate_memtable_to_read_only();
+        store.tick_flush().await.unwrap();
+
+        // usage is counted from sstables once a quota is set
+        let store = store.with_prefix_quota("tenant/", 1024, 100);
+        let usage = store.quota_usage().await.unwrap();
+        assert_eq!((usage[0].bytes, usage[0].keys), (9 * 13, 9));
+
+        clock.advance(std::time::Duration::from_secs(4 * 24 * 60 * 60));
+        for i in 0..3 {
+            store.put(format!("tenant/{}", i), "new").await.unwrap();
+        }
+        store.migrate_memtable_to_read_only();
+        store.tick_flush().await.unwrap();
+        let usage = store.quota_usage().await.unwrap();
+        assert_eq!((usage[0].bytes, usage[0].keys), (3 * 11 + 6 * 13, 9));
+
+        // compaction drops expired entries, usage is counted again
+        store.tick_compaction().await.unwrap();
+        let usage = store.quota_usage().await.unwrap();
+        assert_eq!((usage[0].bytes, usage[0].keys), (3 * 11, 3));
+    }
+
+    #[tokio::test]
+    async fn datastore_put_and_get_json() {
+        let dir = StoreDir::new("store_test_json");
+        let store = dir.open().await;
+
+        #[derive(Serialize, Deserialize, Debug, PartialEq)]
+        struct BigTech {
+            name: String,
+            rank: i32,
+        }
+        let google = BigTech {
+            name: String::from("Google"),
+            rank: 50,
+        };
+        store.put_json("google", &google).await.unwrap();
+        let entry: Option<BigTech> = store.get_json("google").await.unwrap();
+        assert_eq!(entry, Some(google));
+
+        // missing keys are not decode errors
+        let entry: Option<BigTech> = store.get_json("meta").await.unwrap();
+        assert!(entry.is_none());
+
+        store.put("apple", "tim cook").await.unwrap();
+        let res = store.get_json::<BigTech>("apple").await;
+        assert!(matches!(res, Err(Error::ValueDecode(_))));
+    }
+
+    #[cfg(feature = "bincode")]
+    #[tokio::test]
+    async fn datastore_put_and_get_bincode() {
+        let dir = StoreDir::new("store_test_bincode");
+        let store = dir.open().await;
+
+        store.put_bincode("ranks", &vec![1u64, 2, 3]).await.unwrap();
+        let entry: Option<Vec<u64>> = store.get_bincode("ranks").await.unwrap();
+        assert_eq!(entry, Some(vec![1, 2, 3]));
+        assert!(store.get_bincode::<Vec<u64>>("meta").await.unwrap().is_none());
+
+        store.put("apple", "a").await.unwrap();
+        let res = store.get_bincode::<Vec<u64>>("apple").await;
+        assert!(matches!(res, Err(Error::ValueDecode(_))));
+    }
+}
@@ -1,7 +1,9 @@
 use crate::{
     bucket::BucketMap,
+    flush::FlushSignal,
     key_range::KeyRange,
-    memtable::{MemTable, SkipMapValue},
+    memtable::{MemTable, MemtableId, SkipMapValue},
+    meta::Meta,
 };
 use chrono::{DateTime, Utc};
 use crossbeam_skiplist::SkipMap;
@@ -27,8 +29,20 @@ pub type LastModified = DateTime<Utc>;
 /// Represents a tombstone marker (true if entry is deleted)
 pub type IsTombStone = bool;
 
-/// Represents singal sent after flush
-pub type FlushSignal = u8;
+/// Represents number of memtables flushed, bumped after every flush
+pub type FlushGeneration = u64;
+
+/// Represents SSTable file number, allocated from the manifest in increasing order
+pub type FileNumber = u64;
+
+/// Represents sequence number of a write, increasing across restarts
+pub type SeqNumber = u64;
+
+/// Represents on-disk format version recorded in meta
+pub type FormatVersion = u32;
+
+/// Represents fingerprint of a `Config`
+pub type ConfigHash = u64;
 
 /// Represents the number of bytes read
 pub type NoBytesRead = usize;
@@ -36,8 +50,11 @@ pub type NoBytesRead = usize;
 /// Represents entries in a SkipMap with generic key type wih order trait
 pub type SkipMapEntries<K> = Arc<SkipMap<K, SkipMapValue<ValOffset>>>;
 
-/// Represents a receiver for flush signal
-pub type FlushReceiver = async_broadcast::Receiver<FlushSignal>;
+/// Represents a sender of flush signals
+pub type FlushSender = tokio::sync::watch::Sender<FlushSignal>;
+
+/// Represents a receiver of flush signals
+pub type FlushReceiver = tokio::sync::watch::Receiver<FlushSignal>;
 
 /// Thread-safe BucketMap
 pub type BucketMapHandle = Arc<RwLock<BucketMap>>;
@@ -46,7 +63,19 @@ pub type BucketMapHandle = Arc<RwLock<BucketMap>>;
 pub type KeyRangeHandle = Arc<KeyRange>;
 
 /// Represents read-only MemTables
-pub type ImmutableMemTables<K> = Arc<SkipMap<K, Arc<MemTable<K>>>>;
+///
+/// Sealed memtables are never modified, readers take an `Arc` of a table and
+/// search it without locks. Flushes only remove a table from the map once its
+/// SSTable is visible to reads
+pub type ImmutableMemTables<K> = Arc<SkipMap<MemtableId, Arc<MemTable<K>>>>;
+
+/// Represents the active memtable, shared with the memtable age check
+pub type ActiveMemTable<K> = Arc<std::sync::RwLock<MemTable<K>>>;
+
+/// Thread-safe store metadata
+///
+/// NOTE: A std lock is used, `Meta` is cloned before it is written to disk
+pub type MetaHandle = Arc<std::sync::Mutex<Meta>>;
 
 /// Represents read-only memtables without lock
 pub type ImmutableMemTablesLockFree<K> = SkipMap<MemtableId, Arc<MemTable<K>>>;
@@ -54,9 +83,6 @@ pub type ImmutableMemTablesLockFree<K> = SkipMap<MemtableId, Arc<MemTable<K>>>;
 /// Alias for a boolean value
 pub type Bool = bool;
 
-/// Represents an ID for a MemTable
-pub type MemtableId = Vec<u8>;
-
 /// Represents a HashSet that contains unique identifier of memtables currently being flushed
 pub type MemtableFlushStream = std::collections::HashSet<MemtableId>;
 
@@ -23,6 +23,37 @@ pub fn milliseconds_to_datetime(milliseconds: u64) -> DateTime<Utc> {
     Utc.timestamp_opt(seconds, nanoseconds).unwrap()
 }
 
+/// Timestamps at or above this value are encoded with sub-millisecond precision
+const SUB_MILLI_ENCODING_THRESHOLD: u64 = 1 << 52;
+
+/// Bits used for the sub-millisecond part of an encoded timestamp
+const SUB_MILLI_BITS: u32 = 20;
+
+/// Encodes entry timestamp for disk
+///
+/// Timestamps without a sub-millisecond part are written as milliseconds like
+/// before, otherwise milliseconds are shifted left and the nanoseconds within
+/// the millisecond (the logical counter of the hybrid logical clock) are kept
+/// in the low bits so versions survive a flush
+pub fn datetime_to_timestamp(datetime: DateTime<Utc>) -> i64 {
+    let millis = datetime.timestamp_millis();
+    let sub_milli = (datetime.timestamp_subsec_nanos() % 1_000_000) as i64;
+    if sub_milli == 0 {
+        return millis;
+    }
+    (millis << SUB_MILLI_BITS) | sub_milli
+}
+
+/// Decodes entry timestamp written by [`datetime_to_timestamp`]
+pub fn timestamp_to_datetime(timestamp: u64) -> DateTime<Utc> {
+    if timestamp < SUB_MILLI_ENCODING_THRESHOLD {
+        return milliseconds_to_datetime(timestamp);
+    }
+    let millis = timestamp >> SUB_MILLI_BITS;
+    let sub_milli = timestamp & ((1 << SUB_MILLI_BITS) - 1);
+    milliseconds_to_datetime(millis) + chrono::Duration::nanoseconds(sub_milli as i64)
+}
+
 /// Returns lowest possible `DateTime<Utc>`
 pub fn default_datetime() -> DateTime<Utc> {
     Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap()
@@ -53,6 +84,14 @@ pub fn float_from_le_bytes(bytes: &[u8]) -> Option<f64> {
     Some(float)
 }
 
+/// Strips `prefix` from `s` ignoring ASCII case
+///
+/// Directory names can come back in another case from case-insensitive filesystems
+pub fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix).then(|| &s[prefix.len()..])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -76,6 +115,19 @@ mod tests {
         assert_eq!(datetime, Utc.with_ymd_and_hms(1970, 1, 1, 0, 16, 40).unwrap());
     }
 
+    #[test]
+    fn test_timestamp_encoding() {
+        // millisecond timestamps keep the old encoding
+        let datetime = milliseconds_to_datetime(1_700_000_000_000);
+        assert_eq!(datetime_to_timestamp(datetime), 1_700_000_000_000);
+        assert_eq!(timestamp_to_datetime(1_700_000_000_000), datetime);
+
+        let versioned = datetime + chrono::Duration::nanoseconds(42);
+        let encoded = datetime_to_timestamp(versioned);
+        assert!(encoded as u64 >= SUB_MILLI_ENCODING_THRESHOLD);
+        assert_eq!(timestamp_to_datetime(encoded as u64), versioned);
+    }
+
     #[test]
     fn test_default_datetime() {
         let datetime = default_datetime();
@@ -101,4 +153,20 @@ mod tests {
         let result = float_from_le_bytes(&invalid_bytes);
         assert_eq!(result, None);
     }
+
+    #[test]
+    fn test_strip_prefix_ignore_case() {
+        assert_eq!(
+            strip_prefix_ignore_case("sstable_000001", "sstable"),
+            Some("_000001")
+        );
+        assert_eq!(
+            strip_prefix_ignore_case("SSTABLE_000001", "sstable"),
+            Some("_000001")
+        );
+        assert_eq!(strip_prefix_ignore_case("sst", "sstable"), None);
+        assert_eq!(strip_prefix_ignore_case("bucket_1", "sstable"), None);
+        // a multi-byte character straddling the prefix length is no match
+        assert_eq!(strip_prefix_ignore_case("sstablé", "sstable"), None);
+    }
 }
@@ -0,0 +1,55 @@
+use crate::{
+    consts::{SIZE_OF_U32, SIZE_OF_U64},
+    types::ValOffset,
+};
+
+/// Offset written in place of the previous record of a chain starting at an absent key
+const NO_PREVIOUS_RECORD: u64 = u64::MAX;
+
+/// Header of an `Append` record, its value is the previous record's value
+/// followed by the suffix stored after the header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct AppendLink {
+    /// Offset of the record whose value is extended, `None` if the key had no value
+    pub previous: Option<ValOffset>,
+
+    /// Number of `Append` records in the chain, this one included
+    pub depth: u32,
+}
+
+impl AppendLink {
+    /// Returns value of an `Append` record holding `suffix`
+    pub fn encode(&self, suffix: &[u8]) -> Vec<u8> {
+        let previous = self.previous.map_or(NO_PREVIOUS_RECORD, |offset| offset as u64);
+        let mut value = Vec::with_capacity(SIZE_OF_U64 + SIZE_OF_U32 + suffix.len());
+        value.extend_from_slice(&previous.to_le_bytes());
+        value.extend_from_slice(&self.depth.to_le_bytes());
+        value.extend_from_slice(suffix);
+        value
+    }
+
+    /// Returns header and suffix of an `Append` record value
+    pub fn decode(value: &[u8]) -> (Self, &[u8]) {
+        let previous = u64::from_le_bytes(value[..SIZE_OF_U64].try_into().unwrap());
+        let depth = u32::from_le_bytes(value[SIZE_OF_U64..SIZE_OF_U64 + SIZE_OF_U32].try_into().unwrap());
+        let link = Self {
+            previous: (previous != NO_PREVIOUS_RECORD).then_some(previous as ValOffset),
+            depth,
+        };
+        (link, &value[SIZE_OF_U64 + SIZE_OF_U32..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_link_round_trip() {
+        for previous in [None, Some(0), Some(4096)] {
+            let link = AppendLink { previous, depth: 3 };
+            let value = link.encode(b"suffix");
+            assert_eq!(AppendLink::decode(&value), (link, &b"suffix"[..]));
+        }
+    }
+}
@@ -0,0 +1,417 @@
+use super::{AppendLink, RecordType, ValueLog};
+use crate::{
+    consts::{DEDUP_MARKER_FILE_NAME, GC_CHUNK_SIZE, SIZE_OF_U64},
+    err::Error,
+    fs::VLogFs,
+    types::{CreatedAt, Key, ValOffset},
+};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::fs;
+
+/// Key referring to a shared payload along with when the reference was written
+pub(crate) type Referrer = (Key, CreatedAt);
+
+#[derive(Debug, Default)]
+struct DedupState {
+    /// Values at least this many bytes are deduplicated, 0 disables deduplication
+    min_size: usize,
+
+    /// References were rebuilt from the value log
+    loaded: bool,
+
+    /// Marker file exists, it tells later opens that references must be rebuilt
+    marked: bool,
+
+    /// Offset of the most recent payload with the given content hash
+    payloads: HashMap<u64, ValOffset>,
+
+    /// Keys referring to the payload at an offset besides the key stored with it
+    referrers: HashMap<ValOffset, Vec<Referrer>>,
+
+    /// Oldest record and depth of the chain ending at each `Append` record
+    chains: HashMap<ValOffset, (ValOffset, u32)>,
+
+    /// Records extended by an `Append` record
+    linked: HashSet<ValOffset>,
+
+    /// Range of the value log GC is checking, payloads within it cannot gain referrers
+    claimed: Option<(ValOffset, ValOffset)>,
+
+    /// Number of pins held on payloads about to gain a referrer, GC leaves them be
+    pinned: HashMap<ValOffset, usize>,
+
+    /// Bytes of values not written thanks to deduplication since open
+    saved_bytes: usize,
+}
+
+/// Index of value log payloads shared by several keys
+///
+/// A put whose value matches a known payload only writes a `ValueRef` record
+/// pointing at the payload. GC keeps a payload as long as its own key or one
+/// of its referrers is live and moves the references along with it.
+///
+/// References are kept in memory and rebuilt from the value log the first
+/// time they are needed after open. A marker file next to the value log
+/// records that it holds references, so they are rebuilt even when
+/// deduplication is turned off later.
+///
+/// Chains of `Append` records are tracked the same way, GC keeps records
+/// extended by an append until the chain is written again in full.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ValueDedup {
+    inner: Arc<Mutex<DedupState>>,
+}
+
+impl ValueDedup {
+    /// Sets size from which values are deduplicated, 0 disables deduplication
+    pub fn set_min_size(&self, min_size: usize) {
+        self.inner.lock().unwrap().min_size = min_size;
+    }
+
+    /// Returns bytes of values not written thanks to deduplication since open
+    pub fn saved_bytes(&self) -> usize {
+        self.inner.lock().unwrap().saved_bytes
+    }
+
+    /// Returns content hash of `value` if it is large enough to be deduplicated
+    pub fn eligible(&self, value: &[u8]) -> Option<u64> {
+        let min_size = self.inner.lock().unwrap().min_size;
+        if min_size == 0 || value.len() < min_size {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+
+    /// Looks up a payload in `vlog` holding `value`
+    ///
+    /// Returns content hash of `value` if it can be deduplicated along with
+    /// offset of the payload to share, the value log is marked as holding
+    /// references before an offset is returned
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn find(
+        &self,
+        vlog: &ValueLog,
+        value: &[u8],
+    ) -> Result<(Option<u64>, Option<ValOffset>), Error> {
+        let Some(hash) = self.eligible(value) else {
+            return Ok((None, None));
+        };
+        self.load(vlog).await?;
+        let Some(offset) = self.inner.lock().unwrap().payloads.get(&hash).copied() else {
+            return Ok((Some(hash), None));
+        };
+        // hashes can collide, only identical values are shared
+        match vlog.get(offset).await {
+            Ok((payload, false)) if payload[..] == *value => {
+                self.mark(vlog).await?;
+                Ok((Some(hash), Some(offset)))
+            }
+            // the payload may have been freed by garbage collection since
+            Ok(_) | Err(Error::VLogTruncated { .. } | Error::VLogRecordCorrupted { .. }) => {
+                Ok((Some(hash), None))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Records the payload written at `offset` as the one to share for `hash`
+    pub fn register(&self, hash: u64, offset: ValOffset) {
+        self.inner.lock().unwrap().payloads.insert(hash, offset);
+    }
+
+    /// Adds `key` to the referrers of payload at `offset`
+    ///
+    /// Returns `false` if GC claimed the payload since it was looked up, the
+    /// reference must not be relied upon then. Callers make the referring
+    /// entry visible to GC first so a claimed payload never misses it.
+    pub fn attach(&self, hash: u64, offset: ValOffset, key: Key, created_at: CreatedAt, size: usize) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.payloads.get(&hash) != Some(&offset) {
+            return false;
+        }
+        inner.referrers.entry(offset).or_default().push((key, created_at));
+        inner.saved_bytes += size;
+        true
+    }
+
+    /// Keeps GC from claiming the payload at `offset` until the returned pin
+    /// is dropped, whatever its size and content
+    ///
+    /// Used by renames, which move a payload to another key without reading
+    /// it. Returns `None` if GC is checking the payload, the value has to be
+    /// written again then
+    pub fn pin(&self, offset: ValOffset) -> Option<PayloadPin> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .claimed
+            .is_some_and(|(start, end)| offset >= start && offset < end)
+        {
+            return None;
+        }
+        *inner.pinned.entry(offset).or_default() += 1;
+        Some(PayloadPin {
+            dedup: self.clone(),
+            offset,
+        })
+    }
+
+    /// Releases one pin of the payload at `offset`
+    fn unpin(&self, offset: ValOffset) {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(pins) = inner.pinned.get_mut(&offset) {
+            *pins -= 1;
+            if *pins == 0 {
+                inner.pinned.remove(&offset);
+            }
+        }
+    }
+
+    /// Returns oldest record and depth of the chain ending at `offset`, `None`
+    /// if the record at `offset` is not an `Append` record
+    pub fn chain(&self, offset: ValOffset) -> Option<(ValOffset, u32)> {
+        self.inner.lock().unwrap().chains.get(&offset).copied()
+    }
+
+    /// Records the `Append` record at `offset` extending the record `link` points at
+    ///
+    /// Returns `false` if GC is checking a record of the chain, the chain
+    /// must not be relied upon then. Callers make the entry of the `Append`
+    /// record visible to GC first, see [`ValueDedup::refer`]
+    pub fn link(&self, offset: ValOffset, link: AppendLink) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let base = Self::chain_base(&inner, offset, link);
+        if inner.claimed.is_some_and(|(_, end)| base < end) {
+            return false;
+        }
+        inner.chains.insert(offset, (base, link.depth));
+        inner.linked.extend(link.previous);
+        true
+    }
+
+    /// Returns oldest record of the chain ending at the `Append` record at `offset`
+    fn chain_base(inner: &DedupState, offset: ValOffset, link: AppendLink) -> ValOffset {
+        match link.previous {
+            Some(previous) => inner.chains.get(&previous).map_or(previous, |(base, _)| *base),
+            None => offset,
+        }
+    }
+
+    /// Returns `true` if an `Append` record extends the record at `offset`
+    pub fn is_linked(&self, offset: ValOffset) -> bool {
+        self.inner.lock().unwrap().linked.contains(&offset)
+    }
+
+    /// Returns `true` if keys other than the one stored with it refer to the payload at `offset`
+    pub fn is_shared(&self, offset: ValOffset) -> bool {
+        self.inner.lock().unwrap().referrers.contains_key(&offset)
+    }
+
+    /// Returns keys referring to payload at `offset`
+    pub fn referrers(&self, offset: ValOffset) -> Vec<Referrer> {
+        self.inner
+            .lock()
+            .unwrap()
+            .referrers
+            .get(&offset)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Stops sharing payloads within `[start, end)`, GC calls this before it
+    /// checks which of them are live
+    ///
+    /// Returns the payloads claimed, see [`ValueDedup::unclaim`], or `None`
+    /// without claiming anything if a payload within the range is pinned
+    pub fn claim(&self, start: ValOffset, end: ValOffset) -> Option<Vec<(u64, ValOffset)>> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .pinned
+            .keys()
+            .any(|offset| *offset >= start && *offset < end)
+        {
+            return None;
+        }
+        inner.claimed = Some((start, end));
+        let mut claimed = Vec::new();
+        inner.payloads.retain(|hash, offset| {
+            let in_range = *offset >= start && *offset < end;
+            if in_range {
+                claimed.push((*hash, *offset));
+            }
+            !in_range
+        });
+        Some(claimed)
+    }
+
+    /// Shares `claimed` payloads again, GC calls this if it leaves them in place
+    pub fn unclaim(&self, claimed: Vec<(u64, ValOffset)>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.claimed = None;
+        for (hash, offset) in claimed {
+            inner.payloads.entry(hash).or_insert(offset);
+        }
+    }
+
+    /// Records `value` rewritten by GC at `offset` along with keys still referring to it
+    pub fn relocate(&self, value: &[u8], offset: ValOffset, referrers: Vec<Referrer>) {
+        let hash = self.eligible(value);
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(hash) = hash {
+            inner.payloads.insert(hash, offset);
+        }
+        if !referrers.is_empty() {
+            inner.referrers.insert(offset, referrers);
+        }
+    }
+
+    /// Forgets references to payloads within `[start, end)` once GC freed them
+    pub fn release(&self, start: ValOffset, end: ValOffset) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.claimed = None;
+        inner
+            .referrers
+            .retain(|offset, _| *offset < start || *offset >= end);
+        inner.chains.retain(|offset, _| *offset < start || *offset >= end);
+        inner.linked.retain(|offset| *offset < start || *offset >= end);
+    }
+
+    /// Returns path of the marker file of `vlog`
+    fn marker(vlog: &ValueLog) -> PathBuf {
+        vlog.content.path.with_file_name(DEDUP_MARKER_FILE_NAME)
+    }
+
+    /// Creates the marker file of `vlog` once the first reference is written
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn mark(&self, vlog: &ValueLog) -> Result<(), Error> {
+        if self.inner.lock().unwrap().marked {
+            return Ok(());
+        }
+        let path = Self::marker(vlog);
+        fs::write(&path, [])
+            .await
+            .map_err(|error| Error::FileWrite { path, error })?;
+        self.inner.lock().unwrap().marked = true;
+        Ok(())
+    }
+
+    /// Rebuilds references and payload hashes from `vlog` unless it was done
+    /// already or the value log never held references
+    ///
+    /// # Errors
+    ///
+    /// Returns error in case there is an IO error
+    pub async fn load(&self, vlog: &ValueLog) -> Result<(), Error> {
+        let (loaded, min_size) = {
+            let inner = self.inner.lock().unwrap();
+            (inner.loaded, inner.min_size)
+        };
+        if loaded {
+            return Ok(());
+        }
+        let marked = fs::metadata(Self::marker(vlog)).await.is_ok();
+        let mut payloads = HashMap::new();
+        let mut referrers: HashMap<ValOffset, Vec<Referrer>> = HashMap::new();
+        let mut chains = DedupState::default();
+        if marked || min_size > 0 {
+            let mut offset = vlog.tail_offset;
+            loop {
+                let (entries, bytes_read) = vlog
+                    .content
+                    .file
+                    .read_chunk_to_garbage_collect(GC_CHUNK_SIZE, offset as u64)
+                    .await?;
+                if bytes_read == 0 {
+                    break;
+                }
+                for entry in entries {
+                    let record_offset = offset;
+                    offset += entry.record_size();
+                    match entry.record_type {
+                        RecordType::ValueRef => {
+                            let payload = Self::decode_ref(&entry.value);
+                            referrers
+                                .entry(payload)
+                                .or_default()
+                                .push((entry.key, entry.created_at));
+                        }
+                        RecordType::Put => {
+                            if let Some(hash) = self.eligible(&entry.value) {
+                                payloads.insert(hash, record_offset);
+                            }
+                        }
+                        RecordType::Append => {
+                            let (link, _) = AppendLink::decode(&entry.value);
+                            let base = Self::chain_base(&chains, record_offset, link);
+                            chains.chains.insert(record_offset, (base, link.depth));
+                            chains.linked.extend(link.previous);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let mut inner = self.inner.lock().unwrap();
+        // a concurrent load may have finished first
+        if !inner.loaded {
+            inner.payloads.extend(payloads);
+            inner.referrers = referrers;
+            inner.chains.extend(chains.chains);
+            inner.linked.extend(chains.linked);
+            inner.marked = marked;
+            inner.loaded = true;
+        }
+        Ok(())
+    }
+
+    /// Returns value of a `ValueRef` record pointing at `offset`
+    pub fn encode_ref(offset: ValOffset) -> [u8; SIZE_OF_U64] {
+        (offset as u64).to_le_bytes()
+    }
+
+    /// Returns payload offset a `ValueRef` record points at
+    pub fn decode_ref(value: &[u8]) -> ValOffset {
+        let mut bytes = [0; SIZE_OF_U64];
+        bytes.copy_from_slice(&value[..SIZE_OF_U64]);
+        u64::from_le_bytes(bytes) as ValOffset
+    }
+}
+
+/// Pin on a payload keeping GC from claiming it, see [`ValueDedup::pin`]
+#[derive(Debug)]
+pub(crate) struct PayloadPin {
+    dedup: ValueDedup,
+    offset: ValOffset,
+}
+
+impl PayloadPin {
+    /// Adds `key` to the referrers of the pinned payload and releases the pin
+    ///
+    /// Callers make the referring entry visible to GC first, see [`ValueDedup::attach`]
+    pub fn refer(self, key: Key, created_at: CreatedAt) {
+        let mut inner = self.dedup.inner.lock().unwrap();
+        inner
+            .referrers
+            .entry(self.offset)
+            .or_default()
+            .push((key, created_at));
+    }
+}
+
+impl Drop for PayloadPin {
+    fn drop(&mut self) {
+        self.dedup.unpin(self.offset);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{consts::SIZE_OF_U64, types::CreatedAt, util};
+use chrono::{DateTime, TimeDelta};
+use std::time::Duration;
+
+/// Latest expiry in milliseconds since the epoch, the end of year 9999
+const MAX_EXPIRY_MILLIS: i64 = 253_402_300_799_999;
+
+/// Header of an `Expiring` record, its value is stored after the header
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ValueExpiry {
+    /// Time from which the value reads as deleted
+    pub expires_at: CreatedAt,
+}
+
+impl ValueExpiry {
+    /// Returns expiry of a value written at `created_at` to live for `ttl`
+    ///
+    /// Expiry is kept in whole milliseconds and TTLs reaching past `MAX_EXPIRY_MILLIS`
+    /// are cut to it, so it is always encoded as milliseconds
+    pub fn after(created_at: CreatedAt, ttl: Duration) -> Self {
+        let expires_at = TimeDelta::from_std(ttl)
+            .ok()
+            .and_then(|ttl| created_at.checked_add_signed(ttl))
+            .map_or(MAX_EXPIRY_MILLIS, |expires_at| {
+                expires_at.timestamp_millis().min(MAX_EXPIRY_MILLIS)
+            });
+        Self {
+            expires_at: DateTime::from_timestamp_millis(expires_at).unwrap_or_default(),
+        }
+    }
+
+    /// Returns time left at `now` until the value expires
+    pub fn remaining(&self, now: CreatedAt) -> Duration {
+        self.expires_at
+            .signed_duration_since(now)
+            .to_std()
+            .unwrap_or_default()
+    }
+
+    /// Returns value of an `Expiring` record holding `value`
+    pub fn encode(&self, value: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(SIZE_OF_U64 + value.len());
+        encoded.extend_from_slice(&util::datetime_to_timestamp(self.expires_at).to_le_bytes());
+        encoded.extend_from_slice(value);
+        encoded
+    }
+
+    /// Returns header and value of an `Expiring` record value
+    pub fn decode(value: &[u8]) -> (Self, &[u8]) {
+        let expires_at = u64::from_le_bytes(value[..SIZE_OF_U64].try_into().unwrap());
+        let expiry = Self {
+            expires_at: util::timestamp_to_datetime(expires_at),
+        };
+        (expiry, &value[SIZE_OF_U64..])
+    }
+
+    /// Returns `true` if a value expiring at `expires_at` has expired at `now`
+    pub fn has_passed(expires_at: Option<CreatedAt>, now: CreatedAt) -> bool {
+        expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_expiry_round_trip() {
+        let expires_at = chrono::DateTime::from_timestamp_millis(4_102_444_800_000).unwrap();
+        let expiry = ValueExpiry { expires_at };
+        let value = expiry.encode(b"value");
+        assert_eq!(ValueExpiry::decode(&value), (expiry, &b"value"[..]));
+        assert!(ValueExpiry::has_passed(Some(expires_at), expires_at));
+        assert!(!ValueExpiry::has_passed(
+            Some(expires_at),
+            expires_at - chrono::Duration::seconds(1)
+        ));
+        assert!(!ValueExpiry::has_passed(None, expires_at));
+
+        let now = expires_at - chrono::Duration::seconds(5);
+        assert_eq!(ValueExpiry::after(now, Duration::from_secs(5)), expiry);
+        assert_eq!(expiry.remaining(now), Duration::from_secs(5));
+        assert_eq!(
+            expiry.remaining(expires_at + chrono::Duration::seconds(1)),
+            Duration::ZERO
+        );
+    }
+}
@@ -1,3 +1,13 @@
+mod append;
+mod dedup;
+mod expiry;
+mod read_ahead;
 mod v_log;
+pub(crate) use append::AppendLink;
+pub(crate) use dedup::ValueDedup;
+pub(crate) use expiry::ValueExpiry;
+pub use read_ahead::ReadAheadBuffer;
+pub(crate) use v_log::RecordHeader;
+pub use v_log::RecordType;
 pub use v_log::ValueLog;
 pub use v_log::ValueLogEntry;
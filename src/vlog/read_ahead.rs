@@ -0,0 +1,69 @@
+use super::{RecordHeader, RecordType, ValueExpiry};
+use crate::{
+    consts::SIZE_OF_U64,
+    types::{CreatedAt, IsTombStone, ValOffset},
+};
+use bytes::Bytes;
+
+/// Contiguous chunk of the value log read in a single call
+///
+/// Used by range scans, whose value offsets are often ascending and
+/// adjacent, so several values can be decoded from one read instead
+/// of seeking to each offset
+#[derive(Debug, Clone)]
+pub struct ReadAheadBuffer {
+    /// Offset of the first byte in `data`
+    pub start_offset: ValOffset,
+
+    /// Raw value log bytes, values are handed out as slices of it
+    pub data: Bytes,
+}
+
+impl ReadAheadBuffer {
+    /// Creates new `ReadAheadBuffer`
+    pub fn new(start_offset: ValOffset, data: impl Into<Bytes>) -> Self {
+        Self {
+            start_offset,
+            data: data.into(),
+        }
+    }
+
+    /// Returns `true` if a record starting at `offset` could be in the buffer
+    pub fn contains(&self, offset: ValOffset) -> bool {
+        offset >= self.start_offset && offset < self.start_offset + self.data.len()
+    }
+
+    /// Decodes record at `offset`, a value that expired at `now` reads as a
+    /// tombstone, see [`RecordType::Expiring`]
+    ///
+    /// Returns `None` if the record is not fully contained in the buffer, does
+    /// not match its checksum or extends an earlier record, see [`RecordType::Append`]
+    pub fn get(&self, offset: ValOffset, now: CreatedAt) -> Option<(Bytes, IsTombStone)> {
+        if !self.contains(offset) {
+            return None;
+        }
+        let record_start = offset - self.start_offset;
+        let buf = &self.data[record_start..];
+        if buf.len() < RecordHeader::LEN {
+            return None;
+        }
+        let header = RecordHeader::decode(buf);
+        let record_type = header.record_type;
+        if record_type == RecordType::Append
+            || buf.len() < header.record_len()
+            || !header.checksum_matches(&buf[..header.record_len()])
+        {
+            return None;
+        }
+        let val_start = RecordHeader::LEN + header.key_len;
+        let value = self
+            .data
+            .slice(record_start + val_start..record_start + val_start + header.val_len);
+        if record_type == RecordType::Expiring {
+            let (expiry, _) = ValueExpiry::decode(&value);
+            let expired = ValueExpiry::has_passed(Some(expiry.expires_at), now);
+            return Some((value.slice(SIZE_OF_U64..), expired));
+        }
+        Some((value, record_type == RecordType::Delete))
+    }
+}
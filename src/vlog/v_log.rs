@@ -50,7 +50,7 @@
 //! |   Created At      |   (8 bytes)
 //! |                   |
 //! +-------------------+
-//! |  Is Tombstone     |   (1 byte)
+//! |  Record Type      |   (1 byte)
 //! |                   |
 //! |                   |
 //! +-------------------+
@@ -65,7 +65,7 @@
 //! |   Created At      |   (8 bytes)
 //! |                   |
 //! +-------------------+
-//! |  Is Tombstone     |   (1 byte)
+//! |  Record Type      |   (1 byte)
 //! |                   |
 //! |                   |
 //! +-------------------+
@@ -76,17 +76,29 @@
 //! - **Key**: The actual key data, which can vary in size.
 //! - **Value**: The actual value data, which can vary in size.
 //! - **Created At**: A 8-byte field representing the time of insertion in bytes.
-//! - **Is Tombstone**: A 1 byte field representing a boolean of deleted or not deleted entry
+//! - **Record Type**: A 1 byte tag describing the record, see [`RecordType`]. Tags `0` and `1` match
+//!   the former tombstone byte (`0` live entry, `1` deleted entry) so older logs are read unchanged
+//! - **Checksum**: A 4-byte CRC32 of the record following the value, only present if the record type
+//!   has `VLOG_RECORD_CHECKSUM_FLAG` set. Stores created with `META_FEATURE_VLOG_CHECKSUM` write it
 
+use bytes::Bytes;
 use chrono::{DateTime, Utc};
 
 use crate::{
-    consts::{SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, VLOG_FILE_NAME},
+    consts::{
+        MAX_KEY_SIZE, MAX_VALUE_SIZE, SIZE_OF_U32, SIZE_OF_U64, SIZE_OF_U8, VLOG_FILE_NAME,
+        VLOG_RECORD_CHECKSUM_FLAG,
+    },
+    encryption::FileCipher,
     err::Error,
     fs::{FileAsync, FileNode, VLogFileNode, VLogFs},
-    types::{ByteSerializedEntry, CreatedAt, IsTombStone, ValOffset, Value},
+    gc::VLogLiveness,
+    types::{ByteSerializedEntry, CreatedAt, IsTombStone, ValOffset},
+    util,
 };
 use std::path::{Path, PathBuf};
+
+use super::{AppendLink, ReadAheadBuffer, ValueExpiry};
 type TotalBytesRead = usize;
 
 /// Value log file
@@ -123,6 +135,181 @@ pub struct ValueLog {
 
     /// Size of the Value log
     pub size: usize,
+
+    /// Writes to each segment, shared with the `DeadOffsets` of compaction
+    pub(crate) liveness: VLogLiveness,
+
+    /// Whether appended records end with a checksum, see `META_FEATURE_VLOG_CHECKSUM`
+    pub(crate) checksums: bool,
+}
+
+/// Kind of record stored in the value log
+///
+/// Recovery relies on the tag to decide what to replay into the memtable,
+/// records with an unknown tag are refused like any other invalid record,
+/// stores a newer version writes new types to are refused by their format version
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordType {
+    /// User insert or update
+    Put,
+
+    /// User delete (tombstone)
+    Delete,
+
+    /// Head checkpoint
+    Head,
+
+    /// Tail checkpoint
+    Tail,
+
+    /// Start of an atomic batch
+    BatchBegin,
+
+    /// End of an atomic batch, records since the matching
+    /// `BatchBegin` are only replayed if this record exists
+    BatchCommit,
+
+    /// Put whose value is shared with an earlier `Put` record, the value
+    /// holds the offset of that record
+    ValueRef,
+
+    /// Put whose value is the value of an earlier record followed by a
+    /// suffix, the value holds an `AppendLink` and the suffix
+    Append,
+
+    /// Put whose value reads as deleted from a point in time, the value
+    /// holds a `ValueExpiry` followed by the value
+    Expiring,
+
+    /// Records GC freed in place, the value length covers them and the
+    /// value is a punched hole
+    Hole,
+
+    /// Tag written by a newer version
+    Unknown(u8),
+}
+
+/// Header every value log record starts with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct RecordHeader {
+    /// Length of the key
+    pub key_len: usize,
+
+    /// Length of the value
+    pub val_len: usize,
+
+    /// Timestamp the record was created at
+    pub created_at: u64,
+
+    /// Kind of record
+    pub record_type: RecordType,
+
+    /// True means a checksum of the record follows the value
+    pub checksummed: bool,
+}
+
+impl RecordHeader {
+    /// Number of bytes the header takes
+    pub const LEN: usize = SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + SIZE_OF_U8;
+
+    /// Decodes the header at the start of `bytes`, which holds at least [`RecordHeader::LEN`] bytes
+    pub fn decode(bytes: &[u8]) -> Self {
+        let tag = bytes[Self::LEN - SIZE_OF_U8];
+        Self {
+            key_len: u32::from_le_bytes(bytes[..SIZE_OF_U32].try_into().unwrap()) as usize,
+            val_len: u32::from_le_bytes(bytes[SIZE_OF_U32..2 * SIZE_OF_U32].try_into().unwrap()) as usize,
+            created_at: u64::from_le_bytes(
+                bytes[2 * SIZE_OF_U32..2 * SIZE_OF_U32 + SIZE_OF_U64]
+                    .try_into()
+                    .unwrap(),
+            ),
+            record_type: RecordType::from(tag & !VLOG_RECORD_CHECKSUM_FLAG),
+            checksummed: tag & VLOG_RECORD_CHECKSUM_FLAG != 0,
+        }
+    }
+
+    /// Returns number of bytes the record takes in value log
+    pub fn record_len(&self) -> usize {
+        Self::LEN + self.key_len + self.val_len + checksum_len(self.checksummed)
+    }
+
+    /// Returns `true` if no writer produces the header, e.g. the offset does not
+    /// point at a record. Zeroed space reads as a put of an empty key
+    pub fn is_impossible(&self) -> bool {
+        let impossible_type = match self.record_type {
+            RecordType::Unknown(_) => true,
+            record_type => record_type.is_user_entry() && self.key_len == 0,
+        };
+        impossible_type || self.key_len > MAX_KEY_SIZE || self.val_len > MAX_VALUE_SIZE
+    }
+
+    /// Returns `true` if `record`, the whole record this header starts, matches
+    /// its checksum. Records written without a checksum always match
+    pub fn checksum_matches(&self, record: &[u8]) -> bool {
+        if !self.checksummed {
+            return true;
+        }
+        let (body, checksum) = record.split_at(record.len() - SIZE_OF_U32);
+        crc32fast::hash(body) == u32::from_le_bytes(checksum.try_into().unwrap())
+    }
+}
+
+/// Returns number of bytes the checksum of a record takes
+fn checksum_len(checksummed: bool) -> usize {
+    if checksummed {
+        SIZE_OF_U32
+    } else {
+        0
+    }
+}
+
+impl RecordType {
+    /// Returns the on-disk tag
+    pub fn as_byte(&self) -> u8 {
+        match self {
+            RecordType::Put => 0,
+            RecordType::Delete => 1,
+            RecordType::Head => 2,
+            RecordType::Tail => 3,
+            RecordType::BatchBegin => 4,
+            RecordType::BatchCommit => 5,
+            RecordType::ValueRef => 6,
+            RecordType::Append => 7,
+            RecordType::Expiring => 8,
+            RecordType::Hole => 9,
+            RecordType::Unknown(tag) => *tag,
+        }
+    }
+
+    /// Returns `true` if record holds a user entry
+    pub fn is_user_entry(&self) -> bool {
+        matches!(
+            self,
+            RecordType::Put
+                | RecordType::Delete
+                | RecordType::ValueRef
+                | RecordType::Append
+                | RecordType::Expiring
+        )
+    }
+}
+
+impl From<u8> for RecordType {
+    fn from(tag: u8) -> Self {
+        match tag {
+            0 => RecordType::Put,
+            1 => RecordType::Delete,
+            2 => RecordType::Head,
+            3 => RecordType::Tail,
+            4 => RecordType::BatchBegin,
+            5 => RecordType::BatchCommit,
+            6 => RecordType::ValueRef,
+            7 => RecordType::Append,
+            8 => RecordType::Expiring,
+            9 => RecordType::Hole,
+            _ => RecordType::Unknown(tag),
+        }
+    }
 }
 
 /// Value log entry
@@ -145,6 +332,12 @@ pub struct ValueLogEntry {
 
     /// True means entry has been deleted
     pub is_tombstone: bool,
+
+    /// Kind of record
+    pub record_type: RecordType,
+
+    /// True means the record ends with a checksum
+    pub checksummed: bool,
 }
 
 impl ValueLog {
@@ -164,10 +357,12 @@ impl ValueLog {
             content: VFile::new(file_path, file),
             // IMPORTANT: cache vlog size in memory
             size,
+            liveness: VLogLiveness::default(),
+            checksums: false,
         })
     }
 
-    /// Appends new entry to value log
+    /// Appends new user entry to value log
     ///
     /// Returns start offset of the newly inserted entry
     pub async fn append<T: AsRef<[u8]>>(
@@ -177,14 +372,33 @@ impl ValueLog {
         created_at: CreatedAt,
         is_tombstone: bool,
     ) -> Result<ValOffset, Error> {
-        let v_log_entry = ValueLogEntry::new(
+        let record_type = if is_tombstone {
+            RecordType::Delete
+        } else {
+            RecordType::Put
+        };
+        self.append_record(key, value, created_at, record_type).await
+    }
+
+    /// Appends new record of `record_type` to value log
+    ///
+    /// Returns start offset of the newly inserted record
+    pub async fn append_record<T: AsRef<[u8]>>(
+        &mut self,
+        key: T,
+        value: T,
+        created_at: CreatedAt,
+        record_type: RecordType,
+    ) -> Result<ValOffset, Error> {
+        let mut v_log_entry = ValueLogEntry::with_record_type(
             key.as_ref().len(),
             value.as_ref().len(),
             key.as_ref().to_vec(),
             value.as_ref().to_vec(),
             created_at,
-            is_tombstone,
+            record_type,
         );
+        v_log_entry.checksummed = self.checksums;
 
         let serialized_data = v_log_entry.serialize();
         // Get the current offset before writing(this will be the offset of the value stored in the memtable)
@@ -192,18 +406,205 @@ impl ValueLog {
         let data_file = &self.content;
         data_file.file.node.write_all(&serialized_data).await?;
         self.size += serialized_data.len();
+        self.liveness.record_write(last_offset, serialized_data.len());
         Ok(last_offset)
     }
 
+    /// Appends `records` to value log with a single write
+    ///
+    /// Returns start offset of each record
+    pub async fn append_records(&mut self, records: &[ValueLogEntry]) -> Result<Vec<ValOffset>, Error> {
+        let mut offsets = Vec::with_capacity(records.len());
+        let mut serialized_data = Vec::with_capacity(
+            records
+                .iter()
+                .map(|r| r.record_size() + checksum_len(self.checksums))
+                .sum(),
+        );
+        for record in records {
+            offsets.push(self.size + serialized_data.len());
+            serialized_data.extend_from_slice(&record.encode(self.checksums));
+        }
+        let data_file = &self.content;
+        data_file.file.node.write_all(&serialized_data).await?;
+        let end = self.size + serialized_data.len();
+        for (i, offset) in offsets.iter().enumerate() {
+            let next = offsets.get(i + 1).copied().unwrap_or(end);
+            self.liveness.record_write(*offset, next - offset);
+        }
+        self.size = end;
+        Ok(offsets)
+    }
+
     /// Fetches value from value log
     ///
-    /// returns tuple of Value and Tombstone
+    /// returns tuple of Value and Tombstone, the value is handed to callers
+    /// without being copied unless it was appended to, see [`RecordType::Append`]
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::VLogTruncated`] if value log ends before the record,
+    /// [`Error::VLogRecordCorrupted`] if `start_offset` does not point at a
+    /// record or error in case there is an IO error
+    ///
+    /// Values of [`RecordType::Expiring`] records are returned whether or not
+    /// they expired, see [`ValueLog::get_with_expiry`]
+    pub async fn get(&self, start_offset: usize) -> Result<(Bytes, IsTombStone), Error> {
+        let (value, is_tombstone, _) = self.get_with_expiry(start_offset).await?;
+        Ok((value, is_tombstone))
+    }
+
+    /// Fetches value from value log along with the time it expires at, `None`
+    /// unless it was written by a [`RecordType::Expiring`] record
+    ///
+    /// # Error
+    ///
+    /// Returns error from [`ValueLog::get`]
+    pub async fn get_with_expiry(
+        &self,
+        start_offset: usize,
+    ) -> Result<(Bytes, IsTombStone, Option<CreatedAt>), Error> {
+        match self.content.file.get(start_offset).await? {
+            (value, RecordType::Append) => Ok((self.resolve_append(&value).await?, false, None)),
+            (value, RecordType::Expiring) => {
+                let (expiry, _) = ValueExpiry::decode(&value);
+                Ok((value.slice(SIZE_OF_U64..), false, Some(expiry.expires_at)))
+            }
+            (value, record_type) => Ok((value, record_type == RecordType::Delete, None)),
+        }
+    }
+
+    /// Returns the time the value at `start_offset` expires at, `None` unless
+    /// it was written by a [`RecordType::Expiring`] record
+    ///
+    /// Only the record header is read, not the value
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::VLogTruncated`] if value log ends before the record
+    /// or error in case there is an IO error
+    pub async fn record_expiry(&self, start_offset: usize) -> Result<Option<CreatedAt>, Error> {
+        let header = self
+            .content
+            .file
+            .read_bytes(start_offset, RecordHeader::LEN)
+            .await?;
+        if header.len() < RecordHeader::LEN {
+            return Err(Error::VLogTruncated {
+                path: self.content.path.to_owned(),
+                offset: start_offset,
+            });
+        }
+        let header = RecordHeader::decode(&header);
+        if header.record_type != RecordType::Expiring {
+            return Ok(None);
+        }
+        let expiry = self
+            .content
+            .file
+            .read_bytes(start_offset + RecordHeader::LEN + header.key_len, SIZE_OF_U64)
+            .await?;
+        if expiry.len() < SIZE_OF_U64 {
+            return Err(Error::VLogTruncated {
+                path: self.content.path.to_owned(),
+                offset: start_offset,
+            });
+        }
+        Ok(Some(ValueExpiry::decode(&expiry).0.expires_at))
+    }
+
+    /// Fetches value of a put from value log, see [`ValueLog::get`]
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::VLogTombstone`] if the record deletes its key, or error
+    /// from [`ValueLog::get`]
+    pub async fn get_value(&self, start_offset: usize) -> Result<Bytes, Error> {
+        match self.get(start_offset).await? {
+            (_, true) => Err(Error::VLogTombstone(start_offset)),
+            (value, false) => Ok(value),
+        }
+    }
+
+    /// Returns type of the record at `start_offset`
+    ///
+    /// # Error
+    ///
+    /// Returns error from [`ValueLog::get`]
+    pub async fn record_type(&self, start_offset: usize) -> Result<RecordType, Error> {
+        Ok(self.content.file.get(start_offset).await?.1)
+    }
+
+    /// Returns value of an `Append` record, the values of the records it
+    /// extends followed by its suffix
+    ///
+    /// # Error
+    ///
+    /// Returns [`Error::BrokenAppendChain`] if a record of the chain is not a
+    /// put or an append, or error in case there is an IO error
+    async fn resolve_append(&self, value: &[u8]) -> Result<Bytes, Error> {
+        let mut suffixes = Vec::new();
+        let (mut link, suffix) = AppendLink::decode(value);
+        suffixes.push(Bytes::copy_from_slice(suffix));
+        let mut resolved = Vec::new();
+        while let Some(previous) = link.previous {
+            match self.content.file.get(previous).await? {
+                (value, RecordType::Append) => {
+                    let (previous_link, suffix) = AppendLink::decode(&value);
+                    suffixes.push(Bytes::copy_from_slice(suffix));
+                    link = previous_link;
+                }
+                (value, RecordType::Put) => {
+                    resolved = value.to_vec();
+                    break;
+                }
+                _ => return Err(Error::BrokenAppendChain(previous)),
+            }
+        }
+        for suffix in suffixes.iter().rev() {
+            resolved.extend_from_slice(suffix);
+        }
+        Ok(Bytes::from(resolved))
+    }
+
+    /// Reads up to `len` bytes of value log starting at `start_offset`
+    /// in a single call
     ///
     /// # Error
     ///
     /// Returns error in case there is an IO error
-    pub async fn get(&self, start_offset: usize) -> Result<Option<(Value, IsTombStone)>, Error> {
-        self.content.file.get(start_offset).await
+    pub async fn read_ahead(&self, start_offset: usize, len: usize) -> Result<ReadAheadBuffer, Error> {
+        let len = len.min(self.size.saturating_sub(start_offset));
+        let data = self.content.file.read_bytes(start_offset, len).await?;
+        Ok(ReadAheadBuffer::new(start_offset, data))
+    }
+
+    /// Hints the OS that `len` bytes of value log starting at `start_offset`
+    /// will be read soon, so they are cached before being asked for
+    ///
+    /// Only Linux is hinted, the hint is a no-op elsewhere
+    ///
+    /// # Error
+    ///
+    /// Returns error in case the value log cannot be opened
+    pub async fn will_need(&self, start_offset: usize, len: usize) -> Result<(), Error> {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::io::AsRawFd;
+            let file = self.content.file.node.r_lock().await?;
+            // hints are advisory, a failed one only loses the prefetch
+            unsafe {
+                libc::posix_fadvise(
+                    file.as_raw_fd(),
+                    start_offset as libc::off_t,
+                    len as libc::off_t,
+                    libc::POSIX_FADV_WILLNEED,
+                );
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (start_offset, len);
+        Ok(())
     }
 
     /// Ensures value log entries are persisted on the disk
@@ -216,17 +617,44 @@ impl ValueLog {
         self.content.file.node.sync_all().await
     }
 
+    /// Encrypts appended records and decrypts read ones with `cipher`, `None`
+    /// if value log is not encrypted
+    ///
+    /// # Error
+    ///
+    /// Returns error in case there is an IO error
+    pub(crate) async fn set_cipher(&self, cipher: Option<FileCipher>) -> Result<(), Error> {
+        self.content.file.node.set_cipher(cipher).await
+    }
+
     /// Fetches an entry from value log using the `start_offset`
     ///
     ///
     /// This is used to fetch all entries that is yet to be flushed
     /// before crash happened
     ///
+    /// A partial record left at the end of the file by a crash during append
+    /// is dropped and the file is truncated to the last complete record, so is
+    /// everything from the first record [`ValueLog::get`] would refuse on
+    ///
     /// # Error
     ///
     /// Returns error in case there is an IO error
     pub async fn recover(&mut self, start_offset: usize) -> Result<Vec<ValueLogEntry>, Error> {
-        self.content.file.recover(start_offset).await
+        let (entries, bytes_read) = self.content.file.recover(start_offset).await?;
+        let valid_len = start_offset + bytes_read;
+        if valid_len < self.size {
+            log::warn!(
+                "Value log {:?} has a torn or invalid record at offset {}, dropping {} trailing bytes",
+                self.content.path,
+                valid_len,
+                self.size - valid_len
+            );
+            self.content.file.node.truncate(valid_len).await?;
+            self.sync_to_disk().await?;
+            self.size = valid_len;
+        }
+        Ok(entries)
     }
 
     /// Returns entries within `gc_chunk_size` to garbage collection
@@ -276,6 +704,23 @@ impl ValueLogEntry {
         value: T,
         created_at: CreatedAt,
         is_tombstone: bool,
+    ) -> Self {
+        let record_type = if is_tombstone {
+            RecordType::Delete
+        } else {
+            RecordType::Put
+        };
+        Self::with_record_type(ksize, vsize, key, value, created_at, record_type)
+    }
+
+    /// Creates new `ValueLogEntry` of `record_type`
+    pub fn with_record_type<T: AsRef<[u8]>>(
+        ksize: usize,
+        vsize: usize,
+        key: T,
+        value: T,
+        created_at: CreatedAt,
+        record_type: RecordType,
     ) -> Self {
         Self {
             ksize,
@@ -283,28 +728,47 @@ impl ValueLogEntry {
             key: key.as_ref().to_vec(),
             value: value.as_ref().to_vec(),
             created_at,
-            is_tombstone,
+            is_tombstone: record_type == RecordType::Delete,
+            record_type,
+            checksummed: false,
         }
     }
 
+    /// Returns number of bytes the entry takes in value log
+    ///
+    /// Value of a `Hole` record is not read, its size is taken from `vsize`
+    pub(crate) fn record_size(&self) -> usize {
+        RecordHeader::LEN + self.ksize + self.vsize + checksum_len(self.checksummed)
+    }
+
     /// Converts value log entry to a byte vector
     pub(crate) fn serialize(&self) -> ByteSerializedEntry {
-        let entry_len =
-            SIZE_OF_U32 + SIZE_OF_U32 + SIZE_OF_U64 + self.key.len() + self.value.len() + SIZE_OF_U8;
-        let mut serialized_data = Vec::with_capacity(entry_len);
+        self.encode(self.checksummed)
+    }
+
+    /// Converts value log entry to a byte vector, ending with a checksum if `checksummed`
+    fn encode(&self, checksummed: bool) -> ByteSerializedEntry {
+        let mut serialized_data =
+            Vec::with_capacity(RecordHeader::LEN + self.ksize + self.vsize + SIZE_OF_U32);
 
         serialized_data.extend_from_slice(&(self.key.len() as u32).to_le_bytes());
 
         serialized_data.extend_from_slice(&(self.value.len() as u32).to_le_bytes());
 
-        serialized_data.extend_from_slice(&self.created_at.timestamp_millis().to_le_bytes());
+        serialized_data.extend_from_slice(&util::datetime_to_timestamp(self.created_at).to_le_bytes());
 
-        serialized_data.push(self.is_tombstone as u8);
+        let flag = if checksummed { VLOG_RECORD_CHECKSUM_FLAG } else { 0 };
+        serialized_data.push(self.record_type.as_byte() | flag);
 
         serialized_data.extend_from_slice(&self.key);
 
         serialized_data.extend_from_slice(&self.value);
 
+        if checksummed {
+            let checksum = crc32fast::hash(&serialized_data);
+            serialized_data.extend_from_slice(&checksum.to_le_bytes());
+        }
+
         serialized_data
     }
 }
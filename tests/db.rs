@@ -0,0 +1,63 @@
+use futures::future::join_all;
+use tempfile::tempdir;
+use velarixdb::db::Db;
+
+#[tokio::test]
+async fn test_db_handle() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("velarix");
+    let db = Db::open("big_tech", path).await.unwrap(); // handle IO error
+
+    db.put("apple", "tim cook").await.unwrap(); // handle error
+    db.put("google", "sundar pichai").await.unwrap();
+    db.flush().await.unwrap();
+    assert_eq!(db.stats().await.write_buffer_memory, 0);
+
+    let live = db.live_files().await.unwrap();
+    assert_eq!(live.sstables.len(), 1);
+
+    db.update("google", "larry page").await.unwrap();
+    db.delete("apple").await.unwrap();
+    assert!(db.get("apple").await.unwrap().is_none());
+    let entry = db.get("google").await.unwrap();
+    assert_eq!(std::str::from_utf8(&entry.unwrap().val).unwrap(), "larry page");
+
+    let mut iter = db.seek(b"a", b"z").await.unwrap();
+    let entry = iter.next().await.unwrap().unwrap();
+    assert_eq!(entry.key, b"google".to_vec());
+    assert!(iter.next().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_db_handle_shared_across_tasks() {
+    let root = tempdir().unwrap();
+    let path = root.path().join("velarix");
+    let db = Db::open("big_tech", path).await.unwrap(); // handle IO error
+
+    let entries = [
+        ["apple", "tim cook"],
+        ["google", "sundar pichai"],
+        ["nvidia", "jensen huang"],
+        ["microsoft", "satya nadella"],
+    ];
+    let writes = entries.iter().map(|e| {
+        let db = db.clone();
+        let [key, val] = *e;
+        tokio::spawn(async move { db.put(key, val).await })
+    });
+    for res in join_all(writes).await {
+        assert!(res.unwrap().is_ok());
+    }
+
+    let reads = entries.iter().map(|e| {
+        let db = db.clone();
+        let [key, val] = *e;
+        tokio::spawn(async move {
+            let entry = db.get(key).await.unwrap().unwrap();
+            assert_eq!(std::str::from_utf8(&entry.val).unwrap(), val);
+        })
+    });
+    for res in join_all(reads).await {
+        assert!(res.is_ok());
+    }
+}
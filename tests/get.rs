@@ -5,7 +5,7 @@ use velarixdb::db::DataStore;
 async fn test_get() {
     let root = tempdir().unwrap();
     let path = root.path().join("velarixdb");
-    let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
 
     let res1 = store.put("apple", "tim cook").await;
     let res2 = store.put("google", "sundar pichai").await;
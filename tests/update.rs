@@ -5,7 +5,7 @@ use velarixdb::db::DataStore;
 async fn test_update() {
     let root = tempdir().unwrap();
     let path = root.path().join("velarixdb");
-    let mut store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
+    let store = DataStore::open("big_tech", path).await.unwrap(); // handle IO error
 
     store.put("apple", "tim cook").await.unwrap(); // handle error
 